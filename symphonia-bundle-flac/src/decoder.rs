@@ -8,13 +8,15 @@
 use std::cmp;
 use std::num::Wrapping;
 
-use symphonia_core::audio::{AudioBuffer, AudioBufferRef, AsAudioBufferRef};
+use symphonia_core::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, AsAudioBufferRef};
 use symphonia_core::audio::{Signal, SignalSpec};
 use symphonia_core::codecs::{CODEC_TYPE_FLAC, CodecParameters, CodecDescriptor, VerificationCheck};
-use symphonia_core::codecs::{Decoder, DecoderOptions, FinalizeResult};
-use symphonia_core::errors::{Result, decode_error, unsupported_error};
+use symphonia_core::checksum::Crc16Ansi;
+use symphonia_core::codecs::{Concealer, Concealment, Decoder, DecoderOptions, FinalizeResult};
+use symphonia_core::codecs::decode_into_native;
+use symphonia_core::errors::{Error, Result, decode_error, unsupported_error};
 use symphonia_core::formats::Packet;
-use symphonia_core::io::{ReadBitsLtr, BitReaderLtr};
+use symphonia_core::io::{ReadBitsLtr, BitReaderLtr, Monitor};
 use symphonia_core::support_codec;
 use symphonia_core::util::bits::sign_extend_leq32_to_i32;
 
@@ -75,11 +77,30 @@ fn decorrelate_right_side(right: &[i32], side: &mut [i32]) {
     }
 }
 
+/// Verifies the CRC16 in the footer of a frame.
+fn verify_frame_crc(buf: &[u8]) -> Result<()> {
+    if buf.len() < 2 {
+        return decode_error("flac: frame too short");
+    }
+
+    let (frame, footer) = buf.split_at(buf.len() - 2);
+
+    let mut crc = Crc16Ansi::new(0);
+    crc.process_buf_bytes(frame);
+
+    if crc.crc() != u16::from_be_bytes([footer[0], footer[1]]) {
+        return decode_error("flac: frame crc mismatch");
+    }
+
+    Ok(())
+}
+
 /// Free Lossless Audio Codec (FLAC) decoder.
 pub struct FlacDecoder {
     params: CodecParameters,
     is_validating: bool,
     validator: Validator,
+    concealer: Concealer<i32>,
     buf: AudioBuffer<i32>,
 }
 
@@ -118,6 +139,7 @@ impl Decoder for FlacDecoder {
             params: params.clone(),
             is_validating: options.verify,
             validator: Default::default(),
+            concealer: Concealer::new(options.conceal),
             buf: AudioBuffer::new(frames, spec),
         })
     }
@@ -127,7 +149,8 @@ impl Decoder for FlacDecoder {
     }
 
     fn reset(&mut self) {
-        // No state is stored between packets, therefore do nothing.
+        // No decoding state is stored between packets, only concealment state.
+        self.concealer.reset();
     }
 
     fn codec_params(&self) -> &CodecParameters {
@@ -135,6 +158,73 @@ impl Decoder for FlacDecoder {
     }
 
     fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        match self.decode_inner(packet) {
+            Ok(()) => self.concealer.decoded(&self.buf),
+            Err(err) if self.concealer.can_conceal(&err) => self.conceal(packet, err)?,
+            Err(err) => return Err(err),
+        }
+
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn decode_into(&mut self, packet: &Packet, dest: AudioBufferMut<'_>) -> Result<()> {
+        match dest {
+            AudioBufferMut::S32(buf) => decode_into_native(self, packet, buf, |dec| &mut dec.buf),
+            mut dest => {
+                let decoded = self.decode(packet)?;
+                dest.copy_ref(&decoded)
+            }
+        }
+    }
+
+    fn concealed_frames(&self) -> u64 {
+        self.concealer.concealed_frames()
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        let mut result: FinalizeResult = Default::default();
+
+        // If verifying...
+        if self.is_validating {
+            // Try to get the expected MD5 checksum and compare it against the decoded checksum.
+            if let Some(VerificationCheck::Md5(expected)) = self.params.verification_check {
+                let decoded = self.validator.md5();
+
+                // Only generate the expected and decoded MD5 checksum strings if logging is
+                // enabled at the debug level.
+                if log_enabled!(log::Level::Debug) {
+                    use std::fmt::Write;
+
+                    let mut expected_s = String::with_capacity(32);
+                    let mut decoded_s = String::with_capacity(32);
+
+                    expected.iter().for_each(|b| write!(expected_s, "{:02x}", b).unwrap());
+                    decoded.iter().for_each(|b| write!(decoded_s, "{:02x}", b).unwrap());
+
+                    debug!("verification: expected md5 = {}", expected_s);
+                    debug!("verification: decoded md5  = {}", decoded_s);
+                }
+
+                result.verify_ok = Some(decoded == expected)
+            }
+            else {
+                warn!("verification requested but the expected md5 checksum was not provided");
+            }
+        }
+
+        result
+    }
+
+}
+
+impl FlacDecoder {
+    fn decode_inner(&mut self, packet: &Packet) -> Result<()> {
+        // Damage to the subframes of a frame may not cause a decode error. Therefore, if verifying,
+        // or if concealment is enabled, verify the integrity of the frame beforehand.
+        if self.is_validating || self.concealer.mode() != Concealment::Disabled {
+            verify_frame_crc(packet.buf())?;
+        }
+
         let mut reader = packet.as_buf_reader();
 
         // Synchronize to a frame and get the synchronization code.
@@ -215,43 +305,35 @@ impl Decoder for FlacDecoder {
             self.buf.transform(| sample | sample << shift);
         }
 
-        Ok(self.buf.as_audio_buffer_ref())
+        Ok(())
     }
 
-    fn finalize(&mut self) -> FinalizeResult {
-        let mut result: FinalizeResult = Default::default();
+    /// Conceals a damaged packet with silence of the same duration as the packet.
+    fn conceal(&mut self, packet: &Packet, err: Error) -> Result<()> {
+        // Try to get the duration of the damaged packet from its frame header, falling back to
+        // the duration of the packet.
+        let mut reader = packet.as_buf_reader();
 
-        // If verifying...
-        if self.is_validating {
-            // Try to get the expected MD5 checksum and compare it against the decoded checksum.
-            if let Some(VerificationCheck::Md5(expected)) = self.params.verification_check {
-                let decoded = self.validator.md5();
+        let header = sync_frame(&mut reader).and_then(|sync| read_frame_header(&mut reader, sync));
 
-                // Only generate the expected and decoded MD5 checksum strings if logging is
-                // enabled at the debug level.
-                if log_enabled!(log::Level::Debug) {
-                    use std::fmt::Write;
+        let n_frames = match header {
+            Ok(header) => u64::from(header.block_num_samples),
+            Err(_) => packet.duration(),
+        };
 
-                    let mut expected_s = String::with_capacity(32);
-                    let mut decoded_s = String::with_capacity(32);
+        if n_frames == 0 || n_frames > self.buf.capacity() as u64 {
+            return Err(err);
+        }
 
-                    expected.iter().for_each(|b| write!(expected_s, "{:02x}", b).unwrap());
-                    decoded.iter().for_each(|b| write!(decoded_s, "{:02x}", b).unwrap());
+        warn!("concealing damaged packet: {}", err);
 
-                    debug!("verification: expected md5 = {}", expected_s);
-                    debug!("verification: decoded md5  = {}", decoded_s);
-                }
+        self.buf.clear();
+        self.buf.render_silence(Some(n_frames as usize));
 
-                result.verify_ok = Some(decoded == expected)
-            }
-            else {
-                warn!("verification requested but the expected md5 checksum was not provided");
-            }
-        }
+        self.concealer.conceal(&mut self.buf);
 
-        result
+        Ok(())
     }
-
 }
 
 // Subframe business
@@ -721,3 +803,135 @@ lpc_predictor!(lpc_predict_32, 32);
 lpc_predictor!(lpc_predict_12, 12);
 lpc_predictor!(lpc_predict_8, 8);
 lpc_predictor!(lpc_predict_4, 4);
+
+#[cfg(test)]
+mod tests {
+    use symphonia_core::audio::{AsAudioBufferRef, AudioBuffer, AudioBufferMut, AudioBufferRef};
+    use symphonia_core::audio::{Channels, Signal, SignalSpec};
+    use symphonia_core::codecs::{CODEC_TYPE_FLAC, CodecParameters, Concealment, Decoder};
+    use symphonia_core::codecs::{DecoderOptions, Encoder, EncoderOptions};
+    use symphonia_core::formats::Packet;
+
+    use crate::{FlacDecoder, FlacEncoder};
+
+    /// Encodes four blocks of a 16-bit mono tone, and returns the codec parameters and packets.
+    fn encode_tone() -> (CodecParameters, Vec<Packet>) {
+        let spec = SignalSpec::new(44_100, Channels::FRONT_LEFT);
+
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_FLAC)
+            .with_sample_rate(spec.rate)
+            .with_channels(spec.channels)
+            .with_bits_per_sample(16);
+
+        let options = EncoderOptions { compression_level: Some(5), ..Default::default() };
+
+        let mut encoder = FlacEncoder::try_new(&params, &options).unwrap();
+
+        let mut buf = AudioBuffer::<i32>::new(4 * 4096, spec);
+        buf.render_reserved(None);
+
+        for (i, s) in buf.chan_mut(0).iter_mut().enumerate() {
+            let t = i as f64 / 44_100.0;
+            let tone = 10_000.0 * (2.0 * std::f64::consts::PI * 440.0 * t).sin();
+            *s = (tone.round() as i32) << 16;
+        }
+
+        let mut packets = encoder.encode(&buf.as_audio_buffer_ref()).unwrap();
+        packets.extend(encoder.finalize().unwrap());
+
+        (encoder.codec_params().clone(), packets)
+    }
+
+    #[test]
+    fn verify_decode_conceal() {
+        let (params, packets) = encode_tone();
+
+        assert_eq!(packets.len(), 4);
+
+        for &conceal in [Concealment::Silence, Concealment::Repeat].iter() {
+            let mut options = DecoderOptions::default();
+            options.with_conceal(conceal);
+            let mut decoder = FlacDecoder::try_new(&params, &options).unwrap();
+
+            let mut decoded = Vec::new();
+
+            for (i, packet) in packets.iter().enumerate() {
+                // Damage the subframes of the third packet.
+                let packet = if i == 2 {
+                    let mut buf = packet.buf().to_vec();
+                    buf[100] ^= 0x55;
+                    Packet::new_from_slice(0, packet.pts(), packet.duration(), &buf)
+                }
+                else {
+                    packet.clone()
+                };
+
+                if let AudioBufferRef::S32(buf) = decoder.decode(&packet).unwrap() {
+                    decoded.push(buf.chan(0).to_vec());
+                }
+            }
+
+            assert_eq!(decoded.len(), 4);
+            assert_eq!(decoded[2].len(), 4096);
+            assert_eq!(decoder.concealed_frames(), 4096);
+
+            match conceal {
+                Concealment::Silence => assert!(decoded[2].iter().all(|&s| s == 0)),
+                _ => {
+                    for (&s, &prev) in decoded[2].iter().zip(&decoded[1]) {
+                        assert_eq!(s, prev / 2);
+                    }
+                }
+            }
+        }
+
+        // If verifying, a damaged frame is an error even if concealment is disabled.
+        let mut options = DecoderOptions::default();
+        options.with_verify(true);
+        let mut decoder = FlacDecoder::try_new(&params, &options).unwrap();
+
+        let mut buf = packets[2].buf().to_vec();
+        buf[100] ^= 0x55;
+
+        let packet = Packet::new_from_slice(0, packets[2].pts(), packets[2].duration(), &buf);
+
+        assert!(decoder.decode(&packets[2]).is_ok());
+        assert!(decoder.decode(&packet).is_err());
+    }
+
+    #[test]
+    fn verify_decode_into() {
+        let (params, packets) = encode_tone();
+
+        let mut decoder = FlacDecoder::try_new(&params, &Default::default()).unwrap();
+
+        let mut native = AudioBuffer::<i32>::unused();
+        let mut converted = AudioBuffer::<f32>::unused();
+
+        for packet in packets.iter() {
+            let expected = match decoder.decode(packet).unwrap() {
+                AudioBufferRef::S32(buf) => buf.chan(0).to_vec(),
+                _ => unreachable!(),
+            };
+
+            // Audio is decoded directly into a buffer of the native sample format, and is copied
+            // into a buffer of any other sample format.
+            decoder.decode_into(packet, AudioBufferMut::from(&mut native)).unwrap();
+            decoder.decode_into(packet, AudioBufferMut::from(&mut converted)).unwrap();
+
+            assert_eq!(native.chan(0), &expected[..]);
+            assert_eq!(converted.frames(), expected.len());
+
+            // The decoder retains a buffer equivalent to the exchanged buffer.
+            match decoder.decode(packet).unwrap() {
+                AudioBufferRef::S32(buf) => {
+                    assert!(buf.spec() == native.spec());
+                    assert_eq!(buf.capacity(), native.capacity());
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
@@ -148,8 +148,14 @@ impl QueryDescriptor for FlacReader {
         ]
     }
 
-    fn score(_context: &[u8]) -> u8 {
-        255
+    fn score(context: &[u8]) -> u8 {
+        // The stream marker must be followed by the header of the stream information block which
+        // is always the first metadata block and has a fixed length of 34 bytes.
+        match context.get(4..8) {
+            Some(&[block_type, 0, 0, 34]) if block_type & 0x7f == 0 => 255,
+            Some(_) => 0,
+            None => 128,
+        }
     }
 }
 
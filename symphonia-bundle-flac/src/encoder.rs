@@ -0,0 +1,961 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::cmp;
+use std::convert::TryFrom;
+
+use symphonia_core::audio::{AudioBuffer, AudioBufferRef, Signal, SignalSpec};
+use symphonia_core::checksum::Crc16Ansi;
+use symphonia_core::codecs::{CODEC_TYPE_FLAC, CodecParameters, EncoderDescriptor};
+use symphonia_core::codecs::{Encoder, EncoderOptions, VerificationCheck};
+use symphonia_core::conv::IntoSample;
+use symphonia_core::errors::{Result, unsupported_error};
+use symphonia_core::formats::Packet;
+use symphonia_core::io::{BitWriterLtr, Monitor};
+use symphonia_core::sample::Sample;
+use symphonia_core::support_encoder;
+
+use log::debug;
+
+use super::frame::*;
+use super::lpc;
+use super::validate::Validator;
+
+/// The default compression level.
+const DEFAULT_COMPRESSION_LEVEL: u32 = 5;
+
+/// The maximum Rice parameter for the RICE residual coding method. The parameter is 4 bits wide,
+/// and the all 1s value is reserved as an escape code.
+const MAX_RICE_PARAM: u32 = 14;
+
+/// The maximum Rice parameter for the RICE2 residual coding method. The parameter is 5 bits
+/// wide, and the all 1s value is reserved as an escape code.
+const MAX_RICE2_PARAM: u32 = 30;
+
+/// The maximum residual partition order. The partition order is 4 bits wide.
+const MAX_PARTITION_ORDER: u32 = 15;
+
+/// The ratio of the Tukey window used for linear predictive analysis.
+const TUKEY_WINDOW_RATIO: f64 = 0.5;
+
+/// The stereo decorrelation mode used for 2 channel audio.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum StereoMode {
+    /// Channels are always coded independently.
+    Independent,
+    /// The channel assignment is selected by estimating the coded size of each channel.
+    Estimate,
+    /// The channel assignment is selected by fully encoding every channel, and selecting the
+    /// smallest.
+    Exhaustive,
+}
+
+/// The encoder configuration for a compression level.
+#[derive(Copy, Clone, Debug)]
+struct EncoderConfig {
+    /// The number of samples per block.
+    block_len: usize,
+    /// The stereo decorrelation mode.
+    stereo: StereoMode,
+    /// The maximum linear predictor order, or 0 to only use the fixed predictors.
+    max_lpc_order: usize,
+    /// The maximum residual partition order.
+    max_partition_order: u32,
+    /// If true, every linear predictor order is tried instead of an estimated best order.
+    exhaustive_lpc_order: bool,
+}
+
+impl EncoderConfig {
+    /// Gets the encoder configuration for a compression level between 0 (fastest) and 8
+    /// (smallest). The levels loosely follow those of the reference encoder.
+    fn for_level(level: u32) -> Option<Self> {
+        let (block_len, stereo, max_lpc_order, max_partition_order, exhaustive_lpc_order) =
+            match level {
+                0 => (1152, StereoMode::Independent, 0, 3, false),
+                1 => (1152, StereoMode::Estimate, 0, 3, false),
+                2 => (1152, StereoMode::Exhaustive, 0, 3, false),
+                3 => (4096, StereoMode::Independent, 6, 4, false),
+                4 => (4096, StereoMode::Estimate, 8, 4, false),
+                5 => (4096, StereoMode::Exhaustive, 8, 5, false),
+                6 => (4096, StereoMode::Exhaustive, 8, 6, false),
+                7 => (4096, StereoMode::Exhaustive, 12, 6, false),
+                8 => (4096, StereoMode::Exhaustive, 12, 6, true),
+                _ => return None,
+            };
+
+        Some(EncoderConfig {
+            block_len,
+            stereo,
+            max_lpc_order,
+            max_partition_order,
+            exhaustive_lpc_order,
+        })
+    }
+}
+
+/// The Rice coding of a residual.
+struct RiceCoding {
+    /// The partition order. There are 2^order partitions.
+    partition_order: u32,
+    /// The Rice parameter of each partition.
+    params: Vec<u32>,
+    /// If true, the RICE2 coding method is used.
+    is_rice2: bool,
+    /// The estimated length of the coded residual in bits.
+    n_bits: u64,
+}
+
+/// The prediction method of a subframe.
+enum Prediction {
+    Constant,
+    Verbatim,
+    Fixed,
+    Linear { precision: u32, shift: u32, coeffs: Vec<i32> },
+}
+
+/// A fully analyzed subframe that is ready to be written.
+struct Subframe {
+    prediction: Prediction,
+    /// The predictor order, and the number of warm-up samples.
+    order: usize,
+    /// The number of wasted bits per sample.
+    wasted_bps: u32,
+    /// The number of bits per sample, less the wasted bits per sample.
+    bps: u32,
+    /// The samples with the wasted bits removed.
+    samples: Vec<i32>,
+    /// The residual, and its coding, if the subframe is predicted.
+    residual: Option<(Vec<i32>, RiceCoding)>,
+    /// The estimated length of the subframe in bits.
+    n_bits: u64,
+}
+
+/// Maps a signed residual to an unsigned value for Rice coding. This is the inverse of the
+/// decoder's `rice_signed_to_i32`.
+#[inline(always)]
+fn rice_fold(residual: i32) -> u32 {
+    ((residual << 1) ^ (residual >> 31)) as u32
+}
+
+/// Computes the residual of the fixed predictor of the given order. Returns `None` if the
+/// residual, or the prediction, of any sample cannot be represented by a 32-bit integer.
+fn fixed_residual(order: usize, samples: &[i32]) -> Option<Vec<i32>> {
+    let mut residual = vec![0; samples.len()];
+
+    for i in order..samples.len() {
+        let s = |j: usize| i64::from(samples[i - j]);
+
+        let predicted = match order {
+            0 => 0,
+            1 => s(1),
+            2 => 2 * s(1) - s(2),
+            3 => 3 * s(1) - 3 * s(2) + s(3),
+            4 => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+            _ => unreachable!(),
+        };
+
+        i32::try_from(predicted).ok()?;
+        residual[i] = i32::try_from(s(0) - predicted).ok()?;
+    }
+
+    Some(residual)
+}
+
+/// Computes the residual of a linear predictor. Returns `None` if the residual, or the
+/// prediction, of any sample cannot be represented by a 32-bit integer.
+fn lpc_residual(coeffs: &[i32], shift: u32, samples: &[i32]) -> Option<Vec<i32>> {
+    let order = coeffs.len();
+
+    let mut residual = vec![0; samples.len()];
+
+    for i in order..samples.len() {
+        let predicted = coeffs.iter()
+                              .zip(samples[i - order..i].iter().rev())
+                              .map(|(&c, &s)| i64::from(c) * i64::from(s))
+                              .sum::<i64>() >> shift;
+
+        i32::try_from(predicted).ok()?;
+        residual[i] = i32::try_from(i64::from(samples[i]) - predicted).ok()?;
+    }
+
+    Some(residual)
+}
+
+/// Estimates the number of bits required to Rice code a partition of `n` folded residuals that
+/// sum to `sum`, and selects the Rice parameter.
+fn rice_partition_bits(sum: u64, n: u64) -> (u32, u64) {
+    if n == 0 {
+        return (0, 0);
+    }
+
+    // The optimal parameter is approximately the log2 of the mean folded residual.
+    let mean = sum / n;
+
+    let param = if mean > 0 { 63 - mean.leading_zeros() } else { 0 };
+    let param = cmp::min(param, MAX_RICE2_PARAM);
+
+    // Each residual is coded as a unary quotient, a stop bit, and a `param` bit remainder.
+    (param, n * u64::from(param + 1) + (sum >> param))
+}
+
+/// Searches for the residual partition order, and the Rice parameters of each partition, that
+/// yield the smallest coded residual.
+fn rice_coding(residual: &[i32], order: usize, max_partition_order: u32) -> RiceCoding {
+    let n = residual.len();
+
+    // The block must evenly divide into partitions, and the first partition must be atleast as
+    // long as the number of warm-up samples.
+    let mut max_order = 0;
+
+    while max_order < cmp::min(max_partition_order, MAX_PARTITION_ORDER) {
+        let p = max_order + 1;
+
+        if (n >> p) << p != n || (n >> p) < order {
+            break;
+        }
+
+        max_order = p;
+    }
+
+    // Sum the folded residuals of each partition at the maximum partition order. The sums of
+    // lower partition orders are obtained by merging adjacent partitions.
+    let part_len = n >> max_order;
+
+    let mut sums: Vec<u64> = (0..1usize << max_order)
+        .map(|i| {
+            let start = if i == 0 { order } else { i * part_len };
+            residual[start..(i + 1) * part_len].iter().map(|&r| u64::from(rice_fold(r))).sum()
+        })
+        .collect();
+
+    let mut best: Option<RiceCoding> = None;
+    let mut best_bits = u64::MAX;
+
+    for partition_order in (0..=max_order).rev() {
+        let part_len = (n >> partition_order) as u64;
+
+        let mut params = Vec::with_capacity(sums.len());
+        let mut n_bits = 2 + 4;
+
+        for (i, &sum) in sums.iter().enumerate() {
+            let len = if i == 0 { part_len - order as u64 } else { part_len };
+            let (param, bits) = rice_partition_bits(sum, len);
+
+            params.push(param);
+            n_bits += bits;
+        }
+
+        let is_rice2 = params.iter().any(|&param| param > MAX_RICE_PARAM);
+
+        n_bits += params.len() as u64 * if is_rice2 { 5 } else { 4 };
+
+        if n_bits < best_bits {
+            best = Some(RiceCoding { partition_order, params, is_rice2, n_bits });
+            best_bits = n_bits;
+        }
+
+        sums = sums.chunks(2).map(|pair| pair.iter().sum()).collect();
+    }
+
+    best.unwrap()
+}
+
+/// Encodes a subframe of the given samples, selecting the prediction method that yields the
+/// smallest subframe.
+fn encode_subframe(
+    samples: &[i32],
+    bps: u32,
+    config: &EncoderConfig,
+    window: &[f64],
+) -> Subframe {
+    let n = samples.len();
+
+    // The subframe header, and wasted bits flag, are 8 bits.
+    let header_bits = 8;
+
+    // If all the samples are the same, a constant subframe is the smallest.
+    if samples.iter().all(|&s| s == samples[0]) {
+        return Subframe {
+            prediction: Prediction::Constant,
+            order: 0,
+            wasted_bps: 0,
+            bps,
+            samples: samples[..1].to_vec(),
+            residual: None,
+            n_bits: header_bits + u64::from(bps),
+        };
+    }
+
+    // If the least-significant bits of all samples are 0, they are wasted, and can be removed.
+    let wasted_bps = samples.iter()
+                            .fold(0, |acc, &s| acc | s)
+                            .trailing_zeros()
+                            .min(bps - 1);
+
+    let samples: Vec<i32> = samples.iter().map(|&s| s >> wasted_bps).collect();
+
+    let bps = bps - wasted_bps;
+
+    // The number of wasted bits is unary coded.
+    let header_bits = header_bits + u64::from(wasted_bps);
+
+    let mut best = Subframe {
+        prediction: Prediction::Verbatim,
+        order: 0,
+        wasted_bps,
+        bps,
+        samples: Vec::new(),
+        residual: None,
+        n_bits: header_bits + n as u64 * u64::from(bps),
+    };
+
+    // Select the fixed predictor order with the smallest residual.
+    let best_fixed = (0..=cmp::min(4, n - 1))
+        .filter_map(|order| fixed_residual(order, &samples).map(|residual| (order, residual)))
+        .min_by_key(|(_, residual)| {
+            residual.iter().map(|&r| u64::from(rice_fold(r))).sum::<u64>()
+        });
+
+    if let Some((order, residual)) = best_fixed {
+        let coding = rice_coding(&residual, order, config.max_partition_order);
+
+        let n_bits = header_bits + (order as u64 * u64::from(bps)) + coding.n_bits;
+
+        if n_bits < best.n_bits {
+            best = Subframe {
+                prediction: Prediction::Fixed,
+                order,
+                wasted_bps,
+                bps,
+                samples: Vec::new(),
+                residual: Some((residual, coding)),
+                n_bits,
+            };
+        }
+    }
+
+    // Try linear predictors if enabled.
+    let max_lpc_order = cmp::min(config.max_lpc_order, n - 1);
+
+    if max_lpc_order > 0 {
+        let precision = cmp::min(lpc::qlp_precision_for_block_len(n), lpc::MAX_QLP_PRECISION);
+
+        // Use the cached window if it is the correct length.
+        let tukey;
+
+        let window = if window.len() == n {
+            window
+        }
+        else {
+            tukey = lpc::tukey_window(n, TUKEY_WINDOW_RATIO);
+            &tukey
+        };
+
+        let mut autoc = [0.0; lpc::MAX_LPC_ORDER + 1];
+        lpc::autocorrelation(&samples, window, &mut autoc[..=max_lpc_order]);
+
+        let models = lpc::levinson_durbin(&autoc, max_lpc_order);
+
+        let candidates = if config.exhaustive_lpc_order {
+            0..models.len()
+        }
+        else {
+            let best = lpc::estimate_best_order(&models, n, precision + bps);
+            best..cmp::min(best + 1, models.len())
+        };
+
+        for model in &models[candidates] {
+            let order = model.coeffs.len();
+
+            let (coeffs, shift) = match lpc::quantize_coefficients(&model.coeffs, precision) {
+                Some(quantized) => quantized,
+                None => continue,
+            };
+
+            let residual = match lpc_residual(&coeffs, shift, &samples) {
+                Some(residual) => residual,
+                None => continue,
+            };
+
+            let coding = rice_coding(&residual, order, config.max_partition_order);
+
+            // The warm-up samples, precision, shift, coefficients, and residual.
+            let n_bits = header_bits
+                + (order as u64 * u64::from(bps))
+                + 4
+                + 5
+                + (order as u64 * u64::from(precision))
+                + coding.n_bits;
+
+            if n_bits < best.n_bits {
+                best = Subframe {
+                    prediction: Prediction::Linear { precision, shift, coeffs },
+                    order,
+                    wasted_bps,
+                    bps,
+                    samples: Vec::new(),
+                    residual: Some((residual, coding)),
+                    n_bits,
+                };
+            }
+        }
+    }
+
+    // Only verbatim subframes, and the warm-up samples of predicted subframes, need the samples.
+    best.samples = match best.prediction {
+        Prediction::Verbatim => samples,
+        _ => samples[..best.order].to_vec(),
+    };
+
+    best
+}
+
+fn write_residual(bw: &mut BitWriterLtr, residual: &[i32], order: usize, coding: &RiceCoding) {
+    let param_bit_width = if coding.is_rice2 { 5 } else { 4 };
+
+    bw.write_bits_leq32(if coding.is_rice2 { 0x1 } else { 0x0 }, 2);
+    bw.write_bits_leq32(coding.partition_order, 4);
+
+    let part_len = residual.len() >> coding.partition_order;
+
+    for (i, &param) in coding.params.iter().enumerate() {
+        // The first partition does not contain the warm-up samples.
+        let start = if i == 0 { order } else { i * part_len };
+
+        bw.write_bits_leq32(param, param_bit_width);
+
+        for &r in &residual[start..(i + 1) * part_len] {
+            let folded = rice_fold(r);
+
+            bw.write_unary_zeros(folded >> param);
+            bw.write_bits_leq32(folded, param);
+        }
+    }
+}
+
+fn write_subframe(bw: &mut BitWriterLtr, subframe: &Subframe) {
+    let subframe_type_enc = match subframe.prediction {
+        Prediction::Constant => 0x00,
+        Prediction::Verbatim => 0x01,
+        Prediction::Fixed => 0x08 | subframe.order as u32,
+        Prediction::Linear { .. } => 0x20 | (subframe.order as u32 - 1),
+    };
+
+    // The first bit is padding, followed by the subframe type, and the wasted bits flag.
+    bw.write_bits_leq32(subframe_type_enc, 7);
+
+    if subframe.wasted_bps > 0 {
+        bw.write_bit(true);
+        bw.write_unary_zeros(subframe.wasted_bps - 1);
+    }
+    else {
+        bw.write_bit(false);
+    }
+
+    // For constant and verbatim subframes these are all the samples, otherwise these are the
+    // warm-up samples.
+    for &sample in &subframe.samples {
+        bw.write_bits_leq32(sample as u32, subframe.bps);
+    }
+
+    if let Prediction::Linear { precision, shift, ref coeffs } = subframe.prediction {
+        bw.write_bits_leq32(precision - 1, 4);
+        bw.write_bits_leq32(shift, 5);
+
+        for &c in coeffs {
+            bw.write_bits_leq32(c as u32, precision);
+        }
+    }
+
+    if let Some((ref residual, ref coding)) = subframe.residual {
+        write_residual(bw, residual, subframe.order, coding);
+    }
+}
+
+/// Estimates the coded size of a channel using the sum of the residual of the 2nd order fixed
+/// predictor.
+fn estimate_channel_bits(samples: &[i32]) -> u64 {
+    samples.windows(3)
+           .map(|s| (i64::from(s[2]) - 2 * i64::from(s[1]) + i64::from(s[0])).unsigned_abs())
+           .sum()
+}
+
+/// Free Lossless Audio Codec (FLAC) encoder.
+///
+/// `FlacEncoder` supports compression levels 0 through 8. Lower levels use smaller blocks and only
+/// the fixed predictors, while higher levels use linear prediction of increasing order, and more
+/// exhaustive searches for the best stereo decorrelation mode and residual partitioning. The
+/// default compression level is 5.
+///
+/// The MD5 checksum of the encoded audio is available in the `CodecParameters` once the encoder
+/// is finalized.
+pub struct FlacEncoder {
+    params: CodecParameters,
+    config: EncoderConfig,
+    track_id: u32,
+    bits_per_sample: u32,
+    /// The audio that has not yet been encoded.
+    buf: AudioBuffer<i32>,
+    /// The analysis window for a full block.
+    window: Vec<f64>,
+    validator: Validator,
+    /// The number of the next frame.
+    frame_num: u32,
+    /// The total number of samples, per channel, encoded.
+    n_frames: u64,
+}
+
+impl FlacEncoder {
+    fn append<S: Sample + IntoSample<i32>>(
+        &mut self,
+        src: &AudioBuffer<S>,
+        packets: &mut Vec<Packet>,
+    ) -> Result<()> {
+        if src.spec() != self.buf.spec() {
+            return unsupported_error("audio buffer signal spec does not match the encoder");
+        }
+
+        // Samples are converted to 32-bit integers, and then scaled down to the number of bits per
+        // sample.
+        let shift = 32 - self.bits_per_sample;
+        let n_channels = self.buf.spec().channels.count();
+
+        let mut offset = 0;
+
+        while offset < src.frames() {
+            let start = self.buf.frames();
+            let n = cmp::min(src.frames() - offset, self.buf.capacity() - start);
+
+            self.buf.render_reserved(Some(n));
+
+            for ch in 0..n_channels {
+                let dst = &mut self.buf.chan_mut(ch)[start..start + n];
+
+                for (d, &s) in dst.iter_mut().zip(&src.chan(ch)[offset..offset + n]) {
+                    let sample: i32 = s.into_sample();
+                    *d = sample >> shift;
+                }
+            }
+
+            offset += n;
+
+            // Encode a frame once a block has been filled.
+            if self.buf.frames() == self.buf.capacity() {
+                packets.push(self.encode_frame());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encodes all the buffered audio as a frame.
+    fn encode_frame(&mut self) -> Packet {
+        let n = self.buf.frames();
+        let bps = self.bits_per_sample;
+        let n_channels = self.buf.spec().channels.count();
+
+        self.validator.update(&self.buf, bps);
+
+        let config = &self.config;
+        let window = &self.window;
+
+        // Stereo decorrelation is only possible for 2 channels, and if the side channel fits in
+        // 32-bits.
+        let stereo = if n_channels == 2 && bps < 32 {
+            config.stereo
+        }
+        else {
+            StereoMode::Independent
+        };
+
+        let (channel_assignment, subframes) = if stereo == StereoMode::Independent {
+            let subframes = (0..n_channels)
+                .map(|ch| encode_subframe(self.buf.chan(ch), bps, config, window))
+                .collect();
+
+            (ChannelAssignment::Independant(n_channels as u32), subframes)
+        }
+        else {
+            let left = self.buf.chan(0);
+            let right = self.buf.chan(1);
+
+            let mid: Vec<i32> = left.iter()
+                                    .zip(right)
+                                    .map(|(&l, &r)| ((i64::from(l) + i64::from(r)) >> 1) as i32)
+                                    .collect();
+
+            let side: Vec<i32> = left.iter().zip(right).map(|(&l, &r)| l - r).collect();
+
+            // The possible channel assignments, and the channels they code.
+            let assignments = [
+                (ChannelAssignment::Independant(2), [left, right], [bps, bps]),
+                (ChannelAssignment::LeftSide, [left, &side], [bps, bps + 1]),
+                (ChannelAssignment::RightSide, [&side, right], [bps + 1, bps]),
+                (ChannelAssignment::MidSide, [&mid, &side], [bps, bps + 1]),
+            ];
+
+            let encode = |i: usize| {
+                let (_, chans, bps) = &assignments[i];
+
+                vec![
+                    encode_subframe(chans[0], bps[0], config, window),
+                    encode_subframe(chans[1], bps[1], config, window),
+                ]
+            };
+
+            let best = if stereo == StereoMode::Estimate {
+                let estimates: Vec<u64> = [left, right, &mid, &side]
+                    .iter()
+                    .map(|chan| estimate_channel_bits(chan))
+                    .collect();
+
+                let costs = [
+                    estimates[0] + estimates[1],
+                    estimates[0] + estimates[3],
+                    estimates[3] + estimates[1],
+                    estimates[2] + estimates[3],
+                ];
+
+                let i = (0..4).min_by_key(|&i| costs[i]).unwrap();
+
+                (i, encode(i))
+            }
+            else {
+                (0..4).map(|i| (i, encode(i)))
+                      .min_by_key(|(_, subframes)| {
+                          subframes.iter().map(|subframe| subframe.n_bits).sum::<u64>()
+                      })
+                      .unwrap()
+            };
+
+            let channel_assignment = match best.0 {
+                0 => ChannelAssignment::Independant(2),
+                1 => ChannelAssignment::LeftSide,
+                2 => ChannelAssignment::RightSide,
+                _ => ChannelAssignment::MidSide,
+            };
+
+            (channel_assignment, best.1)
+        };
+
+        let header = FrameHeader {
+            block_sequence: BlockSequence::ByFrame(self.frame_num),
+            block_num_samples: n as u16,
+            bits_per_sample: Some(bps),
+            sample_rate: Some(self.buf.spec().rate),
+            channel_assignment,
+        };
+
+        let mut buf = Vec::new();
+
+        write_frame_header(&mut buf, &header);
+
+        // The subframes are bit-packed, and the frame is padded to a byte boundary.
+        let mut bw = BitWriterLtr::with_capacity(n * n_channels * bps as usize / 8);
+
+        for subframe in subframes.iter() {
+            write_subframe(&mut bw, subframe);
+        }
+
+        buf.append(&mut bw.into_inner());
+
+        // The frame, including the header, is checksummed with a CRC16 hash.
+        let mut crc16 = Crc16Ansi::new(0);
+        crc16.process_buf_bytes(&buf);
+
+        buf.extend_from_slice(&crc16.crc().to_be_bytes());
+
+        let packet = Packet::new_from_boxed_slice(
+            self.track_id,
+            self.n_frames,
+            n as u64,
+            buf.into_boxed_slice(),
+        );
+
+        self.frame_num += 1;
+        self.n_frames += n as u64;
+        self.buf.clear();
+
+        packet
+    }
+}
+
+impl Encoder for FlacEncoder {
+
+    fn try_new(params: &CodecParameters, options: &EncoderOptions) -> Result<Self> {
+        if params.codec != CODEC_TYPE_FLAC {
+            return unsupported_error("codec is not flac");
+        }
+
+        let sample_rate = match params.sample_rate {
+            Some(rate) if (1..=655_350).contains(&rate) => rate,
+            Some(_) => return unsupported_error("sample rate is out of bounds"),
+            None => return unsupported_error("sample rate is required"),
+        };
+
+        let channels = match params.channels {
+            Some(channels) if (1..=8).contains(&channels.count()) => channels,
+            Some(_) => return unsupported_error("flac supports between 1 and 8 channels"),
+            None => return unsupported_error("channels are required"),
+        };
+
+        let bits_per_sample = match params.bits_per_sample {
+            Some(bps) if (4..=32).contains(&bps) => bps,
+            Some(_) => return unsupported_error("flac supports between 4 and 32 bits per sample"),
+            None => return unsupported_error("bits per sample is required"),
+        };
+
+        let level = options.compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL);
+
+        let config = match EncoderConfig::for_level(level) {
+            Some(config) => config,
+            None => return unsupported_error("compression level is out of range"),
+        };
+
+        debug!("flac encoder: level={}, config={:?}", level, config);
+
+        let mut out_params = CodecParameters::new();
+
+        out_params
+            .for_codec(CODEC_TYPE_FLAC)
+            .with_sample_rate(sample_rate)
+            .with_bits_per_sample(bits_per_sample)
+            .with_max_frames_per_packet(config.block_len as u64)
+            .with_channels(channels)
+            .with_packet_data_integrity(true);
+
+        Ok(FlacEncoder {
+            params: out_params,
+            config,
+            track_id: options.track_id,
+            bits_per_sample,
+            buf: AudioBuffer::new(config.block_len as u64, SignalSpec::new(sample_rate, channels)),
+            window: lpc::tukey_window(config.block_len, TUKEY_WINDOW_RATIO),
+            validator: Default::default(),
+            frame_num: 0,
+            n_frames: 0,
+        })
+    }
+
+    fn supported_codecs() -> &'static [EncoderDescriptor] {
+        &[ support_encoder!(CODEC_TYPE_FLAC, "flac", "Free Lossless Audio Codec") ]
+    }
+
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.validator = Default::default();
+        self.frame_num = 0;
+        self.n_frames = 0;
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn encode(&mut self, buf: &AudioBufferRef<'_>) -> Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+
+        match buf {
+            AudioBufferRef::U8(buf)  => self.append(buf, &mut packets)?,
+            AudioBufferRef::U16(buf) => self.append(buf, &mut packets)?,
+            AudioBufferRef::U24(buf) => self.append(buf, &mut packets)?,
+            AudioBufferRef::U32(buf) => self.append(buf, &mut packets)?,
+            AudioBufferRef::S8(buf)  => self.append(buf, &mut packets)?,
+            AudioBufferRef::S16(buf) => self.append(buf, &mut packets)?,
+            AudioBufferRef::S24(buf) => self.append(buf, &mut packets)?,
+            AudioBufferRef::S32(buf) => self.append(buf, &mut packets)?,
+            AudioBufferRef::F32(buf) => self.append(buf, &mut packets)?,
+            AudioBufferRef::F64(buf) => self.append(buf, &mut packets)?,
+        }
+
+        Ok(packets)
+    }
+
+    fn finalize(&mut self) -> Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+
+        // Encode the remaining audio as the final, possibly shorter, block.
+        if self.buf.frames() > 0 {
+            packets.push(self.encode_frame());
+        }
+
+        let md5 = self.validator.md5();
+
+        self.params
+            .with_n_frames(self.n_frames)
+            .with_verification_code(VerificationCheck::Md5(md5));
+
+        Ok(packets)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Seek, SeekFrom, Write};
+    use std::sync::{Arc, Mutex};
+
+    use symphonia_core::audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Channels, Signal};
+    use symphonia_core::audio::SignalSpec;
+    use symphonia_core::codecs::{CODEC_TYPE_FLAC, CodecParameters, Decoder, DecoderOptions};
+    use symphonia_core::codecs::{Encoder, EncoderOptions};
+    use symphonia_core::formats::{FormatReader, FormatWriter, Track};
+    use symphonia_core::io::{MediaSink, MediaSourceStream};
+
+    use crate::{FlacDecoder, FlacEncoder, FlacReader, FlacWriter};
+
+    /// A seekable in-memory sink that can be read back after it is given to a writer.
+    #[derive(Clone, Default)]
+    struct MemorySink(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+    impl Write for MemorySink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for MemorySink {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.lock().unwrap().seek(pos)
+        }
+    }
+
+    impl MediaSink for MemorySink {
+        fn is_seekable(&self) -> bool {
+            true
+        }
+    }
+
+    /// Encodes the planes of samples, and then decodes the result with verification enabled.
+    fn encode_and_decode(
+        channels: Channels,
+        bps: u32,
+        level: u32,
+        planes: &[Vec<i32>],
+    ) {
+        let n_frames = planes[0].len();
+
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_FLAC)
+            .with_sample_rate(44_100)
+            .with_channels(channels)
+            .with_bits_per_sample(bps);
+
+        let options = EncoderOptions { compression_level: Some(level), ..Default::default() };
+
+        let mut encoder = FlacEncoder::try_new(&params, &options).unwrap();
+
+        let sink = MemorySink::default();
+        let tracks = [Track::new(0, encoder.codec_params().clone())];
+
+        let mut writer = FlacWriter::try_new(Box::new(sink.clone()), &tracks, None).unwrap();
+
+        // Encode the samples in uneven chunks so that blocks span multiple buffers.
+        let spec = SignalSpec::new(44_100, channels);
+
+        for start in (0..n_frames).step_by(1000) {
+            let len = (n_frames - start).min(1000);
+
+            let mut buf = AudioBuffer::<i32>::new(len as u64, spec);
+            buf.render_reserved(Some(len));
+
+            for (ch, plane) in planes.iter().enumerate() {
+                for (out, &s) in buf.chan_mut(ch).iter_mut().zip(&plane[start..start + len]) {
+                    *out = s << (32 - bps);
+                }
+            }
+
+            for packet in encoder.encode(&buf.as_audio_buffer_ref()).unwrap() {
+                writer.write_packet(&packet).unwrap();
+            }
+        }
+
+        for packet in encoder.finalize().unwrap() {
+            writer.write_packet(&packet).unwrap();
+        }
+
+        writer.update_codec_params(0, encoder.codec_params()).unwrap();
+        writer.finalize().unwrap();
+
+        let buf = sink.0.lock().unwrap().get_ref().clone();
+        let source = MediaSourceStream::new(Box::new(Cursor::new(buf)), Default::default());
+
+        let mut reader = FlacReader::try_new(source, &Default::default()).unwrap();
+
+        let params = &reader.tracks()[0].codec_params;
+
+        assert_eq!(params.n_frames, Some(n_frames as u64));
+        assert_eq!(params.bits_per_sample, Some(bps));
+
+        let mut options = DecoderOptions::default();
+        options.with_verify(true);
+        let mut decoder = FlacDecoder::try_new(params, &options).unwrap();
+
+        let mut pos = 0;
+
+        while let Ok(packet) = reader.next_packet() {
+            assert_eq!(packet.pts(), pos as u64);
+
+            if let AudioBufferRef::S32(buf) = decoder.decode(&packet).unwrap() {
+                for (ch, plane) in planes.iter().enumerate() {
+                    for (&s, &expected) in buf.chan(ch).iter().zip(&plane[pos..]) {
+                        assert_eq!(s >> (32 - bps), expected);
+                    }
+                }
+
+                pos += buf.frames();
+            }
+        }
+
+        assert_eq!(pos, n_frames);
+        assert_eq!(decoder.finalize().verify_ok, Some(true));
+    }
+
+    /// Generates a tone with a little noise.
+    fn tone(n_frames: usize, freq: f64, amplitude: f64, seed: &mut u32) -> Vec<i32> {
+        (0..n_frames).map(|i| {
+            *seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = f64::from((*seed >> 16) & 0xf) - 7.5;
+            let t = i as f64 / 44_100.0;
+            (amplitude * (2.0 * std::f64::consts::PI * freq * t).sin() + noise).round() as i32
+        })
+        .collect()
+    }
+
+    #[test]
+    fn verify_encode_stereo() {
+        let mut seed = 1;
+
+        let left = tone(20_000, 440.0, 20_000.0, &mut seed);
+        let right: Vec<i32> = left.iter().map(|&s| s / 2 + 100).collect();
+
+        let channels = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+
+        for &level in [0, 2, 5, 8].iter() {
+            encode_and_decode(channels, 16, level, &[left.clone(), right.clone()]);
+        }
+    }
+
+    #[test]
+    fn verify_encode_mono_24bit() {
+        let mut seed = 2;
+
+        let mut mono = tone(10_000, 1000.0, 8_000_000.0, &mut seed);
+
+        // Include a block of silence to exercise constant subframes.
+        for s in mono[4096..8192].iter_mut() {
+            *s = 0;
+        }
+
+        encode_and_decode(Channels::FRONT_LEFT, 24, 5, &[mono]);
+    }
+}
@@ -212,6 +212,101 @@ pub fn read_frame_header<B: ReadBytes>(reader: &mut B, sync: u16) -> Result<Fram
     })
 }
 
+/// Serializes a frame header, including the synchronization code and CRC8 checksum, and appends
+/// it to `buf`. This is the inverse of `sync_frame` followed by `read_frame_header`.
+pub fn write_frame_header(buf: &mut Vec<u8>, header: &FrameHeader) {
+    let start = buf.len();
+
+    // The expanded synchronization code contains the blocking strategy in the least-significant
+    // bit.
+    let (sync, seq) = match header.block_sequence {
+        BlockSequence::ByFrame(frame) => (0xfff8u16, u64::from(frame)),
+        BlockSequence::BySample(sample) => (0xfff9u16, sample),
+    };
+
+    buf.extend_from_slice(&sync.to_be_bytes());
+
+    let block_num_samples = u32::from(header.block_num_samples);
+
+    // Use one of the common block sizes if possible. Otherwise, the block size minus 1 is stored as
+    // an 8- or 16-bit value after the frame sequence number.
+    let block_size_enc = match block_num_samples {
+        192 => 0x1,
+        576 | 1152 | 2304 | 4608 => 0x2 + (block_num_samples / 576).trailing_zeros(),
+        256 | 512 | 1024 | 2048 | 4096 | 8192 | 16384 | 32768 => {
+            0x8 + (block_num_samples / 256).trailing_zeros()
+        }
+        1..=256 => 0x6,
+        _ => 0x7,
+    };
+
+    // Use one of the common sample rates if possible. Otherwise, the sample rate is stored as a
+    // 16-bit value in Hz, or tens of Hz, after the block size. If the sample rate cannot be
+    // represented, it must be read from the StreamInfo block.
+    let sample_rate_enc = match header.sample_rate {
+        Some( 88_200) => 0x1,
+        Some(176_400) => 0x2,
+        Some(192_000) => 0x3,
+        Some(  8_000) => 0x4,
+        Some( 16_000) => 0x5,
+        Some( 22_050) => 0x6,
+        Some( 24_000) => 0x7,
+        Some( 32_000) => 0x8,
+        Some( 44_100) => 0x9,
+        Some( 48_000) => 0xa,
+        Some( 96_000) => 0xb,
+        Some(rate) if rate <= 0xffff => 0xd,
+        Some(rate) if rate % 10 == 0 && rate / 10 <= 0xffff => 0xe,
+        _ => 0x0,
+    };
+
+    let bits_per_sample_enc = match header.bits_per_sample {
+        Some( 8) => 0x1,
+        Some(12) => 0x2,
+        Some(16) => 0x4,
+        Some(20) => 0x5,
+        Some(24) => 0x6,
+        _ => 0x0,
+    };
+
+    let channels_enc = match header.channel_assignment {
+        ChannelAssignment::Independant(channels) => {
+            debug_assert!(channels > 0 && channels <= 8);
+            channels - 1
+        }
+        ChannelAssignment::LeftSide  => 0x8,
+        ChannelAssignment::RightSide => 0x9,
+        ChannelAssignment::MidSide   => 0xa,
+    };
+
+    let desc = (block_size_enc << 12)
+        | (sample_rate_enc << 8)
+        | (channels_enc << 4)
+        | (bits_per_sample_enc << 1);
+
+    buf.extend_from_slice(&(desc as u16).to_be_bytes());
+
+    utf8_encode_be_u64(buf, seq);
+
+    match block_size_enc {
+        0x6 => buf.push((block_num_samples - 1) as u8),
+        0x7 => buf.extend_from_slice(&((block_num_samples - 1) as u16).to_be_bytes()),
+        _ => (),
+    }
+
+    match (sample_rate_enc, header.sample_rate) {
+        (0xd, Some(rate)) => buf.extend_from_slice(&(rate as u16).to_be_bytes()),
+        (0xe, Some(rate)) => buf.extend_from_slice(&((rate / 10) as u16).to_be_bytes()),
+        _ => (),
+    }
+
+    // The header, including the sync code, is checksummed with a CRC8 hash.
+    let mut crc8 = Crc8Ccitt::new(0);
+    crc8.process_buf_bytes(&buf[start..]);
+
+    buf.push(crc8.crc());
+}
+
 /// A very quick check if the provided buffer is likely be a FLAC frame header.
 pub fn is_likely_frame_header(buf: &[u8]) -> bool {
     // let is_variable = (buf[1] & 0x1) == 1;
@@ -316,10 +411,36 @@ fn utf8_decode_be_u64<B: ReadBytes>(src : &mut B) -> Result<Option<u64>> {
     Ok(Some(state))
 }
 
+/// Encodes a big-endian unsigned integer via extended UTF8, and appends it to `buf`. This is the
+/// inverse of `utf8_decode_be_u64`, and can encode integers of up-to 36-bits.
+fn utf8_encode_be_u64(buf: &mut Vec<u8>, value: u64) {
+    debug_assert!(value <= 0xf_ffff_ffff);
+
+    // Values 0-127 are stored as-is in a single byte.
+    if value < 0x80 {
+        buf.push(value as u8);
+        return;
+    }
+
+    // Each byte after the first stores 6 bits. The first byte stores 7 - N bits, where N is the
+    // total number of bytes. Find the smallest number of bytes that can store the value.
+    let n_bytes = (2..7).find(|&n| value < 1 << (5 * n + 1)).unwrap_or(7);
+
+    // The first byte is prefixed by N 1s followed by a 0.
+    let prefix = !(0xffu8 >> n_bytes);
+
+    buf.push(prefix | (value >> (6 * (n_bytes - 1))) as u8);
+
+    // Subsequent bytes are prefixed with 0b10.
+    for i in (0..n_bytes - 1).rev() {
+        buf.push(0x80 | ((value >> (6 * i)) & 0x3f) as u8);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use symphonia_core::io::BufReader;
-    use super::utf8_decode_be_u64;
+    use super::{utf8_decode_be_u64, utf8_encode_be_u64};
 
     #[test]
     fn verify_utf8_decode_be_u64() {
@@ -339,4 +460,23 @@ mod tests {
         assert_eq!(utf8_decode_be_u64(&mut stream).unwrap(), None);
         assert_eq!(utf8_decode_be_u64(&mut stream).unwrap(), None);
     }
+
+    #[test]
+    fn verify_utf8_encode_be_u64() {
+        let values = [0, 36, 127, 128, 162, 2361, 8364, 66376, 0x7fff_ffff, 0xf_ffff_ffff];
+
+        let mut buf = Vec::new();
+
+        for &value in values.iter() {
+            utf8_encode_be_u64(&mut buf, value);
+        }
+
+        assert_eq!(&buf[1..6], &[0x24, 0x7f, 0xc2, 0x80, 0xc2]);
+
+        let mut stream = BufReader::new(&buf);
+
+        for &value in values.iter() {
+            assert_eq!(utf8_decode_be_u64(&mut stream).unwrap(), Some(value));
+        }
+    }
 }
@@ -10,9 +10,14 @@
 
 mod decoder;
 mod demuxer;
+mod encoder;
 mod frame;
+mod lpc;
+mod muxer;
 mod parser;
 mod validate;
 
 pub use decoder::FlacDecoder;
-pub use demuxer::FlacReader;
\ No newline at end of file
+pub use demuxer::FlacReader;
+pub use encoder::FlacEncoder;
+pub use muxer::FlacWriter;
\ No newline at end of file
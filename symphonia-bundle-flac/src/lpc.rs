@@ -0,0 +1,207 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::f64::consts::PI;
+
+/// The maximum order of a FLAC linear predictor.
+pub const MAX_LPC_ORDER: usize = 32;
+
+/// The maximum precision of a quantized linear predictor coefficient.
+pub const MAX_QLP_PRECISION: u32 = 15;
+
+/// The maximum shift of the quantized linear predictor coefficients. Negative shifts are not
+/// supported by the decoder, and are therefore never generated.
+const MAX_QLP_SHIFT: i32 = 15;
+
+/// Generates a Tukey (tapered cosine) window of length `len`. The ratio `p` is the fraction of
+/// the window that is tapered, 0.0 being a rectangular window, and 1.0 being a Hann window.
+pub fn tukey_window(len: usize, p: f64) -> Vec<f64> {
+    let mut window = vec![1.0; len];
+
+    // The number of samples in each tapered section.
+    let n_taper = ((p / 2.0) * len as f64) as usize;
+
+    if n_taper > 0 {
+        for i in 0..n_taper {
+            let w = 0.5 - 0.5 * (PI * i as f64 / n_taper as f64).cos();
+            window[i] = w;
+            window[len - 1 - i] = w;
+        }
+    }
+
+    window
+}
+
+/// Computes the autocorrelation of the windowed samples for lags 0 through `autoc.len() - 1`.
+pub fn autocorrelation(samples: &[i32], window: &[f64], autoc: &mut [f64]) {
+    debug_assert!(samples.len() == window.len());
+
+    let windowed: Vec<f64> = samples.iter().zip(window).map(|(&s, &w)| f64::from(s) * w).collect();
+
+    for (lag, ac) in autoc.iter_mut().enumerate() {
+        *ac = if lag < windowed.len() {
+            windowed[lag..].iter().zip(&windowed).map(|(a, b)| a * b).sum()
+        }
+        else {
+            0.0
+        };
+    }
+}
+
+/// A linear predictor computed by the Levinson-Durbin recursion.
+pub struct LpcModel {
+    /// The predictor coefficients. The first coefficient is applied to the previous sample.
+    pub coeffs: Vec<f64>,
+    /// The prediction error.
+    pub error: f64,
+}
+
+/// Computes the linear predictors of orders 1 through `max_order` using the Levinson-Durbin
+/// recursion on the autocorrelation `autoc`. The element at index `i` of the returned vector is
+/// the predictor of order `i + 1`. Fewer than `max_order` predictors are returned if a perfect
+/// predictor is found, or if the recursion becomes unstable.
+pub fn levinson_durbin(autoc: &[f64], max_order: usize) -> Vec<LpcModel> {
+    debug_assert!(max_order < autoc.len());
+
+    let mut models = Vec::with_capacity(max_order);
+
+    // A silent signal cannot be predicted.
+    if autoc[0] <= 0.0 {
+        return models;
+    }
+
+    let mut lpc = [0.0f64; MAX_LPC_ORDER];
+    let mut error = autoc[0];
+
+    for i in 0..max_order {
+        // Compute the reflection coefficient.
+        let mut r = -autoc[i + 1];
+
+        for j in 0..i {
+            r -= lpc[j] * autoc[i - j];
+        }
+
+        r /= error;
+
+        // Update the coefficients in-place.
+        lpc[i] = r;
+
+        for j in 0..i / 2 {
+            let tmp = lpc[j];
+            lpc[j] += r * lpc[i - 1 - j];
+            lpc[i - 1 - j] += r * tmp;
+        }
+
+        if i & 1 == 1 {
+            lpc[i / 2] += lpc[i / 2] * r;
+        }
+
+        error *= 1.0 - r * r;
+
+        if !error.is_finite() || error < 0.0 {
+            break;
+        }
+
+        models.push(LpcModel { coeffs: lpc[..=i].iter().map(|c| -c).collect(), error });
+
+        // The signal was perfectly predicted, higher orders cannot improve upon this.
+        if error == 0.0 {
+            break;
+        }
+    }
+
+    models
+}
+
+/// Estimates the predictor order that will yield the smallest encoded subframe given the
+/// prediction errors of the predictors returned by `levinson_durbin`. Returns the index of the
+/// best predictor.
+pub fn estimate_best_order(models: &[LpcModel], n_samples: usize, bits_per_order: u32) -> usize {
+    let error_scale = 0.5 / n_samples as f64;
+
+    let mut best = 0;
+    let mut best_bits = f64::MAX;
+
+    for (i, model) in models.iter().enumerate() {
+        let order = i + 1;
+
+        // The expected number of bits per residual is approximately half the log2 of the
+        // residual energy per sample.
+        let bits_per_residual = if model.error > 0.0 {
+            (0.5 * (error_scale * model.error).log2()).max(0.0)
+        }
+        else {
+            0.0
+        };
+
+        let bits = bits_per_residual * (n_samples - order) as f64
+            + (order as u32 * bits_per_order) as f64;
+
+        if bits < best_bits {
+            best = i;
+            best_bits = bits;
+        }
+    }
+
+    best
+}
+
+/// Quantizes the predictor coefficients to signed integers of `precision` bits. Returns the
+/// quantized coefficients and the shift that must be applied to the prediction, or `None` if the
+/// coefficients cannot be quantized.
+pub fn quantize_coefficients(coeffs: &[f64], precision: u32) -> Option<(Vec<i32>, u32)> {
+    debug_assert!(precision > 1 && precision <= MAX_QLP_PRECISION);
+
+    let cmax = coeffs.iter().fold(0.0f64, |max, c| max.max(c.abs()));
+
+    if cmax <= 0.0 || !cmax.is_finite() {
+        return None;
+    }
+
+    // The number of integer bits required to represent the largest coefficient. The remaining
+    // bits of precision, less the sign bit, are fractional bits.
+    let log2cmax = cmax.log2().floor() as i32 + 1;
+
+    let shift = (precision as i32 - 1 - log2cmax).min(MAX_QLP_SHIFT);
+
+    if shift < 0 {
+        return None;
+    }
+
+    let qmax = (1i32 << (precision - 1)) - 1;
+    let qmin = -(1i32 << (precision - 1));
+
+    // Quantize each coefficient, carrying the quantization error over to the next coefficient.
+    let mut error = 0.0;
+
+    let qcoeffs = coeffs.iter().map(|c| {
+        error += c * f64::from(1u32 << shift);
+
+        let q = (error.round() as i32).max(qmin).min(qmax);
+
+        error -= f64::from(q);
+
+        q
+    })
+    .collect();
+
+    Some((qcoeffs, shift as u32))
+}
+
+/// Gets the precision of the quantized linear predictor coefficients that should be used for a
+/// given block length.
+pub fn qlp_precision_for_block_len(block_len: usize) -> u32 {
+    match block_len {
+        0..=192 => 7,
+        193..=384 => 8,
+        385..=576 => 9,
+        577..=1152 => 10,
+        1153..=2304 => 11,
+        2305..=4608 => 12,
+        _ => 13,
+    }
+}
@@ -0,0 +1,338 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use symphonia_core::codecs::{CODEC_TYPE_FLAC, CodecParameters, VerificationCheck};
+use symphonia_core::errors::{Result, limit_error, unsupported_error};
+use symphonia_core::formats::{FormatWriter, Packet, Track};
+use symphonia_core::formats::util::SeekPoint;
+use symphonia_core::io::MediaSink;
+use symphonia_core::meta::MetadataRevision;
+
+use symphonia_utils_xiph::flac::metadata::*;
+
+use log::{debug, info};
+
+/// The FLAC start of stream marker: "fLaC" in ASCII.
+const FLAC_STREAM_MARKER: [u8; 4] = *b"fLaC";
+
+/// The vendor string written in the Vorbis Comment block.
+const FLAC_VENDOR_STRING: &str = "Symphonia";
+
+/// The length of a StreamInfo block in bytes.
+const FLAC_STREAM_INFO_LEN: usize = 34;
+
+/// The length of a seek point in bytes.
+const FLAC_SEEK_POINT_LEN: usize = 18;
+
+/// The maximum length of a metadata block in bytes.
+const FLAC_MAX_BLOCK_LEN: usize = 0xff_ffff;
+
+/// The maximum number of samples that may be stated in the StreamInfo block.
+const FLAC_MAX_STREAM_SAMPLES: u64 = 0xf_ffff_ffff;
+
+/// The maximum length of a frame that may be stated in the StreamInfo block.
+const FLAC_MAX_FRAME_LEN: u64 = 0xff_ffff;
+
+/// The interval between seek points in seconds.
+const SEEK_POINT_INTERVAL: u64 = 10;
+
+/// The number of seek points reserved if the length of the stream is unknown.
+const DEFAULT_SEEK_POINTS: usize = 128;
+
+/// Appends a metadata block, including its header, to the buffer.
+fn write_block(buf: &mut Vec<u8>, block_type: MetadataBlockType, is_last: bool, body: &[u8]) {
+    let header = MetadataBlockHeader { is_last, block_type, block_len: body.len() as u32 };
+
+    header.write(buf);
+    buf.extend_from_slice(body);
+}
+
+/// Free Lossless Audio Codec (FLAC) native frame writer.
+///
+/// `FlacWriter` implements a muxer for a single FLAC track, such as one encoded by `FlacEncoder`.
+/// Tags in the metadata revision are written as a Vorbis Comment block.
+///
+/// If the `MediaSink` is seekable, space for a seek table is reserved, and the seek table and
+/// StreamInfo block are completed when the writer is finalized. To include the MD5 checksum of the
+/// audio in the StreamInfo block, the finalized `CodecParameters` of the encoder must be provided
+/// via `update_codec_params` before finalizing the writer.
+pub struct FlacWriter {
+    sink: Box<dyn MediaSink>,
+    track_id: u32,
+    info: StreamInfo,
+    /// The position of the StreamInfo block's contents within the sink.
+    stream_info_pos: u64,
+    /// The position of the seek table block's contents within the sink, and the number of seek
+    /// points reserved.
+    seek_table: Option<(u64, usize)>,
+    /// The timestamp, position relative to the first frame, and length of every frame written.
+    frames: Vec<SeekPoint>,
+    /// The total number of bytes of frames written.
+    frames_len: u64,
+    /// The minimum and maximum length of a frame written.
+    frame_len_min: u64,
+    frame_len_max: u64,
+    /// The total number of samples written.
+    n_samples: u64,
+}
+
+impl FormatWriter for FlacWriter {
+
+    fn try_new(
+        mut sink: Box<dyn MediaSink>,
+        tracks: &[Track],
+        metadata: Option<&MetadataRevision>,
+    ) -> Result<Self> {
+        let track = match tracks {
+            [track] => track,
+            _ => return unsupported_error("flac only supports a single track"),
+        };
+
+        let params = &track.codec_params;
+
+        if params.codec != CODEC_TYPE_FLAC {
+            return unsupported_error("codec is not flac");
+        }
+
+        let sample_rate = match params.sample_rate {
+            Some(rate) if (1..=655_350).contains(&rate) => rate,
+            _ => return unsupported_error("flac sample rate must be between 1 and 655350 Hz"),
+        };
+
+        let channels = match params.channels {
+            Some(channels) if (1..=8).contains(&channels.count()) => channels,
+            _ => return unsupported_error("flac supports between 1 and 8 channels"),
+        };
+
+        let bits_per_sample = match params.bits_per_sample {
+            Some(bps) if (4..=32).contains(&bps) => bps,
+            _ => return unsupported_error("flac supports between 4 and 32 bits per sample"),
+        };
+
+        let block_len = match params.max_frames_per_packet {
+            Some(len) if (16..=0xffff).contains(&len) => len as u16,
+            _ => return unsupported_error("flac block length must be between 16 and 65535"),
+        };
+
+        // The StreamInfo block, as it is known now. If the sink is seekable, it will be updated
+        // when the writer is finalized.
+        let mut info = StreamInfo {
+            block_len_min: block_len,
+            block_len_max: block_len,
+            sample_rate,
+            channels,
+            bits_per_sample,
+            ..Default::default()
+        };
+
+        update_stream_info(&mut info, params);
+
+        let is_seekable = sink.is_seekable();
+
+        // Since the seek table can only be written once all frames are written, it is only
+        // written if the sink is seekable. Reserve a seek point for every interval if the length of
+        // the stream is known.
+        let n_seek_points = match params.n_frames {
+            _ if !is_seekable => 0,
+            Some(n_frames) => {
+                let interval = SEEK_POINT_INTERVAL * u64::from(sample_rate);
+                n_frames.div_ceil(interval) as usize
+            }
+            None => DEFAULT_SEEK_POINTS,
+        };
+
+        let n_seek_points = n_seek_points.min(FLAC_MAX_BLOCK_LEN / FLAC_SEEK_POINT_LEN);
+
+        // A Vorbis Comment block is always written since it contains the vendor string.
+        let tags = match metadata {
+            Some(metadata) => {
+                if !metadata.visuals().is_empty() {
+                    info!("ignoring {} visuals", metadata.visuals().len());
+                }
+                metadata.tags()
+            }
+            None => &[],
+        };
+
+        let mut comment = Vec::new();
+        write_comment_block(&mut comment, FLAC_VENDOR_STRING, tags);
+
+        if comment.len() > FLAC_MAX_BLOCK_LEN {
+            return limit_error("flac vorbis comment block is too large");
+        }
+
+        let mut buf = FLAC_STREAM_MARKER.to_vec();
+
+        let mut info_buf = Vec::with_capacity(FLAC_STREAM_INFO_LEN);
+        info.write(&mut info_buf);
+
+        write_block(&mut buf, MetadataBlockType::StreamInfo, false, &info_buf);
+
+        let stream_info_offset = buf.len() - FLAC_STREAM_INFO_LEN;
+
+        let seek_table_offset = if n_seek_points > 0 {
+            let mut seek_table = Vec::with_capacity(n_seek_points * FLAC_SEEK_POINT_LEN);
+            write_seek_table_block(&mut seek_table, &[], n_seek_points);
+
+            write_block(&mut buf, MetadataBlockType::SeekTable, false, &seek_table);
+
+            Some(buf.len() - seek_table.len())
+        }
+        else {
+            None
+        };
+
+        write_block(&mut buf, MetadataBlockType::VorbisComment, true, &comment);
+
+        let start_pos = if is_seekable { sink.stream_position()? } else { 0 };
+
+        sink.write_all(&buf)?;
+
+        debug!("wrote flac metadata: seek_points={}, len={}", n_seek_points, buf.len());
+
+        Ok(FlacWriter {
+            sink,
+            track_id: track.id,
+            info,
+            stream_info_pos: start_pos + stream_info_offset as u64,
+            seek_table: seek_table_offset.map(|offset| (start_pos + offset as u64, n_seek_points)),
+            frames: Vec::new(),
+            frames_len: 0,
+            frame_len_min: u64::MAX,
+            frame_len_max: 0,
+            n_samples: 0,
+        })
+    }
+
+    fn write_packet(&mut self, packet: &Packet) -> Result<()> {
+        if packet.track_id() != self.track_id {
+            return unsupported_error("packet does not belong to the flac track");
+        }
+
+        let len = packet.buf().len() as u64;
+
+        self.sink.write_all(packet.buf())?;
+
+        self.frame_len_min = self.frame_len_min.min(len);
+        self.frame_len_max = self.frame_len_max.max(len);
+
+        self.frames.push(SeekPoint {
+            frame_ts: self.n_samples,
+            byte_offset: self.frames_len,
+            n_frames: packet.duration() as u32,
+        });
+
+        self.frames_len += len;
+        self.n_samples += packet.duration();
+
+        Ok(())
+    }
+
+    fn update_codec_params(&mut self, track_id: u32, params: &CodecParameters) -> Result<()> {
+        if track_id == self.track_id {
+            update_stream_info(&mut self.info, params);
+        }
+
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        if self.sink.is_seekable() {
+            let end_pos = self.sink.stream_position()?;
+
+            // The total number of samples, and the frame length bounds, are now known. If they
+            // cannot be represented, they remain unknown.
+            if self.n_samples <= FLAC_MAX_STREAM_SAMPLES {
+                self.info.n_samples = Some(self.n_samples);
+            }
+
+            if !self.frames.is_empty() && self.frame_len_max <= FLAC_MAX_FRAME_LEN {
+                self.info.frame_byte_len_min = self.frame_len_min as u32;
+                self.info.frame_byte_len_max = self.frame_len_max as u32;
+            }
+
+            let mut buf = Vec::with_capacity(FLAC_STREAM_INFO_LEN);
+            self.info.write(&mut buf);
+
+            self.sink.seek(SeekFrom::Start(self.stream_info_pos))?;
+            self.sink.write_all(&buf)?;
+
+            if let Some((seek_table_pos, n_seek_points)) = self.seek_table {
+                let points = self.seek_points(n_seek_points);
+
+                let mut buf = Vec::with_capacity(n_seek_points * FLAC_SEEK_POINT_LEN);
+                write_seek_table_block(&mut buf, &points, n_seek_points);
+
+                self.sink.seek(SeekFrom::Start(seek_table_pos))?;
+                self.sink.write_all(&buf)?;
+
+                debug!("wrote flac seek table: seek_points={}", points.len());
+            }
+
+            self.sink.seek(SeekFrom::Start(end_pos))?;
+        }
+
+        self.sink.flush()?;
+
+        Ok(())
+    }
+
+    fn into_inner(self: Box<Self>) -> Box<dyn MediaSink> {
+        self.sink
+    }
+
+}
+
+impl FlacWriter {
+    /// Selects up-to `n_seek_points` frames, evenly spaced throughout the stream, as seek points.
+    fn seek_points(&self, n_seek_points: usize) -> Vec<SeekPoint> {
+        let mut points: Vec<SeekPoint> = Vec::with_capacity(n_seek_points);
+
+        if n_seek_points == 0 {
+            return points;
+        }
+
+        // Place a seek point every interval, unless that would require more seek points than were
+        // reserved.
+        let min_interval = SEEK_POINT_INTERVAL * u64::from(self.info.sample_rate);
+        let interval = min_interval.max(self.n_samples.div_ceil(n_seek_points as u64));
+
+        for i in 0..n_seek_points as u64 {
+            let ts = i * interval;
+
+            if ts >= self.n_samples {
+                break;
+            }
+
+            // Find the frame containing the timestamp.
+            let idx = self.frames.partition_point(|frame| frame.frame_ts <= ts) - 1;
+            let frame = &self.frames[idx];
+
+            // Seek points must be unique.
+            if points.last().map(|point| point.frame_ts) != Some(frame.frame_ts) {
+                points.push(*frame);
+            }
+        }
+
+        points
+    }
+}
+
+/// Updates the StreamInfo block with the codec parameters.
+fn update_stream_info(info: &mut StreamInfo, params: &CodecParameters) {
+    if let Some(VerificationCheck::Md5(md5)) = params.verification_check {
+        info.md5 = md5;
+    }
+
+    if let Some(n_frames) = params.n_frames {
+        if n_frames <= FLAC_MAX_STREAM_SAMPLES {
+            info.n_samples = Some(n_frames);
+        }
+    }
+}
@@ -136,9 +136,22 @@ impl PacketParser {
     }
 
     fn read_fragments<B: ReadBytes>(&mut self, reader: &mut B) -> Result<()> {
-        // Buffer more data if there is not enough to scan for fragments.
-        if self.buf_write - self.buf_read <= PacketParser::FLAC_MAX_FRAME_HEADER_LEN {
-            self.buffer_data(reader)?;
+        // Buffer more data if there is not enough to scan for fragments. If no more data can be
+        // read, the data that remains buffered must still be scanned since it may contain the
+        // final, possibly very short, frame of the stream.
+        let is_underrun = self.buf_write - self.buf_read <= PacketParser::FLAC_MAX_FRAME_HEADER_LEN;
+
+        let read_result = if is_underrun {
+            self.buffer_data(reader)
+        }
+        else {
+            Ok(())
+        };
+
+        let is_eos = read_result.is_err();
+
+        if self.buf_write < self.buf_read + 8 {
+            return read_result;
         }
 
         // Scan for fragments, 8 bytes at a time.
@@ -164,8 +177,10 @@ impl PacketParser {
                 // a valid FLAC frame header.
                 if (sync & 0xfffc) == 0xfff8 {
                     // If there are not enough bytes in the buffer to attempt parsing a frame then
-                    // no more fragments can be fetched.
-                    if pos + i - 1 + PacketParser::FLAC_MAX_FRAME_HEADER_LEN >= self.buf_write {
+                    // no more fragments can be fetched, unless no more data can be read.
+                    if pos + i - 1 + PacketParser::FLAC_MAX_FRAME_HEADER_LEN >= self.buf_write
+                        && !is_eos
+                    {
                         // trace!(
                         //     "found preamble, but not enough data is buffered, pos={}",
                         //     pos + i + 1
@@ -175,11 +190,16 @@ impl PacketParser {
                         return Ok(());
                     }
 
-                    let buf = &self.buf[pos + i + 1..pos + i + PacketParser::FLAC_MAX_FRAME_HEADER_LEN + 1];
+                    let end = min(
+                        pos + i + PacketParser::FLAC_MAX_FRAME_HEADER_LEN + 1,
+                        self.buf_write
+                    );
+
+                    let buf = &self.buf[min(pos + i + 1, end)..end];
 
                     // If the header buffer passes a quick sanity check, then attempt to parse the
                     // frame header in its entirety.
-                    if is_likely_frame_header(buf) {
+                    if buf.len() >= 2 && is_likely_frame_header(buf) {
                         if let Ok(header) = read_frame_header(&mut BufReader::new(buf), sync) {
                             // trace!(
                             //     "new fragment, ts={:?}, pos={}",
@@ -194,7 +214,7 @@ impl PacketParser {
             }
         }
 
-        Ok(())
+        read_result
     }
 
     fn remove_fragments(&mut self, to: usize) {
@@ -372,9 +392,15 @@ impl PacketParser {
 
                         let dur = u64::from(frag.header.block_num_samples);
 
+                        // For a fixed block size stream, every frame except the last is of the
+                        // fixed block size. The last frame may be shorter, so the duration of the
+                        // frame cannot be used to calculate the timestamp.
                         let ts = match &frag.header.block_sequence {
                             BlockSequence::BySample(sample) => *sample,
-                            BlockSequence::ByFrame(frame) => u64::from(*frame) * dur,
+                            BlockSequence::ByFrame(frame) => {
+                                let block_len = u64::from(self.stream_info.block_len_max);
+                                u64::from(*frame) * if block_len > 0 { block_len } else { dur }
+                            }
                         };
 
                         // Remove the fragments that have been consumed to parse this frame.
@@ -263,8 +263,11 @@ pub struct FrameHeader {
     pub sample_rate: u32,
     pub sample_rate_idx: usize,
     pub channel_mode: ChannelMode,
+    #[allow(dead_code)]
     pub emphasis: Emphasis,
+    #[allow(dead_code)]
     pub is_copyrighted: bool,
+    #[allow(dead_code)]
     pub is_original: bool,
     pub has_padding: bool,
     pub has_crc: bool,
@@ -278,6 +281,13 @@ impl FrameHeader {
         self.version == MpegVersion::Mpeg1
     }
 
+    /// Returns true if this a free format frame with an unknown bit-rate and frame size, false
+    /// otherwise.
+    #[inline(always)]
+    pub fn is_free_format(&self) -> bool {
+        self.bitrate == 0
+    }
+
     /// Returns true if this a MPEG2.5 frame, false otherwise.
     #[inline(always)]
     pub fn is_mpeg2p5(&self) -> bool {
@@ -295,6 +305,16 @@ impl FrameHeader {
         SignalSpec::new_with_layout(self.sample_rate, layout)
     }
 
+    /// Returns the number of audio frames (samples per channel) in the frame.
+    #[inline(always)]
+    pub fn duration(&self) -> u64 {
+        match self.layer {
+            MpegLayer::Layer1 => 384,
+            MpegLayer::Layer2 => 1152,
+            MpegLayer::Layer3 => SAMPLES_PER_GRANULE * self.n_granules() as u64,
+        }
+    }
+
     /// Returns the number of granules in the frame.
     #[inline(always)]
     pub fn n_granules(&self) -> usize {
@@ -5,36 +5,120 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use symphonia_core::audio::{AudioBuffer, AudioBufferRef, AsAudioBufferRef, Signal};
-use symphonia_core::codecs::{CODEC_TYPE_MP3, CodecParameters, CodecDescriptor};
-use symphonia_core::codecs::{Decoder, DecoderOptions, FinalizeResult};
-use symphonia_core::errors::{Result, decode_error};
+use symphonia_core::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, AsAudioBufferRef, Signal};
+use symphonia_core::codecs::{CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3};
+use symphonia_core::codecs::{CodecParameters, CodecDescriptor};
+use symphonia_core::codecs::{Concealer, Decoder, DecoderOptions, FinalizeResult};
+use symphonia_core::codecs::decode_into_native;
+use symphonia_core::errors::{Error, Result, decode_error};
 use symphonia_core::formats::Packet;
 use symphonia_core::support_codec;
 
-use super::{common::*, header, layer3};
+use super::{common::*, header, layer12, layer3};
+
+use log::warn;
 
 /// MPEG1 and MPEG2 Layer 1, 2, and 3 decoder.
 pub struct Mp3Decoder {
     params: CodecParameters,
+    is_gapless: bool,
     state: State,
+    concealer: Concealer<f32>,
+    /// The header of the last successfully decoded frame.
+    last_header: Option<FrameHeader>,
     buf: AudioBuffer<f32>,
 }
 
+impl Mp3Decoder {
+    fn decode_inner(&mut self, packet: &Packet) -> Result<()> {
+        let mut reader = packet.as_buf_reader();
+
+        let mut header = header::read_frame_header(&mut reader)?;
+
+        // The size of a free format frame is not signalled. However, a packet always contains
+        // exactly one frame.
+        if header.is_free_format() {
+            let len = packet.buf().len() - header::padding_len(&header);
+            header::set_free_format_frame_len(&mut header, len);
+        }
+
+        // The buffer can only be created after the first frame is decoded. The signal
+        // specification may also change throughout the stream, in which case the buffer must be
+        // reallocated.
+        if self.buf.is_unused() || *self.buf.spec() != header.spec() {
+            self.buf = AudioBuffer::new(1152, header.spec());
+        }
+
+        // Clear the audio output buffer.
+        self.buf.clear();
+
+        // Choose the decode step based on the MPEG layer and the current codec type.
+        let result = match header.layer {
+            MpegLayer::Layer1 if self.params.codec == CODEC_TYPE_MP1 => {
+                // Layer 1
+                layer12::decode_frame(&mut reader, &header, &mut self.state, &mut self.buf)
+            },
+            MpegLayer::Layer2 if self.params.codec == CODEC_TYPE_MP2 => {
+                // Layer 2
+                layer12::decode_frame(&mut reader, &header, &mut self.state, &mut self.buf)
+            },
+            MpegLayer::Layer3 if self.params.codec == CODEC_TYPE_MP3 => {
+                // Layer 3
+                layer3::decode_frame(&mut reader, &header, &mut self.state, &mut self.buf)
+            },
+            _ => decode_error("invalid mpeg audio layer"),
+        };
+
+        // Only the header of a successfully decoded frame can be trusted for concealment.
+        if result.is_ok() {
+            self.last_header = Some(header);
+        }
+
+        result
+    }
+
+    /// Conceals a damaged frame. The duration of the damaged frame is assumed to be the same as
+    /// the last successfully decoded frame.
+    fn conceal(&mut self, err: Error) -> Result<()> {
+        // The buffer may have been reallocated for a damaged frame with a different signal
+        // specification, in which case the frame cannot be concealed.
+        let header = match &self.last_header {
+            Some(header) if !self.buf.is_unused() && *self.buf.spec() == header.spec() => header,
+            _ => return Err(err),
+        };
+
+        warn!("concealing damaged frame: {}", err);
+
+        self.buf.clear();
+
+        match header.layer {
+            MpegLayer::Layer3 => layer3::conceal_frame(header, &mut self.state, &mut self.buf),
+            _ => layer12::conceal_frame(header, &mut self.state, &mut self.buf),
+        }
+
+        self.concealer.conceal(&mut self.buf);
+
+        Ok(())
+    }
+}
+
 impl Decoder for Mp3Decoder {
 
-    fn try_new(params: &CodecParameters, _: &DecoderOptions) -> Result<Self> {
+    fn try_new(params: &CodecParameters, options: &DecoderOptions) -> Result<Self> {
         Ok(Mp3Decoder {
             params: params.clone(),
+            is_gapless: options.gapless,
             state: State::new(),
+            concealer: Concealer::new(options.conceal),
+            last_header: None,
             buf: AudioBuffer::unused(),
         })
     }
 
     fn supported_codecs() -> &'static [CodecDescriptor] {
         &[
-            // support_codec!(CODEC_TYPE_MP1, "mp1", "MPEG Audio Layer 1"),
-            // support_codec!(CODEC_TYPE_MP2, "mp2", "MPEG Audio Layer 2"),
+            support_codec!(CODEC_TYPE_MP1, "mp1", "MPEG Audio Layer 1"),
+            support_codec!(CODEC_TYPE_MP2, "mp2", "MPEG Audio Layer 2"),
             support_codec!(CODEC_TYPE_MP3, "mp3", "MPEG Audio Layer 3"),
         ]
     }
@@ -46,35 +130,138 @@ impl Decoder for Mp3Decoder {
     fn reset(&mut self) {
         // Fully reset the decoder state.
         self.state = State::new();
+        self.concealer.reset();
     }
 
     fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
-        let mut reader = packet.as_buf_reader();
-
-        let header = header::read_frame_header(&mut reader)?;
+        match self.decode_inner(packet) {
+            Ok(()) => self.concealer.decoded(&self.buf),
+            Err(err) if self.concealer.can_conceal(&err) => self.conceal(err)?,
+            Err(err) => return Err(err),
+        }
 
-        // The buffer can only be created after the first frame is decoded. Technically, it can
-        // change throughout the stream as well...
-        if self.buf.is_unused() {
-            self.buf = AudioBuffer::new(1152, header.spec());
+        // Trim the padding for gapless playback.
+        if self.is_gapless {
+            let (start, end) = self.params.padding_trim(packet.pts(), self.buf.frames());
+            self.buf.trim(start, end);
         }
 
-        // Clear the audio output buffer.
-        self.buf.clear();
+        Ok(self.buf.as_audio_buffer_ref())
+    }
 
-        // Choose the decode step based on the MPEG layer and the current codec type.
-        match header.layer {
-            MpegLayer::Layer3 if self.params.codec == CODEC_TYPE_MP3 => {
-                // Layer 3
-                layer3::decode_frame(&mut reader, &header, &mut self.state, &mut self.buf)?;
-            },
-            _ => return decode_error("invalid mpeg audio layer"),
+    fn decode_into(&mut self, packet: &Packet, dest: AudioBufferMut<'_>) -> Result<()> {
+        match dest {
+            AudioBufferMut::F32(buf) => decode_into_native(self, packet, buf, |dec| &mut dec.buf),
+            mut dest => {
+                let decoded = self.decode(packet)?;
+                dest.copy_ref(&decoded)
+            }
         }
+    }
 
-        Ok(self.buf.as_audio_buffer_ref())
+    fn concealed_frames(&self) -> u64 {
+        self.concealer.concealed_frames()
     }
 
     fn finalize(&mut self) -> FinalizeResult {
         Default::default()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use symphonia_core::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, Signal};
+    use symphonia_core::codecs::{CodecParameters, Concealment, Decoder, DecoderOptions};
+    use symphonia_core::codecs::CODEC_TYPE_MP1;
+    use symphonia_core::errors::Error;
+    use symphonia_core::formats::Packet;
+
+    use super::Mp3Decoder;
+
+    /// Makes a silent 128 kbps, 44.1 kHz, MPEG1 Layer 1 frame. Since no sub-band has bits
+    /// allocated, the frame is all zeros after the header.
+    fn layer1_frame(is_mono: bool) -> Vec<u8> {
+        let mut frame = vec![0; 136];
+        frame[..4].copy_from_slice(&[0xff, 0xff, 0x40, if is_mono { 0xc0 } else { 0x00 }]);
+        frame
+    }
+
+    /// Makes a Layer 1 frame that is damaged such that it cannot be decoded.
+    fn damaged_layer1_frame(is_mono: bool) -> Vec<u8> {
+        let mut frame = layer1_frame(is_mono);
+        // A bit allocation of 15 is invalid.
+        frame[4] = 0xf0;
+        frame
+    }
+
+    fn packet(frame: Vec<u8>) -> Packet {
+        Packet::new_from_boxed_slice(0, 0, 384, frame.into_boxed_slice())
+    }
+
+    #[test]
+    fn verify_decode_channel_change() {
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_MP1);
+
+        let mut decoder = Mp3Decoder::try_new(&params, &DecoderOptions::default()).unwrap();
+
+        // The buffer is reallocated when the number of channels changes.
+        for &is_mono in [true, false, true].iter() {
+            match decoder.decode(&packet(layer1_frame(is_mono))).unwrap() {
+                AudioBufferRef::F32(buf) => {
+                    assert_eq!(buf.spec().channels.count(), if is_mono { 1 } else { 2 });
+                    assert_eq!(buf.frames(), 384);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn verify_decode_into_channel_change() {
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_MP1);
+
+        let mut decoder = Mp3Decoder::try_new(&params, &DecoderOptions::default()).unwrap();
+
+        let mut dest = AudioBuffer::<f32>::unused();
+        decoder.decode_into(&packet(layer1_frame(true)), AudioBufferMut::from(&mut dest)).unwrap();
+
+        let spec = *dest.spec();
+        assert_eq!(spec.channels.count(), 1);
+
+        // The destination is not reallocated when the number of channels changes.
+        let stereo = packet(layer1_frame(false));
+        let result = decoder.decode_into(&stereo, AudioBufferMut::from(&mut dest));
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+        assert!(*dest.spec() == spec);
+
+        // An unused destination is allocated with the new signal specification.
+        let mut dest = AudioBuffer::<f32>::unused();
+        decoder.decode_into(&packet(layer1_frame(false)), AudioBufferMut::from(&mut dest)).unwrap();
+        assert_eq!(dest.spec().channels.count(), 2);
+        assert_eq!(dest.frames(), 384);
+    }
+
+    #[test]
+    fn verify_conceal_channel_change() {
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_MP1);
+
+        let mut options = DecoderOptions::default();
+        options.with_conceal(Concealment::Silence);
+        let mut decoder = Mp3Decoder::try_new(&params, &options).unwrap();
+
+        assert!(decoder.decode(&packet(layer1_frame(true))).is_ok());
+
+        // A damaged frame with the same signal specification as the last decoded frame is
+        // concealed.
+        assert_eq!(decoder.decode(&packet(damaged_layer1_frame(true))).unwrap().frames(), 384);
+
+        // A damaged frame with a different signal specification cannot be concealed using the
+        // header of the last decoded frame.
+        assert!(decoder.decode(&packet(damaged_layer1_frame(false))).is_err());
+
+        assert!(decoder.decode(&packet(layer1_frame(true))).is_ok());
+        assert!(decoder.decode(&packet(damaged_layer1_frame(true))).is_ok());
+    }
+}
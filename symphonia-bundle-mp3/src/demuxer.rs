@@ -8,18 +8,18 @@
 use symphonia_core::support_format;
 
 use symphonia_core::checksum::Crc16AnsiLe;
-use symphonia_core::codecs::{CodecParameters, CODEC_TYPE_MP3};
-use symphonia_core::errors::{Result, SeekErrorKind, seek_error};
+use symphonia_core::codecs::{CodecParameters, CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3};
+use symphonia_core::errors::{Result, SeekErrorKind, decode_error, seek_error};
 use symphonia_core::formats::prelude::*;
 use symphonia_core::io::*;
-use symphonia_core::meta::{Metadata, MetadataLog};
+use symphonia_core::meta::{Metadata, MetadataLog, ReplayGain};
 use symphonia_core::probe::{Descriptor, Instantiate, QueryDescriptor};
 
 use std::io::{Seek, SeekFrom};
 
 use log::{debug, info, warn};
 
-use super::common::{ChannelMode, FrameHeader, MpegVersion, SAMPLES_PER_GRANULE};
+use super::common::{ChannelMode, FrameHeader, MpegLayer, MpegVersion};
 use super::header;
 
 /// MPEG1 and MPEG2 audio frame reader.
@@ -32,11 +32,36 @@ pub struct Mp3Reader {
     metadata: MetadataLog,
     first_frame_pos: u64,
     next_packet_ts: u64,
+    pool: BufPool,
+    /// The length of a free format frame excluding padding, if known.
+    free_format_len: Option<usize>,
 }
 
 impl QueryDescriptor for Mp3Reader {
     fn query() -> &'static [Descriptor] {
         &[
+            // Layer 1
+            support_format!(
+                "mp1",
+                "MPEG Audio Layer 1 Native",
+                &[ "mp1" ],
+                &[ "audio/mpeg" ],
+                &[
+                    &[ 0xff, 0xfe ], &[ 0xff, 0xff ], // MPEG 1
+                    &[ 0xff, 0xf6 ], &[ 0xff, 0xf7 ], // MPEG 2
+                    &[ 0xff, 0xe6 ], &[ 0xff, 0xe7 ], // MPEG 2.5
+                ]),
+            // Layer 2
+            support_format!(
+                "mp2",
+                "MPEG Audio Layer 2 Native",
+                &[ "mp2" ],
+                &[ "audio/mpeg" ],
+                &[
+                    &[ 0xff, 0xfc ], &[ 0xff, 0xfd ], // MPEG 1
+                    &[ 0xff, 0xf4 ], &[ 0xff, 0xf5 ], // MPEG 2
+                    &[ 0xff, 0xe4 ], &[ 0xff, 0xe5 ], // MPEG 2.5
+                ]),
             // Layer 3
             support_format!(
                 "mp3",
@@ -51,25 +76,83 @@ impl QueryDescriptor for Mp3Reader {
         ]
     }
 
-    fn score(_context: &[u8]) -> u8 {
-        255
+    fn score(context: &[u8]) -> u8 {
+        // A stray sync word is easily found in random data. Therefore, score the context by the
+        // number of consecutive frames, with consistent parameters, that it contains.
+        const MAX_FRAMES: usize = 4;
+
+        let mut first = None;
+        let mut n_frames = 0;
+        let mut offset = 0;
+
+        while n_frames < MAX_FRAMES && offset + 4 <= context.len() {
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(&context[offset..offset + 4]);
+
+            let sync = u32::from_be_bytes(buf);
+
+            if sync & 0xffe0_0000 != 0xffe0_0000 {
+                break;
+            }
+
+            let mut header = match header::parse_frame_header(sync) {
+                Ok(header) => header,
+                _ => break,
+            };
+
+            // The length of a free format frame must be found by searching for the next frame.
+            if header.is_free_format() {
+                match header::find_free_format_frame_len(sync, &context[offset + 4..]) {
+                    Some(len) => header::set_free_format_frame_len(&mut header, len),
+                    _ => break,
+                }
+            }
+
+            // All frames must have the same version, layer, and sample rate.
+            let params = (header.version, header.layer, header.sample_rate);
+
+            if *first.get_or_insert(params) != params {
+                break;
+            }
+
+            offset += header.frame_size + 4;
+            n_frames += 1;
+        }
+
+        // If the chain of frames was broken before the end of the context, then the first frame
+        // must be followed by at least one other frame for it to be considered valid.
+        if n_frames < 2 && offset + 4 <= context.len() {
+            return 0;
+        }
+
+        (n_frames * 64).min(255) as u8
     }
 }
 
 impl FormatReader for Mp3Reader {
 
     fn try_new(mut source: MediaSourceStream, _options: &FormatOptions) -> Result<Self> {
+        let pool = BufPool::default();
+
+        let mut free_format_len = None;
+
         // Try to read the first MPEG frame.
-        let (header, packet) = read_mpeg_frame(&mut source)?;
+        let (header, packet) = read_mpeg_frame(&mut source, &pool, &mut free_format_len)?;
 
         // Use the header to populate the codec parameters.
         let mut params = CodecParameters::new();
 
-        params.for_codec(CODEC_TYPE_MP3)
+        let codec = match header.layer {
+            MpegLayer::Layer1 => CODEC_TYPE_MP1,
+            MpegLayer::Layer2 => CODEC_TYPE_MP2,
+            MpegLayer::Layer3 => CODEC_TYPE_MP3,
+        };
+
+        params.for_codec(codec)
               .with_sample_rate(header.sample_rate)
               .with_channels(header.channel_mode.channels());
 
-        let audio_frames_per_mpeg_frame = SAMPLES_PER_GRANULE * header.n_granules() as u64;
+        let audio_frames_per_mpeg_frame = header.duration();
 
         // Check if there is a Xing/Info tag contained in the first frame.
         if let Some(info_tag) = try_read_info_tag(&packet, &header) {
@@ -82,6 +165,18 @@ impl FormatReader for Mp3Reader {
             if let Some(lame_tag) = info_tag.lame {
                 params.with_leading_padding(lame_tag.leading_padding)
                       .with_trailing_padding(lame_tag.trailing_padding);
+
+                // The radio and audiophile gains are the track and album gains, respectively.
+                let replay_gain = ReplayGain {
+                    track_gain: lame_tag.replaygain_radio,
+                    track_peak: lame_tag.replaygain_peak,
+                    album_gain: lame_tag.replaygain_audiophile,
+                    album_peak: None,
+                };
+
+                if !replay_gain.is_empty() {
+                    params.with_replay_gain(replay_gain);
+                }
             }
         }
         else {
@@ -93,7 +188,9 @@ impl FormatReader for Mp3Reader {
             if source.is_seekable() {
                 info!("estimating duration from bitrate, may be inaccurate for vbr files");
 
-                if let Some(n_mpeg_frames) = estimate_num_mpeg_frames(&mut source) {
+                let n_mpeg_frames = estimate_num_mpeg_frames(&mut source, free_format_len);
+
+                if let Some(n_mpeg_frames) = n_mpeg_frames {
                     params.with_n_frames(n_mpeg_frames * audio_frames_per_mpeg_frame);
                 }
             }
@@ -108,20 +205,23 @@ impl FormatReader for Mp3Reader {
             metadata: Default::default(),
             first_frame_pos,
             next_packet_ts: 0,
+            pool,
+            free_format_len,
         })
     }
 
     fn next_packet(&mut self) -> Result<Packet> {
-        let (header, packet) = read_mpeg_frame(&mut self.reader)?;
+        let (header, packet) =
+            read_mpeg_frame(&mut self.reader, &self.pool, &mut self.free_format_len)?;
 
-        // Each frame contains 1 or 2 granules with each granule being exactly 576 samples long.
-        let duration = SAMPLES_PER_GRANULE * header.n_granules() as u64;
+        // The duration of a frame depends on the layer, and for Layer 3, the number of granules.
+        let duration = header.duration();
 
         let ts = self.next_packet_ts;
 
         self.next_packet_ts += duration;
 
-        Ok(Packet::new_from_boxed_slice(0, ts, duration, packet.into_boxed_slice()))
+        Ok(Packet::new_from_shared_buf(0, ts, duration, packet.freeze()))
     }
 
     fn metadata(&mut self) -> Metadata<'_> {
@@ -187,13 +287,17 @@ impl FormatReader for Mp3Reader {
         // reached.
         loop {
             // Parse the next frame header.
-            let header = header::parse_frame_header(header::sync_frame(&mut self.reader)?)?;
+            let sync = header::sync_frame(&mut self.reader)?;
+
+            let mut header = header::parse_frame_header(sync)?;
+
+            resolve_free_format(&mut self.reader, &mut header, sync, &mut self.free_format_len)?;
 
             // Position of the frame header.
             let frame_pos = self.reader.pos() - std::mem::size_of::<u32>() as u64;
 
             // Calculate the duration of the frame.
-            let duration = SAMPLES_PER_GRANULE * header.n_granules() as u64;
+            let duration = header.duration();
 
             // Add the frame to the frame ring.
             frames[n_frames & REF_FRAMES_MASK] = FramePos { pos: frame_pos, ts: self.next_packet_ts };
@@ -206,7 +310,12 @@ impl FormatReader for Mp3Reader {
                 // its main data begins. Therefore, for a decoder to properly decode this frame, the
                 // reader must provide previous (reference) frames up-to and including the frame
                 // that contains the first byte this frame's main_data.
-                let main_data_begin = read_main_data_begin(&mut self.reader, &header)? as u64;
+                //
+                // Layer 1 and 2 frames are self-contained and never reference previous frames.
+                let main_data_begin = match header.layer {
+                    MpegLayer::Layer3 => read_main_data_begin(&mut self.reader, &header)? as u64,
+                    _ => 0,
+                };
 
                 debug!(
                     "found frame with ts={} @ pos={} with main_data_begin={}",
@@ -277,8 +386,12 @@ impl FormatReader for Mp3Reader {
 
 /// Reads a MPEG frame and returns the header and buffer.
 #[inline(always)]
-fn read_mpeg_frame(reader: &mut MediaSourceStream) -> Result<(FrameHeader, Vec<u8>)> {
-    let (header, header_word) = loop {
+fn read_mpeg_frame(
+    reader: &mut MediaSourceStream,
+    pool: &BufPool,
+    free_format_len: &mut Option<usize>,
+) -> Result<(FrameHeader, PooledBuf)> {
+    let (mut header, header_word) = loop {
         // Sync to the next frame header.
         let sync = header::sync_frame(reader)?;
 
@@ -291,8 +404,10 @@ fn read_mpeg_frame(reader: &mut MediaSourceStream) -> Result<(FrameHeader, Vec<u
         warn!("invalid mpeg audio header");
     };
 
-    // Allocate frame buffer.
-    let mut packet = vec![0u8; header.frame_size + 4];
+    resolve_free_format(reader, &mut header, header_word, free_format_len)?;
+
+    // Get a frame buffer from the pool.
+    let mut packet = pool.get(header.frame_size + 4);
     packet[0..4].copy_from_slice(&header_word.to_be_bytes());
 
     // Read the frame body.
@@ -302,6 +417,52 @@ fn read_mpeg_frame(reader: &mut MediaSourceStream) -> Result<(FrameHeader, Vec<u
     Ok((header, packet))
 }
 
+/// If the frame is a free format frame, sets the frame size and bit-rate of the frame header.
+///
+/// All frames of a free format stream have the same length, excluding padding. Therefore, the
+/// length is found once by searching for the next frame, and then cached in `free_format_len`.
+fn resolve_free_format(
+    reader: &mut MediaSourceStream,
+    header: &mut FrameHeader,
+    header_word: u32,
+    free_format_len: &mut Option<usize>,
+) -> Result<()> {
+    use std::io::Read;
+
+    // The maximum length of a free format frame, and the frame following it, to search.
+    const MAX_SEARCH_LEN: u64 = 8 * 1024;
+
+    if !header.is_free_format() {
+        return Ok(());
+    }
+
+    let len = match *free_format_len {
+        Some(len) => len,
+        None => {
+            // Read ahead, and then return to the end of the frame header.
+            let pos = reader.pos();
+
+            let mut buf = Vec::new();
+            reader.by_ref().take(MAX_SEARCH_LEN).read_to_end(&mut buf)?;
+
+            reader.seek_buffered(pos);
+
+            let len = match header::find_free_format_frame_len(header_word, &buf) {
+                Some(len) => len,
+                _ => return decode_error("could not find the length of a free format frame"),
+            };
+
+            debug!("free format frame length is {} bytes", len);
+
+            *free_format_len.get_or_insert(len)
+        }
+    };
+
+    header::set_free_format_frame_len(header, len);
+
+    Ok(())
+}
+
 #[derive(Default)]
 struct FramePos {
     ts: u64,
@@ -328,7 +489,10 @@ fn read_main_data_begin<B: ReadBytes>(reader: &mut B, header: &FrameHeader) -> R
 }
 
 /// Estimates the total number of MPEG frames in the media source stream.
-fn estimate_num_mpeg_frames(reader: &mut MediaSourceStream) -> Option<u64> {
+fn estimate_num_mpeg_frames(
+    reader: &mut MediaSourceStream,
+    free_format_len: Option<usize>,
+) -> Option<u64> {
     const MAX_FRAMES: u32 = 16;
     const MAX_LEN: usize  = 16 * 1024;
 
@@ -357,7 +521,14 @@ fn estimate_num_mpeg_frames(reader: &mut MediaSourceStream) -> Option<u64> {
         let header_val = break_on_err!(reader.read_be_u32());
 
         // Parse the frame header.
-        let header = break_on_err!(header::parse_frame_header(header_val));
+        let mut header = break_on_err!(header::parse_frame_header(header_val));
+
+        // The length of a free format frame is known after reading the first frame.
+        if let Some(len) = free_format_len {
+            if header.is_free_format() {
+                header::set_free_format_frame_len(&mut header, len);
+            }
+        }
 
         // Tabulate the size.
         total_frame_len += header.frame_size + 4;
@@ -405,6 +576,11 @@ struct XingInfoTag {
 
 /// Try to read a Xing/Info tag from the provided MPEG frame.
 fn try_read_info_tag(buf: &[u8], header: &FrameHeader) -> Option<XingInfoTag> {
+    // The Xing/Info tag is only present in Layer 3 frames.
+    if header.layer != MpegLayer::Layer3 {
+        return None;
+    }
+
     // The Info header is a completely optional piece of information. Therefore, flatten an error
     // reading the tag into a None.
     try_read_info_tag_inner(buf, header).ok().flatten()
@@ -487,10 +663,10 @@ fn try_read_info_tag_inner(buf: &[u8], header: &FrameHeader) -> Result<Option<Xi
         // Lowpass filter value.
         let _lowpass = reader.read_u8()?;
 
-        // Replay gain peak in 9.23 (bit) fixed-point format.
+        // Replay gain peak in 9.23 (bit) fixed-point format, where 1.0 is full scale.
         let replaygain_peak = match reader.read_be_u32()? {
             0 => None,
-            peak => Some(32767.0 * (peak as f32 / 2.0f32.powi(23))),
+            peak => Some(peak as f32 / 2.0f32.powi(23)),
         };
 
         // Radio replay gain.
@@ -510,8 +686,10 @@ fn try_read_info_tag_inner(buf: &[u8], header: &FrameHeader) -> Result<Option<Xi
 
             if encoder[..4] == *b"LAME" || encoder[..4] == *b"Lavf" || encoder[..4] == *b"Lavc" {
                 // These encoders always add a 529 sample delay on-top of the stated encoder delay.
+                // The decoder delay also shifts the padding at the end of the stream by the same
+                // amount, therefore it is subtracted from the stated padding.
                 let leading = 528 + 1 + (delay >> 12);
-                let trailing = delay & ((1 << 12) - 1);
+                let trailing = (delay & ((1 << 12) - 1)).saturating_sub(528 + 1);
 
                 (leading, trailing)
             }
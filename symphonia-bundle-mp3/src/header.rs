@@ -5,7 +5,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use symphonia_core::errors::{Result, decode_error, unsupported_error};
+use symphonia_core::errors::{Result, decode_error};
 use symphonia_core::io::ReadBytes;
 
 use crate::common::*;
@@ -50,6 +50,37 @@ static BIT_RATES_MPEG2_L23: [u32; 15] =
     64_000, 80_000, 96_000, 112_000, 128_000, 144_000, 160_000,
 ];
 
+/// The bits of a frame header that must be the same for all frames of a free format stream: the
+/// sync word, version, layer, protection, bit-rate, and sample rate.
+const FREE_FORMAT_HEADER_MASK: u32 = 0xffff_fc00;
+
+/// Gets the size of a slot in bytes. Layer 1 frames are composed of 4 byte slots, while Layer 2
+/// and 3 frames are composed of 1 byte slots.
+#[inline(always)]
+fn slot_size(layer: MpegLayer) -> usize {
+    match layer {
+        MpegLayer::Layer1 => 4,
+        _                 => 1,
+    }
+}
+
+/// Gets the number of slots in a frame per bit-rate to sample rate ratio.
+#[inline(always)]
+fn slots_per_frame(version: MpegVersion, layer: MpegLayer) -> u32 {
+    match (version, layer) {
+        (_,                  MpegLayer::Layer1) =>  12,
+        (_,                  MpegLayer::Layer2) => 144,
+        (MpegVersion::Mpeg1, MpegLayer::Layer3) => 144,
+        (_,                  MpegLayer::Layer3) =>  72,
+    }
+}
+
+/// Gets the length of the padding of a frame in bytes.
+#[inline(always)]
+pub fn padding_len(header: &FrameHeader) -> usize {
+    if header.has_padding { slot_size(header.layer) } else { 0 }
+}
+
 /// Quickly check if a header sync word may be valid.
 #[inline]
 pub fn check_header(header: u32) -> bool {
@@ -125,9 +156,9 @@ pub fn parse_frame_header(header: u32) -> Result<FrameHeader> {
     };
 
     let bitrate = match ((header & 0xf000) >> 12, version, layer) {
-        // "Free" bit-rate. Note, this is NOT variable bit-rate and is not a mandatory feature of
-        // MP3 decoders.
-        (0b0000, _, _) => return unsupported_error("free bit-rate is not supported"),
+        // "Free" bit-rate. Note, this is NOT variable bit-rate. The bit-rate is not signalled, and
+        // must be derived from the distance between frames.
+        (0b0000, _, _) => 0,
         // Invalid bit-rate.
         (0b1111, _, _) => return decode_error("invalid bit-rate"),
         // MPEG 1 bit-rates.
@@ -203,11 +234,17 @@ pub fn parse_frame_header(header: u32) -> Result<FrameHeader> {
 
     let has_crc = header & 0x1_0000 == 0;
 
-    // Calculate the size of the frame excluding this header.
-    let frame_size =
-        (if version == MpegVersion::Mpeg1 { 144 } else { 72 } * bitrate / sample_rate) as usize
-        + if has_padding { 1 } else { 0 }
-        - 4;
+    // Calculate the size of the frame excluding this header. The size of a free format frame is
+    // unknown.
+    let frame_size = if bitrate > 0 {
+        let slot_size = slot_size(layer);
+        let n_slots = (slots_per_frame(version, layer) * bitrate / sample_rate) as usize;
+
+        slot_size * n_slots + if has_padding { slot_size } else { 0 } - 4
+    }
+    else {
+        0
+    };
 
     Ok(FrameHeader{
         version,
@@ -230,3 +267,81 @@ pub fn read_frame_header<B: ReadBytes>(reader: &mut B) -> Result<FrameHeader> {
     // Synchronize and parse the frame header.
     parse_frame_header(sync_frame(reader)?)
 }
+
+/// Sets the frame size of a free format frame given the length of the frame, including the header,
+/// but excluding padding. The bit-rate is derived from the length.
+pub fn set_free_format_frame_len(header: &mut FrameHeader, len: usize) {
+    let n_slots = (len / slot_size(header.layer)) as u64;
+
+    let bitrate = n_slots * u64::from(header.sample_rate)
+        / u64::from(slots_per_frame(header.version, header.layer));
+
+    header.frame_size = len + padding_len(header) - 4;
+    header.bitrate = bitrate as u32;
+}
+
+/// Finds the length of a free format frame, including the header, but excluding padding.
+///
+/// Since the length of a free format frame is not signalled, it is found by searching `buf`, the
+/// data following the frame header, `header`, for the next frame header with the same parameters.
+/// If possible, the frame following the next frame is checked as well to gain confidence that the
+/// next frame is not random data.
+pub fn find_free_format_frame_len(header: u32, buf: &[u8]) -> Option<usize> {
+    let slot_size = if (header >> 17) & 0x3 == 0x3 { 4 } else { 1 };
+
+    let padding_len = |header: u32| if header & 0x200 != 0 { slot_size } else { 0 };
+
+    let read_header = |pos: usize| {
+        buf.get(pos..pos + 4).map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+    };
+
+    let is_match = |next: u32| next & FREE_FORMAT_HEADER_MASK == header & FREE_FORMAT_HEADER_MASK;
+
+    for pos in 1..buf.len() {
+        // The next frame header, if one starts at pos.
+        let next = match read_header(pos) {
+            Some(next) if is_match(next) => next,
+            Some(_) => continue,
+            None => break,
+        };
+
+        // The length of this frame including the header, but excluding padding.
+        let len = match (pos + 4).checked_sub(padding_len(header)) {
+            Some(len) if len > 4 => len,
+            _ => continue,
+        };
+
+        // Check the frame header following the next frame, if it is available.
+        match read_header(pos + len + padding_len(next)) {
+            Some(next_next) if !is_match(next_next) => continue,
+            _ => return Some(len),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_free_format_frame_len;
+
+    #[test]
+    fn verify_find_free_format_frame_len() {
+        // MPEG1 Layer 2, free format, 48 kHz, stereo.
+        let header: u32 = 0xfffd_0400;
+        let padded = header | 0x200;
+
+        let mut buf = vec![0u8; 1024];
+
+        // Frames of 300 bytes. The second frame is padded, and a header-like false sync is placed
+        // within the first frame.
+        buf[100..104].copy_from_slice(&header.to_be_bytes());
+        buf[296..300].copy_from_slice(&padded.to_be_bytes());
+        buf[597..601].copy_from_slice(&header.to_be_bytes());
+
+        assert_eq!(find_free_format_frame_len(header, &buf), Some(300));
+
+        // There is no next frame.
+        assert_eq!(find_free_format_frame_len(header, &buf[..296]), None);
+    }
+}
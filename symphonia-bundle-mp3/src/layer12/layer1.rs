@@ -0,0 +1,100 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::{Result, decode_error};
+use symphonia_core::io::{BitReaderLtr, ReadBitsLtr};
+
+use crate::common::FrameHeader;
+use super::{SubbandSamples, intensity_bound, read_scale_factor, requantize};
+
+/// The number of time-domain samples per sub-band in a Layer 1 frame.
+const N_SLOTS: usize = 12;
+
+/// Reads a 4-bit Layer 1 bit allocation, and returns the number of bits per sample.
+#[inline(always)]
+fn read_allocation(bs: &mut BitReaderLtr<'_>) -> Result<u32> {
+    match bs.read_bits_leq32(4)? {
+        // An allocation of 0 indicates that no samples are coded for the sub-band.
+        0 => Ok(0),
+        15 => decode_error("invalid layer 1 bit allocation"),
+        alloc => Ok(alloc + 1),
+    }
+}
+
+/// Reads and requantizes the sub-band samples of a Layer 1 frame as defined in section 2.4.1.5 of
+/// ISO/IEC 11172-3. Returns the number of time slots read.
+pub fn read_samples(
+    bs: &mut BitReaderLtr<'_>,
+    header: &FrameHeader,
+    samples: &mut SubbandSamples,
+) -> Result<usize> {
+
+    let n_channels = header.n_channels();
+
+    // Sub-bands starting from the bound are intensity stereo coded. The samples of these sub-bands
+    // are shared by both channels, but scaled by a scale factor per channel.
+    let bound = intensity_bound(header);
+
+    // The number of bits per sample for each sub-band of each channel.
+    let mut bits = [[0u32; 32]; 2];
+
+    for sb in 0..32 {
+        if sb < bound {
+            for ch_bits in bits[..n_channels].iter_mut() {
+                ch_bits[sb] = read_allocation(bs)?;
+            }
+        }
+        else {
+            let sb_bits = read_allocation(bs)?;
+
+            bits[0][sb] = sb_bits;
+            bits[1][sb] = sb_bits;
+        }
+    }
+
+    // A scale factor is only present for sub-bands with allocated bits.
+    let mut scale_factors = [[0f32; 32]; 2];
+
+    for sb in 0..32 {
+        for ch in 0..n_channels {
+            if bits[ch][sb] > 0 {
+                scale_factors[ch][sb] = read_scale_factor(bs)?;
+            }
+        }
+    }
+
+    for slot_samples in samples[..N_SLOTS].iter_mut() {
+        for sb in 0..32 {
+            if sb < bound {
+                for ch in 0..n_channels {
+                    let sb_bits = bits[ch][sb];
+
+                    if sb_bits > 0 {
+                        let code = bs.read_bits_leq32(sb_bits)?;
+                        let sample = requantize(code, (1 << sb_bits) - 1);
+
+                        slot_samples[ch][sb] = scale_factors[ch][sb] * sample;
+                    }
+                }
+            }
+            else {
+                let sb_bits = bits[0][sb];
+
+                if sb_bits > 0 {
+                    let code = bs.read_bits_leq32(sb_bits)?;
+                    let sample = requantize(code, (1 << sb_bits) - 1);
+
+                    for ch in 0..n_channels {
+                        slot_samples[ch][sb] = scale_factors[ch][sb] * sample;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(N_SLOTS)
+}
@@ -0,0 +1,319 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::Result;
+use symphonia_core::io::{BitReaderLtr, ReadBitsLtr};
+
+use crate::common::FrameHeader;
+use super::{SubbandSamples, intensity_bound, read_scale_factor, requantize};
+
+/// The number of time-domain samples per sub-band in a Layer 2 frame.
+const N_SLOTS: usize = 36;
+
+/// A Layer 2 quantization class as defined in Table B.4 of ISO/IEC 11172-3.
+struct QuantClass {
+    /// The number of quantization steps.
+    steps: u32,
+    /// The number of bits per codeword.
+    bits: u32,
+    /// If true, a codeword contains a group of three consecutive samples.
+    grouped: bool,
+}
+
+/// The Layer 2 quantization classes.
+const QUANT_CLASSES: [QuantClass; 17] = [
+    QuantClass { steps:     3, bits:  5, grouped: true  },
+    QuantClass { steps:     5, bits:  7, grouped: true  },
+    QuantClass { steps:     7, bits:  3, grouped: false },
+    QuantClass { steps:     9, bits: 10, grouped: true  },
+    QuantClass { steps:    15, bits:  4, grouped: false },
+    QuantClass { steps:    31, bits:  5, grouped: false },
+    QuantClass { steps:    63, bits:  6, grouped: false },
+    QuantClass { steps:   127, bits:  7, grouped: false },
+    QuantClass { steps:   255, bits:  8, grouped: false },
+    QuantClass { steps:   511, bits:  9, grouped: false },
+    QuantClass { steps:  1023, bits: 10, grouped: false },
+    QuantClass { steps:  2047, bits: 11, grouped: false },
+    QuantClass { steps:  4095, bits: 12, grouped: false },
+    QuantClass { steps:  8191, bits: 13, grouped: false },
+    QuantClass { steps: 16383, bits: 14, grouped: false },
+    QuantClass { steps: 32767, bits: 15, grouped: false },
+    QuantClass { steps: 65535, bits: 16, grouped: false },
+];
+
+// The possible quantization classes of a sub-band, indexed by the bit allocation of the sub-band
+// minus 1. The number of bits used to code the bit allocation of a sub-band is implied by the
+// number of classes, such that a sub-band with 2^nbal - 1 classes uses nbal bits.
+const CLASSES_A0: [u8; 15] = [0, 2, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+const CLASSES_A1: [u8; 15] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 16];
+const CLASSES_A2: [u8;  7] = [0, 1, 2, 3, 4, 5, 16];
+const CLASSES_A3: [u8;  3] = [0, 1, 16];
+const CLASSES_C0: [u8; 15] = [0, 1, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+const CLASSES_C1: [u8;  7] = [0, 1, 3, 4, 5, 6, 7];
+const CLASSES_L0: [u8; 15] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14];
+const CLASSES_L1: [u8;  3] = [0, 1, 3];
+
+/// Bit allocation table for MPEG1 at high bit-rates, as defined in Table B.2a of ISO/IEC 11172-3.
+static ALLOC_TABLE_A: [&[u8]; 27] = [
+    &CLASSES_A0, &CLASSES_A0, &CLASSES_A0, &CLASSES_A1, &CLASSES_A1, &CLASSES_A1, &CLASSES_A1,
+    &CLASSES_A1, &CLASSES_A1, &CLASSES_A1, &CLASSES_A1, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2,
+    &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2,
+    &CLASSES_A2, &CLASSES_A2, &CLASSES_A3, &CLASSES_A3, &CLASSES_A3, &CLASSES_A3,
+];
+
+/// Bit allocation table for MPEG1 at high bit-rates, as defined in Table B.2b of ISO/IEC 11172-3.
+static ALLOC_TABLE_B: [&[u8]; 30] = [
+    &CLASSES_A0, &CLASSES_A0, &CLASSES_A0, &CLASSES_A1, &CLASSES_A1, &CLASSES_A1, &CLASSES_A1,
+    &CLASSES_A1, &CLASSES_A1, &CLASSES_A1, &CLASSES_A1, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2,
+    &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2, &CLASSES_A2,
+    &CLASSES_A2, &CLASSES_A2, &CLASSES_A3, &CLASSES_A3, &CLASSES_A3, &CLASSES_A3, &CLASSES_A3,
+    &CLASSES_A3, &CLASSES_A3,
+];
+
+/// Bit allocation table for MPEG1 at low bit-rates, as defined in Table B.2c of ISO/IEC 11172-3.
+static ALLOC_TABLE_C: [&[u8]; 8] = [
+    &CLASSES_C0, &CLASSES_C0, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1,
+    &CLASSES_C1,
+];
+
+/// Bit allocation table for MPEG1 at low bit-rates, as defined in Table B.2d of ISO/IEC 11172-3.
+static ALLOC_TABLE_D: [&[u8]; 12] = [
+    &CLASSES_C0, &CLASSES_C0, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1,
+    &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1,
+];
+
+/// Bit allocation table for MPEG2 low sampling frequencies, as defined in Table B.1 of
+/// ISO/IEC 13818-3.
+static ALLOC_TABLE_LSF: [&[u8]; 30] = [
+    &CLASSES_L0, &CLASSES_L0, &CLASSES_L0, &CLASSES_L0, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1,
+    &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_C1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1,
+    &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1,
+    &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1, &CLASSES_L1,
+    &CLASSES_L1, &CLASSES_L1,
+];
+
+/// Selects the bit allocation table for a frame. The table depends on the MPEG version, sample
+/// rate, and bit-rate per channel.
+fn select_alloc_table(header: &FrameHeader) -> &'static [&'static [u8]] {
+    if !header.is_mpeg1() {
+        return &ALLOC_TABLE_LSF;
+    }
+
+    let bitrate_per_channel = header.bitrate / header.n_channels() as u32;
+
+    match (header.sample_rate, bitrate_per_channel) {
+        (48_000, 56_000..=u32::MAX) | (_, 56_000..=80_000) => &ALLOC_TABLE_A,
+        (_, 96_000..=u32::MAX) => &ALLOC_TABLE_B,
+        (32_000, _) => &ALLOC_TABLE_D,
+        (_, 0..=48_000) => &ALLOC_TABLE_C,
+        _ => &ALLOC_TABLE_D,
+    }
+}
+
+/// Reads the bit allocation of a sub-band, and returns the quantization class of the sub-band if
+/// it has allocated bits.
+#[inline(always)]
+fn read_allocation(
+    bs: &mut BitReaderLtr<'_>,
+    classes: &[u8],
+) -> Result<Option<&'static QuantClass>> {
+    let nbal = (classes.len() as u32 + 1).trailing_zeros();
+
+    let class = match bs.read_bits_leq32(nbal)? as usize {
+        0 => None,
+        alloc => Some(&QUANT_CLASSES[usize::from(classes[alloc - 1])]),
+    };
+
+    Ok(class)
+}
+
+/// Reads the codes of three consecutive samples.
+#[inline(always)]
+fn read_triplet(bs: &mut BitReaderLtr<'_>, class: &QuantClass) -> Result<[u32; 3]> {
+    let mut codes = [0; 3];
+
+    if class.grouped {
+        // The codes of a group are packed into a single codeword, with the first code in the
+        // least-significant position.
+        let mut codeword = bs.read_bits_leq32(class.bits)?;
+
+        for code in codes.iter_mut() {
+            *code = codeword % class.steps;
+            codeword /= class.steps;
+        }
+    }
+    else {
+        for code in codes.iter_mut() {
+            *code = bs.read_bits_leq32(class.bits)?;
+        }
+    }
+
+    Ok(codes)
+}
+
+/// Reads and requantizes the sub-band samples of a Layer 2 frame as defined in section 2.4.1.6 of
+/// ISO/IEC 11172-3. Returns the number of time slots read.
+pub fn read_samples(
+    bs: &mut BitReaderLtr<'_>,
+    header: &FrameHeader,
+    samples: &mut SubbandSamples,
+) -> Result<usize> {
+
+    let n_channels = header.n_channels();
+
+    let alloc_table = select_alloc_table(header);
+
+    // Sub-bands past the sub-band limit are not coded.
+    let sblimit = alloc_table.len();
+
+    // Sub-bands starting from the bound are intensity stereo coded. The samples of these sub-bands
+    // are shared by both channels, but scaled by a scale factor per channel.
+    let bound = intensity_bound(header).min(sblimit);
+
+    // The quantization class of each sub-band of each channel.
+    let mut classes: [[Option<&QuantClass>; 32]; 2] = [[None; 32]; 2];
+
+    for (sb, &sb_classes) in alloc_table.iter().enumerate() {
+        if sb < bound {
+            for ch_classes in classes[..n_channels].iter_mut() {
+                ch_classes[sb] = read_allocation(bs, sb_classes)?;
+            }
+        }
+        else {
+            let class = read_allocation(bs, sb_classes)?;
+
+            classes[0][sb] = class;
+            classes[1][sb] = class;
+        }
+    }
+
+    // The scale factor selection information indicates how many scale factors are transmitted for
+    // each sub-band with allocated bits.
+    let mut scfsi = [[0u8; 32]; 2];
+
+    for sb in 0..sblimit {
+        for ch in 0..n_channels {
+            if classes[ch][sb].is_some() {
+                scfsi[ch][sb] = bs.read_bits_leq32(2)? as u8;
+            }
+        }
+    }
+
+    // A frame is divided into three parts of 12 time slots each. Each part may have its own scale
+    // factor, or share the scale factor of another part.
+    let mut scale_factors = [[[0f32; 3]; 32]; 2];
+
+    for sb in 0..sblimit {
+        for ch in 0..n_channels {
+            if classes[ch][sb].is_none() {
+                continue;
+            }
+
+            scale_factors[ch][sb] = match scfsi[ch][sb] {
+                0 => {
+                    let a = read_scale_factor(bs)?;
+                    let b = read_scale_factor(bs)?;
+                    let c = read_scale_factor(bs)?;
+                    [a, b, c]
+                }
+                1 => {
+                    let a = read_scale_factor(bs)?;
+                    let c = read_scale_factor(bs)?;
+                    [a, a, c]
+                }
+                2 => {
+                    let a = read_scale_factor(bs)?;
+                    [a, a, a]
+                }
+                _ => {
+                    let a = read_scale_factor(bs)?;
+                    let b = read_scale_factor(bs)?;
+                    [a, b, b]
+                }
+            };
+        }
+    }
+
+    // The samples are read in granules of three time slots.
+    for gr in 0..(N_SLOTS / 3) {
+        let part = gr / 4;
+        let slot = 3 * gr;
+
+        for sb in 0..sblimit {
+            if sb < bound {
+                for ch in 0..n_channels {
+                    if let Some(class) = classes[ch][sb] {
+                        let codes = read_triplet(bs, class)?;
+                        let scale_factor = scale_factors[ch][sb][part];
+
+                        for (i, &code) in codes.iter().enumerate() {
+                            let sample = requantize(code, class.steps);
+
+                            samples[slot + i][ch][sb] = scale_factor * sample;
+                        }
+                    }
+                }
+            }
+            else if let Some(class) = classes[0][sb] {
+                let codes = read_triplet(bs, class)?;
+
+                for (i, &code) in codes.iter().enumerate() {
+                    let sample = requantize(code, class.steps);
+
+                    for ch in 0..n_channels {
+                        samples[slot + i][ch][sb] = scale_factors[ch][sb][part] * sample;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(N_SLOTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_alloc_tables() {
+        // The number of bits allocated to the bit allocation of each sub-band, as tabulated in
+        // Table B.2 of ISO/IEC 11172-3, and Table B.1 of ISO/IEC 13818-3.
+        let tables: [(&[&[u8]], &[u32]); 5] = [
+            (&ALLOC_TABLE_A, &[4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+                               2, 2, 2, 2]),
+            (&ALLOC_TABLE_B, &[4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+                               2, 2, 2, 2, 2, 2, 2]),
+            (&ALLOC_TABLE_C, &[4, 4, 3, 3, 3, 3, 3, 3]),
+            (&ALLOC_TABLE_D, &[4, 4, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3]),
+            (&ALLOC_TABLE_LSF, &[4, 4, 4, 4, 3, 3, 3, 3, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+                                 2, 2, 2, 2, 2, 2, 2, 2, 2]),
+        ];
+
+        for (table, nbal) in tables.iter() {
+            assert_eq!(table.len(), nbal.len());
+
+            for (classes, &nbal) in table.iter().zip(nbal.iter()) {
+                assert_eq!(classes.len() as u32, (1 << nbal) - 1);
+
+                // Quantization classes are in increasing order of precision.
+                assert!(classes.windows(2).all(|w| w[0] < w[1]));
+            }
+        }
+    }
+
+    #[test]
+    fn verify_read_triplet() {
+        // A grouped 9 step codeword of the codes [7, 2, 5].
+        let codeword = 7 + 9 * (2 + 9 * 5);
+
+        let buf = [(codeword >> 2) as u8, (codeword << 6) as u8];
+        let mut bs = BitReaderLtr::new(&buf);
+
+        assert_eq!(read_triplet(&mut bs, &QUANT_CLASSES[3]).unwrap(), [7, 2, 5]);
+    }
+}
@@ -0,0 +1,176 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `layer12` module implements decoding of MPEG audio Layer 1 and Layer 2 frames.
+//!
+//! Layers 1 and 2 share the same principles. The sub-band samples of a frame are quantized
+//! according to a bit allocation, and are scaled by a scale factor per sub-band. After
+//! requantization, the sub-band samples are directly transformed into PCM samples by the polyphase
+//! synthesis filterbank.
+
+use symphonia_core::audio::{AudioBuffer, Signal};
+use symphonia_core::errors::{Result, decode_error};
+use symphonia_core::io::{BitReaderLtr, BufReader, ReadBitsLtr, ReadBytes};
+
+use lazy_static::lazy_static;
+
+use super::synthesis;
+use crate::common::*;
+
+mod layer1;
+mod layer2;
+
+/// The maximum number of time-domain samples per sub-band in a frame.
+const MAX_SLOTS: usize = 36;
+
+/// The sub-band samples of a frame. Samples are indexed by time slot, channel, then sub-band.
+type SubbandSamples = [[[f32; 32]; 2]; MAX_SLOTS];
+
+lazy_static! {
+    /// Lookup table of scale factors as defined in Table B.1 of ISO/IEC 11172-3. Scale factor
+    /// index 63 is invalid.
+    static ref SCALE_FACTORS: [f32; 63] = {
+        let mut scale_factors = [0f32; 63];
+        for (i, scale_factor) in scale_factors.iter_mut().enumerate() {
+            *scale_factor = (2.0f64 * 2.0f64.powf(-(i as f64) / 3.0)) as f32;
+        }
+        scale_factors
+    };
+}
+
+/// Reads a 6-bit scale factor index and returns the scale factor.
+#[inline(always)]
+fn read_scale_factor(bs: &mut BitReaderLtr<'_>) -> Result<f32> {
+    match SCALE_FACTORS.get(bs.read_bits_leq32(6)? as usize) {
+        Some(&scale_factor) => Ok(scale_factor),
+        _ => decode_error("invalid scale factor"),
+    }
+}
+
+/// Requantizes the sample `code` that was quantized with `steps` quantization steps to the range
+/// (-1.0, 1.0).
+///
+/// This is equivalent to the method described in section 2.4.3.2.4 of ISO/IEC 11172-3 where, after
+/// inverting the most-significant bit of the code, the requantized sample is `C * (code + D)`.
+#[inline(always)]
+fn requantize(code: u32, steps: u32) -> f32 {
+    (2.0 * code as f32 - (steps - 1) as f32) / steps as f32
+}
+
+/// Gets the first sub-band that is intensity stereo coded. All sub-bands are independently coded
+/// if intensity stereo is not used.
+#[inline(always)]
+fn intensity_bound(header: &FrameHeader) -> usize {
+    match header.channel_mode {
+        ChannelMode::JointStereo(Mode::Intensity { bound }) => bound as usize,
+        _ => 32,
+    }
+}
+
+/// Transforms the first `n_slots` time slots of sub-band samples into PCM samples.
+fn synthesis(
+    header: &FrameHeader,
+    samples: &SubbandSamples,
+    n_slots: usize,
+    state: &mut State,
+    out: &mut AudioBuffer<f32>,
+) {
+    out.render_reserved(Some(32 * n_slots));
+
+    for ch in 0..header.n_channels() {
+        let out_ch_samples = out.chan_mut(ch);
+
+        for (slot, out_block) in samples[..n_slots].iter().zip(out_ch_samples.chunks_mut(32)) {
+            synthesis::synthesis_block(&mut state.synthesis[ch], &slot[ch], out_block);
+        }
+    }
+}
+
+/// Decode the MPEG audio Layer 1 or Layer 2 frame into an `AudioBuffer`.
+pub fn decode_frame(
+    reader: &mut BufReader<'_>,
+    header: &FrameHeader,
+    state: &mut State,
+    out: &mut AudioBuffer<f32>,
+) -> Result<()> {
+
+    let _crc = if header.has_crc {
+        Some(reader.read_be_u16()?)
+    }
+    else {
+        None
+    };
+
+    let mut bs = BitReaderLtr::new(reader.read_buf_bytes_available_ref());
+
+    let mut samples: SubbandSamples = [[[0f32; 32]; 2]; MAX_SLOTS];
+
+    // Read and requantize the sub-band samples.
+    let n_slots = match header.layer {
+        MpegLayer::Layer1 => layer1::read_samples(&mut bs, header, &mut samples)?,
+        MpegLayer::Layer2 => layer2::read_samples(&mut bs, header, &mut samples)?,
+        _ => return decode_error("invalid mpeg audio layer"),
+    };
+
+    synthesis(header, &samples, n_slots, state, out);
+
+    Ok(())
+}
+
+/// Conceals a damaged frame by synthesizing a frame from silent sub-band samples. This completes
+/// the last decoded frame, whose remainder is still held in the state of the polyphase filterbank,
+/// such that the output fades to silence without a discontinuity.
+pub fn conceal_frame(header: &FrameHeader, state: &mut State, out: &mut AudioBuffer<f32>) {
+    let samples: SubbandSamples = [[[0f32; 32]; 2]; MAX_SLOTS];
+
+    synthesis(header, &samples, header.duration() as usize / 32, state, out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{requantize, SCALE_FACTORS};
+
+    #[test]
+    fn verify_requantize() {
+        // The C and D constants from Table B.4 of ISO/IEC 11172-3 for a subset of the number of
+        // quantization steps.
+        const CLASSES: [(u32, f64, f64); 10] = [
+            (    3, 1.333_333_333_33, 0.500_000_000_00),
+            (    5, 1.600_000_000_00, 0.500_000_000_00),
+            (    7, 1.142_857_142_86, 0.250_000_000_00),
+            (    9, 1.777_777_777_77, 0.500_000_000_00),
+            (   15, 1.066_666_666_66, 0.125_000_000_00),
+            (   31, 1.032_258_064_52, 0.062_500_000_00),
+            (   63, 1.015_873_015_87, 0.031_250_000_00),
+            (  127, 1.007_874_015_75, 0.015_625_000_00),
+            (  255, 1.003_921_568_63, 0.007_812_500_00),
+            (65535, 1.000_015_259_02, 0.000_030_517_58),
+        ];
+
+        for &(steps, c, d) in CLASSES.iter() {
+            // The number of bits required to represent a code.
+            let nb = 32 - (steps - 1).leading_zeros();
+
+            for code in (0..steps).step_by(1 + steps as usize / 256) {
+                // Invert the most-significant bit and interpret the code as a fraction.
+                let frac = f64::from(code as i32 - (1 << (nb - 1))) / f64::from(1u32 << (nb - 1));
+
+                let expected = c * (frac + d);
+                assert!((f64::from(requantize(code, steps)) - expected).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_scale_factors() {
+        // Spot check against Table B.1 of ISO/IEC 11172-3.
+        assert!((SCALE_FACTORS[0] - 2.0).abs() < 1e-6);
+        assert!((SCALE_FACTORS[1] - 1.587_401_051_968_20).abs() < 1e-6);
+        assert!((SCALE_FACTORS[3] - 1.0).abs() < 1e-6);
+        assert!((SCALE_FACTORS[62] - 0.000_001_201_554_35).abs() < 1e-12);
+    }
+}
@@ -339,3 +339,41 @@ pub fn decode_frame(
     Ok(())
 }
 
+/// Conceals a damaged frame by synthesizing a frame from silent spectral samples. This completes
+/// the last decoded frame, whose remainder is still held in the overlap state of the hybrid
+/// synthesis and polyphase filterbank, such that the output fades to silence without a
+/// discontinuity.
+pub fn conceal_frame(header: &FrameHeader, state: &mut State, out: &mut AudioBuffer<f32>) {
+    // A long block without any spectral samples.
+    let channel: GranuleChannel = Default::default();
+
+    let n_channels = header.n_channels().min(out.spec().channels.count());
+
+    for gr in 0..header.n_granules() {
+        out.render_reserved(Some(576));
+
+        for ch in 0..n_channels {
+            let samples = &mut state.samples[gr][ch];
+
+            *samples = [0.0; 576];
+
+            hybrid_synthesis::hybrid_synthesis(&channel, &mut state.overlap[ch], samples);
+
+            hybrid_synthesis::frequency_inversion(samples);
+
+            let out_ch_samples = out.chan_mut(ch);
+
+            synthesis::synthesis(
+                &mut state.synthesis[ch],
+                samples,
+                &mut out_ch_samples[(gr * 576)..((gr + 1) * 576)],
+            );
+        }
+
+        // Any remaining channels are silent.
+        for ch in n_channels..out.spec().channels.count() {
+            out.chan_mut(ch)[(gr * 576)..((gr + 1) * 576)].fill(0.0);
+        }
+    }
+}
+
@@ -21,6 +21,7 @@ mod common;
 mod decoder;
 mod demuxer;
 mod header;
+mod layer12;
 mod layer3;
 mod synthesis;
 
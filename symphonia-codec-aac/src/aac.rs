@@ -14,23 +14,27 @@
 use std::f32::consts;
 use std::fmt;
 
-use symphonia_core::errors::{decode_error, unsupported_error, Result};
+use symphonia_core::errors::{decode_error, unsupported_error, Error, Result};
 use symphonia_core::io::{ReadBitsLtr, FiniteBitStream, BitReaderLtr};
 use symphonia_core::io::vlc::{Codebook, Entry16x16};
-use symphonia_core::audio::{AudioBuffer, AudioBufferRef, AsAudioBufferRef, Signal, SignalSpec};
+use symphonia_core::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, AsAudioBufferRef};
+use symphonia_core::audio::{Signal, SignalSpec};
 use symphonia_core::codecs::{CODEC_TYPE_AAC, CodecParameters, CodecDescriptor};
-use symphonia_core::codecs::{Decoder, DecoderOptions, FinalizeResult};
-use symphonia_core::dsp::mdct::Imdct;
+use symphonia_core::codecs::{Concealer, Decoder, DecoderOptions, FinalizeResult};
+use symphonia_core::codecs::decode_into_native;
+use symphonia_core::dsp::mdct::{Imdct, Mdct};
+use symphonia_core::dsp::simd;
 use symphonia_core::formats::Packet;
 use symphonia_core::support_codec;
 use symphonia_core::units::Duration;
 
 use super::codebooks;
 use super::common::*;
+use super::sbr::{Sbr, SbrDsp, EXT_SBR_DATA, EXT_SBR_DATA_CRC};
 use super::window::*;
 
 use lazy_static::lazy_static;
-use log::{error, trace};
+use log::{error, trace, warn};
 
 macro_rules! validate {
     ($a:expr) => {
@@ -66,7 +70,8 @@ struct M4AInfo {
     samples: usize,
     sbr_ps_info: Option<(u32, usize)>,
     sbr_present: bool,
-    ps_present: bool,
+    /// If PS is explicitly signalled as present or absent.
+    ps_present: Option<bool>,
 }
 
 impl M4AInfo {
@@ -78,7 +83,7 @@ impl M4AInfo {
             samples: 0,
             sbr_ps_info: Option::None,
             sbr_present: false,
-            ps_present: false,
+            ps_present: None,
         }
     }
 
@@ -127,6 +132,10 @@ impl M4AInfo {
         self.channels = Self::read_channel_config(&mut bs)?;
 
         if (self.otype == M4AType::SBR) || (self.otype == M4AType::PS) {
+            if self.otype == M4AType::PS {
+                self.ps_present = Some(true);
+            }
+
             let ext_srate = Self::read_sampling_frequency(&mut bs)?;
             self.otype = Self::read_object_type(&mut bs)?;
 
@@ -138,12 +147,14 @@ impl M4AInfo {
             };
 
             self.sbr_ps_info = Some((ext_srate, ext_chans));
+            self.sbr_present = true;
         }
 
         match self.otype {
             M4AType::Main
             | M4AType::LC
             | M4AType::SSR
+            | M4AType::LTP
             | M4AType::Scalable
             | M4AType::TwinVQ
             | M4AType::ER_AAC_LC
@@ -274,7 +285,9 @@ impl M4AInfo {
             _ => {}
         };
 
-        if self.sbr_ps_info.is_some() && (bs.bits_left() >= 16) {
+        // Backwards compatible explicit signalling of SBR and PS is appended to the end of the
+        // audio specific config.
+        if self.sbr_ps_info.is_none() && (bs.bits_left() >= 16) {
             let sync = bs.read_bits_leq32(11)?;
 
             if sync == 0x2B7 {
@@ -282,11 +295,12 @@ impl M4AInfo {
                 if ext_otype == M4AType::SBR {
                     self.sbr_present = bs.read_bit()?;
                     if self.sbr_present {
-                        let _ext_srate = Self::read_sampling_frequency(&mut bs)?;
+                        let ext_srate = Self::read_sampling_frequency(&mut bs)?;
+                        self.sbr_ps_info = Some((ext_srate, 0));
                         if bs.bits_left() >= 12 {
                             let sync = bs.read_bits_leq32(11)?;
                             if sync == 0x548 {
-                                self.ps_present = bs.read_bit()?;
+                                self.ps_present = Some(bs.read_bit()?);
                             }
                         }
                     }
@@ -294,7 +308,9 @@ impl M4AInfo {
                 if ext_otype == M4AType::PS {
                     self.sbr_present = bs.read_bit()?;
                     if self.sbr_present {
-                        let _ext_srate = Self::read_sampling_frequency(&mut bs)?;
+                        let ext_srate = Self::read_sampling_frequency(&mut bs)?;
+                        self.sbr_ps_info = Some((ext_srate, 0));
+                        self.ps_present = Some(true);
                     }
                     let _ext_channels = bs.read_bits_leq32(4)?;
                 }
@@ -329,7 +345,7 @@ struct ICSInfo {
     window_groups: usize,
     num_windows: usize,
     max_sfb: usize,
-    predictor_data: Option<LTPData>,
+    predictor_data: Option<PredictorData>,
     long_win: bool,
 }
 
@@ -355,9 +371,15 @@ impl ICSInfo {
         }
     }
 
-    fn decode_ics_info<B: ReadBitsLtr>(&mut self, bs: &mut B) -> Result<()> {
+    fn decode_ics_info<B: ReadBitsLtr>(
+        &mut self,
+        bs: &mut B,
+        m4atype: M4AType,
+        max_pred_sfb: usize,
+    ) -> Result<()> {
         self.prev_window_sequence = self.window_sequence;
         self.prev_window_shape = self.window_shape;
+        self.predictor_data = None;
 
         if bs.read_bit()? {
             return decode_error("ics reserved bit set");
@@ -402,7 +424,7 @@ impl ICSInfo {
             self.long_win = true;
             self.num_windows = 1;
             self.max_sfb = bs.read_bits_leq32(6)? as usize;
-            self.predictor_data = LTPData::read(bs)?;
+            self.predictor_data = PredictorData::read(bs, m4atype, self.max_sfb, max_pred_sfb)?;
         }
         Ok(())
     }
@@ -425,40 +447,179 @@ impl ICSInfo {
     }
 }
 
+/// The maximum number of scale factor bands with backward-adaptive prediction.
+const MAX_PRED_SFBS: usize = 41;
+
+/// The number of spectral coefficients with a backward-adaptive predictor.
+const MAX_PREDICTORS: usize = 672;
+
+/// The number of predictor reset groups.
+const NUM_PRED_RESET_GROUPS: usize = 30;
+
+/// The maximum number of scale factor bands with long term prediction.
+const MAX_LTP_SFBS: usize = 40;
+
+/// Long term prediction coefficients.
+const LTP_COEFS: [f32; 8] = [
+    0.570_829, 0.696_616, 0.813_004, 0.911_304, 0.984_900, 1.067_894, 1.194_601, 1.369_533,
+];
+
+/// Backward-adaptive prediction data (AAC Main).
+#[derive(Clone, Copy)]
+struct PredictionData {
+    /// The predictor reset group, if the predictors of a group are to be reset.
+    reset_group: Option<usize>,
+    /// If prediction is used for each scale factor band.
+    prediction_used: [bool; MAX_PRED_SFBS],
+}
+
+impl PredictionData {
+    fn read<B: ReadBitsLtr>(bs: &mut B, num_sfbs: usize) -> Result<Self> {
+        let predictor_reset = bs.read_bit()?;
+
+        let reset_group = if predictor_reset {
+            let group = bs.read_bits_leq32(5)? as usize;
+            validate!(group > 0 && group <= NUM_PRED_RESET_GROUPS);
+            Some(group)
+        }
+        else {
+            None
+        };
+
+        let mut prediction_used = [false; MAX_PRED_SFBS];
+
+        for used in prediction_used[..num_sfbs].iter_mut() {
+            *used = bs.read_bit()?;
+        }
+
+        Ok(Self { reset_group, prediction_used })
+    }
+}
+
+/// Long term prediction data (AAC LTP).
 #[derive(Clone, Copy)]
-struct LTPData {}
+struct LTPData {
+    lag: usize,
+    coef: f32,
+    long_used: [bool; MAX_LTP_SFBS],
+}
 
 impl LTPData {
-    fn read<B: ReadBitsLtr>(bs: &mut B) -> Result<Option<Self>> {
+    fn read<B: ReadBitsLtr>(bs: &mut B, max_sfb: usize) -> Result<Option<Self>> {
+        let ltp_data_present = bs.read_bit()?;
+        if !ltp_data_present {
+            return Ok(None);
+        }
+
+        let lag = bs.read_bits_leq32(11)? as usize;
+        let coef = LTP_COEFS[bs.read_bits_leq32(3)? as usize];
+
+        let mut long_used = [false; MAX_LTP_SFBS];
+
+        for used in long_used[..max_sfb.min(MAX_LTP_SFBS)].iter_mut() {
+            *used = bs.read_bit()?;
+        }
+
+        Ok(Some(Self { lag, coef, long_used }))
+    }
+}
+
+/// The predictor data of an individual channel stream. The type of predictor depends on the
+/// object type.
+#[derive(Clone, Copy)]
+enum PredictorData {
+    Main(PredictionData),
+    Ltp(Option<LTPData>),
+}
+
+impl PredictorData {
+    fn read<B: ReadBitsLtr>(
+        bs: &mut B,
+        m4atype: M4AType,
+        max_sfb: usize,
+        max_pred_sfb: usize,
+    ) -> Result<Option<Self>> {
         let predictor_data_present = bs.read_bit()?;
         if !predictor_data_present {
             return Ok(None);
         }
-        return unsupported_error("predictor data");
-        /*
-                if is_main {
-                    let predictor_reset                         = bs.read_bit()?;
-                    if predictor_reset {
-                        let predictor_reset_group_number        = bs.read_bits_leq32(5)?;
-                    }
-                    for sfb in 0..max_sfb.min(PRED_SFB_MAX) {
-                        prediction_used[sfb]                    = bs.read_bit()?;
-                    }
-                }
-                else {
-                    let ltp_data_present                        = bs.read_bit()?;
-                    if ltp_data_present {
-                        //ltp data
-                    }
-                    if common_window {
-                        let ltp_data_present                    = bs.read_bit()?;
-                        if ltp_data_present {
-                            //ltp data
-                        }
-                    }
-                }
-                Ok(Some(Self { }))
-        */
+
+        match m4atype {
+            M4AType::Main => {
+                let data = PredictionData::read(bs, max_sfb.min(max_pred_sfb))?;
+                Ok(Some(PredictorData::Main(data)))
+            }
+            M4AType::LTP => Ok(Some(PredictorData::Ltp(LTPData::read(bs, max_sfb)?))),
+            _ => decode_error("predictor data not allowed for object type"),
+        }
+    }
+}
+
+/// Rounds a float to 16 significant bits, with ties rounded away from zero.
+#[inline(always)]
+fn flt16_round(val: f32) -> f32 {
+    f32::from_bits((val.to_bits() + 0x8000) & 0xffff_0000)
+}
+
+/// Rounds a float to 16 significant bits, with ties rounded to even.
+#[inline(always)]
+fn flt16_even(val: f32) -> f32 {
+    let bits = val.to_bits();
+    f32::from_bits((bits + 0x7fff + ((bits >> 16) & 1)) & 0xffff_0000)
+}
+
+/// Truncates a float to 16 significant bits.
+#[inline(always)]
+fn flt16_trunc(val: f32) -> f32 {
+    f32::from_bits(val.to_bits() & 0xffff_0000)
+}
+
+/// The state of the second order backward-adaptive lattice predictor of a spectral coefficient as
+/// defined in section 4.6.7 of ISO/IEC 14496-3.
+#[derive(Clone, Copy)]
+struct PredictorState {
+    cor0: f32,
+    cor1: f32,
+    var0: f32,
+    var1: f32,
+    r0: f32,
+    r1: f32,
+}
+
+impl PredictorState {
+    /// The scale of the spectral coefficients relative to those defined by the specification. The
+    /// predictor is not scale invariant, therefore it must operate on the latter.
+    const SCALE: f32 = 16384.0;
+
+    fn new() -> Self {
+        PredictorState { cor0: 0.0, cor1: 0.0, var0: 1.0, var1: 1.0, r0: 0.0, r1: 0.0 }
+    }
+
+    /// Predicts the spectral coefficient `coef`, and adds the prediction to it if `used` is true.
+    /// The predictor is then updated with the reconstructed coefficient.
+    fn predict(&mut self, coef: &mut f32, used: bool) {
+        const ALPHA: f32 = 0.906_25;
+        const A: f32 = 0.953_125;
+
+        let k1 = if self.var0 > 1.0 { self.cor0 * flt16_even(A / self.var0) } else { 0.0 };
+        let k2 = if self.var1 > 1.0 { self.cor1 * flt16_even(A / self.var1) } else { 0.0 };
+
+        let mut e0 = *coef * PredictorState::SCALE;
+
+        if used {
+            e0 += flt16_round(k1 * self.r0 + k2 * self.r1);
+            *coef = e0 / PredictorState::SCALE;
+        }
+
+        let e1 = e0 - k1 * self.r0;
+
+        self.cor1 = flt16_trunc(ALPHA * self.cor1 + self.r1 * e1);
+        self.var1 = flt16_trunc(ALPHA * self.var1 + 0.5 * (self.r1 * self.r1 + e1 * e1));
+        self.cor0 = flt16_trunc(ALPHA * self.cor0 + self.r0 * e0);
+        self.var0 = flt16_trunc(ALPHA * self.var0 + 0.5 * (self.r0 * self.r0 + e0 * e0));
+
+        self.r1 = flt16_trunc(A * (self.r0 - k1 * e0));
+        self.r0 = flt16_trunc(A * e0);
     }
 }
 
@@ -616,6 +777,85 @@ impl TNSData {
             coeffs,
         }))
     }
+
+    /// Applies the TNS filters to the spectral coefficients `coeffs`. If `inverse` is true, the
+    /// inverse filters, as used by the encoder, are applied instead.
+    fn apply(
+        &self,
+        info: &ICSInfo,
+        sbinfo: &GASubbandInfo,
+        srate_idx: usize,
+        inverse: bool,
+        coeffs: &mut [f32],
+    ) {
+        let (bands, tns_max_bands) = if info.long_win {
+            (sbinfo.long_bands, TNS_MAX_LONG_BANDS[srate_idx])
+        }
+        else {
+            (sbinfo.short_bands, TNS_MAX_SHORT_BANDS[srate_idx])
+        };
+
+        let tns_max_bands = tns_max_bands.min(info.max_sfb);
+
+        for w in 0..info.num_windows {
+            let mut bottom = bands.len() - 1;
+
+            for f in 0..self.n_filt[w] {
+                let top = bottom;
+
+                bottom = if top > self.coeffs[w][f].length {
+                    top - self.coeffs[w][f].length
+                }
+                else {
+                    0
+                };
+
+                let order = self.coeffs[w][f].order;
+
+                if order == 0 {
+                    continue;
+                }
+
+                let start = w * 128 + bands[tns_max_bands.min(bottom)];
+                let end = w * 128 + bands[tns_max_bands.min(top)];
+                let lpc = &self.coeffs[w][f].coef;
+
+                // The decoder filters are all-pole filters, and are applied in the direction of
+                // filtering. The inverse filters are all-zero filters, and are applied in the
+                // opposite direction so that the unfiltered coefficients are used.
+                match (self.coeffs[w][f].direction, inverse) {
+                    (false, false) => {
+                        for (m, i) in (start..end).enumerate() {
+                            for j in 0..order.min(m) {
+                                coeffs[i] -= coeffs[i - j - 1] * lpc[j];
+                            }
+                        }
+                    }
+                    (true, false) => {
+                        for (m, i) in (start..end).rev().enumerate() {
+                            for j in 0..order.min(m) {
+                                coeffs[i] -= coeffs[i + j + 1] * lpc[j];
+                            }
+                        }
+                    }
+                    (false, true) => {
+                        for i in (start..end).rev() {
+                            for j in 0..order.min(i - start) {
+                                coeffs[i] += coeffs[i - j - 1] * lpc[j];
+                            }
+                        }
+                    }
+                    (true, true) => {
+                        for i in start..end {
+                            for j in 0..order.min(end - 1 - i) {
+                                coeffs[i] += coeffs[i + j + 1] * lpc[j];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -664,6 +904,11 @@ struct ICS {
     coeffs: [f32; 1024],
     delay: [f32; 1024],
     lcg: Lcg,
+    /// The backward-adaptive predictors (AAC Main). Allocated on first use.
+    pred_states: Option<Box<[PredictorState; MAX_PREDICTORS]>>,
+    /// The last two frames of output, and the estimate of the next frame, for long term
+    /// prediction (AAC LTP). Allocated on first use.
+    ltp_state: Option<Box<[f32; 3 * 1024]>>,
 }
 
 const INTENSITY_SCALE_MIN: i16 = -155;
@@ -697,12 +942,23 @@ impl ICS {
             coeffs: [0.0; 1024],
             delay: [0.0; 1024],
             lcg: Lcg::new(0x1bad1dea),
+            pred_states: None,
+            ltp_state: None,
         }
     }
 
     fn reset(&mut self) {
         self.info = ICSInfo::new();
         self.delay = [0.0; 1024];
+        self.pred_states = None;
+        self.ltp_state = None;
+    }
+
+    /// Conceals a damaged frame by synthesizing a frame with a silent spectrum. This outputs the
+    /// overlap of the previous frame, thereby fading it out, and then resets the channel.
+    fn conceal(&mut self, dst: &mut [f32]) {
+        dst[..1024].copy_from_slice(&self.delay);
+        self.reset();
     }
 
     fn decode_section_data<B: ReadBitsLtr>(
@@ -829,15 +1085,6 @@ impl ICS {
         }
     }
 
-    fn get_num_bands(&self) -> usize {
-        if self.info.long_win {
-            self.sbinfo.long_bands.len() - 1
-        }
-        else {
-            self.sbinfo.short_bands.len() - 1
-        }
-    }
-
     fn decode_spectrum<B: ReadBitsLtr>(&mut self, bs: &mut B) -> Result<()> {
         // Zero all spectral coefficients.
         self.coeffs = [0.0; 1024];
@@ -932,7 +1179,7 @@ impl ICS {
         self.global_gain = bs.read_bits_leq32(8)? as u8;
 
         if !common_window {
-            self.info.decode_ics_info(bs)?;
+            self.info.decode_ics_info(bs, m4atype, self.sbinfo.max_pred_sfb)?;
         }
 
         self.decode_section_data(bs)?;
@@ -968,68 +1215,145 @@ impl ICS {
         Ok(())
     }
 
-    fn synth_channel(&mut self, dsp: &mut DSP, srate_idx: usize, dst: &mut [f32]) {
+    fn synth_channel(
+        &mut self,
+        dsp: &mut DSP,
+        m4atype: M4AType,
+        srate_idx: usize,
+        dst: &mut [f32],
+    ) {
         self.place_pulses();
 
+        match m4atype {
+            M4AType::Main => self.apply_prediction(),
+            M4AType::LTP => self.apply_ltp(dsp, srate_idx),
+            _ => (),
+        }
+
         if let Some(ref tns_data) = self.tns_data {
+            tns_data.apply(&self.info, &self.sbinfo, srate_idx, false, &mut self.coeffs);
+        }
 
-            let tns_max_bands = (if self.info.long_win {
-                TNS_MAX_LONG_BANDS[srate_idx]
-            }
-            else {
-                TNS_MAX_SHORT_BANDS[srate_idx]
-            })
-            .min(self.info.max_sfb);
+        dsp.synth(
+            &self.coeffs,
+            &mut self.delay,
+            self.info.window_sequence,
+            self.info.window_shape,
+            self.info.prev_window_shape,
+            dst,
+        );
 
-            for w in 0..self.info.num_windows {
-                let mut bottom = self.get_num_bands();
+        if m4atype == M4AType::LTP {
+            self.update_ltp(dst);
+        }
+    }
 
-                for f in 0..tns_data.n_filt[w] {
-                    let top = bottom;
+    /// Applies backward-adaptive prediction (AAC Main). Every predictor is updated in every frame,
+    /// but the prediction is only added to the spectral coefficients of the scale factor bands
+    /// that signal its use.
+    fn apply_prediction(&mut self) {
+        let states = self
+            .pred_states
+            .get_or_insert_with(|| Box::new([PredictorState::new(); MAX_PREDICTORS]));
+
+        // Prediction is not used with short windows, and all predictors are reset instead.
+        if !self.info.long_win {
+            states.fill(PredictorState::new());
+            return;
+        }
 
-                    bottom = if top > tns_data.coeffs[w][f].length {
-                        top - tns_data.coeffs[w][f].length
-                    }
-                    else {
-                        0
-                    };
+        let data = match self.info.predictor_data {
+            Some(PredictorData::Main(data)) => Some(data),
+            _ => None,
+        };
 
-                    let order = tns_data.coeffs[w][f].order;
+        let bands = &self.sbinfo.long_bands[..=self.sbinfo.max_pred_sfb];
 
-                    if order == 0 {
-                        continue;
-                    }
+        for (sfb, band) in bands.windows(2).enumerate() {
+            let used = data.is_some_and(|data| data.prediction_used[sfb]);
 
-                    let start = w * 128 + self.get_band_start(tns_max_bands.min(bottom));
-                    let end = w * 128 + self.get_band_start(tns_max_bands.min(top));
-                    let lpc = &tns_data.coeffs[w][f].coef;
+            let coeffs = &mut self.coeffs[band[0]..band[1]];
 
-                    if !tns_data.coeffs[w][f].direction {
-                        for (m, i) in (start..end).enumerate() {
-                            for j in 0..order.min(m) {
-                                self.coeffs[i] -= self.coeffs[i - j - 1] * lpc[j];
-                            }
-                        }
-                    }
-                    else {
-                        for (m, i) in (start..end).rev().enumerate() {
-                            for j in 0..order.min(m) {
-                                self.coeffs[i] -= self.coeffs[i + j + 1] * lpc[j];
-                            }
-                        }
-                    }
-                }
+            for (state, coef) in states[band[0]..band[1]].iter_mut().zip(coeffs) {
+                state.predict(coef, used);
             }
         }
 
-        dsp.synth(
-            &self.coeffs,
-            &mut self.delay,
+        if let Some(group) = data.and_then(|data| data.reset_group) {
+            for state in states[group - 1..].iter_mut().step_by(NUM_PRED_RESET_GROUPS) {
+                *state = PredictorState::new();
+            }
+        }
+
+        // The predictors of scale factor bands coded with perceptual noise substitution are reset.
+        for (sfb, band) in bands.windows(2).enumerate().take(self.info.max_sfb) {
+            if self.sfb_cb[0][sfb] == NOISE_HCB {
+                states[band[0]..band[1]].fill(PredictorState::new());
+            }
+        }
+    }
+
+    /// Applies long term prediction (AAC LTP). The spectral coefficients of the current frame are
+    /// predicted from the MDCT of the previously decoded output delayed by the signalled lag.
+    fn apply_ltp(&mut self, dsp: &mut DSP, srate_idx: usize) {
+        let ltp = match self.info.predictor_data {
+            Some(PredictorData::Ltp(Some(ltp))) => ltp,
+            _ => return,
+        };
+
+        let state = match &self.ltp_state {
+            Some(state) => state,
+            None => return,
+        };
+
+        // The predicted time domain samples. If the lag is less than the frame length, the
+        // samples following the estimated samples of the current frame are zero.
+        let mut pred_time = [0.0; 2048];
+
+        let num_samples = if ltp.lag < 1024 { ltp.lag + 1024 } else { 2048 };
+
+        for (p, &s) in pred_time[..num_samples].iter_mut().zip(&state[2048 - ltp.lag..]) {
+            *p = s * ltp.coef;
+        }
+
+        let mut pred = [0.0; 1024];
+
+        dsp.analysis(
+            &mut pred_time,
             self.info.window_sequence,
             self.info.window_shape,
             self.info.prev_window_shape,
-            dst,
+            &mut pred,
         );
+
+        // The spectral coefficients of the current frame are filtered by TNS after prediction,
+        // therefore the prediction must be filtered by the inverse filter.
+        if let Some(ref tns_data) = self.tns_data {
+            tns_data.apply(&self.info, &self.sbinfo, srate_idx, true, &mut pred);
+        }
+
+        let num_sfbs = self.info.max_sfb.min(MAX_LTP_SFBS);
+
+        for (band, &used) in self.sbinfo.long_bands.windows(2).zip(&ltp.long_used[..num_sfbs]) {
+            if used {
+                let coeffs = &mut self.coeffs[band[0]..band[1]];
+
+                for (c, &p) in coeffs.iter_mut().zip(&pred[band[0]..band[1]]) {
+                    *c += p;
+                }
+            }
+        }
+    }
+
+    /// Updates the long term prediction state with the output of the current frame, and the
+    /// windowed, but not yet overlapped, second half of the current frame which estimates the
+    /// output of the next frame.
+    fn update_ltp(&mut self, dst: &[f32]) {
+        let state = self.ltp_state.get_or_insert_with(|| Box::new([0.0; 3 * 1024]));
+
+        state.copy_within(1024.., 0);
+        state[1024..2048].copy_from_slice(&dst[..1024]);
+        state[2048..].copy_from_slice(&self.delay);
     }
 }
 
@@ -1203,6 +1527,7 @@ struct ChannelPair {
     ms_used: [[bool; MAX_SFBS]; MAX_WINDOWS],
     ics0: ICS,
     ics1: ICS,
+    sbr: Option<Box<Sbr>>,
 }
 
 impl ChannelPair {
@@ -1215,12 +1540,30 @@ impl ChannelPair {
             ms_used: [[false; MAX_SFBS]; MAX_WINDOWS],
             ics0: ICS::new(sbinfo),
             ics1: ICS::new(sbinfo),
+            sbr: None,
+        }
+    }
+
+    /// Enables SBR for the element, with an output sample rate of `srate`.
+    fn enable_sbr(&mut self, srate: u32) {
+        let n_channels = if self.is_pair { 2 } else { 1 };
+        self.sbr = Some(Box::new(Sbr::new(n_channels, srate)));
+    }
+
+    /// Enables PS for the element. SBR must be enabled first.
+    fn enable_ps(&mut self) {
+        if let Some(sbr) = &mut self.sbr {
+            sbr.enable_ps();
         }
     }
 
     fn reset(&mut self) {
         self.ics0.reset();
         self.ics1.reset();
+
+        if let Some(sbr) = &mut self.sbr {
+            sbr.reset();
+        }
     }
 
     fn decode_ga_sce<B: ReadBitsLtr>(&mut self, bs: &mut B, m4atype: M4AType) -> Result<()> {
@@ -1233,7 +1576,16 @@ impl ChannelPair {
         self.common_window = common_window;
 
         if common_window {
-            self.ics0.info.decode_ics_info(bs)?;
+            self.ics0.info.decode_ics_info(bs, m4atype, self.ics0.sbinfo.max_pred_sfb)?;
+
+            self.ics1.info = self.ics0.info;
+
+            // With a common window, the long term prediction data of the second channel follows
+            // that of the first channel.
+            if let Some(PredictorData::Ltp(_)) = self.ics0.info.predictor_data {
+                let ltp = LTPData::read(bs, self.ics1.info.max_sfb)?;
+                self.ics1.info.predictor_data = Some(PredictorData::Ltp(ltp));
+            }
 
             // Mid-side stereo mask decoding.
             self.ms_mask_present = bs.read_bits_leq32(2)? as u8;
@@ -1257,8 +1609,6 @@ impl ChannelPair {
                 3 => return decode_error("invalid mid-side mask"),
                 _ => unreachable!()
             }
-
-            self.ics1.info = self.ics0.info;
         }
 
         self.ics0.decode_ics(bs, m4atype, common_window)?;
@@ -1323,22 +1673,85 @@ impl ChannelPair {
         Ok(())
     }
 
-    fn synth_audio(&mut self, dsp: &mut DSP, abuf: &mut AudioBuffer<f32>, srate_idx: usize) {
-        self.ics0.synth_channel(dsp, srate_idx, abuf.chan_mut(self.channel));
+    /// Gets the index of the last channel the element outputs to.
+    fn last_channel(&self) -> usize {
+        if self.is_pair || self.sbr.as_ref().is_some_and(|sbr| sbr.has_ps()) {
+            self.channel + 1
+        }
+        else {
+            self.channel
+        }
+    }
+
+    fn conceal(&mut self, sbr_dsp: Option<&mut SbrDsp>, abuf: &mut AudioBuffer<f32>) {
+        self.ics0.conceal(abuf.chan_mut(self.channel));
+
+        if self.is_pair {
+            self.ics1.conceal(abuf.chan_mut(self.channel + 1));
+        }
+
+        // The high band cannot be reconstructed without SBR data, so only upsample.
+        if let Some(sbr) = &mut self.sbr {
+            sbr.start_frame();
+        }
+
+        self.apply_sbr(sbr_dsp, abuf);
+    }
+
+    fn synth_audio(
+        &mut self,
+        dsp: &mut DSP,
+        sbr_dsp: Option<&mut SbrDsp>,
+        abuf: &mut AudioBuffer<f32>,
+        m4atype: M4AType,
+        srate_idx: usize,
+    ) {
+        self.ics0.synth_channel(dsp, m4atype, srate_idx, abuf.chan_mut(self.channel));
 
         if self.is_pair {
-            self.ics1.synth_channel(dsp, srate_idx, abuf.chan_mut(self.channel + 1));
+            self.ics1.synth_channel(dsp, m4atype, srate_idx, abuf.chan_mut(self.channel + 1));
+        }
+
+        self.apply_sbr(sbr_dsp, abuf);
+    }
+
+    /// Applies SBR to the core decoder output of the element, if SBR is enabled. If PS is enabled,
+    /// the single channel is upmixed into the following channel as well.
+    fn apply_sbr(&mut self, sbr_dsp: Option<&mut SbrDsp>, abuf: &mut AudioBuffer<f32>) {
+        if let (Some(sbr), Some(sbr_dsp)) = (&mut self.sbr, sbr_dsp) {
+            if self.is_pair || sbr.has_ps() {
+                let (left, right) = abuf.chan_pair_mut(self.channel, self.channel + 1);
+                sbr.apply(sbr_dsp, &mut [left, right]);
+            }
+            else {
+                sbr.apply(sbr_dsp, &mut [abuf.chan_mut(self.channel)]);
+            }
         }
     }
 }
 
+/// The rising half of a window, and its time reversal, the falling half.
+struct HalfWindow<const N: usize> {
+    rise: [f32; N],
+    fall: [f32; N],
+}
+
+impl<const N: usize> HalfWindow<N> {
+    fn new(rise: [f32; N]) -> Self {
+        let mut fall = rise;
+        fall.reverse();
+        HalfWindow { rise, fall }
+    }
+}
+
 struct DSP {
-    kbd_long_win: [f32; 1024],
-    kbd_short_win: [f32; 128],
-    sine_long_win: [f32; 1024],
-    sine_short_win: [f32; 128],
+    kbd_long_win: HalfWindow<1024>,
+    kbd_short_win: HalfWindow<128>,
+    sine_long_win: HalfWindow<1024>,
+    sine_short_win: HalfWindow<128>,
     imdct_long: Imdct,
     imdct_short: Imdct,
+    mdct_long: Mdct,
     tmp: [f32; 2048],
     ew_buf: [f32; 1152],
 }
@@ -1370,12 +1783,13 @@ impl DSP {
         generate_window(WindowType::Sine, 1.0, 128, true, &mut sine_short_win);
 
         Self {
-            kbd_long_win,
-            kbd_short_win,
-            sine_long_win,
-            sine_short_win,
+            kbd_long_win: HalfWindow::new(kbd_long_win),
+            kbd_short_win: HalfWindow::new(kbd_short_win),
+            sine_long_win: HalfWindow::new(sine_long_win),
+            sine_short_win: HalfWindow::new(sine_short_win),
             imdct_long: Imdct::new(1024),
             imdct_short: Imdct::new(128),
+            mdct_long: Mdct::new(1024),
             tmp: [0.0; 2048],
             ew_buf: [0.0; 1152],
         }
@@ -1402,9 +1816,6 @@ impl DSP {
             false => (&self.sine_long_win, &self.sine_short_win),
         };
 
-        // Zero the output buffer.
-        self.tmp = [0.0; 2048];
-
         // Inverse MDCT
         if seq != EIGHT_SHORT_SEQUENCE {
             self.imdct_long.imdct(coeffs, &mut self.tmp, 1.0 / 2048.0);
@@ -1416,48 +1827,38 @@ impl DSP {
 
             self.ew_buf = [0.0; 1152];
 
+            // Window and overlap-add the short blocks. The first short block overlaps the
+            // previous frame, so the left-hand side of its window has the previous window shape.
             for (w, src) in self.tmp.chunks(256).enumerate() {
-                if w > 0 {
-                    for i in 0..128 {
-                        self.ew_buf[w * 128 + i +   0] += src[i +   0] * short_win[i];
-                        self.ew_buf[w * 128 + i + 128] += src[i + 128] * short_win[127 - i];
-                    }
-                }
-                else {
-                    for i in 0..128 {
-                        self.ew_buf[i +   0] = src[i +   0] * prev_short_win[i];
-                        self.ew_buf[i + 128] = src[i + 128] * short_win[127 - i];
-                    }
-                }
+                let lhs_win = if w > 0 { short_win } else { prev_short_win };
+
+                let (lhs, rhs) = self.ew_buf[w * 128..w * 128 + 256].split_at_mut(128);
+                let (src_lhs, src_rhs) = src.split_at(128);
+
+                simd::mul_add(lhs, src_lhs, &lhs_win.rise);
+                simd::mul_add(rhs, src_rhs, &short_win.fall);
             }
         }
 
+        // The window and overlap-add operations below use the SIMD kernels, which operate on
+        // equal length slices. Therefore, the output is first initialized with the delay.
+        let dst = &mut dst[..1024];
+
+        dst.copy_from_slice(&delay[..]);
+
         // output new data
         match seq {
             ONLY_LONG_SEQUENCE | LONG_START_SEQUENCE => {
-                for i in 0..1024 {
-                    dst[i] = delay[i] + (self.tmp[i] * prev_long_win[i]);
-                }
+                simd::mul_add(dst, &self.tmp[..1024], &prev_long_win.rise);
             }
             EIGHT_SHORT_SEQUENCE => {
-                for i in 0..SHORT_WIN_POINT0 {
-                    dst[i] = delay[i];
-                }
-
-                for i in SHORT_WIN_POINT0..1024 {
-                    dst[i] = delay[i] + self.ew_buf[i - SHORT_WIN_POINT0];
-                }
+                simd::add(&mut dst[SHORT_WIN_POINT0..], &self.ew_buf);
             }
             LONG_STOP_SEQUENCE => {
-                for i in 0..SHORT_WIN_POINT0 {
-                    dst[i] = delay[i];
-                }
-                for i in SHORT_WIN_POINT0..SHORT_WIN_POINT1 {
-                    dst[i] = delay[i] + self.tmp[i] * prev_short_win[i - SHORT_WIN_POINT0];
-                }
-                for i in SHORT_WIN_POINT1..1024 {
-                    dst[i] = delay[i] + self.tmp[i];
-                }
+                let (dst_mid, dst_rhs) = dst[SHORT_WIN_POINT0..].split_at_mut(128);
+
+                simd::mul_add(dst_mid, &self.tmp[SHORT_WIN_POINT0..], &prev_short_win.rise);
+                simd::add(dst_rhs, &self.tmp[SHORT_WIN_POINT1..1024]);
             }
             _ => unreachable!(),
         };
@@ -1465,68 +1866,203 @@ impl DSP {
         // save delay
         match seq {
             ONLY_LONG_SEQUENCE | LONG_STOP_SEQUENCE => {
-                for i in 0..1024 {
-                    delay[i] = self.tmp[i + 1024] * long_win[1023 - i];
-                }
+                delay.copy_from_slice(&self.tmp[1024..]);
+                simd::mul(delay, &long_win.fall);
             }
             EIGHT_SHORT_SEQUENCE => {
-                for i in 0..SHORT_WIN_POINT1 {
-                    // last part is already windowed
-                    delay[i] = self.ew_buf[i + 512 + 64];
-                }
-                for i in SHORT_WIN_POINT1..1024 {
-                    delay[i] = 0.0;
-                }
+                // last part is already windowed
+                let (delay_lhs, delay_rhs) = delay.split_at_mut(SHORT_WIN_POINT1);
+
+                delay_lhs.copy_from_slice(&self.ew_buf[512 + 64..]);
+                delay_rhs.fill(0.0);
             }
             LONG_START_SEQUENCE => {
-                for i in 0..SHORT_WIN_POINT0 {
-                    delay[i] = self.tmp[i + 1024];
-                }
-                for i in SHORT_WIN_POINT0..SHORT_WIN_POINT1 {
-                    delay[i] = self.tmp[i + 1024] * short_win[127 - (i - SHORT_WIN_POINT0)];
-                }
-                for i in SHORT_WIN_POINT1..1024 {
-                    delay[i] = 0.0;
-                }
+                delay[..SHORT_WIN_POINT1].copy_from_slice(&self.tmp[1024..1024 + SHORT_WIN_POINT1]);
+                simd::mul(&mut delay[SHORT_WIN_POINT0..SHORT_WIN_POINT1], &short_win.fall);
+
+                delay[SHORT_WIN_POINT1..].fill(0.0);
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    /// Windows the time domain samples in `src`, and then transforms them into spectral
+    /// coefficients. This is the inverse of `synth`, and is only defined for long windows.
+    fn analysis(
+        &mut self,
+        src: &mut [f32; 2048],
+        seq: u8,
+        window_shape: bool,
+        prev_window_shape: bool,
+        dst: &mut [f32; 1024],
+    ) {
+        let (long_win, short_win) = match window_shape {
+            true  => (&self.kbd_long_win , &self.kbd_short_win ),
+            false => (&self.sine_long_win, &self.sine_short_win),
+        };
+
+        let (prev_long_win, prev_short_win) = match prev_window_shape {
+            true  => (&self.kbd_long_win , &self.kbd_short_win ),
+            false => (&self.sine_long_win, &self.sine_short_win),
+        };
+
+        let (lhs, rhs) = src.split_at_mut(1024);
+
+        match seq {
+            ONLY_LONG_SEQUENCE | LONG_START_SEQUENCE => {
+                simd::mul(lhs, &prev_long_win.rise);
+            }
+            LONG_STOP_SEQUENCE => {
+                lhs[..SHORT_WIN_POINT0].fill(0.0);
+                simd::mul(&mut lhs[SHORT_WIN_POINT0..SHORT_WIN_POINT1], &prev_short_win.rise);
             }
             _ => unreachable!(),
         };
+
+        match seq {
+            ONLY_LONG_SEQUENCE | LONG_STOP_SEQUENCE => {
+                simd::mul(rhs, &long_win.fall);
+            }
+            LONG_START_SEQUENCE => {
+                simd::mul(&mut rhs[SHORT_WIN_POINT0..SHORT_WIN_POINT1], &short_win.fall);
+                rhs[SHORT_WIN_POINT1..].fill(0.0);
+            }
+            _ => unreachable!(),
+        };
+
+        // The inverse MDCT is scaled by 1/2048, therefore the forward MDCT must be scaled by 4 for
+        // the windowed and overlapped transforms to reconstruct the original signal.
+        self.mdct_long.mdct(src, dst, 4.0);
     }
 }
 
 /// Advanced Audio Coding (AAC) decoder.
 ///
 /// Implements a decoder for Advanced Audio Decoding Low-Complexity (AAC-LC) as defined in
-/// ISO/IEC 13818-7 and ISO/IEC 14496-3.
+/// ISO/IEC 13818-7 and ISO/IEC 14496-3, for High-Efficiency AAC (HE-AAC), which extends AAC-LC
+/// with Spectral Band Replication (SBR), and for HE-AAC v2, which further extends HE-AAC with
+/// Parametric Stereo (PS). AAC Main, which adds backward-adaptive prediction, and AAC-LTP, which
+/// adds Long Term Prediction (LTP), are supported as well.
 pub struct AacDecoder {
     // info: NACodecInfoRef,
     m4ainfo: M4AInfo,
     pairs: Vec<ChannelPair>,
     dsp: DSP,
+    /// The SBR transforms and scratch buffers. SBR is enabled if this is not `None`.
+    sbr_dsp: Option<Box<SbrDsp>>,
+    /// If true, SBR was not explicitly signalled, and may be enabled if the first frame contains
+    /// SBR data.
+    sbr_implicit: bool,
+    /// If true, PS is enabled, and the single channel of a mono stream is upmixed to stereo.
+    ps_enabled: bool,
     sbinfo: GASubbandInfo,
     params: CodecParameters,
+    is_gapless: bool,
+    concealer: Concealer<f32>,
+    /// The payload of the current fill element.
+    fill_buf: Vec<u8>,
     buf: AudioBuffer<f32>,
 }
 
 impl AacDecoder {
 
+    /// Enables SBR. The output sample rate, and frame length, are doubled.
+    ///
+    /// PS is enabled for mono streams as well, unless it is explicitly signalled as absent. PS data
+    /// is not required to be present in every frame, therefore the output of a mono stream with PS
+    /// is always stereo.
+    fn enable_sbr(&mut self) {
+        let srate = 2 * self.m4ainfo.srate;
+        let n_frames = 2 * self.m4ainfo.samples;
+
+        self.ps_enabled = self.m4ainfo.channels == 1 && self.m4ainfo.ps_present != Some(false);
+
+        for pair in self.pairs.iter_mut() {
+            pair.enable_sbr(srate);
+
+            if self.ps_enabled {
+                pair.enable_ps();
+            }
+        }
+
+        self.sbr_dsp = Some(Box::new(SbrDsp::new()));
+
+        let channels = if self.ps_enabled {
+            map_channels(2).unwrap()
+        }
+        else {
+            self.buf.spec().channels
+        };
+
+        let spec = SignalSpec::new(srate, channels);
+
+        self.buf = AudioBuffer::new(n_frames as Duration, spec);
+
+        self.params.sample_rate = Some(srate);
+        self.params.channels = Some(channels);
+        self.params.max_frames_per_packet = Some(n_frames as u64);
+    }
+
     fn set_pair(&mut self, pair_no: usize, channel: usize, pair: bool) -> Result<()> {
+        // Validate the channels of the element before creating a pair for them.
+        validate!(if pair { channel + 1 }
+            else { channel } < self.m4ainfo.channels);
+
         if self.pairs.len() <= pair_no {
-            self.pairs
-                .push(ChannelPair::new(pair, channel, self.sbinfo));
+            let mut new_pair = ChannelPair::new(pair, channel, self.sbinfo);
+
+            if self.sbr_dsp.is_some() {
+                new_pair.enable_sbr(2 * self.m4ainfo.srate);
+
+                if self.ps_enabled {
+                    new_pair.enable_ps();
+                }
+            }
+
+            self.pairs.push(new_pair);
         }
         else {
             validate!(self.pairs[pair_no].channel == channel);
             validate!(self.pairs[pair_no].is_pair == pair);
         }
-        validate!(if pair { channel + 1 }
-            else { channel } < self.m4ainfo.channels);
         Ok(())
     }
 
+    /// Decodes the extension payload of a fill element that follows the element `pair_no`.
+    fn decode_extension_payload(&mut self, pair_no: Option<usize>) {
+        let ext_type = match self.fill_buf.first() {
+            Some(&byte) => u32::from(byte >> 4),
+            None => return,
+        };
+
+        // Only SBR extension payloads are supported. SBR data belongs to the preceding element.
+        let pair_no = match pair_no {
+            Some(pair_no) if ext_type == EXT_SBR_DATA || ext_type == EXT_SBR_DATA_CRC => pair_no,
+            _ => return,
+        };
+
+        // If SBR was not explicitly signalled, it is implicitly signalled by the presence of SBR
+        // data in the first frame.
+        if self.sbr_implicit && self.sbr_dsp.is_none() {
+            self.enable_sbr();
+            self.buf.render_reserved(None);
+        }
+
+        if let Some(sbr) = &mut self.pairs[pair_no].sbr {
+            sbr.read_extension(&self.fill_buf);
+        }
+    }
+
     fn decode_ga<B: ReadBitsLtr + FiniteBitStream>(&mut self, bs: &mut B) -> Result<()> {
         let mut cur_pair = 0;
         let mut cur_ch = 0;
+
+        for pair in self.pairs.iter_mut() {
+            if let Some(sbr) = &mut pair.sbr {
+                sbr.start_frame();
+            }
+        }
+
         while bs.bits_left() > 3 {
             let id = bs.read_bits_leq32(3)?;
 
@@ -1583,10 +2119,11 @@ impl AacDecoder {
                         count += bs.read_bits_leq32(8)? as usize;
                         count -= 1;
                     }
+                    self.fill_buf.clear();
                     for _ in 0..count {
-                        // ext payload
-                        bs.ignore_bits(8)?;
+                        self.fill_buf.push(bs.read_bits_leq32(8)? as u8);
                     }
+                    self.decode_extension_payload(cur_pair.checked_sub(1));
                 }
                 7 => {
                     // ID_TERM
@@ -1595,13 +2132,49 @@ impl AacDecoder {
                 _ => unreachable!(),
             };
         }
+        // SBR can only be implicitly signalled in the first frame.
+        self.sbr_implicit = false;
+
         let srate_idx = GASubbandInfo::find_idx(self.m4ainfo.srate);
         for pair in 0..cur_pair {
-            self.pairs[pair].synth_audio(&mut self.dsp, &mut self.buf, srate_idx);
+            let sbr_dsp = self.sbr_dsp.as_deref_mut();
+            let m4atype = self.m4ainfo.otype;
+            self.pairs[pair].synth_audio(&mut self.dsp, sbr_dsp, &mut self.buf, m4atype, srate_idx);
         }
         Ok(())
     }
 
+    fn decode_inner(&mut self, packet: &Packet) -> Result<()> {
+        // Clear the audio output buffer.
+        self.buf.clear();
+        self.buf.render_reserved(None);
+
+        let mut bs = BitReaderLtr::new(packet.buf());
+
+        // Choose decode step based on the object type.
+        match self.m4ainfo.otype {
+            M4AType::LC | M4AType::Main | M4AType::LTP => self.decode_ga(&mut bs),
+            _           => unsupported_error("object type"),
+        }
+    }
+
+    fn conceal(&mut self, err: Error) {
+        warn!("concealing damaged frame: {}", err);
+
+        // Render a silent frame, and then add the overlap of the previous frame.
+        self.buf.clear();
+        self.buf.render_silence(None);
+
+        let n_channels = self.buf.spec().channels.count();
+
+        // Only conceal the elements whose channels are in the buffer.
+        for pair in self.pairs.iter_mut().filter(|pair| pair.last_channel() < n_channels) {
+            pair.conceal(self.sbr_dsp.as_deref_mut(), &mut self.buf);
+        }
+
+        self.concealer.conceal(&mut self.buf);
+    }
+
     // fn flush(&mut self) {
     //     for pair in self.pairs.iter_mut() {
     //         pair.ics[0].delay = [0.0; 1024];
@@ -1613,7 +2186,7 @@ impl AacDecoder {
 
 impl Decoder for AacDecoder {
 
-    fn try_new(params: &CodecParameters, _: &DecoderOptions) -> Result<Self> {
+    fn try_new(params: &CodecParameters, options: &DecoderOptions) -> Result<Self> {
 
         let mut m4ainfo = M4AInfo::new();
 
@@ -1634,7 +2207,12 @@ impl Decoder for AacDecoder {
 
         trace!("{}", m4ainfo);
 
-        if (m4ainfo.otype != M4AType::LC) || (m4ainfo.channels > 2) || (m4ainfo.samples != 1024) {
+        match m4ainfo.otype {
+            M4AType::LC | M4AType::Main | M4AType::LTP => (),
+            _ => return unsupported_error("aac too complex"),
+        }
+
+        if (m4ainfo.channels > 2) || (m4ainfo.samples != 1024) {
             return unsupported_error("aac too complex");
         }
 
@@ -1646,20 +2224,45 @@ impl Decoder for AacDecoder {
         let duration = m4ainfo.samples as Duration;
         let srate = m4ainfo.srate;
 
-        Ok(AacDecoder {
+        // SBR is either explicitly signalled by the audio specific config, or implicitly signalled
+        // by the presence of SBR data in the first frame if the sample rate is low enough.
+        let (sbr_explicit, sbr_implicit) = match m4ainfo.sbr_ps_info {
+            Some((ext_srate, _)) if ext_srate == 2 * srate => (true, false),
+            Some((ext_srate, _)) => {
+                warn!("unsupported sbr sample rate {} Hz, decoding core only", ext_srate);
+                (false, false)
+            }
+            None => (false, srate <= 24000),
+        };
+
+        let mut decoder = AacDecoder {
             m4ainfo,
             pairs: Vec::new(),
             dsp: DSP::new(),
+            sbr_dsp: None,
+            sbr_implicit,
+            ps_enabled: false,
             sbinfo: GASubbandInfo::find(srate),
             params: params.clone(),
+            is_gapless: options.gapless,
+            concealer: Concealer::new(options.conceal),
+            fill_buf: Vec::new(),
             buf: AudioBuffer::new(duration, spec),
-        })
+        };
+
+        if sbr_explicit {
+            decoder.enable_sbr();
+        }
+
+        Ok(decoder)
     }
 
     fn reset(&mut self) {
         for pair in self.pairs.iter_mut() {
             pair.reset();
         }
+
+        self.concealer.reset();
     }
 
     fn supported_codecs() -> &'static [CodecDescriptor] {
@@ -1673,21 +2276,41 @@ impl Decoder for AacDecoder {
     }
 
     fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
-        // Clear the audio output buffer.
-        self.buf.clear();
-        self.buf.render_reserved(None);
+        match self.decode_inner(packet) {
+            Ok(()) => self.concealer.decoded(&self.buf),
+            Err(err) if self.concealer.can_conceal(&err) => self.conceal(err),
+            Err(err) => return Err(err),
+        }
 
-        let mut bs = BitReaderLtr::new(packet.buf());
+        // Trim the padding for gapless playback. With SBR, the timestamps may count the frames of
+        // the core decoder, which are half as long as the output frames.
+        if self.is_gapless {
+            let scale = match self.sbr_dsp {
+                Some(_) if packet.duration() == self.m4ainfo.samples as u64 => 2,
+                _ => 1,
+            };
 
-        // Choose decode step based on the object type.
-        match self.m4ainfo.otype {
-            M4AType::LC => self.decode_ga(&mut bs)?,
-            _           => return unsupported_error("object type"),
+            let (start, end) = self.params.padding_trim(packet.pts(), self.buf.frames() / scale);
+            self.buf.trim(scale * start, scale * end);
         }
 
         Ok(self.buf.as_audio_buffer_ref())
     }
 
+    fn decode_into(&mut self, packet: &Packet, dest: AudioBufferMut<'_>) -> Result<()> {
+        match dest {
+            AudioBufferMut::F32(buf) => decode_into_native(self, packet, buf, |dec| &mut dec.buf),
+            mut dest => {
+                let decoded = self.decode(packet)?;
+                dest.copy_ref(&decoded)
+            }
+        }
+    }
+
+    fn concealed_frames(&self) -> u64 {
+        self.concealer.concealed_frames()
+    }
+
     fn finalize(&mut self) -> FinalizeResult {
         Default::default()
     }
@@ -1846,6 +2469,8 @@ struct GASubbandInfo {
     min_srate: u32,
     long_bands: &'static [usize],
     short_bands: &'static [usize],
+    /// The number of scale factor bands with backward-adaptive prediction (AAC Main).
+    max_pred_sfb: usize,
 }
 
 impl GASubbandInfo {
@@ -1872,60 +2497,227 @@ const AAC_SUBBAND_INFO: [GASubbandInfo; 12] = [
         min_srate: 92017,
         long_bands: &SWB_OFFSET_96K_LONG,
         short_bands: &SWB_OFFSET_64K_SHORT,
+        max_pred_sfb: 33,
     }, //96K
     GASubbandInfo {
         min_srate: 75132,
         long_bands: &SWB_OFFSET_96K_LONG,
         short_bands: &SWB_OFFSET_64K_SHORT,
+        max_pred_sfb: 33,
     }, //88.2K
     GASubbandInfo {
         min_srate: 55426,
         long_bands: &SWB_OFFSET_64K_LONG,
         short_bands: &SWB_OFFSET_64K_SHORT,
+        max_pred_sfb: 38,
     }, //64K
     GASubbandInfo {
         min_srate: 46009,
         long_bands: &SWB_OFFSET_48K_LONG,
         short_bands: &SWB_OFFSET_48K_SHORT,
+        max_pred_sfb: 40,
     }, //48K
     GASubbandInfo {
         min_srate: 37566,
         long_bands: &SWB_OFFSET_48K_LONG,
         short_bands: &SWB_OFFSET_48K_SHORT,
+        max_pred_sfb: 40,
     }, //44.1K
     GASubbandInfo {
         min_srate: 27713,
         long_bands: &SWB_OFFSET_32K_LONG,
         short_bands: &SWB_OFFSET_48K_SHORT,
+        max_pred_sfb: 40,
     }, //32K
     GASubbandInfo {
         min_srate: 23004,
         long_bands: &SWB_OFFSET_24K_LONG,
         short_bands: &SWB_OFFSET_24K_SHORT,
+        max_pred_sfb: 41,
     }, //24K
     GASubbandInfo {
         min_srate: 18783,
         long_bands: &SWB_OFFSET_24K_LONG,
         short_bands: &SWB_OFFSET_24K_SHORT,
+        max_pred_sfb: 41,
     }, //22.05K
     GASubbandInfo {
         min_srate: 13856,
         long_bands: &SWB_OFFSET_16K_LONG,
         short_bands: &SWB_OFFSET_16K_SHORT,
+        max_pred_sfb: 37,
     }, //16K
     GASubbandInfo {
         min_srate: 11502,
         long_bands: &SWB_OFFSET_16K_LONG,
         short_bands: &SWB_OFFSET_16K_SHORT,
+        max_pred_sfb: 37,
     }, //12K
     GASubbandInfo {
         min_srate: 9391,
         long_bands: &SWB_OFFSET_16K_LONG,
         short_bands: &SWB_OFFSET_16K_SHORT,
+        max_pred_sfb: 37,
     }, //11.025K
     GASubbandInfo {
         min_srate: 0,
         long_bands: &SWB_OFFSET_8K_LONG,
         short_bands: &SWB_OFFSET_8K_SHORT,
+        max_pred_sfb: 34,
     }, //8K
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia_core::codecs::Concealment;
+
+    fn lcg_rand(state: &mut u32) -> f32 {
+        *state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (*state >> 16) as f32 / 32768.0 - 1.0
+    }
+
+    #[test]
+    fn verify_ltp_analysis() {
+        // The analysis of two consecutive frames of output must yield the spectral coefficients of
+        // the frame that overlaps both.
+        let mut dsp = DSP::new();
+        let mut state = 1;
+
+        let sequences = [
+            [ONLY_LONG_SEQUENCE, ONLY_LONG_SEQUENCE, ONLY_LONG_SEQUENCE],
+            [ONLY_LONG_SEQUENCE, LONG_START_SEQUENCE, EIGHT_SHORT_SEQUENCE],
+            [EIGHT_SHORT_SEQUENCE, LONG_STOP_SEQUENCE, ONLY_LONG_SEQUENCE],
+        ];
+
+        let shapes = [[false, true, true], [true, false, true], [true, true, false]];
+
+        for (seqs, shapes) in sequences.iter().zip(&shapes) {
+            let mut delay = [0.0; 1024];
+            let mut coeffs = [[0.0; 1024]; 3];
+            let mut output = [0.0; 3 * 1024];
+
+            let frames = coeffs.iter_mut().zip(output.chunks_exact_mut(1024)).enumerate();
+
+            for (i, (c, out)) in frames {
+                for coef in c.iter_mut() {
+                    *coef = lcg_rand(&mut state);
+                }
+
+                // The previous window shape of the first frame is irrelevant.
+                let prev_shape = shapes[i.max(1) - 1];
+
+                dsp.synth(c, &mut delay, seqs[i], shapes[i], prev_shape, out);
+            }
+
+            let mut src = [0.0; 2048];
+            src.copy_from_slice(&output[1024..]);
+
+            let mut actual = [0.0; 1024];
+            dsp.analysis(&mut src, seqs[1], shapes[1], shapes[0], &mut actual);
+
+            for (&a, &e) in actual.iter().zip(&coeffs[1]) {
+                assert!((a - e).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_prediction() {
+        // After adapting to a sinusoid, the predictor must yield a prediction gain.
+        let mut state = PredictorState::new();
+
+        let signal = |t: usize| 0.1 * (0.3 * t as f32).cos();
+
+        let mut signal_nrg = 0.0;
+        let mut error_nrg = 0.0;
+
+        for t in 0..200 {
+            let mut pred = 0.0;
+            state.clone().predict(&mut pred, true);
+
+            if t >= 100 {
+                signal_nrg += signal(t) * signal(t);
+                error_nrg += (signal(t) - pred) * (signal(t) - pred);
+            }
+
+            state.predict(&mut signal(t), false);
+        }
+
+        assert!(error_nrg < 0.1 * signal_nrg);
+    }
+
+    #[test]
+    fn verify_tns_inverse() {
+        let mut state = 1;
+
+        let sbinfo = GASubbandInfo::find(44100);
+        let srate_idx = GASubbandInfo::find_idx(44100);
+
+        let mut info = ICSInfo::new();
+        info.num_windows = 1;
+        info.max_sfb = 40;
+
+        let mut tns_data = TNSData {
+            n_filt: [0; MAX_WINDOWS],
+            coef_res: [false; MAX_WINDOWS],
+            coeffs: [[TNSCoeffs::new(); 4]; MAX_WINDOWS],
+        };
+
+        // An upwards filter over the top 10 bands, and a downwards filter over the 20 bands below.
+        tns_data.n_filt[0] = 2;
+
+        for (filt, (&length, &direction)) in [10, 20].iter().zip(&[false, true]).enumerate() {
+            let coeffs = &mut tns_data.coeffs[0][filt];
+
+            coeffs.length = length;
+            coeffs.order = 4;
+            coeffs.direction = direction;
+            coeffs.coef[..4].copy_from_slice(&[0.5, -0.25, 0.125, -0.0625]);
+        }
+
+        let mut expected = [0.0; 1024];
+
+        for coef in expected.iter_mut() {
+            *coef = lcg_rand(&mut state);
+        }
+
+        let mut actual = expected;
+
+        tns_data.apply(&info, &sbinfo, srate_idx, true, &mut actual);
+
+        assert!(actual.iter().zip(expected.iter()).any(|(&a, &e)| (a - e).abs() > 1e-3));
+
+        tns_data.apply(&info, &sbinfo, srate_idx, false, &mut actual);
+
+        for (&a, &e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn verify_decode_random_packets() {
+        // A mono AAC-LC stream at 44.1 kHz.
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_AAC).with_extra_data(Box::new([0x13, 0x88]));
+
+        for &conceal in [Concealment::Disabled, Concealment::Silence, Concealment::Repeat].iter() {
+            let mut options = DecoderOptions::default();
+            options.with_conceal(conceal);
+            let mut decoder = AacDecoder::try_new(&params, &options).unwrap();
+
+            let mut state = 1;
+
+            // Damaged packets must never cause a panic.
+            for _ in 0..20_000 {
+                let len = (lcg_rand(&mut state).abs() * 32.0) as usize;
+
+                let data: Vec<u8> = (0..len)
+                    .map(|_| ((lcg_rand(&mut state) + 1.0) * 128.0) as u8)
+                    .collect();
+
+                let _ = decoder.decode(&Packet::new_from_slice(0, 0, 1024, &data));
+            }
+        }
+    }
+}
@@ -50,8 +50,43 @@ impl QueryDescriptor for AdtsReader {
         ]
     }
 
-    fn score(_context: &[u8]) -> u8 {
-        255
+    fn score(context: &[u8]) -> u8 {
+        // A stray sync word is easily found in random data. Therefore, score the context by the
+        // number of consecutive ADTS frames, with consistent parameters, that it contains.
+        const MAX_FRAMES: usize = 4;
+
+        let mut first = None;
+        let mut n_frames = 0;
+        let mut offset = 0;
+
+        while n_frames < MAX_FRAMES && offset + AdtsHeader::SIZE <= context.len() {
+            if context[offset..offset + 2] != [0xff, 0xf1] {
+                break;
+            }
+
+            let header = match AdtsHeader::read(&mut BufReader::new(&context[offset..])) {
+                Ok(header) => header,
+                _ => break,
+            };
+
+            // All frames must have the same profile and sample rate.
+            let params = (header.profile, header.sample_rate);
+
+            if *first.get_or_insert(params) != params {
+                break;
+            }
+
+            offset += header.frame_len + AdtsHeader::SIZE;
+            n_frames += 1;
+        }
+
+        // If the chain of frames was broken before the end of the context, then the first frame
+        // must be followed by at least one other frame for it to be considered valid.
+        if n_frames < 2 && offset + AdtsHeader::SIZE <= context.len() {
+            return 0;
+        }
+
+        (n_frames * 64).min(255) as u8
     }
 }
 
@@ -59,7 +94,9 @@ impl QueryDescriptor for AdtsReader {
 struct AdtsHeader {
     profile: M4AType,
     channels: Option<Channels>,
+    channel_config: u32,
     sample_rate: u32,
+    sample_rate_idx: u32,
     frame_len: usize,
 }
 
@@ -89,16 +126,20 @@ impl AdtsHeader {
         let profile = M4A_TYPES[bs.read_bits_leq32(2)? as usize + 1];
 
         // Sample rate index.
-        let sample_rate = match bs.read_bits_leq32(4)? as usize {
+        let sample_rate_idx = bs.read_bits_leq32(4)?;
+
+        let sample_rate = match sample_rate_idx {
             15 => return decode_error("forbidden sample rate"),
-            idx => AAC_SAMPLE_RATES[idx],
+            idx => AAC_SAMPLE_RATES[idx as usize],
         };
 
         // Private bit.
         bs.ignore_bit()?;
 
         // Channel configuration
-        let channels = match bs.read_bits_leq32(3)? {
+        let channel_config = bs.read_bits_leq32(3)?;
+
+        let channels = match channel_config {
             0   => None,
             idx => map_channels(idx),
         };
@@ -124,10 +165,24 @@ impl AdtsHeader {
         Ok(AdtsHeader {
             profile,
             channels,
+            channel_config,
             sample_rate,
+            sample_rate_idx,
             frame_len: frame_len - AdtsHeader::SIZE,
         })
     }
+
+    /// Builds the audio specific config equivalent to the header. The object type of the stream
+    /// is only signalled by the header, therefore the decoder requires it.
+    fn audio_specific_config(&self) -> Box<[u8]> {
+        // Object type (5 bits), sample rate index (4 bits), channel configuration (4 bits), and a
+        // zeroed GA specific config (3 bits).
+        let config = ((self.profile as u32) << 11)
+            | (self.sample_rate_idx << 7)
+            | (self.channel_config << 3);
+
+        Box::new([(config >> 8) as u8, config as u8])
+    }
 }
 
 impl FormatReader for AdtsReader {
@@ -139,7 +194,8 @@ impl FormatReader for AdtsReader {
         let mut params = CodecParameters::new();
 
         params.for_codec(CODEC_TYPE_AAC)
-              .with_sample_rate(header.sample_rate);
+              .with_sample_rate(header.sample_rate)
+              .with_extra_data(header.audio_specific_config());
 
         if let Some(channels) = header.channels {
             params.with_channels(channels);
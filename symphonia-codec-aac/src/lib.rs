@@ -12,6 +12,7 @@ mod aac;
 mod adts;
 mod codebooks;
 mod common;
+mod sbr;
 mod window;
 
 pub use aac::AacDecoder;
@@ -0,0 +1,513 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Parsing and dequantization of the per-channel SBR frame data as defined in section 4.4.2.8 and
+//! section 4.6.18.3 of ISO/IEC 14496-3.
+
+use symphonia_core::errors::{decode_error, Result};
+use symphonia_core::io::ReadBitsLtr;
+
+use super::codebooks::*;
+use super::freq::{FreqTables, MAX_NOISE_BANDS};
+use super::{MAX_BANDS, MAX_ENVELOPES, NUM_TIME_SLOTS};
+
+/// The frame class determines how the envelope borders of a frame are signalled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FrameClass {
+    /// Fixed leading and trailing borders, and evenly spaced envelopes.
+    FixFix,
+    /// A fixed leading border, and a variable trailing border.
+    FixVar,
+    /// A variable leading border, and a fixed trailing border.
+    VarFix,
+    /// Variable leading and trailing borders.
+    VarVar,
+}
+
+/// Reads a scale factor delta coded with the given codebook.
+#[inline(always)]
+fn read_delta<B: ReadBitsLtr>(bs: &mut B, codebook: usize) -> Result<i32> {
+    let value = bs.read_codebook(&SBR_CODEBOOKS[codebook])?.0;
+    Ok(i32::from(value) - sbr_codebook_lav(codebook))
+}
+
+/// The SBR data of one channel.
+#[derive(Clone)]
+pub struct ChannelData {
+    frame_class: FrameClass,
+    /// The amplitude resolution of the envelope scale factors for the frame.
+    amp_res: bool,
+    /// The number of envelopes in the frame.
+    pub num_env: usize,
+    /// The frequency resolution of each envelope. The first element is the frequency resolution of
+    /// the last envelope of the previous frame.
+    pub freq_res: [bool; MAX_ENVELOPES + 1],
+    /// The envelope time borders, in time slots.
+    pub t_env: [usize; MAX_ENVELOPES + 1],
+    /// The number of noise floors in the frame.
+    pub num_noise: usize,
+    /// The noise floor time borders, in time slots.
+    pub t_noise: [usize; 3],
+    /// The envelope which starts at a transient, if any.
+    pub l_a: Option<usize>,
+    /// The envelope of the current frame affected by a transient at the end of the previous frame,
+    /// if any.
+    pub l_a_prev: Option<usize>,
+    df_env: [bool; MAX_ENVELOPES],
+    df_noise: [bool; 2],
+    /// The inverse filtering mode of each noise floor band.
+    pub invf_mode: [u8; MAX_NOISE_BANDS],
+    /// The inverse filtering mode of each noise floor band in the previous frame.
+    pub invf_mode_prev: [u8; MAX_NOISE_BANDS],
+    /// The quantized envelope scale factors. The first element holds the scale factors of the last
+    /// envelope of the previous frame.
+    env_q: [[i32; MAX_BANDS]; MAX_ENVELOPES + 1],
+    /// The quantized noise floor scale factors. The first element holds the scale factors of the
+    /// last noise floor of the previous frame.
+    noise_q: [[i32; MAX_NOISE_BANDS]; 3],
+    /// If true, sinusoids may be added to the high band.
+    pub add_harmonic_flag: bool,
+    /// Indicates if a sinusoid is added to each high frequency resolution band.
+    pub add_harmonic: [bool; MAX_BANDS],
+    /// The dequantized envelope scale factors.
+    pub env: [[f32; MAX_BANDS]; MAX_ENVELOPES],
+    /// The dequantized noise floor scale factors.
+    pub noise: [[f32; MAX_NOISE_BANDS]; 2],
+}
+
+impl ChannelData {
+    pub fn new() -> Self {
+        ChannelData {
+            frame_class: FrameClass::FixFix,
+            amp_res: false,
+            num_env: 0,
+            freq_res: [false; MAX_ENVELOPES + 1],
+            t_env: [0; MAX_ENVELOPES + 1],
+            num_noise: 0,
+            t_noise: [0; 3],
+            l_a: None,
+            l_a_prev: None,
+            df_env: [false; MAX_ENVELOPES],
+            df_noise: [false; 2],
+            invf_mode: [0; MAX_NOISE_BANDS],
+            invf_mode_prev: [0; MAX_NOISE_BANDS],
+            env_q: [[0; MAX_BANDS]; MAX_ENVELOPES + 1],
+            noise_q: [[0; MAX_NOISE_BANDS]; 3],
+            add_harmonic_flag: false,
+            add_harmonic: [false; MAX_BANDS],
+            env: [[0.0; MAX_BANDS]; MAX_ENVELOPES],
+            noise: [[0.0; MAX_NOISE_BANDS]; 2],
+        }
+    }
+
+    /// Starts a new frame by carrying over the state of the last frame that the new frame may
+    /// depend on.
+    pub fn start_frame(&mut self) {
+        self.freq_res[0] = self.freq_res[self.num_env];
+
+        // A transient at the last border of the previous frame affects the first envelope of the
+        // current frame.
+        self.l_a_prev = match self.l_a {
+            Some(l_a) if self.num_env > 0 && l_a == self.num_env => Some(0),
+            _ => None,
+        };
+
+        self.invf_mode_prev = self.invf_mode;
+    }
+
+    /// Reads the time/frequency grid of the frame.
+    pub fn read_grid<B: ReadBitsLtr>(&mut self, bs: &mut B, amp_res: bool) -> Result<()> {
+        self.frame_class = match bs.read_bits_leq32(2)? {
+            0 => FrameClass::FixFix,
+            1 => FrameClass::FixVar,
+            2 => FrameClass::VarFix,
+            _ => FrameClass::VarVar,
+        };
+
+        self.amp_res = amp_res;
+
+        let mut abs_bord_lead = 0;
+        let mut abs_bord_trail = NUM_TIME_SLOTS;
+        let mut rel_bord_lead = [0; MAX_ENVELOPES];
+        let mut rel_bord_trail = [0; MAX_ENVELOPES];
+        let mut num_rel_lead = 0;
+        let mut num_rel_trail = 0;
+        let mut pointer = 0;
+
+        match self.frame_class {
+            FrameClass::FixFix => {
+                let num_env = 1 << bs.read_bits_leq32(2)?;
+
+                if num_env > 4 {
+                    return decode_error("sbr: too many envelopes");
+                }
+
+                self.num_env = num_env;
+
+                // A single envelope always uses the 1.5 dB amplitude resolution.
+                if num_env == 1 {
+                    self.amp_res = false;
+                }
+
+                let freq_res = bs.read_bit()?;
+
+                for res in self.freq_res[1..=num_env].iter_mut() {
+                    *res = freq_res;
+                }
+
+                // The envelopes evenly divide the frame.
+                num_rel_lead = num_env - 1;
+
+                for rel in rel_bord_lead[..num_rel_lead].iter_mut() {
+                    *rel = NUM_TIME_SLOTS / num_env;
+                }
+            }
+            FrameClass::FixVar => {
+                abs_bord_trail += bs.read_bits_leq32(2)? as usize;
+                num_rel_trail = bs.read_bits_leq32(2)? as usize;
+
+                for rel in rel_bord_trail[..num_rel_trail].iter_mut() {
+                    *rel = 2 * bs.read_bits_leq32(2)? as usize + 2;
+                }
+
+                self.num_env = num_rel_trail + 1;
+
+                pointer = bs.read_bits_leq32(pointer_bits(self.num_env))? as usize;
+
+                // The frequency resolutions are transmitted in reverse order.
+                for res in self.freq_res[1..=self.num_env].iter_mut().rev() {
+                    *res = bs.read_bit()?;
+                }
+            }
+            FrameClass::VarFix => {
+                abs_bord_lead = bs.read_bits_leq32(2)? as usize;
+                num_rel_lead = bs.read_bits_leq32(2)? as usize;
+
+                for rel in rel_bord_lead[..num_rel_lead].iter_mut() {
+                    *rel = 2 * bs.read_bits_leq32(2)? as usize + 2;
+                }
+
+                self.num_env = num_rel_lead + 1;
+
+                pointer = bs.read_bits_leq32(pointer_bits(self.num_env))? as usize;
+
+                for res in self.freq_res[1..=self.num_env].iter_mut() {
+                    *res = bs.read_bit()?;
+                }
+            }
+            FrameClass::VarVar => {
+                abs_bord_lead = bs.read_bits_leq32(2)? as usize;
+                abs_bord_trail += bs.read_bits_leq32(2)? as usize;
+                num_rel_lead = bs.read_bits_leq32(2)? as usize;
+                num_rel_trail = bs.read_bits_leq32(2)? as usize;
+
+                self.num_env = num_rel_lead + num_rel_trail + 1;
+
+                if self.num_env > MAX_ENVELOPES {
+                    return decode_error("sbr: too many envelopes");
+                }
+
+                for rel in rel_bord_lead[..num_rel_lead].iter_mut() {
+                    *rel = 2 * bs.read_bits_leq32(2)? as usize + 2;
+                }
+
+                for rel in rel_bord_trail[..num_rel_trail].iter_mut() {
+                    *rel = 2 * bs.read_bits_leq32(2)? as usize + 2;
+                }
+
+                pointer = bs.read_bits_leq32(pointer_bits(self.num_env))? as usize;
+
+                for res in self.freq_res[1..=self.num_env].iter_mut() {
+                    *res = bs.read_bit()?;
+                }
+            }
+        }
+
+        let num_env = self.num_env;
+
+        if pointer > num_env + 1 {
+            return decode_error("sbr: invalid transient pointer");
+        }
+
+        // Calculate the envelope time borders from the leading and trailing borders.
+        self.t_env[0] = abs_bord_lead;
+        self.t_env[num_env] = abs_bord_trail;
+
+        for l in 1..=num_rel_lead {
+            self.t_env[l] = self.t_env[l - 1] + rel_bord_lead[l - 1];
+        }
+
+        for l in 1..=num_rel_trail {
+            // The trailing relative borders are subtracted from the trailing absolute border.
+            let border = self.t_env[num_env + 1 - l].checked_sub(rel_bord_trail[l - 1]);
+
+            match border {
+                Some(border) => self.t_env[num_env - l] = border,
+                None => return decode_error("sbr: invalid envelope border"),
+            }
+        }
+
+        if !self.t_env[..=num_env].windows(2).all(|w| w[0] < w[1]) {
+            return decode_error("sbr: invalid envelope border");
+        }
+
+        // Find the envelope starting at a transient.
+        self.l_a = match self.frame_class {
+            FrameClass::FixFix => None,
+            FrameClass::VarFix if pointer > 1 => Some(pointer - 1),
+            FrameClass::VarFix => None,
+            _ if pointer > 0 => Some(num_env + 1 - pointer),
+            _ => None,
+        };
+
+        // Calculate the noise floor time borders.
+        if num_env > 1 {
+            let middle = match self.frame_class {
+                FrameClass::FixFix => num_env / 2,
+                FrameClass::VarFix => match pointer {
+                    0 => 1,
+                    1 => num_env - 1,
+                    _ => pointer - 1,
+                },
+                _ if pointer > 1 => num_env + 1 - pointer,
+                _ => num_env - 1,
+            };
+
+            self.num_noise = 2;
+            self.t_noise = [self.t_env[0], self.t_env[middle], self.t_env[num_env]];
+        }
+        else {
+            self.num_noise = 1;
+            self.t_noise = [self.t_env[0], self.t_env[1], 0];
+        }
+
+        Ok(())
+    }
+
+    /// Copies the time/frequency grid of the frame from another channel.
+    pub fn copy_grid(&mut self, other: &ChannelData) {
+        self.frame_class = other.frame_class;
+        self.amp_res = other.amp_res;
+        self.num_env = other.num_env;
+        self.freq_res[1..].copy_from_slice(&other.freq_res[1..]);
+        self.t_env = other.t_env;
+        self.num_noise = other.num_noise;
+        self.t_noise = other.t_noise;
+        self.l_a = other.l_a;
+    }
+
+    /// Reads the time or frequency direction coding flags of the envelopes and noise floors.
+    pub fn read_dtdf<B: ReadBitsLtr>(&mut self, bs: &mut B) -> Result<()> {
+        for df in self.df_env[..self.num_env].iter_mut() {
+            *df = bs.read_bit()?;
+        }
+
+        for df in self.df_noise[..self.num_noise].iter_mut() {
+            *df = bs.read_bit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the inverse filtering mode of each noise floor band.
+    pub fn read_invf<B: ReadBitsLtr>(&mut self, bs: &mut B, tables: &FreqTables) -> Result<()> {
+        for mode in self.invf_mode[..tables.n_noise()].iter_mut() {
+            *mode = bs.read_bits_leq32(2)? as u8;
+        }
+        Ok(())
+    }
+
+    /// Copies the inverse filtering modes from another channel.
+    pub fn copy_invf(&mut self, other: &ChannelData) {
+        self.invf_mode = other.invf_mode;
+    }
+
+    /// Reads the envelope scale factors. If `balance` is true, the scale factors are the balance
+    /// of a coupled channel pair.
+    pub fn read_envelope<B: ReadBitsLtr>(
+        &mut self,
+        bs: &mut B,
+        tables: &FreqTables,
+        balance: bool,
+    ) -> Result<()> {
+        // Balance scale factors are coded with half the resolution.
+        let scale = if balance { 2 } else { 1 };
+
+        let (start_bits, t_codebook, f_codebook) = match (balance, self.amp_res) {
+            (false, false) => (7, T_ENV_1_5DB, F_ENV_1_5DB),
+            (false, true) => (6, T_ENV_3_0DB, F_ENV_3_0DB),
+            (true, false) => (6, T_ENV_BAL_1_5DB, F_ENV_BAL_1_5DB),
+            (true, true) => (5, T_ENV_BAL_3_0DB, F_ENV_BAL_3_0DB),
+        };
+
+        let odd = tables.n_high() & 1;
+
+        for l in 0..self.num_env {
+            let res = self.freq_res[l + 1];
+            let prev_res = self.freq_res[l];
+
+            let (prev, cur) = self.env_q.split_at_mut(l + 1);
+            let prev = &prev[l];
+            let cur = &mut cur[0][..tables.n_bands(res)];
+
+            if self.df_env[l] {
+                // Delta coded in the time direction. The scale factors of the previous envelope
+                // must be mapped to the frequency resolution of the current envelope.
+                for (j, val) in cur.iter_mut().enumerate() {
+                    let k = match (prev_res, res) {
+                        (false, true) => (j + odd) >> 1,
+                        (true, false) if j > 0 => 2 * j - odd,
+                        (true, false) => 0,
+                        _ => j,
+                    };
+
+                    *val = prev[k] + scale * read_delta(bs, t_codebook)?;
+                }
+            }
+            else {
+                // Delta coded in the frequency direction.
+                let mut acc = scale * bs.read_bits_leq32(start_bits)? as i32;
+
+                cur[0] = acc;
+
+                for val in cur[1..].iter_mut() {
+                    acc += scale * read_delta(bs, f_codebook)?;
+                    *val = acc;
+                }
+            }
+
+            if cur.iter().any(|&val| !(0..=127).contains(&val)) {
+                return decode_error("sbr: invalid envelope scale factor");
+            }
+        }
+
+        self.env_q[0] = self.env_q[self.num_env];
+
+        Ok(())
+    }
+
+    /// Reads the noise floor scale factors. If `balance` is true, the scale factors are the balance
+    /// of a coupled channel pair.
+    pub fn read_noise<B: ReadBitsLtr>(
+        &mut self,
+        bs: &mut B,
+        tables: &FreqTables,
+        balance: bool,
+    ) -> Result<()> {
+        let scale = if balance { 2 } else { 1 };
+
+        let (t_codebook, f_codebook) = match balance {
+            false => (T_NOISE_3_0DB, F_ENV_3_0DB),
+            true => (T_NOISE_BAL_3_0DB, F_ENV_BAL_3_0DB),
+        };
+
+        let n_noise = tables.n_noise();
+
+        for l in 0..self.num_noise {
+            let (prev, cur) = self.noise_q.split_at_mut(l + 1);
+            let prev = &prev[l];
+            let cur = &mut cur[0][..n_noise];
+
+            if self.df_noise[l] {
+                for (val, &prev) in cur.iter_mut().zip(prev.iter()) {
+                    *val = prev + scale * read_delta(bs, t_codebook)?;
+                }
+            }
+            else {
+                let mut acc = scale * bs.read_bits_leq32(5)? as i32;
+
+                cur[0] = acc;
+
+                for val in cur[1..].iter_mut() {
+                    acc += scale * read_delta(bs, f_codebook)?;
+                    *val = acc;
+                }
+            }
+
+            if cur.iter().any(|&val| !(0..=30).contains(&val)) {
+                return decode_error("sbr: invalid noise floor scale factor");
+            }
+        }
+
+        self.noise_q[0] = self.noise_q[self.num_noise];
+
+        Ok(())
+    }
+
+    /// Reads the sinusoidal coding data.
+    pub fn read_sinusoids<B: ReadBitsLtr>(
+        &mut self,
+        bs: &mut B,
+        tables: &FreqTables,
+    ) -> Result<()> {
+        self.add_harmonic_flag = bs.read_bit()?;
+
+        for add_harmonic in self.add_harmonic[..tables.n_high()].iter_mut() {
+            *add_harmonic = self.add_harmonic_flag && bs.read_bit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Dequantizes the envelope and noise floor scale factors of an independently coded channel.
+    pub fn dequantize(&mut self, tables: &FreqTables) {
+        let amp_step = if self.amp_res { 1.0 } else { 0.5 };
+
+        for l in 0..self.num_env {
+            let n_bands = tables.n_bands(self.freq_res[l + 1]);
+
+            for (env, &env_q) in self.env[l][..n_bands].iter_mut().zip(&self.env_q[l + 1]) {
+                *env = 2f32.powf(amp_step * env_q as f32 + 6.0);
+            }
+        }
+
+        for l in 0..self.num_noise {
+            let n_noise = tables.n_noise();
+
+            for (noise, &noise_q) in self.noise[l][..n_noise].iter_mut().zip(&self.noise_q[l + 1]) {
+                *noise = 2f32.powf((NOISE_FLOOR_OFFSET - noise_q) as f32);
+            }
+        }
+    }
+}
+
+/// The offset of the dequantized noise floor scale factors.
+const NOISE_FLOOR_OFFSET: i32 = 6;
+
+/// Gets the number of bits used to signal the transient pointer for a frame with the given number
+/// of envelopes.
+fn pointer_bits(num_env: usize) -> u32 {
+    usize::BITS - num_env.leading_zeros()
+}
+
+/// Dequantizes the envelope and noise floor scale factors of a coupled channel pair. The scale
+/// factors of the left channel are the levels, while those of the right channel are the balance.
+pub fn dequantize_coupled(left: &mut ChannelData, right: &mut ChannelData, tables: &FreqTables) {
+    let (amp_step, pan_offset) = if left.amp_res { (1.0, 12) } else { (0.5, 24) };
+
+    for l in 0..left.num_env {
+        let n_bands = tables.n_bands(left.freq_res[l + 1]);
+
+        for j in 0..n_bands {
+            let level = 2f32.powf(amp_step * left.env_q[l + 1][j] as f32 + 7.0);
+            let ratio = 2f32.powf(amp_step * (pan_offset - right.env_q[l + 1][j]) as f32);
+
+            left.env[l][j] = level / (1.0 + ratio);
+            right.env[l][j] = left.env[l][j] * ratio;
+        }
+    }
+
+    for l in 0..left.num_noise {
+        for j in 0..tables.n_noise() {
+            let level = 2f32.powf((NOISE_FLOOR_OFFSET - left.noise_q[l + 1][j] + 1) as f32);
+            let ratio = 2f32.powf((12 - right.noise_q[l + 1][j]) as f32);
+
+            left.noise[l][j] = level / (1.0 + ratio);
+            right.noise[l][j] = left.noise[l][j] * ratio;
+        }
+    }
+}
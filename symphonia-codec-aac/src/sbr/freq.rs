@@ -0,0 +1,556 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Derivation of the SBR frequency band tables as defined in section 4.6.18.3 of ISO/IEC 14496-3.
+
+use symphonia_core::errors::{decode_error, unsupported_error, Result};
+
+use super::SbrHeader;
+
+/// The maximum number of patches that may be used to generate the high band.
+const MAX_PATCHES: usize = 6;
+
+/// The maximum number of noise floor bands.
+pub const MAX_NOISE_BANDS: usize = 5;
+
+/// Offsets of the start QMF subband, indexed by `bs_start_freq`, for each class of SBR sample
+/// rate. Table 4.82 of ISO/IEC 14496-3.
+const START_FREQ_OFFSETS: [[i8; 16]; 6] = [
+    [-8, -7, -6, -5, -4, -3, -2, -1, 0, 1, 2, 3, 4, 5, 6, 7],   // 16 kHz
+    [-5, -4, -3, -2, -1, 0, 1, 2, 3, 4, 5, 6, 7, 9, 11, 13],    // 22.05 kHz
+    [-5, -3, -2, -1, 0, 1, 2, 3, 4, 5, 6, 7, 9, 11, 13, 16],    // 24 kHz
+    [-6, -4, -2, -1, 0, 1, 2, 3, 4, 5, 6, 7, 9, 11, 13, 16],    // 32 kHz
+    [-4, -2, -1, 0, 1, 2, 3, 4, 5, 6, 7, 9, 11, 13, 16, 20],    // 44.1 to 64 kHz
+    [-2, -1, 0, 1, 2, 3, 4, 5, 6, 7, 9, 11, 13, 16, 20, 24],    // 88.2 kHz and above
+];
+
+/// A patch maps a range of low band QMF subbands into the high band.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Patch {
+    /// The first source (low band) subband of the patch.
+    pub start: usize,
+    /// The number of subbands in the patch.
+    pub len: usize,
+}
+
+/// The frequency band tables derived from an SBR header.
+#[derive(Clone, Debug)]
+pub struct FreqTables {
+    /// The first QMF subband of the master frequency band table.
+    pub k0: usize,
+    /// The first QMF subband of the SBR range.
+    pub kx: usize,
+    /// The number of QMF subbands in the SBR range.
+    pub m: usize,
+    /// The high frequency resolution band borders.
+    pub f_high: Vec<usize>,
+    /// The low frequency resolution band borders.
+    pub f_low: Vec<usize>,
+    /// The noise floor band borders.
+    pub f_noise: Vec<usize>,
+    /// The limiter band borders.
+    pub f_lim: Vec<usize>,
+    /// The patches used to generate the high band.
+    pub patches: Vec<Patch>,
+}
+
+impl FreqTables {
+    /// Derives the frequency band tables for the SBR header and SBR sample rate.
+    pub fn new(header: &SbrHeader, rate: u32) -> Result<FreqTables> {
+        let (k0, k2, f_master) = master_table(header, rate)?;
+
+        let xover_band = usize::from(header.xover_band);
+
+        if xover_band >= f_master.len() - 1 {
+            return decode_error("sbr: invalid crossover band");
+        }
+
+        // The high resolution table is the master table starting at the crossover band.
+        let f_high = f_master[xover_band..].to_vec();
+
+        let n_high = f_high.len() - 1;
+
+        let kx = f_high[0];
+        let m = f_high[n_high] - kx;
+
+        if kx > 32 || kx + m > 64 {
+            return decode_error("sbr: invalid sbr range");
+        }
+
+        // The low resolution table has half as many bands as the high resolution table.
+        let n_low = n_high - n_high / 2;
+        let odd = n_high & 1;
+
+        let mut f_low = Vec::with_capacity(n_low + 1);
+
+        f_low.push(f_high[0]);
+        f_low.extend((1..=n_low).map(|k| f_high[2 * k - odd]));
+
+        // The noise floor table.
+        let n_noise = if header.noise_bands > 0 {
+            let bands = f64::from(header.noise_bands) * (k2 as f64 / kx as f64).log2();
+            ((bands + 0.5) as usize).max(1)
+        }
+        else {
+            1
+        };
+
+        if n_noise > MAX_NOISE_BANDS {
+            return decode_error("sbr: too many noise floor bands");
+        }
+
+        let mut f_noise = Vec::with_capacity(n_noise + 1);
+        let mut i = 0;
+
+        f_noise.push(f_low[0]);
+
+        for k in 1..=n_noise {
+            i += (n_low - i) / (n_noise + 1 - k);
+            f_noise.push(f_low[i]);
+        }
+
+        let patches = patches(&f_master, k0, kx, m, rate)?;
+
+        let f_lim = limiter_table(&f_low, &patches, kx, header.limiter_bands);
+
+        Ok(FreqTables { k0, kx, m, f_high, f_low, f_noise, f_lim, patches })
+    }
+
+    /// Gets the number of high frequency resolution bands.
+    pub fn n_high(&self) -> usize {
+        self.f_high.len() - 1
+    }
+
+    /// Gets the number of low frequency resolution bands.
+    pub fn n_low(&self) -> usize {
+        self.f_low.len() - 1
+    }
+
+    /// Gets the number of noise floor bands.
+    pub fn n_noise(&self) -> usize {
+        self.f_noise.len() - 1
+    }
+
+    /// Gets the number of envelope scale factor bands for the given frequency resolution.
+    pub fn n_bands(&self, high_res: bool) -> usize {
+        if high_res { self.n_high() } else { self.n_low() }
+    }
+
+    /// Gets the envelope scale factor band table for the given frequency resolution.
+    pub fn bands(&self, high_res: bool) -> &[usize] {
+        if high_res { &self.f_high } else { &self.f_low }
+    }
+}
+
+/// Rounds to the nearest integer, rounding halfway cases up.
+#[inline(always)]
+fn nint(val: f64) -> i32 {
+    (val + 0.5).floor() as i32
+}
+
+/// Divides the range `[start, stop)` into `num_bands` bands on a logarithmic scale and returns the
+/// width of each band.
+fn make_bands(start: usize, stop: usize, num_bands: usize) -> Vec<i32> {
+    let ratio = stop as f64 / start as f64;
+
+    let border = |k: usize| nint(start as f64 * ratio.powf(k as f64 / num_bands as f64));
+
+    (0..num_bands).map(|k| border(k + 1) - border(k)).collect()
+}
+
+/// Accumulates band widths, starting from `start`, into a table of band borders. Returns `None` if
+/// any band is empty.
+fn accumulate_bands(start: usize, widths: &[i32]) -> Option<Vec<usize>> {
+    let mut table = Vec::with_capacity(widths.len() + 1);
+    let mut border = start;
+
+    table.push(border);
+
+    for &width in widths {
+        if width <= 0 {
+            return None;
+        }
+
+        border += width as usize;
+        table.push(border);
+    }
+
+    Some(table)
+}
+
+/// Calculates the master frequency band table. Returns the start and stop subbands, k0 and k2, and
+/// the master table.
+fn master_table(header: &SbrHeader, rate: u32) -> Result<(usize, usize, Vec<usize>)> {
+    let offsets = match rate {
+        16000 => &START_FREQ_OFFSETS[0],
+        22050 => &START_FREQ_OFFSETS[1],
+        24000 => &START_FREQ_OFFSETS[2],
+        32000 => &START_FREQ_OFFSETS[3],
+        44100 | 48000 | 64000 => &START_FREQ_OFFSETS[4],
+        88200 | 96000 => &START_FREQ_OFFSETS[5],
+        _ => return unsupported_error("sbr: unsupported sample rate"),
+    };
+
+    let min_freq = match rate {
+        0..=31999 => 3000,
+        32000..=63999 => 4000,
+        _ => 5000,
+    };
+
+    let start_min = (min_freq * 128 + rate / 2) / rate;
+    let stop_min = (min_freq * 256 + rate / 2) / rate;
+
+    let k0 = start_min as i32 + i32::from(offsets[usize::from(header.start_freq)]);
+
+    if k0 <= 0 {
+        return decode_error("sbr: invalid start frequency");
+    }
+
+    let k0 = k0 as usize;
+
+    let k2 = match header.stop_freq {
+        14 => 2 * k0,
+        15 => 3 * k0,
+        stop_freq => {
+            let mut stop_dk = make_bands(stop_min as usize, 64, 13);
+            stop_dk.sort_unstable();
+
+            let sum: i32 = stop_dk[..usize::from(stop_freq)].iter().sum();
+
+            (stop_min as i32 + sum) as usize
+        }
+    };
+
+    let k2 = k2.min(64);
+
+    // The maximum number of QMF subbands in the SBR range.
+    let max_subbands = match rate {
+        0..=32000 => 48,
+        32001..=47999 => 35,
+        _ => 32,
+    };
+
+    if k2 <= k0 || k2 - k0 > max_subbands {
+        return decode_error("sbr: invalid frequency range");
+    }
+
+    let table = if header.freq_scale == 0 {
+        // Linear frequency scale.
+        let dk = if header.alter_scale { 2 } else { 1 };
+
+        let num_bands = if header.alter_scale {
+            2 * nint((k2 - k0) as f64 / 4.0)
+        }
+        else {
+            2 * ((k2 - k0) as i32 / 2)
+        };
+
+        if num_bands <= 0 {
+            return decode_error("sbr: invalid number of master bands");
+        }
+
+        let mut widths = vec![dk; num_bands as usize];
+
+        // Distribute the remainder of the frequency range across the bands. A surplus is
+        // removed from the lowest bands, while a deficit is added to the highest bands.
+        let k2_diff = (k2 - k0) as i32 - num_bands * dk;
+
+        if k2_diff < 0 {
+            for width in widths.iter_mut().take(k2_diff.unsigned_abs() as usize) {
+                *width -= 1;
+            }
+        }
+        else {
+            for width in widths.iter_mut().rev().take(k2_diff as usize) {
+                *width += 1;
+            }
+        }
+
+        accumulate_bands(k0, &widths)
+    }
+    else {
+        // Logarithmic frequency scale with 12, 10, or 8 bands per octave.
+        let bands = f64::from(14 - 2 * header.freq_scale);
+        let warp = if header.alter_scale { 1.3 } else { 1.0 };
+
+        // If the frequency range spans more than ~2.2449 octaves, it is split into two regions.
+        let two_regions = 49 * k2 > 110 * k0;
+
+        let k1 = if two_regions { 2 * k0 } else { k2 };
+
+        let num_bands0 = 2 * nint(bands * (k1 as f64 / k0 as f64).log2() / 2.0);
+
+        if num_bands0 <= 0 {
+            return decode_error("sbr: invalid number of master bands");
+        }
+
+        let mut widths0 = make_bands(k0, k1, num_bands0 as usize);
+        widths0.sort_unstable();
+
+        let table0 = accumulate_bands(k0, &widths0);
+
+        if two_regions {
+            let num_bands1 = 2 * nint(bands * (k2 as f64 / k1 as f64).log2() / (2.0 * warp));
+
+            if num_bands1 <= 0 {
+                return decode_error("sbr: invalid number of master bands");
+            }
+
+            let mut widths1 = make_bands(k1, k2, num_bands1 as usize);
+            widths1.sort_unstable();
+
+            // The bands of the second region should not be narrower than those of the first.
+            let max0 = widths0[widths0.len() - 1];
+            let last = widths1.len() - 1;
+
+            if widths1[0] < max0 {
+                let change = (max0 - widths1[0]).min((widths1[last] - widths1[0]) / 2);
+
+                widths1[0] += change;
+                widths1[last] -= change;
+                widths1.sort_unstable();
+            }
+
+            match (table0, accumulate_bands(k1, &widths1)) {
+                (Some(mut table0), Some(table1)) => {
+                    table0.extend_from_slice(&table1[1..]);
+                    Some(table0)
+                }
+                _ => None,
+            }
+        }
+        else {
+            table0
+        }
+    };
+
+    match table {
+        Some(table) => Ok((k0, k2, table)),
+        None => decode_error("sbr: invalid master frequency band table"),
+    }
+}
+
+/// Calculates the patches that map the low band into the high band as defined in section
+/// 4.6.18.6.3 of ISO/IEC 14496-3.
+fn patches(f_master: &[usize], k0: usize, kx: usize, m: usize, rate: u32) -> Result<Vec<Patch>> {
+    let n_master = f_master.len() - 1;
+
+    let goal_sb = ((2_048_000 + rate / 2) / rate) as usize;
+
+    let mut k = if goal_sb < kx + m {
+        f_master.iter().position(|&f| f >= goal_sb).unwrap_or(n_master)
+    }
+    else {
+        n_master
+    };
+
+    let mut patches = Vec::with_capacity(MAX_PATCHES);
+
+    let mut msb = k0;
+    let mut usb = kx;
+    let mut last = None;
+
+    loop {
+        // Patch construction must always make progress.
+        if last == Some((k, msb)) {
+            return decode_error("sbr: patch construction failed");
+        }
+
+        last = Some((k, msb));
+
+        // Find the highest master band border that can be patched from the low band while
+        // maintaining the parity of the source and destination subbands.
+        let mut i = k;
+        let mut sb;
+        let mut odd;
+
+        loop {
+            sb = f_master[i];
+            odd = (sb + k0) & 1;
+
+            if i == 0 || sb + odd < k0 + msb {
+                break;
+            }
+
+            i -= 1;
+        }
+
+        if patches.len() >= MAX_PATCHES {
+            return decode_error("sbr: too many patches");
+        }
+
+        let len = sb.saturating_sub(usb);
+
+        if len > 0 {
+            patches.push(Patch { start: k0 - odd - len, len });
+            usb = sb;
+            msb = sb;
+        }
+        else {
+            msb = kx;
+        }
+
+        if f_master[k] < sb + 3 {
+            k = n_master;
+        }
+
+        if sb == kx + m {
+            break;
+        }
+    }
+
+    // Drop the last patch if it is too narrow.
+    if patches.len() > 1 && patches[patches.len() - 1].len < 3 {
+        patches.pop();
+    }
+
+    Ok(patches)
+}
+
+/// Calculates the limiter band table as defined in section 4.6.18.3.2.3 of ISO/IEC 14496-3.
+fn limiter_table(f_low: &[usize], patches: &[Patch], kx: usize, limiter_bands: u8) -> Vec<usize> {
+    let n_low = f_low.len() - 1;
+
+    if limiter_bands == 0 {
+        return vec![f_low[0], f_low[n_low]];
+    }
+
+    let bands_per_octave = [1.2, 2.0, 3.0][usize::from(limiter_bands) - 1];
+
+    // The borders of each patch.
+    let mut patch_borders = Vec::with_capacity(patches.len() + 1);
+
+    patch_borders.push(kx);
+
+    for patch in patches {
+        patch_borders.push(patch_borders[patch_borders.len() - 1] + patch.len);
+    }
+
+    // The end of the SBR range must always remain a border, even if the patches do not reach it.
+    patch_borders.push(f_low[n_low]);
+
+    let mut table = f_low.to_vec();
+
+    if patches.len() > 1 {
+        table.extend_from_slice(&patch_borders[1..patches.len()]);
+    }
+
+    table.sort_unstable();
+
+    // Remove limiter bands that are narrower than 0.49 of the desired limiter band width, unless
+    // the border is also a patch border.
+    let mut k = 1;
+
+    while k < table.len() {
+        let octaves = (table[k] as f64 / table[k - 1] as f64).log2();
+
+        if octaves * bands_per_octave < 0.49 {
+            if table[k] == table[k - 1] || !patch_borders.contains(&table[k]) {
+                table.remove(k);
+                continue;
+            }
+            else if !patch_borders.contains(&table[k - 1]) {
+                table.remove(k - 1);
+                continue;
+            }
+        }
+
+        k += 1;
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SbrHeader;
+    use super::*;
+
+    fn make_header(start_freq: u8, stop_freq: u8, freq_scale: u8, alter_scale: bool) -> SbrHeader {
+        SbrHeader { start_freq, stop_freq, freq_scale, alter_scale, ..Default::default() }
+    }
+
+    fn verify_tables(tables: &FreqTables) {
+        // Every table must be strictly increasing and cover the SBR range.
+        for table in [&tables.f_high, &tables.f_low, &tables.f_noise, &tables.f_lim].iter() {
+            assert!(table.windows(2).all(|w| w[0] <= w[1]));
+            assert_eq!(table[0], tables.kx);
+            assert_eq!(table[table.len() - 1], tables.kx + tables.m);
+        }
+
+        // The patches must exactly fill the SBR range from source subbands within the low band.
+        let len: usize = tables.patches.iter().map(|patch| patch.len).sum();
+
+        assert!(tables.patches.len() <= MAX_PATCHES);
+        assert!(len <= tables.m);
+
+        for patch in tables.patches.iter() {
+            assert!(patch.start >= 1);
+            assert!(patch.start + patch.len <= tables.k0);
+        }
+    }
+
+    #[test]
+    fn verify_linear_master_table() {
+        // A 44.1 kHz stream with bs_start_freq = 5 and bs_stop_freq = 9.
+        let (k0, k2, table) = master_table(&make_header(5, 9, 0, false), 44100).unwrap();
+
+        assert_eq!(k0, 14);
+        assert_eq!(k2, 47);
+
+        // The odd subband is added to the last band.
+        assert_eq!(table.len(), 33);
+        assert!(table[..32].windows(2).all(|w| w[1] - w[0] == 1));
+        assert_eq!(table[32] - table[31], 2);
+
+        // With the alternate scale, bands are 2 subbands wide.
+        let (_, _, table) = master_table(&make_header(5, 9, 0, true), 44100).unwrap();
+
+        assert_eq!(table.len(), 17);
+        assert!(table[..16].windows(2).all(|w| w[1] - w[0] == 2));
+        assert_eq!(table[16] - table[15], 3);
+    }
+
+    #[test]
+    fn verify_log_master_table() {
+        // A 44.1 kHz stream with the default frequency scale.
+        let (k0, k2, table) = master_table(&make_header(5, 9, 2, true), 44100).unwrap();
+
+        assert_eq!(table[0], k0);
+        assert_eq!(table[table.len() - 1], k2);
+
+        // Band widths must be non-decreasing within each region.
+        let widths: Vec<usize> = table.windows(2).map(|w| w[1] - w[0]).collect();
+
+        assert!(widths.iter().all(|&w| w > 0));
+        assert_eq!((table.len() - 1) % 2, 0);
+    }
+
+    #[test]
+    fn verify_freq_tables() {
+        for &rate in [16000, 22050, 24000, 32000, 44100, 48000].iter() {
+            for start_freq in 0..16 {
+                for stop_freq in 0..16 {
+                    for freq_scale in 0..4 {
+                        for &alter_scale in [false, true].iter() {
+                            let mut header =
+                                make_header(start_freq, stop_freq, freq_scale, alter_scale);
+
+                            for limiter_bands in 0..4 {
+                                header.limiter_bands = limiter_bands;
+
+                                if let Ok(tables) = FreqTables::new(&header, rate) {
+                                    verify_tables(&tables);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,479 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! High frequency generation and adjustment as defined in section 4.6.18.6 and section 4.6.18.7 of
+//! ISO/IEC 14496-3.
+
+use symphonia_core::dsp::fft::Complex;
+
+use lazy_static::lazy_static;
+
+use super::data::ChannelData;
+use super::freq::{FreqTables, MAX_NOISE_BANDS};
+use super::{SbrHeader, MAX_BANDS, MAX_ENVELOPES, NUM_TIME_SLOTS, T_HF_ADJ};
+
+/// The maximum number of QMF slots spanned by the envelopes of a frame, including the slots of the
+/// trailing border that may extend into the next frame.
+pub const MAX_ENV_SLOTS: usize = 2 * (NUM_TIME_SLOTS + 3);
+
+/// The length of the gain smoothing filter.
+const SMOOTH_LEN: usize = 4;
+
+/// The gain smoothing filter coefficients. Table 4.182 of ISO/IEC 14496-3.
+const H_SMOOTH: [f32; SMOOTH_LEN + 1] = [
+    0.333_333_34,
+    0.301_502_83,
+    0.218_169_5,
+    0.115_163_83,
+    0.031_830_5,
+];
+
+/// The maximum gain of each limiter gains mode.
+const LIMITER_GAINS: [f32; 4] = [0.707_95, 1.0, 1.412_54, 1e10];
+
+/// The maximum gain boost.
+const MAX_BOOST: f32 = 1.584_893_2;
+
+/// The real and imaginary parts of the sinusoid phase for each sinusoid index.
+const SINE_PHASE: [(f32, f32); 4] = [(1.0, 0.0), (0.0, 1.0), (-1.0, 0.0), (0.0, -1.0)];
+
+/// The length of the noise table.
+const NOISE_TABLE_LEN: usize = 512;
+
+lazy_static! {
+    /// The noise table, V.
+    ///
+    /// The noise table is a pseudo-random sequence of complex values with zero mean and unit mean
+    /// energy.
+    static ref NOISE_TABLE: [Complex; NOISE_TABLE_LEN] = {
+        let mut table = [Complex::default(); NOISE_TABLE_LEN];
+
+        let mut state = 1u32;
+        let mut next = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state as i32) as f64 / f64::from(1u32 << 31)
+        };
+
+        for v in table.iter_mut() {
+            let re = next();
+            let im = next();
+            *v = Complex::new(re as f32, im as f32);
+        }
+
+        let energy = table.iter().map(|v| f64::from(v.norm_sqr())).sum::<f64>();
+        let scale = (NOISE_TABLE_LEN as f64 / energy).sqrt() as f32;
+
+        for v in table.iter_mut() {
+            *v = v.scale(scale);
+        }
+
+        table
+    };
+}
+
+/// A block of 32 low band QMF subbands for each QMF slot.
+pub type LowBand = [[Complex; 32]];
+
+/// A block of 64 QMF subbands for each QMF slot.
+pub type FullBand = [[Complex; 64]];
+
+/// Calculates the linear prediction coefficients used to inverse filter each low band subband.
+pub fn inverse_filter(
+    x_low: &LowBand,
+    k0: usize,
+    alpha0: &mut [Complex; 32],
+    alpha1: &mut [Complex; 32],
+) {
+    const N: usize = 2 * NUM_TIME_SLOTS + 6;
+
+    for k in 0..k0 {
+        let x = |l: usize| x_low[l][k];
+
+        let mut phi01 = Complex::default();
+        let mut phi02 = Complex::default();
+        let mut phi12 = Complex::default();
+        let mut phi11 = 0.0;
+        let mut phi22 = 0.0;
+
+        for n in 0..N {
+            phi01 = phi01 + x(n + 2) * x(n + 1).conj();
+            phi02 = phi02 + x(n + 2) * x(n).conj();
+            phi12 = phi12 + x(n + 1) * x(n).conj();
+            phi11 += x(n + 1).norm_sqr();
+            phi22 += x(n).norm_sqr();
+        }
+
+        let d = phi22 * phi11 - phi12.norm_sqr() / (1.0 + 1e-6);
+
+        let a1 = if d != 0.0 {
+            (phi01 * phi12 - phi02.scale(phi11)).scale(1.0 / d)
+        }
+        else {
+            Complex::default()
+        };
+
+        let a0 = if phi11 != 0.0 {
+            (phi01 + a1 * phi12.conj()).scale(-1.0 / phi11)
+        }
+        else {
+            Complex::default()
+        };
+
+        // Unstable predictors are not used.
+        if a0.norm_sqr() >= 16.0 || a1.norm_sqr() >= 16.0 {
+            alpha0[k] = Complex::default();
+            alpha1[k] = Complex::default();
+        }
+        else {
+            alpha0[k] = a0;
+            alpha1[k] = a1;
+        }
+    }
+}
+
+/// The high frequency generation and adjustment state of one channel.
+#[derive(Clone)]
+pub struct HfState {
+    /// The chirp factor of each noise floor band.
+    bw: [f32; MAX_NOISE_BANDS],
+    /// The gains of the last slots of the previous frame.
+    g_hist: [[f32; MAX_BANDS]; SMOOTH_LEN],
+    /// The noise levels of the last slots of the previous frame.
+    q_hist: [[f32; MAX_BANDS]; SMOOTH_LEN],
+    /// If false, the gain and noise level history is not valid.
+    has_hist: bool,
+    /// The sinusoid placement of the last envelope of the previous frame.
+    s_index_prev: [bool; MAX_BANDS],
+    idx_noise: usize,
+    idx_sine: usize,
+}
+
+impl HfState {
+    pub fn new() -> Self {
+        HfState {
+            bw: [0.0; MAX_NOISE_BANDS],
+            g_hist: [[0.0; MAX_BANDS]; SMOOTH_LEN],
+            q_hist: [[0.0; MAX_BANDS]; SMOOTH_LEN],
+            has_hist: false,
+            s_index_prev: [false; MAX_BANDS],
+            idx_noise: 0,
+            idx_sine: 0,
+        }
+    }
+
+    /// Resets the state after the frequency band tables change.
+    pub fn reset(&mut self) {
+        self.bw = [0.0; MAX_NOISE_BANDS];
+        self.has_hist = false;
+        self.s_index_prev = [false; MAX_BANDS];
+    }
+
+    /// Generates the high band from the low band by patching, and inverse filtering, the low band
+    /// subbands into the high band.
+    pub fn generate(
+        &mut self,
+        x_low: &LowBand,
+        x_high: &mut FullBand,
+        tables: &FreqTables,
+        data: &ChannelData,
+        alpha0: &[Complex; 32],
+        alpha1: &[Complex; 32],
+    ) {
+        // Update the chirp factors.
+        for i in 0..tables.n_noise() {
+            let mode = data.invf_mode[i];
+            let prev_mode = data.invf_mode_prev[i];
+
+            let new_bw = if mode + prev_mode == 1 {
+                0.6
+            }
+            else {
+                [0.0, 0.75, 0.9, 0.98][usize::from(mode)]
+            };
+
+            let bw = if new_bw < self.bw[i] {
+                0.75 * new_bw + 0.25 * self.bw[i]
+            }
+            else {
+                0.90625 * new_bw + 0.09375 * self.bw[i]
+            };
+
+            self.bw[i] = if bw < 0.015_625 { 0.0 } else { bw };
+        }
+
+        let start = 2 * data.t_env[0] + T_HF_ADJ;
+        let end = 2 * data.t_env[data.num_env] + T_HF_ADJ;
+
+        for slot in x_high[start..end].iter_mut() {
+            *slot = [Complex::default(); 64];
+        }
+
+        let mut k = tables.kx;
+
+        for patch in tables.patches.iter() {
+            for p in patch.start..patch.start + patch.len {
+                // Find the noise floor band containing the subband.
+                let g = tables.f_noise[1..tables.n_noise()].iter().take_while(|&&f| f <= k).count();
+
+                let bw = self.bw[g];
+
+                let a0 = alpha0[p].scale(bw);
+                let a1 = alpha1[p].scale(bw * bw);
+
+                for l in start..end {
+                    x_high[l][k] = x_low[l][p] + a0 * x_low[l - 1][p] + a1 * x_low[l - 2][p];
+                }
+
+                k += 1;
+            }
+        }
+    }
+
+    /// Adjusts the envelope of the generated high band, and adds the noise floor and sinusoids.
+    pub fn adjust(
+        &mut self,
+        x_high: &FullBand,
+        y: &mut FullBand,
+        tables: &FreqTables,
+        header: &SbrHeader,
+        data: &ChannelData,
+    ) {
+        let kx = tables.kx;
+        let m_max = tables.m;
+        let num_env = data.num_env;
+
+        let mut e_orig = [[0f32; MAX_BANDS]; MAX_ENVELOPES];
+        let mut e_curr = [[0f32; MAX_BANDS]; MAX_ENVELOPES];
+        let mut q_mapped = [[0f32; MAX_BANDS]; MAX_ENVELOPES];
+        let mut s_index = [[false; MAX_BANDS]; MAX_ENVELOPES];
+        let mut s_mapped = [[false; MAX_BANDS]; MAX_ENVELOPES];
+
+        // Map the envelope and noise floor scale factors, and the sinusoids, to QMF subbands.
+        for e in 0..num_env {
+            let bands = tables.bands(data.freq_res[e + 1]);
+
+            for (w, &env) in bands.windows(2).zip(&data.env[e]) {
+                for val in e_orig[e][w[0] - kx..w[1] - kx].iter_mut() {
+                    *val = env;
+                }
+            }
+
+            let l = if data.num_noise > 1 && data.t_env[e] >= data.t_noise[1] { 1 } else { 0 };
+
+            for (w, &noise) in tables.f_noise.windows(2).zip(&data.noise[l]) {
+                for val in q_mapped[e][w[0] - kx..w[1] - kx].iter_mut() {
+                    *val = noise;
+                }
+            }
+
+            if data.add_harmonic_flag {
+                for (w, &add) in tables.f_high.windows(2).zip(&data.add_harmonic) {
+                    let mid = (w[0] + w[1]) / 2 - kx;
+
+                    let after_transient = match data.l_a {
+                        Some(l_a) => e >= l_a,
+                        None => true,
+                    };
+
+                    s_index[e][mid] = add && (after_transient || self.s_index_prev[mid]);
+                }
+            }
+
+            for w in bands.windows(2) {
+                let range = w[0] - kx..w[1] - kx;
+                let present = s_index[e][range.clone()].iter().any(|&s| s);
+
+                for val in s_mapped[e][range].iter_mut() {
+                    *val = present;
+                }
+            }
+        }
+
+        if num_env > 0 {
+            self.s_index_prev = s_index[num_env - 1];
+        }
+
+        // Estimate the envelope of the generated high band.
+        for e in 0..num_env {
+            let start = 2 * data.t_env[e] + T_HF_ADJ;
+            let end = 2 * data.t_env[e + 1] + T_HF_ADJ;
+
+            let energy = |k: usize| x_high[start..end].iter().map(|s| s[k].norm_sqr()).sum::<f32>();
+
+            if header.interpol_freq {
+                for (m, e_curr) in e_curr[e][..m_max].iter_mut().enumerate() {
+                    *e_curr = energy(kx + m) / (end - start) as f32;
+                }
+            }
+            else {
+                for w in tables.bands(data.freq_res[e + 1]).windows(2) {
+                    let sum = (w[0]..w[1]).map(energy).sum::<f32>();
+                    let avg = sum / ((end - start) * (w[1] - w[0])) as f32;
+
+                    for val in e_curr[e][w[0] - kx..w[1] - kx].iter_mut() {
+                        *val = avg;
+                    }
+                }
+            }
+        }
+
+        // Calculate the gains, noise levels, and sinusoid levels.
+        let mut gain = [[0f32; MAX_BANDS]; MAX_ENVELOPES];
+        let mut q_m = [[0f32; MAX_BANDS]; MAX_ENVELOPES];
+        let mut s_m = [[0f32; MAX_BANDS]; MAX_ENVELOPES];
+
+        let limiter_gain = LIMITER_GAINS[usize::from(header.limiter_gains)];
+
+        for e in 0..num_env {
+            // Noise is not added to the envelopes starting at a transient.
+            let is_transient = Some(e) == data.l_a || Some(e) == data.l_a_prev;
+            let delta = if is_transient { 0.0 } else { 1.0 };
+
+            for w in tables.f_lim.windows(2) {
+                let range = w[0] - kx..w[1] - kx;
+
+                for m in range.clone() {
+                    let orig = e_orig[e][m];
+                    let q = q_mapped[e][m];
+                    let s = if s_index[e][m] { 1.0 } else { 0.0 };
+
+                    q_m[e][m] = (orig * q / (1.0 + q)).sqrt();
+                    s_m[e][m] = (orig * s / (1.0 + q)).sqrt();
+
+                    let g = if s_mapped[e][m] {
+                        orig * q / ((1.0 + e_curr[e][m]) * (1.0 + q))
+                    }
+                    else {
+                        orig / ((1.0 + e_curr[e][m]) * (1.0 + q * delta))
+                    };
+
+                    gain[e][m] = g.sqrt() + f32::MIN_POSITIVE;
+                }
+
+                // Limit the gain of each limiter band.
+                let sum_orig = e_orig[e][range.clone()].iter().sum::<f32>();
+                let sum_curr = e_curr[e][range.clone()].iter().sum::<f32>();
+
+                let ratio = (f32::EPSILON + sum_orig) / (f32::EPSILON + sum_curr);
+                let gain_max = (limiter_gain * ratio.sqrt()).min(1e5);
+
+                for m in range.clone() {
+                    q_m[e][m] = q_m[e][m].min(q_m[e][m] * gain_max / gain[e][m]);
+                    gain[e][m] = gain[e][m].min(gain_max);
+                }
+
+                // Compensate for the energy lost by limiting.
+                let mut sum_adj = 0.0;
+
+                for m in range.clone() {
+                    sum_adj += e_curr[e][m] * gain[e][m] * gain[e][m] + s_m[e][m] * s_m[e][m];
+
+                    if !is_transient && s_m[e][m] == 0.0 {
+                        sum_adj += q_m[e][m] * q_m[e][m];
+                    }
+                }
+
+                let boost = ((f32::EPSILON + sum_orig) / (f32::EPSILON + sum_adj)).sqrt();
+                let boost = boost.min(MAX_BOOST);
+
+                for m in range {
+                    gain[e][m] *= boost;
+                    q_m[e][m] *= boost;
+                    s_m[e][m] *= boost;
+                }
+            }
+        }
+
+        // Expand the gains and noise levels to each slot. The first slots hold the gains and noise
+        // levels of the previous frame used for smoothing.
+        let smooth_len = if header.smoothing_mode { 0 } else { SMOOTH_LEN };
+
+        let mut g_temp = [[0f32; MAX_BANDS]; MAX_ENV_SLOTS + SMOOTH_LEN];
+        let mut q_temp = [[0f32; MAX_BANDS]; MAX_ENV_SLOTS + SMOOTH_LEN];
+
+        let hist_start = 2 * data.t_env[0];
+
+        for j in 0..SMOOTH_LEN {
+            if self.has_hist {
+                g_temp[hist_start + j] = self.g_hist[j];
+                q_temp[hist_start + j] = self.q_hist[j];
+            }
+            else if num_env > 0 {
+                g_temp[hist_start + j] = gain[0];
+                q_temp[hist_start + j] = q_m[0];
+            }
+        }
+
+        for e in 0..num_env {
+            for i in 2 * data.t_env[e]..2 * data.t_env[e + 1] {
+                g_temp[i + smooth_len] = gain[e];
+                q_temp[i + smooth_len] = q_m[e];
+            }
+        }
+
+        let noise_table = &*NOISE_TABLE;
+
+        for (e, s_m) in s_m[..num_env].iter().enumerate() {
+            let is_transient = Some(e) == data.l_a || Some(e) == data.l_a_prev;
+
+            for i in 2 * data.t_env[e]..2 * data.t_env[e + 1] {
+                let mut g_filt = [0f32; MAX_BANDS];
+                let mut q_filt = [0f32; MAX_BANDS];
+
+                if smooth_len > 0 && !is_transient {
+                    for (j, &h) in H_SMOOTH.iter().enumerate() {
+                        let g_row = &g_temp[i + smooth_len - j];
+                        let q_row = &q_temp[i + smooth_len - j];
+
+                        for m in 0..m_max {
+                            g_filt[m] += h * g_row[m];
+                            q_filt[m] += h * q_row[m];
+                        }
+                    }
+                }
+                else {
+                    g_filt = g_temp[i + smooth_len];
+                    q_filt = q_temp[i + smooth_len];
+                }
+
+                let (sine_re, sine_im) = SINE_PHASE[self.idx_sine];
+
+                for m in 0..m_max {
+                    let k = kx + m;
+
+                    let mut val = x_high[i + T_HF_ADJ][k].scale(g_filt[m]);
+
+                    // The sign of the imaginary part of a sinusoid alternates between subbands.
+                    let sine_im = if k & 1 == 0 { sine_im } else { -sine_im };
+
+                    if s_m[m] != 0.0 {
+                        val = val + Complex::new(s_m[m] * sine_re, s_m[m] * sine_im);
+                    }
+                    else if !is_transient {
+                        let idx = (self.idx_noise + m + 1) % NOISE_TABLE_LEN;
+                        val = val + noise_table[idx].scale(q_filt[m]);
+                    }
+
+                    y[i][k] = val;
+                }
+
+                self.idx_noise = (self.idx_noise + m_max) % NOISE_TABLE_LEN;
+                self.idx_sine = (self.idx_sine + 1) & 3;
+            }
+        }
+
+        // Save the gains and noise levels of the last slots for the next frame.
+        if num_env > 0 {
+            let hist_start = 2 * data.t_env[num_env] + smooth_len - SMOOTH_LEN;
+
+            self.g_hist.copy_from_slice(&g_temp[hist_start..hist_start + SMOOTH_LEN]);
+            self.q_hist.copy_from_slice(&q_temp[hist_start..hist_start + SMOOTH_LEN]);
+
+            self.has_hist = true;
+        }
+    }
+}
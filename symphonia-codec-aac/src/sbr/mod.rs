@@ -0,0 +1,712 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `sbr` module implements the Spectral Band Replication (SBR) tool of High-Efficiency AAC
+//! (HE-AAC) as defined in section 4.6.18 of ISO/IEC 14496-3.
+//!
+//! SBR reconstructs the high band of a signal from the low band decoded by the AAC core decoder.
+//! The output of the core decoder is split into 32 subbands by a QMF analysis filterbank. The high
+//! band subbands are generated by patching the low band subbands, and then adjusting their spectral
+//! envelope to the transmitted envelope. Noise and sinusoids are added to the high band where the
+//! patched subbands are not tonal or noisy enough. Lastly, the low and high band subbands are
+//! transformed back into PCM samples by a 64-band QMF synthesis filterbank, which yields twice the
+//! sample rate of the core decoder.
+//!
+//! The SBR payload of a single channel element may also carry Parametric Stereo (PS) data, from
+//! which a stereo signal is reconstructed in the QMF domain before synthesis. See the `ps` module.
+
+use symphonia_core::dsp::fft::Complex;
+use symphonia_core::errors::{decode_error, Result};
+use symphonia_core::io::{BitReaderLtr, FiniteBitStream, ReadBitsLtr};
+
+use log::warn;
+
+mod codebooks;
+mod data;
+mod freq;
+mod hf;
+mod ps;
+mod qmf;
+
+use data::ChannelData;
+use freq::FreqTables;
+use hf::{HfState, MAX_ENV_SLOTS};
+use ps::{Ps, EXTENSION_ID_PS};
+use qmf::{Qmf, QmfAnalysis, QmfSynthesis};
+
+/// The number of PCM samples output by the AAC core decoder per frame.
+pub const CORE_FRAME_LEN: usize = 1024;
+
+/// The number of SBR time slots per frame.
+pub const NUM_TIME_SLOTS: usize = 16;
+
+/// The number of QMF slots per SBR time slot.
+const RATE: usize = 2;
+
+/// The number of QMF slots per frame.
+const NUM_QMF_SLOTS: usize = NUM_TIME_SLOTS * RATE;
+
+/// The number of QMF slots of the previous frame required for high frequency generation.
+const T_HF_GEN: usize = 8;
+
+/// The offset of the first QMF slot of the high frequency adjustment.
+pub const T_HF_ADJ: usize = 2;
+
+/// The maximum number of envelopes per frame.
+pub const MAX_ENVELOPES: usize = 5;
+
+/// The maximum number of QMF subbands in the SBR range.
+pub const MAX_BANDS: usize = 48;
+
+/// The extension payload type of SBR data.
+pub const EXT_SBR_DATA: u32 = 13;
+
+/// The extension payload type of SBR data protected by a CRC.
+pub const EXT_SBR_DATA_CRC: u32 = 14;
+
+/// The SBR header.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SbrHeader {
+    pub amp_res: bool,
+    pub start_freq: u8,
+    pub stop_freq: u8,
+    pub xover_band: u8,
+    pub freq_scale: u8,
+    pub alter_scale: bool,
+    pub noise_bands: u8,
+    pub limiter_bands: u8,
+    pub limiter_gains: u8,
+    pub interpol_freq: bool,
+    pub smoothing_mode: bool,
+}
+
+impl SbrHeader {
+    fn read<B: ReadBitsLtr>(bs: &mut B) -> Result<Self> {
+        let amp_res = bs.read_bit()?;
+        let start_freq = bs.read_bits_leq32(4)? as u8;
+        let stop_freq = bs.read_bits_leq32(4)? as u8;
+        let xover_band = bs.read_bits_leq32(3)? as u8;
+        let _reserved = bs.read_bits_leq32(2)?;
+        let header_extra_1 = bs.read_bit()?;
+        let header_extra_2 = bs.read_bit()?;
+
+        let mut header = SbrHeader {
+            amp_res,
+            start_freq,
+            stop_freq,
+            xover_band,
+            freq_scale: 2,
+            alter_scale: true,
+            noise_bands: 2,
+            limiter_bands: 2,
+            limiter_gains: 2,
+            interpol_freq: true,
+            smoothing_mode: true,
+        };
+
+        if header_extra_1 {
+            header.freq_scale = bs.read_bits_leq32(2)? as u8;
+            header.alter_scale = bs.read_bit()?;
+            header.noise_bands = bs.read_bits_leq32(2)? as u8;
+        }
+
+        if header_extra_2 {
+            header.limiter_bands = bs.read_bits_leq32(2)? as u8;
+            header.limiter_gains = bs.read_bits_leq32(2)? as u8;
+            header.interpol_freq = bs.read_bit()?;
+            header.smoothing_mode = bs.read_bit()?;
+        }
+
+        Ok(header)
+    }
+
+    /// Returns true if a change from this header to the other header requires the SBR state to be
+    /// reset.
+    fn needs_reset(&self, other: &SbrHeader) -> bool {
+        self.start_freq != other.start_freq
+            || self.stop_freq != other.stop_freq
+            || self.freq_scale != other.freq_scale
+            || self.alter_scale != other.alter_scale
+            || self.xover_band != other.xover_band
+            || self.noise_bands != other.noise_bands
+    }
+}
+
+/// The transforms and scratch buffers shared by all SBR elements.
+pub struct SbrDsp {
+    qmf: Qmf,
+    /// The low band QMF subbands.
+    x_low: Vec<[Complex; 32]>,
+    /// The generated high band QMF subbands.
+    x_high: Vec<[Complex; 64]>,
+    /// The adjusted high band QMF subbands.
+    y: Vec<[Complex; 64]>,
+    /// The QMF subbands to synthesize for each channel.
+    x: [Vec<[Complex; 64]>; 2],
+}
+
+impl SbrDsp {
+    pub fn new() -> Self {
+        SbrDsp {
+            qmf: Qmf::new(),
+            x_low: vec![[Default::default(); 32]; NUM_QMF_SLOTS + T_HF_GEN],
+            x_high: vec![[Default::default(); 64]; NUM_QMF_SLOTS + T_HF_GEN],
+            y: vec![[Default::default(); 64]; MAX_ENV_SLOTS],
+            x: [
+                vec![[Default::default(); 64]; NUM_QMF_SLOTS],
+                vec![[Default::default(); 64]; NUM_QMF_SLOTS],
+            ],
+        }
+    }
+}
+
+/// The SBR state of one channel.
+#[derive(Clone)]
+struct SbrChannel {
+    data: ChannelData,
+    hf: HfState,
+    analysis: QmfAnalysis,
+    synthesis: QmfSynthesis,
+    /// The last low band QMF slots of the previous frame.
+    w_hist: [[Complex; 32]; T_HF_GEN],
+    /// The adjusted high band QMF slots of the previous frame that extend into the current frame.
+    y_hist: [[Complex; 64]; MAX_ENV_SLOTS - NUM_QMF_SLOTS],
+    /// The number of valid slots in `y_hist`.
+    y_hist_len: usize,
+}
+
+impl SbrChannel {
+    fn new() -> Self {
+        SbrChannel {
+            data: ChannelData::new(),
+            hf: HfState::new(),
+            analysis: QmfAnalysis::new(),
+            synthesis: QmfSynthesis::new(),
+            w_hist: [[Default::default(); 32]; T_HF_GEN],
+            y_hist: [[Default::default(); 64]; MAX_ENV_SLOTS - NUM_QMF_SLOTS],
+            y_hist_len: 0,
+        }
+    }
+}
+
+/// The SBR state of a single channel or channel pair element.
+#[derive(Clone)]
+pub struct Sbr {
+    /// The output sample rate.
+    rate: u32,
+    header: Option<SbrHeader>,
+    tables: Option<FreqTables>,
+    channels: Vec<SbrChannel>,
+    /// If true, valid SBR data was read for the current frame.
+    has_data: bool,
+    /// The first subband of the SBR range of the previous frame.
+    kx_prev: usize,
+    /// The number of subbands in the SBR range of the previous frame.
+    m_prev: usize,
+    /// The PS state, if PS is enabled for the element.
+    ps: Option<Box<Ps>>,
+}
+
+impl Sbr {
+    /// Instantiate the SBR state for an element with `n_channels` channels, and an output sample
+    /// rate of `rate`.
+    pub fn new(n_channels: usize, rate: u32) -> Self {
+        Sbr {
+            rate,
+            header: None,
+            tables: None,
+            channels: vec![SbrChannel::new(); n_channels],
+            has_data: false,
+            kx_prev: 32,
+            m_prev: 0,
+            ps: None,
+        }
+    }
+
+    /// Enables PS. PS is only supported for single channel elements, which are then upmixed to
+    /// two channels.
+    pub fn enable_ps(&mut self) {
+        if self.channels.len() == 1 && self.ps.is_none() {
+            self.ps = Some(Box::new(Ps::new()));
+        }
+    }
+
+    /// Returns true if PS is enabled.
+    pub fn has_ps(&self) -> bool {
+        self.ps.is_some()
+    }
+
+    /// Fully reset the SBR state.
+    pub fn reset(&mut self) {
+        let ps_enabled = self.ps.is_some();
+
+        *self = Sbr::new(self.channels.len(), self.rate);
+
+        if ps_enabled {
+            self.enable_ps();
+        }
+    }
+
+    /// Starts a new frame. SBR data must be read for each frame, otherwise the high band will not
+    /// be reconstructed.
+    pub fn start_frame(&mut self) {
+        self.has_data = false;
+
+        if let Some(ps) = &mut self.ps {
+            ps.start_frame();
+        }
+    }
+
+    /// Reads an SBR extension payload. If the payload is invalid, SBR is inactive for the current
+    /// frame.
+    pub fn read_extension(&mut self, buf: &[u8]) {
+        let mut bs = BitReaderLtr::new(buf);
+
+        if let Err(err) = self.read_extension_inner(&mut bs) {
+            warn!("sbr: {}", err);
+            self.has_data = false;
+        }
+    }
+
+    fn read_extension_inner(&mut self, bs: &mut BitReaderLtr<'_>) -> Result<()> {
+        let ext_type = bs.read_bits_leq32(4)?;
+
+        if ext_type == EXT_SBR_DATA_CRC {
+            let _crc = bs.read_bits_leq32(10)?;
+        }
+
+        if bs.read_bit()? {
+            let header = SbrHeader::read(bs)?;
+
+            if self.header != Some(header) {
+                let needs_reset = match &self.header {
+                    Some(prev) => prev.needs_reset(&header),
+                    None => true,
+                };
+
+                if needs_reset {
+                    for ch in self.channels.iter_mut() {
+                        ch.hf.reset();
+                    }
+                }
+
+                // If the tables cannot be derived, SBR is inactive until the next valid header.
+                self.header = Some(header);
+                self.tables = None;
+                self.tables = Some(FreqTables::new(&header, self.rate)?);
+            }
+        }
+
+        // Without a valid header, the SBR data cannot be read.
+        let (header, tables) = match (&self.header, &self.tables) {
+            (Some(header), Some(tables)) => (header, tables),
+            _ => return Ok(()),
+        };
+
+        if self.channels.len() == 1 {
+            read_sce_data(bs, header, tables, &mut self.channels[0].data)?;
+        }
+        else {
+            let (left, right) = self.channels.split_at_mut(1);
+            read_cpe_data(bs, header, tables, &mut left[0].data, &mut right[0].data)?;
+        }
+
+        // The extended data is read last.
+        read_extended_data(bs, self.ps.as_deref_mut())?;
+
+        self.has_data = true;
+
+        Ok(())
+    }
+
+    /// Applies SBR to the core decoder output of the element. Each channel buffer must hold
+    /// `CORE_FRAME_LEN` samples from the core decoder, and will be overwritten by twice as many
+    /// samples. If PS is enabled, a second channel buffer must follow the buffer of the single
+    /// channel, and will be overwritten by the right channel.
+    pub fn apply(&mut self, dsp: &mut SbrDsp, bufs: &mut [&mut [f32]]) {
+        let active = match (&self.header, &self.tables) {
+            (Some(header), Some(tables)) if self.has_data => Some((header, tables)),
+            _ => None,
+        };
+
+        // If SBR is not active, the high band is empty and only upsampling is performed.
+        let (kx, m) = match active {
+            Some((_, tables)) => (tables.kx, tables.m),
+            None => (32, 0),
+        };
+
+        for (c, ch) in self.channels.iter_mut().enumerate() {
+            let input = &bufs[c][..CORE_FRAME_LEN];
+
+            ch.build_subbands(dsp, input, c, active, self.kx_prev, self.m_prev);
+
+            // The adjustment of the next frame depends on the adjustment of this frame. If SBR is
+            // not active, this dependency is broken.
+            if active.is_none() {
+                ch.hf.reset();
+            }
+        }
+
+        self.kx_prev = kx;
+        self.m_prev = m;
+
+        if let Some(ps) = &mut self.ps {
+            ps.apply(&dsp.x_low, &mut dsp.x, kx + m);
+        }
+
+        for (c, ch) in self.channels.iter_mut().enumerate() {
+            for (slot, out) in dsp.x[c].iter().zip(bufs[c].chunks_exact_mut(64)) {
+                dsp.qmf.synthesis(&mut ch.synthesis, slot, out);
+            }
+        }
+
+        if let Some(ps) = &mut self.ps {
+            for (slot, out) in dsp.x[1].iter().zip(bufs[1].chunks_exact_mut(64)) {
+                dsp.qmf.synthesis(&mut ps.synthesis, slot, out);
+            }
+        }
+    }
+}
+
+impl SbrChannel {
+    /// Analyses the core decoder output, generates and adjusts the high band, and assembles the
+    /// QMF subbands to synthesize into `dsp.x[c]`.
+    fn build_subbands(
+        &mut self,
+        dsp: &mut SbrDsp,
+        input: &[f32],
+        c: usize,
+        active: Option<(&SbrHeader, &FreqTables)>,
+        kx_prev: usize,
+        m_prev: usize,
+    ) {
+        let (kx, m) = match active {
+            Some((_, tables)) => (tables.kx, tables.m),
+            None => (32, 0),
+        };
+
+        // The first slots of the low band are the last slots of the previous frame.
+        for (x_low, w_hist) in dsp.x_low.iter_mut().zip(self.w_hist.iter()) {
+            *x_low = *w_hist;
+
+            for val in x_low[kx_prev..].iter_mut() {
+                *val = Default::default();
+            }
+        }
+
+        for (x_low, samples) in dsp.x_low[T_HF_GEN..].iter_mut().zip(input.chunks_exact(32)) {
+            dsp.qmf.analysis(&mut self.analysis, samples, x_low);
+        }
+
+        self.w_hist.copy_from_slice(&dsp.x_low[NUM_QMF_SLOTS..]);
+
+        if let Some((header, tables)) = active {
+            let mut alpha0 = [Complex::default(); 32];
+            let mut alpha1 = [Complex::default(); 32];
+
+            hf::inverse_filter(&dsp.x_low, tables.k0, &mut alpha0, &mut alpha1);
+
+            self.hf.generate(&dsp.x_low, &mut dsp.x_high, tables, &self.data, &alpha0, &alpha1);
+
+            for slot in dsp.y.iter_mut() {
+                *slot = [Default::default(); 64];
+            }
+
+            self.hf.adjust(&dsp.x_high, &mut dsp.y, tables, header, &self.data);
+        }
+
+        // Assemble the low and high bands.
+        for (i, x) in dsp.x[c].iter_mut().enumerate() {
+            *x = [Default::default(); 64];
+
+            let (kx, m, y) = if i < self.y_hist_len {
+                (kx_prev, m_prev, &self.y_hist[i])
+            }
+            else {
+                (kx, m, &dsp.y[i])
+            };
+
+            x[..kx].copy_from_slice(&dsp.x_low[i + T_HF_ADJ][..kx]);
+            x[kx..kx + m].copy_from_slice(&y[kx..kx + m]);
+        }
+
+        // Save the slots of the high band that extend into the next frame.
+        self.y_hist_len = match active {
+            Some(_) => (2 * self.data.t_env[self.data.num_env]).saturating_sub(NUM_QMF_SLOTS),
+            None => 0,
+        };
+
+        self.y_hist.copy_from_slice(&dsp.y[NUM_QMF_SLOTS..]);
+    }
+}
+
+/// Reads the SBR data of a single channel element.
+fn read_sce_data<B: ReadBitsLtr>(
+    bs: &mut B,
+    header: &SbrHeader,
+    tables: &FreqTables,
+    ch: &mut ChannelData,
+) -> Result<()> {
+    if bs.read_bit()? {
+        let _reserved = bs.read_bits_leq32(4)?;
+    }
+
+    ch.start_frame();
+    ch.read_grid(bs, header.amp_res)?;
+    ch.read_dtdf(bs)?;
+    ch.read_invf(bs, tables)?;
+    ch.read_envelope(bs, tables, false)?;
+    ch.read_noise(bs, tables, false)?;
+    ch.read_sinusoids(bs, tables)?;
+
+    ch.dequantize(tables);
+
+    Ok(())
+}
+
+/// Reads the SBR data of a channel pair element.
+fn read_cpe_data<B: ReadBitsLtr>(
+    bs: &mut B,
+    header: &SbrHeader,
+    tables: &FreqTables,
+    left: &mut ChannelData,
+    right: &mut ChannelData,
+) -> Result<()> {
+    if bs.read_bit()? {
+        let _reserved = bs.read_bits_leq32(8)?;
+    }
+
+    let coupling = bs.read_bit()?;
+
+    left.start_frame();
+    right.start_frame();
+
+    if coupling {
+        // The right channel shares the time/frequency grid and inverse filtering modes of the left
+        // channel, and its envelopes and noise floors are the balance between the channels.
+        left.read_grid(bs, header.amp_res)?;
+        right.copy_grid(left);
+        left.read_dtdf(bs)?;
+        right.read_dtdf(bs)?;
+        left.read_invf(bs, tables)?;
+        right.copy_invf(left);
+        left.read_envelope(bs, tables, false)?;
+        left.read_noise(bs, tables, false)?;
+        right.read_envelope(bs, tables, true)?;
+        right.read_noise(bs, tables, true)?;
+    }
+    else {
+        left.read_grid(bs, header.amp_res)?;
+        right.read_grid(bs, header.amp_res)?;
+        left.read_dtdf(bs)?;
+        right.read_dtdf(bs)?;
+        left.read_invf(bs, tables)?;
+        right.read_invf(bs, tables)?;
+        left.read_envelope(bs, tables, false)?;
+        right.read_envelope(bs, tables, false)?;
+        left.read_noise(bs, tables, false)?;
+        right.read_noise(bs, tables, false)?;
+    }
+
+    left.read_sinusoids(bs, tables)?;
+    right.read_sinusoids(bs, tables)?;
+
+    if coupling {
+        data::dequantize_coupled(left, right, tables);
+    }
+    else {
+        left.dequantize(tables);
+        right.dequantize(tables);
+    }
+
+    Ok(())
+}
+
+/// Reads the extended data of an element. PS data is read if PS is enabled, all other extensions
+/// are skipped.
+fn read_extended_data<B: ReadBitsLtr + FiniteBitStream>(
+    bs: &mut B,
+    mut ps: Option<&mut Ps>,
+) -> Result<()> {
+    if bs.read_bit()? {
+        let mut count = bs.read_bits_leq32(4)?;
+
+        if count == 15 {
+            count += bs.read_bits_leq32(8)?;
+        }
+
+        let mut bits_left = u64::from(8 * count);
+
+        while bits_left > 7 {
+            let extension_id = bs.read_bits_leq32(2)?;
+            bits_left -= 2;
+
+            match ps.as_deref_mut() {
+                Some(ps) if extension_id == EXTENSION_ID_PS => {
+                    let start = bs.bits_left();
+
+                    ps.read(bs)?;
+
+                    let len = start - bs.bits_left();
+
+                    if len > bits_left {
+                        return decode_error("sbr: invalid extended data length");
+                    }
+
+                    bits_left -= len;
+                }
+                _ => {
+                    bs.ignore_bits(bits_left as u32)?;
+                    bits_left = 0;
+                }
+            }
+        }
+
+        // Skip the fill bits.
+        bs.ignore_bits(bits_left as u32)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::codebooks::{sbr_codebook_code, F_ENV_1_5DB, F_ENV_3_0DB};
+    use super::*;
+
+    use symphonia_core::io::BitWriterLtr;
+
+    /// Writes an SBR extension payload for a single channel element with a single envelope.
+    fn write_sce_payload(header: &SbrHeader, tables: &FreqTables, env: u32, noise: u32) -> Vec<u8> {
+        let mut bw = BitWriterLtr::new();
+
+        bw.write_bits_leq32(EXT_SBR_DATA, 4);
+
+        // SBR header.
+        bw.write_bit(true);
+        bw.write_bit(header.amp_res);
+        bw.write_bits_leq32(u32::from(header.start_freq), 4);
+        bw.write_bits_leq32(u32::from(header.stop_freq), 4);
+        bw.write_bits_leq32(u32::from(header.xover_band), 3);
+        bw.write_bits_leq32(0, 2);
+        bw.write_bit(false);
+        bw.write_bit(false);
+
+        // The FIXFIX frame class with a single high frequency resolution envelope.
+        bw.write_bit(false);
+        bw.write_bits_leq32(0, 2);
+        bw.write_bits_leq32(0, 2);
+        bw.write_bit(true);
+
+        // Frequency direction delta coding.
+        bw.write_bit(false);
+        bw.write_bit(false);
+
+        // No inverse filtering.
+        for _ in 0..tables.n_noise() {
+            bw.write_bits_leq32(0, 2);
+        }
+
+        // A flat envelope with 1.5 dB resolution.
+        bw.write_bits_leq32(env, 7);
+
+        for _ in 1..tables.n_high() {
+            let (code, len) = sbr_codebook_code(F_ENV_1_5DB, 0);
+            bw.write_bits_leq32(code, len);
+        }
+
+        // A flat noise floor.
+        bw.write_bits_leq32(noise, 5);
+
+        for _ in 1..tables.n_noise() {
+            let (code, len) = sbr_codebook_code(F_ENV_3_0DB, 0);
+            bw.write_bits_leq32(code, len);
+        }
+
+        // No sinusoids, or extended data.
+        bw.write_bit(false);
+        bw.write_bit(false);
+
+        bw.realign();
+        bw.into_inner()
+    }
+
+    /// Decodes a 1 kHz sine wave, which is entirely within the low band, with a flat envelope and
+    /// noise floor. Returns the energy of the reconstructed high band.
+    fn high_band_energy(env: u32) -> f64 {
+        const RATE: u32 = 44100;
+
+        let header = SbrHeader {
+            amp_res: true,
+            start_freq: 5,
+            stop_freq: 9,
+            xover_band: 0,
+            freq_scale: 2,
+            alter_scale: true,
+            noise_bands: 2,
+            limiter_bands: 2,
+            limiter_gains: 2,
+            interpol_freq: true,
+            smoothing_mode: true,
+        };
+
+        let tables = FreqTables::new(&header, RATE).unwrap();
+        let payload = write_sce_payload(&header, &tables, env, 10);
+
+        let mut dsp = SbrDsp::new();
+        let mut sbr = Sbr::new(1, RATE);
+        let mut upsampler = Sbr::new(1, RATE);
+
+        let mut energy = 0.0;
+
+        for frame in 0..8 {
+            let mut input = vec![0f32; 2 * CORE_FRAME_LEN];
+
+            for (i, sample) in input[..CORE_FRAME_LEN].iter_mut().enumerate() {
+                let t = (frame * CORE_FRAME_LEN + i) as f32 / (RATE / 2) as f32;
+                *sample = 0.25 * (2.0 * std::f32::consts::PI * 1000.0 * t).sin();
+            }
+
+            let mut output = input.clone();
+
+            sbr.start_frame();
+            sbr.read_extension(&payload);
+            assert!(sbr.has_data);
+            sbr.apply(&mut dsp, &mut [&mut output]);
+
+            // Without SBR data, the input is only upsampled.
+            upsampler.start_frame();
+            upsampler.apply(&mut dsp, &mut [&mut input]);
+
+            assert!(output.iter().all(|sample| sample.is_finite()));
+
+            // The low bands are identical, therefore the difference is the high band.
+            if frame > 1 {
+                energy += output
+                    .iter()
+                    .zip(&input)
+                    .map(|(&a, &b)| f64::from(a - b) * f64::from(a - b))
+                    .sum::<f64>();
+            }
+        }
+
+        energy
+    }
+
+    #[test]
+    fn verify_sbr_high_band() {
+        let low = high_band_energy(50);
+        let high = high_band_energy(56);
+
+        assert!(low > 0.0);
+
+        // Increasing the envelope by 6 steps of 1.5 dB increases the energy of the high band by
+        // 9 dB.
+        let ratio = high / low;
+        assert!(ratio > 7.0 && ratio < 9.0);
+    }
+}
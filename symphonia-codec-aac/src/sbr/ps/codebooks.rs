@@ -0,0 +1,205 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Huffman codebooks for the PS inter-channel intensity difference and inter-channel coherence
+//! parameters, as defined in Table 8.B.1 through Table 8.B.6 of ISO/IEC 14496-3.
+
+use symphonia_core::io::vlc::*;
+
+use lazy_static::lazy_static;
+
+const HUFFMAN_IID_DF_COARSE_LENS: [u8; 29] = [
+    17, 17, 17, 17, 16, 15, 13, 10,  9,  7,  6,  5,  4,  3,  1,  3,
+     4,  5,  6,  6,  8, 11, 13, 14, 14, 15, 17, 18, 18,
+];
+
+const HUFFMAN_IID_DF_COARSE_CODES: [u32; 29] = [
+    0x1fffb, 0x1fffc, 0x1fffd, 0x1fffa, 0x0fffc, 0x07ffc, 0x01ffd, 0x003fe,
+    0x001fe, 0x0007e, 0x0003c, 0x0001d, 0x0000d, 0x00005, 0x00000, 0x00004,
+    0x0000c, 0x0001c, 0x0003d, 0x0003e, 0x000fe, 0x007fe, 0x01ffc, 0x03ffc,
+    0x03ffd, 0x07ffd, 0x1fffe, 0x3fffe, 0x3ffff,
+];
+
+const HUFFMAN_IID_DT_COARSE_LENS: [u8; 29] = [
+    19, 19, 19, 20, 20, 20, 17, 15, 12, 10,  8,  6,  4,  2,  1,  3,
+     5,  7,  9, 11, 13, 14, 17, 19, 20, 20, 20, 20, 20,
+];
+
+const HUFFMAN_IID_DT_COARSE_CODES: [u32; 29] = [
+    0x7fff9, 0x7fffa, 0x7fffb, 0xffff8, 0xffff9, 0xffffa, 0x1fffd, 0x07ffe,
+    0x00ffe, 0x003fe, 0x000fe, 0x0003e, 0x0000e, 0x00002, 0x00000, 0x00006,
+    0x0001e, 0x0007e, 0x001fe, 0x007fe, 0x01ffe, 0x03ffe, 0x1fffc, 0x7fff8,
+    0xffffb, 0xffffc, 0xffffd, 0xffffe, 0xfffff,
+];
+
+const HUFFMAN_IID_DF_FINE_LENS: [u8; 61] = [
+    18, 18, 18, 18, 18, 18, 18, 18, 18, 17, 18, 17, 17, 16, 16, 15,
+    14, 14, 13, 12, 12, 11, 10, 10,  8,  7,  6,  5,  4,  3,  1,  3,
+     4,  5,  6,  7,  8,  9, 10, 11, 11, 12, 13, 14, 14, 15, 16, 16,
+    17, 17, 18, 17, 18, 18, 18, 18, 18, 18, 18, 18, 18,
+];
+
+const HUFFMAN_IID_DF_FINE_CODES: [u32; 61] = [
+    0x1feb4, 0x1feb5, 0x1fd76, 0x1fd77, 0x1fd74, 0x1fd75, 0x1fe8a, 0x1fe8b,
+    0x1fe88, 0x0fe80, 0x1feb6, 0x0fe82, 0x0feb8, 0x07f42, 0x07fae, 0x03faf,
+    0x01fd1, 0x01fe9, 0x00fe9, 0x007ea, 0x007fb, 0x003fb, 0x001fb, 0x001ff,
+    0x0007c, 0x0003c, 0x0001c, 0x0000c, 0x00000, 0x00001, 0x00001, 0x00002,
+    0x00001, 0x0000d, 0x0001d, 0x0003d, 0x0007d, 0x000fc, 0x001fc, 0x003fc,
+    0x003f4, 0x007eb, 0x00fea, 0x01fea, 0x01fd6, 0x03fd0, 0x07faf, 0x07f43,
+    0x0feb9, 0x0fe83, 0x1feb7, 0x0fe81, 0x1fe89, 0x1fe8e, 0x1fe8f, 0x1fe8c,
+    0x1fe8d, 0x1feb2, 0x1feb3, 0x1feb0, 0x1feb1,
+];
+
+const HUFFMAN_IID_DT_FINE_LENS: [u8; 61] = [
+    16, 16, 16, 16, 16, 16, 16, 16, 16, 15, 15, 15, 15, 15, 15, 14,
+    14, 13, 13, 13, 12, 12, 11, 10,  9,  9,  7,  6,  5,  3,  1,  2,
+     5,  6,  7,  8,  9, 10, 11, 11, 12, 12, 13, 13, 14, 14, 15, 15,
+    15, 15, 16, 16, 16, 16, 16, 16, 16, 16, 16, 16, 16,
+];
+
+const HUFFMAN_IID_DT_FINE_CODES: [u32; 61] = [
+    0x4ed4, 0x4ed5, 0x4ece, 0x4ecf, 0x4ecc, 0x4ed6, 0x4ed8, 0x4f46,
+    0x4f60, 0x2718, 0x2719, 0x2764, 0x2765, 0x276d, 0x27b1, 0x13b7,
+    0x13d6, 0x09c7, 0x09e9, 0x09ed, 0x04ee, 0x04f7, 0x0278, 0x0139,
+    0x009a, 0x009f, 0x0020, 0x0011, 0x000a, 0x0003, 0x0001, 0x0000,
+    0x000b, 0x0012, 0x0021, 0x004c, 0x009b, 0x013a, 0x0279, 0x0270,
+    0x04ef, 0x04e2, 0x09ea, 0x09d8, 0x13d7, 0x13d0, 0x27b2, 0x27a2,
+    0x271a, 0x271b, 0x4f66, 0x4f67, 0x4f61, 0x4f47, 0x4ed9, 0x4ed7,
+    0x4ecd, 0x4ed2, 0x4ed3, 0x4ed0, 0x4ed1,
+];
+
+const HUFFMAN_ICC_DF_LENS: [u8; 15] = [
+    14, 14, 12, 10,  7,  5,  3,  1,  2,  4,  6,  8,  9, 11, 13,
+];
+
+const HUFFMAN_ICC_DF_CODES: [u32; 15] = [
+    0x3fff, 0x3ffe, 0x0ffe, 0x03fe, 0x007e, 0x001e, 0x0006, 0x0000,
+    0x0002, 0x000e, 0x003e, 0x00fe, 0x01fe, 0x07fe, 0x1ffe,
+];
+
+const HUFFMAN_ICC_DT_LENS: [u8; 15] = [
+    14, 13, 11,  9,  7,  5,  3,  1,  2,  4,  6,  8, 10, 12, 14,
+];
+
+const HUFFMAN_ICC_DT_CODES: [u32; 15] = [
+    0x3ffe, 0x1ffe, 0x07fe, 0x01fe, 0x007e, 0x001e, 0x0006, 0x0000,
+    0x0002, 0x000e, 0x003e, 0x00fe, 0x03fe, 0x0ffe, 0x3fff,
+];
+
+struct PsTable {
+    codes: &'static [u32],
+    lens: &'static [u8],
+    /// The largest absolute value of a coded parameter delta.
+    lav: i32,
+}
+
+/// Frequency-direction IID codebook, coarse quantization.
+pub const IID_DF_COARSE: usize = 0;
+/// Time-direction IID codebook, coarse quantization.
+pub const IID_DT_COARSE: usize = 1;
+/// Frequency-direction IID codebook, fine quantization.
+pub const IID_DF_FINE: usize = 2;
+/// Time-direction IID codebook, fine quantization.
+pub const IID_DT_FINE: usize = 3;
+/// Frequency-direction ICC codebook.
+pub const ICC_DF: usize = 4;
+/// Time-direction ICC codebook.
+pub const ICC_DT: usize = 5;
+
+const PS_TABLES: [PsTable; 6] = [
+    PsTable {
+        codes: &HUFFMAN_IID_DF_COARSE_CODES,
+        lens: &HUFFMAN_IID_DF_COARSE_LENS,
+        lav: 14,
+    },
+    PsTable {
+        codes: &HUFFMAN_IID_DT_COARSE_CODES,
+        lens: &HUFFMAN_IID_DT_COARSE_LENS,
+        lav: 14,
+    },
+    PsTable {
+        codes: &HUFFMAN_IID_DF_FINE_CODES,
+        lens: &HUFFMAN_IID_DF_FINE_LENS,
+        lav: 30,
+    },
+    PsTable {
+        codes: &HUFFMAN_IID_DT_FINE_CODES,
+        lens: &HUFFMAN_IID_DT_FINE_LENS,
+        lav: 30,
+    },
+    PsTable {
+        codes: &HUFFMAN_ICC_DF_CODES,
+        lens: &HUFFMAN_ICC_DF_LENS,
+        lav: 7,
+    },
+    PsTable {
+        codes: &HUFFMAN_ICC_DT_CODES,
+        lens: &HUFFMAN_ICC_DT_LENS,
+        lav: 7,
+    },
+];
+
+/// Gets the largest absolute value of a parameter delta coded with the given codebook. Decoded
+/// codebook values are offset by this amount.
+pub fn ps_codebook_lav(codebook: usize) -> i32 {
+    PS_TABLES[codebook].lav
+}
+
+/// Gets the code, and code length, of a parameter delta coded with the given codebook.
+#[cfg(test)]
+pub fn ps_codebook_code(codebook: usize, delta: i32) -> (u32, u32) {
+    let table = &PS_TABLES[codebook];
+    let idx = (delta + table.lav) as usize;
+    (table.codes[idx], u32::from(table.lens[idx]))
+}
+
+lazy_static! {
+    pub static ref PS_CODEBOOKS: [Codebook<Entry8x16>; 6] = {
+        let mut codebooks: [Codebook<Entry8x16>; 6] = Default::default();
+
+        for (codebook, table) in codebooks.iter_mut().zip(&PS_TABLES) {
+            debug_assert_eq!(table.codes.len(), table.lens.len());
+
+            let len = table.codes.len() as u8;
+
+            // Generate values for the codebook.
+            let values: Vec<u8> = (0..len).collect();
+
+            // Generate the codebook.
+            let mut builder = CodebookBuilder::new(BitOrder::Verbatim);
+            *codebook = builder.make(table.codes, table.lens, &values).unwrap();
+        }
+
+        codebooks
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PS_TABLES;
+
+    #[test]
+    fn verify_ps_tables() {
+        for table in PS_TABLES.iter() {
+            // A codebook for values in the range [-lav, lav].
+            assert_eq!(table.codes.len(), 2 * table.lav as usize + 1);
+
+            // Each codebook must be a complete prefix code.
+            let kraft: u64 = table.lens.iter().map(|&len| 1u64 << (32 - len)).sum();
+            assert_eq!(kraft, 1u64 << 32);
+
+            for (i, (&a, &a_len)) in table.codes.iter().zip(table.lens).enumerate() {
+                assert!(a >> a_len == 0);
+
+                for (&b, &b_len) in table.codes.iter().zip(table.lens).skip(i + 1) {
+                    let len = a_len.min(b_len);
+                    assert_ne!(a >> (a_len - len), b >> (b_len - len));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The hybrid analysis and synthesis filterbanks as defined in section 8.6.4.3 of ISO/IEC 14496-3.
+//!
+//! To increase the frequency resolution of the lowest QMF subbands, the first QMF subband is split
+//! into 8 sub-subbands, of which pairs of the highest 4 are merged, and the second and third QMF
+//! subbands are split into 2 sub-subbands each. The remaining QMF subbands are passed through. This
+//! yields 71 hybrid subbands for the 20 stereo parameter bands of the baseline decoder.
+
+use std::f64::consts::PI;
+
+use symphonia_core::dsp::fft::Complex;
+
+use lazy_static::lazy_static;
+
+use super::super::{NUM_QMF_SLOTS, T_HF_ADJ};
+
+/// The number of hybrid subbands.
+pub const NUM_HYBRID_BANDS: usize = 71;
+
+/// The number of QMF subbands split by the hybrid filterbank.
+const NUM_SPLIT_BANDS: usize = 3;
+
+/// The number of hybrid subbands derived from the split QMF subbands.
+const NUM_SPLIT_HYBRID_BANDS: usize = 10;
+
+/// The length of the hybrid filters.
+const FILTER_LEN: usize = 13;
+
+/// The delay of the hybrid filters in QMF slots.
+const DELAY: usize = FILTER_LEN / 2;
+
+/// The first half, and centre tap, of the prototype filter of the 8-band complex filterbank.
+const G0_Q8: [f32; 7] = [
+    0.007_460_829_5,
+    0.022_704_21,
+    0.045_468_66,
+    0.072_661_14,
+    0.098_851_09,
+    0.117_937_1,
+    0.125,
+];
+
+/// The first half, and centre tap, of the prototype filter of the 2-band real filterbank.
+const G1_Q2: [f32; 7] = [0.0, 0.018_994_875, 0.0, -0.072_931_39, 0.0, 0.305_966_3, 0.5];
+
+/// Maps each hybrid subband to the stereo parameter band it belongs to.
+pub const K_TO_I_20: [usize; NUM_HYBRID_BANDS] = [
+     1,  0,  0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13,
+    14, 14, 15, 15, 15, 16, 16, 16, 16, 17, 17, 17, 17, 17, 18, 18,
+    18, 18, 18, 18, 18, 18, 18, 18, 18, 18, 18, 19, 19, 19, 19, 19,
+    19, 19, 19, 19, 19, 19, 19, 19, 19, 19, 19, 19, 19, 19, 19, 19,
+    19, 19, 19, 19, 19, 19, 19,
+];
+
+lazy_static! {
+    /// The 8-band complex filterbank, G_q(n) = g(n) exp(-j 2pi/8 (q + 1/2) (n - 6)), where tap n
+    /// is applied to QMF slot n - 6 relative to the current slot.
+    static ref FILTERS_Q8: [[Complex; FILTER_LEN]; 8] = {
+        let mut filters = [[Complex::default(); FILTER_LEN]; 8];
+
+        for (q, filter) in filters.iter_mut().enumerate() {
+            for (n, coef) in filter.iter_mut().enumerate() {
+                let g = f64::from(G0_Q8[n.min(FILTER_LEN - 1 - n)]);
+                let theta = -2.0 * PI * (q as f64 + 0.5) * (n as f64 - DELAY as f64) / 8.0;
+
+                *coef = Complex::new((g * theta.cos()) as f32, (g * theta.sin()) as f32);
+            }
+        }
+
+        filters
+    };
+}
+
+/// The state of the hybrid analysis filterbank.
+#[derive(Clone)]
+pub struct HybridAnalysis {
+    /// The last QMF slots of the previous frame of the split QMF subbands.
+    hist: [[Complex; DELAY]; NUM_SPLIT_BANDS],
+}
+
+impl HybridAnalysis {
+    pub fn new() -> Self {
+        HybridAnalysis { hist: [[Default::default(); DELAY]; NUM_SPLIT_BANDS] }
+    }
+
+    /// Splits the QMF subbands `x` into hybrid subbands. The hybrid filters are centred on the
+    /// current slot, the slots following the frame are read from the low band QMF subbands
+    /// `x_low`.
+    pub fn analysis(
+        &mut self,
+        x_low: &[[Complex; 32]],
+        x: &[[Complex; 64]],
+        out: &mut [[Complex; NUM_QMF_SLOTS]],
+    ) {
+        let mut buf = [Complex::default(); NUM_QMF_SLOTS + 2 * DELAY];
+
+        for band in 0..NUM_SPLIT_BANDS {
+            buf[..DELAY].copy_from_slice(&self.hist[band]);
+
+            for (b, slot) in buf[DELAY..DELAY + NUM_QMF_SLOTS].iter_mut().zip(x) {
+                *b = slot[band];
+            }
+
+            let lookahead = &x_low[NUM_QMF_SLOTS + T_HF_ADJ..];
+
+            for (b, slot) in buf[DELAY + NUM_QMF_SLOTS..].iter_mut().zip(lookahead) {
+                *b = slot[band];
+            }
+
+            self.hist[band].copy_from_slice(&buf[NUM_QMF_SLOTS..NUM_QMF_SLOTS + DELAY]);
+
+            match band {
+                0 => hybrid8(&buf, &mut out[..6]),
+                1 => hybrid2(&buf, &mut out[6..8], true),
+                _ => hybrid2(&buf, &mut out[8..10], false),
+            }
+        }
+
+        for (k, band) in out[NUM_SPLIT_HYBRID_BANDS..].iter_mut().enumerate() {
+            for (b, slot) in band.iter_mut().zip(x) {
+                *b = slot[k + NUM_SPLIT_BANDS];
+            }
+        }
+    }
+}
+
+/// Merges the hybrid subbands back into QMF subbands.
+pub fn synthesis(input: &[[Complex; NUM_QMF_SLOTS]], x: &mut [[Complex; 64]]) {
+    for (i, slot) in x.iter_mut().enumerate() {
+        slot[0] = input[..6].iter().fold(Complex::default(), |sum, band| sum + band[i]);
+        slot[1] = input[6][i] + input[7][i];
+        slot[2] = input[8][i] + input[9][i];
+
+        for (s, band) in slot[NUM_SPLIT_BANDS..].iter_mut().zip(&input[NUM_SPLIT_HYBRID_BANDS..]) {
+            *s = band[i];
+        }
+    }
+}
+
+/// Splits a QMF subband into 8 sub-subbands with a complex filterbank, and merges them into 6
+/// hybrid subbands. The sub-subbands 2 and 5, and 3 and 4, are merged.
+fn hybrid8(buf: &[Complex], out: &mut [[Complex; NUM_QMF_SLOTS]]) {
+    let filters = &*FILTERS_Q8;
+
+    for (i, taps) in buf.windows(FILTER_LEN).enumerate() {
+        let mut sub = [Complex::default(); 8];
+
+        for (s, filter) in sub.iter_mut().zip(filters.iter()) {
+            *s = taps.iter().zip(filter.iter()).fold(Complex::default(), |sum, (&x, &h)| {
+                sum + x * h
+            });
+        }
+
+        out[0][i] = sub[6];
+        out[1][i] = sub[7];
+        out[2][i] = sub[0];
+        out[3][i] = sub[1];
+        out[4][i] = sub[2] + sub[5];
+        out[5][i] = sub[3] + sub[4];
+    }
+}
+
+/// Splits a QMF subband into 2 hybrid subbands with a real filterbank. The spectrum of odd QMF
+/// subbands is reversed, therefore the order of the hybrid subbands must be reversed as well.
+fn hybrid2(buf: &[Complex], out: &mut [[Complex; NUM_QMF_SLOTS]], reverse: bool) {
+    let (lo, hi) = if reverse { (1, 0) } else { (0, 1) };
+
+    for (i, taps) in buf.windows(FILTER_LEN).enumerate() {
+        let even = taps[DELAY].scale(G1_Q2[DELAY]);
+
+        let mut odd = Complex::default();
+
+        for n in (1..DELAY).step_by(2) {
+            odd = odd + (taps[n] + taps[FILTER_LEN - 1 - n]).scale(G1_Q2[n]);
+        }
+
+        out[lo][i] = even + odd;
+        out[hi][i] = even - odd;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_hybrid_reconstruction() {
+        // The sum of the hybrid filters is a unit impulse at the centre tap. Therefore, the hybrid
+        // synthesis of the hybrid analysis of a signal is the signal itself.
+        let mut lcg = 1u32;
+        let mut rand = move || {
+            lcg = lcg.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (lcg >> 16) as f32 / 32768.0 - 1.0
+        };
+
+        let mut analysis = HybridAnalysis::new();
+        let mut hybrid = vec![[Complex::default(); NUM_QMF_SLOTS]; NUM_HYBRID_BANDS];
+
+        let mut x_low = vec![[Complex::default(); 32]; 40];
+        let mut x = vec![[Complex::default(); 64]; NUM_QMF_SLOTS];
+        let mut y = x.clone();
+
+        for frame in 0..4 {
+            // The low band of the next frame is the look-ahead of the current frame.
+            let next: Vec<[Complex; 64]> = (0..NUM_QMF_SLOTS)
+                .map(|_| {
+                    let mut slot = [Complex::default(); 64];
+                    for s in slot.iter_mut() {
+                        *s = Complex::new(rand(), rand());
+                    }
+                    slot
+                })
+                .collect();
+
+            for (low, slot) in x_low[NUM_QMF_SLOTS + T_HF_ADJ..].iter_mut().zip(&next) {
+                low.copy_from_slice(&slot[..32]);
+            }
+
+            analysis.analysis(&x_low, &x, &mut hybrid);
+            synthesis(&hybrid, &mut y);
+
+            if frame > 0 {
+                for (a, b) in x.iter().zip(&y) {
+                    for (a, b) in a.iter().zip(b.iter()) {
+                        assert!((*a - *b).norm() < 1e-5);
+                    }
+                }
+            }
+
+            x.copy_from_slice(&next);
+        }
+    }
+}
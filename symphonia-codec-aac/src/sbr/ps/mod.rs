@@ -0,0 +1,547 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `ps` module implements the Parametric Stereo (PS) tool of HE-AAC v2 as defined in section
+//! 8.6.4 of ISO/IEC 14496-3.
+//!
+//! PS reconstructs a stereo signal from a mono downmix, and a set of stereo parameters carried in
+//! the extended data of the SBR payload: the inter-channel intensity differences (IID), and the
+//! inter-channel coherences (ICC). The QMF subbands of the downmix are split into hybrid subbands
+//! to increase the frequency resolution of the lowest subbands. A decorrelated signal is derived
+//! from the downmix by a bank of all-pass filters and delays, and the left and right channels are
+//! then mixed from the downmix and the decorrelated signal according to the stereo parameters.
+//!
+//! This is a baseline PS decoder as defined in section 8.A. Stereo parameters with 34 bands are
+//! mapped to 20 bands, and the inter-channel and overall phase differences (IPD and OPD) are not
+//! used.
+
+use std::ops::RangeInclusive;
+
+use symphonia_core::dsp::fft::Complex;
+use symphonia_core::errors::{decode_error, Result};
+use symphonia_core::io::ReadBitsLtr;
+
+mod codebooks;
+mod hybrid;
+mod stereo;
+
+use codebooks::*;
+use hybrid::{HybridAnalysis, K_TO_I_20, NUM_HYBRID_BANDS};
+use stereo::Decorrelator;
+
+use super::qmf::QmfSynthesis;
+use super::NUM_QMF_SLOTS;
+
+/// The extension ID of PS data in the extended data of an SBR payload.
+pub const EXTENSION_ID_PS: u32 = 2;
+
+/// The maximum number of envelopes per frame, including an implied envelope.
+const MAX_ENVELOPES: usize = 5;
+
+/// The maximum number of stereo parameters per envelope.
+const MAX_PARAMS: usize = 34;
+
+/// The number of stereo parameter bands of the baseline decoder.
+const NUM_PAR_BANDS: usize = 20;
+
+/// The number of stereo parameters for each IID and ICC mode.
+const NUM_PARAMS: [usize; 3] = [10, 20, 34];
+
+/// The number of envelopes for each frame class.
+const NUM_ENVELOPES: [[usize; 4]; 2] = [[0, 1, 2, 4], [1, 2, 3, 4]];
+
+type Params = [[i8; MAX_PARAMS]; MAX_ENVELOPES];
+
+/// The PS data of a frame.
+#[derive(Clone)]
+struct PsData {
+    /// If true, IIDs are transmitted.
+    enable_iid: bool,
+    /// If true, the IIDs are finely quantized.
+    iid_fine: bool,
+    /// The number of IIDs per envelope.
+    num_iid: usize,
+    /// If true, ICCs are transmitted.
+    enable_icc: bool,
+    /// If true, mixing procedure R_b is used instead of mixing procedure R_a.
+    mixing_b: bool,
+    /// The number of ICCs per envelope.
+    num_icc: usize,
+    /// If true, the PS extension is present.
+    enable_ext: bool,
+    /// The number of envelopes in the frame.
+    num_env: usize,
+    /// The first QMF slot of each envelope, and the end of the last envelope.
+    borders: [usize; MAX_ENVELOPES + 1],
+    /// The quantized IIDs of each envelope.
+    iid: Params,
+    /// The quantized ICCs of each envelope.
+    icc: Params,
+}
+
+impl PsData {
+    fn new() -> Self {
+        PsData {
+            enable_iid: false,
+            iid_fine: false,
+            num_iid: NUM_PARAMS[0],
+            enable_icc: false,
+            mixing_b: false,
+            num_icc: NUM_PARAMS[0],
+            enable_ext: false,
+            num_env: 0,
+            borders: [0; MAX_ENVELOPES + 1],
+            iid: [[0; MAX_PARAMS]; MAX_ENVELOPES],
+            icc: [[0; MAX_PARAMS]; MAX_ENVELOPES],
+        }
+    }
+
+    fn read<B: ReadBitsLtr>(&mut self, bs: &mut B) -> Result<()> {
+        // If the header is not present, the header of the previous frame is used.
+        if bs.read_bit()? {
+            self.enable_iid = bs.read_bit()?;
+
+            if self.enable_iid {
+                let iid_mode = bs.read_bits_leq32(3)? as usize;
+
+                if iid_mode > 5 {
+                    return decode_error("ps: invalid iid mode");
+                }
+
+                self.iid_fine = iid_mode > 2;
+                self.num_iid = NUM_PARAMS[iid_mode % 3];
+            }
+
+            self.enable_icc = bs.read_bit()?;
+
+            if self.enable_icc {
+                let icc_mode = bs.read_bits_leq32(3)? as usize;
+
+                if icc_mode > 5 {
+                    return decode_error("ps: invalid icc mode");
+                }
+
+                self.mixing_b = icc_mode > 2;
+                self.num_icc = NUM_PARAMS[icc_mode % 3];
+            }
+
+            self.enable_ext = bs.read_bit()?;
+        }
+
+        let frame_class = bs.read_bit()?;
+        let num_env = NUM_ENVELOPES[usize::from(frame_class)][bs.read_bits_leq32(2)? as usize];
+
+        // The number of envelopes, including the implied envelope, of the previous frame.
+        let num_env_prev = self.num_env;
+
+        self.borders[0] = 0;
+
+        if frame_class {
+            // Variable borders.
+            for e in 1..=num_env {
+                self.borders[e] = bs.read_bits_leq32(5)? as usize + 1;
+
+                if self.borders[e] < self.borders[e - 1] {
+                    return decode_error("ps: invalid envelope border");
+                }
+            }
+        }
+        else {
+            // Borders evenly dividing the frame.
+            for e in 1..=num_env {
+                self.borders[e] = e * NUM_QMF_SLOTS / num_env;
+            }
+        }
+
+        // Time direction delta coding is relative to the previous envelope. The previous envelope
+        // of the first envelope is the last envelope of the previous frame.
+        let prev_env = |e: usize| if e > 0 { e - 1 } else { num_env_prev.saturating_sub(1) };
+
+        if self.enable_iid {
+            let (cb_df, cb_dt, max) = if self.iid_fine {
+                (IID_DF_FINE, IID_DT_FINE, 15)
+            }
+            else {
+                (IID_DF_COARSE, IID_DT_COARSE, 7)
+            };
+
+            for e in 0..num_env {
+                let dt = bs.read_bit()?;
+                let (cb, prev) = if dt { (cb_dt, Some(prev_env(e))) } else { (cb_df, None) };
+                read_params(bs, &mut self.iid, e, prev, cb, self.num_iid, -max..=max)?;
+            }
+        }
+        else {
+            self.iid = [[0; MAX_PARAMS]; MAX_ENVELOPES];
+        }
+
+        if self.enable_icc {
+            for e in 0..num_env {
+                let dt = bs.read_bit()?;
+                let (cb, prev) = if dt { (ICC_DT, Some(prev_env(e))) } else { (ICC_DF, None) };
+                read_params(bs, &mut self.icc, e, prev, cb, self.num_icc, 0..=7)?;
+            }
+        }
+        else {
+            self.icc = [[0; MAX_PARAMS]; MAX_ENVELOPES];
+        }
+
+        if self.enable_ext {
+            let mut count = bs.read_bits_leq32(4)?;
+
+            if count == 15 {
+                count += bs.read_bits_leq32(8)?;
+            }
+
+            // The extension only carries the IPDs and OPDs, which the baseline decoder ignores.
+            bs.ignore_bits(8 * count)?;
+        }
+
+        // The last envelope must end at the end of the frame. If it does not, an envelope is
+        // implied that repeats the stereo parameters of the last envelope, or the last envelope of
+        // the previous frame if there are no envelopes.
+        let num_env = if num_env == 0 || self.borders[num_env] < NUM_QMF_SLOTS {
+            let source = if num_env > 0 { Some(num_env - 1) } else { num_env_prev.checked_sub(1) };
+
+            if let Some(source) = source.filter(|&source| source != num_env) {
+                self.iid[num_env] = self.iid[source];
+                self.icc[num_env] = self.icc[source];
+            }
+
+            self.borders[num_env + 1] = NUM_QMF_SLOTS;
+
+            num_env + 1
+        }
+        else {
+            num_env
+        };
+
+        self.num_env = num_env;
+
+        Ok(())
+    }
+
+    /// Computes the mixing coefficients of each parameter band for each envelope.
+    fn mixing_coefs(&self, h: &mut [[[f32; 4]; NUM_PAR_BANDS]]) {
+        for (e, h) in h[..self.num_env].iter_mut().enumerate() {
+            let iid = map_params(&self.iid[e], self.num_iid);
+            let icc = map_params(&self.icc[e], self.num_icc);
+
+            for (h, (&iid, &icc)) in h.iter_mut().zip(iid.iter().zip(&icc)) {
+                *h = stereo::mixing_coefs(iid, icc, self.iid_fine, self.mixing_b);
+            }
+        }
+    }
+}
+
+/// Reads the stereo parameters of envelope `e`. Parameters are delta coded either in frequency
+/// direction, or in time direction relative to the envelope `prev`.
+fn read_params<B: ReadBitsLtr>(
+    bs: &mut B,
+    params: &mut [[i8; MAX_PARAMS]],
+    e: usize,
+    prev: Option<usize>,
+    codebook: usize,
+    num: usize,
+    range: RangeInclusive<i32>,
+) -> Result<()> {
+    let prev = prev.map(|prev| params[prev]);
+
+    let mut val = 0;
+
+    for (b, param) in params[e][..num].iter_mut().enumerate() {
+        let delta = i32::from(bs.read_codebook(&PS_CODEBOOKS[codebook])?.0);
+        let delta = delta - ps_codebook_lav(codebook);
+
+        val = match &prev {
+            Some(prev) => i32::from(prev[b]) + delta,
+            None => val + delta,
+        };
+
+        if !range.contains(&val) {
+            return decode_error("ps: stereo parameter out of range");
+        }
+
+        *param = val as i8;
+    }
+
+    Ok(())
+}
+
+/// Maps 10, 20, or 34 stereo parameters to the 20 parameter bands of the baseline decoder.
+fn map_params(params: &[i8; MAX_PARAMS], num: usize) -> [i8; NUM_PAR_BANDS] {
+    let mut mapped = [0; NUM_PAR_BANDS];
+
+    match num {
+        10 => {
+            for (mapped, &param) in mapped.chunks_exact_mut(2).zip(params) {
+                mapped[0] = param;
+                mapped[1] = param;
+            }
+        }
+        20 => mapped.copy_from_slice(&params[..NUM_PAR_BANDS]),
+        _ => {
+            let mut p = [0i32; MAX_PARAMS];
+
+            for (p, &param) in p.iter_mut().zip(params) {
+                *p = i32::from(param);
+            }
+
+            let vals = [
+                (2 * p[0] + p[1]) / 3,
+                (p[1] + 2 * p[2]) / 3,
+                (2 * p[3] + p[4]) / 3,
+                (p[4] + 2 * p[5]) / 3,
+                (p[6] + p[7]) / 2,
+                (p[8] + p[9]) / 2,
+                p[10],
+                p[11],
+                (p[12] + p[13]) / 2,
+                (p[14] + p[15]) / 2,
+                p[16],
+                p[17],
+                p[18],
+                p[19],
+                (p[20] + p[21]) / 2,
+                (p[22] + p[23]) / 2,
+                (p[24] + p[25]) / 2,
+                (p[26] + p[27]) / 2,
+                (p[28] + p[29] + p[30] + p[31]) / 4,
+                (p[32] + p[33]) / 2,
+            ];
+
+            for (mapped, &val) in mapped.iter_mut().zip(&vals) {
+                *mapped = val as i8;
+            }
+        }
+    }
+
+    mapped
+}
+
+/// The PS state of a single channel element.
+#[derive(Clone)]
+pub struct Ps {
+    data: PsData,
+    /// If true, valid PS data was read for the current frame.
+    has_data: bool,
+    hybrid: HybridAnalysis,
+    decorrelator: Decorrelator,
+    /// The mixing coefficients of each parameter band at the end of the previous frame.
+    h_prev: [[f32; 4]; NUM_PAR_BANDS],
+    /// The hybrid subbands of the left channel.
+    l: Vec<[Complex; NUM_QMF_SLOTS]>,
+    /// The hybrid subbands of the right channel.
+    r: Vec<[Complex; NUM_QMF_SLOTS]>,
+    /// The QMF synthesis filterbank of the right channel.
+    pub synthesis: QmfSynthesis,
+}
+
+impl Ps {
+    pub fn new() -> Self {
+        Ps {
+            data: PsData::new(),
+            has_data: false,
+            hybrid: HybridAnalysis::new(),
+            decorrelator: Decorrelator::new(),
+            // Until stereo parameters are read, the downmix is output on both channels.
+            h_prev: [stereo::mixing_coefs(0, 0, false, false); NUM_PAR_BANDS],
+            l: vec![[Default::default(); NUM_QMF_SLOTS]; NUM_HYBRID_BANDS],
+            r: vec![[Default::default(); NUM_QMF_SLOTS]; NUM_HYBRID_BANDS],
+            synthesis: QmfSynthesis::new(),
+        }
+    }
+
+    /// Starts a new frame.
+    pub fn start_frame(&mut self) {
+        self.has_data = false;
+    }
+
+    /// Reads the PS data of a frame. If the data is invalid, the stereo parameters are reset.
+    pub fn read<B: ReadBitsLtr>(&mut self, bs: &mut B) -> Result<()> {
+        match self.data.read(bs) {
+            Ok(()) => {
+                self.has_data = true;
+                Ok(())
+            }
+            Err(err) => {
+                self.data.num_env = 0;
+                self.data.iid = [[0; MAX_PARAMS]; MAX_ENVELOPES];
+                self.data.icc = [[0; MAX_PARAMS]; MAX_ENVELOPES];
+                Err(err)
+            }
+        }
+    }
+
+    /// Upmixes the QMF subbands of the downmix in `x[0]` into the QMF subbands of the left and
+    /// right channels in `x[0]` and `x[1]`. The QMF subbands from `top` onwards are empty. The
+    /// slots following the frame are read from the low band QMF subbands `x_low`.
+    pub fn apply(&mut self, x_low: &[[Complex; 32]], x: &mut [Vec<[Complex; 64]>; 2], top: usize) {
+        let (x_left, x_right) = x.split_at_mut(1);
+        let (x_left, x_right) = (&mut x_left[0], &mut x_right[0]);
+
+        self.hybrid.analysis(x_low, x_left, &mut self.l);
+
+        // The hybrid subbands of the QMF subbands from 3 onwards are offset by 7.
+        self.decorrelator.process(&self.l, &mut self.r, top.max(3) + 7);
+
+        // The mixing coefficients of each envelope. If there is no PS data for the frame, the
+        // mixing coefficients of the previous frame are held.
+        let mut h = [[[0f32; 4]; NUM_PAR_BANDS]; MAX_ENVELOPES];
+
+        let (num_env, borders) = if self.has_data {
+            self.data.mixing_coefs(&mut h);
+            (self.data.num_env, self.data.borders)
+        }
+        else {
+            h[0] = self.h_prev;
+            (1, [0, NUM_QMF_SLOTS, 0, 0, 0, 0])
+        };
+
+        for e in 0..num_env {
+            let (start, end) = (borders[e], borders[e + 1]);
+
+            // The mixing coefficients are interpolated linearly from the previous envelope.
+            let width = 1.0 / (end - start).max(1) as f32;
+
+            let h_start = if e > 0 { h[e - 1] } else { self.h_prev };
+
+            for (k, (l, r)) in self.l.iter_mut().zip(self.r.iter_mut()).enumerate() {
+                let b = K_TO_I_20[k];
+
+                let mut coefs = h_start[b];
+                let mut steps = [0f32; 4];
+
+                for ((step, &end), &start) in steps.iter_mut().zip(&h[e][b]).zip(&h_start[b]) {
+                    *step = (end - start) * width;
+                }
+
+                for (l, r) in l[start..end].iter_mut().zip(&mut r[start..end]) {
+                    for (coef, &step) in coefs.iter_mut().zip(&steps) {
+                        *coef += step;
+                    }
+
+                    let (ls, rs) = (*l, *r);
+
+                    *l = ls.scale(coefs[0]) + rs.scale(coefs[2]);
+                    *r = ls.scale(coefs[1]) + rs.scale(coefs[3]);
+                }
+            }
+        }
+
+        self.h_prev = h[num_env - 1];
+
+        hybrid::synthesis(&self.l, x_left);
+        hybrid::synthesis(&self.r, x_right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::codebooks::ps_codebook_code;
+    use super::*;
+
+    use symphonia_core::io::{BitReaderLtr, BitWriterLtr};
+
+    /// Writes PS data with a single envelope, and the same IID and ICC index for all parameter
+    /// bands.
+    fn write_ps_data(iid: i32, icc: i32) -> Vec<u8> {
+        let mut bw = BitWriterLtr::new();
+
+        // The header with 10 coarsely quantized IIDs, 10 ICCs, and mixing procedure R_a.
+        bw.write_bit(true);
+        bw.write_bit(true);
+        bw.write_bits_leq32(0, 3);
+        bw.write_bit(true);
+        bw.write_bits_leq32(0, 3);
+        bw.write_bit(false);
+
+        // A single envelope with a fixed border.
+        bw.write_bit(false);
+        bw.write_bits_leq32(1, 2);
+
+        // Frequency direction delta coded IIDs and ICCs.
+        for (codebook, val) in [(IID_DF_COARSE, iid), (ICC_DF, icc)] {
+            bw.write_bit(false);
+
+            for b in 0..10 {
+                let (code, len) = ps_codebook_code(codebook, if b == 0 { val } else { 0 });
+                bw.write_bits_leq32(code, len);
+            }
+        }
+
+        bw.realign();
+        bw.into_inner()
+    }
+
+    /// Upmixes noise with the given stereo parameters. Returns the energy of the left and right
+    /// channels, and their correlation coefficient.
+    fn upmix(iid: i32, icc: i32) -> (f64, f64, f64) {
+        let data = write_ps_data(iid, icc);
+
+        let mut lcg = 1u32;
+        let mut rand = move || {
+            lcg = lcg.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (lcg >> 16) as f32 / 32768.0 - 1.0
+        };
+
+        let mut ps = Ps::new();
+
+        let mut x_low = vec![[Complex::default(); 32]; NUM_QMF_SLOTS + 8];
+        let mut x = [
+            vec![[Complex::default(); 64]; NUM_QMF_SLOTS],
+            vec![[Complex::default(); 64]; NUM_QMF_SLOTS],
+        ];
+
+        let (mut left, mut right, mut cross) = (0.0, 0.0, 0.0);
+
+        for frame in 0..16 {
+            ps.start_frame();
+            ps.read(&mut BitReaderLtr::new(&data)).unwrap();
+
+            for slot in x[0].iter_mut() {
+                for s in slot[..32].iter_mut() {
+                    *s = Complex::new(rand(), rand());
+                }
+            }
+
+            for slot in x_low.iter_mut() {
+                for s in slot.iter_mut() {
+                    *s = Complex::new(rand(), rand());
+                }
+            }
+
+            ps.apply(&x_low, &mut x, 32);
+
+            // Skip the interpolation from the initial mixing coefficients.
+            if frame > 1 {
+                for (l, r) in x[0].iter().flatten().zip(x[1].iter().flatten()) {
+                    left += f64::from(l.norm_sqr());
+                    right += f64::from(r.norm_sqr());
+                    cross += f64::from((*l * r.conj()).re);
+                }
+            }
+        }
+
+        (left, right, cross / (left * right).sqrt())
+    }
+
+    #[test]
+    fn verify_ps_upmix() {
+        // Coherent channels with an intensity difference of 10 dB.
+        let (left, right, corr) = upmix(4, 0);
+
+        assert!((left / right - 10.0).abs() < 1e-3);
+        assert!(corr > 0.999);
+
+        // Incoherent channels with no intensity difference.
+        let (left, right, corr) = upmix(0, 5);
+
+        assert!((left / right - 1.0).abs() < 0.1);
+        assert!(corr.abs() < 0.1);
+    }
+}
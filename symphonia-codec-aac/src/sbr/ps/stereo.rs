@@ -0,0 +1,315 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The decorrelation and stereo mixing of PS as defined in section 8.6.4.5 and section 8.6.4.6 of
+//! ISO/IEC 14496-3.
+
+use std::f64::consts::PI;
+
+use symphonia_core::dsp::fft::Complex;
+
+use lazy_static::lazy_static;
+
+use super::super::NUM_QMF_SLOTS;
+use super::hybrid::{K_TO_I_20, NUM_HYBRID_BANDS};
+use super::NUM_PAR_BANDS;
+
+/// The number of hybrid subbands decorrelated by all-pass filters. The following subbands are
+/// decorrelated by delays.
+const NUM_ALLPASS_BANDS: usize = 30;
+
+/// The first hybrid subband decorrelated by a short delay.
+const SHORT_DELAY_BAND: usize = 42;
+
+/// The delay of the hybrid subbands decorrelated by a long delay.
+const LONG_DELAY: usize = 14;
+
+/// The number of all-pass filter links.
+const NUM_LINKS: usize = 3;
+
+/// The maximum delay of an all-pass filter link.
+const MAX_LINK_DELAY: usize = 5;
+
+/// The filter coefficients of the all-pass filter links.
+const LINK_COEFS: [f32; NUM_LINKS] = [0.651_439_1, 0.564_718_1, 0.489_541_66];
+
+/// The fractional delays of the all-pass filter links.
+const LINK_FRACT_DELAYS: [f64; NUM_LINKS] = [0.43, 0.75, 0.347];
+
+/// The fractional delay of the all-pass filters.
+const FRACT_DELAY: f64 = 0.39;
+
+/// The first hybrid subband of which the all-pass filter decays.
+const DECAY_CUTOFF: usize = 10;
+
+/// The slope of the all-pass filter decay.
+const DECAY_SLOPE: f32 = 0.05;
+
+/// The centre frequencies of the hybrid subbands derived from the split QMF subbands, in eighths of
+/// a QMF subband.
+const F_CENTER_20: [f64; 10] = [-3.0, -1.0, 1.0, 3.0, 5.0, 7.0, 10.0, 14.0, 18.0, 22.0];
+
+/// The decay factor of the peak energy of the transient detector.
+const PEAK_DECAY_FACTOR: f32 = 0.765_928_3;
+
+/// The impact of a transient on the decorrelated signal.
+const TRANSIENT_IMPACT: f32 = 1.5;
+
+/// The smoothing coefficient of the transient detector.
+const A_SMOOTH: f32 = 0.25;
+
+/// The inter-channel intensity differences in dB for the default quantization.
+const IID_DB_COARSE: [f64; 15] =
+    [-25.0, -18.0, -14.0, -10.0, -7.0, -4.0, -2.0, 0.0, 2.0, 4.0, 7.0, 10.0, 14.0, 18.0, 25.0];
+
+/// The inter-channel intensity differences in dB for the fine quantization.
+const IID_DB_FINE: [f64; 31] = [
+    -50.0, -45.0, -40.0, -35.0, -30.0, -25.0, -22.0, -19.0, -16.0, -13.0, -10.0, -8.0, -6.0, -4.0,
+    -2.0, 0.0, 2.0, 4.0, 6.0, 8.0, 10.0, 13.0, 16.0, 19.0, 22.0, 25.0, 30.0, 35.0, 40.0, 45.0, 50.0,
+];
+
+/// The inter-channel coherences.
+const ICC: [f64; 8] = [1.0, 0.937, 0.84118, 0.60092, 0.36764, 0.0, -0.589, -1.0];
+
+/// The mixing coefficients h11, h12, h21, and h22 indexed by the IID, and the ICC.
+type MixingTable = [[[f32; 4]; 8]; IID_DB_COARSE.len() + IID_DB_FINE.len()];
+
+lazy_static! {
+    /// The phase rotations of the fractional delays of the all-pass filters and filter links for
+    /// each all-pass subband.
+    static ref FRACT_ROTATIONS: [(Complex, [Complex; NUM_LINKS]); NUM_ALLPASS_BANDS] = {
+        let mut rotations: [(Complex, [Complex; NUM_LINKS]); NUM_ALLPASS_BANDS] =
+            [Default::default(); NUM_ALLPASS_BANDS];
+
+        for (k, (phi, q)) in rotations.iter_mut().enumerate() {
+            let f_center = match F_CENTER_20.get(k) {
+                Some(f) => f / 8.0,
+                None => k as f64 - 6.5,
+            };
+
+            *phi = rotation(-PI * FRACT_DELAY * f_center);
+
+            for (q, &delay) in q.iter_mut().zip(&LINK_FRACT_DELAYS) {
+                *q = rotation(-PI * delay * f_center);
+            }
+        }
+
+        rotations
+    };
+
+    /// The mixing coefficients of mixing procedure R_a.
+    static ref MIXING_RA: MixingTable = {
+        let mut table: MixingTable = [[[0.0; 4]; 8]; IID_DB_COARSE.len() + IID_DB_FINE.len()];
+
+        for (h, &iid) in table.iter_mut().zip(IID_DB_COARSE.iter().chain(&IID_DB_FINE)) {
+            let c = 10f64.powf(iid / 20.0);
+            let c1 = 2f64.sqrt() / (1.0 + c * c).sqrt();
+            let c2 = c * c1;
+
+            for (h, &icc) in h.iter_mut().zip(&ICC) {
+                let alpha = 0.5 * icc.acos();
+                let beta = alpha * (c1 - c2) / 2f64.sqrt();
+
+                h[0] = (c2 * (beta + alpha).cos()) as f32;
+                h[1] = (c1 * (beta - alpha).cos()) as f32;
+                h[2] = (c2 * (beta + alpha).sin()) as f32;
+                h[3] = (c1 * (beta - alpha).sin()) as f32;
+            }
+        }
+
+        table
+    };
+
+    /// The mixing coefficients of mixing procedure R_b.
+    static ref MIXING_RB: MixingTable = {
+        let mut table: MixingTable = [[[0.0; 4]; 8]; IID_DB_COARSE.len() + IID_DB_FINE.len()];
+
+        for (h, &iid) in table.iter_mut().zip(IID_DB_COARSE.iter().chain(&IID_DB_FINE)) {
+            let c = 10f64.powf(iid / 20.0);
+
+            for (h, &icc) in h.iter_mut().zip(&ICC) {
+                let rho = icc.max(0.05);
+
+                let mut alpha = 0.5 * (2.0 * c * rho).atan2(c * c - 1.0);
+
+                if alpha < 0.0 {
+                    alpha += 0.5 * PI;
+                }
+
+                let mu = c + 1.0 / c;
+                let mu = (1.0 + (4.0 * rho * rho - 4.0) / (mu * mu)).sqrt();
+                let gamma = ((1.0 - mu) / (1.0 + mu)).sqrt().atan();
+
+                h[0] = (2f64.sqrt() * alpha.cos() * gamma.cos()) as f32;
+                h[1] = (2f64.sqrt() * alpha.sin() * gamma.cos()) as f32;
+                h[2] = (-2f64.sqrt() * alpha.sin() * gamma.sin()) as f32;
+                h[3] = (2f64.sqrt() * alpha.cos() * gamma.sin()) as f32;
+            }
+        }
+
+        table
+    };
+}
+
+fn rotation(theta: f64) -> Complex {
+    Complex::new(theta.cos() as f32, theta.sin() as f32)
+}
+
+/// Gets the mixing coefficients h11, h12, h21, and h22 for the given IID and ICC indices.
+pub fn mixing_coefs(iid: i8, icc: i8, iid_fine: bool, procedure_b: bool) -> [f32; 4] {
+    // The coarse IIDs are in the range [-7, 7], and the fine IIDs are in the range [-15, 15].
+    let iid = if iid_fine { iid + 15 + IID_DB_COARSE.len() as i8 } else { iid + 7 };
+
+    let table = if procedure_b { &*MIXING_RB } else { &*MIXING_RA };
+
+    table[iid as usize][icc as usize]
+}
+
+/// The state of the decorrelator.
+#[derive(Clone)]
+pub struct Decorrelator {
+    /// The delay line of each hybrid subband.
+    delay: Vec<[Complex; LONG_DELAY]>,
+    /// The delay lines of the all-pass filter links of each all-pass subband.
+    link_delay: Vec<[[Complex; MAX_LINK_DELAY]; NUM_LINKS]>,
+    /// The decaying peak energy of each parameter band.
+    peak_decay_nrg: [f32; NUM_PAR_BANDS],
+    /// The smoothed energy of each parameter band.
+    power_smooth: [f32; NUM_PAR_BANDS],
+    /// The smoothed difference between the decaying peak energy and the energy of each parameter
+    /// band.
+    peak_decay_diff_smooth: [f32; NUM_PAR_BANDS],
+}
+
+impl Decorrelator {
+    pub fn new() -> Self {
+        Decorrelator {
+            delay: vec![Default::default(); NUM_HYBRID_BANDS],
+            link_delay: vec![Default::default(); NUM_ALLPASS_BANDS],
+            peak_decay_nrg: [0.0; NUM_PAR_BANDS],
+            power_smooth: [0.0; NUM_PAR_BANDS],
+            peak_decay_diff_smooth: [0.0; NUM_PAR_BANDS],
+        }
+    }
+
+    /// Derives the decorrelated signal `d` from the hybrid subbands `s`. The hybrid subbands from
+    /// `top` onwards are empty.
+    pub fn process(
+        &mut self,
+        s: &[[Complex; NUM_QMF_SLOTS]],
+        d: &mut [[Complex; NUM_QMF_SLOTS]],
+        top: usize,
+    ) {
+        // Flush the delay lines of the empty subbands such that no stale signal is output if the
+        // subbands are not empty in a later frame.
+        for delay in self.delay.iter_mut().skip(top) {
+            *delay = Default::default();
+        }
+
+        for link_delay in self.link_delay.iter_mut().skip(top) {
+            *link_delay = Default::default();
+        }
+
+        let gains = self.transient_gains(s);
+
+        let rotations = &*FRACT_ROTATIONS;
+
+        for (k, (s, d)) in s.iter().zip(d.iter_mut()).enumerate() {
+            let gains = &gains[K_TO_I_20[k]];
+
+            let mut buf = [Complex::default(); LONG_DELAY + NUM_QMF_SLOTS];
+
+            buf[..LONG_DELAY].copy_from_slice(&self.delay[k]);
+            buf[LONG_DELAY..].copy_from_slice(s);
+
+            self.delay[k].copy_from_slice(&buf[NUM_QMF_SLOTS..]);
+
+            if k < NUM_ALLPASS_BANDS {
+                let (phi, q) = &rotations[k];
+
+                let decay = 1.0 - DECAY_SLOPE * (k as f32 - DECAY_CUTOFF as f32);
+                let decay = decay.clamp(0.0, 1.0);
+
+                let mut links = [[Complex::default(); MAX_LINK_DELAY + NUM_QMF_SLOTS]; NUM_LINKS];
+
+                for (link, hist) in links.iter_mut().zip(&self.link_delay[k]) {
+                    link[..MAX_LINK_DELAY].copy_from_slice(hist);
+                }
+
+                // A delay of 2 slots, followed by a fractional delay, and a cascade of all-pass
+                // filter links with delays of 3, 4, and 5 slots.
+                let input = &buf[LONG_DELAY - 2..LONG_DELAY - 2 + NUM_QMF_SLOTS];
+
+                for (n, ((d, &x), &gain)) in d.iter_mut().zip(input).zip(gains).enumerate() {
+                    let mut x = x * *phi;
+
+                    for (m, (link, &q)) in links.iter_mut().zip(q).enumerate() {
+                        let a = LINK_COEFS[m] * decay;
+
+                        let w = link[n + 2 - m] * q - x.scale(a);
+                        link[n + MAX_LINK_DELAY] = x + w.scale(a);
+                        x = w;
+                    }
+
+                    *d = x.scale(gain);
+                }
+
+                for (hist, link) in self.link_delay[k].iter_mut().zip(&links) {
+                    hist.copy_from_slice(&link[NUM_QMF_SLOTS..]);
+                }
+            }
+            else {
+                let delay = if k < SHORT_DELAY_BAND { LONG_DELAY } else { 1 };
+
+                let input = &buf[LONG_DELAY - delay..LONG_DELAY - delay + NUM_QMF_SLOTS];
+
+                for ((d, &x), &gain) in d.iter_mut().zip(input).zip(gains) {
+                    *d = x.scale(gain);
+                }
+            }
+        }
+    }
+
+    /// Detects transients in each parameter band, and computes the gains that attenuate the
+    /// decorrelated signal during transients.
+    fn transient_gains(
+        &mut self,
+        s: &[[Complex; NUM_QMF_SLOTS]],
+    ) -> [[f32; NUM_QMF_SLOTS]; NUM_PAR_BANDS] {
+        let mut power = [[0f32; NUM_QMF_SLOTS]; NUM_PAR_BANDS];
+
+        for (s, &i) in s.iter().zip(K_TO_I_20.iter()) {
+            for (p, x) in power[i].iter_mut().zip(s) {
+                *p += x.norm_sqr();
+            }
+        }
+
+        let mut gains = [[1f32; NUM_QMF_SLOTS]; NUM_PAR_BANDS];
+
+        for (i, (gains, power)) in gains.iter_mut().zip(&power).enumerate() {
+            let peak_decay_nrg = &mut self.peak_decay_nrg[i];
+            let power_smooth = &mut self.power_smooth[i];
+            let peak_decay_diff_smooth = &mut self.peak_decay_diff_smooth[i];
+
+            for (gain, &p) in gains.iter_mut().zip(power) {
+                *peak_decay_nrg = p.max(PEAK_DECAY_FACTOR * *peak_decay_nrg);
+                *power_smooth += A_SMOOTH * (p - *power_smooth);
+                let peak_decay_diff = *peak_decay_nrg - p;
+
+                *peak_decay_diff_smooth += A_SMOOTH * (peak_decay_diff - *peak_decay_diff_smooth);
+
+                let denom = TRANSIENT_IMPACT * *peak_decay_diff_smooth;
+
+                if denom > *power_smooth {
+                    *gain = *power_smooth / denom;
+                }
+            }
+        }
+
+        gains
+    }
+}
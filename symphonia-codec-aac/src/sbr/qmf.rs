@@ -0,0 +1,246 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The complex-valued QMF analysis and synthesis filterbanks as defined in section 4.6.18.4 and
+//! section 4.6.18.8 of ISO/IEC 14496-3.
+
+use std::f64::consts::PI;
+
+use symphonia_core::dsp::fft::{Complex, Fft};
+
+use lazy_static::lazy_static;
+
+/// The length of the QMF prototype filter.
+const WINDOW_LEN: usize = 640;
+
+/// The scale of the core decoder output expected by the SBR tool. The envelope and noise floor
+/// scale factors are absolute energies relative to 16-bit PCM samples.
+const PCM_SCALE: f64 = 32768.0;
+
+/// Computes the zeroth-order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let y = 0.25 * x * x;
+
+    let mut val = 1.0;
+    for n in (1..64).rev() {
+        val *= y / f64::from(n * n);
+        val += 1.0;
+    }
+    val
+}
+
+lazy_static! {
+    /// The QMF window coefficients, c(n).
+    ///
+    /// The window is derived from a 640-tap low-pass prototype filter designed as a Kaiser windowed
+    /// sinc such that the cascade of the analysis and synthesis filterbanks is near perfectly
+    /// reconstructing with unity gain. Every block of 128 coefficients is sign alternated, which
+    /// allows the filterbanks to be computed on every other sample of the window.
+    static ref QMF_WINDOW: [f32; WINDOW_LEN] = {
+        const BETA: f64 = 6.5;
+        const CUTOFF: f64 = 1.177_122_357_312_058_2 * PI / 128.0;
+
+        let mut proto = [0f64; WINDOW_LEN];
+
+        for (n, p) in proto.iter_mut().enumerate().skip(1) {
+            let m = n as f64 - 0.5 * WINDOW_LEN as f64;
+
+            let sinc = if m == 0.0 { CUTOFF / PI } else { (CUTOFF * m).sin() / (PI * m) };
+
+            let r = 2.0 * n as f64 / WINDOW_LEN as f64 - 1.0;
+            let kaiser = bessel_i0(BETA * (1.0 - r * r).sqrt()) / bessel_i0(BETA);
+
+            *p = sinc * kaiser;
+        }
+
+        let scale = 64.0 * 2f64.sqrt() / proto.iter().sum::<f64>();
+
+        let mut window = [0f32; WINDOW_LEN];
+
+        for (n, (c, p)) in window.iter_mut().zip(proto.iter()).enumerate() {
+            let sign = if (n / 128) & 1 == 0 { 1.0 } else { -1.0 };
+            *c = (sign * scale * p) as f32;
+        }
+
+        window
+    };
+}
+
+/// The state of a 32-band QMF analysis filterbank.
+#[derive(Clone)]
+pub struct QmfAnalysis {
+    x: [f32; 320],
+}
+
+impl QmfAnalysis {
+    pub fn new() -> Self {
+        QmfAnalysis { x: [0.0; 320] }
+    }
+}
+
+/// The state of a 64-band QMF synthesis filterbank.
+#[derive(Clone)]
+pub struct QmfSynthesis {
+    v: [f32; 1280],
+}
+
+impl QmfSynthesis {
+    pub fn new() -> Self {
+        QmfSynthesis { v: [0.0; 1280] }
+    }
+}
+
+/// The transforms and scratch buffers shared by all QMF filterbanks.
+pub struct Qmf {
+    fft64: Fft,
+    fft128: Fft,
+    analysis_pre: [Complex; 64],
+    analysis_post: [Complex; 32],
+    synthesis_pre: [Complex; 64],
+    synthesis_post: [Complex; 128],
+    buf_in: [Complex; 128],
+    buf_out: [Complex; 128],
+}
+
+impl Qmf {
+    pub fn new() -> Self {
+        // Both filterbanks are computed with an inverse FFT by factoring the modulation into pre-
+        // and post-twiddles. The twiddles also scale the PCM samples to and from 16-bit range.
+        let mut analysis_pre = [Default::default(); 64];
+        let mut analysis_post = [Default::default(); 32];
+        let mut synthesis_pre = [Default::default(); 64];
+        let mut synthesis_post = [Default::default(); 128];
+
+        for (n, w) in analysis_pre.iter_mut().enumerate() {
+            *w = twiddle(PI * n as f64 / 64.0, PCM_SCALE);
+        }
+
+        for (k, w) in analysis_post.iter_mut().enumerate() {
+            *w = twiddle(-PI * (0.5 * k as f64 + 0.25) / 64.0, 2.0);
+        }
+
+        for (k, w) in synthesis_pre.iter_mut().enumerate() {
+            *w = twiddle(-PI * 255.0 * k as f64 / 128.0, 1.0);
+        }
+
+        for (n, w) in synthesis_post.iter_mut().enumerate() {
+            *w = twiddle(PI * (n as f64 - 127.5) / 128.0, 1.0 / (64.0 * PCM_SCALE));
+        }
+
+        Qmf {
+            fft64: Fft::new(64),
+            fft128: Fft::new(128),
+            analysis_pre,
+            analysis_post,
+            synthesis_pre,
+            synthesis_post,
+            buf_in: [Default::default(); 128],
+            buf_out: [Default::default(); 128],
+        }
+    }
+
+    /// Transforms a slot of 32 PCM samples into 32 complex sub-band samples.
+    pub fn analysis(&mut self, state: &mut QmfAnalysis, input: &[f32], out: &mut [Complex]) {
+        let window = &*QMF_WINDOW;
+
+        // Shift the input buffer and insert the new samples in reverse order.
+        state.x.copy_within(0..288, 32);
+
+        for (x, &sample) in state.x[..32].iter_mut().rev().zip(&input[..32]) {
+            *x = sample;
+        }
+
+        // Window and fold the input buffer.
+        for (n, (u, &w)) in self.buf_in[..64].iter_mut().zip(&self.analysis_pre).enumerate() {
+            let mut sum = 0.0;
+
+            for j in 0..5 {
+                sum += state.x[n + 64 * j] * window[2 * (n + 64 * j)];
+            }
+
+            *u = w.scale(sum);
+        }
+
+        self.fft64.ifft(&self.buf_in[..64], &mut self.buf_out[..64]);
+
+        for ((x, &a), &w) in out[..32].iter_mut().zip(&self.buf_out).zip(&self.analysis_post) {
+            *x = a * w;
+        }
+    }
+
+    /// Transforms a slot of 64 complex sub-band samples into 64 PCM samples.
+    pub fn synthesis(&mut self, state: &mut QmfSynthesis, input: &[Complex], out: &mut [f32]) {
+        let window = &*QMF_WINDOW;
+
+        for ((b, &x), &w) in self.buf_in.iter_mut().zip(&input[..64]).zip(&self.synthesis_pre) {
+            *b = x * w;
+        }
+
+        for b in self.buf_in[64..].iter_mut() {
+            *b = Default::default();
+        }
+
+        self.fft128.ifft(&self.buf_in, &mut self.buf_out);
+
+        // Shift the synthesis buffer and insert the new samples.
+        state.v.copy_within(0..1152, 128);
+
+        for ((v, &b), &w) in state.v[..128].iter_mut().zip(&self.buf_out).zip(&self.synthesis_post)
+        {
+            *v = (b * w).re;
+        }
+
+        for (n, sample) in out[..64].iter_mut().enumerate() {
+            let mut sum = 0.0;
+
+            for i in 0..5 {
+                sum += state.v[256 * i + n] * window[128 * i + n];
+                sum += state.v[256 * i + 192 + n] * window[128 * i + 64 + n];
+            }
+
+            *sample = sum;
+        }
+    }
+}
+
+fn twiddle(theta: f64, scale: f64) -> Complex {
+    Complex::new((scale * theta.cos()) as f32, (scale * theta.sin()) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_qmf_reconstruction() {
+        // The cascade of the 32-band analysis and the 64-band synthesis filterbanks upsamples the
+        // input by a factor of 2, with a constant delay.
+        const DELAY: usize = 578;
+
+        let mut qmf = Qmf::new();
+        let mut analysis = QmfAnalysis::new();
+        let mut synthesis = QmfSynthesis::new();
+
+        let freq = 0.123 * std::f32::consts::PI;
+
+        let input: Vec<f32> = (0..32 * 64).map(|n| 0.5 * (freq * n as f32).cos()).collect();
+        let mut output = vec![0f32; 2 * input.len()];
+
+        let mut subbands = [Complex::default(); 64];
+
+        for (slot_in, slot_out) in input.chunks_exact(32).zip(output.chunks_exact_mut(64)) {
+            qmf.analysis(&mut analysis, slot_in, &mut subbands);
+            qmf.synthesis(&mut synthesis, &subbands, slot_out);
+        }
+
+        // Skip the start-up transient of the filterbanks.
+        for (m, &sample) in output.iter().enumerate().skip(4 * DELAY) {
+            let expected = 0.5 * (0.5 * freq * (m as f32 - DELAY as f32)).cos();
+            assert!((sample - expected).abs() < 0.01);
+        }
+    }
+}
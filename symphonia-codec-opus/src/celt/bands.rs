@@ -0,0 +1,1206 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Shape decoding (RFC 6716 Section 4.3.4).
+
+use crate::range::{ilog, RangeDecoder, BITRES};
+
+use super::rate::{bits2pulses, get_pulses, pulse_cache, pulses2bits};
+use super::tables::*;
+
+/// No spreading.
+pub const SPREAD_NONE: i32 = 0;
+/// Aggressive spreading.
+pub const SPREAD_AGGRESSIVE: i32 = 3;
+/// The default spreading used if the decision is not coded.
+pub const SPREAD_NORMAL: i32 = 2;
+
+const QTHETA_OFFSET: i32 = 4;
+const QTHETA_OFFSET_TWOPHASE: i32 = 16;
+
+/// A linear congruential pseudo-random number generator.
+#[inline(always)]
+pub fn lcg_rand(seed: u32) -> u32 {
+    seed.wrapping_mul(1664525).wrapping_add(1013904223)
+}
+
+/// A cosine approximation that is bit-exact on every platform. Bit exactness is important
+/// because it has an impact on the bit allocation.
+fn bitexact_cos(x: i32) -> i32 {
+    let tmp = (4096 + x * x) >> 13;
+    let x2 = tmp;
+    let x2 = (32767 - x2) + frac_mul16(x2, -7651 + frac_mul16(x2, 8277 + frac_mul16(-626, x2)));
+    1 + x2
+}
+
+fn bitexact_log2tan(isin: i32, icos: i32) -> i32 {
+    let lc = ilog(icos as u32) as i32;
+    let ls = ilog(isin as u32) as i32;
+
+    let icos = icos << (15 - lc);
+    let isin = isin << (15 - ls);
+
+    (ls - lc) * (1 << 11) + frac_mul16(isin, frac_mul16(isin, -2597) + 7932)
+        - frac_mul16(icos, frac_mul16(icos, -2597) + 7932)
+}
+
+#[inline(always)]
+fn frac_mul16(a: i32, b: i32) -> i32 {
+    (16384 + i32::from(a as i16) * i32::from(b as i16)) >> 15
+}
+
+fn isqrt32(mut val: u32) -> u32 {
+    // Search for the largest binary digit b such that (g + b) * (g + b) <= val, and add it to
+    // the solution g.
+    let mut g = 0;
+    let mut bshift = (ilog(val) as i32 - 1) >> 1;
+    let mut b = 1u32 << bshift;
+
+    loop {
+        let t = ((g << 1) + b) << bshift;
+
+        if t <= val {
+            g += b;
+            val -= t;
+        }
+
+        b >>= 1;
+        bshift -= 1;
+
+        if bshift < 0 {
+            break;
+        }
+    }
+
+    g
+}
+
+fn inner_prod(x: &[f32], y: &[f32]) -> f32 {
+    x.iter().zip(y).map(|(&x, &y)| x * y).sum()
+}
+
+/// Normalizes `x` such that its L2-norm is `gain`.
+pub fn renormalise_vector(x: &mut [f32], gain: f32) {
+    let e = 1e-15 + inner_prod(x, x);
+    let g = gain / e.sqrt();
+
+    for x in x.iter_mut() {
+        *x *= g;
+    }
+}
+
+fn exp_rotation1(x: &mut [f32], len: usize, stride: usize, c: f32, s: f32) {
+    let ms = -s;
+
+    for i in 0..len - stride {
+        let x1 = x[i];
+        let x2 = x[i + stride];
+        x[i + stride] = c * x2 + s * x1;
+        x[i] = c * x1 + ms * x2;
+    }
+
+    if len > 2 * stride {
+        for i in (0..len - 2 * stride).rev() {
+            let x1 = x[i];
+            let x2 = x[i + stride];
+            x[i + stride] = c * x2 + s * x1;
+            x[i] = c * x1 + ms * x2;
+        }
+    }
+}
+
+/// Applies the inverse spreading rotation.
+fn exp_rotation(x: &mut [f32], len: usize, stride: usize, k: i32, spread: i32) {
+    const SPREAD_FACTOR: [i32; 3] = [15, 10, 5];
+
+    if 2 * k >= len as i32 || spread == SPREAD_NONE {
+        return;
+    }
+
+    let factor = SPREAD_FACTOR[spread as usize - 1];
+
+    let gain = len as f32 / (len as i32 + factor * k) as f32;
+    let theta = 0.5 * (gain * gain);
+
+    let c = (0.5 * std::f32::consts::PI * theta).cos();
+    let s = (0.5 * std::f32::consts::PI * (1.0 - theta)).cos();
+
+    let mut stride2 = 0;
+
+    if len >= 8 * stride {
+        stride2 = 1;
+        // Equivalent to computing sqrt(len / stride) with rounding.
+        while (stride2 * stride2 + stride2) * stride + (stride >> 2) < len {
+            stride2 += 1;
+        }
+    }
+
+    let len = len / stride;
+
+    for i in 0..stride {
+        let x = &mut x[i * len..];
+
+        if stride2 != 0 {
+            exp_rotation1(x, len, stride2, s, c);
+        }
+
+        exp_rotation1(x, len, 1, c, s);
+    }
+}
+
+/// Computes the next row of any recurrence that obeys the relation
+/// `u[i][j] = u[i-1][j] + u[i][j-1] + u[i-1][j-1]`.
+fn unext(u: &mut [u32], len: usize, mut ui0: u32) {
+    for j in 1..len {
+        let ui1 = u[j].wrapping_add(u[j - 1]).wrapping_add(ui0);
+        u[j - 1] = ui0;
+        ui0 = ui1;
+    }
+
+    u[len - 1] = ui0;
+}
+
+/// Computes the previous row of any recurrence that obeys the relation
+/// `u[i-1][j] = u[i][j] - u[i][j-1] - u[i-1][j-1]`.
+fn uprev(u: &mut [u32], n: usize, mut ui0: u32) {
+    for j in 1..n {
+        let ui1 = u[j].wrapping_sub(u[j - 1]).wrapping_sub(ui0);
+        u[j - 1] = ui0;
+        ui0 = ui1;
+    }
+
+    u[n - 1] = ui0;
+}
+
+/// Computes `V(n, k)`, and fills `u` with `U(n, 0..k+1)`.
+fn ncwrs_urow(n: usize, k: usize, u: &mut [u32]) -> u32 {
+    let len = k + 2;
+
+    u[0] = 0;
+    u[1] = 1;
+
+    for (i, u) in u.iter_mut().enumerate().take(len).skip(2) {
+        *u = ((i as u32) << 1) - 1;
+    }
+
+    for _ in 2..n {
+        unext(&mut u[1..], k + 1, 1);
+    }
+
+    u[k].wrapping_add(u[k + 1])
+}
+
+/// Decodes the `i`'th combination of `k` unit pulses in `n` dimensions with associated signs.
+/// Returns the squared L2-norm of the result.
+fn cwrsi(n: usize, mut k: usize, mut i: u32, y: &mut [i32], u: &mut [u32]) -> f32 {
+    let mut yy = 0.0;
+
+    for yj in y.iter_mut().take(n) {
+        let mut p = u[k + 1];
+        let s = if i >= p { -1 } else { 0 };
+        i = i.wrapping_sub(p & s as u32);
+
+        let k0 = k;
+        p = u[k];
+
+        while p > i {
+            k -= 1;
+            p = u[k];
+        }
+
+        i -= p;
+
+        let val = ((k0 - k) as i32 + s) ^ s;
+        *yj = val;
+        yy += (val * val) as f32;
+
+        uprev(u, k + 2, 0);
+    }
+
+    yy
+}
+
+fn decode_pulses(rc: &mut RangeDecoder<'_>, y: &mut [i32], n: usize, k: usize) -> f32 {
+    let mut u = [0u32; 2 + 256];
+    let v = ncwrs_urow(n, k, &mut u);
+    let i = rc.decode_uint(v);
+    cwrsi(n, k, i, y, &mut u)
+}
+
+fn extract_collapse_mask(iy: &[i32], n: usize, b: usize) -> u32 {
+    if b <= 1 {
+        return 1;
+    }
+
+    let n0 = n / b;
+
+    let mut collapse_mask = 0;
+
+    for i in 0..b {
+        if iy[i * n0..(i + 1) * n0].iter().any(|&y| y != 0) {
+            collapse_mask |= 1 << i;
+        }
+    }
+
+    collapse_mask
+}
+
+/// Decodes a pulse vector and normalizes the result.
+fn alg_unquant(
+    rc: &mut RangeDecoder<'_>,
+    x: &mut [f32],
+    n: usize,
+    k: usize,
+    spread: i32,
+    b: usize,
+    gain: f32,
+) -> u32 {
+    let mut iy = [0i32; 176];
+
+    let ryy = decode_pulses(rc, &mut iy, n, k);
+
+    // Normalize the residual.
+    let g = gain / ryy.sqrt();
+
+    for (x, &y) in x[..n].iter_mut().zip(&iy[..n]) {
+        *x = g * y as f32;
+    }
+
+    exp_rotation(x, n, b, k as i32, spread);
+
+    extract_collapse_mask(&iy, n, b)
+}
+
+/// Indexing table for converting from natural Hadamard to ordery Hadamard.
+const ORDERY_TABLE: [usize; 30] = [
+    1, 0,
+    3, 0, 2, 1,
+    7, 0, 4, 3, 6, 1, 5, 2,
+    15, 0, 8, 7, 12, 3, 11, 4, 14, 1, 9, 6, 13, 2, 10, 5,
+];
+
+fn deinterleave_hadamard(x: &mut [f32], n0: usize, stride: usize, hadamard: bool) {
+    let n = n0 * stride;
+
+    let mut tmp = [0f32; 176];
+
+    if hadamard {
+        let ordery = &ORDERY_TABLE[stride - 2..];
+
+        for i in 0..stride {
+            for j in 0..n0 {
+                tmp[ordery[i] * n0 + j] = x[j * stride + i];
+            }
+        }
+    }
+    else {
+        for i in 0..stride {
+            for j in 0..n0 {
+                tmp[i * n0 + j] = x[j * stride + i];
+            }
+        }
+    }
+
+    x[..n].copy_from_slice(&tmp[..n]);
+}
+
+fn interleave_hadamard(x: &mut [f32], n0: usize, stride: usize, hadamard: bool) {
+    let n = n0 * stride;
+
+    let mut tmp = [0f32; 176];
+
+    if hadamard {
+        let ordery = &ORDERY_TABLE[stride - 2..];
+
+        for i in 0..stride {
+            for j in 0..n0 {
+                tmp[j * stride + i] = x[ordery[i] * n0 + j];
+            }
+        }
+    }
+    else {
+        for i in 0..stride {
+            for j in 0..n0 {
+                tmp[j * stride + i] = x[i * n0 + j];
+            }
+        }
+    }
+
+    x[..n].copy_from_slice(&tmp[..n]);
+}
+
+fn haar1(x: &mut [f32], n0: usize, stride: usize) {
+    const FRAC_1_SQRT_2: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+    for i in 0..stride {
+        for j in 0..n0 >> 1 {
+            let tmp1 = FRAC_1_SQRT_2 * x[stride * 2 * j + i];
+            let tmp2 = FRAC_1_SQRT_2 * x[stride * (2 * j + 1) + i];
+            x[stride * 2 * j + i] = tmp1 + tmp2;
+            x[stride * (2 * j + 1) + i] = tmp1 - tmp2;
+        }
+    }
+}
+
+fn compute_qn(n: usize, b: i32, offset: i32, pulse_cap: i32, stereo: bool) -> i32 {
+    const EXP2_TABLE8: [i32; 8] = [16384, 17866, 19483, 21247, 23170, 25267, 27554, 30048];
+
+    let mut n2 = 2 * n as i32 - 1;
+
+    if stereo && n == 2 {
+        n2 -= 1;
+    }
+
+    // The upper limit ensures that in a stereo split with itheta==16384, there will always be
+    // enough bits left over to code at least one pulse in the side.
+    let qb = (b + n2 * offset) / n2;
+    let qb = (b - pulse_cap - (4 << BITRES)).min(qb);
+    let qb = (8 << BITRES).min(qb);
+
+    if qb < (1 << BITRES >> 1) {
+        1
+    }
+    else {
+        let qn = EXP2_TABLE8[(qb & 0x7) as usize] >> (14 - (qb >> BITRES));
+        ((qn + 1) >> 1) << 1
+    }
+}
+
+fn stereo_merge(x: &mut [f32], y: &mut [f32], mid: f32, n: usize) {
+    // Compute the norm of X+Y and X-Y as |X|^2 + |Y|^2 +/- sum(xy).
+    let xp = mid * inner_prod(&y[..n], &x[..n]);
+    let side = inner_prod(&y[..n], &y[..n]);
+
+    let el = mid * mid + side - 2.0 * xp;
+    let er = mid * mid + side + 2.0 * xp;
+
+    if er < 6e-4 || el < 6e-4 {
+        y[..n].copy_from_slice(&x[..n]);
+        return;
+    }
+
+    let lgain = 1.0 / el.sqrt();
+    let rgain = 1.0 / er.sqrt();
+
+    for (x, y) in x[..n].iter_mut().zip(&mut y[..n]) {
+        // Apply mid scaling (side is already scaled).
+        let l = mid * *x;
+        let r = *y;
+        *x = lgain * (l - r);
+        *y = rgain * (l + r);
+    }
+}
+
+/// The state shared between all the bands of a frame.
+struct BandCtx<'a, 'b> {
+    rc: &'a mut RangeDecoder<'b>,
+    band: usize,
+    intensity: usize,
+    spread: i32,
+    tf_change: i32,
+    remaining_bits: i32,
+    seed: u32,
+    disable_inv: bool,
+}
+
+/// The parameters of a split.
+struct SplitCtx {
+    inv: bool,
+    imid: i32,
+    iside: i32,
+    delta: i32,
+    itheta: i32,
+    qalloc: i32,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compute_theta(
+    ctx: &mut BandCtx<'_, '_>,
+    n: usize,
+    b: &mut i32,
+    big_b: usize,
+    b0: usize,
+    lm: i32,
+    stereo: bool,
+    fill: &mut u32,
+) -> SplitCtx {
+    let i = ctx.band;
+
+    // Decide on the resolution to give to the split parameter theta.
+    let pulse_cap = i32::from(LOG_N[i]) + lm * (1 << BITRES);
+
+    let offset = (pulse_cap >> 1)
+        - if stereo && n == 2 { QTHETA_OFFSET_TWOPHASE } else { QTHETA_OFFSET };
+
+    let mut qn = compute_qn(n, *b, offset, pulse_cap, stereo);
+
+    if stereo && i >= ctx.intensity {
+        qn = 1;
+    }
+
+    let tell = ctx.rc.tell_frac() as i32;
+
+    let mut itheta = 0;
+    let mut inv = false;
+
+    if qn != 1 {
+        // Entropy coding of the angle. A uniform pdf is used for the time split, a step for
+        // stereo, and a triangular one for the rest.
+        if stereo && n > 2 {
+            let p0 = 3;
+            let x0 = qn / 2;
+            let ft = p0 * (x0 + 1) + x0;
+
+            // Use a probability of p0 up to itheta=8192 and then use 1 after.
+            let fs = ctx.rc.decode(ft as u32) as i32;
+
+            let x = if fs < (x0 + 1) * p0 { fs / p0 } else { x0 + 1 + (fs - (x0 + 1) * p0) };
+
+            let (fl, fh) = if x <= x0 {
+                (p0 * x, p0 * (x + 1))
+            }
+            else {
+                ((x - 1 - x0) + (x0 + 1) * p0, (x - x0) + (x0 + 1) * p0)
+            };
+
+            ctx.rc.update(fl as u32, fh as u32, ft as u32);
+            itheta = x;
+        }
+        else if b0 > 1 || stereo {
+            // Uniform pdf.
+            itheta = ctx.rc.decode_uint(qn as u32 + 1) as i32;
+        }
+        else {
+            // Triangular pdf.
+            let ft = ((qn >> 1) + 1) * ((qn >> 1) + 1);
+
+            let fm = ctx.rc.decode(ft as u32) as i32;
+
+            let (fl, fs);
+
+            if fm < (((qn >> 1) * ((qn >> 1) + 1)) >> 1) {
+                itheta = (isqrt32(8 * fm as u32 + 1) as i32 - 1) >> 1;
+                fs = itheta + 1;
+                fl = (itheta * (itheta + 1)) >> 1;
+            }
+            else {
+                itheta = (2 * (qn + 1) - isqrt32(8 * (ft - fm - 1) as u32 + 1) as i32) >> 1;
+                fs = qn + 1 - itheta;
+                fl = ft - (((qn + 1 - itheta) * (qn + 2 - itheta)) >> 1);
+            }
+
+            ctx.rc.update(fl as u32, (fl + fs) as u32, ft as u32);
+        }
+
+        itheta = itheta * 16384 / qn;
+    }
+    else if stereo {
+        if *b > 2 << BITRES && ctx.remaining_bits > 2 << BITRES {
+            inv = ctx.rc.decode_bit_logp(2);
+        }
+
+        // The inversion flag is overridden to avoid problems with downmixing.
+        if ctx.disable_inv {
+            inv = false;
+        }
+    }
+
+    let qalloc = ctx.rc.tell_frac() as i32 - tell;
+    *b -= qalloc;
+
+    let (imid, iside, delta);
+
+    if itheta == 0 {
+        imid = 32767;
+        iside = 0;
+        *fill &= (1 << big_b) - 1;
+        delta = -16384;
+    }
+    else if itheta == 16384 {
+        imid = 0;
+        iside = 32767;
+        *fill &= ((1 << big_b) - 1) << big_b;
+        delta = 16384;
+    }
+    else {
+        imid = bitexact_cos(itheta);
+        iside = bitexact_cos(16384 - itheta);
+        // This is the mid vs side allocation that minimizes squared error in the band.
+        delta = frac_mul16((n as i32 - 1) << 7, bitexact_log2tan(iside, imid));
+    }
+
+    SplitCtx { inv, imid, iside, delta, itheta, qalloc }
+}
+
+fn quant_band_n1(
+    ctx: &mut BandCtx<'_, '_>,
+    x: &mut [f32],
+    y: Option<&mut [f32]>,
+    lowband_out: Option<&mut [f32]>,
+) -> u32 {
+    fn decode_sign(ctx: &mut BandCtx<'_, '_>, x: &mut [f32]) {
+        let mut sign = 0;
+
+        if ctx.remaining_bits >= 1 << BITRES {
+            sign = ctx.rc.decode_bits(1);
+            ctx.remaining_bits -= 1 << BITRES;
+        }
+
+        x[0] = if sign != 0 { -1.0 } else { 1.0 };
+    }
+
+    decode_sign(ctx, x);
+
+    if let Some(y) = y {
+        decode_sign(ctx, y);
+    }
+
+    if let Some(lowband_out) = lowband_out {
+        lowband_out[0] = x[0];
+    }
+
+    1
+}
+
+/// Decodes a mono partition. The band may be split in two recursively, and the energy
+/// difference between the two half-bands is transmitted.
+#[allow(clippy::too_many_arguments)]
+fn quant_partition(
+    ctx: &mut BandCtx<'_, '_>,
+    x: &mut [f32],
+    mut n: usize,
+    mut b: i32,
+    mut big_b: usize,
+    lowband: Option<&[f32]>,
+    mut lm: i32,
+    gain: f32,
+    mut fill: u32,
+) -> u32 {
+    let i = ctx.band;
+    let b0 = big_b;
+
+    let cache = pulse_cache(i, lm);
+
+    // If more than 1.5 bits more than can be produced are needed, split the band in two.
+    if lm != -1 && b > i32::from(cache[usize::from(cache[0])]) + 12 && n > 2 {
+        n >>= 1;
+
+        let (x, y) = x.split_at_mut(n);
+
+        lm -= 1;
+
+        if big_b == 1 {
+            fill = (fill & 1) | (fill << 1);
+        }
+
+        big_b = (big_b + 1) >> 1;
+
+        let sctx = compute_theta(ctx, n, &mut b, big_b, b0, lm, false, &mut fill);
+
+        let mid = (1.0 / 32768.0) * sctx.imid as f32;
+        let side = (1.0 / 32768.0) * sctx.iside as f32;
+
+        let itheta = sctx.itheta;
+        let mut delta = sctx.delta;
+
+        // Give more bits to low-energy MDCTs than they would otherwise deserve.
+        if b0 > 1 && (itheta & 0x3fff) != 0 {
+            if itheta > 8192 {
+                // Rough approximation for pre-echo masking.
+                delta -= delta >> (4 - lm);
+            }
+            else {
+                // Corresponds to a forward-masking slope of 1.5 dB per 10 ms.
+                delta = 0.min(delta + ((n as i32) << BITRES >> (5 - lm)));
+            }
+        }
+
+        let mut mbits = 0.max(b.min((b - delta) / 2));
+        let mut sbits = b - mbits;
+
+        ctx.remaining_bits -= sctx.qalloc;
+
+        let next_lowband2 = lowband.map(|lowband| &lowband[n..]);
+
+        let mut rebalance = ctx.remaining_bits;
+
+        let mut cm;
+
+        if mbits >= sbits {
+            cm = quant_partition(ctx, x, n, mbits, big_b, lowband, lm, gain * mid, fill);
+
+            rebalance = mbits - (rebalance - ctx.remaining_bits);
+
+            if rebalance > 3 << BITRES && itheta != 0 {
+                sbits += rebalance - (3 << BITRES);
+            }
+
+            let (side_gain, side_fill) = (gain * side, fill >> big_b);
+            cm |= quant_partition(ctx, y, n, sbits, big_b, next_lowband2, lm, side_gain, side_fill)
+                << (b0 >> 1);
+        }
+        else {
+            let (side_gain, side_fill) = (gain * side, fill >> big_b);
+            cm = quant_partition(ctx, y, n, sbits, big_b, next_lowband2, lm, side_gain, side_fill)
+                << (b0 >> 1);
+
+            rebalance = sbits - (rebalance - ctx.remaining_bits);
+
+            if rebalance > 3 << BITRES && itheta != 16384 {
+                mbits += rebalance - (3 << BITRES);
+            }
+
+            cm |= quant_partition(ctx, x, n, mbits, big_b, lowband, lm, gain * mid, fill);
+        }
+
+        cm
+    }
+    else {
+        // This is the basic no-split case.
+        let mut q = bits2pulses(i, lm, b);
+        let mut curr_bits = pulses2bits(i, lm, q);
+
+        ctx.remaining_bits -= curr_bits;
+
+        // Ensures the budget can never be busted.
+        while ctx.remaining_bits < 0 && q > 0 {
+            ctx.remaining_bits += curr_bits;
+            q -= 1;
+            curr_bits = pulses2bits(i, lm, q);
+            ctx.remaining_bits -= curr_bits;
+        }
+
+        if q != 0 {
+            let k = get_pulses(q) as usize;
+            alg_unquant(ctx.rc, x, n, k, ctx.spread, big_b, gain)
+        }
+        else {
+            // If there's no pulse, fill the band anyway.
+            let cm_mask = (1u32 << big_b) - 1;
+
+            fill &= cm_mask;
+
+            if fill == 0 {
+                for x in x[..n].iter_mut() {
+                    *x = 0.0;
+                }
+
+                0
+            }
+            else {
+                let cm = match lowband {
+                    None => {
+                        // Noise.
+                        for x in x[..n].iter_mut() {
+                            ctx.seed = lcg_rand(ctx.seed);
+                            *x = (ctx.seed as i32 >> 20) as f32;
+                        }
+
+                        cm_mask
+                    }
+                    Some(lowband) => {
+                        // Folded spectrum.
+                        for (x, &l) in x[..n].iter_mut().zip(lowband) {
+                            ctx.seed = lcg_rand(ctx.seed);
+                            // About 48 dB below the "normal" folding level.
+                            let tmp = 1.0 / 256.0;
+                            *x = if ctx.seed & 0x8000 != 0 { l + tmp } else { l - tmp };
+                        }
+
+                        fill
+                    }
+                };
+
+                renormalise_vector(&mut x[..n], gain);
+
+                cm
+            }
+        }
+    }
+}
+
+/// Decodes a band for the mono case.
+#[allow(clippy::too_many_arguments)]
+fn quant_band(
+    ctx: &mut BandCtx<'_, '_>,
+    x: &mut [f32],
+    n: usize,
+    b: i32,
+    mut big_b: usize,
+    mut lowband: Option<&mut [f32]>,
+    lm: i32,
+    lowband_out: Option<&mut [f32]>,
+    gain: f32,
+    mut fill: u32,
+) -> u32 {
+    const BIT_INTERLEAVE_TABLE: [u32; 16] = [0, 1, 1, 1, 2, 3, 3, 3, 2, 3, 3, 3, 2, 3, 3, 3];
+
+    const BIT_DEINTERLEAVE_TABLE: [u32; 16] = [
+        0x00, 0x03, 0x0c, 0x0f, 0x30, 0x33, 0x3c, 0x3f,
+        0xc0, 0xc3, 0xcc, 0xcf, 0xf0, 0xf3, 0xfc, 0xff,
+    ];
+
+    let n0 = n;
+    let b0 = big_b;
+    let long_blocks = b0 == 1;
+
+    let mut n_b = n / big_b;
+    let mut tf_change = ctx.tf_change;
+    let mut time_divide = 0;
+
+    // Special case for one sample.
+    if n == 1 {
+        return quant_band_n1(ctx, x, None, lowband_out);
+    }
+
+    let recombine = if tf_change > 0 { tf_change as usize } else { 0 };
+
+    // Band recombining to increase frequency resolution.
+    for k in 0..recombine {
+        if let Some(lowband) = lowband.as_deref_mut() {
+            haar1(lowband, n >> k, 1 << k);
+        }
+
+        fill = BIT_INTERLEAVE_TABLE[(fill & 0xf) as usize]
+            | BIT_INTERLEAVE_TABLE[(fill >> 4) as usize] << 2;
+    }
+
+    big_b >>= recombine;
+    n_b <<= recombine;
+
+    // Increasing the time resolution.
+    while n_b & 1 == 0 && tf_change < 0 {
+        if let Some(lowband) = lowband.as_deref_mut() {
+            haar1(lowband, n_b, big_b);
+        }
+
+        fill |= fill << big_b;
+        big_b <<= 1;
+        n_b >>= 1;
+        time_divide += 1;
+        tf_change += 1;
+    }
+
+    let b0_tf = big_b;
+    let n_b0 = n_b;
+
+    // Reorganize the samples in time order instead of frequency order.
+    if b0_tf > 1 {
+        if let Some(lowband) = lowband.as_deref_mut() {
+            deinterleave_hadamard(lowband, n_b >> recombine, b0_tf << recombine, long_blocks);
+        }
+    }
+
+    let mut cm = quant_partition(ctx, x, n, b, big_b, lowband.as_deref(), lm, gain, fill);
+
+    // Undo the sample reorganization going from time order to frequency order.
+    if b0_tf > 1 {
+        interleave_hadamard(x, n_b >> recombine, b0_tf << recombine, long_blocks);
+    }
+
+    // Undo time-freq changes done earlier.
+    n_b = n_b0;
+    big_b = b0_tf;
+
+    for _ in 0..time_divide {
+        big_b >>= 1;
+        n_b <<= 1;
+        cm |= cm >> big_b;
+        haar1(x, n_b, big_b);
+    }
+
+    for k in 0..recombine {
+        cm = BIT_DEINTERLEAVE_TABLE[cm as usize];
+        haar1(x, n0 >> k, 1 << k);
+    }
+
+    big_b <<= recombine;
+
+    // Scale output for later folding.
+    if let Some(lowband_out) = lowband_out {
+        let scale = (n0 as f32).sqrt();
+
+        for (out, &x) in lowband_out[..n0].iter_mut().zip(&x[..n0]) {
+            *out = scale * x;
+        }
+    }
+
+    cm & ((1 << big_b) - 1)
+}
+
+/// Decodes a band for the stereo case.
+#[allow(clippy::too_many_arguments)]
+fn quant_band_stereo(
+    ctx: &mut BandCtx<'_, '_>,
+    x: &mut [f32],
+    y: &mut [f32],
+    n: usize,
+    mut b: i32,
+    big_b: usize,
+    lowband: Option<&mut [f32]>,
+    lm: i32,
+    lowband_out: Option<&mut [f32]>,
+    mut fill: u32,
+) -> u32 {
+    // Special case for one sample.
+    if n == 1 {
+        return quant_band_n1(ctx, x, Some(y), lowband_out);
+    }
+
+    let orig_fill = fill;
+
+    let sctx = compute_theta(ctx, n, &mut b, big_b, big_b, lm, true, &mut fill);
+
+    let mid = (1.0 / 32768.0) * sctx.imid as f32;
+    let side = (1.0 / 32768.0) * sctx.iside as f32;
+
+    let itheta = sctx.itheta;
+
+    let mut cm;
+
+    if n == 2 {
+        // This is a special case for N=2 that only works for stereo and takes advantage of the
+        // fact that mid and side are orthogonal to encode the side with just one bit.
+        let mut mbits = b;
+        let mut sbits = 0;
+
+        // Only one bit is needed for the side.
+        if itheta != 0 && itheta != 16384 {
+            sbits = 1 << BITRES;
+        }
+
+        mbits -= sbits;
+
+        let c = itheta > 8192;
+
+        ctx.remaining_bits -= sctx.qalloc + sbits;
+
+        let mut sign = 0;
+
+        if sbits != 0 {
+            sign = ctx.rc.decode_bits(1) as i32;
+        }
+
+        let sign = (1 - 2 * sign) as f32;
+
+        let (x2, y2) = if c { (&mut *y, &mut *x) } else { (&mut *x, &mut *y) };
+
+        // Use orig_fill here because the side should be folded, but if itheta==16384, the low
+        // bits of fill will have been cleared.
+        cm = quant_band(ctx, x2, n, mbits, big_b, lowband, lm, lowband_out, 1.0, orig_fill);
+
+        // N=2 bands are not split, so cm is either 1 or 0 (for a fold-collapse), and there's no
+        // need to worry about mixing with the other channel.
+        y2[0] = -sign * x2[1];
+        y2[1] = sign * x2[0];
+
+        x[0] *= mid;
+        x[1] *= mid;
+        y[0] *= side;
+        y[1] *= side;
+
+        let tmp = x[0];
+        x[0] = tmp - y[0];
+        y[0] += tmp;
+
+        let tmp = x[1];
+        x[1] = tmp - y[1];
+        y[1] += tmp;
+    }
+    else {
+        // "Normal" split code.
+        let mut mbits = 0.max(b.min((b - sctx.delta) / 2));
+        let mut sbits = b - mbits;
+
+        ctx.remaining_bits -= sctx.qalloc;
+
+        let mut rebalance = ctx.remaining_bits;
+
+        if mbits >= sbits {
+            // In stereo mode, no scaling is applied to the mid because the normalized mid is
+            // needed for folding later.
+            cm = quant_band(ctx, x, n, mbits, big_b, lowband, lm, lowband_out, 1.0, fill);
+
+            rebalance = mbits - (rebalance - ctx.remaining_bits);
+
+            if rebalance > 3 << BITRES && itheta != 0 {
+                sbits += rebalance - (3 << BITRES);
+            }
+
+            // For a stereo split, the high bits of fill are always zero, so no folding will be
+            // done to the side.
+            cm |= quant_band(ctx, y, n, sbits, big_b, None, lm, None, side, fill >> big_b);
+        }
+        else {
+            // For a stereo split, the high bits of fill are always zero, so no folding will be
+            // done to the side.
+            cm = quant_band(ctx, y, n, sbits, big_b, None, lm, None, side, fill >> big_b);
+
+            rebalance = sbits - (rebalance - ctx.remaining_bits);
+
+            if rebalance > 3 << BITRES && itheta != 16384 {
+                mbits += rebalance - (3 << BITRES);
+            }
+
+            // In stereo mode, no scaling is applied to the mid because the normalized mid is
+            // needed for folding later.
+            cm |= quant_band(ctx, x, n, mbits, big_b, lowband, lm, lowband_out, 1.0, fill);
+        }
+    }
+
+    if n != 2 {
+        stereo_merge(x, y, mid, n);
+    }
+
+    if sctx.inv {
+        for y in y[..n].iter_mut() {
+            *y = -*y;
+        }
+    }
+
+    cm
+}
+
+/// Duplicates enough of the first band's folding data to be able to fold the second band. Copies
+/// no data for CELT-only mode.
+fn special_hybrid_folding(
+    norm: &mut [f32],
+    norm2: &mut [f32],
+    start: usize,
+    m: usize,
+    dual_stereo: bool,
+) {
+    let n1 = m * (EBANDS[start + 1] - EBANDS[start]);
+    let n2 = m * (EBANDS[start + 2] - EBANDS[start + 1]);
+
+    if n2 > n1 {
+        norm.copy_within(2 * n1 - n2..n1, n1);
+
+        if dual_stereo {
+            norm2.copy_within(2 * n1 - n2..n1, n1);
+        }
+    }
+}
+
+/// The parameters of `decode_all_bands`.
+pub struct BandParams<'a> {
+    pub start: usize,
+    pub end: usize,
+    pub pulses: &'a [i32],
+    pub short_blocks: bool,
+    pub spread: i32,
+    pub dual_stereo: bool,
+    pub intensity: usize,
+    pub tf_res: &'a [i32],
+    pub total_bits: i32,
+    pub balance: i32,
+    pub lm: usize,
+    pub coded_bands: usize,
+    pub disable_inv: bool,
+}
+
+/// Decodes the normalized shape of every band in `[start, end)`. `x` and `y` receive the shape
+/// of the first and second channel, respectively.
+pub fn decode_all_bands(
+    rc: &mut RangeDecoder<'_>,
+    params: &BandParams<'_>,
+    x_buf: &mut [f32],
+    mut y_buf: Option<&mut [f32]>,
+    collapse_masks: &mut [u8],
+    seed: &mut u32,
+) {
+    let start = params.start;
+    let end = params.end;
+    let lm = params.lm;
+    let m = 1 << lm;
+    let big_b = if params.short_blocks { m } else { 1 };
+    let channels = if y_buf.is_some() { 2 } else { 1 };
+
+    let norm_offset = m * EBANDS[start];
+
+    // No need to allocate norm for the last band because an output in that band is not needed.
+    let norm_len = m * EBANDS[NUM_BANDS - 1] - norm_offset;
+
+    let mut norm_buf = [0f32; 2 * 8 * 100];
+    let (norm, norm2) = norm_buf.split_at_mut(norm_len);
+    let norm2 = &mut norm2[..norm_len];
+
+    let mut lowband_scratch = [0f32; 176];
+
+    let mut dual_stereo = params.dual_stereo;
+    let mut balance = params.balance;
+
+    let mut ctx = BandCtx {
+        rc,
+        band: 0,
+        intensity: params.intensity,
+        spread: params.spread,
+        tf_change: 0,
+        remaining_bits: 0,
+        seed: *seed,
+        disable_inv: params.disable_inv,
+    };
+
+    let mut lowband_offset = 0;
+    let mut update_lowband = true;
+
+    for i in start..end {
+        ctx.band = i;
+
+        let last = i == end - 1;
+
+        let band_start = m * EBANDS[i];
+        let n = m * EBANDS[i + 1] - band_start;
+
+        let tell = ctx.rc.tell_frac() as i32;
+
+        // Compute how many bits are wanted for this band.
+        if i != start {
+            balance -= tell;
+        }
+
+        let remaining_bits = params.total_bits - tell - 1;
+
+        ctx.remaining_bits = remaining_bits;
+
+        let b = if i < params.coded_bands {
+            let curr_balance = balance / 3.min((params.coded_bands - i) as i32);
+            0.max(16383.min((remaining_bits + 1).min(params.pulses[i] + curr_balance)))
+        }
+        else {
+            0
+        };
+
+        if (band_start >= n + m * EBANDS[start] || i == start + 1)
+            && (update_lowband || lowband_offset == 0)
+        {
+            lowband_offset = i;
+        }
+
+        if i == start + 1 {
+            special_hybrid_folding(norm, norm2, start, m, dual_stereo);
+        }
+
+        let tf_change = params.tf_res[i];
+        ctx.tf_change = tf_change;
+
+        // Get a conservative estimate of the collapse masks for the bands that will be folded
+        // from.
+        let mut effective_lowband = None;
+
+        let (x_cm, y_cm) = if lowband_offset != 0
+            && (params.spread != SPREAD_AGGRESSIVE || big_b > 1 || tf_change < 0)
+        {
+            // This ensures spectral content is never repeated within one band.
+            let eff = (m * EBANDS[lowband_offset]).saturating_sub(norm_offset + n);
+
+            let mut fold_start = lowband_offset;
+
+            loop {
+                fold_start -= 1;
+                if m * EBANDS[fold_start] <= eff + norm_offset {
+                    break;
+                }
+            }
+
+            let mut fold_end = lowband_offset - 1;
+
+            loop {
+                fold_end += 1;
+                if !(fold_end < i && m * EBANDS[fold_end] < eff + norm_offset + n) {
+                    break;
+                }
+            }
+
+            let mut x_cm = 0;
+            let mut y_cm = 0;
+
+            for fold_i in fold_start..fold_end.max(fold_start + 1) {
+                x_cm |= u32::from(collapse_masks[fold_i * channels]);
+                y_cm |= u32::from(collapse_masks[fold_i * channels + channels - 1]);
+            }
+
+            effective_lowband = Some(eff);
+
+            (x_cm, y_cm)
+        }
+        else {
+            // Otherwise the LCG will be used to fold, so all blocks will (almost always) be
+            // non-zero.
+            ((1 << big_b) - 1, (1 << big_b) - 1)
+        };
+
+        if dual_stereo && i == params.intensity {
+            // Switch off dual stereo to do intensity.
+            dual_stereo = false;
+
+            for (a, &b) in norm[..band_start - norm_offset].iter_mut().zip(norm2.iter()) {
+                *a = 0.5 * (*a + b);
+            }
+        }
+
+        let x = &mut x_buf[band_start..band_start + n];
+        let out_offset = band_start - norm_offset;
+        let lm = lm as i32;
+
+        let x_cm_out;
+        let y_cm_out;
+
+        if dual_stereo {
+            let b = b / 2;
+            let y = &mut y_buf.as_deref_mut().unwrap()[band_start..band_start + n];
+
+            let lowband = effective_lowband.map(|eff| {
+                lowband_scratch[..n].copy_from_slice(&norm[eff..eff + n]);
+                &mut lowband_scratch[..n]
+            });
+
+            let lowband_out = if last { None } else { Some(&mut norm[out_offset..][..n]) };
+
+            x_cm_out = quant_band(&mut ctx, x, n, b, big_b, lowband, lm, lowband_out, 1.0, x_cm);
+
+            let lowband = effective_lowband.map(|eff| {
+                lowband_scratch[..n].copy_from_slice(&norm2[eff..eff + n]);
+                &mut lowband_scratch[..n]
+            });
+
+            let lowband_out = if last { None } else { Some(&mut norm2[out_offset..][..n]) };
+
+            y_cm_out = quant_band(&mut ctx, y, n, b, big_b, lowband, lm, lowband_out, 1.0, y_cm);
+        }
+        else {
+            let lowband = effective_lowband.map(|eff| {
+                lowband_scratch[..n].copy_from_slice(&norm[eff..eff + n]);
+                &mut lowband_scratch[..n]
+            });
+
+            let lowband_out = if last { None } else { Some(&mut norm[out_offset..][..n]) };
+
+            let fill = x_cm | y_cm;
+
+            x_cm_out = match y_buf.as_deref_mut() {
+                Some(y_buf) => {
+                    let y = &mut y_buf[band_start..band_start + n];
+                    quant_band_stereo(&mut ctx, x, y, n, b, big_b, lowband, lm, lowband_out, fill)
+                }
+                None => quant_band(&mut ctx, x, n, b, big_b, lowband, lm, lowband_out, 1.0, fill),
+            };
+
+            y_cm_out = x_cm_out;
+        }
+
+        collapse_masks[i * channels] = x_cm_out as u8;
+        collapse_masks[i * channels + channels - 1] = y_cm_out as u8;
+
+        balance += params.pulses[i] + tell;
+
+        // Update the folding position only as long as there is 1 bit/sample depth.
+        update_lowband = b > (n << BITRES) as i32;
+    }
+
+    *seed = ctx.seed;
+}
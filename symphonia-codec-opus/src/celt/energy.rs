@@ -0,0 +1,168 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Band energy decoding (RFC 6716 Section 4.3.2).
+
+use crate::range::RangeDecoder;
+
+use super::tables::*;
+
+/// The maximum number of fine energy bits per band.
+pub const MAX_FINE_BITS: i32 = 8;
+
+/// The minimum probability of an energy delta.
+const LAPLACE_MINP: u32 = 1;
+/// The minimum number of guaranteed representable energy deltas (in one direction).
+const LAPLACE_NMIN: u32 = 16;
+
+/// Decodes a value with a Laplace-like distribution. `fs` is the probability of 0 and `decay` is
+/// the rate of decay of the distribution, both in Q15.
+fn decode_laplace(rc: &mut RangeDecoder<'_>, mut fs: u32, decay: u32) -> i32 {
+    let mut val = 0;
+    let mut fl = 0;
+
+    let fm = rc.decode_bin(15);
+
+    if fm >= fs {
+        val += 1;
+        fl = fs;
+        fs = (((32768 - LAPLACE_MINP * (2 * LAPLACE_NMIN) - fs) * (16384 - decay)) >> 15)
+            + LAPLACE_MINP;
+
+        // Search the decaying part of the PDF.
+        while fs > LAPLACE_MINP && fm >= fl + 2 * fs {
+            fs *= 2;
+            fl += fs;
+            fs = (((fs - 2 * LAPLACE_MINP) * decay) >> 15) + LAPLACE_MINP;
+            val += 1;
+        }
+
+        // Everything beyond that has probability LAPLACE_MINP.
+        if fs <= LAPLACE_MINP {
+            let di = (fm - fl) >> 1;
+            val += di as i32;
+            fl += 2 * di * LAPLACE_MINP;
+        }
+
+        if fm < fl + fs {
+            val = -val;
+        }
+        else {
+            fl += fs;
+        }
+    }
+
+    rc.update(fl, (fl + fs).min(32768), 32768);
+    val
+}
+
+/// Decodes the coarse energy of each band, updating the previous frame's energy in-place.
+pub fn decode_coarse_energy(
+    rc: &mut RangeDecoder<'_>,
+    energy: &mut [f32],
+    start: usize,
+    end: usize,
+    intra: bool,
+    channels: usize,
+    lm: usize,
+) {
+    let prob_model = &E_PROB_MODEL[(2 * lm + intra as usize) * 42..][..42];
+
+    let (coef, beta) = if intra { (0.0, BETA_INTRA) } else { (PRED_COEF[lm], BETA_COEF[lm]) };
+
+    let budget = 8 * rc.storage() as i32;
+
+    let mut prev = [0f32; 2];
+
+    for i in start..end {
+        for c in 0..channels {
+            let tell = rc.tell();
+
+            let qi = if budget - tell >= 15 {
+                let pi = 2 * i.min(20);
+                decode_laplace(
+                    rc,
+                    u32::from(prob_model[pi]) << 7,
+                    u32::from(prob_model[pi + 1]) << 6,
+                )
+            }
+            else if budget - tell >= 2 {
+                let qi = rc.decode_icdf(&SMALL_ENERGY_ICDF, 2) as i32;
+                (qi >> 1) ^ -(qi & 1)
+            }
+            else if budget - tell >= 1 {
+                -(rc.decode_bit_logp(1) as i32)
+            }
+            else {
+                -1
+            };
+
+            let q = qi as f32;
+
+            let e = &mut energy[i + c * NUM_BANDS];
+
+            *e = e.max(-9.0);
+            *e = coef * *e + prev[c] + q;
+
+            prev[c] = prev[c] + q - beta * q;
+        }
+    }
+}
+
+/// Decodes the fine energy of each band.
+pub fn decode_fine_energy(
+    rc: &mut RangeDecoder<'_>,
+    energy: &mut [f32],
+    start: usize,
+    end: usize,
+    fine_quant: &[i32],
+    channels: usize,
+) {
+    for i in start..end {
+        if fine_quant[i] <= 0 {
+            continue;
+        }
+
+        for c in 0..channels {
+            let q2 = rc.decode_bits(fine_quant[i] as u32);
+            let offset = (q2 as f32 + 0.5) * (1 << (14 - fine_quant[i])) as f32 / 16384.0 - 0.5;
+            energy[i + c * NUM_BANDS] += offset;
+        }
+    }
+}
+
+/// Uses any remaining bits to further refine the energy of each band.
+#[allow(clippy::too_many_arguments)]
+pub fn decode_energy_finalise(
+    rc: &mut RangeDecoder<'_>,
+    energy: &mut [f32],
+    start: usize,
+    end: usize,
+    fine_quant: &[i32],
+    fine_priority: &[i32],
+    mut bits_left: i32,
+    channels: usize,
+) {
+    for prio in 0..2 {
+        for i in start..end {
+            if bits_left < channels as i32 {
+                break;
+            }
+
+            if fine_quant[i] >= MAX_FINE_BITS || fine_priority[i] != prio {
+                continue;
+            }
+
+            for c in 0..channels {
+                let q2 = rc.decode_bits(1);
+                let offset = (q2 as f32 - 0.5) * (1 << (14 - fine_quant[i] - 1)) as f32 / 16384.0;
+                energy[i + c * NUM_BANDS] += offset;
+                bits_left -= 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The CELT inverse MDCT.
+//!
+//! CELT uses MDCT sizes that are not a power-of-two (e.g., 1920 for a 20 ms frame). Therefore,
+//! the IMDCT is implemented in terms of the mixed-radix complex FFT of size N/4 provided by
+//! `symphonia-core`.
+
+use std::f64;
+
+use symphonia_core::dsp::fft::{Complex, Fft};
+
+/// The CELT inverse MDCT for a single transform size.
+pub struct Imdct {
+    n: usize,
+    fft: Fft,
+    trig: Vec<f32>,
+    fft_in: Vec<Complex>,
+    fft_out: Vec<Complex>,
+}
+
+impl Imdct {
+    /// Instantiate a N-point (output) IMDCT. N must be a multiple of 4. The FFT is most
+    /// efficient if N/4 only has the factors 2, 3, and 5.
+    pub fn new(n: usize) -> Self {
+        let trig = (0..n / 2)
+            .map(|i| (2.0 * f64::consts::PI * (i as f64 + 0.125) / n as f64).cos() as f32)
+            .collect();
+
+        Imdct {
+            n,
+            fft: Fft::new(n / 4),
+            trig,
+            fft_in: vec![Default::default(); n / 4],
+            fft_out: vec![Default::default(); n / 4],
+        }
+    }
+
+    /// Computes the IMDCT of the N/2 coefficients in `input`, taken with a stride of `stride`, and
+    /// applies TDAC windowing with the previous frame's overlap.
+    ///
+    /// On entry, `out[..overlap]` should contain the windowed overlap of the previous frame. On
+    /// exit, `out[..overlap / 2 + N / 2]` will contain the fully overlapped output samples.
+    pub fn imdct(&mut self, input: &[f32], out: &mut [f32], window: &[f32], stride: usize) {
+        let n2 = self.n >> 1;
+        let n4 = self.n >> 2;
+        let overlap = window.len();
+        let trig = &self.trig;
+
+        // Pre-rotate.
+        for i in 0..n4 {
+            let x1 = input[2 * i * stride];
+            let x2 = input[stride * (n2 - 1 - 2 * i)];
+
+            let yr = x2 * trig[i] + x1 * trig[n4 + i];
+            let yi = x1 * trig[i] - x2 * trig[n4 + i];
+
+            // Swap real and imaginary because a FFT is used instead of an IFFT.
+            self.fft_in[i] = Complex::new(yi, yr);
+        }
+
+        self.fft.fft(&self.fft_in, &mut self.fft_out);
+
+        // Post-rotate and de-shuffle from both ends of the buffer at once.
+        let yp = &mut out[overlap >> 1..(overlap >> 1) + n2];
+
+        for i in 0..(n4 + 1) >> 1 {
+            let j = n4 - 1 - i;
+
+            // Swap real and imaginary because a FFT is used instead of an IFFT.
+            let re = self.fft_out[i].im;
+            let im = self.fft_out[i].re;
+
+            let yr0 = re * trig[i] + im * trig[n4 + i];
+            let yi1 = re * trig[n4 + i] - im * trig[i];
+
+            let re = self.fft_out[j].im;
+            let im = self.fft_out[j].re;
+
+            let yr1 = re * trig[n4 - i - 1] + im * trig[n2 - i - 1];
+            let yi0 = re * trig[n2 - i - 1] - im * trig[n4 - i - 1];
+
+            yp[2 * i] = yr0;
+            yp[2 * j + 1] = yi1;
+            yp[2 * j] = yr1;
+            yp[2 * i + 1] = yi0;
+        }
+
+        // Mirror on both sides for TDAC.
+        for i in 0..overlap / 2 {
+            let x1 = out[overlap - 1 - i];
+            let x2 = out[i];
+            let w1 = window[i];
+            let w2 = window[overlap - 1 - i];
+
+            out[i] = w2 * x2 - w1 * x1;
+            out[overlap - 1 - i] = w1 * x2 + w2 * x1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dft(x: &[Complex]) -> Vec<Complex> {
+        let n = x.len();
+
+        (0..n)
+            .map(|k| {
+                let mut acc = (0.0f64, 0.0f64);
+
+                for (j, x) in x.iter().enumerate() {
+                    let theta = -2.0 * f64::consts::PI * (j * k) as f64 / n as f64;
+                    acc.0 += f64::from(x.re) * theta.cos() - f64::from(x.im) * theta.sin();
+                    acc.1 += f64::from(x.re) * theta.sin() + f64::from(x.im) * theta.cos();
+                }
+
+                Complex::new(acc.0 as f32, acc.1 as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn verify_fft() {
+        for &n in &[60, 120, 240, 480] {
+            let input: Vec<Complex> = (0..n)
+                .map(|i| Complex::new(((i * 7) % 13) as f32 - 6.0, ((i * 3) % 5) as f32 - 2.0))
+                .collect();
+
+            let mut actual = vec![Complex::default(); n];
+            Fft::new(n).fft(&input, &mut actual);
+
+            for (a, e) in actual.iter().zip(dft(&input)) {
+                assert!((a.re - e.re).abs() < 1e-2);
+                assert!((a.im - e.im).abs() < 1e-2);
+            }
+        }
+    }
+}
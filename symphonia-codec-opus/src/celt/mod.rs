@@ -0,0 +1,842 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The CELT layer of the Opus codec (RFC 6716 Section 4.3).
+
+use symphonia_core::errors::{decode_error, Result};
+
+use crate::range::{RangeDecoder, BITRES};
+
+mod bands;
+mod energy;
+mod mdct;
+mod rate;
+mod tables;
+
+use bands::{lcg_rand, renormalise_vector, BandParams, SPREAD_NORMAL};
+use mdct::Imdct;
+use tables::*;
+
+pub use tables::WINDOW;
+
+/// The number of samples of history kept for each channel.
+const DECODE_BUFFER_SIZE: usize = 2048;
+
+/// The minimum period of the post-filter.
+const COMBFILTER_MINPERIOD: usize = 15;
+
+/// The largest frame size, in samples, of a CELT frame.
+pub const MAX_FRAME_SIZE: usize = SHORT_MDCT_SIZE << MAX_LM;
+
+/// The number of bands coded by CELT in hybrid mode are limited to those above 8 kHz.
+pub const HYBRID_START_BAND: usize = 17;
+
+/// `CeltDecoder` decodes CELT frames.
+pub struct CeltDecoder {
+    /// The number of output channels.
+    channels: usize,
+    /// The number of channels coded in the stream.
+    stream_channels: usize,
+    /// The first band to decode.
+    start: usize,
+    /// The band after the last band to decode.
+    end: usize,
+    /// If true, the phase inversion of intensity stereo is disabled.
+    disable_inv: bool,
+    /// The final range of the last decoded frame, also used to seed noise generation.
+    rng: u32,
+    /// The number of consecutive frames lost.
+    loss_count: u32,
+    postfilter_period: usize,
+    postfilter_period_old: usize,
+    postfilter_gain: f32,
+    postfilter_gain_old: f32,
+    postfilter_tapset: usize,
+    postfilter_tapset_old: usize,
+    /// The de-emphasis filter memory for each channel.
+    preemph_mem: [f32; 2],
+    /// The history of synthesized samples (and the MDCT overlap) for each channel.
+    decode_mem: [Vec<f32>; 2],
+    old_band_e: [f32; 2 * NUM_BANDS],
+    old_log_e: [f32; 2 * NUM_BANDS],
+    old_log_e2: [f32; 2 * NUM_BANDS],
+    background_log_e: [f32; 2 * NUM_BANDS],
+    /// The IMDCTs, indexed by the MDCT shift.
+    imdct: Vec<Imdct>,
+    /// The normalized shape of each band for each channel.
+    x: Vec<f32>,
+    /// The denormalized MDCT coefficients of one channel.
+    freq: Vec<f32>,
+}
+
+impl CeltDecoder {
+    /// Instantiate a new CELT decoder outputting `channels` channels.
+    pub fn new(channels: usize) -> Self {
+        assert!(channels == 1 || channels == 2);
+
+        let mut decoder = CeltDecoder {
+            channels,
+            stream_channels: channels,
+            start: 0,
+            end: NUM_BANDS,
+            disable_inv: channels == 1,
+            rng: 0,
+            loss_count: 0,
+            postfilter_period: 0,
+            postfilter_period_old: 0,
+            postfilter_gain: 0.0,
+            postfilter_gain_old: 0.0,
+            postfilter_tapset: 0,
+            postfilter_tapset_old: 0,
+            preemph_mem: [0.0; 2],
+            decode_mem: [
+                vec![0.0; DECODE_BUFFER_SIZE + OVERLAP],
+                vec![0.0; DECODE_BUFFER_SIZE + OVERLAP],
+            ],
+            old_band_e: [0.0; 2 * NUM_BANDS],
+            old_log_e: [0.0; 2 * NUM_BANDS],
+            old_log_e2: [0.0; 2 * NUM_BANDS],
+            background_log_e: [0.0; 2 * NUM_BANDS],
+            imdct: (0..=MAX_LM).map(|shift| Imdct::new((2 * MAX_FRAME_SIZE) >> shift)).collect(),
+            x: vec![0.0; 2 * MAX_FRAME_SIZE],
+            freq: vec![0.0; MAX_FRAME_SIZE],
+        };
+
+        decoder.reset();
+        decoder
+    }
+
+    /// Resets the decoder state.
+    pub fn reset(&mut self) {
+        self.rng = 0;
+        self.loss_count = 0;
+        self.postfilter_period = 0;
+        self.postfilter_period_old = 0;
+        self.postfilter_gain = 0.0;
+        self.postfilter_gain_old = 0.0;
+        self.postfilter_tapset = 0;
+        self.postfilter_tapset_old = 0;
+        self.preemph_mem = [0.0; 2];
+
+        for mem in self.decode_mem.iter_mut() {
+            for s in mem.iter_mut() {
+                *s = 0.0;
+            }
+        }
+
+        self.old_band_e = [0.0; 2 * NUM_BANDS];
+        self.old_log_e = [-28.0; 2 * NUM_BANDS];
+        self.old_log_e2 = [-28.0; 2 * NUM_BANDS];
+        self.background_log_e = [0.0; 2 * NUM_BANDS];
+    }
+
+    /// Sets the first band to decode.
+    pub fn set_start_band(&mut self, start: usize) {
+        self.start = start;
+    }
+
+    /// Sets the band after the last band to decode.
+    pub fn set_end_band(&mut self, end: usize) {
+        self.end = end;
+    }
+
+    /// Sets the number of channels coded in the stream.
+    pub fn set_stream_channels(&mut self, stream_channels: usize) {
+        self.stream_channels = stream_channels;
+    }
+
+    /// Decodes a CELT frame of `frame_size` samples from the range decoder. `len` is the length
+    /// of the frame in bytes, which may be shared with SILK for hybrid frames. The interleaved
+    /// output samples are written to `pcm`.
+    pub fn decode(
+        &mut self,
+        rc: &mut RangeDecoder<'_>,
+        len: usize,
+        pcm: &mut [f32],
+        frame_size: usize,
+    ) -> Result<()> {
+        let lm = match (0..=MAX_LM).find(|&lm| SHORT_MDCT_SIZE << lm == frame_size) {
+            Some(lm) => lm,
+            _ => return decode_error("opus: invalid celt frame size"),
+        };
+
+        let m = 1 << lm;
+        let n = m * SHORT_MDCT_SIZE;
+
+        let cc = self.channels;
+        let c = self.stream_channels;
+        let start = self.start;
+        let end = self.end;
+
+        if c == 1 {
+            for i in 0..NUM_BANDS {
+                self.old_band_e[i] = self.old_band_e[i].max(self.old_band_e[NUM_BANDS + i]);
+            }
+        }
+
+        let mut total_bits = 8 * len as i32;
+        let mut tell = rc.tell();
+
+        let silence = if tell >= total_bits {
+            true
+        }
+        else if tell == 1 {
+            rc.decode_bit_logp(15)
+        }
+        else {
+            false
+        };
+
+        if silence {
+            // Pretend all the remaining bits were read.
+            tell = total_bits;
+            rc.set_tell(tell);
+        }
+
+        let mut postfilter_gain = 0.0;
+        let mut postfilter_pitch = 0;
+        let mut postfilter_tapset = 0;
+
+        if start == 0 && tell + 16 <= total_bits {
+            if rc.decode_bit_logp(1) {
+                let octave = rc.decode_uint(6);
+                postfilter_pitch = ((16 << octave) + rc.decode_bits(4 + octave) - 1) as usize;
+
+                let qg = rc.decode_bits(3);
+
+                if rc.tell() + 2 <= total_bits {
+                    postfilter_tapset = rc.decode_icdf(&TAPSET_ICDF, 2);
+                }
+
+                postfilter_gain = 0.09375 * (qg + 1) as f32;
+            }
+
+            tell = rc.tell();
+        }
+
+        let is_transient = if lm > 0 && tell + 3 <= total_bits {
+            let is_transient = rc.decode_bit_logp(3);
+            tell = rc.tell();
+            is_transient
+        }
+        else {
+            false
+        };
+
+        // Decode the global flags.
+        let intra_ener = if tell + 3 <= total_bits { rc.decode_bit_logp(3) } else { false };
+
+        // Get the band energies.
+        energy::decode_coarse_energy(rc, &mut self.old_band_e, start, end, intra_ener, c, lm);
+
+        let mut tf_res = [0; NUM_BANDS];
+        tf_decode(rc, start, end, is_transient, &mut tf_res, lm);
+
+        let spread = if rc.tell() + 4 <= total_bits {
+            rc.decode_icdf(&SPREAD_ICDF, 5) as i32
+        }
+        else {
+            SPREAD_NORMAL
+        };
+
+        let mut cap = [0; NUM_BANDS];
+        rate::init_caps(&mut cap, lm, c);
+
+        let mut offsets = [0; NUM_BANDS];
+        let mut dynalloc_logp = 6;
+
+        total_bits <<= BITRES;
+        let mut tell = rc.tell_frac() as i32;
+
+        for i in start..end {
+            let width = ((c * (EBANDS[i + 1] - EBANDS[i])) << lm) as i32;
+
+            // Quanta is 6 bits, but no more than 1 bit/sample and no less than 1/8 bit/sample.
+            let quanta = (width << BITRES).min((6 << BITRES).max(width));
+
+            let mut dynalloc_loop_logp = dynalloc_logp;
+            let mut boost = 0;
+
+            while tell + (dynalloc_loop_logp << BITRES) < total_bits && boost < cap[i] {
+                let flag = rc.decode_bit_logp(dynalloc_loop_logp as u32);
+                tell = rc.tell_frac() as i32;
+
+                if !flag {
+                    break;
+                }
+
+                boost += quanta;
+                total_bits -= quanta;
+                dynalloc_loop_logp = 1;
+            }
+
+            offsets[i] = boost;
+
+            // Making dynalloc more likely.
+            if boost > 0 {
+                dynalloc_logp = 2.max(dynalloc_logp - 1);
+            }
+        }
+
+        let alloc_trim = if tell + (6 << BITRES) <= total_bits {
+            rc.decode_icdf(&TRIM_ICDF, 7) as i32
+        }
+        else {
+            5
+        };
+
+        let mut bits = ((len as i32 * 8) << BITRES) - rc.tell_frac() as i32 - 1;
+
+        let anti_collapse_rsv = if is_transient && lm >= 2 && bits >= (lm as i32 + 2) << BITRES {
+            1 << BITRES
+        }
+        else {
+            0
+        };
+
+        bits -= anti_collapse_rsv;
+
+        let mut pulses = [0; NUM_BANDS];
+        let mut fine_quant = [0; NUM_BANDS];
+        let mut fine_priority = [0; NUM_BANDS];
+
+        let alloc = rate::compute_allocation(
+            rc,
+            start,
+            end,
+            &offsets,
+            &cap,
+            alloc_trim,
+            bits,
+            &mut pulses,
+            &mut fine_quant,
+            &mut fine_priority,
+            c,
+            lm,
+        );
+
+        energy::decode_fine_energy(rc, &mut self.old_band_e, start, end, &fine_quant, c);
+
+        for mem in self.decode_mem[..cc].iter_mut() {
+            mem.copy_within(n..DECODE_BUFFER_SIZE + OVERLAP / 2, 0);
+        }
+
+        // Decode the fixed codebook.
+        let mut collapse_masks = [0u8; 2 * NUM_BANDS];
+
+        let params = BandParams {
+            start,
+            end,
+            pulses: &pulses,
+            short_blocks: is_transient,
+            spread,
+            dual_stereo: alloc.dual_stereo,
+            intensity: alloc.intensity,
+            tf_res: &tf_res,
+            total_bits: ((len as i32) * (8 << BITRES)) - anti_collapse_rsv,
+            balance: alloc.balance,
+            lm,
+            coded_bands: alloc.coded_bands,
+            disable_inv: self.disable_inv,
+        };
+
+        {
+            let (x, y) = self.x.split_at_mut(n);
+            let y = if c == 2 { Some(&mut y[..n]) } else { None };
+
+            bands::decode_all_bands(rc, &params, x, y, &mut collapse_masks, &mut self.rng);
+        }
+
+        let anti_collapse_on = anti_collapse_rsv > 0 && rc.decode_bits(1) != 0;
+
+        let bits_left = 8 * len as i32 - rc.tell();
+
+        energy::decode_energy_finalise(
+            rc,
+            &mut self.old_band_e,
+            start,
+            end,
+            &fine_quant,
+            &fine_priority,
+            bits_left,
+            c,
+        );
+
+        if anti_collapse_on {
+            anti_collapse(
+                &mut self.x,
+                &collapse_masks,
+                lm,
+                c,
+                n,
+                start,
+                end,
+                &self.old_band_e,
+                &self.old_log_e,
+                &self.old_log_e2,
+                &pulses,
+                self.rng,
+            );
+        }
+
+        if silence {
+            for e in self.old_band_e[..c * NUM_BANDS].iter_mut() {
+                *e = -28.0;
+            }
+        }
+
+        self.synthesis(c, is_transient, lm, silence, start, end);
+
+        for mem in self.decode_mem[..cc].iter_mut() {
+            self.postfilter_period = self.postfilter_period.max(COMBFILTER_MINPERIOD);
+            self.postfilter_period_old = self.postfilter_period_old.max(COMBFILTER_MINPERIOD);
+
+            let out_syn = DECODE_BUFFER_SIZE - n;
+
+            comb_filter(
+                mem,
+                out_syn,
+                self.postfilter_period_old,
+                self.postfilter_period,
+                SHORT_MDCT_SIZE,
+                self.postfilter_gain_old,
+                self.postfilter_gain,
+                self.postfilter_tapset_old,
+                self.postfilter_tapset,
+            );
+
+            if lm != 0 {
+                comb_filter(
+                    mem,
+                    out_syn + SHORT_MDCT_SIZE,
+                    self.postfilter_period,
+                    postfilter_pitch,
+                    n - SHORT_MDCT_SIZE,
+                    self.postfilter_gain,
+                    postfilter_gain,
+                    self.postfilter_tapset,
+                    postfilter_tapset,
+                );
+            }
+        }
+
+        self.postfilter_period_old = self.postfilter_period;
+        self.postfilter_gain_old = self.postfilter_gain;
+        self.postfilter_tapset_old = self.postfilter_tapset;
+        self.postfilter_period = postfilter_pitch;
+        self.postfilter_gain = postfilter_gain;
+        self.postfilter_tapset = postfilter_tapset;
+
+        if lm != 0 {
+            self.postfilter_period_old = self.postfilter_period;
+            self.postfilter_gain_old = self.postfilter_gain;
+            self.postfilter_tapset_old = self.postfilter_tapset;
+        }
+
+        if c == 1 {
+            self.old_band_e.copy_within(..NUM_BANDS, NUM_BANDS);
+        }
+
+        if !is_transient {
+            self.old_log_e2 = self.old_log_e;
+            self.old_log_e = self.old_band_e;
+
+            // In normal circumstances, only allow the noise floor to increase by up to 2.4 dB per
+            // second, but when in DTX, allow up to 6 dB increase for each update.
+            let max_background_increase = if self.loss_count < 10 { m as f32 * 0.001 } else { 1.0 };
+
+            for (bg, &e) in self.background_log_e.iter_mut().zip(&self.old_band_e) {
+                *bg = (*bg + max_background_increase).min(e);
+            }
+        }
+        else {
+            for (log_e, &e) in self.old_log_e.iter_mut().zip(&self.old_band_e) {
+                *log_e = log_e.min(e);
+            }
+        }
+
+        for c in 0..2 {
+            for i in (0..start).chain(end..NUM_BANDS) {
+                self.old_band_e[c * NUM_BANDS + i] = 0.0;
+                self.old_log_e[c * NUM_BANDS + i] = -28.0;
+                self.old_log_e2[c * NUM_BANDS + i] = -28.0;
+            }
+        }
+
+        self.rng = rc.range();
+
+        self.deemphasis(pcm, n);
+
+        self.loss_count = 0;
+
+        if rc.tell() > 8 * len as i32 {
+            return decode_error("opus: celt frame overran the packet");
+        }
+
+        Ok(())
+    }
+
+    /// Conceals a lost CELT frame of `frame_size` samples.
+    ///
+    /// Only noise-based concealment is performed, the lost frame is synthesized from noise shaped
+    /// by the decaying energy of the last good frame.
+    pub fn decode_lost(&mut self, pcm: &mut [f32], frame_size: usize) {
+        let lm = (0..=MAX_LM).find(|&lm| SHORT_MDCT_SIZE << lm == frame_size).unwrap_or(MAX_LM);
+
+        let n = SHORT_MDCT_SIZE << lm;
+        let c = self.channels;
+        let start = self.start;
+        let end = self.end;
+        let eff_end = start.max(end.min(NUM_BANDS));
+
+        // Energy decay.
+        let decay = if self.loss_count == 0 { 1.5 } else { 0.5 };
+
+        for c in 0..c {
+            for i in start..end {
+                let e = &mut self.old_band_e[c * NUM_BANDS + i];
+                *e = self.background_log_e[c * NUM_BANDS + i].max(*e - decay);
+            }
+        }
+
+        let mut seed = self.rng;
+
+        for c in 0..c {
+            for i in start..eff_end {
+                let boffs = n * c + (EBANDS[i] << lm);
+                let blen = (EBANDS[i + 1] - EBANDS[i]) << lm;
+
+                let x = &mut self.x[boffs..boffs + blen];
+
+                for x in x.iter_mut() {
+                    seed = lcg_rand(seed);
+                    *x = (seed as i32 >> 20) as f32;
+                }
+
+                renormalise_vector(x, 1.0);
+            }
+        }
+
+        self.rng = seed;
+
+        for mem in self.decode_mem[..c].iter_mut() {
+            mem.copy_within(n..DECODE_BUFFER_SIZE + OVERLAP / 2, 0);
+        }
+
+        self.synthesis(c, false, lm, false, start, eff_end);
+
+        self.deemphasis(pcm, n);
+
+        self.loss_count += 1;
+    }
+
+    /// Denormalizes the decoded bands of the `c` coded channels, and synthesizes the output
+    /// signal of each output channel.
+    fn synthesis(
+        &mut self,
+        c: usize,
+        is_transient: bool,
+        lm: usize,
+        silence: bool,
+        start: usize,
+        end: usize,
+    ) {
+        let m = 1 << lm;
+        let n = m * SHORT_MDCT_SIZE;
+
+        let (b, nb, shift) =
+            if is_transient { (m, SHORT_MDCT_SIZE, MAX_LM) } else { (1, n, MAX_LM - lm) };
+
+        let freq = &mut self.freq[..n];
+        let x = &self.x;
+        let band_e = &self.old_band_e;
+
+        for cc in 0..self.channels {
+            if c == 2 && self.channels == 1 {
+                // Downmix a stereo stream to mono.
+                let mut freq2 = [0f32; MAX_FRAME_SIZE];
+
+                let (band_e0, band_e1) = band_e.split_at(NUM_BANDS);
+
+                denormalise_bands(&x[..n], freq, band_e0, start, end, m, silence);
+                denormalise_bands(&x[n..], &mut freq2, band_e1, start, end, m, silence);
+
+                for (f, &f2) in freq.iter_mut().zip(&freq2[..]) {
+                    *f = 0.5 * *f + 0.5 * f2;
+                }
+            }
+            else {
+                // If a mono stream is copied to two channels, the same channel is denormalized
+                // for both outputs.
+                let c = cc.min(c - 1);
+
+                let x = &x[c * n..];
+                let band_e = &band_e[c * NUM_BANDS..];
+
+                denormalise_bands(x, freq, band_e, start, end, m, silence);
+            }
+
+            let out_syn = &mut self.decode_mem[cc][DECODE_BUFFER_SIZE - n..];
+
+            for bi in 0..b {
+                self.imdct[shift].imdct(&freq[bi..], &mut out_syn[nb * bi..], &WINDOW, b);
+            }
+        }
+    }
+
+    /// Applies the de-emphasis filter and writes the interleaved output samples to `pcm`.
+    fn deemphasis(&mut self, pcm: &mut [f32], n: usize) {
+        const VERY_SMALL: f32 = 1e-30;
+        const SCALE: f32 = 1.0 / 32768.0;
+
+        let cc = self.channels;
+
+        for c in 0..cc {
+            let x = &self.decode_mem[c][DECODE_BUFFER_SIZE - n..DECODE_BUFFER_SIZE];
+
+            let mut m = self.preemph_mem[c];
+
+            for (y, &x) in pcm.iter_mut().skip(c).step_by(cc).zip(x) {
+                let tmp = x + VERY_SMALL + m;
+                m = PREEMPHASIS * tmp;
+                *y = SCALE * tmp;
+            }
+
+            self.preemph_mem[c] = m;
+        }
+    }
+}
+
+/// Decodes the time-frequency resolution changes of each band.
+fn tf_decode(
+    rc: &mut RangeDecoder<'_>,
+    start: usize,
+    end: usize,
+    is_transient: bool,
+    tf_res: &mut [i32],
+    lm: usize,
+) {
+    let mut budget = 8 * rc.storage() as i32;
+    let mut tell = rc.tell();
+    let mut logp = if is_transient { 2 } else { 4 };
+
+    let tf_select_rsv = lm > 0 && tell + logp < budget;
+
+    if tf_select_rsv {
+        budget -= 1;
+    }
+
+    let mut tf_changed = 0;
+    let mut curr = 0;
+
+    for tf in tf_res[start..end].iter_mut() {
+        if tell + logp <= budget {
+            curr ^= rc.decode_bit_logp(logp as u32) as usize;
+            tell = rc.tell();
+            tf_changed |= curr;
+        }
+
+        *tf = curr as i32;
+        logp = if is_transient { 4 } else { 5 };
+    }
+
+    let t = 4 * is_transient as usize;
+    let table = &TF_SELECT_TABLE[lm];
+
+    let tf_select = if tf_select_rsv && table[t + tf_changed] != table[t + 2 + tf_changed] {
+        rc.decode_bit_logp(1) as usize
+    }
+    else {
+        0
+    };
+
+    for tf in tf_res[start..end].iter_mut() {
+        *tf = i32::from(table[t + 2 * tf_select + *tf as usize]);
+    }
+}
+
+/// Applies the band energies to the normalized bands `x` to produce the MDCT coefficients.
+fn denormalise_bands(
+    x: &[f32],
+    freq: &mut [f32],
+    band_log_e: &[f32],
+    start: usize,
+    end: usize,
+    m: usize,
+    silence: bool,
+) {
+    let n = m * SHORT_MDCT_SIZE;
+
+    let (start, end) = if silence { (0, 0) } else { (start, end) };
+    let bound = m * EBANDS[end];
+
+    for f in freq[..m * EBANDS[start]].iter_mut() {
+        *f = 0.0;
+    }
+
+    for i in start..end {
+        let band = m * EBANDS[i]..m * EBANDS[i + 1];
+
+        let lg = band_log_e[i] + E_MEANS[i];
+        let g = lg.min(32.0).exp2();
+
+        for (f, &x) in freq[band.clone()].iter_mut().zip(&x[band]) {
+            *f = x * g;
+        }
+    }
+
+    for f in freq[bound..n].iter_mut() {
+        *f = 0.0;
+    }
+}
+
+/// Fills collapsed short blocks of transient frames with noise to prevent energy collapse.
+#[allow(clippy::too_many_arguments)]
+fn anti_collapse(
+    x: &mut [f32],
+    collapse_masks: &[u8],
+    lm: usize,
+    channels: usize,
+    size: usize,
+    start: usize,
+    end: usize,
+    log_e: &[f32],
+    prev1_log_e: &[f32],
+    prev2_log_e: &[f32],
+    pulses: &[i32],
+    mut seed: u32,
+) {
+    for i in start..end {
+        let n0 = EBANDS[i + 1] - EBANDS[i];
+
+        // Depth in 1/8 bits.
+        let depth = ((1 + pulses[i]) / n0 as i32) >> lm;
+
+        let thresh = 0.5 * (-0.125 * depth as f32).exp2();
+        let sqrt_1 = 1.0 / ((n0 << lm) as f32).sqrt();
+
+        for c in 0..channels {
+            let mut prev1 = prev1_log_e[c * NUM_BANDS + i];
+            let mut prev2 = prev2_log_e[c * NUM_BANDS + i];
+
+            if channels == 1 {
+                prev1 = prev1.max(prev1_log_e[NUM_BANDS + i]);
+                prev2 = prev2.max(prev2_log_e[NUM_BANDS + i]);
+            }
+
+            let ediff = (log_e[c * NUM_BANDS + i] - prev1.min(prev2)).max(0.0);
+
+            // r needs to be multiplied by 2 or 2*sqrt(2) depending on LM because short blocks
+            // don't have the same energy as long.
+            let mut r = 2.0 * (-ediff).exp2();
+
+            if lm == 3 {
+                r *= std::f32::consts::SQRT_2;
+            }
+
+            let r = r.min(thresh) * sqrt_1;
+
+            let x = &mut x[c * size + (EBANDS[i] << lm)..][..n0 << lm];
+
+            let mut renormalize = false;
+
+            for k in 0..1 << lm {
+                // Detect collapse.
+                if collapse_masks[i * channels + c] & (1 << k) == 0 {
+                    // Fill with noise.
+                    for j in 0..n0 {
+                        seed = lcg_rand(seed);
+                        x[(j << lm) + k] = if seed & 0x8000 != 0 { r } else { -r };
+                    }
+
+                    renormalize = true;
+                }
+            }
+
+            // Some energy was just added, so renormalize.
+            if renormalize {
+                renormalise_vector(x, 1.0);
+            }
+        }
+    }
+}
+
+/// Applies the pitch post-filter in-place to `n` samples of `buf` starting at `pos`, cross-fading
+/// from the previous filter parameters to the new ones over the overlap.
+#[allow(clippy::too_many_arguments)]
+fn comb_filter(
+    buf: &mut [f32],
+    pos: usize,
+    t0: usize,
+    t1: usize,
+    n: usize,
+    g0: f32,
+    g1: f32,
+    tapset0: usize,
+    tapset1: usize,
+) {
+    if g0 == 0.0 && g1 == 0.0 {
+        return;
+    }
+
+    // When the gain is zero, T0 and/or T1 is set to zero, they must be at least 2 to avoid
+    // processing garbage data.
+    let t0 = t0.max(COMBFILTER_MINPERIOD);
+    let t1 = t1.max(COMBFILTER_MINPERIOD);
+
+    let g00 = g0 * POSTFILTER_TAPS[tapset0][0];
+    let g01 = g0 * POSTFILTER_TAPS[tapset0][1];
+    let g02 = g0 * POSTFILTER_TAPS[tapset0][2];
+    let g10 = g1 * POSTFILTER_TAPS[tapset1][0];
+    let g11 = g1 * POSTFILTER_TAPS[tapset1][1];
+    let g12 = g1 * POSTFILTER_TAPS[tapset1][2];
+
+    let mut x1 = buf[pos - t1 + 1];
+    let mut x2 = buf[pos - t1];
+    let mut x3 = buf[pos - t1 - 1];
+    let mut x4 = buf[pos - t1 - 2];
+
+    // If the filter didn't change, the overlap is not needed.
+    let overlap = if g0 == g1 && t0 == t1 && tapset0 == tapset1 { 0 } else { OVERLAP.min(n) };
+
+    for (i, &w) in WINDOW[..overlap].iter().enumerate() {
+        let p = pos + i;
+        let x0 = buf[p - t1 + 2];
+
+        let f = w * w;
+
+        buf[p] = buf[p]
+            + (1.0 - f) * g00 * buf[p - t0]
+            + (1.0 - f) * g01 * (buf[p - t0 + 1] + buf[p - t0 - 1])
+            + (1.0 - f) * g02 * (buf[p - t0 + 2] + buf[p - t0 - 2])
+            + f * g10 * x2
+            + f * g11 * (x1 + x3)
+            + f * g12 * (x0 + x4);
+
+        x4 = x3;
+        x3 = x2;
+        x2 = x1;
+        x1 = x0;
+    }
+
+    if g1 == 0.0 {
+        return;
+    }
+
+    // Compute the part with the constant filter.
+    for i in overlap..n {
+        let p = pos + i;
+        let x0 = buf[p - t1 + 2];
+
+        buf[p] = buf[p] + g10 * x2 + g11 * (x1 + x3) + g12 * (x0 + x4);
+
+        x4 = x3;
+        x3 = x2;
+        x2 = x1;
+        x1 = x0;
+    }
+}
@@ -0,0 +1,503 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Bit allocation (RFC 6716 Section 4.3.3).
+
+use crate::range::{RangeDecoder, BITRES};
+
+use super::energy::MAX_FINE_BITS;
+use super::tables::*;
+
+const ALLOC_STEPS: i32 = 6;
+const LOG_MAX_PSEUDO: usize = 6;
+
+/// The fine energy offset in 1/8 bits.
+const FINE_OFFSET: i32 = 21;
+
+/// Gets the number of pulses for pseudo-pulse index `i`.
+#[inline(always)]
+pub fn get_pulses(i: i32) -> i32 {
+    if i < 8 { i } else { (8 + (i & 7)) << ((i >> 3) - 1) }
+}
+
+/// Gets the pulse cache for a band at a given LM.
+#[inline(always)]
+pub fn pulse_cache(band: usize, lm: i32) -> &'static [u8] {
+    let idx = CACHE_INDEX[(lm + 1) as usize * NUM_BANDS + band];
+    &CACHE_BITS[idx as usize..]
+}
+
+/// Gets the number of pseudo-pulses that most closely matches `bits` for a band.
+pub fn bits2pulses(band: usize, lm: i32, bits: i32) -> i32 {
+    let cache = pulse_cache(band, lm);
+
+    let mut lo = 0;
+    let mut hi = i32::from(cache[0]);
+
+    let bits = bits - 1;
+
+    for _ in 0..LOG_MAX_PSEUDO {
+        let mid = (lo + hi + 1) >> 1;
+
+        if i32::from(cache[mid as usize]) >= bits {
+            hi = mid;
+        }
+        else {
+            lo = mid;
+        }
+    }
+
+    let lo_bits = if lo == 0 { -1 } else { i32::from(cache[lo as usize]) };
+
+    if bits - lo_bits <= i32::from(cache[hi as usize]) - bits { lo } else { hi }
+}
+
+/// Gets the number of bits required to code `pulses` pseudo-pulses in a band.
+pub fn pulses2bits(band: usize, lm: i32, pulses: i32) -> i32 {
+    if pulses == 0 { 0 } else { i32::from(pulse_cache(band, lm)[pulses as usize]) + 1 }
+}
+
+/// Computes the maximum number of bits each band may use.
+pub fn init_caps(caps: &mut [i32], lm: usize, channels: usize) {
+    for (i, cap) in caps.iter_mut().enumerate().take(NUM_BANDS) {
+        let n = ((EBANDS[i + 1] - EBANDS[i]) << lm) as i32;
+        let c = i32::from(CACHE_CAPS[NUM_BANDS * (2 * lm + channels - 1) + i]);
+        *cap = ((c + 64) * channels as i32 * n) >> 2;
+    }
+}
+
+/// The result of the bit allocation.
+pub struct Allocation {
+    /// The number of coded bands.
+    pub coded_bands: usize,
+    /// The first band coded with intensity stereo.
+    pub intensity: usize,
+    /// Whether dual stereo is in use.
+    pub dual_stereo: bool,
+    /// The left-over bits to be rebalanced while decoding the bands.
+    pub balance: i32,
+}
+
+/// Computes the bit allocation for the bands in `[start, end)`.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_allocation(
+    rc: &mut RangeDecoder<'_>,
+    start: usize,
+    end: usize,
+    offsets: &[i32],
+    cap: &[i32],
+    alloc_trim: i32,
+    total: i32,
+    pulses: &mut [i32],
+    ebits: &mut [i32],
+    fine_priority: &mut [i32],
+    channels: usize,
+    lm: usize,
+) -> Allocation {
+    let c = channels as i32;
+    let lm_i = lm as i32;
+
+    let mut total = total.max(0);
+
+    let mut skip_start = start;
+
+    // Reserve a bit to signal the end of manually skipped bands.
+    let skip_rsv = if total >= 1 << BITRES { 1 << BITRES } else { 0 };
+    total -= skip_rsv;
+
+    // Reserve bits for the intensity and dual stereo parameters.
+    let mut intensity_rsv = 0;
+    let mut dual_stereo_rsv = 0;
+
+    if channels == 2 {
+        intensity_rsv = i32::from(LOG2_FRAC_TABLE[end - start]);
+
+        if intensity_rsv > total {
+            intensity_rsv = 0;
+        }
+        else {
+            total -= intensity_rsv;
+            dual_stereo_rsv = if total >= 1 << BITRES { 1 << BITRES } else { 0 };
+            total -= dual_stereo_rsv;
+        }
+    }
+
+    let mut bits1 = [0i32; NUM_BANDS];
+    let mut bits2 = [0i32; NUM_BANDS];
+    let mut thresh = [0i32; NUM_BANDS];
+    let mut trim_offset = [0i32; NUM_BANDS];
+
+    for j in start..end {
+        let width = (EBANDS[j + 1] - EBANDS[j]) as i32;
+
+        // Below this threshold, no PVQ bits are allocated.
+        thresh[j] = (c << BITRES).max(((3 * (width << lm)) << BITRES) >> 4);
+
+        // Tilt of the allocation curve.
+        trim_offset[j] = (c * width * (alloc_trim - 5 - lm_i) * (end - j - 1) as i32
+            * (1 << (lm_i + BITRES as i32)))
+            >> 6;
+
+        // Give less resolution to single-coefficient bands because they get more benefit from
+        // having one coarse value per coefficient.
+        if width << lm == 1 {
+            trim_offset[j] -= c << BITRES;
+        }
+    }
+
+    let mut lo = 1;
+    let mut hi = NUM_ALLOC_VECTORS as i32 - 1;
+
+    loop {
+        let mut done = false;
+        let mut psum = 0;
+        let mid = (lo + hi) >> 1;
+
+        for j in (start..end).rev() {
+            let n = (EBANDS[j + 1] - EBANDS[j]) as i32;
+
+            let mut bitsj = alloc_vector_bits(mid as usize, j, c * n, lm);
+
+            if bitsj > 0 {
+                bitsj = (bitsj + trim_offset[j]).max(0);
+            }
+
+            bitsj += offsets[j];
+
+            if bitsj >= thresh[j] || done {
+                done = true;
+                // Don't allocate more than can actually be used.
+                psum += bitsj.min(cap[j]);
+            }
+            else if bitsj >= c << BITRES {
+                psum += c << BITRES;
+            }
+        }
+
+        if psum > total {
+            hi = mid - 1;
+        }
+        else {
+            lo = mid + 1;
+        }
+
+        if lo > hi {
+            break;
+        }
+    }
+
+    hi = lo;
+    lo -= 1;
+
+    for j in start..end {
+        let n = (EBANDS[j + 1] - EBANDS[j]) as i32;
+
+        let mut bits1j = alloc_vector_bits(lo as usize, j, c * n, lm);
+
+        let mut bits2j = if hi >= NUM_ALLOC_VECTORS as i32 {
+            cap[j]
+        }
+        else {
+            alloc_vector_bits(hi as usize, j, c * n, lm)
+        };
+
+        if bits1j > 0 {
+            bits1j = (bits1j + trim_offset[j]).max(0);
+        }
+
+        if bits2j > 0 {
+            bits2j = (bits2j + trim_offset[j]).max(0);
+        }
+
+        if lo > 0 {
+            bits1j += offsets[j];
+        }
+
+        bits2j += offsets[j];
+
+        if offsets[j] > 0 {
+            skip_start = j;
+        }
+
+        bits1[j] = bits1j;
+        bits2[j] = (bits2j - bits1j).max(0);
+    }
+
+    interp_bits2pulses(
+        rc,
+        start,
+        end,
+        skip_start,
+        &bits1,
+        &bits2,
+        &thresh,
+        cap,
+        total,
+        skip_rsv,
+        intensity_rsv,
+        dual_stereo_rsv,
+        pulses,
+        ebits,
+        fine_priority,
+        channels,
+        lm,
+    )
+}
+
+/// Gets the bits allocated to band `j` by the static allocation vector `vector`, for a band
+/// with `n` coefficients over all channels.
+fn alloc_vector_bits(vector: usize, j: usize, n: i32, lm: usize) -> i32 {
+    ((n * i32::from(BAND_ALLOCATION[vector * NUM_BANDS + j])) << lm) >> 2
+}
+
+#[allow(clippy::too_many_arguments)]
+fn interp_bits2pulses(
+    rc: &mut RangeDecoder<'_>,
+    start: usize,
+    end: usize,
+    skip_start: usize,
+    bits1: &[i32],
+    bits2: &[i32],
+    thresh: &[i32],
+    cap: &[i32],
+    mut total: i32,
+    skip_rsv: i32,
+    mut intensity_rsv: i32,
+    mut dual_stereo_rsv: i32,
+    bits: &mut [i32],
+    ebits: &mut [i32],
+    fine_priority: &mut [i32],
+    channels: usize,
+    lm: usize,
+) -> Allocation {
+    let c = channels as i32;
+    let stereo = (channels > 1) as i32;
+
+    let alloc_floor = c << BITRES;
+    let log_m = (lm as i32) << BITRES;
+
+    let mut lo = 0;
+    let mut hi = 1 << ALLOC_STEPS;
+
+    for _ in 0..ALLOC_STEPS {
+        let mid = (lo + hi) >> 1;
+        let mut psum = 0;
+        let mut done = false;
+
+        for j in (start..end).rev() {
+            let tmp = bits1[j] + ((mid * bits2[j]) >> ALLOC_STEPS);
+
+            if tmp >= thresh[j] || done {
+                done = true;
+                // Don't allocate more than can actually be used.
+                psum += tmp.min(cap[j]);
+            }
+            else if tmp >= alloc_floor {
+                psum += alloc_floor;
+            }
+        }
+
+        if psum > total {
+            hi = mid;
+        }
+        else {
+            lo = mid;
+        }
+    }
+
+    let mut psum = 0;
+    let mut done = false;
+
+    for j in (start..end).rev() {
+        let mut tmp = bits1[j] + ((lo * bits2[j]) >> ALLOC_STEPS);
+
+        if tmp < thresh[j] && !done {
+            tmp = if tmp >= alloc_floor { alloc_floor } else { 0 };
+        }
+        else {
+            done = true;
+        }
+
+        // Don't allocate more than can actually be used.
+        tmp = tmp.min(cap[j]);
+        bits[j] = tmp;
+        psum += tmp;
+    }
+
+    // Decide which bands to skip, working backwards from the end.
+    let mut coded_bands = end;
+
+    loop {
+        let j = coded_bands - 1;
+
+        // Never skip the first band, nor a band that has been boosted by dynalloc.
+        if j <= skip_start {
+            // Give the bit reserved to end skipping back.
+            total += skip_rsv;
+            break;
+        }
+
+        // Figure out how many left-over bits would be added to this band. This can include bits
+        // stolen back from higher, skipped bands.
+        let mut left = total - psum;
+        let percoeff = left / (EBANDS[coded_bands] - EBANDS[start]) as i32;
+        left -= (EBANDS[coded_bands] - EBANDS[start]) as i32 * percoeff;
+
+        let rem = (left - (EBANDS[j] - EBANDS[start]) as i32).max(0);
+        let band_width = (EBANDS[coded_bands] - EBANDS[j]) as i32;
+        let mut band_bits = bits[j] + percoeff * band_width + rem;
+
+        // Only code a skip decision if above the threshold for this band. Otherwise it is
+        // force-skipped. This ensures that there are enough bits to code the skip flag.
+        if band_bits >= thresh[j].max(alloc_floor + (1 << BITRES)) {
+            if rc.decode_bit_logp(1) {
+                break;
+            }
+
+            // A bit was used to skip this band.
+            psum += 1 << BITRES;
+            band_bits -= 1 << BITRES;
+        }
+
+        // Reclaim the bits originally allocated to this band.
+        psum -= bits[j] + intensity_rsv;
+
+        if intensity_rsv > 0 {
+            intensity_rsv = i32::from(LOG2_FRAC_TABLE[j - start]);
+        }
+
+        psum += intensity_rsv;
+
+        if band_bits >= alloc_floor {
+            // If there is enough for a fine energy bit per channel, use it.
+            psum += alloc_floor;
+            bits[j] = alloc_floor;
+        }
+        else {
+            // Otherwise this band gets nothing at all.
+            bits[j] = 0;
+        }
+
+        coded_bands -= 1;
+    }
+
+    // Decode the intensity and dual stereo parameters.
+    let intensity = if intensity_rsv > 0 {
+        start + rc.decode_uint((coded_bands + 1 - start) as u32) as usize
+    }
+    else {
+        0
+    };
+
+    if intensity <= start {
+        total += dual_stereo_rsv;
+        dual_stereo_rsv = 0;
+    }
+
+    let dual_stereo = if dual_stereo_rsv > 0 { rc.decode_bit_logp(1) } else { false };
+
+    // Allocate the remaining bits.
+    let mut left = total - psum;
+    let percoeff = left / (EBANDS[coded_bands] - EBANDS[start]) as i32;
+    left -= (EBANDS[coded_bands] - EBANDS[start]) as i32 * percoeff;
+
+    for j in start..coded_bands {
+        bits[j] += percoeff * (EBANDS[j + 1] - EBANDS[j]) as i32;
+    }
+
+    for j in start..coded_bands {
+        let tmp = left.min((EBANDS[j + 1] - EBANDS[j]) as i32);
+        bits[j] += tmp;
+        left -= tmp;
+    }
+
+    let mut balance = 0;
+
+    for j in start..coded_bands {
+        let n0 = (EBANDS[j + 1] - EBANDS[j]) as i32;
+        let n = n0 << lm;
+        let bit = bits[j] + balance;
+
+        let mut excess;
+
+        if n > 1 {
+            excess = (bit - cap[j]).max(0);
+            bits[j] = bit - excess;
+
+            // Compensate for the extra degree of freedom in stereo.
+            let den = c * n
+                + (channels == 2 && n > 2 && !dual_stereo && j < intensity) as i32;
+
+            let nc_log_n = den * (i32::from(LOG_N[j]) + log_m);
+
+            // Offset for the number of fine bits by log2(N)/2 + FINE_OFFSET compared to their
+            // "fair share" of total/N.
+            let mut offset = (nc_log_n >> 1) - den * FINE_OFFSET;
+
+            // N=2 is the only point that doesn't match the curve.
+            if n == 2 {
+                offset += (den << BITRES) >> 2;
+            }
+
+            // Changing the offset for allocating the second and third fine energy bit.
+            if bits[j] + offset < (den * 2) << BITRES {
+                offset += nc_log_n >> 2;
+            }
+            else if bits[j] + offset < (den * 3) << BITRES {
+                offset += nc_log_n >> 3;
+            }
+
+            // Divide with rounding.
+            ebits[j] = (bits[j] + offset + (den << (BITRES - 1))).max(0);
+            ebits[j] = (ebits[j] / den) >> BITRES;
+
+            // Make sure not to bust.
+            if c * ebits[j] > (bits[j] >> BITRES) {
+                ebits[j] = bits[j] >> stereo >> BITRES;
+            }
+
+            // More than that is useless because that's about as far as PVQ can go.
+            ebits[j] = ebits[j].min(MAX_FINE_BITS);
+
+            // If rounded down or capped, this band is a candidate for the final fine energy
+            // pass.
+            fine_priority[j] = (ebits[j] * (den << BITRES) >= bits[j] + offset) as i32;
+
+            // Remove the allocated fine bits, the rest are assigned to PVQ.
+            bits[j] -= (c * ebits[j]) << BITRES;
+        }
+        else {
+            // For N=1, all bits go to fine energy except for a single sign bit.
+            excess = (bit - (c << BITRES)).max(0);
+            bits[j] = bit - excess;
+            ebits[j] = 0;
+            fine_priority[j] = 1;
+        }
+
+        // Fine energy can't take advantage of the re-balancing in quant_all_bands(). Instead,
+        // do the re-balancing here.
+        if excess > 0 {
+            let extra_fine = (excess >> (stereo + BITRES as i32)).min(MAX_FINE_BITS - ebits[j]);
+            ebits[j] += extra_fine;
+
+            let extra_bits = (extra_fine * c) << BITRES;
+            fine_priority[j] = (extra_bits >= excess - balance) as i32;
+            excess -= extra_bits;
+        }
+
+        balance = excess;
+    }
+
+    // The skipped bands use all their bits for fine energy.
+    for j in coded_bands..end {
+        ebits[j] = bits[j] >> stereo >> BITRES;
+        bits[j] = 0;
+        fine_priority[j] = (ebits[j] < 1) as i32;
+    }
+
+    Allocation { coded_bands, intensity, dual_stereo, balance }
+}
@@ -0,0 +1,371 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![warn(rust_2018_idioms)]
+#![forbid(unsafe_code)]
+// Justification: the CELT tables are copied verbatim from the reference implementation.
+#![allow(clippy::excessive_precision)]
+
+use symphonia_core::audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Channels};
+use symphonia_core::audio::{Signal, SignalSpec};
+use symphonia_core::codecs::{CodecDescriptor, CodecParameters, CODEC_TYPE_OPUS};
+use symphonia_core::codecs::{Decoder, DecoderOptions, FinalizeResult};
+use symphonia_core::errors::{decode_error, unsupported_error, Result};
+use symphonia_core::formats::Packet;
+use symphonia_core::io::{BufReader, ReadBytes};
+use symphonia_core::support_codec;
+use symphonia_core::units::Duration;
+
+mod celt;
+mod packet;
+mod range;
+mod silk;
+mod stream;
+
+use packet::{parse_packet, MAX_PACKET_DURATION};
+use stream::StreamDecoder;
+
+/// The signature of the Opus identification header.
+const OPUS_HEAD_SIGNATURE: &[u8] = b"OpusHead";
+
+/// The minimum size of the Opus identification header.
+const OPUS_HEAD_MIN_SIZE: usize = 19;
+
+/// The maximum supported major version of the Opus identification header.
+const OPUS_HEAD_MAX_MAJOR_VERSION: u8 = 0;
+
+/// The sample rate of decoded audio.
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+
+/// The channel mapping and output gain of an Opus stream, as described by the identification
+/// header (RFC 7845 Section 5.1).
+#[derive(Debug)]
+struct IdentHeader {
+    n_channels: u8,
+    output_gain: i16,
+    mapping_family: u8,
+    n_streams: u8,
+    n_coupled_streams: u8,
+    mapping: Vec<u8>,
+}
+
+impl IdentHeader {
+    /// The identification header implied for a mono or stereo stream without one.
+    fn default_for_channels(n_channels: u8) -> Self {
+        IdentHeader {
+            n_channels,
+            output_gain: 0,
+            mapping_family: 0,
+            n_streams: 1,
+            n_coupled_streams: n_channels - 1,
+            mapping: (0..n_channels).collect(),
+        }
+    }
+}
+
+fn read_ident_header<B: ReadBytes>(reader: &mut B) -> Result<IdentHeader> {
+    let mut magic = [0; 8];
+    reader.read_buf_exact(&mut magic)?;
+
+    if magic != *OPUS_HEAD_SIGNATURE {
+        return decode_error("opus: invalid identification header signature");
+    }
+
+    // The version is split into a major and minor version in the upper and lower 4 bits,
+    // respectively. Only the major version indicates an incompatible change.
+    let version = reader.read_byte()?;
+
+    if version >> 4 > OPUS_HEAD_MAX_MAJOR_VERSION {
+        return unsupported_error("opus: unsupported identification header version");
+    }
+
+    let n_channels = reader.read_byte()?;
+
+    if n_channels == 0 {
+        return decode_error("opus: invalid channel count");
+    }
+
+    // The pre-skip is handled by the demuxer, and the original sample rate is informative.
+    let _pre_skip = reader.read_u16()?;
+    let _input_sample_rate = reader.read_u32()?;
+
+    let output_gain = reader.read_u16()? as i16;
+
+    let mapping_family = reader.read_byte()?;
+
+    let header = match mapping_family {
+        // Mono or stereo, the channel mapping table is omitted.
+        0 => {
+            if n_channels > 2 {
+                return decode_error("opus: invalid channel count for mapping family 0");
+            }
+
+            IdentHeader { output_gain, ..IdentHeader::default_for_channels(n_channels) }
+        }
+        // Vorbis channel order.
+        1 => {
+            if n_channels > 8 {
+                return decode_error("opus: invalid channel count for mapping family 1");
+            }
+
+            let n_streams = reader.read_byte()?;
+            let n_coupled_streams = reader.read_byte()?;
+
+            if n_streams == 0 || n_coupled_streams > n_streams {
+                return decode_error("opus: invalid stream count");
+            }
+
+            if u32::from(n_streams) + u32::from(n_coupled_streams) > 255 {
+                return decode_error("opus: invalid stream count");
+            }
+
+            let mut mapping = vec![0; usize::from(n_channels)];
+            reader.read_buf_exact(&mut mapping)?;
+
+            // Each entry must either index a decoded channel, or be 255 to indicate silence.
+            let n_decoded_channels = n_streams + n_coupled_streams;
+
+            if mapping.iter().any(|&m| m != 255 && m >= n_decoded_channels) {
+                return decode_error("opus: invalid channel mapping");
+            }
+
+            IdentHeader {
+                n_channels,
+                output_gain,
+                mapping_family,
+                n_streams,
+                n_coupled_streams,
+                mapping,
+            }
+        }
+        _ => return unsupported_error("opus: unsupported channel mapping family"),
+    };
+
+    Ok(header)
+}
+
+/// Gets the channels of a stream in Vorbis channel order.
+fn vorbis_channels(n_channels: u8) -> Channels {
+    match n_channels {
+        1 => Channels::FRONT_LEFT,
+        2 => Channels::FRONT_LEFT | Channels::FRONT_RIGHT,
+        3 => Channels::FRONT_LEFT | Channels::FRONT_CENTRE | Channels::FRONT_RIGHT,
+        4 => {
+            Channels::FRONT_LEFT
+                | Channels::FRONT_RIGHT
+                | Channels::REAR_LEFT
+                | Channels::REAR_RIGHT
+        }
+        5 => {
+            Channels::FRONT_LEFT
+                | Channels::FRONT_CENTRE
+                | Channels::FRONT_RIGHT
+                | Channels::REAR_LEFT
+                | Channels::REAR_RIGHT
+        }
+        6 => {
+            Channels::FRONT_LEFT
+                | Channels::FRONT_CENTRE
+                | Channels::FRONT_RIGHT
+                | Channels::REAR_LEFT
+                | Channels::REAR_RIGHT
+                | Channels::LFE1
+        }
+        7 => {
+            Channels::FRONT_LEFT
+                | Channels::FRONT_CENTRE
+                | Channels::FRONT_RIGHT
+                | Channels::SIDE_LEFT
+                | Channels::SIDE_RIGHT
+                | Channels::REAR_CENTRE
+                | Channels::LFE1
+        }
+        _ => {
+            Channels::FRONT_LEFT
+                | Channels::FRONT_CENTRE
+                | Channels::FRONT_RIGHT
+                | Channels::SIDE_LEFT
+                | Channels::SIDE_RIGHT
+                | Channels::REAR_LEFT
+                | Channels::REAR_RIGHT
+                | Channels::LFE1
+        }
+    }
+}
+
+/// Opus decoder.
+pub struct OpusDecoder {
+    /// Codec paramters.
+    params: CodecParameters,
+    /// Trim the padding for gapless playback.
+    is_gapless: bool,
+    /// Identification header.
+    ident: IdentHeader,
+    /// The decoder of each elementary stream. Coupled (stereo) streams come first.
+    streams: Vec<StreamDecoder>,
+    /// The linear output gain.
+    gain: f32,
+    /// Interleaved output of a single stream.
+    pcm: Vec<f32>,
+    /// Output buffer.
+    buf: AudioBuffer<f32>,
+}
+
+impl OpusDecoder {
+    /// Copies the decoded audio of stream `s` to all output channels mapped to it.
+    fn copy_stream_out(&mut self, s: usize, n_frames: usize) {
+        let n_coupled = usize::from(self.ident.n_coupled_streams);
+        let stride = if s < n_coupled { 2 } else { 1 };
+
+        for (c, &m) in self.ident.mapping.iter().enumerate() {
+            let m = usize::from(m);
+
+            // Determine which channel of the stream, if any, the output channel is mapped to.
+            let src = if m == 255 {
+                None
+            }
+            else if m < 2 * n_coupled {
+                if m / 2 == s { Some(m & 1) } else { None }
+            }
+            else if m - n_coupled == s {
+                Some(0)
+            }
+            else {
+                None
+            };
+
+            if let Some(src) = src {
+                let pcm = self.pcm[src..].iter().step_by(stride);
+
+                for (out, &s) in self.buf.chan_mut(c)[..n_frames].iter_mut().zip(pcm) {
+                    *out = s;
+                }
+            }
+        }
+    }
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, options: &DecoderOptions) -> Result<Self> {
+        // Read the identification header from the extra data. If there is no extra data, assume
+        // a mono or stereo stream.
+        let ident = match params.extra_data.as_ref() {
+            Some(buf) => {
+                if buf.len() < OPUS_HEAD_MIN_SIZE {
+                    return decode_error("opus: identification header too short");
+                }
+
+                read_ident_header(&mut BufReader::new(buf))?
+            }
+            _ => match params.channels.map(|channels| channels.count()) {
+                Some(n_channels) if n_channels == 1 || n_channels == 2 => {
+                    IdentHeader::default_for_channels(n_channels as u8)
+                }
+                _ => return unsupported_error("opus: missing extra data"),
+            },
+        };
+
+        let channels = if ident.mapping_family == 0 && ident.n_channels == 1 {
+            Channels::FRONT_LEFT
+        }
+        else {
+            vorbis_channels(ident.n_channels)
+        };
+
+        let streams = (0..ident.n_streams)
+            .map(|s| StreamDecoder::new(if s < ident.n_coupled_streams { 2 } else { 1 }))
+            .collect();
+
+        // The output gain is in Q7.8 dB.
+        let gain = 10f32.powf(f32::from(ident.output_gain) / (20.0 * 256.0));
+
+        let spec = SignalSpec::new(OPUS_SAMPLE_RATE, channels);
+
+        Ok(OpusDecoder {
+            params: params.clone(),
+            is_gapless: options.gapless,
+            ident,
+            streams,
+            gain,
+            pcm: vec![0.0; 2 * MAX_PACKET_DURATION],
+            buf: AudioBuffer::new(MAX_PACKET_DURATION as Duration, spec),
+        })
+    }
+
+    fn reset(&mut self) {
+        for stream in self.streams.iter_mut() {
+            stream.reset();
+        }
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[
+            support_codec!(CODEC_TYPE_OPUS, "opus", "Opus"),
+        ]
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        self.buf.clear();
+
+        let mut buf = packet.buf();
+        let mut n_frames = None;
+
+        let n_streams = self.streams.len();
+
+        for s in 0..n_streams {
+            // All but the last stream use self-delimited framing.
+            let stream_packet = parse_packet(buf, s + 1 < n_streams)?;
+
+            let duration = stream_packet.duration();
+
+            // All streams must have the same duration.
+            match n_frames {
+                Some(n_frames) if n_frames != duration => {
+                    return decode_error("opus: stream duration mismatch");
+                }
+                Some(_) => (),
+                None => {
+                    self.buf.render_reserved(Some(duration));
+                    n_frames = Some(duration);
+                }
+            }
+
+            let channels = if s < usize::from(self.ident.n_coupled_streams) { 2 } else { 1 };
+
+            self.streams[s].decode(&stream_packet, &mut self.pcm[..channels * duration])?;
+
+            self.copy_stream_out(s, duration);
+
+            buf = &buf[stream_packet.len..];
+        }
+
+        // Apply the output gain.
+        if self.ident.output_gain != 0 {
+            for c in 0..self.ident.mapping.len() {
+                for s in self.buf.chan_mut(c).iter_mut() {
+                    *s *= self.gain;
+                }
+            }
+        }
+
+        // Trim the padding for gapless playback.
+        if self.is_gapless {
+            let (start, end) = self.params.padding_trim(packet.pts(), self.buf.frames());
+            self.buf.trim(start, end);
+        }
+
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        Default::default()
+    }
+}
@@ -0,0 +1,377 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Opus packet framing (RFC 6716 Section 3).
+
+use symphonia_core::errors::{decode_error, Result};
+
+/// The maximum number of frames in a packet (48 x 2.5 ms = 120 ms).
+pub const MAX_FRAMES: usize = 48;
+
+/// The maximum duration of a packet in samples at 48 kHz.
+pub const MAX_PACKET_DURATION: usize = 5760;
+
+/// The maximum size of a frame in bytes.
+const MAX_FRAME_BYTES: usize = 1275;
+
+/// The coding mode of a frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    SilkOnly,
+    Hybrid,
+    CeltOnly,
+}
+
+/// The audio bandwidth of a frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bandwidth {
+    /// 4 kHz bandwidth, 8 kHz sample rate.
+    Narrowband,
+    /// 6 kHz bandwidth, 12 kHz sample rate.
+    Mediumband,
+    /// 8 kHz bandwidth, 16 kHz sample rate.
+    Wideband,
+    /// 12 kHz bandwidth, 24 kHz sample rate.
+    SuperWideband,
+    /// 20 kHz bandwidth, 48 kHz sample rate.
+    Fullband,
+}
+
+/// The table-of-contents byte of a packet (RFC 6716 Section 3.1).
+#[derive(Copy, Clone, Debug)]
+pub struct Toc {
+    /// The coding mode of all frames in the packet.
+    pub mode: Mode,
+    /// The audio bandwidth of all frames in the packet.
+    pub bandwidth: Bandwidth,
+    /// The duration of each frame in the packet, in samples at 48 kHz.
+    pub frame_size: usize,
+    /// The number of channels coded in the packet.
+    pub stream_channels: usize,
+}
+
+impl Toc {
+    pub fn new(toc: u8) -> Self {
+        let config = toc >> 3;
+
+        let (mode, bandwidth, frame_size) = match config {
+            0..=11 => {
+                let bandwidth = match config >> 2 {
+                    0 => Bandwidth::Narrowband,
+                    1 => Bandwidth::Mediumband,
+                    _ => Bandwidth::Wideband,
+                };
+
+                // SILK-only frames are 10, 20, 40, or 60 ms.
+                let frame_size = match config & 0x3 {
+                    0 => 480,
+                    1 => 960,
+                    2 => 1920,
+                    _ => 2880,
+                };
+
+                (Mode::SilkOnly, bandwidth, frame_size)
+            }
+            12..=15 => {
+                let bandwidth = if config < 14 {
+                    Bandwidth::SuperWideband
+                }
+                else {
+                    Bandwidth::Fullband
+                };
+
+                // Hybrid frames are 10 or 20 ms.
+                let frame_size = if config & 0x1 == 0 { 480 } else { 960 };
+
+                (Mode::Hybrid, bandwidth, frame_size)
+            }
+            _ => {
+                let bandwidth = match (config >> 2) & 0x3 {
+                    0 => Bandwidth::Narrowband,
+                    1 => Bandwidth::Wideband,
+                    2 => Bandwidth::SuperWideband,
+                    _ => Bandwidth::Fullband,
+                };
+
+                // CELT-only frames are 2.5, 5, 10, or 20 ms.
+                let frame_size = 120 << (config & 0x3);
+
+                (Mode::CeltOnly, bandwidth, frame_size)
+            }
+        };
+
+        let stream_channels = if toc & 0x4 != 0 { 2 } else { 1 };
+
+        Toc { mode, bandwidth, frame_size, stream_channels }
+    }
+}
+
+/// A parsed Opus packet.
+pub struct FramePacket<'a> {
+    /// The table-of-contents.
+    pub toc: Toc,
+    /// The compressed frames.
+    pub frames: [&'a [u8]; MAX_FRAMES],
+    /// The number of frames in the packet.
+    pub n_frames: usize,
+    /// The total length of the packet in bytes, including any padding.
+    pub len: usize,
+}
+
+impl<'a> FramePacket<'a> {
+    /// Gets the compressed frames of the packet.
+    pub fn frames(&self) -> &[&'a [u8]] {
+        &self.frames[..self.n_frames]
+    }
+
+    /// Gets the duration of the packet in samples at 48 kHz.
+    pub fn duration(&self) -> usize {
+        self.n_frames * self.toc.frame_size
+    }
+}
+
+/// Reads a frame length (RFC 6716 Section 3.2.1), returning the length and the number of bytes
+/// consumed.
+fn parse_size(buf: &[u8]) -> Result<(usize, usize)> {
+    match buf {
+        [b0, ..] if *b0 < 252 => Ok((usize::from(*b0), 1)),
+        [b0, b1, ..] => Ok((4 * usize::from(*b1) + usize::from(*b0), 2)),
+        _ => decode_error("opus: invalid frame length"),
+    }
+}
+
+/// Parses an Opus packet into its constituent frames (RFC 6716 Section 3.2).
+///
+/// If `self_delimited` is set, the packet is expected to use the self-delimiting framing of
+/// RFC 6716 Appendix B, as used by all but the last stream of a multistream packet.
+pub fn parse_packet(buf: &[u8], self_delimited: bool) -> Result<FramePacket<'_>> {
+    if buf.is_empty() {
+        return decode_error("opus: empty packet");
+    }
+
+    let toc = Toc::new(buf[0]);
+
+    // The frame data, and the number of bytes of it that are available for frames. Padding is
+    // not available for frames.
+    let mut data = &buf[1..];
+    let mut avail = data.len();
+
+    let mut sizes = [0usize; MAX_FRAMES];
+    let mut pad = 0;
+    let mut cbr = false;
+    let mut last_size = avail;
+
+    let count = match buf[0] & 0x3 {
+        // One frame.
+        0 => 1,
+        // Two frames of equal size.
+        1 => {
+            cbr = true;
+
+            if !self_delimited {
+                if avail & 0x1 != 0 {
+                    return decode_error("opus: odd length for code 1 packet");
+                }
+
+                last_size = avail / 2;
+                sizes[0] = last_size;
+            }
+
+            2
+        }
+        // Two frames of different sizes.
+        2 => {
+            let (size, bytes) = parse_size(&data[..avail])?;
+            data = &data[bytes..];
+            avail -= bytes;
+
+            if size > avail {
+                return decode_error("opus: invalid frame length in code 2 packet");
+            }
+
+            sizes[0] = size;
+            last_size = avail - size;
+
+            2
+        }
+        // An arbitrary number of frames.
+        _ => {
+            if avail < 1 {
+                return decode_error("opus: missing frame count in code 3 packet");
+            }
+
+            let ch = data[0];
+            data = &data[1..];
+            avail -= 1;
+
+            let count = usize::from(ch & 0x3f);
+
+            if count == 0 || count * toc.frame_size > MAX_PACKET_DURATION {
+                return decode_error("opus: invalid frame count in code 3 packet");
+            }
+
+            // Bit 6 indicates the presence of padding.
+            if ch & 0x40 != 0 {
+                loop {
+                    if avail < 1 {
+                        return decode_error("opus: invalid padding in code 3 packet");
+                    }
+
+                    let p = data[0];
+                    data = &data[1..];
+                    avail -= 1;
+
+                    let len = if p == 255 { 254 } else { usize::from(p) };
+
+                    if len > avail {
+                        return decode_error("opus: invalid padding in code 3 packet");
+                    }
+
+                    avail -= len;
+                    pad += len;
+
+                    if p != 255 {
+                        break;
+                    }
+                }
+            }
+
+            // Bit 7 indicates variable bitrate.
+            cbr = ch & 0x80 == 0;
+
+            if !cbr {
+                last_size = avail;
+
+                for size in sizes[..count - 1].iter_mut() {
+                    let (len, bytes) = parse_size(&data[..avail])?;
+                    data = &data[bytes..];
+                    avail -= bytes;
+
+                    if len > avail || bytes + len > last_size {
+                        return decode_error("opus: invalid frame length in code 3 packet");
+                    }
+
+                    *size = len;
+                    last_size -= bytes + len;
+                }
+            }
+            else if !self_delimited {
+                last_size = avail / count;
+
+                if last_size * count != avail {
+                    return decode_error("opus: invalid length for code 3 cbr packet");
+                }
+
+                for size in sizes[..count - 1].iter_mut() {
+                    *size = last_size;
+                }
+            }
+
+            count
+        }
+    };
+
+    if self_delimited {
+        // Self-delimited framing has an explicit size for the last frame.
+        let (size, bytes) = parse_size(&data[..avail])?;
+        data = &data[bytes..];
+        avail -= bytes;
+
+        if size > avail {
+            return decode_error("opus: invalid self-delimited frame length");
+        }
+
+        if cbr {
+            // For CBR packets, the size applies to all frames.
+            if size * count > avail {
+                return decode_error("opus: invalid self-delimited frame length");
+            }
+
+            for s in sizes[..count].iter_mut() {
+                *s = size;
+            }
+        }
+        else if bytes + size > last_size {
+            return decode_error("opus: invalid self-delimited frame length");
+        }
+
+        sizes[count - 1] = size;
+    }
+    else {
+        // The size of the last frame is implicit, ensure it does not exceed the maximum.
+        if last_size > MAX_FRAME_BYTES {
+            return decode_error("opus: frame too large");
+        }
+
+        sizes[count - 1] = last_size;
+    }
+
+    let mut frames: [&[u8]; MAX_FRAMES] = [&[]; MAX_FRAMES];
+
+    for (frame, &size) in frames.iter_mut().zip(&sizes[..count]) {
+        let (head, tail) = data.split_at(size);
+        *frame = head;
+        data = tail;
+    }
+
+    // The packet spans up-to the end of the last frame, followed by any padding.
+    let len = buf.len() - data.len() + pad;
+
+    Ok(FramePacket { toc, frames, n_frames: count, len })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_parse_code0() {
+        // CELT-only, fullband, 20 ms, stereo, one frame.
+        let packet = parse_packet(&[0xfc, 1, 2, 3], false).unwrap();
+
+        assert_eq!(packet.toc.mode, Mode::CeltOnly);
+        assert_eq!(packet.toc.bandwidth, Bandwidth::Fullband);
+        assert_eq!(packet.toc.stream_channels, 2);
+        assert_eq!(packet.duration(), 960);
+        assert_eq!(packet.frames(), &[&[1u8, 2, 3][..]]);
+        assert_eq!(packet.len, 4);
+    }
+
+    #[test]
+    fn verify_parse_code3_padded() {
+        // SILK-only, narrowband, 10 ms, mono, two VBR frames of 1 and 2 bytes with 3 bytes of
+        // padding.
+        let buf = [0x03, 0xc2, 3, 1, 0xa, 0xb, 0xc, 0, 0, 0];
+
+        let packet = parse_packet(&buf, false).unwrap();
+
+        assert_eq!(packet.toc.mode, Mode::SilkOnly);
+        assert_eq!(packet.duration(), 960);
+        assert_eq!(packet.frames(), &[&[0xa][..], &[0xb, 0xc][..]]);
+        assert_eq!(packet.len, buf.len());
+    }
+
+    #[test]
+    fn verify_parse_self_delimited() {
+        // A self-delimited packet followed by the data of another packet.
+        let buf = [0xfc, 2, 1, 2, 0xff, 0xff];
+
+        let packet = parse_packet(&buf, true).unwrap();
+
+        assert_eq!(packet.frames(), &[&[1u8, 2][..]]);
+        assert_eq!(packet.len, 4);
+    }
+
+    #[test]
+    fn verify_parse_invalid() {
+        assert!(parse_packet(&[], false).is_err());
+        // Code 1 packets must have an even payload length.
+        assert!(parse_packet(&[0x01, 1, 2, 3], false).is_err());
+        // Code 3 packets must have a non-zero frame count.
+        assert!(parse_packet(&[0x03, 0x00], false).is_err());
+    }
+}
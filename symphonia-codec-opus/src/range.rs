@@ -0,0 +1,319 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The Opus range decoder (RFC 6716 Section 4.1).
+
+/// The number of bits output at a time.
+const SYM_BITS: u32 = 8;
+/// The total number of bits in each of the state registers.
+const CODE_BITS: u32 = 32;
+/// The maximum symbol value.
+const SYM_MAX: u32 = (1 << SYM_BITS) - 1;
+/// The carry bit of the high-order range symbol.
+const CODE_TOP: u32 = 1 << (CODE_BITS - 1);
+/// The low-order bit of the high-order range symbol.
+const CODE_BOT: u32 = CODE_TOP >> SYM_BITS;
+/// The number of bits available for the last, partial symbol in the code field.
+const CODE_EXTRA: u32 = (CODE_BITS - 2) % SYM_BITS + 1;
+/// The number of bits to use for the range-coded part of unsigned integers.
+const UINT_BITS: u32 = 8;
+/// The size of the raw bit window in bits.
+const WINDOW_SIZE: u32 = 32;
+
+/// The resolution of fractional-precision bit usage measurements, i.e., 3 => 1/8th bits.
+pub const BITRES: u32 = 3;
+
+/// Returns the number of bits required to represent `x`, or 0 if `x` is 0.
+#[inline(always)]
+pub fn ilog(x: u32) -> u32 {
+    32 - x.leading_zeros()
+}
+
+/// `RangeDecoder` decodes range-coded symbols from the front of a buffer, and raw bits from the
+/// back of the same buffer.
+pub struct RangeDecoder<'a> {
+    buf: &'a [u8],
+    /// The offset of the next range-coded byte to read.
+    offs: usize,
+    /// The number of raw bytes read from the end of the buffer.
+    end_offs: usize,
+    /// Raw bits read from the end of the buffer that are yet to be consumed.
+    end_window: u32,
+    /// The number of valid bits in `end_window`.
+    nend_bits: u32,
+    /// The total number of whole bits read.
+    nbits_total: i32,
+    /// The size of the current range.
+    rng: u32,
+    /// The difference between the top of the current range and the input value, minus one.
+    val: u32,
+    /// The saved normalization factor from the last call to `decode`.
+    ext: u32,
+    /// A buffered input symbol, awaiting carry propagation.
+    rem: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    /// Instantiate a new range decoder over `buf`.
+    pub fn new(buf: &'a [u8]) -> Self {
+        let mut dec = RangeDecoder {
+            buf,
+            offs: 0,
+            end_offs: 0,
+            end_window: 0,
+            nend_bits: 0,
+            nbits_total: (CODE_BITS + 1 - ((CODE_BITS - CODE_EXTRA) / SYM_BITS) * SYM_BITS) as i32,
+            rng: 1 << CODE_EXTRA,
+            val: 0,
+            ext: 0,
+            rem: 0,
+        };
+
+        dec.rem = dec.read_byte();
+        dec.val = dec.rng - 1 - (dec.rem >> (SYM_BITS - CODE_EXTRA));
+        dec.normalize();
+        dec
+    }
+
+    /// Gets the total size of the buffer in bytes.
+    #[inline(always)]
+    pub fn storage(&self) -> u32 {
+        self.buf.len() as u32
+    }
+
+    /// Shrinks the buffer by `n` bytes from the end. Only valid before any raw bits are read.
+    pub fn shrink(&mut self, n: usize) {
+        debug_assert!(self.end_offs == 0);
+        self.buf = &self.buf[..self.buf.len() - n];
+    }
+
+    /// Gets the final range of the decoder. Used to verify that a decoder is bit-exact with an
+    /// encoder.
+    #[inline(always)]
+    pub fn range(&self) -> u32 {
+        self.rng
+    }
+
+    fn read_byte(&mut self) -> u32 {
+        if self.offs < self.buf.len() {
+            let byte = self.buf[self.offs];
+            self.offs += 1;
+            u32::from(byte)
+        }
+        else {
+            0
+        }
+    }
+
+    fn read_byte_from_end(&mut self) -> u32 {
+        if self.end_offs < self.buf.len() {
+            self.end_offs += 1;
+            u32::from(self.buf[self.buf.len() - self.end_offs])
+        }
+        else {
+            0
+        }
+    }
+
+    /// Normalizes the contents of `val` and `rng` so that `rng` lies entirely in the high-order
+    /// symbol.
+    fn normalize(&mut self) {
+        while self.rng <= CODE_BOT {
+            self.nbits_total += SYM_BITS as i32;
+            self.rng <<= SYM_BITS;
+
+            // Use up the remaining bits from the last symbol.
+            let mut sym = self.rem;
+
+            // Read the next value from the input.
+            self.rem = self.read_byte();
+
+            // Take the rest of the bits needed from the new symbol.
+            sym = ((sym << SYM_BITS) | self.rem) >> (SYM_BITS - CODE_EXTRA);
+
+            // Subtract them from val, capped to be less than CODE_TOP.
+            self.val = ((self.val << SYM_BITS).wrapping_add(SYM_MAX & !sym)) & (CODE_TOP - 1);
+        }
+    }
+
+    /// Calculates the cumulative frequency for the next symbol given the total frequency `ft`.
+    /// Must be followed by a call to `update`.
+    pub fn decode(&mut self, ft: u32) -> u32 {
+        self.ext = self.rng / ft;
+        let s = self.val / self.ext;
+        ft - (s + 1).min(ft)
+    }
+
+    /// Equivalent to `decode` with `ft == 1 << bits`.
+    pub fn decode_bin(&mut self, bits: u32) -> u32 {
+        self.ext = self.rng >> bits;
+        let s = self.val / self.ext;
+        (1 << bits) - (s + 1).min(1 << bits)
+    }
+
+    /// Advances past the symbol that was decoded with the range `[fl, fh)` out of `ft`.
+    pub fn update(&mut self, fl: u32, fh: u32, ft: u32) {
+        let s = self.ext.wrapping_mul(ft - fh);
+        self.val = self.val.wrapping_sub(s);
+        self.rng = if fl > 0 { self.ext.wrapping_mul(fh - fl) } else { self.rng.wrapping_sub(s) };
+        self.normalize();
+    }
+
+    /// Decodes a bit that has a `1 / (1 << logp)` probability of being a one.
+    pub fn decode_bit_logp(&mut self, logp: u32) -> bool {
+        let r = self.rng;
+        let d = self.val;
+        let s = r >> logp;
+        let ret = d < s;
+
+        if !ret {
+            self.val = d - s;
+        }
+
+        self.rng = if ret { s } else { r - s };
+        self.normalize();
+        ret
+    }
+
+    /// Decodes a symbol given an inverse cumulative distribution function table with a total
+    /// frequency of `1 << ftb`.
+    pub fn decode_icdf(&mut self, icdf: &[u8], ftb: u32) -> usize {
+        let mut s = self.rng;
+        let d = self.val;
+        let r = s >> ftb;
+
+        let mut ret = 0;
+        let mut t;
+
+        loop {
+            t = s;
+            s = r.wrapping_mul(u32::from(icdf[ret]));
+            if d >= s {
+                break;
+            }
+            ret += 1;
+        }
+
+        self.val = d - s;
+        self.rng = t - s;
+        self.normalize();
+        ret
+    }
+
+    /// Decodes a uniformly distributed integer in the range `[0, ft)`.
+    pub fn decode_uint(&mut self, ft: u32) -> u32 {
+        debug_assert!(ft > 1);
+
+        let ft = ft - 1;
+        let mut ftb = ilog(ft);
+
+        if ftb > UINT_BITS {
+            ftb -= UINT_BITS;
+
+            let ft1 = (ft >> ftb) + 1;
+            let s = self.decode(ft1);
+            self.update(s, s + 1, ft1);
+
+            let t = (s << ftb) | self.decode_bits(ftb);
+
+            if t <= ft {
+                return t;
+            }
+
+            // The value is out of range, the stream is corrupt.
+            ft
+        }
+        else {
+            let ft1 = ft + 1;
+            let s = self.decode(ft1);
+            self.update(s, s + 1, ft1);
+            s
+        }
+    }
+
+    /// Decodes `bits` raw bits from the end of the buffer.
+    pub fn decode_bits(&mut self, bits: u32) -> u32 {
+        let mut window = self.end_window;
+        let mut available = self.nend_bits;
+
+        if available < bits {
+            loop {
+                window |= self.read_byte_from_end() << available;
+                available += SYM_BITS;
+
+                if available > WINDOW_SIZE - SYM_BITS {
+                    break;
+                }
+            }
+        }
+
+        let ret = if bits == 32 { window } else { window & ((1 << bits) - 1) };
+
+        window = if bits == 32 { 0 } else { window >> bits };
+        available -= bits;
+
+        self.end_window = window;
+        self.nend_bits = available;
+        self.nbits_total += bits as i32;
+        ret
+    }
+
+    /// Returns the number of bits "used" by the decoded symbols so far, rounded up to a whole
+    /// bit.
+    #[inline(always)]
+    pub fn tell(&self) -> i32 {
+        self.nbits_total - ilog(self.rng) as i32
+    }
+
+    /// Adjusts the number of bits "used" such that `tell` returns `bits`.
+    pub fn set_tell(&mut self, bits: i32) {
+        self.nbits_total += bits - self.tell();
+    }
+
+    /// Returns the number of bits "used" by the decoded symbols so far, in units of 1/8 bits.
+    pub fn tell_frac(&self) -> u32 {
+        const CORRECTION: [u32; 8] = [35733, 38967, 42495, 46340, 50535, 55109, 60097, 65535];
+
+        let nbits = (self.nbits_total as u32) << BITRES;
+        let mut l = ilog(self.rng);
+        let r = self.rng >> (l - 16);
+        let mut b = (r >> 12) - 8;
+
+        if r > CORRECTION[b as usize] {
+            b += 1;
+        }
+
+        l = (l << 3) + b;
+        nbits - l
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RangeDecoder;
+
+    #[test]
+    fn verify_empty_buffer() {
+        // An empty buffer decodes as if it was all zeros. The decoder always claims to have used
+        // one bit after initialization.
+        let mut dec = RangeDecoder::new(&[]);
+        assert_eq!(dec.tell(), 1);
+        assert_eq!(dec.tell_frac(), 8);
+        assert!(!dec.decode_bit_logp(1));
+        assert_eq!(dec.decode_bits(8), 0);
+        assert_eq!(dec.tell(), 10);
+    }
+
+    #[test]
+    fn verify_raw_bits() {
+        // Raw bits are read LSb first from the last byte of the buffer backwards.
+        let mut dec = RangeDecoder::new(&[0x00, 0x00, 0xa5, 0x3c]);
+        assert_eq!(dec.decode_bits(4), 0xc);
+        assert_eq!(dec.decode_bits(4), 0x3);
+        assert_eq!(dec.decode_bits(8), 0xa5);
+    }
+}
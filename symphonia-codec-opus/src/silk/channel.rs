@@ -0,0 +1,806 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoding of a single SILK channel (RFC 6716 Sections 4.2.7 to 4.2.7.9).
+
+use crate::range::RangeDecoder;
+
+use super::fixed::*;
+use super::lpc::{lpc_analysis_filter, nlsf2a, nlsf_decode, nlsf_unpack, NLSF_QUANT_MAX_AMPLITUDE};
+use super::resampler::Resampler;
+use super::tables::*;
+use super::{MAX_FRAME_LENGTH, MAX_LPC_ORDER, MAX_NB_SUBFR};
+
+/// The number of LTP filter taps.
+const LTP_ORDER: usize = 5;
+
+/// The length of a shell coding block.
+const SHELL_CODEC_FRAME_LENGTH: usize = 16;
+
+/// The maximum number of pulses in a shell coding block.
+const SILK_MAX_PULSES: usize = 16;
+
+/// The maximum number of shell coding blocks in a frame.
+const MAX_NB_SHELL_BLOCKS: usize = MAX_FRAME_LENGTH / SHELL_CODEC_FRAME_LENGTH;
+
+/// The number of gain quantization levels.
+const N_LEVELS_QGAIN: i32 = 64;
+
+/// The minimum and maximum delta gain indices.
+const MIN_DELTA_GAIN_QUANT: i32 = -4;
+const MAX_DELTA_GAIN_QUANT: i32 = 36;
+
+/// The offset between the gain index and log gain in Q7.
+const GAIN_OFFSET: i32 = (2 * 128) / 6 + 16 * 128;
+
+/// The log gain step size in Q16.
+const GAIN_INV_SCALE_Q16: i32 = (65536 * (((88 - 2) * 128) / 6)) / (N_LEVELS_QGAIN - 1);
+
+/// The amount pulses are moved towards zero during excitation reconstruction, in Q10.
+const QUANT_LEVEL_ADJUST_Q10: i32 = 80;
+
+/// Signal types.
+pub const TYPE_VOICED: i32 = 2;
+
+/// Conditional coding types.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum CondCoding {
+    Independently,
+    IndependentlyNoLtpScaling,
+    Conditionally,
+}
+
+/// The quantization indices of the side information of a frame.
+#[derive(Default)]
+struct SideInfoIndices {
+    gains_indices: [i32; MAX_NB_SUBFR],
+    ltp_index: [usize; MAX_NB_SUBFR],
+    nlsf_indices: [i8; MAX_LPC_ORDER + 1],
+    lag_index: i32,
+    contour_index: usize,
+    signal_type: i32,
+    quant_offset_type: i32,
+    nlsf_interp_coef_q2: i32,
+    per_index: usize,
+    ltp_scale_index: usize,
+    seed: i32,
+}
+
+/// The dequantized parameters of a frame.
+#[derive(Default)]
+struct DecoderControl {
+    pitch_l: [i32; MAX_NB_SUBFR],
+    gains_q16: [i32; MAX_NB_SUBFR],
+    pred_coef_q12: [[i16; MAX_LPC_ORDER]; 2],
+    ltp_coef_q14: [i16; LTP_ORDER * MAX_NB_SUBFR],
+    ltp_scale_q14: i32,
+}
+
+/// The decoder state of a single SILK channel.
+pub struct ChannelDecoder {
+    prev_gain_q16: i32,
+    exc_q14: [i32; MAX_FRAME_LENGTH],
+    s_lpc_q14_buf: [i32; MAX_LPC_ORDER],
+    out_buf: [i16; MAX_FRAME_LENGTH + 2 * MAX_FRAME_LENGTH / MAX_NB_SUBFR],
+    last_gain_index: i32,
+    pub fs_khz: usize,
+    pub nb_subfr: usize,
+    pub frame_length: usize,
+    subfr_length: usize,
+    ltp_mem_length: usize,
+    lpc_order: usize,
+    prev_nlsf_q15: [i16; MAX_LPC_ORDER],
+    first_frame_after_reset: bool,
+    pitch_lag_low_bits_icdf: &'static [u8],
+    pitch_contour_icdf: &'static [u8],
+    pub n_frames_decoded: usize,
+    pub n_frames_per_packet: usize,
+    ec_prev_signal_type: i32,
+    ec_prev_lag_index: i32,
+    pub vad_flags: [bool; 3],
+    pub lbrr_flag: bool,
+    pub lbrr_flags: [bool; 3],
+    pub resampler: Resampler,
+    nlsf_cb: &'static NlsfCodebook,
+    indices: SideInfoIndices,
+}
+
+impl ChannelDecoder {
+    pub fn new() -> Self {
+        ChannelDecoder {
+            prev_gain_q16: 65536,
+            exc_q14: [0; MAX_FRAME_LENGTH],
+            s_lpc_q14_buf: [0; MAX_LPC_ORDER],
+            out_buf: [0; MAX_FRAME_LENGTH + 2 * MAX_FRAME_LENGTH / MAX_NB_SUBFR],
+            last_gain_index: 0,
+            fs_khz: 0,
+            nb_subfr: 0,
+            frame_length: 0,
+            subfr_length: 0,
+            ltp_mem_length: 0,
+            lpc_order: 0,
+            prev_nlsf_q15: [0; MAX_LPC_ORDER],
+            first_frame_after_reset: true,
+            pitch_lag_low_bits_icdf: &UNIFORM4_ICDF,
+            pitch_contour_icdf: &PITCH_CONTOUR_NB_ICDF,
+            n_frames_decoded: 0,
+            n_frames_per_packet: 0,
+            ec_prev_signal_type: 0,
+            ec_prev_lag_index: 0,
+            vad_flags: [false; 3],
+            lbrr_flag: false,
+            lbrr_flags: [false; 3],
+            resampler: Resampler::new(8),
+            nlsf_cb: &NLSF_CB_NB_MB,
+            indices: Default::default(),
+        }
+    }
+
+    /// Sets the internal sampling rate, and updates the frame size for the current number of
+    /// subframes.
+    pub fn set_fs(&mut self, fs_khz: usize) {
+        self.subfr_length = 5 * fs_khz;
+
+        let frame_length = self.nb_subfr * self.subfr_length;
+
+        // Initialize the resampler when switching the internal sampling rate.
+        if self.fs_khz != fs_khz {
+            self.resampler = Resampler::new(fs_khz);
+        }
+
+        if self.fs_khz != fs_khz || frame_length != self.frame_length {
+            self.pitch_contour_icdf = match (fs_khz, self.nb_subfr == MAX_NB_SUBFR) {
+                (8, true) => &PITCH_CONTOUR_NB_ICDF,
+                (8, false) => &PITCH_CONTOUR_10MS_NB_ICDF,
+                (_, true) => &PITCH_CONTOUR_ICDF,
+                (_, false) => &PITCH_CONTOUR_10MS_ICDF,
+            };
+
+            if self.fs_khz != fs_khz {
+                self.ltp_mem_length = 20 * fs_khz;
+
+                if fs_khz == 16 {
+                    self.lpc_order = 16;
+                    self.nlsf_cb = &NLSF_CB_WB;
+                }
+                else {
+                    self.lpc_order = 10;
+                    self.nlsf_cb = &NLSF_CB_NB_MB;
+                }
+
+                self.pitch_lag_low_bits_icdf = match fs_khz {
+                    16 => &UNIFORM8_ICDF,
+                    12 => &UNIFORM6_ICDF,
+                    _ => &UNIFORM4_ICDF,
+                };
+
+                self.first_frame_after_reset = true;
+                self.last_gain_index = 10;
+                self.out_buf = [0; MAX_FRAME_LENGTH + 2 * MAX_FRAME_LENGTH / MAX_NB_SUBFR];
+                self.s_lpc_q14_buf = [0; MAX_LPC_ORDER];
+            }
+
+            self.fs_khz = fs_khz;
+            self.frame_length = frame_length;
+        }
+    }
+
+    /// Resets the prediction memory of the channel. Used when a side channel is coded after one
+    /// or more frames were not.
+    pub fn reset_prediction(&mut self) {
+        self.out_buf = [0; MAX_FRAME_LENGTH + 2 * MAX_FRAME_LENGTH / MAX_NB_SUBFR];
+        self.s_lpc_q14_buf = [0; MAX_LPC_ORDER];
+        self.last_gain_index = 10;
+        self.first_frame_after_reset = true;
+    }
+
+    /// Decodes, and discards, a LBRR frame.
+    pub fn skip_lbrr_frame(
+        &mut self,
+        rc: &mut RangeDecoder<'_>,
+        frame_index: usize,
+        cond_coding: CondCoding,
+    ) {
+        let mut pulses = [0i16; MAX_FRAME_LENGTH];
+
+        self.decode_indices(rc, frame_index, true, cond_coding);
+
+        decode_pulses(
+            rc,
+            &mut pulses,
+            self.indices.signal_type,
+            self.indices.quant_offset_type,
+            self.frame_length,
+        );
+    }
+
+    /// Decodes a frame into `out`.
+    pub fn decode_frame(
+        &mut self,
+        rc: &mut RangeDecoder<'_>,
+        out: &mut [i16],
+        cond_coding: CondCoding,
+    ) {
+        let mut ctrl = DecoderControl::default();
+        let mut pulses = [0i16; MAX_FRAME_LENGTH];
+
+        let frame_length = self.frame_length;
+
+        self.decode_indices(rc, self.n_frames_decoded, false, cond_coding);
+
+        decode_pulses(
+            rc,
+            &mut pulses,
+            self.indices.signal_type,
+            self.indices.quant_offset_type,
+            frame_length,
+        );
+
+        self.decode_parameters(&mut ctrl, cond_coding);
+
+        self.decode_core(&ctrl, &mut out[..frame_length], &pulses);
+
+        self.first_frame_after_reset = false;
+
+        // Update the output buffer.
+        let mv_len = self.ltp_mem_length - frame_length;
+
+        self.out_buf.copy_within(frame_length..frame_length + mv_len, 0);
+        self.out_buf[mv_len..mv_len + frame_length].copy_from_slice(&out[..frame_length]);
+    }
+
+    /// Decodes the side information of a frame (RFC 6716 Sections 4.2.7.3 to 4.2.7.7).
+    fn decode_indices(
+        &mut self,
+        rc: &mut RangeDecoder<'_>,
+        frame_index: usize,
+        decode_lbrr: bool,
+        cond_coding: CondCoding,
+    ) {
+        let indices = &mut self.indices;
+
+        // Decode the signal type and quantizer offset.
+        let ix = if decode_lbrr || self.vad_flags[frame_index] {
+            rc.decode_icdf(&TYPE_OFFSET_VAD_ICDF, 8) + 2
+        }
+        else {
+            rc.decode_icdf(&TYPE_OFFSET_NO_VAD_ICDF, 8)
+        };
+
+        indices.signal_type = (ix >> 1) as i32;
+        indices.quant_offset_type = (ix & 1) as i32;
+
+        // Decode the gain of the first subframe.
+        if cond_coding == CondCoding::Conditionally {
+            indices.gains_indices[0] = rc.decode_icdf(&DELTA_GAIN_ICDF, 8) as i32;
+        }
+        else {
+            // Independent coding of the MSBs followed by the 3 LSBs.
+            let gain_icdf = &GAIN_ICDF[8 * indices.signal_type as usize..][..8];
+
+            indices.gains_indices[0] = (rc.decode_icdf(gain_icdf, 8) << 3) as i32;
+            indices.gains_indices[0] += rc.decode_icdf(&UNIFORM8_ICDF, 8) as i32;
+        }
+
+        // Decode the gains of the remaining subframes.
+        for gain_index in indices.gains_indices[1..self.nb_subfr].iter_mut() {
+            *gain_index = rc.decode_icdf(&DELTA_GAIN_ICDF, 8) as i32;
+        }
+
+        // Decode the NLSF indices.
+        let cb = self.nlsf_cb;
+
+        let cb1_icdf = &cb.cb1_icdf[(indices.signal_type as usize >> 1) * cb.n_vectors..];
+
+        indices.nlsf_indices[0] = rc.decode_icdf(cb1_icdf, 8) as i8;
+
+        let mut ec_ix = [0usize; MAX_LPC_ORDER];
+        let mut pred_q8 = [0u8; MAX_LPC_ORDER];
+
+        nlsf_unpack(&mut ec_ix, &mut pred_q8, cb, indices.nlsf_indices[0] as usize);
+
+        for (i, &ec_ix) in ec_ix[..cb.order].iter().enumerate() {
+            let mut ix = rc.decode_icdf(&cb.ec_icdf[ec_ix..], 8) as i32;
+
+            if ix == 0 {
+                ix -= rc.decode_icdf(&NLSF_EXT_ICDF, 8) as i32;
+            }
+            else if ix == 2 * NLSF_QUANT_MAX_AMPLITUDE {
+                ix += rc.decode_icdf(&NLSF_EXT_ICDF, 8) as i32;
+            }
+
+            indices.nlsf_indices[i + 1] = (ix - NLSF_QUANT_MAX_AMPLITUDE) as i8;
+        }
+
+        // Decode the NLSF interpolation factor.
+        indices.nlsf_interp_coef_q2 = if self.nb_subfr == MAX_NB_SUBFR {
+            rc.decode_icdf(&NLSF_INTERP_FACTOR_ICDF, 8) as i32
+        }
+        else {
+            4
+        };
+
+        if indices.signal_type == TYPE_VOICED {
+            // Decode the pitch lag.
+            let mut decode_absolute_lag_index = true;
+
+            if cond_coding == CondCoding::Conditionally && self.ec_prev_signal_type == TYPE_VOICED
+            {
+                // Decode the delta index.
+                let delta_lag_index = rc.decode_icdf(&PITCH_DELTA_ICDF, 8) as i32;
+
+                if delta_lag_index > 0 {
+                    indices.lag_index = self.ec_prev_lag_index + delta_lag_index - 9;
+                    decode_absolute_lag_index = false;
+                }
+            }
+
+            if decode_absolute_lag_index {
+                // Absolute decoding.
+                indices.lag_index =
+                    rc.decode_icdf(&PITCH_LAG_ICDF, 8) as i32 * (self.fs_khz as i32 >> 1);
+                indices.lag_index += rc.decode_icdf(self.pitch_lag_low_bits_icdf, 8) as i32;
+            }
+
+            self.ec_prev_lag_index = indices.lag_index;
+
+            // Decode the pitch contour index.
+            indices.contour_index = rc.decode_icdf(self.pitch_contour_icdf, 8);
+
+            // Decode the LTP filter indices.
+            indices.per_index = rc.decode_icdf(&LTP_PER_INDEX_ICDF, 8);
+
+            for ltp_index in indices.ltp_index[..self.nb_subfr].iter_mut() {
+                *ltp_index = rc.decode_icdf(LTP_GAIN_ICDF[indices.per_index], 8);
+            }
+
+            // Decode the LTP scaling index.
+            indices.ltp_scale_index = if cond_coding == CondCoding::Independently {
+                rc.decode_icdf(&LTP_SCALE_ICDF, 8)
+            }
+            else {
+                0
+            };
+        }
+
+        self.ec_prev_signal_type = indices.signal_type;
+
+        // Decode the LCG seed.
+        indices.seed = rc.decode_icdf(&UNIFORM4_ICDF, 8) as i32;
+    }
+
+    /// Dequantizes the side information of a frame.
+    fn decode_parameters(&mut self, ctrl: &mut DecoderControl, cond_coding: CondCoding) {
+        let order = self.lpc_order;
+
+        // Dequantize the gains.
+        gains_dequant(
+            &mut ctrl.gains_q16[..self.nb_subfr],
+            &self.indices.gains_indices,
+            &mut self.last_gain_index,
+            cond_coding == CondCoding::Conditionally,
+        );
+
+        // Decode the NLSFs, and convert them to LPC coefficients.
+        let mut nlsf_q15 = [0i16; MAX_LPC_ORDER];
+
+        nlsf_decode(&mut nlsf_q15, &self.indices.nlsf_indices, self.nlsf_cb);
+
+        nlsf2a(&mut ctrl.pred_coef_q12[1][..order], &nlsf_q15[..order]);
+
+        // If the decoder was just reset, do not allow interpolation. This improves the case of
+        // packet loss in the first frame after a switch.
+        if self.first_frame_after_reset {
+            self.indices.nlsf_interp_coef_q2 = 4;
+        }
+
+        if self.indices.nlsf_interp_coef_q2 < 4 {
+            // Calculate the interpolated NLSFs for the first half of the frame from the
+            // interpolation factor, the previous NLSFs, and the current NLSFs.
+            let mut nlsf0_q15 = [0i16; MAX_LPC_ORDER];
+
+            for i in 0..order {
+                let prev = i32::from(self.prev_nlsf_q15[i]);
+                let diff = i32::from(nlsf_q15[i]) - prev;
+
+                nlsf0_q15[i] = (prev + ((self.indices.nlsf_interp_coef_q2 * diff) >> 2)) as i16;
+            }
+
+            nlsf2a(&mut ctrl.pred_coef_q12[0][..order], &nlsf0_q15[..order]);
+        }
+        else {
+            // Copy the LPC coefficients of the second half to the first half.
+            ctrl.pred_coef_q12[0] = ctrl.pred_coef_q12[1];
+        }
+
+        self.prev_nlsf_q15 = nlsf_q15;
+
+        if self.indices.signal_type == TYPE_VOICED {
+            // Decode the pitch lags.
+            decode_pitch(
+                self.indices.lag_index,
+                self.indices.contour_index,
+                &mut ctrl.pitch_l,
+                self.fs_khz,
+                self.nb_subfr,
+            );
+
+            // Decode the LTP filter taps.
+            let cbk_q7 = LTP_GAIN_VQ_Q7[self.indices.per_index];
+
+            for k in 0..self.nb_subfr {
+                let ix = self.indices.ltp_index[k];
+
+                for i in 0..LTP_ORDER {
+                    ctrl.ltp_coef_q14[k * LTP_ORDER + i] =
+                        i16::from(cbk_q7[ix * LTP_ORDER + i]) << 7;
+                }
+            }
+
+            // Decode the LTP scaling.
+            ctrl.ltp_scale_q14 = i32::from(LTP_SCALES_Q14[self.indices.ltp_scale_index]);
+        }
+        else {
+            ctrl.pitch_l = [0; MAX_NB_SUBFR];
+            ctrl.ltp_coef_q14 = [0; LTP_ORDER * MAX_NB_SUBFR];
+            self.indices.per_index = 0;
+            ctrl.ltp_scale_q14 = 0;
+        }
+    }
+
+    /// Reconstructs the excitation, and runs the LTP and LPC synthesis filters.
+    fn decode_core(&mut self, ctrl: &DecoderControl, xq: &mut [i16], pulses: &[i16]) {
+        let subfr_length = self.subfr_length;
+        let ltp_mem_length = self.ltp_mem_length;
+        let order = self.lpc_order;
+
+        let mut s_ltp = [0i16; MAX_FRAME_LENGTH];
+        let mut s_ltp_q15 = [0i32; 2 * MAX_FRAME_LENGTH];
+        let mut res_q14 = [0i32; MAX_FRAME_LENGTH / MAX_NB_SUBFR];
+        let mut s_lpc_q14 = [0i32; MAX_FRAME_LENGTH / MAX_NB_SUBFR + MAX_LPC_ORDER];
+
+        let offset_q10 = QUANTIZATION_OFFSETS_Q10[self.indices.signal_type as usize >> 1]
+            [self.indices.quant_offset_type as usize];
+
+        let nlsf_interpolation = self.indices.nlsf_interp_coef_q2 < 4;
+
+        // Decode the excitation.
+        let mut rand_seed = self.indices.seed;
+
+        for (exc, &pulse) in self.exc_q14[..self.frame_length].iter_mut().zip(pulses) {
+            rand_seed = rand(rand_seed);
+
+            *exc = i32::from(pulse) << 14;
+
+            if *exc > 0 {
+                *exc -= QUANT_LEVEL_ADJUST_Q10 << 4;
+            }
+            else if *exc < 0 {
+                *exc += QUANT_LEVEL_ADJUST_Q10 << 4;
+            }
+
+            *exc += offset_q10 << 4;
+
+            if rand_seed < 0 {
+                *exc = -*exc;
+            }
+
+            rand_seed = rand_seed.wrapping_add(i32::from(pulse));
+        }
+
+        // Copy the LPC state.
+        s_lpc_q14[..MAX_LPC_ORDER].copy_from_slice(&self.s_lpc_q14_buf);
+
+        let mut s_ltp_buf_idx = ltp_mem_length;
+
+        for k in 0..self.nb_subfr {
+            let a_q12 = &ctrl.pred_coef_q12[k >> 1][..order];
+            let b_q14 = &ctrl.ltp_coef_q14[k * LTP_ORDER..][..LTP_ORDER];
+            let gains_q16 = ctrl.gains_q16[k];
+
+            let gain_q10 = gains_q16 >> 6;
+            let mut inv_gain_q31 = inverse32_varq(gains_q16, 47);
+
+            // Calculate the gain adjustment factor.
+            let gain_adj_q16 = if gains_q16 != self.prev_gain_q16 {
+                let gain_adj_q16 = div32_varq(self.prev_gain_q16, gains_q16, 16);
+
+                // Scale the short-term state.
+                for s in s_lpc_q14[..MAX_LPC_ORDER].iter_mut() {
+                    *s = smulww(gain_adj_q16, *s);
+                }
+
+                gain_adj_q16
+            }
+            else {
+                1 << 16
+            };
+
+            self.prev_gain_q16 = gains_q16;
+
+            let exc_q14 = &self.exc_q14[k * subfr_length..][..subfr_length];
+
+            if self.indices.signal_type == TYPE_VOICED {
+                let lag = ctrl.pitch_l[k] as usize;
+
+                // Re-whitening.
+                if k == 0 || (k == 2 && nlsf_interpolation) {
+                    // Re-whiten with the new LPC coefficients.
+                    let start_idx = ltp_mem_length - lag - order - LTP_ORDER / 2;
+
+                    if k == 2 {
+                        self.out_buf[ltp_mem_length..ltp_mem_length + 2 * subfr_length]
+                            .copy_from_slice(&xq[..2 * subfr_length]);
+                    }
+
+                    lpc_analysis_filter(
+                        &mut s_ltp[start_idx..],
+                        &self.out_buf[start_idx + k * subfr_length..],
+                        a_q12,
+                        ltp_mem_length - start_idx,
+                    );
+
+                    // After re-whitening the LTP state is unscaled.
+                    if k == 0 {
+                        // Do LTP downscaling to reduce inter-packet dependency.
+                        inv_gain_q31 = smulwb(inv_gain_q31, ctrl.ltp_scale_q14) << 2;
+                    }
+
+                    for i in 0..lag + LTP_ORDER / 2 {
+                        s_ltp_q15[s_ltp_buf_idx - i - 1] =
+                            smulwb(inv_gain_q31, i32::from(s_ltp[ltp_mem_length - i - 1]));
+                    }
+                }
+                else if gain_adj_q16 != 1 << 16 {
+                    // Update the LTP state when the gain changes.
+                    for i in 0..lag + LTP_ORDER / 2 {
+                        let s = &mut s_ltp_q15[s_ltp_buf_idx - i - 1];
+                        *s = smulww(gain_adj_q16, *s);
+                    }
+                }
+
+                // Long-term prediction.
+                let pred_lag_start = s_ltp_buf_idx - lag + LTP_ORDER / 2;
+
+                for i in 0..subfr_length {
+                    let pred_lag_idx = pred_lag_start + i;
+
+                    // Start at 2 to avoid a bias because smlawb always rounds to -inf.
+                    let mut ltp_pred_q13 = 2;
+
+                    for (j, &b) in b_q14.iter().enumerate() {
+                        ltp_pred_q13 =
+                            smlawb(ltp_pred_q13, s_ltp_q15[pred_lag_idx - j], i32::from(b));
+                    }
+
+                    // Generate the LPC excitation.
+                    res_q14[i] = exc_q14[i].wrapping_add(ltp_pred_q13 << 1);
+
+                    // Update the LTP state.
+                    s_ltp_q15[s_ltp_buf_idx] = res_q14[i] << 1;
+                    s_ltp_buf_idx += 1;
+                }
+            }
+            else {
+                res_q14[..subfr_length].copy_from_slice(exc_q14);
+            }
+
+            // Short-term prediction.
+            for i in 0..subfr_length {
+                // Start at order / 2 to avoid a bias because smlawb always rounds to -inf.
+                let mut lpc_pred_q10 = (order >> 1) as i32;
+
+                for (j, &a) in a_q12.iter().enumerate() {
+                    lpc_pred_q10 =
+                        smlawb(lpc_pred_q10, s_lpc_q14[MAX_LPC_ORDER + i - j - 1], i32::from(a));
+                }
+
+                // Add the prediction to the LPC excitation.
+                s_lpc_q14[MAX_LPC_ORDER + i] =
+                    res_q14[i].saturating_add(lshift_sat32(lpc_pred_q10, 4));
+
+                // Scale with the gain.
+                xq[k * subfr_length + i] =
+                    sat16(rshift_round(smulww(s_lpc_q14[MAX_LPC_ORDER + i], gain_q10), 8)) as i16;
+            }
+
+            // Update the LPC filter state.
+            s_lpc_q14.copy_within(subfr_length..subfr_length + MAX_LPC_ORDER, 0);
+        }
+
+        // Save the LPC state.
+        self.s_lpc_q14_buf.copy_from_slice(&s_lpc_q14[..MAX_LPC_ORDER]);
+    }
+}
+
+/// Dequantizes the subframe gains (RFC 6716 Section 4.2.7.4).
+fn gains_dequant(gain_q16: &mut [i32], ind: &[i32], prev_ind: &mut i32, conditional: bool) {
+    for (k, gain) in gain_q16.iter_mut().enumerate() {
+        if k == 0 && !conditional {
+            // The gain index may not go down more than 16 steps (~21.8 dB).
+            *prev_ind = ind[k].max(*prev_ind - 16);
+        }
+        else {
+            // Delta index.
+            let ind_tmp = ind[k] + MIN_DELTA_GAIN_QUANT;
+
+            // Accumulate deltas.
+            let double_step_size_threshold =
+                2 * MAX_DELTA_GAIN_QUANT - N_LEVELS_QGAIN + *prev_ind;
+
+            if ind_tmp > double_step_size_threshold {
+                *prev_ind += (ind_tmp << 1) - double_step_size_threshold;
+            }
+            else {
+                *prev_ind += ind_tmp;
+            }
+        }
+
+        *prev_ind = (*prev_ind).clamp(0, N_LEVELS_QGAIN - 1);
+
+        // Scale and convert to the linear domain.
+        *gain = log2lin((smulwb(GAIN_INV_SCALE_Q16, *prev_ind) + GAIN_OFFSET).min(3967));
+    }
+}
+
+/// Decodes the pitch lag of each subframe from the lag index and contour index (RFC 6716 Section
+/// 4.2.7.6.1).
+fn decode_pitch(
+    lag_index: i32,
+    contour_index: usize,
+    pitch_lags: &mut [i32],
+    fs_khz: usize,
+    nb_subfr: usize,
+) {
+    let (lag_cb, cbk_size): (&[i8], usize) = match (fs_khz, nb_subfr == MAX_NB_SUBFR) {
+        (8, true) => (&CB_LAGS_STAGE2, 11),
+        (8, false) => (&CB_LAGS_STAGE2_10MS, 3),
+        (_, true) => (&CB_LAGS_STAGE3, 34),
+        (_, false) => (&CB_LAGS_STAGE3_10MS, 12),
+    };
+
+    let min_lag = 2 * fs_khz as i32;
+    let max_lag = 18 * fs_khz as i32;
+
+    let lag = min_lag + lag_index;
+
+    for (k, pitch_lag) in pitch_lags[..nb_subfr].iter_mut().enumerate() {
+        let lag = lag + i32::from(lag_cb[k * cbk_size + contour_index]);
+        *pitch_lag = lag.max(min_lag).min(max_lag);
+    }
+}
+
+/// Decodes the split of pulses between two halves of a partition.
+fn decode_split(rc: &mut RangeDecoder<'_>, p: i16, shell_table: &[u8]) -> (i16, i16) {
+    if p > 0 {
+        let offset = usize::from(SHELL_CODE_TABLE_OFFSETS[p as usize]);
+        let child1 = rc.decode_icdf(&shell_table[offset..], 8) as i16;
+        (child1, p - child1)
+    }
+    else {
+        (0, 0)
+    }
+}
+
+/// Decodes the pulse amplitudes of a shell coding block given the total number of pulses
+/// (RFC 6716 Section 4.2.7.8.3).
+fn shell_decoder(pulses0: &mut [i16], rc: &mut RangeDecoder<'_>, pulses4: i16) {
+    let mut pulses2 = [0i16; 4];
+    let mut pulses1 = [0i16; 8];
+
+    let t0 = &SHELL_CODE_TABLE0;
+    let t1 = &SHELL_CODE_TABLE1;
+    let t2 = &SHELL_CODE_TABLE2;
+    let t3 = &SHELL_CODE_TABLE3;
+
+    // The partitions are decoded depth-first.
+    let (a, b) = decode_split(rc, pulses4, t3);
+    let pulses3 = [a, b];
+
+    for i in 0..2 {
+        let (a, b) = decode_split(rc, pulses3[i], t2);
+        pulses2[2 * i] = a;
+        pulses2[2 * i + 1] = b;
+
+        for j in 2 * i..2 * i + 2 {
+            let (a, b) = decode_split(rc, pulses2[j], t1);
+            pulses1[2 * j] = a;
+            pulses1[2 * j + 1] = b;
+
+            for k in 2 * j..2 * j + 2 {
+                let (a, b) = decode_split(rc, pulses1[k], t0);
+                pulses0[2 * k] = a;
+                pulses0[2 * k + 1] = b;
+            }
+        }
+    }
+}
+
+/// Decodes the excitation pulses of a frame (RFC 6716 Section 4.2.7.8).
+fn decode_pulses(
+    rc: &mut RangeDecoder<'_>,
+    pulses: &mut [i16],
+    signal_type: i32,
+    quant_offset_type: i32,
+    frame_length: usize,
+) {
+    let mut sum_pulses = [0usize; MAX_NB_SHELL_BLOCKS];
+    let mut n_lshifts = [0usize; MAX_NB_SHELL_BLOCKS];
+
+    // Decode the rate level.
+    let rate_level_index =
+        rc.decode_icdf(&RATE_LEVELS_ICDF[9 * (signal_type as usize >> 1)..][..9], 8);
+
+    // Calculate the number of shell blocks. A 10 ms frame at 12 kHz is not a multiple of the
+    // shell block size, and therefore the last block is partially used.
+    let iter = frame_length.div_ceil(SHELL_CODEC_FRAME_LENGTH);
+
+    // Decode the sum of the pulses in each block.
+    let cdf = &PULSES_PER_BLOCK_ICDF[18 * rate_level_index..][..18];
+
+    for i in 0..iter {
+        sum_pulses[i] = rc.decode_icdf(cdf, 8);
+
+        // A sum of SILK_MAX_PULSES + 1 indicates an additional LSB.
+        while sum_pulses[i] == SILK_MAX_PULSES + 1 {
+            n_lshifts[i] += 1;
+
+            // After 10 LSBs, shift the table to not allow SILK_MAX_PULSES + 1.
+            let cdf = &PULSES_PER_BLOCK_ICDF[18 * 9 + usize::from(n_lshifts[i] == 10)..];
+            sum_pulses[i] = rc.decode_icdf(cdf, 8);
+        }
+    }
+
+    // Shell decoding.
+    for (i, block) in pulses.chunks_exact_mut(SHELL_CODEC_FRAME_LENGTH).take(iter).enumerate() {
+        if sum_pulses[i] > 0 {
+            shell_decoder(block, rc, sum_pulses[i] as i16);
+        }
+        else {
+            for pulse in block.iter_mut() {
+                *pulse = 0;
+            }
+        }
+    }
+
+    // LSB decoding.
+    for (i, block) in pulses.chunks_exact_mut(SHELL_CODEC_FRAME_LENGTH).take(iter).enumerate() {
+        let n_ls = n_lshifts[i];
+
+        if n_ls > 0 {
+            for pulse in block.iter_mut() {
+                let mut abs_q = i32::from(*pulse);
+
+                for _ in 0..n_ls {
+                    abs_q = (abs_q << 1) + rc.decode_icdf(&LSB_ICDF, 8) as i32;
+                }
+
+                *pulse = abs_q as i16;
+            }
+
+            // Mark the number of pulses as non-zero for sign decoding.
+            sum_pulses[i] |= n_ls << 5;
+        }
+    }
+
+    // Decode and apply the signs of the pulses.
+    let sign_icdf = &SIGN_ICDF[7 * (quant_offset_type + (signal_type << 1)) as usize..];
+
+    for (i, block) in pulses.chunks_exact_mut(SHELL_CODEC_FRAME_LENGTH).take(iter).enumerate() {
+        let p = sum_pulses[i];
+
+        if p > 0 {
+            let icdf = [sign_icdf[(p & 0x1f).min(6)], 0];
+
+            for pulse in block.iter_mut() {
+                if *pulse > 0 && rc.decode_icdf(&icdf, 8) == 0 {
+                    *pulse = -*pulse;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fixed-point arithmetic primitives used by SILK.
+//!
+//! SILK is specified in terms of the fixed-point reference implementation, therefore these
+//! primitives must exactly reproduce its rounding and truncation behaviour. The naming follows
+//! the reference: `W` denotes a 32-bit operand, and `B` the bottom 16 bits of an operand.
+
+/// (a * (b as i16)) >> 16
+#[inline(always)]
+pub fn smulwb(a: i32, b: i32) -> i32 {
+    ((i64::from(a) * i64::from(b as i16)) >> 16) as i32
+}
+
+/// a + ((b * (c as i16)) >> 16)
+#[inline(always)]
+pub fn smlawb(a: i32, b: i32, c: i32) -> i32 {
+    a.wrapping_add(smulwb(b, c))
+}
+
+/// (a as i16) * (b as i16)
+#[inline(always)]
+pub fn smulbb(a: i32, b: i32) -> i32 {
+    i32::from(a as i16) * i32::from(b as i16)
+}
+
+/// a + (b as i16) * (c as i16)
+#[inline(always)]
+pub fn smlabb(a: i32, b: i32, c: i32) -> i32 {
+    a.wrapping_add(smulbb(b, c))
+}
+
+/// (a * b) >> 16
+#[inline(always)]
+pub fn smulww(a: i32, b: i32) -> i32 {
+    ((i64::from(a) * i64::from(b)) >> 16) as i32
+}
+
+/// a + ((b * c) >> 16)
+#[inline(always)]
+pub fn smlaww(a: i32, b: i32, c: i32) -> i32 {
+    a.wrapping_add(smulww(b, c))
+}
+
+/// (a * b) >> 32
+#[inline(always)]
+pub fn smmul(a: i32, b: i32) -> i32 {
+    ((i64::from(a) * i64::from(b)) >> 32) as i32
+}
+
+/// Arithmetic right shift with rounding.
+#[inline(always)]
+pub fn rshift_round(a: i32, shift: u32) -> i32 {
+    if shift == 1 {
+        (a >> 1) + (a & 1)
+    }
+    else {
+        ((a >> (shift - 1)) + 1) >> 1
+    }
+}
+
+/// Arithmetic right shift with rounding of a 64-bit value.
+#[inline(always)]
+pub fn rshift_round64(a: i64, shift: u32) -> i64 {
+    if shift == 1 {
+        (a >> 1) + (a & 1)
+    }
+    else {
+        ((a >> (shift - 1)) + 1) >> 1
+    }
+}
+
+/// Left shift with saturation.
+#[inline(always)]
+pub fn lshift_sat32(a: i32, shift: u32) -> i32 {
+    a.max(i32::MIN >> shift).min(i32::MAX >> shift) << shift
+}
+
+/// Saturate to the range of a 16-bit signed integer.
+#[inline(always)]
+pub fn sat16(a: i32) -> i32 {
+    a.max(i32::from(i16::MIN)).min(i32::from(i16::MAX))
+}
+
+/// The SILK linear congruential pseudo-random number generator.
+#[inline(always)]
+pub fn rand(seed: i32) -> i32 {
+    907_633_515i32.wrapping_add(seed.wrapping_mul(196_314_165))
+}
+
+/// Count the leading zeros of a 32-bit integer.
+#[inline(always)]
+pub fn clz32(a: i32) -> i32 {
+    a.leading_zeros() as i32
+}
+
+/// Returns an approximation of `(1 << q_res) / b`.
+pub fn inverse32_varq(b: i32, q_res: i32) -> i32 {
+    // Compute the number of bits of headroom, and normalize the input.
+    let b_headrm = clz32(b.abs()) - 1;
+    let b_nrm = b << b_headrm;
+
+    // Inverse of b, with 14 bits of precision.
+    let b_inv = (i32::MAX >> 2) / (b_nrm >> 16);
+
+    // First approximation.
+    let result = b_inv << 16;
+
+    // Compute the residual by subtracting the product of the denominator and the first
+    // approximation from one.
+    let err_q32 = ((1 << 29) - smulwb(b_nrm, b_inv)) << 3;
+
+    // Refinement.
+    let result = smlaww(result, err_q32, b_inv);
+
+    // Convert to the Q-domain of the result.
+    let lshift = 61 - b_headrm - q_res;
+
+    if lshift <= 0 {
+        lshift_sat32(result, -lshift as u32)
+    }
+    else if lshift < 32 {
+        result >> lshift
+    }
+    else {
+        0
+    }
+}
+
+/// Returns an approximation of `(a << q_res) / b`.
+pub fn div32_varq(a: i32, b: i32, q_res: i32) -> i32 {
+    // Compute the number of bits of headroom, and normalize the inputs.
+    let a_headrm = clz32(a.abs()) - 1;
+    let a_nrm = a << a_headrm;
+    let b_headrm = clz32(b.abs()) - 1;
+    let b_nrm = b << b_headrm;
+
+    // Inverse of b, with 14 bits of precision.
+    let b_inv = (i32::MAX >> 2) / (b_nrm >> 16);
+
+    // First approximation.
+    let result = smulwb(a_nrm, b_inv);
+
+    // Compute the residual by subtracting the product of the denominator and the first
+    // approximation. Overflow is allowed because the final value of a_nrm is always small.
+    let a_nrm = a_nrm.wrapping_sub(smmul(b_nrm, result).wrapping_shl(3));
+
+    // Refinement.
+    let result = smlawb(result, a_nrm, b_inv);
+
+    // Convert to the Q-domain of the result.
+    let lshift = 29 + a_headrm - b_headrm - q_res;
+
+    if lshift < 0 {
+        lshift_sat32(result, -lshift as u32)
+    }
+    else if lshift < 32 {
+        result >> lshift
+    }
+    else {
+        0
+    }
+}
+
+/// Approximation of 2^(x / 128) for a value x in Q7.
+pub fn log2lin(in_log_q7: i32) -> i32 {
+    if in_log_q7 < 0 {
+        return 0;
+    }
+    else if in_log_q7 >= 3967 {
+        return i32::MAX;
+    }
+
+    let out = 1 << (in_log_q7 >> 7);
+    let frac_q7 = in_log_q7 & 0x7f;
+
+    // Piece-wise parabolic approximation.
+    let frac = smlawb(frac_q7, smulbb(frac_q7, 128 - frac_q7), -174);
+
+    if in_log_q7 < 2048 {
+        out + ((out * frac) >> 7)
+    }
+    else {
+        out + (out >> 7) * frac
+    }
+}
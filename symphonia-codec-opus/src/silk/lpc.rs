@@ -0,0 +1,480 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Normalized line spectral frequency (NLSF) decoding, and the conversion of NLSFs to linear
+//! prediction coefficients (RFC 6716 Sections 4.2.7.5.3 to 4.2.7.5.8).
+
+use super::fixed::*;
+use super::tables::{NlsfCodebook, LSF_COS_TAB_Q12};
+use super::MAX_LPC_ORDER;
+
+/// The maximum absolute value of a stage 2 NLSF index before an extension is coded.
+pub const NLSF_QUANT_MAX_AMPLITUDE: i32 = 4;
+
+/// The maximum number of iterations of the NLSF stabilizer before falling back to sorting.
+const MAX_STABILIZE_LOOPS: usize = 20;
+
+/// The maximum number of bandwidth expansion iterations applied to unstable LPC filters.
+const MAX_LPC_STABILIZE_ITERATIONS: u32 = 16;
+
+/// The Q-domain of the intermediate polynomials in NLSF to LPC conversion.
+const QA: u32 = 16;
+
+/// Unpacks the stage 2 entropy coding table offsets, and prediction weights, for a stage 1
+/// codebook vector.
+pub fn nlsf_unpack(
+    ec_ix: &mut [usize],
+    pred_q8: &mut [u8],
+    cb: &NlsfCodebook,
+    cb1_index: usize,
+) {
+    let order = cb.order;
+    let ec_sel = &cb.ec_sel[cb1_index * order / 2..];
+
+    for (i, &entry) in (0..order).step_by(2).zip(ec_sel) {
+        let entry = usize::from(entry);
+
+        ec_ix[i] = ((entry >> 1) & 7) * (2 * NLSF_QUANT_MAX_AMPLITUDE as usize + 1);
+        pred_q8[i] = cb.pred_q8[i + (entry & 1) * (order - 1)];
+        ec_ix[i + 1] = ((entry >> 5) & 7) * (2 * NLSF_QUANT_MAX_AMPLITUDE as usize + 1);
+        pred_q8[i + 1] = cb.pred_q8[i + ((entry >> 4) & 1) * (order - 1) + 1];
+    }
+}
+
+/// Dequantizes the stage 2 residuals using backwards prediction.
+fn nlsf_residual_dequant(
+    x_q10: &mut [i32],
+    indices: &[i8],
+    pred_coef_q8: &[u8],
+    quant_step_size_q16: i32,
+    order: usize,
+) {
+    // NLSF_QUANT_LEVEL_ADJ (0.1) in Q10.
+    const LEVEL_ADJ_Q10: i32 = 102;
+
+    let mut out_q10 = 0;
+
+    for i in (0..order).rev() {
+        let pred_q10 = smulbb(out_q10, i32::from(pred_coef_q8[i])) >> 8;
+
+        out_q10 = i32::from(indices[i]) << 10;
+
+        if out_q10 > 0 {
+            out_q10 -= LEVEL_ADJ_Q10;
+        }
+        else if out_q10 < 0 {
+            out_q10 += LEVEL_ADJ_Q10;
+        }
+
+        out_q10 = smlawb(pred_q10, out_q10, quant_step_size_q16);
+        x_q10[i] = out_q10;
+    }
+}
+
+/// Decodes a NLSF vector from its stage 1 and stage 2 indices.
+pub fn nlsf_decode(nlsf_q15: &mut [i16], nlsf_indices: &[i8], cb: &NlsfCodebook) {
+    let order = cb.order;
+    let cb1_index = nlsf_indices[0] as usize;
+
+    let mut pred_q8 = [0u8; MAX_LPC_ORDER];
+    let mut ec_ix = [0usize; MAX_LPC_ORDER];
+    let mut res_q10 = [0i32; MAX_LPC_ORDER];
+
+    nlsf_unpack(&mut ec_ix, &mut pred_q8, cb, cb1_index);
+
+    let step_q16 = cb.quant_step_size_q16;
+
+    nlsf_residual_dequant(&mut res_q10, &nlsf_indices[1..], &pred_q8, step_q16, order);
+
+    // Apply the inverse square-rooted weights to the residual, and add the stage 1 vector.
+    let cb_element = &cb.cb1_nlsf_q8[cb1_index * order..][..order];
+    let cb_wght_q9 = &cb.cb1_wght_q9[cb1_index * order..][..order];
+
+    for i in 0..order {
+        let nlsf = (res_q10[i] << 14) / i32::from(cb_wght_q9[i]) + (i32::from(cb_element[i]) << 7);
+        nlsf_q15[i] = nlsf.clamp(0, 32767) as i16;
+    }
+
+    nlsf_stabilize(&mut nlsf_q15[..order], cb.delta_min_q15);
+}
+
+/// Ensures the NLSFs are ordered, and are separated by at least the minimum spacing.
+fn nlsf_stabilize(nlsf_q15: &mut [i16], delta_min_q15: &[i16]) {
+    let l = nlsf_q15.len();
+
+    for _ in 0..MAX_STABILIZE_LOOPS {
+        // Find the smallest distance.
+        let mut min_diff_q15 = i32::from(nlsf_q15[0]) - i32::from(delta_min_q15[0]);
+        let mut idx = 0;
+
+        for i in 1..l {
+            let diff_q15 = i32::from(nlsf_q15[i])
+                - (i32::from(nlsf_q15[i - 1]) + i32::from(delta_min_q15[i]));
+
+            if diff_q15 < min_diff_q15 {
+                min_diff_q15 = diff_q15;
+                idx = i;
+            }
+        }
+
+        let diff_q15 = (1 << 15) - (i32::from(nlsf_q15[l - 1]) + i32::from(delta_min_q15[l]));
+
+        if diff_q15 < min_diff_q15 {
+            min_diff_q15 = diff_q15;
+            idx = l;
+        }
+
+        // If the smallest distance is non-negative, the NLSFs are stable.
+        if min_diff_q15 >= 0 {
+            return;
+        }
+
+        if idx == 0 {
+            // Move away from the lower limit.
+            nlsf_q15[0] = delta_min_q15[0];
+        }
+        else if idx == l {
+            // Move away from the upper limit.
+            nlsf_q15[l - 1] = ((1 << 15) - i32::from(delta_min_q15[l])) as i16;
+        }
+        else {
+            // Find the lower extreme for the location of the current center frequency.
+            let mut min_center_q15 = 0;
+
+            for &delta_min in &delta_min_q15[..idx] {
+                min_center_q15 += i32::from(delta_min);
+            }
+
+            min_center_q15 += i32::from(delta_min_q15[idx]) >> 1;
+
+            // Find the upper extreme for the location of the current center frequency.
+            let mut max_center_q15 = 1 << 15;
+
+            for &delta_min in &delta_min_q15[idx + 1..=l] {
+                max_center_q15 -= i32::from(delta_min);
+            }
+
+            max_center_q15 -= i32::from(delta_min_q15[idx]) >> 1;
+
+            // Move apart, sorted by value, while keeping the same center frequency.
+            let center =
+                rshift_round(i32::from(nlsf_q15[idx - 1]) + i32::from(nlsf_q15[idx]), 1);
+
+            let center_freq_q15 = if min_center_q15 > max_center_q15 {
+                center.max(max_center_q15).min(min_center_q15)
+            }
+            else {
+                center.max(min_center_q15).min(max_center_q15)
+            };
+
+            let lower = center_freq_q15 - (i32::from(delta_min_q15[idx]) >> 1);
+
+            nlsf_q15[idx - 1] = lower as i16;
+            nlsf_q15[idx] = (lower + i32::from(delta_min_q15[idx])) as i16;
+        }
+    }
+
+    // Fall back to a simple, but less ideal, method.
+    nlsf_q15.sort_unstable();
+
+    // The first NLSF should be no less than the minimum spacing.
+    nlsf_q15[0] = nlsf_q15[0].max(delta_min_q15[0]);
+
+    // Keep the minimum spacing between the NLSFs.
+    for i in 1..l {
+        let min = sat16(i32::from(nlsf_q15[i - 1]) + i32::from(delta_min_q15[i])) as i16;
+        nlsf_q15[i] = nlsf_q15[i].max(min);
+    }
+
+    // The last NLSF should be no higher than 1 minus the minimum spacing.
+    nlsf_q15[l - 1] = nlsf_q15[l - 1].min(((1 << 15) - i32::from(delta_min_q15[l])) as i16);
+
+    // Keep the minimum spacing between the NLSFs.
+    for i in (0..l - 1).rev() {
+        let max = i32::from(nlsf_q15[i + 1]) - i32::from(delta_min_q15[i + 1]);
+        nlsf_q15[i] = i32::from(nlsf_q15[i]).min(max) as i16;
+    }
+}
+
+/// Computes one of the two polynomials, P or Q, from the interleaved cosines of the NLSFs.
+fn nlsf2a_find_poly(out: &mut [i32], c_lsf: &[i32], dd: usize) {
+    out[0] = 1 << QA;
+    out[1] = -c_lsf[0];
+
+    for k in 1..dd {
+        let ftmp = i64::from(c_lsf[2 * k]);
+
+        out[k + 1] = (out[k - 1] << 1) - rshift_round64(ftmp * i64::from(out[k]), QA) as i32;
+
+        for n in (2..=k).rev() {
+            out[n] += out[n - 2] - rshift_round64(ftmp * i64::from(out[n - 1]), QA) as i32;
+        }
+
+        out[1] -= ftmp as i32;
+    }
+}
+
+/// Converts NLSFs in Q15 to a monic whitening filter with coefficients in Q12.
+pub fn nlsf2a(a_q12: &mut [i16], nlsf: &[i16]) {
+    // These orderings were found to maximize the numerical accuracy of the polynomial
+    // computation.
+    const ORDERING16: [usize; 16] = [0, 15, 8, 7, 4, 11, 12, 3, 2, 13, 10, 5, 6, 9, 14, 1];
+    const ORDERING10: [usize; 10] = [0, 9, 6, 3, 4, 5, 8, 1, 2, 7];
+
+    let d = nlsf.len();
+
+    let ordering: &[usize] = if d == 16 { &ORDERING16 } else { &ORDERING10 };
+
+    let mut cos_lsf_qa = [0i32; MAX_LPC_ORDER];
+
+    // Convert the LSFs to 2*cos(LSF) using a piecewise linear approximation.
+    for (&lsf, &pos) in nlsf.iter().zip(ordering) {
+        let lsf = i32::from(lsf);
+
+        // The integer part, range 0 to 127.
+        let f_int = (lsf >> (15 - 7)) as usize;
+        // The fractional part, range 0 to 255.
+        let f_frac = lsf - ((f_int as i32) << (15 - 7));
+
+        let cos_val = i32::from(LSF_COS_TAB_Q12[f_int]);
+        let delta = i32::from(LSF_COS_TAB_Q12[f_int + 1]) - cos_val;
+
+        // Linear interpolation.
+        cos_lsf_qa[pos] = rshift_round((cos_val << 8) + delta * f_frac, 20 - QA);
+    }
+
+    let dd = d >> 1;
+
+    let mut p = [0i32; MAX_LPC_ORDER / 2 + 1];
+    let mut q = [0i32; MAX_LPC_ORDER / 2 + 1];
+
+    // Generate the even and odd polynomials using convolution.
+    nlsf2a_find_poly(&mut p, &cos_lsf_qa[0..], dd);
+    nlsf2a_find_poly(&mut q, &cos_lsf_qa[1..], dd);
+
+    // Convert the even and odd polynomials to filter coefficients in Q(QA + 1).
+    let mut a32_qa1 = [0i32; MAX_LPC_ORDER];
+
+    for k in 0..dd {
+        let p_tmp = p[k + 1] + p[k];
+        let q_tmp = q[k + 1] - q[k];
+
+        a32_qa1[k] = -q_tmp - p_tmp;
+        a32_qa1[d - k - 1] = q_tmp - p_tmp;
+    }
+
+    let a32_qa1 = &mut a32_qa1[..d];
+
+    // Convert to Q12 coefficients.
+    lpc_fit(a_q12, a32_qa1, 12, QA + 1);
+
+    let mut i = 0;
+
+    while lpc_inverse_pred_gain(a_q12) == 0 && i < MAX_LPC_STABILIZE_ITERATIONS {
+        // The filter is (too close to) unstable. Apply bandwidth expansion on the unscaled
+        // coefficients, convert them to Q12, and try again.
+        bwexpander_32(a32_qa1, 65536 - (2 << i));
+
+        for (a, &a32) in a_q12.iter_mut().zip(a32_qa1.iter()) {
+            *a = rshift_round(a32, QA + 1 - 12) as i16;
+        }
+
+        i += 1;
+    }
+}
+
+/// Converts 32-bit coefficients to 16-bit coefficients while preventing wrap-around.
+fn lpc_fit(a_qout: &mut [i16], a_qin: &mut [i32], q_out: u32, q_in: u32) {
+    let mut fit = false;
+
+    // Limit the maximum absolute value of the prediction coefficients so that they will fit in a
+    // 16-bit integer.
+    for _ in 0..10 {
+        // Find the maximum absolute value and its index.
+        let mut maxabs = 0;
+        let mut idx = 0;
+
+        for (k, &a) in a_qin.iter().enumerate() {
+            let absval = a.abs();
+
+            if absval > maxabs {
+                maxabs = absval;
+                idx = k;
+            }
+        }
+
+        let maxabs = rshift_round(maxabs, q_in - q_out);
+
+        if maxabs > i32::from(i16::MAX) {
+            // Reduce the magnitude of the prediction coefficients.
+            let maxabs = maxabs.min(163_838);
+
+            let chirp_q16 = 65470
+                - ((maxabs - i32::from(i16::MAX)) << 14) / ((maxabs * (idx as i32 + 1)) >> 2);
+
+            bwexpander_32(a_qin, chirp_q16);
+        }
+        else {
+            fit = true;
+            break;
+        }
+    }
+
+    if fit {
+        for (a, &a32) in a_qout.iter_mut().zip(a_qin.iter()) {
+            *a = rshift_round(a32, q_in - q_out) as i16;
+        }
+    }
+    else {
+        // Reached the last iteration, clip the coefficients.
+        for (a, a32) in a_qout.iter_mut().zip(a_qin.iter_mut()) {
+            *a = sat16(rshift_round(*a32, q_in - q_out)) as i16;
+            *a32 = i32::from(*a) << (q_in - q_out);
+        }
+    }
+}
+
+/// Applies bandwidth expansion (chirp) to an AR filter with 32-bit coefficients.
+fn bwexpander_32(ar: &mut [i32], mut chirp_q16: i32) {
+    let d = ar.len();
+    let chirp_minus_one_q16 = chirp_q16 - 65536;
+
+    for a in ar[..d - 1].iter_mut() {
+        *a = smulww(chirp_q16, *a);
+        chirp_q16 += rshift_round(chirp_q16 * chirp_minus_one_q16, 16);
+    }
+
+    ar[d - 1] = smulww(chirp_q16, ar[d - 1]);
+}
+
+/// Computes the inverse of the prediction gain of a LPC filter with coefficients in Q12. Returns
+/// 0 if the filter is unstable.
+pub fn lpc_inverse_pred_gain(a_q12: &[i16]) -> i32 {
+    const QA: u32 = 24;
+    // 0.99975 in QA.
+    const A_LIMIT: i32 = 16_773_022;
+    // 1 / MAX_PREDICTION_POWER_GAIN (1e4) in Q30.
+    const MIN_INV_GAIN_Q30: i32 = 107_374;
+
+    let order = a_q12.len();
+
+    let mut a_qa = [0i32; MAX_LPC_ORDER];
+    let mut dc_resp = 0;
+
+    // Increase the Q-domain of the coefficients.
+    for (a, &a12) in a_qa.iter_mut().zip(a_q12) {
+        dc_resp += i32::from(a12);
+        *a = i32::from(a12) << (QA - 12);
+    }
+
+    // If the DC is unstable, the full calculation is not required.
+    if dc_resp >= 4096 {
+        return 0;
+    }
+
+    let mul32_frac_q31 = |a: i32, b: i32| rshift_round64(i64::from(a) * i64::from(b), 31) as i32;
+
+    let mut inv_gain_q30 = 1 << 30;
+
+    for k in (1..order).rev() {
+        // Check for stability.
+        if a_qa[k] > A_LIMIT || a_qa[k] < -A_LIMIT {
+            return 0;
+        }
+
+        // Set the reflection coefficient to the negated AR coefficient.
+        let rc_q31 = -(a_qa[k] << (31 - QA));
+
+        // Range: [1, 2^30].
+        let rc_mult1_q30 = (1 << 30) - smmul(rc_q31, rc_q31);
+
+        // Update the inverse gain. Range: [0, 2^30].
+        inv_gain_q30 = smmul(inv_gain_q30, rc_mult1_q30) << 2;
+
+        if inv_gain_q30 < MIN_INV_GAIN_Q30 {
+            return 0;
+        }
+
+        // Range: [2^30, i32::MAX].
+        let mult2q = 32 - clz32(rc_mult1_q30.abs());
+        let rc_mult2 = inverse32_varq(rc_mult1_q30, mult2q + 30);
+
+        // Update the AR coefficients.
+        for n in 0..(k + 1) >> 1 {
+            let tmp1 = a_qa[n];
+            let tmp2 = a_qa[k - n - 1];
+
+            let tmp64 = rshift_round64(
+                i64::from(tmp1.saturating_sub(mul32_frac_q31(tmp2, rc_q31))) * i64::from(rc_mult2),
+                mult2q as u32,
+            );
+
+            if tmp64 > i64::from(i32::MAX) || tmp64 < i64::from(i32::MIN) {
+                return 0;
+            }
+
+            a_qa[n] = tmp64 as i32;
+
+            let tmp64 = rshift_round64(
+                i64::from(tmp2.saturating_sub(mul32_frac_q31(tmp1, rc_q31))) * i64::from(rc_mult2),
+                mult2q as u32,
+            );
+
+            if tmp64 > i64::from(i32::MAX) || tmp64 < i64::from(i32::MIN) {
+                return 0;
+            }
+
+            a_qa[k - n - 1] = tmp64 as i32;
+        }
+    }
+
+    // Check for stability.
+    if a_qa[0] > A_LIMIT || a_qa[0] < -A_LIMIT {
+        return 0;
+    }
+
+    // Set the reflection coefficient to the negated AR coefficient.
+    let rc_q31 = -(a_qa[0] << (31 - QA));
+
+    // Range: [1, 2^30].
+    let rc_mult1_q30 = (1 << 30) - smmul(rc_q31, rc_q31);
+
+    // Update the inverse gain. Range: [0, 2^30].
+    inv_gain_q30 = smmul(inv_gain_q30, rc_mult1_q30) << 2;
+
+    if inv_gain_q30 < MIN_INV_GAIN_Q30 {
+        return 0;
+    }
+
+    inv_gain_q30
+}
+
+/// Applies a LPC analysis (whitening) filter with coefficients in Q12 to the input signal. The
+/// first `order` samples of the output are zeroed.
+pub fn lpc_analysis_filter(out: &mut [i16], input: &[i16], b: &[i16], len: usize) {
+    let d = b.len();
+
+    for ix in d..len {
+        let mut out32_q12 = 0i32;
+
+        // Wrapping is allowed since two wraps may cancel each other. Wrapping can only be
+        // triggered by invalid streams.
+        for (j, &b) in b.iter().enumerate() {
+            out32_q12 = out32_q12.wrapping_add(smulbb(i32::from(input[ix - 1 - j]), i32::from(b)));
+        }
+
+        // Subtract the prediction.
+        out32_q12 = (i32::from(input[ix]) << 12).wrapping_sub(out32_q12);
+
+        // Scale to Q0 and saturate.
+        out[ix] = sat16(rshift_round(out32_q12, 12)) as i16;
+    }
+
+    for o in out[..d].iter_mut() {
+        *o = 0;
+    }
+}
@@ -0,0 +1,398 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The SILK layer of the Opus codec (RFC 6716 Section 4.2).
+//!
+//! Packet loss concealment and comfort noise generation are not implemented since lost packets
+//! are never decoded. Likewise, low bit-rate redundancy (LBRR) frames are parsed and discarded.
+
+mod channel;
+mod fixed;
+mod lpc;
+mod resampler;
+mod tables;
+
+use crate::range::RangeDecoder;
+
+use channel::{ChannelDecoder, CondCoding};
+use fixed::*;
+use tables::*;
+
+/// The maximum number of subframes in a SILK frame.
+pub const MAX_NB_SUBFR: usize = 4;
+
+/// The maximum LPC filter order.
+pub const MAX_LPC_ORDER: usize = 16;
+
+/// The maximum length of a SILK frame, in samples.
+pub const MAX_FRAME_LENGTH: usize = 20 * 16;
+
+/// The length of the interpolation between stereo predictors, in ms.
+const STEREO_INTERP_LEN_MS: usize = 8;
+
+/// The state of the mid/side to left/right conversion.
+#[derive(Default)]
+struct StereoState {
+    pred_prev_q13: [i32; 2],
+    s_mid: [i16; 2],
+    s_side: [i16; 2],
+}
+
+/// The SILK decoder.
+pub struct SilkDecoder {
+    channels: [ChannelDecoder; 2],
+    stereo: StereoState,
+    n_channels_api: usize,
+    n_channels_internal: usize,
+    prev_decode_only_middle: bool,
+}
+
+impl SilkDecoder {
+    pub fn new() -> Self {
+        SilkDecoder {
+            channels: [ChannelDecoder::new(), ChannelDecoder::new()],
+            stereo: Default::default(),
+            n_channels_api: 0,
+            n_channels_internal: 0,
+            prev_decode_only_middle: false,
+        }
+    }
+
+    /// Resets the decoder state.
+    pub fn reset(&mut self) {
+        self.channels = [ChannelDecoder::new(), ChannelDecoder::new()];
+        self.stereo = Default::default();
+        self.prev_decode_only_middle = false;
+    }
+
+    /// Decodes one 10 or 20 ms SILK frame from the packet into `out` as interleaved 16-bit
+    /// samples at 48 kHz.
+    ///
+    /// `new_packet` must be set for the first frame of each Opus frame. `n_channels_api` is the
+    /// number of output channels, `n_channels_internal` the number of channels coded in the
+    /// packet, `internal_rate` the internal SILK sample rate in Hz, and `payload_ms` the
+    /// duration of the Opus frame. Returns the number of samples per channel written.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode(
+        &mut self,
+        rc: &mut RangeDecoder<'_>,
+        new_packet: bool,
+        n_channels_api: usize,
+        n_channels_internal: usize,
+        internal_rate: usize,
+        payload_ms: usize,
+        out: &mut [i16],
+    ) -> usize {
+        let mut ms_pred_q13 = [0i32; 2];
+        let mut decode_only_middle = false;
+
+        if new_packet {
+            for ch in self.channels[..n_channels_internal].iter_mut() {
+                ch.n_frames_decoded = 0;
+            }
+        }
+
+        // On a mono to stereo transition in the bitstream, initialize the second channel.
+        if n_channels_internal > self.n_channels_internal {
+            self.channels[1] = ChannelDecoder::new();
+        }
+
+        let stereo_to_mono = n_channels_internal == 1
+            && self.n_channels_internal == 2
+            && internal_rate == 1000 * self.channels[0].fs_khz;
+
+        if self.channels[0].n_frames_decoded == 0 {
+            let (n_frames_per_packet, nb_subfr) = match payload_ms {
+                10 => (1, 2),
+                20 => (1, 4),
+                40 => (2, 4),
+                _ => (3, 4),
+            };
+
+            let fs_khz = (internal_rate >> 10) + 1;
+
+            for ch in self.channels[..n_channels_internal].iter_mut() {
+                ch.n_frames_per_packet = n_frames_per_packet;
+                ch.nb_subfr = nb_subfr;
+                ch.set_fs(fs_khz);
+            }
+        }
+
+        if n_channels_api == 2
+            && n_channels_internal == 2
+            && (self.n_channels_api == 1 || self.n_channels_internal == 1)
+        {
+            self.stereo.pred_prev_q13 = [0; 2];
+            self.stereo.s_side = [0; 2];
+            self.channels[1].resampler = self.channels[0].resampler.clone();
+        }
+
+        self.n_channels_api = n_channels_api;
+        self.n_channels_internal = n_channels_internal;
+
+        if self.channels[0].n_frames_decoded == 0 {
+            // This is the first frame of the packet. Decode the VAD and LBRR flags.
+            for ch in self.channels[..n_channels_internal].iter_mut() {
+                for i in 0..ch.n_frames_per_packet {
+                    ch.vad_flags[i] = rc.decode_bit_logp(1);
+                }
+                ch.lbrr_flag = rc.decode_bit_logp(1);
+            }
+
+            for ch in self.channels[..n_channels_internal].iter_mut() {
+                ch.lbrr_flags = [false; 3];
+
+                if ch.lbrr_flag {
+                    if ch.n_frames_per_packet == 1 {
+                        ch.lbrr_flags[0] = true;
+                    }
+                    else {
+                        let icdf: &[u8] = if ch.n_frames_per_packet == 2 {
+                            &LBRR_FLAGS_2_ICDF
+                        }
+                        else {
+                            &LBRR_FLAGS_3_ICDF
+                        };
+
+                        let symbol = rc.decode_icdf(icdf, 8) + 1;
+
+                        for i in 0..ch.n_frames_per_packet {
+                            ch.lbrr_flags[i] = (symbol >> i) & 1 != 0;
+                        }
+                    }
+                }
+            }
+
+            // Skip all LBRR data.
+            for i in 0..self.channels[0].n_frames_per_packet {
+                for n in 0..n_channels_internal {
+                    if !self.channels[n].lbrr_flags[i] {
+                        continue;
+                    }
+
+                    if n_channels_internal == 2 && n == 0 {
+                        stereo_decode_pred(rc, &mut ms_pred_q13);
+
+                        if !self.channels[1].lbrr_flags[i] {
+                            decode_only_middle = stereo_decode_mid_only(rc);
+                        }
+                    }
+
+                    // Use conditional coding if the previous frame is available.
+                    let cond_coding = if i > 0 && self.channels[n].lbrr_flags[i - 1] {
+                        CondCoding::Conditionally
+                    }
+                    else {
+                        CondCoding::Independently
+                    };
+
+                    self.channels[n].skip_lbrr_frame(rc, i, cond_coding);
+                }
+            }
+        }
+
+        // Decode the mid/side prediction weights.
+        if n_channels_internal == 2 {
+            stereo_decode_pred(rc, &mut ms_pred_q13);
+
+            let frame_index = self.channels[0].n_frames_decoded;
+
+            decode_only_middle = if !self.channels[1].vad_flags[frame_index] {
+                stereo_decode_mid_only(rc)
+            }
+            else {
+                false
+            };
+        }
+
+        // Reset the side channel prediction memory for the first frame with side coding.
+        if n_channels_internal == 2 && !decode_only_middle && self.prev_decode_only_middle {
+            self.channels[1].reset_prediction();
+        }
+
+        let frame_length = self.channels[0].frame_length;
+
+        // Each channel buffer is prefixed by 2 samples of history for the stereo conversion.
+        let mut samples = [[0i16; MAX_FRAME_LENGTH + 2]; 2];
+
+        let has_side = !decode_only_middle;
+
+        for (n, samples) in samples[..n_channels_internal].iter_mut().enumerate() {
+            if n == 0 || has_side {
+                let frame_index = self.channels[0].n_frames_decoded as isize - n as isize;
+
+                // Use independent coding if no previous frame is available.
+                let cond_coding = if frame_index <= 0 {
+                    CondCoding::Independently
+                }
+                else if n > 0 && self.prev_decode_only_middle {
+                    // If a side frame was skipped in this packet LTP scaling is not required
+                    // since the LTP state is well-defined.
+                    CondCoding::IndependentlyNoLtpScaling
+                }
+                else {
+                    CondCoding::Conditionally
+                };
+
+                self.channels[n].decode_frame(rc, &mut samples[2..], cond_coding);
+            }
+
+            self.channels[n].n_frames_decoded += 1;
+        }
+
+        if n_channels_api == 2 && n_channels_internal == 2 {
+            // Convert mid/side to left/right.
+            let (mid, side) = samples.split_at_mut(1);
+
+            stereo_ms_to_lr(
+                &mut self.stereo,
+                &mut mid[0][..frame_length + 2],
+                &mut side[0][..frame_length + 2],
+                &ms_pred_q13,
+                self.channels[0].fs_khz,
+            );
+        }
+        else {
+            // Buffering.
+            samples[0][..2].copy_from_slice(&self.stereo.s_mid);
+            self.stereo.s_mid.copy_from_slice(&samples[0][frame_length..frame_length + 2]);
+        }
+
+        let n_samples_out = frame_length * 48 / self.channels[0].fs_khz;
+
+        let mut resampled = [0i16; 20 * 48];
+
+        for n in 0..n_channels_api.min(n_channels_internal) {
+            let input = &samples[n][1..1 + frame_length];
+
+            self.channels[n].resampler.resample(&mut resampled[..n_samples_out], input);
+
+            for (o, &s) in out[n..].iter_mut().step_by(n_channels_api).zip(&resampled) {
+                *o = s;
+            }
+        }
+
+        // Create two channel output from a mono stream.
+        if n_channels_api == 2 && n_channels_internal == 1 {
+            if stereo_to_mono {
+                // Resample the right channel for newly collapsed stereo in case collapsing was not
+                // done when switching to mono.
+                let input = &samples[0][1..1 + frame_length];
+
+                self.channels[1].resampler.resample(&mut resampled[..n_samples_out], input);
+
+                for i in 0..n_samples_out {
+                    out[2 * i + 1] = resampled[i];
+                }
+            }
+            else {
+                for i in 0..n_samples_out {
+                    out[2 * i + 1] = out[2 * i];
+                }
+            }
+        }
+
+        self.prev_decode_only_middle = decode_only_middle;
+
+        n_samples_out
+    }
+}
+
+/// Decodes the mid/side prediction weights (RFC 6716 Section 4.2.7.1).
+fn stereo_decode_pred(rc: &mut RangeDecoder<'_>, pred_q13: &mut [i32; 2]) {
+    let mut ix = [[0usize; 3]; 2];
+
+    let n = rc.decode_icdf(&STEREO_PRED_JOINT_ICDF, 8);
+
+    ix[0][2] = n / 5;
+    ix[1][2] = n - 5 * ix[0][2];
+
+    for ix in ix.iter_mut() {
+        ix[0] = rc.decode_icdf(&UNIFORM3_ICDF, 8);
+        ix[1] = rc.decode_icdf(&UNIFORM5_ICDF, 8);
+    }
+
+    for (pred, ix) in pred_q13.iter_mut().zip(ix.iter_mut()) {
+        ix[0] += 3 * ix[2];
+
+        let low_q13 = i32::from(STEREO_PRED_QUANT_Q13[ix[0]]);
+        let step_q13 = smulwb(i32::from(STEREO_PRED_QUANT_Q13[ix[0] + 1]) - low_q13, 6554);
+
+        *pred = smlabb(low_q13, step_q13, 2 * ix[1] as i32 + 1);
+    }
+
+    // Subtract the second from the first predictor, this helps when actually applying them.
+    pred_q13[0] -= pred_q13[1];
+}
+
+/// Decodes the flag indicating only the mid channel is coded (RFC 6716 Section 4.2.7.2).
+fn stereo_decode_mid_only(rc: &mut RangeDecoder<'_>) -> bool {
+    rc.decode_icdf(&STEREO_ONLY_CODE_MID_ICDF, 8) != 0
+}
+
+/// Converts mid/side to left/right in-place. Both buffers contain two samples of history followed
+/// by the frame.
+fn stereo_ms_to_lr(
+    state: &mut StereoState,
+    x1: &mut [i16],
+    x2: &mut [i16],
+    pred_q13: &[i32; 2],
+    fs_khz: usize,
+) {
+    let frame_length = x1.len() - 2;
+
+    // Buffer the mid and side history.
+    x1[..2].copy_from_slice(&state.s_mid);
+    x2[..2].copy_from_slice(&state.s_side);
+    state.s_mid.copy_from_slice(&x1[frame_length..]);
+    state.s_side.copy_from_slice(&x2[frame_length..]);
+
+    // Interpolate the predictors, and subtract the prediction from the side channel.
+    let mut pred0_q13 = state.pred_prev_q13[0];
+    let mut pred1_q13 = state.pred_prev_q13[1];
+
+    let interp_len = STEREO_INTERP_LEN_MS * fs_khz;
+
+    let denom_q16 = (1 << 16) / interp_len as i32;
+    let delta0_q13 = rshift_round(smulbb(pred_q13[0] - state.pred_prev_q13[0], denom_q16), 16);
+    let delta1_q13 = rshift_round(smulbb(pred_q13[1] - state.pred_prev_q13[1], denom_q16), 16);
+
+    for n in 0..frame_length {
+        if n < interp_len {
+            pred0_q13 += delta0_q13;
+            pred1_q13 += delta1_q13;
+        }
+        else {
+            pred0_q13 = pred_q13[0];
+            pred1_q13 = pred_q13[1];
+        }
+
+        let x1_0 = i32::from(x1[n]);
+        let x1_1 = i32::from(x1[n + 1]);
+        let x1_2 = i32::from(x1[n + 2]);
+
+        // Q11
+        let sum = ((x1_0 + x1_2) + (x1_1 << 1)) << 9;
+        // Q8
+        let sum = smlawb(i32::from(x2[n + 1]) << 8, sum, pred0_q13);
+        // Q8
+        let sum = smlawb(sum, x1_1 << 11, pred1_q13);
+
+        x2[n + 1] = sat16(rshift_round(sum, 8)) as i16;
+    }
+
+    state.pred_prev_q13 = *pred_q13;
+
+    // Convert to left/right.
+    for n in 1..=frame_length {
+        let sum = i32::from(x1[n]) + i32::from(x2[n]);
+        let diff = i32::from(x1[n]) - i32::from(x2[n]);
+
+        x1[n] = sat16(sum) as i16;
+        x2[n] = sat16(diff) as i16;
+    }
+}
@@ -0,0 +1,188 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The SILK upsampler.
+//!
+//! The decoder always outputs audio at 48 kHz. Therefore, only the upsampler used for 8, 12, and
+//! 16 kHz to 48 kHz conversion is implemented. It consists of an all-pass based 2x upsampler
+//! followed by a fractional FIR interpolator.
+
+use super::fixed::*;
+use super::tables::RESAMPLER_FRAC_FIR_12;
+
+/// The output sample rate of the resampler in kHz.
+const FS_OUT_KHZ: usize = 48;
+
+/// The number of FIR interpolation filter taps.
+const ORDER_FIR_12: usize = 8;
+
+/// The maximum number of input samples processed in a batch, in ms.
+const MAX_BATCH_SIZE_MS: usize = 10;
+
+/// All-pass coefficients for the even output samples of the 2x upsampler.
+const UP2_HQ_0: [i32; 3] = [1746, 14986, 39083 - 65536];
+
+/// All-pass coefficients for the odd output samples of the 2x upsampler.
+const UP2_HQ_1: [i32; 3] = [6854, 25769, 55542 - 65536];
+
+/// Upsamples 8, 12, or 16 kHz audio to 48 kHz.
+#[derive(Clone)]
+pub struct Resampler {
+    s_iir: [i32; 6],
+    s_fir: [i16; ORDER_FIR_12],
+    delay_buf: [i16; 16],
+    fs_in_khz: usize,
+    input_delay: usize,
+    batch_size: usize,
+    inv_ratio_q16: i32,
+}
+
+impl Resampler {
+    pub fn new(fs_in_khz: usize) -> Self {
+        // The delay, in input samples, required to align the output of the resampler with the
+        // other decoding modes.
+        let input_delay = match fs_in_khz {
+            8 => 0,
+            12 => 4,
+            _ => 7,
+        };
+
+        let fs_in = 1000 * fs_in_khz as i32;
+        let fs_out = 1000 * FS_OUT_KHZ as i32;
+
+        // The ratio of the input rate to the output rate of the interpolator, in Q16. The
+        // interpolator runs on the 2x upsampled signal.
+        let mut inv_ratio_q16 = ((fs_in << 15) / fs_out) << 2;
+
+        // Round up to ensure enough output samples are generated.
+        while smulww(inv_ratio_q16, fs_out) < (fs_in << 1) {
+            inv_ratio_q16 += 1;
+        }
+
+        Resampler {
+            s_iir: [0; 6],
+            s_fir: [0; ORDER_FIR_12],
+            delay_buf: [0; 16],
+            fs_in_khz,
+            input_delay,
+            batch_size: MAX_BATCH_SIZE_MS * fs_in_khz,
+            inv_ratio_q16,
+        }
+    }
+
+    /// Resamples `input`, which must be a multiple of 1 ms long, into `out`.
+    pub fn resample(&mut self, out: &mut [i16], input: &[i16]) {
+        let n_samples = self.fs_in_khz - self.input_delay;
+
+        // The first 1 ms of output is computed from the delay buffer.
+        self.delay_buf[self.input_delay..self.fs_in_khz].copy_from_slice(&input[..n_samples]);
+
+        let delay_buf = self.delay_buf;
+
+        self.iir_fir(out, &delay_buf[..self.fs_in_khz]);
+        self.iir_fir(&mut out[FS_OUT_KHZ..], &input[n_samples..input.len() - self.input_delay]);
+
+        // Buffer the delayed samples for the next call.
+        let len = input.len();
+        self.delay_buf[..self.input_delay].copy_from_slice(&input[len - self.input_delay..]);
+    }
+
+    /// Upsamples using 2x all-pass based upsampling followed by FIR interpolation.
+    fn iir_fir(&mut self, mut out: &mut [i16], mut input: &[i16]) {
+        let mut buf = [0i16; 2 * MAX_BATCH_SIZE_MS * 16 + ORDER_FIR_12];
+
+        // Copy the buffered samples to the start of the buffer.
+        buf[..ORDER_FIR_12].copy_from_slice(&self.s_fir);
+
+        loop {
+            let n_samples_in = input.len().min(self.batch_size);
+
+            // Upsample 2x.
+            up2_hq(&mut self.s_iir, &mut buf[ORDER_FIR_12..], &input[..n_samples_in]);
+
+            let max_index_q16 = (n_samples_in as i32) << (16 + 1);
+
+            let written = interpolate(out, &buf, max_index_q16, self.inv_ratio_q16);
+            out = &mut out[written..];
+
+            input = &input[n_samples_in..];
+
+            if input.is_empty() {
+                // Copy the end of the filtered signal to the state for the next call.
+                let tail = &buf[n_samples_in << 1..][..ORDER_FIR_12];
+                self.s_fir.copy_from_slice(tail);
+                break;
+            }
+
+            // More iterations are required. Copy the end of the filtered signal to the start of
+            // the buffer.
+            buf.copy_within(n_samples_in << 1..(n_samples_in << 1) + ORDER_FIR_12, 0);
+        }
+    }
+}
+
+/// Interpolates the 2x upsampled signal, returning the number of samples written.
+fn interpolate(out: &mut [i16], buf: &[i16], max_index_q16: i32, index_incr_q16: i32) -> usize {
+    let mut index_q16 = 0;
+    let mut n = 0;
+
+    while index_q16 < max_index_q16 {
+        let table_index = smulwb(index_q16 & 0xffff, 12) as usize;
+        let buf = &buf[(index_q16 >> 16) as usize..];
+
+        let fir0 = &RESAMPLER_FRAC_FIR_12[4 * table_index..][..4];
+        let fir1 = &RESAMPLER_FRAC_FIR_12[4 * (11 - table_index)..][..4];
+
+        let mut res_q15 = smulbb(i32::from(buf[0]), i32::from(fir0[0]));
+        res_q15 = smlabb(res_q15, i32::from(buf[1]), i32::from(fir0[1]));
+        res_q15 = smlabb(res_q15, i32::from(buf[2]), i32::from(fir0[2]));
+        res_q15 = smlabb(res_q15, i32::from(buf[3]), i32::from(fir0[3]));
+        res_q15 = smlabb(res_q15, i32::from(buf[4]), i32::from(fir1[3]));
+        res_q15 = smlabb(res_q15, i32::from(buf[5]), i32::from(fir1[2]));
+        res_q15 = smlabb(res_q15, i32::from(buf[6]), i32::from(fir1[1]));
+        res_q15 = smlabb(res_q15, i32::from(buf[7]), i32::from(fir1[0]));
+
+        out[n] = sat16(rshift_round(res_q15, 15)) as i16;
+        n += 1;
+
+        index_q16 += index_incr_q16;
+    }
+
+    n
+}
+
+/// A single first-order all-pass section.
+#[inline(always)]
+fn allpass(s: &mut i32, input: i32, coef: i32, last: bool) -> i32 {
+    let y = input - *s;
+    let x = if last { smlawb(y, y, coef) } else { smulwb(y, coef) };
+    let out = *s + x;
+    *s = input + x;
+    out
+}
+
+/// Upsamples 2x using two cascades of three all-pass sections. The state is in Q10.
+fn up2_hq(s: &mut [i32; 6], out: &mut [i16], input: &[i16]) {
+    for (&x, out) in input.iter().zip(out.chunks_exact_mut(2)) {
+        // Convert to Q10.
+        let in32 = i32::from(x) << 10;
+
+        // Even output sample.
+        let y = allpass(&mut s[0], in32, UP2_HQ_0[0], false);
+        let y = allpass(&mut s[1], y, UP2_HQ_0[1], false);
+        let y = allpass(&mut s[2], y, UP2_HQ_0[2], true);
+
+        out[0] = sat16(rshift_round(y, 10)) as i16;
+
+        // Odd output sample.
+        let y = allpass(&mut s[3], in32, UP2_HQ_1[0], false);
+        let y = allpass(&mut s[4], y, UP2_HQ_1[1], false);
+        let y = allpass(&mut s[5], y, UP2_HQ_1[2], true);
+
+        out[1] = sat16(rshift_round(y, 10)) as i16;
+    }
+}
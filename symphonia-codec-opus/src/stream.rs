@@ -0,0 +1,353 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoding of a single, mono or stereo, Opus stream (RFC 6716 Section 4).
+
+use symphonia_core::errors::Result;
+
+use crate::celt::{CeltDecoder, HYBRID_START_BAND, WINDOW};
+use crate::packet::{Bandwidth, FramePacket, Mode, Toc};
+use crate::range::RangeDecoder;
+use crate::silk::SilkDecoder;
+
+/// The duration of 2.5 ms at 48 kHz.
+const F2_5: usize = 120;
+
+/// The duration of 5 ms at 48 kHz.
+const F5: usize = 240;
+
+/// The duration of 10 ms at 48 kHz.
+const F10: usize = 480;
+
+/// The duration of 20 ms at 48 kHz.
+const F20: usize = 960;
+
+/// The maximum duration of a frame at 48 kHz.
+const MAX_FRAME_SIZE: usize = 2880;
+
+/// `StreamDecoder` decodes the packets of a single Opus stream to 48 kHz audio.
+pub struct StreamDecoder {
+    /// The number of output channels.
+    channels: usize,
+    silk: SilkDecoder,
+    celt: CeltDecoder,
+    /// The TOC of the current packet.
+    toc: Option<Toc>,
+    /// The mode of the previous frame, if any.
+    prev_mode: Option<Mode>,
+    /// If true, the previous frame ended with a redundant CELT frame.
+    prev_redundancy: bool,
+    /// The decoded SILK samples of a frame.
+    pcm_silk: Vec<i16>,
+    /// A redundant CELT frame, or the concealed audio used to fade between modes.
+    pcm_aux: Vec<f32>,
+}
+
+impl StreamDecoder {
+    /// Instantiate a new decoder outputting `channels` channels.
+    pub fn new(channels: usize) -> Self {
+        StreamDecoder {
+            channels,
+            silk: SilkDecoder::new(),
+            celt: CeltDecoder::new(channels),
+            toc: None,
+            prev_mode: None,
+            prev_redundancy: false,
+            pcm_silk: vec![0; MAX_FRAME_SIZE * channels],
+            pcm_aux: vec![0.0; F5 * channels],
+        }
+    }
+
+    /// Resets the decoder state.
+    pub fn reset(&mut self) {
+        self.silk.reset();
+        self.celt.reset();
+        self.toc = None;
+        self.prev_mode = None;
+        self.prev_redundancy = false;
+    }
+
+    /// Decodes all the frames of a packet. The decoded interleaved audio is written to `pcm`,
+    /// which must be large enough for the duration of the packet.
+    pub fn decode(&mut self, packet: &FramePacket<'_>, pcm: &mut [f32]) -> Result<()> {
+        self.toc = Some(packet.toc);
+
+        let frame_len = packet.toc.frame_size * self.channels;
+
+        for (frame, pcm) in packet.frames().iter().zip(pcm.chunks_exact_mut(frame_len)) {
+            self.decode_frame(frame, pcm)?;
+        }
+
+        Ok(())
+    }
+
+    /// Conceals a missing frame. Only the CELT layer supports concealment, SILK frames are
+    /// concealed with silence.
+    fn conceal(&mut self, pcm: &mut [f32], frame_size: usize) {
+        for s in pcm[..frame_size * self.channels].iter_mut() {
+            *s = 0.0;
+        }
+
+        if self.prev_mode == Some(Mode::CeltOnly) {
+            self.celt.set_start_band(0);
+
+            // Conceal in the largest possible CELT frame sizes.
+            let mut pos = 0;
+
+            while pos < frame_size {
+                let len = match frame_size - pos {
+                    n if n >= F20 => F20,
+                    n if n >= F10 => F10,
+                    n if n >= F5 => F5,
+                    _ => F2_5,
+                };
+
+                self.celt.decode_lost(&mut pcm[pos * self.channels..], len);
+                pos += len;
+            }
+        }
+
+        self.prev_redundancy = false;
+    }
+
+    /// Decodes a single frame (RFC 6716 Section 4).
+    fn decode_frame(&mut self, data: &[u8], pcm: &mut [f32]) -> Result<()> {
+        let toc = self.toc.unwrap();
+
+        let channels = self.channels;
+        let frame_size = toc.frame_size;
+        let mode = toc.mode;
+
+        let pcm = &mut pcm[..frame_size * channels];
+
+        // Frames of 0 or 1 bytes signal discontinuous transmission, and are concealed.
+        if data.len() <= 1 {
+            if self.prev_mode.is_some() {
+                self.conceal(pcm, frame_size);
+            }
+            else {
+                for s in pcm.iter_mut() {
+                    *s = 0.0;
+                }
+            }
+
+            return Ok(());
+        }
+
+        let mut len = data.len();
+        let mut rc = RangeDecoder::new(data);
+
+        // A transition between the CELT-only mode and any mode using SILK requires a cross-fade
+        // from the concealed output of the previous mode, unless a redundant frame is present.
+        let mut transition = match self.prev_mode {
+            Some(prev_mode) => {
+                (mode == Mode::CeltOnly && prev_mode != Mode::CeltOnly && !self.prev_redundancy)
+                    || (mode != Mode::CeltOnly && prev_mode == Mode::CeltOnly)
+            }
+            None => false,
+        };
+
+        let transition_size = F5.min(frame_size);
+
+        let mut pcm_transition = [0.0; F5 * 2];
+
+        if transition && mode == Mode::CeltOnly {
+            self.conceal(&mut pcm_transition, transition_size);
+        }
+
+        // Decode SILK.
+        if mode != Mode::CeltOnly {
+            if self.prev_mode == Some(Mode::CeltOnly) {
+                self.silk.reset();
+            }
+
+            let internal_rate = match (mode, toc.bandwidth) {
+                (Mode::SilkOnly, Bandwidth::Narrowband) => 8000,
+                (Mode::SilkOnly, Bandwidth::Mediumband) => 12000,
+                _ => 16000,
+            };
+
+            let payload_ms = (1000 * frame_size / 48000).max(10);
+
+            let mut decoded = 0;
+
+            while decoded < frame_size {
+                decoded += self.silk.decode(
+                    &mut rc,
+                    decoded == 0,
+                    channels,
+                    toc.stream_channels,
+                    internal_rate,
+                    payload_ms,
+                    &mut self.pcm_silk[decoded * channels..],
+                );
+            }
+        }
+
+        // Decode the redundancy flags and the redundant frame length.
+        let mut redundancy = false;
+        let mut celt_to_silk = false;
+        let mut redundancy_bytes = 0;
+
+        let hybrid_bits = if mode == Mode::Hybrid { 20 } else { 0 };
+
+        if mode != Mode::CeltOnly && rc.tell() + 17 + hybrid_bits <= 8 * len as i32 {
+            // Check if there is a redundant 0-8 kHz band.
+            redundancy = if mode == Mode::Hybrid { rc.decode_bit_logp(12) } else { true };
+
+            if redundancy {
+                celt_to_silk = rc.decode_bit_logp(1);
+
+                // The redundant frame length is at least 2 bytes for the SILK-only mode due to
+                // the above check.
+                redundancy_bytes = if mode == Mode::Hybrid {
+                    rc.decode_uint(256) as usize + 2
+                }
+                else {
+                    len - ((rc.tell() as usize + 7) >> 3)
+                };
+
+                // An invalid redundant frame length is not fatal, but disables redundancy.
+                if redundancy_bytes > len || 8 * (len - redundancy_bytes) < rc.tell() as usize {
+                    len = 0;
+                    redundancy_bytes = 0;
+                    redundancy = false;
+                }
+                else {
+                    len -= redundancy_bytes;
+                }
+
+                // The redundant frame is not range coded as part of the main frame.
+                rc.shrink(redundancy_bytes);
+            }
+        }
+
+        let start_band = if mode != Mode::CeltOnly { HYBRID_START_BAND } else { 0 };
+
+        if redundancy {
+            transition = false;
+        }
+
+        if transition && mode != Mode::CeltOnly {
+            self.conceal(&mut pcm_transition, transition_size);
+        }
+
+        let end_band = match toc.bandwidth {
+            Bandwidth::Narrowband => 13,
+            Bandwidth::Mediumband | Bandwidth::Wideband => 17,
+            Bandwidth::SuperWideband => 19,
+            Bandwidth::Fullband => 21,
+        };
+
+        self.celt.set_end_band(end_band);
+        self.celt.set_stream_channels(toc.stream_channels);
+
+        let redundant_data = &data[len..len + redundancy_bytes];
+
+        // A 5 ms redundant frame for CELT to SILK transitions.
+        if redundancy && celt_to_silk {
+            self.celt.set_start_band(0);
+
+            let mut rc = RangeDecoder::new(redundant_data);
+            self.celt.decode(&mut rc, redundancy_bytes, &mut self.pcm_aux, F5)?;
+        }
+
+        self.celt.set_start_band(start_band);
+
+        if mode != Mode::SilkOnly {
+            // Discard any previous CELT state on a mode change.
+            let mode_changed = self.prev_mode.is_some() && self.prev_mode != Some(mode);
+
+            if mode_changed && !self.prev_redundancy {
+                self.celt.reset();
+            }
+
+            self.celt.decode(&mut rc, len, pcm, frame_size.min(F20))?;
+        }
+        else {
+            for s in pcm.iter_mut() {
+                *s = 0.0;
+            }
+
+            // For hybrid to SILK transitions, let the CELT MDCT fade-out by decoding a silent
+            // frame.
+            if self.prev_mode == Some(Mode::Hybrid)
+                && !(redundancy && celt_to_silk && self.prev_redundancy)
+            {
+                let silence = [0xff, 0xff];
+
+                self.celt.set_start_band(0);
+
+                let mut rc = RangeDecoder::new(&silence);
+                self.celt.decode(&mut rc, silence.len(), pcm, F2_5)?;
+            }
+        }
+
+        // Mix in the SILK output.
+        if mode != Mode::CeltOnly {
+            for (s, &silk) in pcm.iter_mut().zip(self.pcm_silk.iter()) {
+                *s += (1.0 / 32768.0) * f32::from(silk);
+            }
+        }
+
+        // A 5 ms redundant frame for SILK to CELT transitions.
+        if redundancy && !celt_to_silk {
+            self.celt.reset();
+            self.celt.set_start_band(0);
+
+            let mut rc = RangeDecoder::new(redundant_data);
+            self.celt.decode(&mut rc, redundancy_bytes, &mut self.pcm_aux, F5)?;
+
+            let pos = channels * (frame_size - F2_5);
+
+            smooth_fade_in_place(&mut pcm[pos..], &self.pcm_aux[channels * F2_5..], channels, true);
+        }
+
+        if redundancy && celt_to_silk {
+            pcm[..channels * F2_5].copy_from_slice(&self.pcm_aux[..channels * F2_5]);
+
+            let pos = channels * F2_5;
+
+            smooth_fade_in_place(&mut pcm[pos..], &self.pcm_aux[pos..], channels, false);
+        }
+
+        if transition {
+            if frame_size >= F5 {
+                pcm[..channels * F2_5].copy_from_slice(&pcm_transition[..channels * F2_5]);
+
+                let pos = channels * F2_5;
+
+                smooth_fade_in_place(&mut pcm[pos..], &pcm_transition[pos..], channels, false);
+            }
+            else {
+                // There is not enough time to do a clean transition, but do it anyway.
+                smooth_fade_in_place(pcm, &pcm_transition, channels, false);
+            }
+        }
+
+        self.prev_mode = Some(mode);
+        self.prev_redundancy = redundancy && !celt_to_silk;
+
+        Ok(())
+    }
+}
+
+/// Cross-fades between two signals over 2.5 ms using the square of the CELT window. If
+/// `fade_out` is true, `pcm` is faded out while `other` is faded in, otherwise `other` is faded
+/// out while `pcm` is faded in. The result is written to `pcm`.
+fn smooth_fade_in_place(pcm: &mut [f32], other: &[f32], channels: usize, fade_out: bool) {
+    for (i, &w) in WINDOW.iter().enumerate() {
+        let w = w * w;
+
+        for c in 0..channels {
+            let a = &mut pcm[i * channels + c];
+            let b = other[i * channels + c];
+
+            *a = if fade_out { w * b + (1.0 - w) * *a } else { w * *a + (1.0 - w) * b };
+        }
+    }
+}
@@ -10,7 +10,8 @@
 
 use symphonia_core::support_codec;
 
-use symphonia_core::audio::{AudioBuffer, AudioBufferRef, AsAudioBufferRef, Signal, SignalSpec};
+use symphonia_core::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, AsAudioBufferRef};
+use symphonia_core::audio::{Signal, SignalSpec};
 use symphonia_core::codecs::{CodecParameters, CodecDescriptor};
 use symphonia_core::codecs::{Decoder, DecoderOptions, FinalizeResult};
 // Signed Int PCM codecs
@@ -26,8 +27,8 @@ use symphonia_core::codecs::{CODEC_TYPE_PCM_F32LE, CODEC_TYPE_PCM_F32BE};
 use symphonia_core::codecs::{CODEC_TYPE_PCM_F64LE, CODEC_TYPE_PCM_F64BE};
 // G711 ALaw and MuLaw PCM cdoecs.
 use symphonia_core::codecs::{CODEC_TYPE_PCM_ALAW, CODEC_TYPE_PCM_MULAW};
-use symphonia_core::conv::FromSample;
-use symphonia_core::errors::{Result, unsupported_error};
+use symphonia_core::conv::{ConvertibleSample, IntoSample};
+use symphonia_core::errors::{Result, limit_error, unsupported_error};
 use symphonia_core::formats::Packet;
 use symphonia_core::io::ReadBytes;
 
@@ -35,7 +36,7 @@ macro_rules! read_pcm_signed {
     ($buf:expr, $read:expr, $shift:expr) => {
         $buf.fill(| audio_planes, idx | -> Result<()> {
             for plane in audio_planes.planes() {
-                plane[idx] = (($read as u32) << $shift) as i32;
+                plane[idx] = ((($read as u32) << $shift) as i32).into_sample();
             }
             Ok(())
         })
@@ -46,7 +47,8 @@ macro_rules! read_pcm_unsigned {
     ($buf:expr, $read:expr, $shift:expr) => {
         $buf.fill(| audio_planes, idx | -> Result<()> {
             for plane in audio_planes.planes() {
-                plane[idx] = (($read as u32) << $shift).wrapping_add(0x80000000) as i32
+                let sample = (($read as u32) << $shift).wrapping_add(0x80000000) as i32;
+                plane[idx] = sample.into_sample();
             }
             Ok(())
         })
@@ -57,7 +59,7 @@ macro_rules! read_pcm_floating {
     ($buf:expr, $read:expr) => {
         $buf.fill(| audio_planes, idx | -> Result<()> {
             for plane in audio_planes.planes() {
-                plane[idx] = i32::from_sample($read);
+                plane[idx] = $read.into_sample();
             }
             Ok(())
         })
@@ -68,7 +70,7 @@ macro_rules! read_pcm_transfer_func {
     ($buf:expr, $func:expr) => {
         $buf.fill(| audio_planes, idx | -> Result<()> {
             for plane in audio_planes.planes() {
-                plane[idx] = i32::from_sample($func);
+                plane[idx] = $func.into_sample();
             }
             Ok(())
         })
@@ -110,6 +112,69 @@ fn mulaw_to_linear(mut mu_val: u8) -> i16 {
     if mu_val & 0x80 == 0x80 { t - BIAS } else { BIAS - t }
 }
 
+/// Decodes a packet of PCM audio into `buf`, converting the samples to the sample format of `buf`.
+fn decode_pcm<S: ConvertibleSample>(
+    params: &CodecParameters,
+    sample_width: u32,
+    packet: &Packet,
+    buf: &mut AudioBuffer<S>,
+) -> Result<()> {
+    let mut stream = packet.as_buf_reader();
+
+    // Signed or unsigned integer PCM codecs must be shifted to expand the sample into the
+    // entire i32 range. Only floating point samples may exceed 32 bits per coded sample, but
+    // they cannot be shifted, so int_shift = 0.
+    let int_shift = if sample_width <= 32 { 32 - sample_width } else { 0 };
+
+    let _ = match params.codec {
+        CODEC_TYPE_PCM_S32LE => read_pcm_signed!(buf,   stream.read_u32()?,    int_shift),
+        CODEC_TYPE_PCM_S32BE => read_pcm_signed!(buf,   stream.read_be_u32()?, int_shift),
+        CODEC_TYPE_PCM_S24LE => read_pcm_signed!(buf,   stream.read_u24()?,    int_shift),
+        CODEC_TYPE_PCM_S24BE => read_pcm_signed!(buf,   stream.read_be_u24()?, int_shift),
+        CODEC_TYPE_PCM_S16LE => read_pcm_signed!(buf,   stream.read_u16()?,    int_shift),
+        CODEC_TYPE_PCM_S16BE => read_pcm_signed!(buf,   stream.read_be_u16()?, int_shift),
+        CODEC_TYPE_PCM_S8    => read_pcm_signed!(buf,   stream.read_u8()?,     int_shift),
+        CODEC_TYPE_PCM_U32LE => read_pcm_unsigned!(buf, stream.read_u32()?,    int_shift),
+        CODEC_TYPE_PCM_U32BE => read_pcm_unsigned!(buf, stream.read_be_u32()?, int_shift),
+        CODEC_TYPE_PCM_U24LE => read_pcm_unsigned!(buf, stream.read_u24()?,    int_shift),
+        CODEC_TYPE_PCM_U24BE => read_pcm_unsigned!(buf, stream.read_be_u24()?, int_shift),
+        CODEC_TYPE_PCM_U16LE => read_pcm_unsigned!(buf, stream.read_u16()?,    int_shift),
+        CODEC_TYPE_PCM_U16BE => read_pcm_unsigned!(buf, stream.read_be_u16()?, int_shift),
+        CODEC_TYPE_PCM_U8    => read_pcm_unsigned!(buf, stream.read_u8()?,     int_shift),
+        CODEC_TYPE_PCM_F32LE => read_pcm_floating!(buf, stream.read_f32()?),
+        CODEC_TYPE_PCM_F32BE => read_pcm_floating!(buf, stream.read_be_f32()?),
+        CODEC_TYPE_PCM_F64LE => read_pcm_floating!(buf, stream.read_f64()?),
+        CODEC_TYPE_PCM_F64BE => read_pcm_floating!(buf, stream.read_be_f64()?),
+        CODEC_TYPE_PCM_ALAW  => {
+            read_pcm_transfer_func!(buf, alaw_to_linear(stream.read_u8()?))
+        },
+        CODEC_TYPE_PCM_MULAW => {
+            read_pcm_transfer_func!(buf, mulaw_to_linear(stream.read_u8()?))
+        },
+        // CODEC_TYPE_PCM_S32LE_PLANAR =>
+        // CODEC_TYPE_PCM_S32BE_PLANAR =>
+        // CODEC_TYPE_PCM_S24LE_PLANAR =>
+        // CODEC_TYPE_PCM_S24BE_PLANAR =>
+        // CODEC_TYPE_PCM_S16LE_PLANAR =>
+        // CODEC_TYPE_PCM_S16BE_PLANAR =>
+        // CODEC_TYPE_PCM_S8_PLANAR    =>
+        // CODEC_TYPE_PCM_U32LE_PLANAR =>
+        // CODEC_TYPE_PCM_U32BE_PLANAR =>
+        // CODEC_TYPE_PCM_U24LE_PLANAR =>
+        // CODEC_TYPE_PCM_U24BE_PLANAR =>
+        // CODEC_TYPE_PCM_U16LE_PLANAR =>
+        // CODEC_TYPE_PCM_U16BE_PLANAR =>
+        // CODEC_TYPE_PCM_U8_PLANAR    =>
+        // CODEC_TYPE_PCM_F32LE_PLANAR =>
+        // CODEC_TYPE_PCM_F32BE_PLANAR =>
+        // CODEC_TYPE_PCM_F64LE_PLANAR =>
+        // CODEC_TYPE_PCM_F64BE_PLANAR =>
+        _ => return unsupported_error("pcm: codec is unsupported.")
+    };
+
+    Ok(())
+}
+
 /// Pulse Code Modulation (PCM) decoder for all raw PCM, and log-PCM codecs.
 pub struct PcmDecoder {
     params: CodecParameters,
@@ -117,6 +182,28 @@ pub struct PcmDecoder {
     buf: AudioBuffer<i32>,
 }
 
+impl PcmDecoder {
+    /// Decodes a packet directly into a caller-provided buffer. The buffer is allocated if it is
+    /// unused. Otherwise, the buffer must have the signal specification of the decoder's buffer,
+    /// and enough capacity for the maximum number of frames per packet.
+    fn decode_into_typed<S>(&self, packet: &Packet, buf: &mut AudioBuffer<S>) -> Result<()>
+    where
+        S: ConvertibleSample,
+    {
+        if buf.is_unused() {
+            *buf = self.buf.make_equivalent();
+        }
+        else if buf.spec() != self.buf.spec() {
+            return unsupported_error("pcm: audio buffer signal specification does not match");
+        }
+        else if buf.capacity() < self.buf.capacity() {
+            return limit_error("pcm: audio buffer capacity is too small");
+        }
+
+        decode_pcm(&self.params, self.sample_width, packet, buf)
+    }
+}
+
 impl Decoder for PcmDecoder {
 
     fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
@@ -370,63 +457,84 @@ impl Decoder for PcmDecoder {
     }
 
     fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
-        let mut stream = packet.as_buf_reader();
-
-        // Signed or unsigned integer PCM codecs must be shifted to expand the sample into the
-        // entire i32 range. Only floating point samples may exceed 32 bits per coded sample, but
-        // they cannot be shifted, so int_shift = 0.
-        let int_shift = if self.sample_width <= 32 { 32 - self.sample_width } else { 0 };
-
-        let _ = match self.params.codec {
-            CODEC_TYPE_PCM_S32LE => read_pcm_signed!(self.buf,   stream.read_u32()?,    int_shift),
-            CODEC_TYPE_PCM_S32BE => read_pcm_signed!(self.buf,   stream.read_be_u32()?, int_shift),
-            CODEC_TYPE_PCM_S24LE => read_pcm_signed!(self.buf,   stream.read_u24()?,    int_shift),
-            CODEC_TYPE_PCM_S24BE => read_pcm_signed!(self.buf,   stream.read_be_u24()?, int_shift),
-            CODEC_TYPE_PCM_S16LE => read_pcm_signed!(self.buf,   stream.read_u16()?,    int_shift),
-            CODEC_TYPE_PCM_S16BE => read_pcm_signed!(self.buf,   stream.read_be_u16()?, int_shift),
-            CODEC_TYPE_PCM_S8    => read_pcm_signed!(self.buf,   stream.read_u8()?,     int_shift),
-            CODEC_TYPE_PCM_U32LE => read_pcm_unsigned!(self.buf, stream.read_u32()?,    int_shift),
-            CODEC_TYPE_PCM_U32BE => read_pcm_unsigned!(self.buf, stream.read_be_u32()?, int_shift),
-            CODEC_TYPE_PCM_U24LE => read_pcm_unsigned!(self.buf, stream.read_u24()?,    int_shift),
-            CODEC_TYPE_PCM_U24BE => read_pcm_unsigned!(self.buf, stream.read_be_u24()?, int_shift),
-            CODEC_TYPE_PCM_U16LE => read_pcm_unsigned!(self.buf, stream.read_u16()?,    int_shift),
-            CODEC_TYPE_PCM_U16BE => read_pcm_unsigned!(self.buf, stream.read_be_u16()?, int_shift),
-            CODEC_TYPE_PCM_U8    => read_pcm_unsigned!(self.buf, stream.read_u8()?,     int_shift),
-            CODEC_TYPE_PCM_F32LE => read_pcm_floating!(self.buf, stream.read_f32()?),
-            CODEC_TYPE_PCM_F32BE => read_pcm_floating!(self.buf, stream.read_be_f32()?),
-            CODEC_TYPE_PCM_F64LE => read_pcm_floating!(self.buf, stream.read_f64()?),
-            CODEC_TYPE_PCM_F64BE => read_pcm_floating!(self.buf, stream.read_be_f64()?),
-            CODEC_TYPE_PCM_ALAW  => {
-                read_pcm_transfer_func!(self.buf, alaw_to_linear(stream.read_u8()?))
-            },
-            CODEC_TYPE_PCM_MULAW => {
-                read_pcm_transfer_func!(self.buf, mulaw_to_linear(stream.read_u8()?))
-            },
-            // CODEC_TYPE_PCM_S32LE_PLANAR =>
-            // CODEC_TYPE_PCM_S32BE_PLANAR =>
-            // CODEC_TYPE_PCM_S24LE_PLANAR =>
-            // CODEC_TYPE_PCM_S24BE_PLANAR =>
-            // CODEC_TYPE_PCM_S16LE_PLANAR =>
-            // CODEC_TYPE_PCM_S16BE_PLANAR =>
-            // CODEC_TYPE_PCM_S8_PLANAR    =>
-            // CODEC_TYPE_PCM_U32LE_PLANAR =>
-            // CODEC_TYPE_PCM_U32BE_PLANAR =>
-            // CODEC_TYPE_PCM_U24LE_PLANAR =>
-            // CODEC_TYPE_PCM_U24BE_PLANAR =>
-            // CODEC_TYPE_PCM_U16LE_PLANAR =>
-            // CODEC_TYPE_PCM_U16BE_PLANAR =>
-            // CODEC_TYPE_PCM_U8_PLANAR    =>
-            // CODEC_TYPE_PCM_F32LE_PLANAR =>
-            // CODEC_TYPE_PCM_F32BE_PLANAR =>
-            // CODEC_TYPE_PCM_F64LE_PLANAR =>
-            // CODEC_TYPE_PCM_F64BE_PLANAR =>
-            _ => return unsupported_error("pcm: codec is unsupported.")
-        };
+        decode_pcm(&self.params, self.sample_width, packet, &mut self.buf)?;
 
         Ok(self.buf.as_audio_buffer_ref())
     }
 
+    fn decode_into(&mut self, packet: &Packet, dest: AudioBufferMut<'_>) -> Result<()> {
+        match dest {
+            AudioBufferMut::U8(buf)  => self.decode_into_typed(packet, buf),
+            AudioBufferMut::U16(buf) => self.decode_into_typed(packet, buf),
+            AudioBufferMut::U24(buf) => self.decode_into_typed(packet, buf),
+            AudioBufferMut::U32(buf) => self.decode_into_typed(packet, buf),
+            AudioBufferMut::S8(buf)  => self.decode_into_typed(packet, buf),
+            AudioBufferMut::S16(buf) => self.decode_into_typed(packet, buf),
+            AudioBufferMut::S24(buf) => self.decode_into_typed(packet, buf),
+            AudioBufferMut::S32(buf) => self.decode_into_typed(packet, buf),
+            AudioBufferMut::F32(buf) => self.decode_into_typed(packet, buf),
+            AudioBufferMut::F64(buf) => self.decode_into_typed(packet, buf),
+        }
+    }
+
     fn finalize(&mut self) -> FinalizeResult {
         Default::default()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use symphonia_core::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, Channels, Signal};
+    use symphonia_core::audio::SignalSpec;
+    use symphonia_core::codecs::{CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_PCM_S16LE};
+    use symphonia_core::errors::Error;
+    use symphonia_core::formats::Packet;
+
+    use super::PcmDecoder;
+
+    #[test]
+    fn verify_decode_into() {
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_PCM_S16LE)
+            .with_sample_rate(8_000)
+            .with_channels(Channels::FRONT_LEFT | Channels::FRONT_RIGHT)
+            .with_bits_per_coded_sample(16)
+            .with_max_frames_per_packet(4);
+
+        let mut decoder = PcmDecoder::try_new(&params, &DecoderOptions::default()).unwrap();
+
+        // Three interleaved stereo frames.
+        let data = [0x00, 0x40, 0x00, 0xc0, 0x00, 0x20, 0x00, 0xe0, 0x00, 0x10, 0x00, 0xf0];
+        let packet = Packet::new_from_slice(0, 0, 3, &data);
+
+        let expected = match decoder.decode(&packet).unwrap() {
+            AudioBufferRef::S32(buf) => [buf.chan(0).to_vec(), buf.chan(1).to_vec()],
+            _ => unreachable!(),
+        };
+
+        assert_eq!(expected[0], [0x4000 << 16, 0x2000 << 16, 0x1000 << 16]);
+
+        // An unused buffer is allocated with the decoder's signal specification and capacity.
+        let mut dest = AudioBuffer::<i32>::unused();
+        decoder.decode_into(&packet, AudioBufferMut::from(&mut dest)).unwrap();
+        assert_eq!(dest.capacity(), 4);
+        assert_eq!([dest.chan(0).to_vec(), dest.chan(1).to_vec()], expected);
+
+        // Samples are decoded directly into the sample format of the buffer.
+        let mut dest = AudioBuffer::<i16>::unused();
+        decoder.decode_into(&packet, AudioBufferMut::from(&mut dest)).unwrap();
+        assert_eq!(dest.chan(0), &[0x4000, 0x2000, 0x1000]);
+        assert_eq!(dest.chan(1), &[-0x4000, -0x2000, -0x1000]);
+
+        // A buffer that is too small for the maximum number of frames per packet is an error.
+        let mut dest = AudioBuffer::<i32>::new(2, *dest.spec());
+        let result = decoder.decode_into(&packet, AudioBufferMut::from(&mut dest));
+        assert!(matches!(result, Err(Error::LimitError(_))));
+
+        // As is a buffer with a different signal specification, which is not reallocated.
+        let mut dest = AudioBuffer::<i32>::new(4, SignalSpec::new(8_000, Channels::FRONT_LEFT));
+        let result = decoder.decode_into(&packet, AudioBufferMut::from(&mut dest));
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+        assert_eq!(dest.spec().channels, Channels::FRONT_LEFT);
+    }
+}
@@ -13,6 +13,7 @@ use std::cmp::min;
 /// complement integer value `x`. Values of `x` less than zero are defined to return zero.
 
 use symphonia_core::dsp::mdct::Imdct;
+use symphonia_core::dsp::simd;
 
 use super::residue::ResidueScratch;
 use super::window::{Windows, Window, WindowHalf};
@@ -107,16 +108,12 @@ impl DspChannel {
         let l_start = win.left.start;
         let l_end = win.left.end;
 
-        for (s, &w) in self.overlap[l_start..l_end].iter_mut().zip(&win.window[l_start..l_end]) {
-            *s *= w;
-        }
+        simd::mul(&mut self.overlap[l_start..l_end], &win.window[l_start..l_end]);
 
         let r_start = win.right.start;
         let r_end = win.right.end;
 
-        for (s, &w) in self.overlap[r_start..r_end].iter_mut().zip(&win.window[r_start..r_end]) {
-            *s *= w;
-        }
+        simd::mul(&mut self.overlap[r_start..r_end], &win.window[r_start..r_end]);
 
         // Step 4
         //
@@ -131,9 +128,7 @@ impl DspChannel {
 
             // The left-hand side overlaps the right-hand side in this region. The output buffer
             // contains the left-hand side samples, so add the right-hand side samples.
-            for (o, &s) in buf[overlap_start..overlap_end].iter_mut().zip(lhs) {
-                *o += s;
-            }
+            simd::add(&mut buf[overlap_start..overlap_end], lhs);
 
             // The left-hand side has ended, so simply copy the right-hand side samples to the output.
             for (o, &s) in buf[overlap_end..].iter_mut().zip(&lhs[overlap_end - overlap_start..]) {
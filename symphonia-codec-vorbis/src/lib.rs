@@ -8,11 +8,13 @@
 #![warn(rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
-use symphonia_core::audio::{AudioBuffer, AudioBufferRef, AsAudioBufferRef, Channels, Signal, SignalSpec};
+use symphonia_core::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, AsAudioBufferRef};
+use symphonia_core::audio::{Channels, Signal, SignalSpec};
 use symphonia_core::codecs::{CODEC_TYPE_VORBIS, CodecParameters, CodecDescriptor};
-use symphonia_core::codecs::{Decoder, DecoderOptions, FinalizeResult};
+use symphonia_core::codecs::{Concealer, Decoder, DecoderOptions, FinalizeResult};
+use symphonia_core::codecs::decode_into_native;
 use symphonia_core::dsp::mdct::Imdct;
-use symphonia_core::errors::{Result, decode_error, unsupported_error};
+use symphonia_core::errors::{Error, Result, decode_error, unsupported_error};
 use symphonia_core::formats::Packet;
 use symphonia_core::io::{ReadBitsRtl, BitReaderRtl, ReadBytes, BufReader, FiniteBitStream};
 use symphonia_core::support_codec;
@@ -52,13 +54,15 @@ pub struct VorbisDecoder {
     mappings: Vec<Mapping>,
     /// DSP.
     dsp: Dsp,
+    /// Error concealment.
+    concealer: Concealer<f32>,
     /// Output buffer.
     buf: AudioBuffer<f32>,
 }
 
 impl Decoder for VorbisDecoder {
 
-    fn try_new(params: &CodecParameters, _: &DecoderOptions) -> Result<Self> {
+    fn try_new(params: &CodecParameters, options: &DecoderOptions) -> Result<Self> {
         // Get the extra data (mandatory).
         let extra_data = match params.extra_data.as_ref() {
             Some(buf) => buf,
@@ -110,12 +114,14 @@ impl Decoder for VorbisDecoder {
             modes: setup.modes,
             mappings: setup.mappings,
             dsp,
+            concealer: Concealer::new(options.conceal),
             buf: AudioBuffer::new(duration, spec),
         })
     }
 
     fn reset(&mut self) {
         self.dsp.reset();
+        self.concealer.reset();
     }
 
     fn supported_codecs() -> &'static [CodecDescriptor] {
@@ -129,22 +135,40 @@ impl Decoder for VorbisDecoder {
     }
 
     fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
-        let mut bs = BitReaderRtl::new(packet.buf());
+        match self.decode_inner(packet) {
+            Ok(()) => self.concealer.decoded(&self.buf),
+            Err(err) if self.concealer.can_conceal(&err) => self.conceal(packet, err),
+            Err(err) => return Err(err),
+        }
 
-        // Section 4.3.1 - Packet Type, Mode, and Window Decode
+        Ok(self.buf.as_audio_buffer_ref())
+    }
 
-        // First bit must be 0 to indicate audio packet.
-        if bs.read_bit()? {
-            return decode_error("vorbis: not an audio packet");
+    fn decode_into(&mut self, packet: &Packet, dest: AudioBufferMut<'_>) -> Result<()> {
+        match dest {
+            AudioBufferMut::F32(buf) => decode_into_native(self, packet, buf, |dec| &mut dec.buf),
+            mut dest => {
+                let decoded = self.decode(packet)?;
+                dest.copy_ref(&decoded)
+            }
         }
+    }
 
-        let num_modes = self.modes.len() - 1;
+    fn concealed_frames(&self) -> u64 {
+        self.concealer.concealed_frames()
+    }
 
-        let mode_number = bs.read_bits_leq32(common::ilog(num_modes as u32))? as usize;
+    fn finalize(&mut self) -> FinalizeResult {
+        Default::default()
+    }
+}
 
-        if mode_number > self.modes.len() {
-            return decode_error("vorbis: invalid packet mode number");
-        }
+impl VorbisDecoder {
+    fn decode_inner(&mut self, packet: &Packet) -> Result<()> {
+        let mut bs = BitReaderRtl::new(packet.buf());
+
+        // Section 4.3.1 - Packet Type, Mode, and Window Decode
+        let mode_number = self.read_mode_number(&mut bs)?;
 
         let mode = &self.modes[mode_number];
         let mapping = &self.mappings[usize::from(mode.mapping)];
@@ -319,11 +343,81 @@ impl Decoder for VorbisDecoder {
             prev_win_right: window.right
         });
 
-        Ok(self.buf.as_audio_buffer_ref())
+        Ok(())
     }
 
-    fn finalize(&mut self) -> FinalizeResult {
-        Default::default()
+    fn read_mode_number(&self, bs: &mut BitReaderRtl<'_>) -> Result<usize> {
+        // First bit must be 0 to indicate audio packet.
+        if bs.read_bit()? {
+            return decode_error("vorbis: not an audio packet");
+        }
+
+        let num_modes = self.modes.len() - 1;
+
+        let mode_number = bs.read_bits_leq32(common::ilog(num_modes as u32))? as usize;
+
+        if mode_number >= self.modes.len() {
+            return decode_error("vorbis: invalid packet mode number");
+        }
+
+        Ok(mode_number)
+    }
+
+    /// Conceals a damaged packet by synthesizing a block with a silent spectrum. This completes the
+    /// overlap of the previous block such that it fades to silence.
+    fn conceal(&mut self, packet: &Packet, err: Error) {
+        warn!("concealing damaged packet: {}", err);
+
+        let long_n = 1 << self.ident.bs1_exp;
+
+        let prev_is_long = match &self.dsp.lapping_state {
+            Some(lap_state) => lap_state.prev_block_size == long_n,
+            _ => false,
+        };
+
+        // The block size of the damaged packet may still be readable. If not, assume it is the
+        // same as the previous block.
+        let mut bs = BitReaderRtl::new(packet.buf());
+
+        let is_long = match self.read_mode_number(&mut bs) {
+            Ok(mode_number) => self.modes[mode_number].block_flag,
+            _ => prev_is_long,
+        };
+
+        // The shape of the right-hand side of the window is not known. However, since the
+        // synthesized block is silent, a short right-hand side is always compatible with the
+        // following block.
+        let (n, imdct, window) = if is_long {
+            let window = match prev_is_long {
+                true  => &self.dsp.windows.long_long_short,
+                false => &self.dsp.windows.short_long_short,
+            };
+
+            (long_n, &mut self.dsp.imdct_long, window)
+        }
+        else {
+            let n = 1 << self.ident.bs0_exp;
+            (n, &mut self.dsp.imdct_short, &self.dsp.windows.short)
+        };
+
+        self.buf.clear();
+
+        if let Some(prev_win) = &self.dsp.lapping_state {
+            let render_len = (prev_win.prev_block_size >> 2) + (n >> 2);
+            self.buf.render_reserved(Some(render_len));
+        }
+
+        for (i, channel) in self.dsp.channels.iter_mut().enumerate() {
+            channel.floor[..n >> 1].fill(0.0);
+            channel.synth(n, &self.dsp.lapping_state, window, imdct, self.buf.chan_mut(i));
+        }
+
+        self.dsp.lapping_state = Some(LappingState {
+            prev_block_size: n,
+            prev_win_right: window.right
+        });
+
+        self.concealer.conceal(&mut self.buf);
     }
 }
 
@@ -0,0 +1,538 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::{decode_error, unsupported_error, Result};
+use symphonia_core::io::{BitReaderRtl, ReadBitsRtl};
+
+use crate::decorr::*;
+use crate::header::*;
+use crate::words::WordsDecoder;
+
+// Floating point information flags.
+const FLOAT_SHIFT_ONES: u8 = 0x1;
+const FLOAT_SHIFT_SAME: u8 = 0x2;
+const FLOAT_SHIFT_SENT: u8 = 0x4;
+const FLOAT_ZEROS_SENT: u8 = 0x8;
+const FLOAT_NEG_ZEROS: u8 = 0x10;
+
+/// The initial value of the sample checksums.
+const CRC_INIT: u32 = 0xffff_ffff;
+
+/// Information required to restore 32-bit integer samples that exceed the precision of the
+/// entropy coder.
+#[derive(Copy, Clone, Default)]
+struct Int32Info {
+    sent_bits: u32,
+    zeros: u32,
+    ones: u32,
+    dups: u32,
+}
+
+/// Information required to restore floating point samples from integers.
+#[derive(Copy, Clone, Default)]
+pub struct FloatInfo {
+    flags: u8,
+    shift: u32,
+    max_exp: u32,
+    /// The exponent of a full-scale sample.
+    pub norm_exp: u32,
+}
+
+/// Reads an extra bits bitstream and its checksum.
+fn read_wvx_bitstream(data: &[u8]) -> Result<(u32, BitReaderRtl<'_>)> {
+    if data.len() < 4 {
+        return decode_error("wavpack: invalid extended bitstream");
+    }
+
+    let crc = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+
+    Ok((crc, BitReaderRtl::new(&data[4..])))
+}
+
+/// Applies the shift and restores the discarded low-order bits of integer samples.
+fn fixup_int_samples(
+    flags: u32,
+    int32: Option<Int32Info>,
+    wvx: Option<&mut BitReaderRtl<'_>>,
+    is_lossy: bool,
+    buf: &mut [i32],
+) -> Result<u32> {
+    let mut shift = (flags & FLAG_SHIFT_MASK) >> FLAG_SHIFT_LSB;
+    let mut crc = CRC_INIT;
+
+    if let Some(info) = int32 {
+        let Int32Info { sent_bits, mut zeros, mut ones, mut dups } = info;
+
+        if let Some(wvx) = wvx {
+            // The extra bits were sent, the samples can be restored losslessly.
+            for sample in buf.iter_mut() {
+                let mut value = *sample;
+
+                if sent_bits > 0 {
+                    let bits = wvx.read_bits_leq32(sent_bits)?;
+                    value = value.wrapping_shl(sent_bits) | bits as i32;
+                }
+
+                value = restore_int32(value, zeros, ones, dups);
+
+                crc = crc
+                    .wrapping_mul(9)
+                    .wrapping_add((value as u32 & 0xffff).wrapping_mul(3))
+                    .wrapping_add((value as u32 >> 16) & 0xffff);
+
+                *sample = value;
+            }
+        }
+        else if sent_bits == 0 && zeros + ones + dups > 0 {
+            // Without the extra bits, trade the lost precision of lossy samples for a shift.
+            while is_lossy && flags & FLAG_BYTES_STORED == 3 && shift < 8 {
+                if zeros > 0 {
+                    zeros -= 1;
+                }
+                else if ones > 0 {
+                    ones -= 1;
+                }
+                else if dups > 0 {
+                    dups -= 1;
+                }
+                else {
+                    break;
+                }
+
+                shift += 1;
+            }
+
+            for sample in buf.iter_mut() {
+                *sample = restore_int32(*sample, zeros, ones, dups);
+            }
+        }
+        else {
+            shift += zeros + sent_bits + ones + dups;
+        }
+    }
+
+    let shift = shift.min(31);
+
+    if is_lossy {
+        // Lossy samples may exceed the range of the stored sample width, clip them.
+        let (min, max) = match flags & FLAG_BYTES_STORED {
+            0 => (-128, 127),
+            1 => (-32768, 32767),
+            2 => (-8_388_608, 8_388_607),
+            _ => (i32::MIN, i32::MAX),
+        };
+
+        let (min, max) = (min >> shift, max >> shift);
+
+        for sample in buf.iter_mut() {
+            *sample = (*sample).max(min).min(max) << shift;
+        }
+    }
+    else if shift > 0 {
+        for sample in buf.iter_mut() {
+            *sample = sample.wrapping_shl(shift);
+        }
+    }
+
+    Ok(crc)
+}
+
+/// Restores the low-order bits of a 32-bit integer sample that are all zeros, ones, or a
+/// duplicate of the lowest-order bit.
+fn restore_int32(value: i32, zeros: u32, ones: u32, dups: u32) -> i32 {
+    if zeros > 0 {
+        value.wrapping_shl(zeros)
+    }
+    else if ones > 0 {
+        value.wrapping_add(1).wrapping_shl(ones).wrapping_sub(1)
+    }
+    else if dups > 0 {
+        let lsb = value & 1;
+        value.wrapping_add(lsb).wrapping_shl(dups).wrapping_sub(lsb)
+    }
+    else {
+        value
+    }
+}
+
+/// Converts integer samples to the bits of floating point samples.
+fn float_values(
+    info: &FloatInfo,
+    wvx: Option<&mut BitReaderRtl<'_>>,
+    buf: &mut [i32],
+) -> Result<u32> {
+    let mut crc = CRC_INIT;
+
+    match wvx {
+        Some(wvx) => {
+            for sample in buf.iter_mut() {
+                let bits = float_value_wvx(info, wvx, *sample)?;
+
+                crc = crc
+                    .wrapping_mul(27)
+                    .wrapping_add((bits & 0x7f_ffff).wrapping_mul(9))
+                    .wrapping_add(((bits >> 23) & 0xff) * 3)
+                    .wrapping_add(bits >> 31);
+
+                *sample = bits as i32;
+            }
+        }
+        None => {
+            for sample in buf.iter_mut() {
+                *sample = float_value(info, *sample) as i32;
+            }
+        }
+    }
+
+    Ok(crc)
+}
+
+/// Packs the sign, exponent, and mantissa of a single precision float.
+fn make_float(sign: bool, exp: u32, mantissa: u32) -> u32 {
+    (sign as u32) << 31 | (exp & 0xff) << 23 | (mantissa & 0x7f_ffff)
+}
+
+/// Converts an integer sample to the bits of a float sample without any extra information.
+fn float_value(info: &FloatInfo, value: i32) -> u32 {
+    if value == 0 {
+        return 0;
+    }
+
+    let mut value = value.wrapping_shl(info.shift);
+    let mut exp = info.max_exp;
+
+    let sign = value < 0;
+
+    if sign {
+        value = value.wrapping_neg();
+    }
+
+    let mut value = value as u32;
+
+    if value >= 0x100_0000 {
+        while value & 0xf00_0000 != 0 {
+            value >>= 1;
+            exp += 1;
+        }
+    }
+    else if exp > 0 {
+        let mut shift_count = 0;
+
+        while value & 0x80_0000 == 0 {
+            exp -= 1;
+
+            if exp == 0 {
+                break;
+            }
+
+            shift_count += 1;
+            value <<= 1;
+        }
+
+        if shift_count > 0 && info.flags & FLOAT_SHIFT_ONES != 0 {
+            value |= (1 << shift_count) - 1;
+        }
+    }
+
+    make_float(sign, exp, value)
+}
+
+/// Converts an integer sample to the bits of a float sample using the extra information from the
+/// extended bitstream.
+fn float_value_wvx(info: &FloatInfo, wvx: &mut BitReaderRtl<'_>, value: i32) -> Result<u32> {
+    let mut exp = info.max_exp;
+
+    if value == 0 {
+        let mut bits = 0;
+
+        if info.flags & FLOAT_ZEROS_SENT != 0 {
+            if wvx.read_bit()? {
+                let mantissa = wvx.read_bits_leq32(23)?;
+
+                let exp = if exp >= 25 { wvx.read_bits_leq32(8)? } else { 0 };
+
+                bits = make_float(wvx.read_bit()?, exp, mantissa);
+            }
+            else if info.flags & FLOAT_NEG_ZEROS != 0 {
+                bits = make_float(wvx.read_bit()?, 0, 0);
+            }
+        }
+
+        return Ok(bits);
+    }
+
+    let mut value = value.wrapping_shl(info.shift);
+
+    let sign = value < 0;
+
+    if sign {
+        value = value.wrapping_neg();
+    }
+
+    let mut value = value as u32;
+
+    if value == 0x100_0000 {
+        // An infinity or not-a-number.
+        let mantissa = if wvx.read_bit()? { wvx.read_bits_leq32(23)? } else { 0 };
+
+        return Ok(make_float(sign, 255, mantissa));
+    }
+
+    let mut shift_count = 0;
+
+    if exp > 0 {
+        while value & 0x80_0000 == 0 {
+            exp -= 1;
+
+            if exp == 0 {
+                break;
+            }
+
+            shift_count += 1;
+            value <<= 1;
+        }
+    }
+
+    if shift_count > 0 {
+        if info.flags & FLOAT_SHIFT_ONES != 0
+            || (info.flags & FLOAT_SHIFT_SAME != 0 && wvx.read_bit()?)
+        {
+            value |= (1 << shift_count) - 1;
+        }
+        else if info.flags & FLOAT_SHIFT_SENT != 0 {
+            value |= wvx.read_bits_leq32(shift_count)? & ((1 << shift_count) - 1);
+        }
+    }
+
+    Ok(make_float(sign, exp, value))
+}
+
+/// The format of the samples decoded from a block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleKind {
+    /// Integer samples stored in the given number of bytes.
+    Int(u32),
+    /// The bits of single precision floating point samples.
+    Float,
+}
+
+/// The result of decoding a block.
+pub struct DecodedBlock {
+    /// The number of channels decoded.
+    pub n_channels: usize,
+    /// The format of the decoded samples.
+    pub kind: SampleKind,
+    /// The floating point information if the samples are floating point.
+    pub float_info: Option<FloatInfo>,
+}
+
+/// Decodes a block, and optionally its correction block, into `buf` as interleaved samples.
+pub fn decode_block(
+    block: &Block<'_>,
+    correction: Option<&Block<'_>>,
+    buf: &mut Vec<i32>,
+) -> Result<DecodedBlock> {
+    let header = &block.header;
+    let flags = header.flags;
+
+    if flags & FLAG_DSD != 0 {
+        return unsupported_error("wavpack: dsd audio is not supported");
+    }
+
+    let is_mono = header.is_mono_data();
+    let is_hybrid = flags & FLAG_HYBRID != 0;
+
+    let mut passes = Vec::new();
+    let mut words = WordsDecoder::new(flags);
+    let mut int32 = None;
+    let mut float_info = None;
+    let mut wv = None;
+    let mut wvx = None;
+
+    let mut has_entropy_vars = false;
+
+    for sub_block in block.sub_blocks.iter() {
+        let data = sub_block.data;
+
+        match sub_block.id {
+            ID_DECORR_TERMS => passes = read_decorr_terms(data, is_mono)?,
+            ID_DECORR_WEIGHTS => read_decorr_weights(&mut passes, data, is_mono)?,
+            ID_DECORR_SAMPLES => {
+                read_decorr_samples(&mut passes, data, is_mono, header.version, is_hybrid)?
+            }
+            ID_ENTROPY_VARS => {
+                words.read_entropy_vars(data)?;
+                has_entropy_vars = true;
+            }
+            ID_HYBRID_PROFILE => words.read_hybrid_profile(data)?,
+            ID_INT32_INFO => {
+                if data.len() != 4 {
+                    return decode_error("wavpack: invalid int32 information");
+                }
+
+                int32 = Some(Int32Info {
+                    sent_bits: u32::from(data[0]),
+                    zeros: u32::from(data[1]),
+                    ones: u32::from(data[2]),
+                    dups: u32::from(data[3]),
+                });
+            }
+            ID_FLOAT_INFO => {
+                if data.len() != 4 {
+                    return decode_error("wavpack: invalid float information");
+                }
+
+                float_info = Some(FloatInfo {
+                    flags: data[0],
+                    shift: u32::from(data[1]),
+                    max_exp: u32::from(data[2]),
+                    norm_exp: u32::from(data[3]),
+                });
+            }
+            ID_WV_BITSTREAM => wv = Some(BitReaderRtl::new(data)),
+            ID_WVX_BITSTREAM => wvx = Some(read_wvx_bitstream(data)?),
+            _ => (),
+        }
+    }
+
+    let n_samples = header.block_samples as usize;
+
+    let mut wv = match wv {
+        Some(wv) if has_entropy_vars => wv,
+        _ => return decode_error("wavpack: block is missing the bitstream"),
+    };
+
+    // The correction block provides the correction bitstream and, for 32-bit integer and floating
+    // point samples, the extra bits bitstream.
+    let mut wvc = None;
+    let mut expected_crc = header.crc;
+
+    if let Some(correction) = correction {
+        if is_hybrid {
+            for sub_block in correction.sub_blocks.iter() {
+                match sub_block.id {
+                    ID_WVC_BITSTREAM => wvc = Some(BitReaderRtl::new(sub_block.data)),
+                    ID_WVX_BITSTREAM => wvx = Some(read_wvx_bitstream(sub_block.data)?),
+                    _ => (),
+                }
+            }
+
+            // The checksum of the correction block is of the losslessly decoded samples.
+            if wvc.is_some() {
+                expected_crc = correction.header.crc;
+            }
+        }
+    }
+
+    let is_lossy = is_hybrid && wvc.is_none();
+
+    let n_coded_chans = if is_mono { 1 } else { 2 };
+
+    buf.clear();
+    buf.resize(n_coded_chans * n_samples, 0);
+
+    words.read_words(&mut wv, wvc.as_mut(), buf)?;
+
+    if is_mono {
+        for pass in passes.iter_mut() {
+            decorr_mono_pass(pass, buf);
+        }
+    }
+    else {
+        for pass in passes.iter_mut() {
+            decorr_stereo_pass(pass, buf);
+        }
+
+        if flags & FLAG_JOINT_STEREO != 0 {
+            for frame in buf.chunks_exact_mut(2) {
+                frame[1] = frame[1].wrapping_sub(frame[0] >> 1);
+                frame[0] = frame[0].wrapping_add(frame[1]);
+            }
+        }
+    }
+
+    // Mute the block if any sample exceeds the magnitude signalled by the header, since it is
+    // certainly corrupt.
+    let mut mute_limit = (1i64 << ((flags & FLAG_MAG_MASK) >> FLAG_MAG_LSB)) + 2;
+
+    if is_lossy {
+        mute_limit *= 2;
+    }
+
+    if buf.iter().any(|&sample| i64::from(sample).abs() > mute_limit) {
+        return decode_error("wavpack: sample exceeds the magnitude of the block");
+    }
+
+    let crc = buf.iter().fold(CRC_INIT, |crc, &sample| {
+        crc.wrapping_mul(3).wrapping_add(sample as u32)
+    });
+
+    if crc != expected_crc {
+        return decode_error("wavpack: block checksum mismatch");
+    }
+
+    let mut wvx_reader = wvx.as_mut().map(|(_, wvx)| wvx);
+
+    let (kind, crc_x) = if flags & FLAG_FLOAT_DATA != 0 {
+        let info = match float_info {
+            Some(ref info) => info,
+            _ => return decode_error("wavpack: block is missing the float information"),
+        };
+
+        (SampleKind::Float, float_values(info, wvx_reader.take(), buf)?)
+    }
+    else {
+        let int32 = if flags & FLAG_INT32_DATA != 0 { int32 } else { None };
+        let crc_x = fixup_int_samples(flags, int32, wvx_reader.take(), is_lossy, buf)?;
+
+        (SampleKind::Int(header.bytes_per_sample()), crc_x)
+    };
+
+    if let Some((expected_crc_x, _)) = wvx {
+        if crc_x != expected_crc_x {
+            return decode_error("wavpack: extended block checksum mismatch");
+        }
+    }
+
+    // Duplicate mono samples for false stereo blocks.
+    let n_channels = header.n_channels();
+
+    if n_channels == 2 && is_mono {
+        buf.resize(2 * n_samples, 0);
+
+        for i in (0..n_samples).rev() {
+            buf[2 * i + 1] = buf[i];
+            buf[2 * i] = buf[i];
+        }
+    }
+
+    Ok(DecodedBlock { n_channels, kind, float_info })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_restore_int32() {
+        assert_eq!(restore_int32(3, 2, 0, 0), 12);
+        assert_eq!(restore_int32(3, 0, 2, 0), 15);
+        assert_eq!(restore_int32(3, 0, 0, 2), 15);
+        assert_eq!(restore_int32(2, 0, 0, 2), 8);
+    }
+
+    #[test]
+    fn verify_float_value() {
+        let info = FloatInfo { flags: 0, shift: 0, max_exp: 127, norm_exp: 127 };
+
+        // The maximum exponent is that of an integer with bit 23 set.
+        assert_eq!(f32::from_bits(float_value(&info, 0x80_0000)), 1.0);
+        assert_eq!(f32::from_bits(float_value(&info, -0x40_0000)), -0.5);
+        assert_eq!(f32::from_bits(float_value(&info, 0x60_0000)), 0.75);
+        assert_eq!(float_value(&info, 0), 0);
+    }
+}
@@ -0,0 +1,284 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec};
+use symphonia_core::codecs::{CodecDescriptor, CodecParameters, VerificationCheck};
+use symphonia_core::codecs::{Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_WAVPACK};
+use symphonia_core::errors::{decode_error, unsupported_error, Result};
+use symphonia_core::formats::Packet;
+use symphonia_core::support_codec;
+
+use log::{debug, log_enabled, warn};
+
+use crate::block::{decode_block, SampleKind};
+use crate::header::{read_blocks, Block, FLAG_FLOAT_DATA, FLAG_HYBRID};
+use crate::header::{ID_WVC_BITSTREAM, ID_WV_BITSTREAM};
+use crate::layout::channel_map;
+use crate::validate::Validator;
+
+/// The default number of frames of the audio buffer if the maximum frames per packet is unknown.
+const DEFAULT_MAX_FRAMES: u64 = 22050;
+
+/// Scales the exponent of the bits of a single precision float by `delta_exp`.
+fn float_normalize(bits: u32, delta_exp: i32) -> f32 {
+    let exp = ((bits >> 23) & 0xff) as i32;
+
+    let bits = if exp == 0 || exp + delta_exp <= 0 {
+        0
+    }
+    else if exp == 255 || exp + delta_exp >= 255 {
+        (bits & 0x8000_0000) | 0x7f80_0000
+    }
+    else {
+        (bits & 0x807f_ffff) | ((exp + delta_exp) as u32) << 23
+    };
+
+    f32::from_bits(bits)
+}
+
+/// WavPack decoder.
+///
+/// A packet is a sequence of blocks sharing the same block index, each coding one or two
+/// channels, optionally followed by the correction blocks of any hybrid blocks.
+pub struct WavPackDecoder {
+    params: CodecParameters,
+    is_validating: bool,
+    validator: Validator,
+    /// The audio buffer plane of each coded channel.
+    chan_map: Vec<usize>,
+    /// Decoded samples in the sample width of the stream.
+    buf: AudioBuffer<i32>,
+    /// Decoded floating point samples.
+    float_buf: Option<AudioBuffer<f32>>,
+    /// Interleaved samples of a single block.
+    block_buf: Vec<i32>,
+}
+
+impl WavPackDecoder {
+    /// Ensures the audio buffers can hold `n_frames` frames.
+    fn reserve(&mut self, n_frames: usize, is_float: bool) {
+        let spec = *self.buf.spec();
+
+        if n_frames > self.buf.capacity() {
+            self.buf = AudioBuffer::new(n_frames as u64, spec);
+        }
+
+        if is_float {
+            let capacity = self.buf.capacity() as u64;
+
+            match self.float_buf {
+                Some(ref buf) if buf.capacity() as u64 >= capacity => (),
+                _ => self.float_buf = Some(AudioBuffer::new(capacity, spec)),
+            }
+        }
+    }
+}
+
+impl Decoder for WavPackDecoder {
+    fn try_new(params: &CodecParameters, options: &DecoderOptions) -> Result<Self> {
+        let sample_rate = match params.sample_rate {
+            Some(rate) => rate,
+            None => return unsupported_error("wavpack: sample rate is required"),
+        };
+
+        let channels = match params.channels {
+            Some(channels) => channels,
+            None => return unsupported_error("wavpack: channels are required"),
+        };
+
+        let spec = SignalSpec::new(sample_rate, channels);
+
+        let max_frames = params.max_frames_per_packet.unwrap_or(DEFAULT_MAX_FRAMES);
+
+        Ok(WavPackDecoder {
+            params: params.clone(),
+            is_validating: options.verify,
+            validator: Default::default(),
+            chan_map: channel_map(channels),
+            buf: AudioBuffer::new(max_frames, spec),
+            float_buf: None,
+            block_buf: Vec::new(),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_WAVPACK, "wavpack", "WavPack")]
+    }
+
+    fn reset(&mut self) {
+        // Every block is independently decodable, therefore do nothing.
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        let blocks = read_blocks(packet.buf())?;
+
+        // Correction blocks only contain a correction bitstream, while audio blocks always
+        // contain a bitstream. Blocks without samples only contain metadata.
+        let is_correction = |block: &Block<'_>| {
+            block.find(ID_WVC_BITSTREAM).is_some() && block.find(ID_WV_BITSTREAM).is_none()
+        };
+
+        let mut corrections = blocks.iter().filter(|block| is_correction(block)).peekable();
+
+        let audio_blocks: Vec<&Block<'_>> = blocks
+            .iter()
+            .filter(|block| !is_correction(block) && block.header.block_samples > 0)
+            .collect();
+
+        let n_frames = match audio_blocks.first() {
+            Some(block) => block.header.block_samples as usize,
+            None => {
+                self.buf.clear();
+                return Ok(self.buf.as_audio_buffer_ref());
+            }
+        };
+
+        let is_float = audio_blocks.iter().any(|block| block.header.flags & FLAG_FLOAT_DATA != 0);
+
+        self.reserve(n_frames, is_float);
+
+        self.buf.clear();
+        self.buf.render_reserved(Some(n_frames));
+
+        let mut chan = 0;
+        let mut kind = SampleKind::Float;
+        let mut norm_exp = 127;
+
+        for block in audio_blocks {
+            let header = &block.header;
+
+            if header.block_samples as usize != n_frames {
+                return decode_error("wavpack: blocks have a different number of samples");
+            }
+
+            // Correction blocks follow the same order as the hybrid blocks they correct.
+            let correction = match corrections.peek() {
+                Some(correction)
+                    if header.flags & FLAG_HYBRID != 0
+                        && correction.header.block_index == header.block_index =>
+                {
+                    corrections.next()
+                }
+                _ => None,
+            };
+
+            let decoded = decode_block(block, correction, &mut self.block_buf)?;
+
+            if chan + decoded.n_channels > self.chan_map.len() {
+                return decode_error("wavpack: too many channels");
+            }
+
+            for c in 0..decoded.n_channels {
+                let plane = self.buf.chan_mut(self.chan_map[chan + c]);
+
+                for (out, &sample) in
+                    plane.iter_mut().zip(self.block_buf.iter().skip(c).step_by(decoded.n_channels))
+                {
+                    *out = sample;
+                }
+            }
+
+            chan += decoded.n_channels;
+            kind = decoded.kind;
+
+            if let Some(info) = decoded.float_info {
+                norm_exp = info.norm_exp as i32;
+            }
+        }
+
+        if chan != self.chan_map.len() {
+            return decode_error("wavpack: too few channels");
+        }
+
+        // Feed the validator if validation is enabled.
+        if self.is_validating {
+            self.validator.update(&self.buf, &self.chan_map, kind);
+        }
+
+        match kind {
+            SampleKind::Int(bytes) => {
+                // Shift all samples such that the output is always 32 bits per sample.
+                if bytes < 4 {
+                    let shift = 32 - 8 * bytes;
+                    self.buf.transform(|sample| sample << shift);
+                }
+
+                Ok(self.buf.as_audio_buffer_ref())
+            }
+            SampleKind::Float => {
+                // Scale the samples such that full-scale is +/- 1.0.
+                let delta_exp = 127 - norm_exp;
+
+                let float_buf = self.float_buf.as_mut().unwrap();
+
+                float_buf.clear();
+                float_buf.render_reserved(Some(n_frames));
+
+                for plane in 0..self.chan_map.len() {
+                    for (out, &bits) in
+                        float_buf.chan_mut(plane).iter_mut().zip(self.buf.chan(plane))
+                    {
+                        *out = float_normalize(bits as u32, delta_exp);
+                    }
+                }
+
+                Ok(float_buf.as_audio_buffer_ref())
+            }
+        }
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        let mut result: FinalizeResult = Default::default();
+
+        // If verifying...
+        if self.is_validating {
+            // Try to get the expected MD5 checksum and compare it against the decoded checksum.
+            if let Some(VerificationCheck::Md5(expected)) = self.params.verification_check {
+                let decoded = self.validator.md5();
+
+                // Only generate the expected and decoded MD5 checksum strings if logging is
+                // enabled at the debug level.
+                if log_enabled!(log::Level::Debug) {
+                    use std::fmt::Write;
+
+                    let mut expected_s = String::with_capacity(32);
+                    let mut decoded_s = String::with_capacity(32);
+
+                    expected.iter().for_each(|b| write!(expected_s, "{:02x}", b).unwrap());
+                    decoded.iter().for_each(|b| write!(decoded_s, "{:02x}", b).unwrap());
+
+                    debug!("verification: expected md5 = {}", expected_s);
+                    debug!("verification: decoded md5  = {}", decoded_s);
+                }
+
+                result.verify_ok = Some(decoded == expected)
+            }
+            else {
+                warn!("verification requested but the expected md5 checksum was not provided");
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::float_normalize;
+
+    #[test]
+    fn verify_float_normalize() {
+        assert_eq!(float_normalize(1.0f32.to_bits(), -1), 0.5);
+        assert_eq!(float_normalize((-2.0f32).to_bits(), 2), -8.0);
+        assert_eq!(float_normalize(1.0f32.to_bits(), 200), f32::INFINITY);
+        assert_eq!(float_normalize(1.0f32.to_bits(), -200), 0.0);
+    }
+}
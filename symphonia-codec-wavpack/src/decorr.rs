@@ -0,0 +1,365 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decorrelation passes.
+//!
+//! Each pass is an adaptive single-tap predictor. Positive terms 1 to 8 predict from the sample
+//! `term` samples prior in the same channel, terms 17 and 18 extrapolate from the previous two
+//! samples, and the negative terms of stereo blocks predict across channels.
+
+use symphonia_core::errors::{decode_error, Result};
+
+use crate::words::read_exp2s;
+
+/// The maximum number of decorrelation passes.
+const MAX_TERMS: usize = 16;
+
+/// The maximum sample history of a decorrelation pass.
+const MAX_TERM: usize = 8;
+
+/// A decorrelation pass.
+#[derive(Copy, Clone, Default)]
+pub struct DecorrPass {
+    term: i32,
+    delta: i32,
+    weight_a: i32,
+    weight_b: i32,
+    samples_a: [i32; MAX_TERM],
+    samples_b: [i32; MAX_TERM],
+}
+
+/// Restores a weight from its 8-bit stored form.
+fn restore_weight(weight: i8) -> i32 {
+    let weight = i32::from(weight) << 3;
+    if weight > 0 { weight + ((weight + 64) >> 7) } else { weight }
+}
+
+#[inline(always)]
+fn apply_weight(weight: i32, sample: i32) -> i32 {
+    ((i64::from(weight) * i64::from(sample) + 512) >> 10) as i32
+}
+
+#[inline(always)]
+fn update_weight(weight: &mut i32, delta: i32, source: i32, result: i32) {
+    if source != 0 && result != 0 {
+        if (source ^ result) < 0 {
+            *weight -= delta;
+        }
+        else {
+            *weight += delta;
+        }
+    }
+}
+
+#[inline(always)]
+fn update_weight_clip(weight: &mut i32, delta: i32, source: i32, result: i32) {
+    if source != 0 && result != 0 {
+        if (source ^ result) < 0 {
+            *weight = (*weight - delta).max(-1024);
+        }
+        else {
+            *weight = (*weight + delta).min(1024);
+        }
+    }
+}
+
+/// Reads the decorrelation terms and deltas. The passes are stored in reverse order.
+pub fn read_decorr_terms(data: &[u8], is_mono: bool) -> Result<Vec<DecorrPass>> {
+    if data.len() > MAX_TERMS {
+        return decode_error("wavpack: too many decorrelation terms");
+    }
+
+    let mut passes = Vec::with_capacity(data.len());
+
+    for &byte in data.iter().rev() {
+        let term = i32::from(byte & 0x1f) - 5;
+        let delta = i32::from(byte >> 5) & 0x7;
+
+        let is_valid = match term {
+            1..=8 | 17 | 18 => true,
+            -3..=-1 => !is_mono,
+            _ => false,
+        };
+
+        if !is_valid {
+            return decode_error("wavpack: invalid decorrelation term");
+        }
+
+        passes.push(DecorrPass { term, delta, ..Default::default() });
+    }
+
+    Ok(passes)
+}
+
+/// Reads the initial weights of the decorrelation passes. Weights are stored starting from the
+/// final pass, and any passes without a stored weight have an initial weight of 0.
+pub fn read_decorr_weights(passes: &mut [DecorrPass], data: &[u8], is_mono: bool) -> Result<()> {
+    let n_weights = if is_mono { data.len() } else { data.len() / 2 };
+
+    if n_weights > passes.len() {
+        return decode_error("wavpack: too many decorrelation weights");
+    }
+
+    let step = if is_mono { 1 } else { 2 };
+
+    for (pass, buf) in passes.iter_mut().rev().zip(data.chunks_exact(step)) {
+        pass.weight_a = restore_weight(buf[0] as i8);
+
+        if !is_mono {
+            pass.weight_b = restore_weight(buf[1] as i8);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the initial sample history of the decorrelation passes. Histories are stored starting
+/// from the final pass.
+pub fn read_decorr_samples(
+    passes: &mut [DecorrPass],
+    data: &[u8],
+    is_mono: bool,
+    version: u16,
+    is_hybrid: bool,
+) -> Result<()> {
+    let mut rem = data;
+
+    // Early hybrid streams store the encoder's noise shaping error first.
+    if version == 0x402 && is_hybrid {
+        let skip = if is_mono { 2 } else { 4 };
+
+        if rem.len() < skip {
+            return decode_error("wavpack: invalid decorrelation samples");
+        }
+
+        rem = &rem[skip..];
+    }
+
+    for pass in passes.iter_mut().rev() {
+        if rem.is_empty() {
+            break;
+        }
+
+        let (n_a, n_b) = match pass.term {
+            17 | 18 if is_mono => (2, 0),
+            17 | 18 => (2, 2),
+            term if term < 0 => (1, 1),
+            term if is_mono => (term as usize, 0),
+            term => (term as usize, term as usize),
+        };
+
+        let len = 2 * (n_a + n_b);
+
+        if rem.len() < len {
+            return decode_error("wavpack: invalid decorrelation samples");
+        }
+
+        if pass.term < 0 {
+            pass.samples_a[0] = read_exp2s(&rem[0..2]);
+            pass.samples_b[0] = read_exp2s(&rem[2..4]);
+        }
+        else if pass.term > MAX_TERM as i32 {
+            pass.samples_a[0] = read_exp2s(&rem[0..2]);
+            pass.samples_a[1] = read_exp2s(&rem[2..4]);
+
+            if !is_mono {
+                pass.samples_b[0] = read_exp2s(&rem[4..6]);
+                pass.samples_b[1] = read_exp2s(&rem[6..8]);
+            }
+        }
+        else {
+            // The sample histories of the channels are interleaved.
+            let step = if is_mono { 2 } else { 4 };
+
+            for (m, buf) in rem[..len].chunks_exact(step).enumerate() {
+                pass.samples_a[m] = read_exp2s(&buf[0..2]);
+
+                if !is_mono {
+                    pass.samples_b[m] = read_exp2s(&buf[2..4]);
+                }
+            }
+        }
+
+        rem = &rem[len..];
+    }
+
+    if !rem.is_empty() {
+        return decode_error("wavpack: invalid decorrelation samples");
+    }
+
+    Ok(())
+}
+
+/// Applies a decorrelation pass to a single channel of residuals.
+pub fn decorr_mono_pass(pass: &mut DecorrPass, buf: &mut [i32]) {
+    let delta = pass.delta;
+    let mut weight_a = pass.weight_a;
+
+    match pass.term {
+        17 | 18 => {
+            for sample in buf.iter_mut() {
+                let [s0, s1] = [pass.samples_a[0], pass.samples_a[1]];
+
+                let sam = if pass.term == 17 {
+                    s0.wrapping_mul(2).wrapping_sub(s1)
+                }
+                else {
+                    s0.wrapping_mul(3).wrapping_sub(s1) >> 1
+                };
+
+                pass.samples_a[1] = s0;
+                pass.samples_a[0] = apply_weight(weight_a, sam).wrapping_add(*sample);
+                update_weight(&mut weight_a, delta, sam, *sample);
+                *sample = pass.samples_a[0];
+            }
+        }
+        term => {
+            let term = term as usize;
+
+            let mut m = 0;
+            let mut k = term & (MAX_TERM - 1);
+
+            for sample in buf.iter_mut() {
+                let sam = pass.samples_a[m];
+                pass.samples_a[k] = apply_weight(weight_a, sam).wrapping_add(*sample);
+                update_weight(&mut weight_a, delta, sam, *sample);
+                *sample = pass.samples_a[k];
+
+                m = (m + 1) & (MAX_TERM - 1);
+                k = (k + 1) & (MAX_TERM - 1);
+            }
+
+            // Rotate the history such that the most recent sample is at index term - 1.
+            pass.samples_a.rotate_left(m);
+        }
+    }
+
+    pass.weight_a = weight_a;
+}
+
+/// Applies a decorrelation pass to a pair of channels of interleaved residuals.
+pub fn decorr_stereo_pass(pass: &mut DecorrPass, buf: &mut [i32]) {
+    let delta = pass.delta;
+    let mut weight_a = pass.weight_a;
+    let mut weight_b = pass.weight_b;
+
+    match pass.term {
+        term @ 17 | term @ 18 => {
+            let predict = |s0: i32, s1: i32| {
+                if term == 17 {
+                    s0.wrapping_mul(2).wrapping_sub(s1)
+                }
+                else {
+                    s0.wrapping_mul(3).wrapping_sub(s1) >> 1
+                }
+            };
+
+            for frame in buf.chunks_exact_mut(2) {
+                let sam_a = predict(pass.samples_a[0], pass.samples_a[1]);
+                pass.samples_a[1] = pass.samples_a[0];
+                pass.samples_a[0] = apply_weight(weight_a, sam_a).wrapping_add(frame[0]);
+                update_weight(&mut weight_a, delta, sam_a, frame[0]);
+                frame[0] = pass.samples_a[0];
+
+                let sam_b = predict(pass.samples_b[0], pass.samples_b[1]);
+                pass.samples_b[1] = pass.samples_b[0];
+                pass.samples_b[0] = apply_weight(weight_b, sam_b).wrapping_add(frame[1]);
+                update_weight(&mut weight_b, delta, sam_b, frame[1]);
+                frame[1] = pass.samples_b[0];
+            }
+        }
+        -1 => {
+            for frame in buf.chunks_exact_mut(2) {
+                let sam_a = apply_weight(weight_a, pass.samples_a[0]).wrapping_add(frame[0]);
+                update_weight_clip(&mut weight_a, delta, pass.samples_a[0], frame[0]);
+                frame[0] = sam_a;
+
+                pass.samples_a[0] = apply_weight(weight_b, sam_a).wrapping_add(frame[1]);
+                update_weight_clip(&mut weight_b, delta, sam_a, frame[1]);
+                frame[1] = pass.samples_a[0];
+            }
+        }
+        -2 => {
+            for frame in buf.chunks_exact_mut(2) {
+                let sam_b = apply_weight(weight_b, pass.samples_b[0]).wrapping_add(frame[1]);
+                update_weight_clip(&mut weight_b, delta, pass.samples_b[0], frame[1]);
+                frame[1] = sam_b;
+
+                pass.samples_b[0] = apply_weight(weight_a, sam_b).wrapping_add(frame[0]);
+                update_weight_clip(&mut weight_a, delta, sam_b, frame[0]);
+                frame[0] = pass.samples_b[0];
+            }
+        }
+        -3 => {
+            for frame in buf.chunks_exact_mut(2) {
+                let sam_a = apply_weight(weight_a, pass.samples_a[0]).wrapping_add(frame[0]);
+                update_weight_clip(&mut weight_a, delta, pass.samples_a[0], frame[0]);
+
+                let sam_b = apply_weight(weight_b, pass.samples_b[0]).wrapping_add(frame[1]);
+                update_weight_clip(&mut weight_b, delta, pass.samples_b[0], frame[1]);
+
+                pass.samples_b[0] = sam_a;
+                pass.samples_a[0] = sam_b;
+
+                frame[0] = sam_a;
+                frame[1] = sam_b;
+            }
+        }
+        term => {
+            let term = term as usize;
+
+            let mut m = 0;
+            let mut k = term & (MAX_TERM - 1);
+
+            for frame in buf.chunks_exact_mut(2) {
+                let sam_a = pass.samples_a[m];
+                pass.samples_a[k] = apply_weight(weight_a, sam_a).wrapping_add(frame[0]);
+                update_weight(&mut weight_a, delta, sam_a, frame[0]);
+                frame[0] = pass.samples_a[k];
+
+                let sam_b = pass.samples_b[m];
+                pass.samples_b[k] = apply_weight(weight_b, sam_b).wrapping_add(frame[1]);
+                update_weight(&mut weight_b, delta, sam_b, frame[1]);
+                frame[1] = pass.samples_b[k];
+
+                m = (m + 1) & (MAX_TERM - 1);
+                k = (k + 1) & (MAX_TERM - 1);
+            }
+
+            pass.samples_a.rotate_left(m);
+            pass.samples_b.rotate_left(m);
+        }
+    }
+
+    pass.weight_a = weight_a;
+    pass.weight_b = weight_b;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_restore_weight() {
+        assert_eq!(restore_weight(0), 0);
+        assert_eq!(restore_weight(-128), -1024);
+        assert_eq!(restore_weight(127), 1024);
+        assert_eq!(restore_weight(64), 516);
+    }
+
+    #[test]
+    fn verify_decorr_mono_pass() {
+        // With a full weight and a term of 1, the pass is a first-order integrator.
+        let mut pass = DecorrPass { term: 1, delta: 0, weight_a: 1024, ..Default::default() };
+        let mut buf = [1, 1, 1, -3];
+
+        decorr_mono_pass(&mut pass, &mut buf);
+
+        assert_eq!(buf, [1, 2, 3, 0]);
+        assert_eq!(pass.samples_a[0], 0);
+    }
+}
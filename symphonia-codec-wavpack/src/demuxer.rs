@@ -0,0 +1,499 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{Seek, SeekFrom};
+
+use symphonia_core::audio::Channels;
+use symphonia_core::codecs::{CodecParameters, VerificationCheck, CODEC_TYPE_WAVPACK};
+use symphonia_core::errors::{decode_error, seek_error, Error, Result, SeekErrorKind};
+use symphonia_core::formats::prelude::*;
+use symphonia_core::io::*;
+use symphonia_core::meta::{Metadata, MetadataLog};
+use symphonia_core::probe::{Descriptor, Instantiate, QueryDescriptor};
+use symphonia_core::sample::SampleFormat;
+use symphonia_core::support_format;
+
+use log::{debug, warn};
+
+use crate::header::*;
+use crate::layout::channels_from_mask;
+
+/// The number of bytes at the end of the stream that are searched for the MD5 checksum. The
+/// checksum is stored in a metadata-only block following the final audio block, which may itself
+/// be followed by tags.
+const TAIL_SCAN_LEN: u64 = 256 * 1024;
+
+/// When the byte range of a binary search becomes smaller than this, the search continues
+/// linearly.
+const LINEAR_SEARCH_LEN: u64 = 64 * 1024;
+
+/// A frame, one or more consecutive blocks sharing the same block index that together code all
+/// channels.
+struct Frame {
+    /// The position of the first block of the frame.
+    pos: u64,
+    /// The header of the first block of the frame.
+    header: BlockHeader,
+    /// The blocks of the frame.
+    data: Vec<u8>,
+}
+
+/// Reads the next block, synchronizing to the block marker if required. Returns the position of
+/// the block, its header, and the entire block including the header.
+fn read_block(reader: &mut MediaSourceStream) -> Result<(u64, BlockHeader, Vec<u8>)> {
+    let mut marker = reader.read_quad_bytes()?;
+
+    loop {
+        if marker == BLOCK_MARKER {
+            let pos = reader.pos() - 4;
+
+            let mut buf = vec![0; BLOCK_HEADER_LEN];
+            buf[..4].copy_from_slice(&BLOCK_MARKER);
+            reader.read_buf_exact(&mut buf[4..])?;
+
+            match BlockHeader::read_after_marker(&mut BufReader::new(&buf[4..])) {
+                Ok(header) => {
+                    buf.resize(header.block_len as usize, 0);
+                    reader.read_buf_exact(&mut buf[BLOCK_HEADER_LEN..])?;
+
+                    return Ok((pos, header, buf));
+                }
+                Err(_) => {
+                    // A false marker, rescan from the byte following the marker.
+                    reader.seek_buffered(pos + 1);
+                }
+            }
+        }
+
+        marker = [marker[1], marker[2], marker[3], reader.read_byte()?];
+    }
+}
+
+/// Reads the next frame. Blocks without samples, and blocks of an incomplete frame, are skipped.
+fn read_frame(reader: &mut MediaSourceStream) -> Result<Frame> {
+    let mut frame: Option<Frame> = None;
+
+    loop {
+        let (pos, header, buf) = read_block(reader)?;
+
+        if header.block_samples == 0 {
+            continue;
+        }
+
+        frame = match frame {
+            Some(mut frame)
+                if frame.header.block_index == header.block_index
+                    && header.flags & FLAG_INITIAL_BLOCK == 0 =>
+            {
+                frame.data.extend_from_slice(&buf);
+                Some(frame)
+            }
+            _ if header.flags & FLAG_INITIAL_BLOCK != 0 => {
+                Some(Frame { pos, header, data: buf })
+            }
+            _ => {
+                debug!("skipping block at {} without an initial block", pos);
+                None
+            }
+        };
+
+        if header.flags & FLAG_FINAL_BLOCK != 0 {
+            if let Some(frame) = frame {
+                return Ok(frame);
+            }
+        }
+    }
+}
+
+/// Seeks to the frame containing the sample at `block_index`, and reads it. If there is no such
+/// frame, the first frame following it is read instead.
+fn seek_frame(reader: &mut MediaSourceStream, first_pos: u64, block_index: u64) -> Result<Frame> {
+    let contains = |frame: &Frame| {
+        let start = frame.header.block_index;
+        block_index >= start && block_index < start + u64::from(frame.header.block_samples)
+    };
+
+    if reader.is_seekable() {
+        // Coarsely seek to the frame using a binary search of the byte range of the stream.
+        let mut start = first_pos;
+        let mut end = reader.seek(SeekFrom::End(0))?;
+
+        while end - start > LINEAR_SEARCH_LEN {
+            let mid = (start + end) / 2;
+
+            reader.seek(SeekFrom::Start(mid))?;
+
+            let frame = match read_frame(reader) {
+                Ok(frame) if frame.pos < end => frame,
+                Ok(_) => {
+                    end = mid;
+                    continue;
+                }
+                Err(Error::IoError(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    end = mid;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if contains(&frame) {
+                return Ok(frame);
+            }
+            else if block_index < frame.header.block_index {
+                end = mid;
+            }
+            else {
+                start = frame.pos;
+            }
+        }
+
+        reader.seek(SeekFrom::Start(start))?;
+    }
+
+    // Linearly search for the frame. This is also the only way to seek forward if the stream is
+    // not seekable.
+    loop {
+        let frame = read_frame(reader)?;
+
+        if contains(&frame) || block_index < frame.header.block_index {
+            return Ok(frame);
+        }
+    }
+}
+
+/// Searches the tail of the stream for the MD5 checksum.
+fn read_tail_md5(reader: &mut MediaSourceStream, first_pos: u64) -> Result<Option<[u8; 16]>> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let start = end.saturating_sub(TAIL_SCAN_LEN).max(first_pos);
+
+    reader.seek(SeekFrom::Start(start))?;
+
+    let mut buf = vec![0; (end - start) as usize];
+    reader.read_buf_exact(&mut buf)?;
+
+    let mut md5 = None;
+
+    for pos in 0..buf.len().saturating_sub(BLOCK_HEADER_LEN) {
+        if buf[pos..pos + 4] != BLOCK_MARKER {
+            continue;
+        }
+
+        let mut block = &buf[pos..];
+
+        if let Ok(header) = BlockHeader::read(&mut BufReader::new(block)) {
+            if header.block_samples != 0 || header.block_len as usize > block.len() {
+                continue;
+            }
+
+            block = &block[..header.block_len as usize];
+
+            if let Ok(blocks) = read_blocks(block) {
+                if let Some(value) = blocks[0].find(ID_MD5_CHECKSUM) {
+                    md5 = read_md5(value);
+                }
+            }
+        }
+    }
+
+    Ok(md5)
+}
+
+/// Reads a MD5 checksum sub-block.
+fn read_md5(data: &[u8]) -> Option<[u8; 16]> {
+    if data.len() == 16 {
+        let mut md5 = [0; 16];
+        md5.copy_from_slice(data);
+        Some(md5)
+    }
+    else {
+        None
+    }
+}
+
+/// WavPack (`.wv`) format reader.
+///
+/// Each packet is a frame of blocks sharing the same block index. A hybrid stream may be read
+/// along with its correction (`.wvc`) file using [`WavPackReader::try_new_with_correction`], in
+/// which case the correction blocks are appended to each packet for lossless decoding.
+pub struct WavPackReader {
+    reader: MediaSourceStream,
+    correction: Option<MediaSourceStream>,
+    tracks: Vec<Track>,
+    cues: Vec<Cue>,
+    metadata: MetadataLog,
+    /// The position of the first block of the stream and correction stream.
+    first_pos: u64,
+    first_correction_pos: u64,
+    /// The block index of the first frame. Timestamps are relative to this index.
+    first_block_index: u64,
+    /// A frame that was read, but not yet returned.
+    pending: Option<Frame>,
+    /// A correction frame that was read ahead of the frame it corrects.
+    pending_correction: Option<Frame>,
+}
+
+impl WavPackReader {
+    /// Instantiates a reader for a hybrid stream and its correction stream. The correction stream
+    /// is ignored if the stream is not hybrid.
+    pub fn try_new_with_correction(
+        source: MediaSourceStream,
+        mut correction: MediaSourceStream,
+        options: &FormatOptions,
+    ) -> Result<Self> {
+        let mut reader = WavPackReader::try_new(source, options)?;
+
+        let frame = read_frame(&mut correction)?;
+
+        reader.first_correction_pos = frame.pos;
+        reader.pending_correction = Some(frame);
+        reader.correction = Some(correction);
+
+        Ok(reader)
+    }
+
+    /// Reads the correction frame for the frame with the given block index, if there is one.
+    fn read_correction_frame(&mut self, block_index: u64) -> Result<Option<Frame>> {
+        let correction = match self.correction {
+            Some(ref mut correction) => correction,
+            None => return Ok(None),
+        };
+
+        loop {
+            let frame = match self.pending_correction.take() {
+                Some(frame) => frame,
+                None => match read_frame(correction) {
+                    Ok(frame) => frame,
+                    Err(Error::IoError(ref err))
+                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    {
+                        warn!("correction stream ended early");
+                        return Ok(None);
+                    }
+                    Err(err) => return Err(err),
+                },
+            };
+
+            if frame.header.block_index == block_index {
+                return Ok(Some(frame));
+            }
+            else if frame.header.block_index > block_index {
+                self.pending_correction = Some(frame);
+                return Ok(None);
+            }
+        }
+    }
+}
+
+impl QueryDescriptor for WavPackReader {
+    fn query() -> &'static [Descriptor] {
+        &[support_format!(
+            "wavpack",
+            "WavPack",
+            &["wv"],
+            &["audio/wavpack", "audio/x-wavpack"],
+            &[b"wvpk"]
+        )]
+    }
+
+    fn score(context: &[u8]) -> u8 {
+        if context.len() < BLOCK_HEADER_LEN {
+            return 128;
+        }
+
+        match BlockHeader::read(&mut BufReader::new(context)) {
+            Ok(_) => 255,
+            Err(_) => 0,
+        }
+    }
+}
+
+impl FormatReader for WavPackReader {
+    fn try_new(mut source: MediaSourceStream, _options: &FormatOptions) -> Result<Self> {
+        let frame = read_frame(&mut source)?;
+
+        let blocks = read_blocks(&frame.data)?;
+        let header = &blocks[0].header;
+
+        // The sample rate is either a standard sample rate signalled in the header, or stored in
+        // a sub-block.
+        let sample_rate = match blocks[0].find(ID_SAMPLE_RATE).and_then(read_sample_rate) {
+            Some(rate) => rate,
+            None => match header.sample_rate() {
+                Some(rate) => rate,
+                None => return decode_error("wavpack: missing sample rate"),
+            },
+        };
+
+        // Without channel information, a stream is either mono or stereo.
+        let channels = match blocks[0].find(ID_CHANNEL_INFO) {
+            Some(data) => {
+                let (n_channels, mask) = read_channel_info(data)?;
+
+                match channels_from_mask(n_channels, mask) {
+                    Some(channels) => channels,
+                    None => return decode_error("wavpack: unsupported channel layout"),
+                }
+            }
+            None if header.flags & FLAG_MONO != 0 => Channels::FRONT_LEFT,
+            None => Channels::FRONT_LEFT | Channels::FRONT_RIGHT,
+        };
+
+        let mut codec_params = CodecParameters::new();
+
+        codec_params
+            .for_codec(CODEC_TYPE_WAVPACK)
+            .with_sample_rate(sample_rate)
+            .with_channels(channels)
+            .with_max_frames_per_packet(u64::from(header.block_samples))
+            .with_packet_data_integrity(true);
+
+        if header.flags & FLAG_FLOAT_DATA != 0 {
+            codec_params.with_sample_format(SampleFormat::F32).with_bits_per_sample(32);
+        }
+        else {
+            codec_params
+                .with_sample_format(SampleFormat::S32)
+                .with_bits_per_sample(header.bits_per_sample());
+        }
+
+        if let Some(n_frames) = header.total_samples {
+            codec_params.with_n_frames(n_frames);
+        }
+
+        // The MD5 checksum is usually stored in a metadata-only block at the end of the stream.
+        let mut md5 =
+            blocks.iter().find_map(|block| block.find(ID_MD5_CHECKSUM)).and_then(read_md5);
+
+        let first_pos = frame.pos;
+
+        if md5.is_none() && source.is_seekable() {
+            let pos = source.pos();
+            md5 = read_tail_md5(&mut source, first_pos)?;
+            source.seek(SeekFrom::Start(pos))?;
+        }
+
+        if let Some(md5) = md5 {
+            codec_params.with_verification_code(VerificationCheck::Md5(md5));
+        }
+
+        Ok(WavPackReader {
+            reader: source,
+            correction: None,
+            tracks: vec![Track::new(0, codec_params)],
+            cues: Default::default(),
+            metadata: Default::default(),
+            first_pos,
+            first_correction_pos: 0,
+            first_block_index: header.block_index,
+            pending: Some(frame),
+            pending_correction: None,
+        })
+    }
+
+    fn next_packet(&mut self) -> Result<Packet> {
+        let mut frame = match self.pending.take() {
+            Some(frame) => frame,
+            None => read_frame(&mut self.reader)?,
+        };
+
+        let header = frame.header;
+
+        if header.flags & FLAG_HYBRID != 0 {
+            if let Some(correction) = self.read_correction_frame(header.block_index)? {
+                frame.data.extend_from_slice(&correction.data);
+            }
+        }
+
+        let ts = header.block_index.saturating_sub(self.first_block_index);
+        let dur = u64::from(header.block_samples);
+
+        Ok(Packet::new_from_boxed_slice(0, ts, dur, frame.data.into_boxed_slice()))
+    }
+
+    fn metadata(&mut self) -> Metadata<'_> {
+        self.metadata.metadata()
+    }
+
+    fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    fn seek(&mut self, _mode: SeekMode, to: SeekTo) -> Result<SeekedTo> {
+        let params = &self.tracks[0].codec_params;
+
+        let required_ts = match to {
+            SeekTo::TimeStamp { ts, .. } => ts,
+            SeekTo::Time { time, .. } => {
+                TimeBase::new(1, params.sample_rate.unwrap()).calc_timestamp(time)
+            }
+        };
+
+        if let Some(n_frames) = params.n_frames {
+            if required_ts >= n_frames {
+                return seek_error(SeekErrorKind::OutOfRange);
+            }
+        }
+
+        debug!("seeking to frame_ts={}", required_ts);
+
+        let block_index = self.first_block_index + required_ts;
+
+        // A pending frame may already contain the sample.
+        let frame = match self.pending.take() {
+            Some(frame)
+                if block_index >= frame.header.block_index
+                    && block_index
+                        < frame.header.block_index + u64::from(frame.header.block_samples) =>
+            {
+                frame
+            }
+            Some(frame) if !self.reader.is_seekable() => {
+                if block_index < frame.header.block_index {
+                    self.pending = Some(frame);
+                    return seek_error(SeekErrorKind::ForwardOnly);
+                }
+
+                seek_frame(&mut self.reader, self.first_pos, block_index)?
+            }
+            _ => seek_frame(&mut self.reader, self.first_pos, block_index)?,
+        };
+
+        if !self.reader.is_seekable() && block_index < frame.header.block_index {
+            warn!("unable to seek backwards in an unseekable stream");
+        }
+
+        let actual_ts = frame.header.block_index - self.first_block_index;
+
+        // Seek the correction stream to the same frame. If it is not seekable, it will be read
+        // forward as required.
+        if let Some(ref mut correction) = self.correction {
+            if correction.is_seekable() {
+                let index = frame.header.block_index;
+                let correction_frame = seek_frame(correction, self.first_correction_pos, index)?;
+                self.pending_correction = Some(correction_frame);
+            }
+        }
+
+        self.pending = Some(frame);
+
+        debug!(
+            "seeked to packet_ts={} (delta={})",
+            actual_ts,
+            actual_ts as i64 - required_ts as i64
+        );
+
+        Ok(SeekedTo { track_id: 0, required_ts, actual_ts })
+    }
+
+    fn into_inner(self: Box<Self>) -> MediaSourceStream {
+        self.reader
+    }
+}
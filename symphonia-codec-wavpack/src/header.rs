@@ -0,0 +1,338 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::{decode_error, unsupported_error, Result};
+use symphonia_core::io::{BufReader, ReadBytes};
+
+/// The WavPack block marker: "wvpk" in ASCII.
+pub const BLOCK_MARKER: [u8; 4] = *b"wvpk";
+
+/// The length of a block header in bytes.
+pub const BLOCK_HEADER_LEN: usize = 32;
+
+/// The maximum length of a block. The reference encoder never exceeds 1 MiB.
+pub const MAX_BLOCK_LEN: u32 = 1 << 20;
+
+/// The maximum number of samples in a block. The reference encoder never exceeds 131072 samples.
+pub const MAX_BLOCK_SAMPLES: u32 = 131_072;
+
+/// The minimum and maximum supported stream versions.
+const MIN_STREAM_VERSION: u16 = 0x402;
+const MAX_STREAM_VERSION: u16 = 0x410;
+
+// Block header flags.
+pub const FLAG_BYTES_STORED: u32 = 0x3;
+pub const FLAG_MONO: u32 = 0x4;
+pub const FLAG_HYBRID: u32 = 0x8;
+pub const FLAG_JOINT_STEREO: u32 = 0x10;
+pub const FLAG_HYBRID_BITRATE: u32 = 0x200;
+pub const FLAG_HYBRID_BALANCE: u32 = 0x400;
+pub const FLAG_FLOAT_DATA: u32 = 0x80;
+pub const FLAG_INT32_DATA: u32 = 0x100;
+pub const FLAG_INITIAL_BLOCK: u32 = 0x800;
+pub const FLAG_FINAL_BLOCK: u32 = 0x1000;
+pub const FLAG_SHIFT_LSB: u32 = 13;
+pub const FLAG_SHIFT_MASK: u32 = 0x1f << FLAG_SHIFT_LSB;
+pub const FLAG_MAG_LSB: u32 = 18;
+pub const FLAG_MAG_MASK: u32 = 0x1f << FLAG_MAG_LSB;
+pub const FLAG_SRATE_LSB: u32 = 23;
+pub const FLAG_SRATE_MASK: u32 = 0xf << FLAG_SRATE_LSB;
+pub const FLAG_FALSE_STEREO: u32 = 0x4000_0000;
+pub const FLAG_DSD: u32 = 0x8000_0000;
+
+/// A block of mono data is either a true mono block, or a false stereo block where the decoded
+/// mono channel is duplicated.
+pub const FLAG_MONO_DATA: u32 = FLAG_MONO | FLAG_FALSE_STEREO;
+
+// Metadata sub-block identifiers.
+pub const ID_DECORR_TERMS: u8 = 0x2;
+pub const ID_DECORR_WEIGHTS: u8 = 0x3;
+pub const ID_DECORR_SAMPLES: u8 = 0x4;
+pub const ID_ENTROPY_VARS: u8 = 0x5;
+pub const ID_HYBRID_PROFILE: u8 = 0x6;
+pub const ID_FLOAT_INFO: u8 = 0x8;
+pub const ID_INT32_INFO: u8 = 0x9;
+pub const ID_WV_BITSTREAM: u8 = 0xa;
+pub const ID_WVC_BITSTREAM: u8 = 0xb;
+pub const ID_WVX_BITSTREAM: u8 = 0xc;
+pub const ID_CHANNEL_INFO: u8 = 0xd;
+pub const ID_MD5_CHECKSUM: u8 = 0x26;
+pub const ID_SAMPLE_RATE: u8 = 0x27;
+
+/// Metadata sub-block identifier flags.
+const ID_UNIQUE: u8 = 0x3f;
+const ID_ODD_SIZE: u8 = 0x40;
+const ID_LARGE: u8 = 0x80;
+
+/// The standard sample rates that may be signalled in the block header flags.
+const SAMPLE_RATES: [u32; 15] = [
+    6000, 8000, 9600, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000, 64000, 88200, 96000,
+    192000,
+];
+
+/// A WavPack block header.
+#[derive(Copy, Clone, Debug)]
+pub struct BlockHeader {
+    /// The length of the block, including the header.
+    pub block_len: u32,
+    /// The stream version.
+    pub version: u16,
+    /// The total number of samples in the stream, if known.
+    pub total_samples: Option<u64>,
+    /// The index of the first sample in the block.
+    pub block_index: u64,
+    /// The number of samples in the block. Blocks without samples only carry metadata.
+    pub block_samples: u32,
+    /// The block flags.
+    pub flags: u32,
+    /// The checksum of the decoded samples.
+    pub crc: u32,
+}
+
+impl BlockHeader {
+    /// Reads a block header, including the block marker.
+    pub fn read<B: ReadBytes>(reader: &mut B) -> Result<BlockHeader> {
+        if reader.read_quad_bytes()? != BLOCK_MARKER {
+            return decode_error("wavpack: missing block marker");
+        }
+
+        BlockHeader::read_after_marker(reader)
+    }
+
+    /// Reads a block header after the block marker was consumed.
+    pub fn read_after_marker<B: ReadBytes>(reader: &mut B) -> Result<BlockHeader> {
+        let ck_size = reader.read_u32()?;
+        let version = reader.read_u16()?;
+        let block_index_u8 = reader.read_u8()?;
+        let total_samples_u8 = reader.read_u8()?;
+        let total_samples = reader.read_u32()?;
+        let block_index = reader.read_u32()?;
+        let block_samples = reader.read_u32()?;
+        let flags = reader.read_u32()?;
+        let crc = reader.read_u32()?;
+
+        if ck_size & 1 != 0
+            || ck_size < (BLOCK_HEADER_LEN - 8) as u32
+            || ck_size > MAX_BLOCK_LEN
+        {
+            return decode_error("wavpack: invalid block size");
+        }
+
+        // The number of samples determines the size of the decoded audio buffers. Therefore, it
+        // must be bounded before any buffer is allocated.
+        if block_samples > MAX_BLOCK_SAMPLES {
+            return decode_error("wavpack: too many samples in block");
+        }
+
+        if !(MIN_STREAM_VERSION..=MAX_STREAM_VERSION).contains(&version) {
+            return unsupported_error("wavpack: unsupported stream version");
+        }
+
+        // The upper 8 bits of the 40-bit total sample count were added in later stream versions.
+        // Each increment of the upper bits only adds 2^32 - 1 samples since a lower value of
+        // 0xffff_ffff signals an unknown length.
+        let total_samples = if total_samples == u32::MAX {
+            None
+        }
+        else {
+            let upper = u64::from(total_samples_u8);
+            Some(u64::from(total_samples) + (upper << 32) - upper)
+        };
+
+        let block_index = u64::from(block_index) + (u64::from(block_index_u8) << 32);
+
+        Ok(BlockHeader {
+            block_len: ck_size + 8,
+            version,
+            total_samples,
+            block_index,
+            block_samples,
+            flags,
+            crc,
+        })
+    }
+
+    /// Returns true if the block contains mono data.
+    pub fn is_mono_data(&self) -> bool {
+        self.flags & FLAG_MONO_DATA != 0
+    }
+
+    /// Gets the number of channels decoded from the block.
+    pub fn n_channels(&self) -> usize {
+        if self.flags & FLAG_MONO != 0 { 1 } else { 2 }
+    }
+
+    /// Gets the number of bytes used to store a sample.
+    pub fn bytes_per_sample(&self) -> u32 {
+        (self.flags & FLAG_BYTES_STORED) + 1
+    }
+
+    /// Gets the number of significant bits per sample.
+    pub fn bits_per_sample(&self) -> u32 {
+        let shift = (self.flags & FLAG_SHIFT_MASK) >> FLAG_SHIFT_LSB;
+        (8 * self.bytes_per_sample()).saturating_sub(shift)
+    }
+
+    /// Gets the sample rate signalled by the block header flags. If the sample rate is not one of
+    /// the standard sample rates, it must be read from a sample rate sub-block.
+    pub fn sample_rate(&self) -> Option<u32> {
+        let idx = (self.flags & FLAG_SRATE_MASK) >> FLAG_SRATE_LSB;
+        SAMPLE_RATES.get(idx as usize).copied()
+    }
+}
+
+/// A metadata sub-block of a block.
+pub struct SubBlock<'a> {
+    /// The sub-block identifier.
+    pub id: u8,
+    /// The sub-block data.
+    pub data: &'a [u8],
+}
+
+/// Splits the data following a block header into metadata sub-blocks.
+pub fn read_sub_blocks(buf: &[u8]) -> Result<Vec<SubBlock<'_>>> {
+    let mut sub_blocks = Vec::new();
+    let mut reader = BufReader::new(buf);
+
+    while (reader.pos() as usize) < buf.len() {
+        let id = reader.read_u8()?;
+
+        // The length is stored in 16-bit words.
+        let words = if id & ID_LARGE != 0 {
+            reader.read_u24()? as usize
+        }
+        else {
+            usize::from(reader.read_u8()?)
+        };
+
+        let start = reader.pos() as usize;
+        let padded_len = 2 * words;
+
+        if start + padded_len > buf.len() {
+            return decode_error("wavpack: sub-block exceeds block size");
+        }
+
+        // An odd sized sub-block is padded to a 16-bit boundary.
+        let len = if id & ID_ODD_SIZE != 0 { padded_len.saturating_sub(1) } else { padded_len };
+
+        sub_blocks.push(SubBlock { id: id & ID_UNIQUE, data: &buf[start..start + len] });
+
+        reader.ignore_bytes(padded_len as u64)?;
+    }
+
+    Ok(sub_blocks)
+}
+
+/// A complete block, a header and its sub-blocks.
+pub struct Block<'a> {
+    pub header: BlockHeader,
+    pub sub_blocks: Vec<SubBlock<'a>>,
+}
+
+impl<'a> Block<'a> {
+    /// Finds the first sub-block with the given identifier.
+    pub fn find(&self, id: u8) -> Option<&[u8]> {
+        self.sub_blocks.iter().find(|sub_block| sub_block.id == id).map(|sub_block| sub_block.data)
+    }
+}
+
+/// Splits a buffer of one or more consecutive blocks into blocks.
+pub fn read_blocks(buf: &[u8]) -> Result<Vec<Block<'_>>> {
+    let mut blocks = Vec::new();
+    let mut rem = buf;
+
+    while !rem.is_empty() {
+        let header = BlockHeader::read(&mut BufReader::new(rem))?;
+
+        let block_len = header.block_len as usize;
+
+        if block_len > rem.len() {
+            return decode_error("wavpack: block exceeds packet size");
+        }
+
+        let sub_blocks = read_sub_blocks(&rem[BLOCK_HEADER_LEN..block_len])?;
+
+        blocks.push(Block { header, sub_blocks });
+
+        rem = &rem[block_len..];
+    }
+
+    Ok(blocks)
+}
+
+/// Reads the sample rate stored in a sample rate sub-block.
+pub fn read_sample_rate(data: &[u8]) -> Option<u32> {
+    match data.len() {
+        3 | 4 => Some(u32::from(data[0]) | u32::from(data[1]) << 8 | u32::from(data[2]) << 16),
+        _ => None,
+    }
+}
+
+/// Reads the channel count and WAVE channel mask stored in a channel information sub-block.
+pub fn read_channel_info(data: &[u8]) -> Result<(usize, u32)> {
+    match data.len() {
+        1..=5 => {
+            let mask = data[1..]
+                .iter()
+                .enumerate()
+                .fold(0, |mask, (i, &b)| mask | u32::from(b) << (8 * i));
+
+            Ok((usize::from(data[0]), mask))
+        }
+        0 => decode_error("wavpack: invalid channel information"),
+        _ => unsupported_error("wavpack: more than 255 channels are not supported"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_read_sub_blocks() {
+        // An odd sized sub-block followed by a large sub-block.
+        let buf = [0x4a, 0x02, 1, 2, 3, 0, 0x8d, 0x01, 0x00, 0x00, 2, 3];
+
+        let sub_blocks = read_sub_blocks(&buf).unwrap();
+
+        assert_eq!(sub_blocks.len(), 2);
+        assert_eq!(sub_blocks[0].id, ID_WV_BITSTREAM);
+        assert_eq!(sub_blocks[0].data, &[1, 2, 3]);
+        assert_eq!(sub_blocks[1].id, ID_CHANNEL_INFO);
+        assert_eq!(sub_blocks[1].data, &[2, 3]);
+
+        assert!(read_sub_blocks(&[0x0a, 0x02, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn verify_read_block_header() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"wvpk");
+        buf.extend_from_slice(&24u32.to_le_bytes());
+        buf.extend_from_slice(&0x407u16.to_le_bytes());
+        buf.extend_from_slice(&[1, 1]);
+        buf.extend_from_slice(&10u32.to_le_bytes());
+        buf.extend_from_slice(&20u32.to_le_bytes());
+        buf.extend_from_slice(&4096u32.to_le_bytes());
+        buf.extend_from_slice(&(9u32 << FLAG_SRATE_LSB | 1 | 3 << FLAG_SHIFT_LSB).to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+
+        let header = BlockHeader::read(&mut BufReader::new(&buf)).unwrap();
+
+        assert_eq!(header.block_len, 32);
+        assert_eq!(header.total_samples, Some(10 + (1 << 32) - 1));
+        assert_eq!(header.block_index, 20 + (1 << 32));
+        assert_eq!(header.block_samples, 4096);
+        assert_eq!(header.sample_rate(), Some(44100));
+        assert_eq!(header.bits_per_sample(), 13);
+        assert_eq!(header.n_channels(), 2);
+
+        // A block may not have more than the maximum number of samples.
+        buf[20..24].copy_from_slice(&(MAX_BLOCK_SAMPLES + 1).to_le_bytes());
+        assert!(BlockHeader::read(&mut BufReader::new(&buf)).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::audio::Channels;
+
+/// The channels of a WAVE channel mask, in the order of the bits of the mask.
+const WAVE_CHANNELS: [Channels; 18] = [
+    Channels::FRONT_LEFT,
+    Channels::FRONT_RIGHT,
+    Channels::FRONT_CENTRE,
+    Channels::LFE1,
+    Channels::REAR_LEFT,
+    Channels::REAR_RIGHT,
+    Channels::FRONT_LEFT_CENTRE,
+    Channels::FRONT_RIGHT_CENTRE,
+    Channels::REAR_CENTRE,
+    Channels::SIDE_LEFT,
+    Channels::SIDE_RIGHT,
+    Channels::TOP_CENTRE,
+    Channels::TOP_FRONT_LEFT,
+    Channels::TOP_FRONT_CENTRE,
+    Channels::TOP_FRONT_RIGHT,
+    Channels::TOP_REAR_LEFT,
+    Channels::TOP_REAR_CENTRE,
+    Channels::TOP_REAR_RIGHT,
+];
+
+/// Gets the channels for a channel count and WAVE channel mask. If the mask does not describe
+/// every channel, the channels are assumed to be the first channels of a WAVE channel mask.
+pub fn channels_from_mask(n_channels: usize, mask: u32) -> Option<Channels> {
+    let mask = if mask.count_ones() as usize == n_channels {
+        mask
+    }
+    else if n_channels <= WAVE_CHANNELS.len() {
+        (1 << n_channels) - 1
+    }
+    else {
+        return None;
+    };
+
+    let channels = WAVE_CHANNELS
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| mask & (1 << i) != 0)
+        .fold(Channels::empty(), |channels, (_, &channel)| channels | channel);
+
+    // Channels beyond those of the WAVE channel mask can't be represented.
+    if channels.count() == n_channels && n_channels > 0 {
+        Some(channels)
+    }
+    else {
+        None
+    }
+}
+
+/// Gets the audio buffer plane of each coded channel. Channels are coded in the order of the
+/// WAVE channel mask, while audio buffer planes are in the order of `Channels`.
+pub fn channel_map(channels: Channels) -> Vec<usize> {
+    let mut planes: Vec<(usize, usize)> = WAVE_CHANNELS
+        .iter()
+        .enumerate()
+        .filter(|(_, &channel)| channels.contains(channel))
+        .map(|(wave_idx, &channel)| {
+            // The plane of a channel is the number of channels of a lower bit.
+            let plane = (channels.bits() & (channel.bits() - 1)).count_ones() as usize;
+            (wave_idx, plane)
+        })
+        .collect();
+
+    planes.sort_unstable();
+
+    planes.into_iter().map(|(_, plane)| plane).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_channel_map() {
+        // 5.1 surround sound.
+        let channels = channels_from_mask(6, 0x3f).unwrap();
+
+        // The LFE channel is coded fourth, but is the final plane.
+        assert_eq!(channel_map(channels), [0, 1, 2, 5, 3, 4]);
+
+        assert_eq!(channels_from_mask(1, 0x4), Some(Channels::FRONT_CENTRE));
+        assert_eq!(channels_from_mask(2, 0), Some(Channels::FRONT_LEFT | Channels::FRONT_RIGHT));
+    }
+}
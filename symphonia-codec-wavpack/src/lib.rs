@@ -0,0 +1,21 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![warn(rust_2018_idioms)]
+#![forbid(unsafe_code)]
+
+mod block;
+mod decoder;
+mod decorr;
+mod demuxer;
+mod header;
+mod layout;
+mod validate;
+mod words;
+
+pub use decoder::WavPackDecoder;
+pub use demuxer::WavPackReader;
@@ -0,0 +1,53 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::audio::{AudioBuffer, Signal};
+use symphonia_core::checksum::Md5;
+use symphonia_core::io::Monitor;
+
+use crate::block::SampleKind;
+
+/// `Validator` computes the MD5 checksum of an audio stream the same way as the WavPack encoder.
+#[derive(Default)]
+pub struct Validator {
+    state: Md5,
+    buf: Vec<u8>,
+}
+
+impl Validator {
+    /// Processes the audio buffer of decoded, but otherwise unscaled, samples and updates the
+    /// state of the validator. The checksum is calculated on interleaved little-endian samples,
+    /// with the channels in coded order, as they would be stored in a WAVE file.
+    pub fn update(&mut self, buf: &AudioBuffer<i32>, chan_map: &[usize], kind: SampleKind) {
+        let bytes_per_sample = match kind {
+            SampleKind::Int(bytes) => bytes as usize,
+            SampleKind::Float => 4,
+        };
+
+        self.buf.clear();
+        self.buf.reserve(buf.frames() * chan_map.len() * bytes_per_sample);
+
+        for i in 0..buf.frames() {
+            for &plane in chan_map {
+                let sample = buf.chan(plane)[i];
+
+                match bytes_per_sample {
+                    // 8-bit samples are unsigned.
+                    1 => self.buf.push((sample + 128) as u8),
+                    _ => self.buf.extend_from_slice(&sample.to_le_bytes()[..bytes_per_sample]),
+                }
+            }
+        }
+
+        self.state.process_buf_bytes(&self.buf);
+    }
+
+    /// Get the checksum.
+    pub fn md5(&mut self) -> [u8; 16] {
+        self.state.md5()
+    }
+}
@@ -0,0 +1,522 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Entropy decoding of residuals.
+//!
+//! Residuals are coded with an adaptive Golomb-like code. Three running medians per channel
+//! partition the magnitude of a residual into a unary coded "ones count" selecting a range, and a
+//! truncated binary code selecting a value within that range. Long runs of zeros are run-length
+//! coded. In hybrid mode only the high-order part of the value within the range is coded in the
+//! main bitstream, the remainder is coded in the correction bitstream.
+
+use symphonia_core::errors::{decode_error, Result};
+use symphonia_core::io::{BitReaderRtl, ReadBitsRtl};
+
+use crate::header::{FLAG_HYBRID, FLAG_HYBRID_BALANCE, FLAG_HYBRID_BITRATE, FLAG_MONO_DATA};
+
+/// The maximum number of unary coded ones before an escape code is used.
+const LIMIT_ONES: u32 = 16;
+
+/// The slow level filter shift, and rounding offset.
+const SLS: u32 = 8;
+const SLO: i32 = 1 << (SLS - 1);
+
+/// Divisors of the adaptation rate of each median.
+const MEDIAN_DIVISORS: [u32; 3] = [128, 64, 32];
+
+/// `round(256 * log2(1 + i / 256))`
+#[rustfmt::skip]
+const LOG2_TABLE: [u8; 256] = [
+    0x00, 0x01, 0x03, 0x04, 0x06, 0x07, 0x09, 0x0a, 0x0b, 0x0d, 0x0e, 0x10, 0x11, 0x12, 0x14, 0x15,
+    0x16, 0x18, 0x19, 0x1a, 0x1c, 0x1d, 0x1e, 0x20, 0x21, 0x22, 0x24, 0x25, 0x26, 0x28, 0x29, 0x2a,
+    0x2c, 0x2d, 0x2e, 0x2f, 0x31, 0x32, 0x33, 0x34, 0x36, 0x37, 0x38, 0x39, 0x3b, 0x3c, 0x3d, 0x3e,
+    0x3f, 0x41, 0x42, 0x43, 0x44, 0x45, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4d, 0x4e, 0x4f, 0x50, 0x51,
+    0x52, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x5c, 0x5d, 0x5e, 0x5f, 0x60, 0x61, 0x62, 0x63,
+    0x64, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x70, 0x71, 0x72, 0x74, 0x75,
+    0x76, 0x77, 0x78, 0x79, 0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f, 0x80, 0x81, 0x82, 0x83, 0x84, 0x85,
+    0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d, 0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95,
+    0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b, 0x9b, 0x9c, 0x9d, 0x9e, 0x9f, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4,
+    0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xa9, 0xaa, 0xab, 0xac, 0xad, 0xae, 0xaf, 0xb0, 0xb1, 0xb2, 0xb2,
+    0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xb9, 0xba, 0xbb, 0xbc, 0xbd, 0xbe, 0xbf, 0xc0, 0xc0,
+    0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcb, 0xcc, 0xcd, 0xce,
+    0xcf, 0xd0, 0xd0, 0xd1, 0xd2, 0xd3, 0xd4, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd8, 0xd9, 0xda, 0xdb,
+    0xdc, 0xdc, 0xdd, 0xde, 0xdf, 0xe0, 0xe0, 0xe1, 0xe2, 0xe3, 0xe4, 0xe4, 0xe5, 0xe6, 0xe7, 0xe7,
+    0xe8, 0xe9, 0xea, 0xea, 0xeb, 0xec, 0xed, 0xee, 0xee, 0xef, 0xf0, 0xf1, 0xf1, 0xf2, 0xf3, 0xf4,
+    0xf4, 0xf5, 0xf6, 0xf7, 0xf7, 0xf8, 0xf9, 0xf9, 0xfa, 0xfb, 0xfc, 0xfc, 0xfd, 0xfe, 0xff, 0xff,
+];
+
+/// `round(256 * 2^(i / 256)) - 256`
+#[rustfmt::skip]
+const EXP2_TABLE: [u8; 256] = [
+    0x00, 0x01, 0x01, 0x02, 0x03, 0x03, 0x04, 0x05, 0x06, 0x06, 0x07, 0x08, 0x08, 0x09, 0x0a, 0x0b,
+    0x0b, 0x0c, 0x0d, 0x0e, 0x0e, 0x0f, 0x10, 0x10, 0x11, 0x12, 0x13, 0x13, 0x14, 0x15, 0x16, 0x16,
+    0x17, 0x18, 0x19, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1d, 0x1e, 0x1f, 0x20, 0x20, 0x21, 0x22, 0x23,
+    0x24, 0x24, 0x25, 0x26, 0x27, 0x28, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2c, 0x2d, 0x2e, 0x2f, 0x30,
+    0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3a, 0x3b, 0x3c, 0x3d,
+    0x3e, 0x3f, 0x40, 0x41, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x48, 0x49, 0x4a, 0x4b,
+    0x4c, 0x4d, 0x4e, 0x4f, 0x50, 0x51, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a,
+    0x5b, 0x5c, 0x5d, 0x5e, 0x5e, 0x5f, 0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79,
+    0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f, 0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x87, 0x88, 0x89, 0x8a,
+    0x8b, 0x8c, 0x8d, 0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+    0x9c, 0x9d, 0x9f, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa8, 0xa9, 0xaa, 0xab, 0xac, 0xad,
+    0xaf, 0xb0, 0xb1, 0xb2, 0xb3, 0xb4, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xbc, 0xbd, 0xbe, 0xbf, 0xc0,
+    0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc8, 0xc9, 0xca, 0xcb, 0xcd, 0xce, 0xcf, 0xd0, 0xd2, 0xd3, 0xd4,
+    0xd6, 0xd7, 0xd8, 0xd9, 0xdb, 0xdc, 0xdd, 0xde, 0xe0, 0xe1, 0xe2, 0xe4, 0xe5, 0xe6, 0xe8, 0xe9,
+    0xea, 0xec, 0xed, 0xee, 0xf0, 0xf1, 0xf2, 0xf4, 0xf5, 0xf6, 0xf8, 0xf9, 0xfa, 0xfc, 0xfd, 0xff,
+];
+
+/// Gets the number of bits required to represent `value`.
+fn bit_len(value: u32) -> u32 {
+    32 - value.leading_zeros()
+}
+
+/// Computes a fixed-point base-2 logarithm, with 8 fractional bits, of an unsigned value.
+pub fn log2(value: u32) -> i32 {
+    let value = value.wrapping_add(value >> 9);
+
+    if value < (1 << 8) {
+        let dbits = bit_len(value);
+        ((dbits << 8) + u32::from(LOG2_TABLE[((value << (9 - dbits)) & 0xff) as usize])) as i32
+    }
+    else {
+        let dbits = bit_len(value);
+        ((dbits << 8) + u32::from(LOG2_TABLE[((value >> (dbits - 9)) & 0xff) as usize])) as i32
+    }
+}
+
+/// Computes a signed value from a fixed-point base-2 logarithm, with 8 fractional bits.
+pub fn exp2s(log: i32) -> i32 {
+    if log < 0 {
+        return -exp2s(-log);
+    }
+
+    let value = u32::from(EXP2_TABLE[(log & 0xff) as usize]) | 0x100;
+    let log = log >> 8;
+
+    if log <= 9 {
+        (value >> (9 - log)) as i32
+    }
+    else {
+        value.wrapping_shl(((log - 9) & 0x1f) as u32) as i32
+    }
+}
+
+/// Reads a 16-bit little-endian logarithm and computes its signed value.
+pub fn read_exp2s(buf: &[u8]) -> i32 {
+    exp2s(i32::from(i16::from_le_bytes([buf[0], buf[1]])))
+}
+
+/// Reads a truncated binary coded value in the range [0, `max_code`].
+fn read_code(bs: &mut BitReaderRtl<'_>, max_code: u32) -> Result<u32> {
+    if max_code < 2 {
+        return if max_code == 1 { Ok(bs.read_bit()? as u32) } else { Ok(0) };
+    }
+
+    let n_bits = bit_len(max_code);
+    let extras = ((1u64 << n_bits) - u64::from(max_code) - 1) as u32;
+
+    let code = bs.read_bits_leq32(n_bits - 1)?;
+
+    if code >= extras {
+        Ok((code << 1) - extras + bs.read_bit()? as u32)
+    }
+    else {
+        Ok(code)
+    }
+}
+
+/// Reads an Elias gamma-like escape code. Returns `None` if the code is invalid.
+fn read_escape_code(bs: &mut BitReaderRtl<'_>) -> Result<Option<u32>> {
+    let mut n_bits = 0;
+
+    while n_bits < 33 && bs.read_bit()? {
+        n_bits += 1;
+    }
+
+    match n_bits {
+        33 => Ok(None),
+        0 | 1 => Ok(Some(n_bits)),
+        _ => {
+            let mask = 1 << (n_bits - 1);
+            Ok(Some(bs.read_bits_leq32(n_bits - 1)? | mask))
+        }
+    }
+}
+
+/// Per-channel entropy coder state.
+#[derive(Copy, Clone, Default)]
+struct Channel {
+    median: [u32; 3],
+    slow_level: i32,
+    error_limit: u32,
+    bitrate_acc: i32,
+    bitrate_delta: i32,
+}
+
+impl Channel {
+    fn get_median(&self, i: usize) -> u32 {
+        (self.median[i] >> 4) + 1
+    }
+
+    fn inc_median(&mut self, i: usize) {
+        let div = MEDIAN_DIVISORS[i];
+        let step = (self.median[i].wrapping_add(div) / div) * 5;
+        self.median[i] = self.median[i].wrapping_add(step);
+    }
+
+    fn dec_median(&mut self, i: usize) {
+        let div = MEDIAN_DIVISORS[i];
+        let step = (self.median[i].wrapping_add(div - 2) / div) * 2;
+        self.median[i] = self.median[i].wrapping_sub(step);
+    }
+
+    fn decay_slow_level(&mut self) {
+        self.slow_level -= (self.slow_level + SLO) >> SLS;
+    }
+}
+
+/// The entropy decoder of a block.
+#[derive(Default)]
+pub struct WordsDecoder {
+    flags: u32,
+    chans: [Channel; 2],
+    holding_zero: bool,
+    holding_one: bool,
+    zeros_acc: u32,
+}
+
+impl WordsDecoder {
+    pub fn new(flags: u32) -> Self {
+        WordsDecoder { flags, ..Default::default() }
+    }
+
+    fn is_mono(&self) -> bool {
+        self.flags & FLAG_MONO_DATA != 0
+    }
+
+    /// Gets the number of bytes of per-channel entropy information expected for the block.
+    fn chan_bytes(&self, n: usize) -> usize {
+        if self.is_mono() { n } else { 2 * n }
+    }
+
+    /// Reads the initial medians from an entropy variables sub-block.
+    pub fn read_entropy_vars(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() != self.chan_bytes(6) {
+            return decode_error("wavpack: invalid entropy variables");
+        }
+
+        for (chan, buf) in self.chans.iter_mut().zip(data.chunks_exact(6)) {
+            for (median, value) in chan.median.iter_mut().zip(buf.chunks_exact(2)) {
+                *median = exp2s(i32::from(u16::from_le_bytes([value[0], value[1]]))) as u32;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the hybrid bitrate profile from a hybrid profile sub-block.
+    pub fn read_hybrid_profile(&mut self, data: &[u8]) -> Result<()> {
+        let n = self.chan_bytes(2);
+        let mut rem = data;
+
+        if self.flags & FLAG_HYBRID_BITRATE != 0 {
+            if rem.len() < n {
+                return decode_error("wavpack: invalid hybrid profile");
+            }
+
+            for (chan, buf) in self.chans.iter_mut().zip(rem[..n].chunks_exact(2)) {
+                chan.slow_level = read_exp2s(buf);
+            }
+
+            rem = &rem[n..];
+        }
+
+        if rem.len() < n {
+            return decode_error("wavpack: invalid hybrid profile");
+        }
+
+        for (chan, buf) in self.chans.iter_mut().zip(rem[..n].chunks_exact(2)) {
+            chan.bitrate_acc = i32::from(u16::from_le_bytes([buf[0], buf[1]])) << 16;
+        }
+
+        rem = &rem[n..];
+
+        if !rem.is_empty() {
+            if rem.len() != n {
+                return decode_error("wavpack: invalid hybrid profile");
+            }
+
+            for (chan, buf) in self.chans.iter_mut().zip(rem.chunks_exact(2)) {
+                chan.bitrate_delta = read_exp2s(buf);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the error limits of the hybrid mode quantizer.
+    fn update_error_limit(&mut self) {
+        let mut bitrate = [0; 2];
+
+        let n_chans = if self.is_mono() { 1 } else { 2 };
+
+        for (chan, bitrate) in self.chans[..n_chans].iter_mut().zip(bitrate.iter_mut()) {
+            chan.bitrate_acc = chan.bitrate_acc.wrapping_add(chan.bitrate_delta);
+            *bitrate = chan.bitrate_acc >> 16;
+        }
+
+        if self.flags & FLAG_HYBRID_BITRATE == 0 {
+            for (chan, &bitrate) in self.chans[..n_chans].iter_mut().zip(bitrate.iter()) {
+                chan.error_limit = exp2s(bitrate) as u32;
+            }
+            return;
+        }
+
+        let mut slow_log = [0; 2];
+
+        for (chan, slow_log) in self.chans[..n_chans].iter().zip(slow_log.iter_mut()) {
+            *slow_log = (chan.slow_level + SLO) >> SLS;
+        }
+
+        if n_chans == 2 && self.flags & FLAG_HYBRID_BALANCE != 0 {
+            let balance = (slow_log[1] - slow_log[0] + bitrate[1] + 1) >> 1;
+
+            if balance > bitrate[0] {
+                bitrate[1] = bitrate[0] * 2;
+                bitrate[0] = 0;
+            }
+            else if -balance > bitrate[0] {
+                bitrate[0] *= 2;
+                bitrate[1] = 0;
+            }
+            else {
+                bitrate[1] = bitrate[0] + balance;
+                bitrate[0] -= balance;
+            }
+        }
+
+        for (i, chan) in self.chans[..n_chans].iter_mut().enumerate() {
+            chan.error_limit = if slow_log[i] - bitrate[i] > -0x100 {
+                exp2s(slow_log[i] - bitrate[i] + 0x100) as u32
+            }
+            else {
+                0
+            };
+        }
+    }
+
+    /// Reads the number of ones of the unary coded part of a residual. Returns `None` if the
+    /// bitstream is invalid.
+    fn read_ones_count(&mut self, bs: &mut BitReaderRtl<'_>) -> Result<Option<u32>> {
+        let mut ones_count = bs.read_unary_ones()?;
+
+        if ones_count > LIMIT_ONES {
+            return Ok(None);
+        }
+        else if ones_count == LIMIT_ONES {
+            ones_count = match read_escape_code(bs)? {
+                Some(count) => count + LIMIT_ONES,
+                None => return Ok(None),
+            };
+        }
+
+        // Every odd ones count carries over to the next residual.
+        if self.holding_one {
+            self.holding_one = ones_count & 1 != 0;
+            ones_count = (ones_count >> 1) + 1;
+        }
+        else {
+            self.holding_one = ones_count & 1 != 0;
+            ones_count >>= 1;
+        }
+
+        self.holding_zero = !self.holding_one;
+
+        Ok(Some(ones_count))
+    }
+
+    /// Reads residuals into `buf`. For stereo blocks the residuals are interleaved. If a
+    /// correction bitstream is provided, the corrections are added to the residuals.
+    pub fn read_words(
+        &mut self,
+        bs: &mut BitReaderRtl<'_>,
+        mut wvc: Option<&mut BitReaderRtl<'_>>,
+        buf: &mut [i32],
+    ) -> Result<()> {
+        let is_hybrid = self.flags & FLAG_HYBRID != 0;
+        let is_hybrid_bitrate = self.flags & FLAG_HYBRID_BITRATE != 0;
+        let n_chans = if self.is_mono() { 1 } else { 2 };
+
+        for (i, sample) in buf.iter_mut().enumerate() {
+            let ch = i % n_chans;
+
+            // Run-length coded zeros are signalled when all medians are small.
+            if self.chans[0].median[0] < 2
+                && self.chans[1].median[0] < 2
+                && !self.holding_zero
+                && !self.holding_one
+            {
+                if self.zeros_acc > 0 {
+                    self.zeros_acc -= 1;
+
+                    if self.zeros_acc > 0 {
+                        self.chans[ch].decay_slow_level();
+                        *sample = 0;
+                        continue;
+                    }
+                }
+                else {
+                    self.zeros_acc = match read_escape_code(bs)? {
+                        Some(count) => count,
+                        None => return decode_error("wavpack: invalid zero run"),
+                    };
+
+                    if self.zeros_acc > 0 {
+                        self.chans[ch].decay_slow_level();
+                        self.chans[0].median = [0; 3];
+                        self.chans[1].median = [0; 3];
+                        *sample = 0;
+                        continue;
+                    }
+                }
+            }
+
+            let ones_count = if self.holding_zero {
+                self.holding_zero = false;
+                0
+            }
+            else {
+                match self.read_ones_count(bs)? {
+                    Some(count) => count,
+                    None => return decode_error("wavpack: invalid residual"),
+                }
+            };
+
+            if is_hybrid && ch == 0 {
+                self.update_error_limit();
+            }
+
+            let c = &mut self.chans[ch];
+
+            let (low, high) = match ones_count {
+                0 => {
+                    let high = c.get_median(0) - 1;
+                    c.dec_median(0);
+                    (0, high)
+                }
+                1 => {
+                    let low = c.get_median(0);
+                    c.inc_median(0);
+                    let high = low + c.get_median(1) - 1;
+                    c.dec_median(1);
+                    (low, high)
+                }
+                2 => {
+                    let low = c.get_median(0);
+                    c.inc_median(0);
+                    let low = low.wrapping_add(c.get_median(1));
+                    c.inc_median(1);
+                    let high = low.wrapping_add(c.get_median(2) - 1);
+                    c.dec_median(2);
+                    (low, high)
+                }
+                _ => {
+                    let low = c.get_median(0);
+                    c.inc_median(0);
+                    let low = low.wrapping_add(c.get_median(1));
+                    c.inc_median(1);
+                    let low = low.wrapping_add((ones_count - 2).wrapping_mul(c.get_median(2)));
+                    let high = low.wrapping_add(c.get_median(2) - 1);
+                    c.inc_median(2);
+                    (low, high)
+                }
+            };
+
+            let mut low = low & 0x7fff_ffff;
+            let mut high = (high & 0x7fff_ffff).max(low);
+
+            let mut mid = (high + low + 1) >> 1;
+
+            if c.error_limit == 0 {
+                mid = read_code(bs, high - low)? + low;
+            }
+            else {
+                // In hybrid mode, the value is only coded to within the error limit by a binary
+                // search of the range.
+                while high - low > c.error_limit {
+                    if bs.read_bit()? {
+                        low = mid;
+                    }
+                    else {
+                        high = mid - 1;
+                    }
+
+                    mid = (high + low + 1) >> 1;
+                }
+            }
+
+            let is_negative = bs.read_bit()?;
+
+            // The correction bitstream codes the exact value within the final range.
+            let correction = match wvc {
+                Some(ref mut wvc) if c.error_limit != 0 => {
+                    let value = read_code(wvc, high - low)? + low;
+                    value.wrapping_sub(mid) as i32
+                }
+                _ => 0,
+            };
+
+            if is_hybrid_bitrate {
+                c.decay_slow_level();
+                c.slow_level += log2(mid);
+            }
+
+            *sample = if is_negative {
+                !(mid as i32) - correction
+            }
+            else {
+                mid as i32 + correction
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exp2s, log2, read_code};
+    use symphonia_core::io::BitReaderRtl;
+
+    #[test]
+    fn verify_log2_exp2s() {
+        assert_eq!(log2(0), 0);
+        assert_eq!(log2(1), 0x100);
+        assert_eq!(log2(256), 0x900);
+        assert_eq!(exp2s(0x100), 1);
+        assert_eq!(exp2s(0x900), 256);
+        assert_eq!(exp2s(-0x900), -256);
+
+        // The round trip should be within the precision of the logarithm.
+        for &value in &[3u32, 100, 1000, 65535, 1 << 20] {
+            let error = (exp2s(log2(value)) - value as i32).abs();
+            assert!(error <= (value as i32 >> 7) + 1);
+        }
+    }
+
+    #[test]
+    fn verify_read_code() {
+        // With a maximum code of 4, 0 to 2 are coded with 2 bits, and 3 and 4 with 3 bits.
+        let buf = [0b1110_1101];
+        let mut bs = BitReaderRtl::new(&buf);
+
+        assert_eq!(read_code(&mut bs, 4).unwrap(), 1);
+        assert_eq!(read_code(&mut bs, 4).unwrap(), 3);
+        assert_eq!(read_code(&mut bs, 4).unwrap(), 4);
+    }
+}
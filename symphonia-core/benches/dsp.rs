@@ -0,0 +1,51 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use symphonia_core::dsp::fft::{Complex, Fft};
+use symphonia_core::dsp::mdct::Imdct;
+
+fn bench_imdct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("imdct");
+
+    // The transform sizes used by MP3 (long blocks), Vorbis, and AAC (short and long blocks).
+    for &n in &[128u32, 256, 1024, 2048] {
+        let mut imdct = Imdct::new(n);
+
+        let src: Vec<f32> = (0..n).map(|i| (i as f32 * 0.37).sin()).collect();
+        let mut dst = vec![0.0; 2 * n as usize];
+
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, _| {
+            b.iter(|| imdct.imdct(black_box(&src), &mut dst, 1.0))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_fft(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fft");
+
+    for &n in &[64usize, 128, 512, 1024] {
+        let fft = Fft::new(n);
+
+        let src: Vec<Complex> = (0..n)
+            .map(|i| Complex::new((i as f32 * 0.37).sin(), (i as f32 * 0.11).cos()))
+            .collect();
+        let mut dst = vec![Complex::default(); n];
+
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, _| {
+            b.iter(|| fft.fft(black_box(&src), &mut dst))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_imdct, bench_fft);
+criterion_main!(benches);
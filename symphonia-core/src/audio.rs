@@ -12,13 +12,15 @@ use std::borrow::Cow;
 use std::fmt;
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::Range;
 use std::vec::Vec;
 
 use arrayvec::ArrayVec;
 use bitflags::bitflags;
 
 use crate::conv::{ConvertibleSample, IntoSample};
-use crate::errors::Result;
+use crate::conv::dither::{Dither, MaybeDither};
+use crate::errors::{Result, limit_error, unsupported_error};
 use crate::sample::{Sample, i24, u24};
 use crate::units::Duration;
 
@@ -86,6 +88,19 @@ bitflags! {
 }
 
 impl Channels {
+    /// Front Left, Right, and Centre, Side Left and Right, Rear Left and Right, and a single
+    /// low-frequency channel.
+    pub const SEVEN_POINT_ONE: Channels = Channels {
+        bits: Channels::FRONT_LEFT.bits
+            | Channels::FRONT_RIGHT.bits
+            | Channels::FRONT_CENTRE.bits
+            | Channels::SIDE_LEFT.bits
+            | Channels::SIDE_RIGHT.bits
+            | Channels::REAR_LEFT.bits
+            | Channels::REAR_RIGHT.bits
+            | Channels::LFE1.bits,
+    };
+
     /// Gets the number of channels.
     pub fn count(self) -> usize {
         self.bits.count_ones() as usize
@@ -258,6 +273,39 @@ impl<'a, S : Sample> AudioPlanesMut<'a, S> {
     }
 }
 
+/// The shape of the gain curve of a fade.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum FadeCurve {
+    /// The gain changes linearly.
+    #[default]
+    Linear,
+    /// The gain changes exponentially such that the change in loudness is perceptually linear.
+    /// The gain changes by 60 dB over the length of the fade.
+    Exponential,
+}
+
+impl FadeCurve {
+    /// Gets the gain at position `t`, in the range [0, 1], of a fade from `from` to `to`.
+    fn gain(self, from: f64, to: f64, t: f64) -> f64 {
+        match self {
+            FadeCurve::Linear => from + (to - from) * t,
+            FadeCurve::Exponential => {
+                // An exponential curve from 0 to 1 spanning a 60 dB (1000x) range. Fades that
+                // decrease the gain use the mirrored curve such that both change quickly at high
+                // gains and slowly at low gains.
+                let curve = |t: f64| (1000f64.powf(t) - 1.0) / 999.0;
+
+                if to >= from {
+                    from + (to - from) * curve(t)
+                }
+                else {
+                    to + (from - to) * curve(1.0 - t)
+                }
+            }
+        }
+    }
+}
+
 /// `AudioBuffer` is a container for multi-channel planar audio sample data. An `AudioBuffer` is
 /// characterized by the duration (capacity), and audio specification (channels and sample rate).
 /// The capacity of an `AudioBuffer` is the maximum number of samples the buffer may store per
@@ -383,6 +431,278 @@ impl<S : Sample> AudioBuffer<S> {
     pub fn make_equivalent<E: Sample>(&self) -> AudioBuffer<E> {
         AudioBuffer::<E>::new(self.n_capacity as Duration, self.spec)
     }
+
+    /// Replaces the contents of the buffer with the audio in the source `AudioBufferRef`,
+    /// converting the samples if required. See [`AudioBuffer::copy_typed`] for details.
+    pub fn copy_ref(&mut self, src: &AudioBufferRef<'_>) -> Result<()>
+    where
+        S: ConvertibleSample,
+    {
+        match src {
+            AudioBufferRef::U8(buf)  => self.copy_typed(buf),
+            AudioBufferRef::U16(buf) => self.copy_typed(buf),
+            AudioBufferRef::U24(buf) => self.copy_typed(buf),
+            AudioBufferRef::U32(buf) => self.copy_typed(buf),
+            AudioBufferRef::S8(buf)  => self.copy_typed(buf),
+            AudioBufferRef::S16(buf) => self.copy_typed(buf),
+            AudioBufferRef::S24(buf) => self.copy_typed(buf),
+            AudioBufferRef::S32(buf) => self.copy_typed(buf),
+            AudioBufferRef::F32(buf) => self.copy_typed(buf),
+            AudioBufferRef::F64(buf) => self.copy_typed(buf),
+        }
+    }
+
+    /// Replaces the contents of the buffer with the audio in a source `AudioBuffer`, converting
+    /// the samples if required.
+    ///
+    /// If the buffer is unused, it is allocated with the signal specification and capacity of the
+    /// source buffer. Otherwise, the buffer is never reallocated. An error is returned, and the
+    /// buffer is left unchanged, if the buffers have different signal specifications, or if the
+    /// capacity of the buffer is less than the number of frames in the source buffer.
+    pub fn copy_typed<F>(&mut self, src: &AudioBuffer<F>) -> Result<()>
+    where
+        F: Sample + IntoSample<S>,
+    {
+        // The source buffer may be unused, so there is no source plane to copy.
+        if src.n_frames == 0 {
+            self.n_frames = 0;
+            return Ok(());
+        }
+
+        if self.is_unused() {
+            *self = AudioBuffer::new(src.n_capacity as Duration, src.spec);
+        }
+        else if self.spec != src.spec {
+            return unsupported_error("audio buffer signal specifications do not match");
+        }
+        else if self.n_capacity < src.n_frames {
+            return limit_error("audio buffer capacity is too small");
+        }
+
+        let planes = self.buf.chunks_exact_mut(self.n_capacity);
+
+        for (dest, src_plane) in planes.zip(src.buf.chunks_exact(src.n_capacity)) {
+            for (d, &s) in dest.iter_mut().zip(&src_plane[..src.n_frames]) {
+                *d = s.into_sample();
+            }
+        }
+
+        self.n_frames = src.n_frames;
+
+        Ok(())
+    }
+
+    /// Trims `start` frames from the start, and `end` frames from the end, of the buffer. The
+    /// remaining frames are moved to the start of the buffer. If more frames are trimmed than are
+    /// written, the buffer is cleared.
+    pub fn trim(&mut self, start: usize, end: usize) {
+        let start = start.min(self.n_frames);
+        let end = end.min(self.n_frames - start);
+
+        let n_frames = self.n_frames - start - end;
+
+        if start > 0 {
+            for plane in self.buf.chunks_exact_mut(self.n_capacity) {
+                plane.copy_within(start..start + n_frames, 0);
+            }
+        }
+
+        self.n_frames = n_frames;
+    }
+
+    /// Copies the frames in `range` into a new `AudioBuffer` with a capacity equal to the number
+    /// of frames copied.
+    pub fn slice(&self, range: Range<usize>) -> AudioBuffer<S> {
+        assert!(range.start <= range.end && range.end <= self.n_frames, "range out of bounds");
+
+        if self.is_unused() {
+            return AudioBuffer::unused();
+        }
+
+        // A buffer with no capacity is unused, so always allocate at least one frame.
+        let mut dest = AudioBuffer::new(range.len().max(1) as Duration, self.spec);
+        dest.append_range(self, range);
+        dest
+    }
+
+    /// Appends all frames of `src` to the end of the buffer. The capacity of the buffer is
+    /// increased if required. The two buffers must have the same signal specification.
+    pub fn append(&mut self, src: &AudioBuffer<S>) {
+        self.append_range(src, 0..src.n_frames);
+    }
+
+    /// Appends the frames of `src` in `range` to the end of the buffer. The capacity of the buffer
+    /// is increased if required. The two buffers must have the same signal specification, unless
+    /// the buffer is unused, in which case it takes the signal specification of `src`.
+    pub fn append_range(&mut self, src: &AudioBuffer<S>, range: Range<usize>) {
+        assert!(range.start <= range.end && range.end <= src.n_frames, "range out of bounds");
+
+        // Appending nothing is a no-op, even if the source buffer is unused.
+        if range.is_empty() {
+            return;
+        }
+
+        if self.is_unused() {
+            self.spec = src.spec;
+        }
+
+        assert!(src.spec == self.spec, "signal specifications do not match");
+
+        let end = self.n_frames + range.len();
+
+        if end > self.n_capacity {
+            self.grow(end.max(2 * self.n_capacity));
+        }
+
+        let planes = self.buf.chunks_exact_mut(self.n_capacity);
+
+        for (dest, src) in planes.zip(src.buf.chunks_exact(src.n_capacity)) {
+            dest[self.n_frames..end].copy_from_slice(&src[range.clone()]);
+        }
+
+        self.n_frames = end;
+    }
+
+    /// Splits the buffer into two at frame `at`. The buffer retains the frames [0, `at`), and a new
+    /// buffer containing the frames [`at`, N) is returned.
+    pub fn split_off(&mut self, at: usize) -> AudioBuffer<S> {
+        assert!(at <= self.n_frames, "split point out of bounds");
+
+        let dest = self.slice(at..self.n_frames);
+        self.n_frames = at;
+        dest
+    }
+
+    /// Renders a number of silent frames. Rendering into an unused buffer does nothing.
+    ///
+    /// If `n_frames` is `None`, the remaining number of samples will be used. If `n_frames` is too
+    /// large, this function will assert.
+    pub fn render_silence(&mut self, n_frames: Option<usize>) {
+        if self.is_unused() {
+            return;
+        }
+
+        let start = self.n_frames;
+
+        self.render_reserved(n_frames);
+
+        for plane in self.buf.chunks_exact_mut(self.n_capacity) {
+            plane[start..self.n_frames].fill(S::MID);
+        }
+    }
+
+    /// Multiplies every sample in the buffer by `gain`. Samples are clamped to the range of the
+    /// sample format.
+    pub fn apply_gain(&mut self, gain: f32)
+    where
+        S: IntoSample<f64>,
+        f64: IntoSample<S>,
+    {
+        if self.n_frames == 0 {
+            return;
+        }
+
+        let gain = f64::from(gain);
+
+        self.transform(|s| (gain * s.into_sample()).into_sample());
+    }
+
+    /// Mixes `src`, multiplied by `gain`, into the buffer. The source buffer must have the same
+    /// signal specification, and no more frames, than the buffer. Samples are clamped to the range
+    /// of the sample format.
+    pub fn mix(&mut self, src: &AudioBuffer<S>, gain: f32)
+    where
+        S: IntoSample<f64>,
+        f64: IntoSample<S>,
+    {
+        // Mixing nothing is a no-op, even if either buffer is unused.
+        if src.n_frames == 0 {
+            return;
+        }
+
+        assert!(src.spec == self.spec, "signal specifications do not match");
+        assert!(src.n_frames <= self.n_frames, "source buffer has more frames than the buffer");
+
+        let gain = f64::from(gain);
+
+        let planes = self.buf.chunks_exact_mut(self.n_capacity);
+
+        for (dest, plane) in planes.zip(src.buf.chunks_exact(src.n_capacity)) {
+            for (d, &s) in dest.iter_mut().zip(&plane[..src.n_frames]) {
+                let (d64, s64): (f64, f64) = ((*d).into_sample(), s.into_sample());
+                *d = (d64 + gain * s64).into_sample();
+            }
+        }
+    }
+
+    /// Applies a fade to the frames in `range`. The gain changes from `from` at the first frame to
+    /// `to` at the last frame following the curve provided. Samples are clamped to the range of
+    /// the sample format.
+    pub fn fade(&mut self, range: Range<usize>, from: f32, to: f32, curve: FadeCurve)
+    where
+        S: IntoSample<f64>,
+        f64: IntoSample<S>,
+    {
+        assert!(range.start <= range.end && range.end <= self.n_frames, "range out of bounds");
+
+        let len = range.len();
+
+        if len == 0 {
+            return;
+        }
+
+        let step = if len > 1 { 1.0 / (len - 1) as f64 } else { 0.0 };
+
+        for plane in self.buf.chunks_exact_mut(self.n_capacity) {
+            for (i, s) in plane[range.clone()].iter_mut().enumerate() {
+                let gain = curve.gain(f64::from(from), f64::from(to), i as f64 * step);
+                let faded: f64 = gain * (*s).into_sample();
+                *s = faded.into_sample();
+            }
+        }
+    }
+
+    /// Fades in the first `n_frames` frames of the buffer from silence.
+    pub fn fade_in(&mut self, n_frames: usize, curve: FadeCurve)
+    where
+        S: IntoSample<f64>,
+        f64: IntoSample<S>,
+    {
+        self.fade(0..n_frames.min(self.n_frames), 0.0, 1.0, curve);
+    }
+
+    /// Fades out the last `n_frames` frames of the buffer to silence.
+    pub fn fade_out(&mut self, n_frames: usize, curve: FadeCurve)
+    where
+        S: IntoSample<f64>,
+        f64: IntoSample<S>,
+    {
+        let start = self.n_frames - n_frames.min(self.n_frames);
+
+        self.fade(start..self.n_frames, 1.0, 0.0, curve);
+    }
+
+    /// Increases the capacity of the buffer to `n_capacity` frames.
+    fn grow(&mut self, n_capacity: usize) {
+        if n_capacity <= self.n_capacity {
+            return;
+        }
+
+        let n_channels = self.spec.channels.count();
+
+        let mut buf = vec![S::MID; n_channels * n_capacity];
+
+        if self.n_capacity > 0 {
+            let planes = buf.chunks_exact_mut(n_capacity);
+
+            for (dest, src) in planes.zip(self.buf.chunks_exact(self.n_capacity)) {
+                dest[..self.n_frames].copy_from_slice(&src[..self.n_frames]);
+            }
+        }
+
+        self.buf = buf;
+        self.n_capacity = n_capacity;
+    }
 }
 
 macro_rules! impl_audio_buffer_ref_func {
@@ -432,6 +752,24 @@ impl<'a> AudioBufferRef<'a> {
     pub fn frames(&self) -> usize {
         impl_audio_buffer_ref_func!(self, buf, buf.frames())
     }
+
+    /// Copies the frames in `range` into a new, owned, `AudioBufferRef` with a capacity equal to
+    /// the number of frames copied. Unlike trimming a copy of the buffer, only the frames in
+    /// `range` are copied.
+    pub fn slice(&self, range: Range<usize>) -> AudioBufferRef<'static> {
+        match self {
+            AudioBufferRef::U8(buf)  => AudioBufferRef::U8(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::U16(buf) => AudioBufferRef::U16(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::U24(buf) => AudioBufferRef::U24(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::U32(buf) => AudioBufferRef::U32(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::S8(buf)  => AudioBufferRef::S8(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::S16(buf) => AudioBufferRef::S16(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::S24(buf) => AudioBufferRef::S24(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::S32(buf) => AudioBufferRef::S32(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::F32(buf) => AudioBufferRef::F32(Cow::Owned(buf.slice(range))),
+            AudioBufferRef::F64(buf) => AudioBufferRef::F64(Cow::Owned(buf.slice(range))),
+        }
+    }
 }
 
 /// `AsAudioBufferRef` is a trait implemented for `AudioBuffer`s that may be referenced in an
@@ -462,6 +800,94 @@ impl_as_audio_buffer_ref!(i32, AudioBufferRef::S32);
 impl_as_audio_buffer_ref!(f32, AudioBufferRef::F32);
 impl_as_audio_buffer_ref!(f64, AudioBufferRef::F64);
 
+macro_rules! impl_audio_buffer_mut_func {
+    ($var:expr, $buf:ident,$expr:expr) => {
+        match $var {
+            AudioBufferMut::U8($buf)  => $expr,
+            AudioBufferMut::U16($buf) => $expr,
+            AudioBufferMut::U24($buf) => $expr,
+            AudioBufferMut::U32($buf) => $expr,
+            AudioBufferMut::S8($buf)  => $expr,
+            AudioBufferMut::S16($buf) => $expr,
+            AudioBufferMut::S24($buf) => $expr,
+            AudioBufferMut::S32($buf) => $expr,
+            AudioBufferMut::F32($buf) => $expr,
+            AudioBufferMut::F64($buf) => $expr,
+        }
+    };
+}
+
+/// `AudioBufferMut` is a mutable reference to an `AudioBuffer` of any type. It is used to provide
+/// an output buffer, of the caller's choosing of sample format, to a `Decoder`.
+pub enum AudioBufferMut<'a> {
+    U8(&'a mut AudioBuffer<u8>),
+    U16(&'a mut AudioBuffer<u16>),
+    U24(&'a mut AudioBuffer<u24>),
+    U32(&'a mut AudioBuffer<u32>),
+    S8(&'a mut AudioBuffer<i8>),
+    S16(&'a mut AudioBuffer<i16>),
+    S24(&'a mut AudioBuffer<i24>),
+    S32(&'a mut AudioBuffer<i32>),
+    F32(&'a mut AudioBuffer<f32>),
+    F64(&'a mut AudioBuffer<f64>),
+}
+
+impl<'a> AudioBufferMut<'a> {
+    /// Gets the signal specification for the buffer.
+    pub fn spec(&self) -> &SignalSpec {
+        impl_audio_buffer_mut_func!(self, buf, buf.spec())
+    }
+
+    /// Gets the total capacity of the buffer. The capacity is the maximum number of audio frames
+    /// a buffer can store.
+    pub fn capacity(&self) -> usize {
+        impl_audio_buffer_mut_func!(self, buf, buf.capacity())
+    }
+
+    /// Gets the number of frames in the buffer.
+    pub fn frames(&self) -> usize {
+        impl_audio_buffer_mut_func!(self, buf, buf.frames())
+    }
+
+    /// Replaces the contents of the buffer with the audio in the source `AudioBufferRef`,
+    /// converting the samples to the sample format of the buffer if required. See
+    /// [`AudioBuffer::copy_typed`] for details.
+    pub fn copy_ref(&mut self, src: &AudioBufferRef<'_>) -> Result<()> {
+        impl_audio_buffer_mut_func!(self, buf, buf.copy_ref(src))
+    }
+
+    /// Trims `start` frames from the start, and `end` frames from the end, of the buffer.
+    pub fn trim(&mut self, start: usize, end: usize) {
+        impl_audio_buffer_mut_func!(self, buf, buf.trim(start, end))
+    }
+
+    /// Reborrows the buffer for a shorter lifetime.
+    pub fn reborrow(&mut self) -> AudioBufferMut<'_> {
+        impl_audio_buffer_mut_func!(self, buf, AudioBufferMut::from(&mut **buf))
+    }
+}
+
+macro_rules! impl_audio_buffer_mut_from {
+    ($fmt:ty, $ref:path) => (
+        impl<'a> From<&'a mut AudioBuffer<$fmt>> for AudioBufferMut<'a> {
+            fn from(buf: &'a mut AudioBuffer<$fmt>) -> Self {
+                $ref(buf)
+            }
+        }
+    )
+}
+
+impl_audio_buffer_mut_from!(u8,  AudioBufferMut::U8);
+impl_audio_buffer_mut_from!(u16, AudioBufferMut::U16);
+impl_audio_buffer_mut_from!(u24, AudioBufferMut::U24);
+impl_audio_buffer_mut_from!(u32, AudioBufferMut::U32);
+impl_audio_buffer_mut_from!(i8,  AudioBufferMut::S8);
+impl_audio_buffer_mut_from!(i16, AudioBufferMut::S16);
+impl_audio_buffer_mut_from!(i24, AudioBufferMut::S24);
+impl_audio_buffer_mut_from!(i32, AudioBufferMut::S32);
+impl_audio_buffer_mut_from!(f32, AudioBufferMut::F32);
+impl_audio_buffer_mut_from!(f64, AudioBufferMut::F64);
+
 /// The `Signal` trait provides methods for rendering and transforming contiguous buffers of audio
 /// data.
 pub trait Signal<S : Sample> {
@@ -755,6 +1181,64 @@ impl<S: Sample> SampleBuffer<S> {
         // Commit the written samples.
         self.n_written = n_samples;
     }
+
+    /// Copies all audio samples from a source `AudioBuffer` into the `SampleBuffer` in planar
+    /// channel order. If the conversion is lossy, a dither is applied to each channel using the
+    /// dither in `dithers` with the same index as the channel. The two buffers must be equivalent.
+    pub fn copy_planar_dithered<F, D>(&mut self, src: &AudioBuffer<F>, dithers: &mut [D])
+    where
+        F: Sample + IntoSample<S> + MaybeDither<S>,
+        D: Dither<F, S>,
+    {
+        let n_frames = src.frames();
+        let n_channels = src.spec.channels.count();
+        let n_samples = n_frames * n_channels;
+
+        // Ensure that the capacity of the sample buffer is greater than or equal to the number
+        // of samples that will be copied from the source buffer.
+        assert!(self.capacity() >= n_samples);
+        assert!(dithers.len() >= n_channels, "a dither is required for each channel");
+
+        for (ch, dither) in dithers[..n_channels].iter_mut().enumerate() {
+            let ch_slice = src.chan(ch);
+
+            for (dst, src) in self.buf[ch * n_frames..].iter_mut().zip(ch_slice) {
+                *dst = src.maybe_dither(dither).into_sample();
+            }
+        }
+
+        // Commit the written samples.
+        self.n_written = n_samples;
+    }
+
+    /// Copies all audio samples from a source `AudioBuffer` into the `SampleBuffer` in interleaved
+    /// channel order. If the conversion is lossy, a dither is applied to each channel using the
+    /// dither in `dithers` with the same index as the channel. The two buffers must be equivalent.
+    pub fn copy_interleaved_dithered<F, D>(&mut self, src: &AudioBuffer<F>, dithers: &mut [D])
+    where
+        F: Sample + IntoSample<S> + MaybeDither<S>,
+        D: Dither<F, S>,
+    {
+        let n_channels = src.spec.channels.count();
+        let n_samples = src.frames() * n_channels;
+
+        // Ensure that the capacity of the sample buffer is greater than or equal to the number
+        // of samples that will be copied from the source buffer.
+        assert!(self.capacity() >= n_samples);
+        assert!(dithers.len() >= n_channels, "a dither is required for each channel");
+
+        // Interleave the source buffer channels into the sample buffer.
+        for (ch, dither) in dithers[..n_channels].iter_mut().enumerate() {
+            let ch_slice = src.chan(ch);
+
+            for (dst, src) in self.buf[ch..].iter_mut().step_by(n_channels).zip(ch_slice) {
+                *dst = src.maybe_dither(dither).into_sample();
+            }
+        }
+
+        // Commit the written samples.
+        self.n_written = n_samples;
+    }
 }
 
 /// This non-public module contains the trait `Sealed` which is used to constrain
@@ -988,6 +1472,38 @@ impl<S: Sample + RawSample> RawSampleBuffer<S> {
         self.n_written = n_samples;
     }
 
+    /// Copies all audio data from a source `AudioBuffer` that is of a different sample format type
+    /// than that of the `RawSampleBuffer` in planar channel order. If the conversion is lossy, a
+    /// dither is applied to each channel using the dither in `dithers` with the same index as the
+    /// channel. The two buffers must be equivalent.
+    pub fn copy_planar_dithered<F, D>(&mut self, src: &AudioBuffer<F>, dithers: &mut [D])
+    where
+        F: Sample + IntoSample<S> + MaybeDither<S>,
+        D: Dither<F, S>,
+    {
+        let n_channels = src.spec.channels.count();
+        let n_samples = n_channels * src.n_frames;
+
+        // Ensure that the capacity of the sample buffer is greater than or equal to the number
+        // of samples that will be copied from the source buffer.
+        assert!(self.capacity() >= n_samples);
+        assert!(dithers.len() >= n_channels, "a dither is required for each channel");
+
+        let dst_buf = &mut self.buf[..n_samples];
+
+        let dst_chs = dst_buf.chunks_exact_mut(src.n_frames).zip(dithers.iter_mut());
+
+        for (ch, (dst_ch, dither)) in dst_chs.enumerate() {
+            let src_ch = src.chan(ch);
+
+            for (&s, d) in src_ch.iter().zip(dst_ch) {
+                *d = s.maybe_dither(dither).into_sample().into_raw_sample();
+            }
+        }
+
+        self.n_written = n_samples;
+    }
+
     /// Copies all audio data from the source `AudioBuffer` to the `RawSampleBuffer` in planar order.
     /// The two buffers must be equivalent.
     pub fn copy_planar(&mut self, src: &AudioBuffer<S>) {
@@ -1085,6 +1601,39 @@ impl<S: Sample + RawSample> RawSampleBuffer<S> {
         self.n_written = n_samples;
     }
 
+    /// Copies all audio data from a source `AudioBuffer` that is of a different sample format type
+    /// than that of the `RawSampleBuffer` in interleaved channel order. If the conversion is lossy,
+    /// a dither is applied to each channel using the dither in `dithers` with the same index as the
+    /// channel. The two buffers must be equivalent.
+    pub fn copy_interleaved_dithered<F, D>(&mut self, src: &AudioBuffer<F>, dithers: &mut [D])
+    where
+        F: Sample + IntoSample<S> + MaybeDither<S>,
+        D: Dither<F, S>,
+    {
+        let n_frames = src.n_frames;
+        let n_channels = src.spec.channels.count();
+        let n_samples = n_frames * n_channels;
+
+        // Ensure that the capacity of the sample buffer is greater than or equal to the number
+        // of samples that will be copied from the source buffer.
+        assert!(self.capacity() >= n_samples);
+        assert!(dithers.len() >= n_channels, "a dither is required for each channel");
+
+        // The destination buffer slice.
+        let dst_buf = &mut self.buf[..n_samples];
+
+        for (ch, dither) in dithers[..n_channels].iter_mut().enumerate() {
+            let src_ch = src.chan(ch);
+            let dst_ch_iter = dst_buf[ch..].iter_mut().step_by(n_channels);
+
+            for (&s, d) in src_ch.iter().zip(dst_ch_iter) {
+                *d = s.maybe_dither(dither).into_sample().into_raw_sample();
+            }
+        }
+
+        self.n_written = n_samples;
+    }
+
     /// Copies all audio data from the source `AudioBuffer` to the `RawSampleBuffer` in interleaved
     /// channel order. The two buffers must be equivalent.
     pub fn copy_interleaved(&mut self, src: &AudioBuffer<S>) {
@@ -1134,4 +1683,156 @@ impl<S: Sample + RawSample> RawSampleBuffer<S> {
 
         self.n_written = n_samples;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Error;
+
+    fn ramp(n_frames: usize, start: f32) -> AudioBuffer<f32> {
+        let spec = SignalSpec::new(44_100, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+
+        let mut buf = AudioBuffer::<f32>::new(n_frames as Duration, spec);
+        buf.render_reserved(None);
+
+        for ch in 0..2 {
+            for (i, s) in buf.chan_mut(ch).iter_mut().enumerate() {
+                *s = start + i as f32 + 100.0 * ch as f32;
+            }
+        }
+
+        buf
+    }
+
+    #[test]
+    fn verify_slice_append_split() {
+        let mut buf = ramp(8, 0.0);
+
+        let slice = buf.slice(2..5);
+        assert_eq!(slice.frames(), 3);
+        assert_eq!(slice.chan(0), &[2.0, 3.0, 4.0]);
+        assert_eq!(slice.chan(1), &[102.0, 103.0, 104.0]);
+
+        // Appending beyond the capacity grows the buffer while preserving existing frames.
+        buf.append(&ramp(6, 8.0));
+        assert_eq!(buf.frames(), 14);
+        assert!(buf.capacity() >= 14);
+
+        for ch in 0..2 {
+            for (i, &s) in buf.chan(ch).iter().enumerate() {
+                assert_eq!(s, i as f32 + 100.0 * ch as f32);
+            }
+        }
+
+        let tail = buf.split_off(10);
+        assert_eq!(buf.frames(), 10);
+        assert_eq!(tail.frames(), 4);
+        assert_eq!(tail.chan(1), &[110.0, 111.0, 112.0, 113.0]);
+
+        assert_eq!(buf.slice(3..3).frames(), 0);
+    }
+
+    #[test]
+    fn verify_unused_buffers() {
+        // Appending or mixing an empty or unused buffer does nothing.
+        let mut buf = ramp(4, 0.0);
+        buf.append(&AudioBuffer::unused());
+        buf.append_range(&ramp(4, 0.0), 2..2);
+        buf.mix(&AudioBuffer::unused(), 1.0);
+        assert_eq!(buf.frames(), 4);
+        assert_eq!(buf.chan(0), &[0.0, 1.0, 2.0, 3.0]);
+
+        // Operations on an unused buffer do nothing.
+        let mut buf = AudioBuffer::<f32>::unused();
+        buf.append(&AudioBuffer::unused());
+        buf.mix(&AudioBuffer::unused(), 1.0);
+        buf.apply_gain(2.0);
+        buf.fade_in(4, FadeCurve::Linear);
+        buf.fade_out(4, FadeCurve::Exponential);
+        buf.render_silence(None);
+        assert_eq!(buf.split_off(0).frames(), 0);
+        assert_eq!(buf.slice(0..0).frames(), 0);
+        assert!(buf.is_unused());
+
+        // Appending to an unused buffer allocates it.
+        buf.append(&ramp(3, 1.0));
+        assert_eq!(buf.frames(), 3);
+        assert_eq!(buf.chan(1), &[101.0, 102.0, 103.0]);
+
+        // As does appending to a buffer with no capacity.
+        let mut buf = AudioBuffer::<f32>::new(0, *ramp(1, 0.0).spec());
+        buf.append(&ramp(2, 0.0));
+        assert_eq!(buf.chan(0), &[0.0, 1.0]);
+    }
+
+    #[test]
+    fn verify_mix_and_fade() {
+        let mut buf = ramp(4, 1.0);
+        buf.mix(&ramp(2, 1.0), 0.5);
+        assert_eq!(buf.chan(0), &[1.5, 3.0, 3.0, 4.0]);
+
+        buf.apply_gain(2.0);
+        assert_eq!(buf.chan(0), &[3.0, 6.0, 6.0, 8.0]);
+
+        let mut buf = ramp(5, 1.0);
+        buf.fade_in(5, FadeCurve::Linear);
+        assert_eq!(buf.chan(0), &[0.0, 0.5, 1.5, 3.0, 5.0]);
+
+        let mut buf = ramp(5, 1.0);
+        buf.fade_out(3, FadeCurve::Exponential);
+        assert_eq!(&buf.chan(0)[..3], &[1.0, 2.0, 3.0]);
+        assert_eq!(buf.chan(0)[4], 0.0);
+        assert!(buf.chan(0)[3] < 0.5 * 4.0);
+
+        // Integer samples are clamped.
+        let spec = SignalSpec::new(44_100, Channels::FRONT_LEFT);
+        let mut buf = AudioBuffer::<i16>::new(2, spec);
+        buf.render_reserved(None);
+        buf.chan_mut(0).copy_from_slice(&[20_000, -20_000]);
+        buf.apply_gain(2.0);
+        assert_eq!(buf.chan(0), &[i16::MAX, i16::MIN]);
+    }
+
+    #[test]
+    fn verify_copy_ref() {
+        let src = ramp(4, 0.0);
+
+        // A destination with a different signal specification is an error, and is not reallocated.
+        let spec = SignalSpec::new(8_000, Channels::FRONT_LEFT);
+        let mut dest = AudioBuffer::<f64>::new(8, spec);
+        assert!(matches!(dest.copy_typed(&src), Err(Error::Unsupported(_))));
+        assert!(dest.spec() == &spec && dest.capacity() == 8);
+
+        let mut dest = AudioBuffer::<f64>::new(4, *src.spec());
+
+        let mut dest_mut = AudioBufferMut::from(&mut dest);
+        dest_mut.copy_ref(&src.as_audio_buffer_ref()).unwrap();
+        dest_mut.trim(1, 0);
+        assert_eq!(dest_mut.frames(), 3);
+
+        assert!(dest.spec() == src.spec());
+        assert_eq!(dest.chan(0), &[1.0, 2.0, 3.0]);
+        assert_eq!(dest.chan(1), &[101.0, 102.0, 103.0]);
+
+        // Samples are converted to the sample format of the destination.
+        let mut dest = AudioBuffer::<i16>::new(4, *src.spec());
+        let mut src = src.slice(0..2);
+        src.transform(|s| s / 200.0);
+        dest.copy_ref(&src.as_audio_buffer_ref()).unwrap();
+        assert_eq!(dest.chan(1), &[16384, 16548]);
+
+        // An unused destination is allocated, and copying an unused source clears it.
+        let mut dest = AudioBuffer::<f32>::unused();
+        dest.copy_typed(&src).unwrap();
+        assert_eq!(dest.chan(0), src.chan(0));
+
+        dest.copy_typed(&AudioBuffer::<f32>::unused()).unwrap();
+        assert_eq!(dest.frames(), 0);
+
+        // A destination that is too small is not reallocated.
+        let mut dest = AudioBuffer::<f32>::new(3, *src.spec());
+        assert!(matches!(dest.copy_typed(&ramp(4, 0.0)), Err(Error::LimitError(_))));
+        assert_eq!(dest.capacity(), 3);
+    }
+}
@@ -6,16 +6,19 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! The `codec` module provides the traits and support structures necessary to implement audio codec
-//! decoders.
+//! decoders and encoders.
 
 use std::collections::HashMap;
 use std::default::Default;
 use std::fmt;
+use std::mem;
 
-use crate::audio::{AudioBufferRef, Channels, Layout};
-use crate::errors::{Result, unsupported_error};
+use crate::audio::{AudioBuffer, AudioBufferMut, AudioBufferRef, Channels, Layout, Signal};
+use crate::conv::IntoSample;
+use crate::errors::{Error, Result, unsupported_error};
 use crate::formats::Packet;
-use crate::sample::SampleFormat;
+use crate::meta::ReplayGain;
+use crate::sample::{Sample, SampleFormat};
 use crate::units::TimeBase;
 
 /// A `CodecType` is a unique identifier used to identify a specific codec. `CodecType` is mainly
@@ -163,7 +166,11 @@ pub enum VerificationCheck {
 
 /// Codec parameters stored in a container format's headers and metadata may be passed to a codec
 /// using the `CodecParameters` structure.
+///
+/// New parameters may be added at any time, therefore `CodecParameters` must be instantiated with
+/// `CodecParameters::new`, and populated using the `with_*` functions.
 #[derive(Clone)]
+#[non_exhaustive]
 pub struct CodecParameters {
     /// The codec type.
     pub codec: CodecType,
@@ -197,7 +204,7 @@ pub struct CodecParameters {
     pub leading_padding: Option<u32>,
 
     /// The number of trailing frames inserted by the encoder for padding that should be skipped
-    /// during playback.
+    /// during playback. The padding is counted from the end of the total number of frames.
     pub trailing_padding: Option<u32>,
 
     /// The maximum number of frames a packet will contain.
@@ -209,6 +216,9 @@ pub struct CodecParameters {
     /// A method and expected value that may be used to perform verification on the decoded audio.
     pub verification_check: Option<VerificationCheck>,
 
+    /// The ReplayGain information stored in the codec or container format's headers.
+    pub replay_gain: Option<ReplayGain>,
+
     /// Extra data (defined by the codec).
     pub extra_data: Option<Box<[u8]>>,
 }
@@ -230,6 +240,7 @@ impl CodecParameters {
             max_frames_per_packet: None,
             packet_data_integrity: false,
             verification_check: None,
+            replay_gain: None,
             extra_data: None,
         }
     }
@@ -318,11 +329,42 @@ impl CodecParameters {
         self
     }
 
+    /// Provide the ReplayGain information.
+    pub fn with_replay_gain(&mut self, replay_gain: ReplayGain) -> &mut Self {
+        self.replay_gain = Some(replay_gain);
+        self
+    }
+
     /// Provide a verification code of the final decoded audio.
     pub fn with_verification_code(&mut self, code: VerificationCheck) -> &mut Self {
         self.verification_check = Some(code);
         self
     }
+
+    /// Gets the number of frames to trim from the start and end of `n_frames` decoded frames
+    /// starting at timestamp `ts` such that any leading and trailing padding is removed. The
+    /// timestamp must be in units of frames, and count the leading padding.
+    ///
+    /// Trailing padding can only be removed if the total number of frames is known.
+    pub fn padding_trim(&self, ts: u64, n_frames: usize) -> (usize, usize) {
+        let n = n_frames as u64;
+
+        let leading = u64::from(self.leading_padding.unwrap_or(0));
+
+        let start = leading.saturating_sub(ts).min(n);
+
+        let end = match self.n_frames {
+            Some(total) => {
+                let trailing = u64::from(self.trailing_padding.unwrap_or(0));
+                let last = total.saturating_sub(trailing).max(leading);
+
+                (ts + n).saturating_sub(last).min(n - start)
+            }
+            None => 0,
+        };
+
+        (start as usize, end as usize)
+    }
 }
 
 /// `FinalizeResult` contains optional information that can only be found, calculated, or
@@ -335,17 +377,155 @@ pub struct FinalizeResult {
 }
 
 /// `DecoderOptions` is a common set of options that all decoders use.
+///
+/// New options may be added at any time, therefore `DecoderOptions` must be instantiated with
+/// `DecoderOptions::default`, and modified using the `with_*` functions.
+#[non_exhaustive]
 pub struct DecoderOptions {
     /// The decoded audio should be verified if possible during the decode process.
     pub verify: bool,
+    /// The leading and trailing padding signalled by the `CodecParameters` should be trimmed from
+    /// the decoded audio for gapless playback. Packet timestamps are used to locate the padding,
+    /// therefore the padding is also trimmed after a seek.
+    pub gapless: bool,
+    /// How damaged packets should be concealed. Not all decoders support concealment.
+    pub conceal: Concealment,
 }
 
 impl Default for DecoderOptions {
     fn default() -> Self {
         DecoderOptions {
             verify: false,
+            gapless: false,
+            conceal: Concealment::Disabled,
+        }
+    }
+}
+
+impl DecoderOptions {
+    /// Specify if the decoded audio should be verified.
+    pub fn with_verify(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Specify if the padding should be trimmed from the decoded audio for gapless playback.
+    pub fn with_gapless(&mut self, gapless: bool) -> &mut Self {
+        self.gapless = gapless;
+        self
+    }
+
+    /// Specify how damaged packets should be concealed.
+    pub fn with_conceal(&mut self, conceal: Concealment) -> &mut Self {
+        self.conceal = conceal;
+        self
+    }
+}
+
+/// `Concealment` specifies how a `Decoder` handles a damaged packet.
+///
+/// If concealment is enabled, a `Decoder` that fails to decode a damaged packet outputs
+/// concealment audio of the same duration as the damaged packet instead of returning a decode
+/// error. This keeps the decoded audio continuous, and its timestamps accurate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Concealment {
+    /// Damaged packets are not concealed, and a decode error is returned.
+    #[default]
+    Disabled,
+    /// Damaged packets are concealed with silence. Decoders using an overlapping transform
+    /// complete the last decoded packet as if the damaged packet was silent, muting the audio
+    /// without a discontinuity.
+    Silence,
+    /// Damaged packets are concealed by repeating the audio of the last decoded packet. The audio
+    /// is attenuated by 6 dB each time it is repeated.
+    Repeat,
+}
+
+/// `Concealer` implements the common parts of damaged packet concealment for a `Decoder`.
+pub struct Concealer<S: Sample> {
+    mode: Concealment,
+    /// The audio of the last decoded packet if the audio may be repeated.
+    last: Option<AudioBuffer<S>>,
+    /// The number of consecutive packets concealed.
+    n_consecutive: u32,
+    /// The total number of frames concealed.
+    n_concealed: u64,
+}
+
+impl<S> Concealer<S>
+where
+    S: Sample + IntoSample<f64>,
+    f64: IntoSample<S>,
+{
+    /// Instantiate a new `Concealer` for the concealment mode provided.
+    pub fn new(mode: Concealment) -> Self {
+        Concealer { mode, last: None, n_consecutive: 0, n_concealed: 0 }
+    }
+
+    /// Gets the concealment mode.
+    pub fn mode(&self) -> Concealment {
+        self.mode
+    }
+
+    /// Returns `true` if concealment is enabled, and the error returned while decoding a packet
+    /// indicates the packet is damaged. An IO error is not concealed since it may indicate the
+    /// end of the stream rather than a damaged packet.
+    pub fn can_conceal(&self, err: &Error) -> bool {
+        self.mode != Concealment::Disabled && matches!(err, Error::DecodeError(_))
+    }
+
+    /// Gets the total number of audio frames concealed.
+    pub fn concealed_frames(&self) -> u64 {
+        self.n_concealed
+    }
+
+    /// Informs the `Concealer` that a packet was successfully decoded into `buf`.
+    pub fn decoded(&mut self, buf: &AudioBuffer<S>) {
+        self.n_consecutive = 0;
+
+        if self.mode == Concealment::Repeat {
+            match self.last {
+                Some(ref mut last) if last.spec() == buf.spec() => {
+                    last.clear();
+                    last.append(buf);
+                }
+                _ => self.last = Some(buf.slice(0..buf.frames())),
+            }
+        }
+    }
+
+    /// Conceals a damaged packet. On entry, `buf` must contain the silent concealment audio of the
+    /// damaged packet. That is, either silence, or the output of the decoder's overlapping
+    /// transform given a silent packet. In `Repeat` mode, the contents of `buf` are replaced with
+    /// the attenuated audio of the last decoded packet.
+    pub fn conceal(&mut self, buf: &mut AudioBuffer<S>) {
+        self.n_consecutive = self.n_consecutive.saturating_add(1);
+        self.n_concealed += buf.frames() as u64;
+
+        if let Some(last) = &self.last {
+            // The last decoded packet may be shorter or longer than the damaged packet, therefore
+            // repeat it as many times as required to fill the buffer.
+            if last.frames() > 0 && last.spec() == buf.spec() {
+                for ch in 0..buf.spec().channels.count() {
+                    let src = last.chan(ch).iter().cycle();
+
+                    for (d, &s) in buf.chan_mut(ch).iter_mut().zip(src) {
+                        *d = s;
+                    }
+                }
+
+                let gain = 0.5f32.powi(self.n_consecutive.min(64) as i32);
+
+                buf.apply_gain(gain);
+            }
         }
     }
+
+    /// Resets the `Concealer`. The audio of the last decoded packet is discarded.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.n_consecutive = 0;
+    }
 }
 
 /// A `Decoder` implements a codec's decode algorithm. It consumes `Packet`s and produces
@@ -377,10 +557,134 @@ pub trait Decoder: Send {
     /// of the decoded audio.
     fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef>;
 
+    /// Decodes a `Packet` of audio data into a caller-provided audio buffer. The contents of
+    /// `dest` are replaced with the decoded audio, converted to the sample format of `dest` if
+    /// required.
+    ///
+    /// If `dest` is unused, it is allocated for the decoded audio, and should then be reused for
+    /// subsequent packets. Otherwise, `dest` is never reallocated. If `dest` does not have the
+    /// signal specification of the decoded audio, an `Unsupported` error is returned. If the
+    /// capacity of `dest` is too small for the decoded audio, a `LimitError` is returned. In
+    /// either case, the decoded audio is discarded, and an unused buffer may be provided to
+    /// decode subsequent packets.
+    ///
+    /// The default implementation copies the audio returned by `decode` into `dest`. Decoders that
+    /// can decode directly into `dest` should override this implementation.
+    fn decode_into(&mut self, packet: &Packet, mut dest: AudioBufferMut<'_>) -> Result<()> {
+        let decoded = self.decode(packet)?;
+        dest.copy_ref(&decoded)
+    }
+
+    /// Gets the total number of audio frames concealed by the decoder since it was instantiated.
+    /// If the decoder does not support concealment, or concealment is disabled, this is always 0.
+    fn concealed_frames(&self) -> u64 {
+        0
+    }
+
     /// Optionally, obtain post-decode information such as the verification status.
     fn finalize(&mut self) -> FinalizeResult;
 }
 
+/// Decodes a `Packet` directly into `dest`, an audio buffer of the native sample format of the
+/// `Decoder`, by exchanging it with the `Decoder`'s own audio buffer for the duration of the call
+/// to `decode`. The function `buf` gets the `Decoder`'s own audio buffer.
+///
+/// The exchange is only possible if `dest` is unused, or has the same signal specification and
+/// capacity as the `Decoder`'s buffer, and the `Decoder` does not reallocate its buffer while
+/// decoding. Otherwise, the decoded audio is copied into `dest`. See [`Decoder::decode_into`] for
+/// details.
+///
+/// This function is intended to be used by `Decoder`s to implement `decode_into`.
+pub fn decode_into_native<D, S>(
+    decoder: &mut D,
+    packet: &Packet,
+    dest: &mut AudioBuffer<S>,
+    buf: fn(&mut D) -> &mut AudioBuffer<S>,
+) -> Result<()>
+where
+    D: Decoder,
+    S: Sample + IntoSample<S>,
+{
+    let own = buf(decoder);
+
+    if dest.is_unused() {
+        if !own.is_unused() {
+            *dest = own.make_equivalent();
+        }
+    }
+    else if dest.spec() != own.spec() || dest.capacity() != own.capacity() {
+        decoder.decode(packet)?;
+        return dest.copy_typed(buf(decoder));
+    }
+
+    mem::swap(own, dest);
+
+    let result = decoder.decode(packet).map(|_| ());
+
+    let own = buf(decoder);
+
+    // If the decoder reallocated the buffer it was given in exchange, for example because the
+    // signal specification changed, then the decoder keeps the new buffer and the decoded audio is
+    // copied into `dest`. This way, `dest` retains the signal specification and capacity it had.
+    if own.spec() != dest.spec() || own.capacity() != dest.capacity() {
+        result?;
+        return dest.copy_typed(own);
+    }
+
+    mem::swap(own, dest);
+
+    result
+}
+
+/// `EncoderOptions` is a common set of options that all encoders use.
+#[derive(Default)]
+pub struct EncoderOptions {
+    /// The ID of the track the encoded packets belong to.
+    pub track_id: u32,
+    /// The codec-specific compression level. Higher levels typically trade encoding speed for a
+    /// smaller encoded size. If `None`, the encoder's default compression level is used.
+    pub compression_level: Option<u32>,
+    /// The target bit rate in bits per second. Only applicable to lossy codecs. If `None`, the
+    /// encoder's default bit rate is used.
+    pub bit_rate: Option<u32>,
+}
+
+/// An `Encoder` implements a codec's encode algorithm. It consumes `AudioBuffer`s and produces
+/// `Packet`s.
+pub trait Encoder: Send {
+    /// Attempts to instantiate an `Encoder` using the provided `CodecParameters`. The parameters
+    /// must describe the audio that will be encoded, including the codec, sample rate, and
+    /// channels.
+    fn try_new(params: &CodecParameters, options: &EncoderOptions) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Gets a list of encoder descriptors for the codecs supported by this Encoder.
+    fn supported_codecs() -> &'static [EncoderDescriptor]
+    where
+        Self: Sized;
+
+    /// Reset the `Encoder`.
+    ///
+    /// Any buffered audio that has not yet been encoded is discarded, and the next packet will be
+    /// encoded as if it were the first packet.
+    fn reset(&mut self);
+
+    /// Gets a reference to the parameters of the encoded stream. Some parameters, such as the
+    /// total number of frames, or the codec's extra data, may only be known after the `Encoder`
+    /// is finalized.
+    fn codec_params(&self) -> &CodecParameters;
+
+    /// Encodes a generic (untyped) audio buffer, and returns all the `Packet`s that could be
+    /// completed. Since encoders may buffer audio internally, zero or more packets may be
+    /// returned.
+    fn encode(&mut self, buf: &AudioBufferRef) -> Result<Vec<Packet>>;
+
+    /// Encodes any remaining buffered audio and returns the final `Packet`s. After the `Encoder`
+    /// is finalized, `codec_params` returns the finalized `CodecParameters` of the encoded stream.
+    fn finalize(&mut self) -> Result<Vec<Packet>>;
+}
+
 /// A `CodecDescriptor` stores a description of a single logical codec. Common information such as
 /// the `CodecType`, a short name, and a long name are provided. The `CodecDescriptor` also provides
 /// an instantiation function. When the instantiation function is called, a `Decoder` for the codec
@@ -397,10 +701,26 @@ pub struct CodecDescriptor {
     pub inst_func: fn(&CodecParameters, &DecoderOptions) -> Result<Box<dyn Decoder>>,
 }
 
-/// A `CodecRegistry` allows the registration of codecs, and provides a method to instantiate a
-/// `Decoder` given a `CodecParameters` object.
+/// An `EncoderDescriptor` stores a description of a single logical codec that can be encoded. It is
+/// the `Encoder` counterpart of a `CodecDescriptor`. When the instantiation function is called, an
+/// `Encoder` for the codec is returned.
+#[derive(Copy, Clone)]
+pub struct EncoderDescriptor {
+    /// The `CodecType` identifier.
+    pub codec: CodecType,
+    /// A short ASCII-only string identifying the codec.
+    pub short_name: &'static str,
+    /// A longer, more descriptive, string identifying the codec.
+    pub long_name: &'static str,
+    // An instantiation function for the encoder.
+    pub inst_func: fn(&CodecParameters, &EncoderOptions) -> Result<Box<dyn Encoder>>,
+}
+
+/// A `CodecRegistry` allows the registration of codecs, and provides methods to instantiate a
+/// `Decoder` or `Encoder` given a `CodecParameters` object.
 pub struct CodecRegistry {
     codecs: HashMap<CodecType, CodecDescriptor>,
+    encoders: HashMap<CodecType, EncoderDescriptor>,
 }
 
 impl CodecRegistry {
@@ -408,6 +728,7 @@ impl CodecRegistry {
     pub fn new() -> Self {
         CodecRegistry {
             codecs: HashMap::new(),
+            encoders: HashMap::new(),
         }
     }
 
@@ -444,6 +765,40 @@ impl CodecRegistry {
             unsupported_error("unsupported codec")
         }
     }
+
+    /// Gets the `EncoderDescriptor` for a registered encoder.
+    pub fn get_encoder(&self, codec: CodecType) -> Option<&EncoderDescriptor> {
+        self.encoders.get(&codec)
+    }
+
+    /// Registers all codecs supported by `Encoder`. If a supported codec was previously registered
+    /// by another `Encoder` it will be replaced within the registry.
+    pub fn register_all_encoders<E: Encoder>(&mut self) {
+        for descriptor in E::supported_codecs() {
+            self.register_encoder(descriptor);
+        }
+    }
+
+    /// Register a single encoder. If an encoder for the codec was previously registered it will be
+    /// replaced within the registry.
+    pub fn register_encoder(&mut self, descriptor: &EncoderDescriptor) {
+        self.encoders.insert(descriptor.codec, *descriptor);
+    }
+
+    /// Searches the registry for an `Encoder` that supports the codec. If one is found, it will be
+    /// instantiated with the provided `CodecParameters` and returned. If an `Encoder` could not be
+    /// found, or the `CodecParameters` are either insufficient or invalid for the `Encoder`, an
+    /// error will be returned.
+    pub fn make_encoder(&self, params: &CodecParameters, options: &EncoderOptions)
+        -> Result<Box<dyn Encoder>> {
+
+        if let Some(descriptor) = self.encoders.get(&params.codec) {
+            Ok((descriptor.inst_func)(params, options)?)
+        }
+        else {
+            unsupported_error("unsupported encoder")
+        }
+    }
 }
 
 /// Convenience macro for declaring a `CodecDescriptor`.
@@ -459,4 +814,129 @@ macro_rules! support_codec {
             }
         }
     };
-}
\ No newline at end of file
+}
+
+/// Convenience macro for declaring an `EncoderDescriptor`.
+#[macro_export]
+macro_rules! support_encoder {
+    ($type:expr, $short_name:expr, $long_name:expr) => {
+        EncoderDescriptor {
+            codec: $type,
+            short_name: $short_name,
+            long_name: $long_name,
+            inst_func: |params, opt| {
+                Ok(Box::new(Self::try_new(&params, &opt)?))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{AsAudioBufferRef, AudioBuffer, Signal, SignalSpec};
+    use crate::errors::Result;
+
+    /// An encoder that emits a packet containing the number of frames of every buffer encoded.
+    struct CountingEncoder {
+        params: CodecParameters,
+        track_id: u32,
+        ts: u64,
+    }
+
+    impl Encoder for CountingEncoder {
+        fn try_new(params: &CodecParameters, options: &EncoderOptions) -> Result<Self> {
+            Ok(CountingEncoder { params: params.clone(), track_id: options.track_id, ts: 0 })
+        }
+
+        fn supported_codecs() -> &'static [EncoderDescriptor] {
+            &[support_encoder!(CODEC_TYPE_PCM_U8, "counting", "Counting Encoder")]
+        }
+
+        fn reset(&mut self) {
+            self.ts = 0;
+        }
+
+        fn codec_params(&self) -> &CodecParameters {
+            &self.params
+        }
+
+        fn encode(&mut self, buf: &AudioBufferRef) -> Result<Vec<Packet>> {
+            let dur = buf.frames() as u64;
+            let packet = Packet::new_from_slice(self.track_id, self.ts, dur, &[dur as u8]);
+            self.ts += dur;
+            Ok(vec![packet])
+        }
+
+        fn finalize(&mut self) -> Result<Vec<Packet>> {
+            self.params.with_n_frames(self.ts);
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn verify_encoder_registry() {
+        let mut registry = CodecRegistry::new();
+        registry.register_all_encoders::<CountingEncoder>();
+
+        assert!(registry.get_encoder(CODEC_TYPE_PCM_U8).is_some());
+        assert!(registry.get_codec(CODEC_TYPE_PCM_U8).is_none());
+
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_PCM_U8).with_sample_rate(8000);
+
+        let options = EncoderOptions { track_id: 3, ..Default::default() };
+        let mut encoder = registry.make_encoder(&params, &options).unwrap();
+
+        let spec = SignalSpec::new(8000, Channels::FRONT_LEFT);
+        let mut buf = AudioBuffer::<u8>::new(16, spec);
+        buf.render_reserved(Some(10));
+
+        for &ts in &[0, 10] {
+            let packets = encoder.encode(&buf.as_audio_buffer_ref()).unwrap();
+            assert_eq!(packets.len(), 1);
+            assert_eq!((packets[0].track_id(), packets[0].pts()), (3, ts));
+        }
+
+        assert!(encoder.finalize().unwrap().is_empty());
+        assert_eq!(encoder.codec_params().n_frames, Some(20));
+
+        params.for_codec(CODEC_TYPE_PCM_U16LE);
+        assert!(registry.make_encoder(&params, &options).is_err());
+    }
+
+    #[test]
+    fn verify_padding_trim() {
+        let mut params = CodecParameters::new();
+        params.with_n_frames(4 * 1152).with_leading_padding(1105).with_trailing_padding(1000);
+
+        // First packet: mostly leading padding.
+        assert_eq!(params.padding_trim(0, 1152), (1105, 0));
+        // Packets without any padding are not trimmed.
+        assert_eq!(params.padding_trim(1152, 1152), (0, 0));
+        // Last packet: trailing padding.
+        assert_eq!(params.padding_trim(3 * 1152, 1152), (0, 1000));
+        // Packets entirely within the padding are trimmed completely.
+        assert_eq!(params.padding_trim(0, 1000), (1000, 0));
+        assert_eq!(params.padding_trim(4 * 1152 - 500, 500), (0, 500));
+
+        // Trailing padding is not trimmed if the number of frames is unknown.
+        params.n_frames = None;
+        assert_eq!(params.padding_trim(3 * 1152, 1152), (0, 0));
+    }
+
+    #[test]
+    fn verify_can_conceal() {
+        let damaged = Error::DecodeError("damaged");
+        let eof = || Error::IoError(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "eof"));
+
+        assert!(!Concealer::<f32>::new(Concealment::Disabled).can_conceal(&damaged));
+
+        for &mode in [Concealment::Silence, Concealment::Repeat].iter() {
+            let concealer = Concealer::<f32>::new(mode);
+            assert!(concealer.can_conceal(&damaged));
+            assert!(!concealer.can_conceal(&eof()));
+            assert!(!concealer.can_conceal(&Error::ResetRequired));
+        }
+    }
+}
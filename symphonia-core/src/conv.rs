@@ -26,6 +26,10 @@ pub mod dither {
     //! Multiple dithering algorithms are provided, each drawing noise from a different probability
     //! distribution. In addition to different distributions, a dithering algorithm may also shape
     //! the noise such that the bulk of the noise is placed in an inaudible frequency range.
+    //!
+    //! Dithers that shape the noise maintain state, therefore each channel must be dithered by a
+    //! separate instance. The dithered copy functions of `SampleBuffer` and `RawSampleBuffer`
+    //! accept one dither per channel.
     use std::marker::PhantomData;
     use super::FromSample;
     use crate::sample::{u24, i24};
@@ -243,6 +247,164 @@ pub mod dither {
         }
     }
 
+    /// `HighPassTriangular` implements a dither using a triangular distribution of noise that is
+    /// high-pass filtered.
+    ///
+    /// The noise is the difference of consecutive uniformly distributed random values. This places
+    /// more of the noise at high frequencies, where it is less audible, than `Triangular` dither
+    /// while retaining the same probability distribution.
+    pub struct HighPassTriangular<F: Sample, T: Sample> {
+        prng: prng::Xoshiro128pp,
+        prev: i32,
+        from_type: PhantomData<F>,
+        to_type: PhantomData<T>,
+    }
+
+    impl<F: Sample, T: Sample> HighPassTriangular<F, T> {
+        pub fn new() -> Self {
+            HighPassTriangular {
+                prng: prng::Xoshiro128pp::new(0xb2c1_01f4_425b_987e),
+                prev: 0,
+                from_type: PhantomData,
+                to_type: PhantomData,
+            }
+        }
+    }
+
+    impl<F: Sample, T: Sample> Default for HighPassTriangular<F, T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<F: Sample, T: Sample> Dither<F, T> for HighPassTriangular<F, T>
+    where
+        RandomNoise : AddNoise<F>
+    {
+        fn dither(&mut self, sample: F) -> F {
+            debug_assert!(F::EFF_BITS > T::EFF_BITS);
+
+            let dither_bits = 32 - T::EFF_BITS;
+
+            // Generate a high-pass triangular distribution from the difference of consecutive
+            // values of the uniform distribution.
+            let next = self.prng.next() as i32 >> 1;
+            let hp_tpdf = next - self.prev;
+            self.prev = next;
+
+            // Add the noise to the sample.
+            let noise = RandomNoise::from(hp_tpdf, dither_bits);
+            noise.add_noise(sample)
+        }
+    }
+
+    /// The error feedback filter used by `NoiseShaped` dither.
+    ///
+    /// The filters are designed for a sample rate of 44.1 kHz, and move the noise from the
+    /// frequencies where hearing is most sensitive to the frequencies where it is least sensitive.
+    /// At other sample rates, the shaping is less effective.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum NoiseShape {
+        /// The 5-tap E-weighted filter by Lipshitz, Vanderkooy, and Wannamaker.
+        Lipshitz,
+        /// The 3-tap F-weighted filter by Wannamaker.
+        Wannamaker3,
+        /// The 9-tap F-weighted filter by Wannamaker.
+        Wannamaker9,
+    }
+
+    impl NoiseShape {
+        /// Gets the error feedback filter coefficients.
+        fn coefficients(self) -> &'static [f64] {
+            match self {
+                NoiseShape::Lipshitz => &[2.033, -2.165, 1.959, -1.590, 0.6149],
+                NoiseShape::Wannamaker3 => &[1.623, -0.982, 0.109],
+                NoiseShape::Wannamaker9 => &[
+                    2.412, -3.370, 3.937, -4.174, 3.353, -2.205, 1.281, -0.569, 0.0847,
+                ],
+            }
+        }
+    }
+
+    /// The maximum number of error feedback filter coefficients.
+    const MAX_NOISE_SHAPE_TAPS: usize = 9;
+
+    /// `NoiseShaped` implements a dither using a triangular distribution of noise, and shapes the
+    /// spectrum of the total quantization error using an error feedback filter.
+    ///
+    /// The quantization error of previous samples is filtered and subtracted from the current
+    /// sample before it is quantized. Therefore, a `NoiseShaped` dither maintains state and an
+    /// instance must only be used to dither a single channel.
+    pub struct NoiseShaped<F: Sample, T: Sample> {
+        prng: prng::Xoshiro128pp,
+        coefficients: &'static [f64],
+        /// The quantization error of the previous samples, most recent first.
+        errors: [f64; MAX_NOISE_SHAPE_TAPS],
+        from_type: PhantomData<F>,
+        to_type: PhantomData<T>,
+    }
+
+    impl<F: Sample, T: Sample> NoiseShaped<F, T> {
+        pub fn new(shape: NoiseShape) -> Self {
+            NoiseShaped {
+                prng: prng::Xoshiro128pp::new(0xb2c1_01f4_425b_987e),
+                coefficients: shape.coefficients(),
+                errors: [0.0; MAX_NOISE_SHAPE_TAPS],
+                from_type: PhantomData,
+                to_type: PhantomData,
+            }
+        }
+
+        /// Clears the error feedback state.
+        pub fn reset(&mut self) {
+            self.errors = [0.0; MAX_NOISE_SHAPE_TAPS];
+        }
+    }
+
+    impl<F: Sample, T: Sample> Dither<F, T> for NoiseShaped<F, T>
+    where
+        F: FromSample<f64>,
+        T: FromSample<F>,
+        f64: FromSample<F> + FromSample<T>,
+    {
+        fn dither(&mut self, sample: F) -> F {
+            debug_assert!(F::EFF_BITS > T::EFF_BITS);
+
+            let dither_bits = 32 - T::EFF_BITS;
+
+            // The size of the least significant bit of the destination sample format.
+            let lsb = 2.0f64.powi(1 - T::EFF_BITS as i32);
+
+            // Subtract the filtered quantization error of the previous samples.
+            let feedback = self
+                .coefficients
+                .iter()
+                .zip(&self.errors)
+                .map(|(&c, &e)| c * e)
+                .sum::<f64>();
+
+            let shaped = f64::from_sample(sample) - feedback;
+
+            // Add triangular noise with a peak amplitude of 1 LSB. This is the minimum amplitude
+            // that fully decorrelates the power of the quantization error from the signal.
+            let tpdf = (self.prng.next() as i32 >> 1) + (self.prng.next() as i32 >> 1);
+            let noise = RandomNoise::from(tpdf, dither_bits + 1);
+
+            let dithered = F::from_sample(shaped + f64::from_sample(noise.0));
+
+            // Quantize the sample exactly as the conversion to the destination sample format will,
+            // and store the total error. The error is limited such that clipping cannot cause the
+            // filter to become unstable.
+            let quantized = f64::from_sample(T::from_sample(dithered));
+            let error = (quantized - shaped).max(-2.0 * lsb).min(2.0 * lsb);
+
+            self.errors.copy_within(..MAX_NOISE_SHAPE_TAPS - 1, 1);
+            self.errors[0] = error;
+
+            dithered
+        }
+    }
+
     /// Enumeration of dither algorithms.
     pub enum DitherType {
         /// No dithering.
@@ -251,6 +413,10 @@ pub mod dither {
         Rectangular,
         /// Apply triangular dithering. See `Triangular` for more details.
         Triangular,
+        /// Apply high-pass triangular dithering. See `HighPassTriangular` for more details.
+        HighPassTriangular,
+        /// Apply noise shaped dithering. See `NoiseShaped` for more details.
+        NoiseShaped(NoiseShape),
     }
 
     /// `MaybeDither` conditionally applies a dither to a sample depending on the source and
@@ -621,8 +787,82 @@ where
 #[cfg(test)]
 mod tests {
     use std::{u8, i8, u16, i16, u32, i32};
+    use crate::audio::{AudioBuffer, Channels, SampleBuffer, Signal, SignalSpec};
+    use crate::dsp::fft::{Complex, RealFft};
     use crate::sample::{u24, i24, Sample};
     use super::FromSample;
+    use super::dither::{Dither, HighPassTriangular, NoiseShape, NoiseShaped, Triangular};
+
+    /// Gets the power of the quantization error, below 4 kHz, of a 1 kHz sinusoid at 44.1 kHz
+    /// when converted from `i32` to `i16` using the dither provided.
+    fn low_frequency_error_power<D: Dither<i32, i16>>(dither: D) -> f64 {
+        const N: usize = 8192;
+
+        let spec = SignalSpec::new(44_100, Channels::FRONT_LEFT);
+
+        let mut src = AudioBuffer::<i32>::new(N as u64, spec);
+        src.render_reserved(None);
+
+        for (i, s) in src.chan_mut(0).iter_mut().enumerate() {
+            let theta = 2.0 * std::f64::consts::PI * 1000.0 * i as f64 / 44_100.0;
+            *s = i32::from_sample(0.01 * theta.sin());
+        }
+
+        let mut dst = SampleBuffer::<i16>::new(N as u64, spec);
+        dst.copy_interleaved_dithered(&src, &mut [dither]);
+
+        let error: Vec<f32> = src
+            .chan(0)
+            .iter()
+            .zip(dst.samples())
+            .map(|(&x, &q)| (f64::from_sample(q) - f64::from_sample(x)) as f32)
+            .collect();
+
+        let mut spectrum = vec![Complex::default(); N / 2 + 1];
+        RealFft::new(N).fft(&error, &mut spectrum);
+
+        spectrum[..N * 4000 / 44_100].iter().map(|c| f64::from(c.norm_sqr())).sum()
+    }
+
+    #[test]
+    fn verify_noise_shaped_dither() {
+        let tpdf = low_frequency_error_power(Triangular::new());
+
+        for &shape in &[NoiseShape::Lipshitz, NoiseShape::Wannamaker3, NoiseShape::Wannamaker9] {
+            let shaped = low_frequency_error_power(NoiseShaped::new(shape));
+            assert!(shaped < 0.25 * tpdf);
+        }
+    }
+
+    #[test]
+    fn verify_high_pass_triangular_dither() {
+        const N: usize = 65536;
+
+        let mut dither = HighPassTriangular::<i32, i16>::new();
+
+        // Dither silence to obtain the noise alone.
+        let noise: Vec<f64> = (0..N).map(|_| f64::from(dither.dither(0))).collect();
+
+        // Each uniform value has a peak amplitude of 1/4 LSB of the destination sample format, so
+        // their difference has a peak amplitude of 1/2 LSB.
+        let peak = f64::from(1 << 14);
+
+        assert!(noise.iter().all(|&x| x.abs() <= 2.0 * peak));
+
+        let mean = noise.iter().sum::<f64>() / N as f64;
+        let var = noise.iter().map(|&x| (x - mean) * (x - mean)).sum::<f64>() / N as f64;
+        let cov = noise.windows(2).map(|w| (w[0] - mean) * (w[1] - mean)).sum::<f64>() / N as f64;
+
+        // The variance is that of two independent uniformly distributed values.
+        let expected_var = 2.0 * peak * peak / 3.0;
+
+        assert!(mean.abs() < 0.02 * peak);
+        assert!((var - expected_var).abs() < 0.02 * expected_var);
+
+        // Consecutive noise values share a uniform value with opposite signs, so the lag-1
+        // autocorrelation is -0.5. The noise is therefore high-passed, white noise would have none.
+        assert!((cov / var + 0.5).abs() < 0.02);
+    }
 
     #[test]
     fn verify_u8_from_sample() {
@@ -0,0 +1,530 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `fft` module implements the Fast Fourier Transform (FFT).
+//!
+//! `Fft` is a mixed-radix, decimation-in-time, complex FFT. Any size is supported, though sizes
+//! with only the factors 2, 3, and 5 are the most efficient. `RealFft` computes the FFT of a real
+//! signal using a complex FFT of half the size.
+
+use std::f64;
+use std::ops::{Add, Mul, Sub};
+
+use super::simd;
+
+/// A complex number.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Complex {
+    /// The real component.
+    pub re: f32,
+    /// The imaginary component.
+    pub im: f32,
+}
+
+impl Complex {
+    /// Create a new complex number.
+    #[inline(always)]
+    pub fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    /// Gets the complex conjugate.
+    #[inline(always)]
+    pub fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    /// Multiplies both components by `scale`.
+    #[inline(always)]
+    pub fn scale(self, scale: f32) -> Self {
+        Complex::new(self.re * scale, self.im * scale)
+    }
+
+    /// Gets the squared magnitude.
+    #[inline(always)]
+    pub fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Gets the magnitude.
+    #[inline(always)]
+    pub fn norm(self) -> f32 {
+        self.norm_sqr().sqrt()
+    }
+
+    /// Multiplies by `i` if `inverse` is `true`, or by `-i` otherwise.
+    #[inline(always)]
+    fn rotate(self, inverse: bool) -> Self {
+        if inverse {
+            Complex::new(-self.im, self.re)
+        }
+        else {
+            Complex::new(self.im, -self.re)
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    #[inline(always)]
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    #[inline(always)]
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    #[inline(always)]
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+/// Gets `exp(-2 * pi * i * k / n)`.
+fn twiddle(k: usize, n: usize) -> Complex {
+    let theta = -2.0 * f64::consts::PI * k as f64 / n as f64;
+    Complex::new(theta.cos() as f32, theta.sin() as f32)
+}
+
+/// Conjugates a twiddle factor for the inverse transform.
+#[inline(always)]
+fn conj_if<const INVERSE: bool>(w: Complex) -> Complex {
+    if INVERSE { w.conj() } else { w }
+}
+
+/// Multiplies each sample by the corresponding twiddle factor, conjugated for the inverse
+/// transform.
+#[inline(always)]
+fn apply_twiddles<const INVERSE: bool>(out: &mut [Complex], twiddles: &[Complex]) {
+    if INVERSE {
+        simd::complex_mul_conj(out, twiddles)
+    }
+    else {
+        simd::complex_mul(out, twiddles)
+    }
+}
+
+/// The largest radix that may be computed using a stack allocated scratch buffer.
+const MAX_STACK_RADIX: usize = 8;
+
+/// A single radix-p stage of the FFT that combines p sub-transforms of length m.
+struct Stage {
+    p: usize,
+    m: usize,
+    /// The twiddle factors applied to the sub-transforms, ordered such that the m twiddle factors
+    /// for each of sub-transforms 1 through p - 1 are contiguous.
+    twiddles: Vec<Complex>,
+    /// The p-th roots of unity used by the generic butterfly.
+    roots: Vec<Complex>,
+}
+
+/// A N-point complex Fast Fourier Transform.
+///
+/// Both the forward and inverse transforms are unscaled. Therefore, the inverse transform of the
+/// forward transform of a signal is the signal multiplied by N.
+pub struct Fft {
+    n: usize,
+    stages: Vec<Stage>,
+}
+
+impl Fft {
+    /// Instantiate a N-point FFT.
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "fft size must be greater than 0");
+
+        // Factor N, preferring the radices with optimized butterflies.
+        let mut factors = Vec::new();
+        let mut rem = n;
+
+        for &p in &[4, 2, 3, 5] {
+            while rem.is_multiple_of(p) {
+                factors.push(p);
+                rem /= p;
+            }
+        }
+
+        // Any remaining factors use a generic butterfly.
+        let mut p = 7;
+
+        while rem > 1 {
+            if p * p > rem {
+                p = rem;
+            }
+
+            while rem.is_multiple_of(p) {
+                factors.push(p);
+                rem /= p;
+            }
+
+            p += 2;
+        }
+
+        // The radix-4 butterfly is the most efficient, so compute the radix-4 stages innermost
+        // where the most butterflies are computed.
+        factors.reverse();
+
+        // Each stage combines the sub-transforms computed by the next stage.
+        let mut len = n;
+
+        let stages = factors
+            .iter()
+            .map(|&p| {
+                let m = len / p;
+
+                let twiddles = (1..p)
+                    .flat_map(|q| (0..m).map(move |k| twiddle(q * k, len)))
+                    .collect();
+
+                let roots = (0..p).map(|r| twiddle(r, p)).collect();
+
+                len = m;
+
+                Stage { p, m, twiddles, roots }
+            })
+            .collect();
+
+        Fft { n, stages }
+    }
+
+    /// Gets the size of the FFT.
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    /// Computes the forward FFT of `input` into `output`. Both slices must have a length of N.
+    pub fn fft(&self, input: &[Complex], output: &mut [Complex]) {
+        assert_eq!(input.len(), self.n);
+        assert_eq!(output.len(), self.n);
+
+        self.fft_step::<false>(input, 1, output, 0);
+    }
+
+    /// Computes the inverse FFT of `input` into `output`. Both slices must have a length of N.
+    pub fn ifft(&self, input: &[Complex], output: &mut [Complex]) {
+        assert_eq!(input.len(), self.n);
+        assert_eq!(output.len(), self.n);
+
+        self.fft_step::<true>(input, 1, output, 0);
+    }
+
+    fn fft_step<const INVERSE: bool>(
+        &self,
+        input: &[Complex],
+        stride: usize,
+        out: &mut [Complex],
+        stage: usize,
+    ) {
+        // A 1-point FFT has no stages.
+        if self.stages.is_empty() {
+            out[0] = input[0];
+            return;
+        }
+
+        let Stage { p, m, ref twiddles, ref roots } = self.stages[stage];
+
+        // Decimate in time: compute the p sub-transforms of size m. If m is 1, then the
+        // sub-transforms are the input samples themselves, and no twiddle factors are required.
+        if m == 1 {
+            if p == 4 {
+                butterfly_4_leaf::<INVERSE>(input, stride, out);
+                return;
+            }
+
+            for (q, out) in out.iter_mut().enumerate() {
+                *out = input[q * stride];
+            }
+        }
+        else {
+            for (q, out) in out.chunks_exact_mut(m).enumerate() {
+                self.fft_step::<INVERSE>(&input[q * stride..], stride * p, out, stage + 1);
+            }
+        }
+
+        // Combine the sub-transforms with a radix-p butterfly.
+        match p {
+            2 => butterfly_2::<INVERSE>(out, m, twiddles),
+            4 => butterfly_4::<INVERSE>(out, m, twiddles),
+            _ if p <= MAX_STACK_RADIX => {
+                let mut scratch = [Complex::default(); MAX_STACK_RADIX];
+                butterfly_generic::<INVERSE>(out, m, twiddles, roots, &mut scratch[..p]);
+            }
+            _ => {
+                let mut scratch = vec![Complex::default(); p];
+                butterfly_generic::<INVERSE>(out, m, twiddles, roots, &mut scratch);
+            }
+        }
+    }
+}
+
+fn butterfly_2<const INVERSE: bool>(out: &mut [Complex], m: usize, twiddles: &[Complex]) {
+    let (out0, out1) = out.split_at_mut(m);
+
+    apply_twiddles::<INVERSE>(out1, twiddles);
+
+    for (x0, x1) in out0.iter_mut().zip(out1) {
+        let a = *x0;
+        let b = *x1;
+
+        *x0 = a + b;
+        *x1 = a - b;
+    }
+}
+
+fn butterfly_4<const INVERSE: bool>(out: &mut [Complex], m: usize, twiddles: &[Complex]) {
+    let (out0, out) = out.split_at_mut(m);
+    let (out1, out) = out.split_at_mut(m);
+    let (out2, out3) = out.split_at_mut(m);
+
+    let (w1, w) = twiddles.split_at(m);
+    let (w2, w3) = w.split_at(m);
+
+    apply_twiddles::<INVERSE>(out1, w1);
+    apply_twiddles::<INVERSE>(out2, w2);
+    apply_twiddles::<INVERSE>(out3, w3);
+
+    let outs = out0.iter_mut().zip(out1).zip(out2).zip(out3);
+
+    for (((x0, x1), x2), x3) in outs {
+        let a0 = *x0;
+        let a1 = *x1;
+        let a2 = *x2;
+        let a3 = *x3;
+
+        let t0 = a0 + a2;
+        let t1 = a0 - a2;
+        let t2 = a1 + a3;
+        let t3 = (a1 - a3).rotate(INVERSE);
+
+        *x0 = t0 + t2;
+        *x1 = t1 + t3;
+        *x2 = t0 - t2;
+        *x3 = t1 - t3;
+    }
+}
+
+fn butterfly_4_leaf<const INVERSE: bool>(input: &[Complex], stride: usize, out: &mut [Complex]) {
+    let a0 = input[0];
+    let a1 = input[stride];
+    let a2 = input[2 * stride];
+    let a3 = input[3 * stride];
+
+    let t0 = a0 + a2;
+    let t1 = a0 - a2;
+    let t2 = a1 + a3;
+    let t3 = (a1 - a3).rotate(INVERSE);
+
+    out[0] = t0 + t2;
+    out[1] = t1 + t3;
+    out[2] = t0 - t2;
+    out[3] = t1 - t3;
+}
+
+fn butterfly_generic<const INVERSE: bool>(
+    out: &mut [Complex],
+    m: usize,
+    twiddles: &[Complex],
+    roots: &[Complex],
+    scratch: &mut [Complex],
+) {
+    let p = scratch.len();
+
+    for k in 0..m {
+        scratch[0] = out[k];
+
+        for (q, s) in scratch.iter_mut().enumerate().skip(1) {
+            *s = out[q * m + k] * conj_if::<INVERSE>(twiddles[(q - 1) * m + k]);
+        }
+
+        for r in 0..p {
+            let mut acc = scratch[0];
+
+            for (q, &s) in scratch.iter().enumerate().skip(1) {
+                acc = acc + s * conj_if::<INVERSE>(roots[(q * r) % p]);
+            }
+
+            out[k + r * m] = acc;
+        }
+    }
+}
+
+/// A N-point Fast Fourier Transform of a real signal.
+///
+/// The transform of a real signal is conjugate symmetric, therefore only the first N/2 + 1 bins
+/// are computed. Both the forward and inverse transforms are unscaled.
+pub struct RealFft {
+    n: usize,
+    fft: Fft,
+    twiddles: Vec<Complex>,
+    fft_in: Vec<Complex>,
+    fft_out: Vec<Complex>,
+}
+
+impl RealFft {
+    /// Instantiate a N-point real FFT. N must be even.
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0 && n.is_multiple_of(2), "real fft size must be even and greater than 0");
+
+        let n2 = n / 2;
+
+        RealFft {
+            n,
+            fft: Fft::new(n2),
+            twiddles: (0..=n2).map(|k| twiddle(k, n)).collect(),
+            fft_in: vec![Default::default(); n2],
+            fft_out: vec![Default::default(); n2],
+        }
+    }
+
+    /// Gets the size of the FFT.
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    /// Computes the forward FFT of the N real samples in `input`, and writes the first N/2 + 1
+    /// bins into `output`.
+    pub fn fft(&mut self, input: &[f32], output: &mut [Complex]) {
+        let n2 = self.n / 2;
+
+        assert_eq!(input.len(), self.n);
+        assert_eq!(output.len(), n2 + 1);
+
+        // Pack the even and odd samples into the real and imaginary components of a complex signal
+        // of half the length.
+        for (z, x) in self.fft_in.iter_mut().zip(input.chunks_exact(2)) {
+            *z = Complex::new(x[0], x[1]);
+        }
+
+        self.fft.fft(&self.fft_in, &mut self.fft_out);
+
+        // Separate the transforms of the even and odd samples, and combine them.
+        for (k, out) in output.iter_mut().enumerate() {
+            let z0 = self.fft_out[k % n2];
+            let z1 = self.fft_out[(n2 - k) % n2].conj();
+
+            let even = (z0 + z1).scale(0.5);
+            let odd = (z0 - z1).scale(0.5).rotate(false);
+
+            *out = even + self.twiddles[k] * odd;
+        }
+    }
+
+    /// Computes the inverse FFT of the first N/2 + 1 bins of a conjugate symmetric spectrum in
+    /// `input`, and writes the N real samples into `output`.
+    pub fn ifft(&mut self, input: &[Complex], output: &mut [f32]) {
+        let n2 = self.n / 2;
+
+        assert_eq!(input.len(), n2 + 1);
+        assert_eq!(output.len(), self.n);
+
+        // Recover the transforms of the even and odd samples, and pack them into a complex
+        // spectrum of half the length. The spectrum is scaled by 2 such that the output is
+        // scaled by N.
+        for (k, z) in self.fft_in.iter_mut().enumerate() {
+            let x0 = input[k];
+            let x1 = input[n2 - k].conj();
+
+            let even = x0 + x1;
+            let odd = (x0 - x1) * self.twiddles[k].conj();
+
+            *z = even + odd.rotate(true);
+        }
+
+        self.fft.ifft(&self.fft_in, &mut self.fft_out);
+
+        for (x, z) in output.chunks_exact_mut(2).zip(&self.fft_out) {
+            x[0] = z.re;
+            x[1] = z.im;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dft(x: &[Complex], inverse: bool) -> Vec<Complex> {
+        let n = x.len();
+        let sign = if inverse { 1.0 } else { -1.0 };
+
+        (0..n)
+            .map(|k| {
+                let mut acc = (0.0f64, 0.0f64);
+
+                for (j, x) in x.iter().enumerate() {
+                    let theta = sign * 2.0 * f64::consts::PI * ((j * k) % n) as f64 / n as f64;
+                    acc.0 += f64::from(x.re) * theta.cos() - f64::from(x.im) * theta.sin();
+                    acc.1 += f64::from(x.re) * theta.sin() + f64::from(x.im) * theta.cos();
+                }
+
+                Complex::new(acc.0 as f32, acc.1 as f32)
+            })
+            .collect()
+    }
+
+    fn signal(n: usize) -> Vec<Complex> {
+        (0..n)
+            .map(|i| Complex::new(((i * 7) % 13) as f32 - 6.0, ((i * 3) % 5) as f32 - 2.0))
+            .collect()
+    }
+
+    fn assert_close(actual: &[Complex], expected: &[Complex]) {
+        let tolerance = 1e-4 * actual.len() as f32;
+
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((*a - *e).norm() < tolerance, "{:?} != {:?}", a, e);
+        }
+    }
+
+    #[test]
+    fn verify_fft() {
+        for &n in &[1, 2, 4, 8, 12, 16, 60, 64, 77, 128, 240, 256, 1024, 2048] {
+            let input = signal(n);
+            let fft = Fft::new(n);
+
+            let mut actual = vec![Complex::default(); n];
+
+            fft.fft(&input, &mut actual);
+            assert_close(&actual, &dft(&input, false));
+
+            fft.ifft(&input, &mut actual);
+            assert_close(&actual, &dft(&input, true));
+        }
+    }
+
+    #[test]
+    fn verify_real_fft() {
+        for &n in &[2, 4, 8, 30, 64, 1024] {
+            let input: Vec<f32> = signal(n).iter().map(|c| c.re).collect();
+            let complex: Vec<Complex> = input.iter().map(|&x| Complex::new(x, 0.0)).collect();
+
+            let mut fft = RealFft::new(n);
+
+            let mut spectrum = vec![Complex::default(); n / 2 + 1];
+            fft.fft(&input, &mut spectrum);
+            assert_close(&spectrum, &dft(&complex, false)[..n / 2 + 1]);
+
+            let mut output = vec![0.0; n];
+            fft.ifft(&spectrum, &mut output);
+
+            for (&a, &e) in output.iter().zip(&input) {
+                assert!((a - e * n as f32).abs() < 1e-3 * n as f32);
+            }
+        }
+    }
+}
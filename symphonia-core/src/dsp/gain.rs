@@ -0,0 +1,224 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `gain` module implements loudness normalization using ReplayGain information.
+//!
+//! ReplayGain information may be stored in a track's tags, or in the codec or container format's
+//! headers, such as the LAME tag of an MP3 file. The `Normalizer` combines both sources, selects
+//! the track or album gain, and applies it to decoded audio.
+//!
+//! Note that the output gain in the header of an Opus stream is always applied by the Opus
+//! decoder. The R128 gain tags of an Opus stream are relative to that output gain.
+
+use std::borrow::Cow;
+
+use crate::audio::{AudioBuffer, AudioBufferRef, Signal};
+use crate::codecs::CodecParameters;
+use crate::conv::IntoSample;
+use crate::meta::{ReplayGain, Tag};
+use crate::sample::Sample;
+
+/// The ReplayGain mode selects which gain is applied.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ReplayGainMode {
+    /// Normalize the loudness of each track. If the track gain is unknown, the album gain is used.
+    #[default]
+    Track,
+    /// Normalize the loudness of each album, preserving the relative loudness of the tracks in the
+    /// album. If the album gain is unknown, the track gain is used.
+    Album,
+}
+
+/// `NormalizerOptions` is a common set of options for a `Normalizer`.
+#[derive(Copy, Clone, Debug)]
+pub struct NormalizerOptions {
+    /// The ReplayGain mode.
+    pub mode: ReplayGainMode,
+    /// An additional gain, in decibels, applied to tracks with ReplayGain information.
+    pub preamp: f32,
+    /// The gain, in decibels, applied to tracks without ReplayGain information.
+    pub fallback_gain: f32,
+    /// If the peak sample value is known, limit the gain such that the peak does not clip.
+    pub prevent_clipping: bool,
+}
+
+impl Default for NormalizerOptions {
+    fn default() -> Self {
+        NormalizerOptions {
+            mode: ReplayGainMode::Track,
+            preamp: 0.0,
+            fallback_gain: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+/// A `Normalizer` applies ReplayGain to decoded audio.
+#[derive(Copy, Clone, Debug)]
+pub struct Normalizer {
+    gain: f32,
+}
+
+impl Normalizer {
+    /// Instantiate a new `Normalizer` that applies the ReplayGain information provided.
+    pub fn new(replay_gain: &ReplayGain, options: &NormalizerOptions) -> Self {
+        let track = (replay_gain.track_gain, replay_gain.track_peak);
+        let album = (replay_gain.album_gain, replay_gain.album_peak);
+
+        let ((gain, peak), (alt_gain, alt_peak)) = match options.mode {
+            ReplayGainMode::Track => (track, album),
+            ReplayGainMode::Album => (album, track),
+        };
+
+        let gain_db = match gain.or(alt_gain) {
+            Some(gain) => gain + options.preamp,
+            None => options.fallback_gain,
+        };
+
+        let mut gain = 10f32.powf(gain_db / 20.0);
+
+        if options.prevent_clipping {
+            if let Some(peak) = peak.or(alt_peak) {
+                gain = gain.min(1.0 / peak);
+            }
+        }
+
+        Normalizer { gain }
+    }
+
+    /// Instantiate a new `Normalizer` for a track using the ReplayGain information found in the
+    /// track's tags and codec parameters. If both provide the same information, the tags are
+    /// preferred.
+    pub fn for_track(params: &CodecParameters, tags: &[Tag], options: &NormalizerOptions) -> Self {
+        let replay_gain = ReplayGain::from_tags(tags).or(params.replay_gain.unwrap_or_default());
+
+        Normalizer::new(&replay_gain, options)
+    }
+
+    /// Gets the linear gain applied by the normalizer.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Applies the gain to the audio in `buf`. Samples are clamped to the range of the sample
+    /// format.
+    pub fn apply<S>(&self, buf: &mut AudioBuffer<S>)
+    where
+        S: Sample + IntoSample<f64>,
+        f64: IntoSample<S>,
+    {
+        if self.gain != 1.0 {
+            let gain = f64::from(self.gain);
+
+            buf.transform(|s| (gain * s.into_sample()).into_sample());
+        }
+    }
+
+    /// Applies the gain to the audio in `buf`. If the buffer is borrowed, and the gain is not 1,
+    /// then the buffer is copied first.
+    pub fn apply_ref(&self, buf: &mut AudioBufferRef<'_>) {
+        if self.gain == 1.0 {
+            return;
+        }
+
+        match buf {
+            AudioBufferRef::U8(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::U16(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::U24(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::U32(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::S8(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::S16(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::S24(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::S32(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::F32(buf) => self.apply(Cow::to_mut(buf)),
+            AudioBufferRef::F64(buf) => self.apply(Cow::to_mut(buf)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+    use crate::codecs::CodecParameters;
+    use crate::meta::{ReplayGain, StandardTagKey, Tag, Value};
+    use super::{Normalizer, NormalizerOptions, ReplayGainMode};
+
+    fn tag(std_key: StandardTagKey, value: &str) -> Tag {
+        Tag::new(Some(std_key), "", Value::from(value))
+    }
+
+    #[test]
+    fn verify_replay_gain_from_tags() {
+        let tags = [
+            tag(StandardTagKey::ReplayGainTrackGain, "-6.50 dB"),
+            tag(StandardTagKey::ReplayGainTrackPeak, "0.988"),
+            tag(StandardTagKey::ReplayGainAlbumGain, "+1.25dB"),
+            tag(StandardTagKey::ReplayGainAlbumPeak, "invalid"),
+        ];
+
+        let replay_gain = ReplayGain::from_tags(&tags);
+
+        assert_eq!(replay_gain.track_gain, Some(-6.5));
+        assert_eq!(replay_gain.track_peak, Some(0.988));
+        assert_eq!(replay_gain.album_gain, Some(1.25));
+        assert_eq!(replay_gain.album_peak, None);
+
+        // R128 gains are preferred, and are converted to the ReplayGain reference level.
+        let tags = [
+            tag(StandardTagKey::ReplayGainTrackGain, "-6.50 dB"),
+            tag(StandardTagKey::R128TrackGain, "-1536"),
+        ];
+
+        assert_eq!(ReplayGain::from_tags(&tags).track_gain, Some(-1.0));
+    }
+
+    #[test]
+    fn verify_normalizer() {
+        let replay_gain = ReplayGain {
+            track_gain: Some(-6.0),
+            track_peak: Some(0.5),
+            album_gain: Some(12.0),
+            album_peak: None,
+        };
+
+        let options = NormalizerOptions { preamp: -2.0, ..Default::default() };
+        let normalizer = Normalizer::new(&replay_gain, &options);
+        assert!((normalizer.gain() - 10f32.powf(-8.0 / 20.0)).abs() < 1e-6);
+
+        // The album gain is limited by the track peak.
+        let options = NormalizerOptions { mode: ReplayGainMode::Album, ..Default::default() };
+        let normalizer = Normalizer::new(&replay_gain, &options);
+        assert_eq!(normalizer.gain(), 2.0);
+
+        let options = NormalizerOptions { prevent_clipping: false, ..options };
+        let normalizer = Normalizer::new(&replay_gain, &options);
+        assert!((normalizer.gain() - 10f32.powf(12.0 / 20.0)).abs() < 1e-5);
+
+        // The tags are preferred over the codec parameters, and the fallback gain is only used if
+        // no gain is known.
+        let mut params = CodecParameters::new();
+        params.with_replay_gain(replay_gain);
+
+        let tags = [tag(StandardTagKey::ReplayGainTrackGain, "-20 dB")];
+        let normalizer = Normalizer::for_track(&params, &tags, &Default::default());
+        assert!((normalizer.gain() - 0.1).abs() < 1e-6);
+
+        let options = NormalizerOptions { fallback_gain: -20.0, ..Default::default() };
+        let normalizer = Normalizer::for_track(&CodecParameters::new(), &[], &options);
+        assert!((normalizer.gain() - 0.1).abs() < 1e-6);
+
+        // Applying the gain clamps the samples.
+        let mut buf = AudioBuffer::<i16>::new(3, SignalSpec::new(44_100, Channels::FRONT_LEFT));
+        buf.render_reserved(None);
+        buf.chan_mut(0).copy_from_slice(&[1000, -20000, 20000]);
+
+        let options = NormalizerOptions { mode: ReplayGainMode::Album, ..Default::default() };
+        let normalizer = Normalizer::new(&replay_gain, &options);
+        normalizer.apply(&mut buf);
+        assert_eq!(buf.chan(0), &[2000, -32768, 32767]);
+    }
+}
@@ -0,0 +1,552 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `loudness` module implements loudness measurement as described in ITU-R BS.1770 \[1\] and
+//! EBU R128 \[2\].
+//!
+//! The loudness of each channel is measured after applying the K-weighting filter, and the
+//! channels are summed with a weight that depends on their position. Momentary (400 ms) and
+//! short-term (3 s) loudness are measured over a sliding window that advances every 100 ms.
+//! Integrated loudness is the gated mean loudness of the momentary blocks, and the loudness range
+//! is the spread of the gated short-term loudness distribution as described in EBU Tech 3342 \[3\].
+//!
+//! \[1\] ITU-R, "Algorithms to measure audio programme loudness and true-peak audio level,"
+//!       Recommendation ITU-R BS.1770-4, 2015.
+//!
+//! \[2\] EBU, "Loudness normalisation and permitted maximum level of audio signals," EBU R 128,
+//!       2020.
+//!
+//! \[3\] EBU, "Loudness Range: A measure to supplement EBU R 128 loudness normalization," EBU Tech
+//!       3342, 2016.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use crate::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+use crate::conv::IntoSample;
+use crate::meta::ReplayGain;
+use crate::sample::Sample;
+
+use super::resampler::{Resampler, ResamplerQuality};
+
+/// The number of 100 ms sub-blocks in a momentary block.
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+
+/// The number of 100 ms sub-blocks in a short-term block.
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+
+/// The absolute gating threshold in LUFS.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// The relative gating threshold, in LU, for integrated loudness.
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+
+/// The relative gating threshold, in LU, for loudness range.
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+
+/// The reference loudness of ReplayGain 2.0 in LUFS.
+const REPLAYGAIN_REFERENCE: f64 = -18.0;
+
+/// Converts a mean-square energy into loudness in LUFS.
+fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Converts a loudness in LUFS into a mean-square energy.
+fn loudness_to_energy(loudness: f64) -> f64 {
+    10f64.powf((loudness + 0.691) / 10.0)
+}
+
+/// Gets the weight of a channel when summing the loudness of all channels.
+fn channel_weight(channel: Channels) -> f64 {
+    match channel {
+        // Low-frequency effects channels are not included in the measurement.
+        Channels::LFE1 | Channels::LFE2 => 0.0,
+        // Surround channels are weighted by +1.5 dB.
+        Channels::SIDE_LEFT
+        | Channels::SIDE_RIGHT
+        | Channels::REAR_LEFT
+        | Channels::REAR_CENTRE
+        | Channels::REAR_RIGHT
+        | Channels::REAR_LEFT_CENTRE
+        | Channels::REAR_RIGHT_CENTRE
+        | Channels::TOP_REAR_LEFT
+        | Channels::TOP_REAR_CENTRE
+        | Channels::TOP_REAR_RIGHT => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// A second-order IIR filter.
+#[derive(Copy, Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// Instantiate the K-weighting pre-filter, a high-shelf filter modelling the acoustic effect
+    /// of the head.
+    fn pre_filter(rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// Instantiate the K-weighting RLB filter, a high-pass filter.
+    fn rlb_filter(rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (PI * f0 / rate).tan();
+
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, x: f64) -> f64 {
+        // Transposed direct form II.
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+
+    fn reset(&mut self) {
+        self.z = [0.0; 2];
+    }
+}
+
+/// The state of a measured channel.
+struct ChannelState {
+    weight: f64,
+    pre_filter: Biquad,
+    rlb_filter: Biquad,
+    sample_peak: f64,
+    true_peak: f64,
+}
+
+/// A `LoudnessMeter` measures the loudness and peak level of audio.
+///
+/// All loudness values are in LUFS, except the loudness range which is in LU. Peak levels are
+/// linear, where 1.0 is full scale. Audio must be provided to the meter in order, and the
+/// measurements are updated after each call to `process`.
+pub struct LoudnessMeter {
+    spec: SignalSpec,
+    channels: Vec<ChannelState>,
+    /// The length of a 100 ms sub-block in frames.
+    sub_block_len: usize,
+    /// The number of frames measured in the current sub-block.
+    sub_block_pos: usize,
+    /// The weighted sum of the squared samples in the current sub-block.
+    sub_block_energy: f64,
+    /// The weighted sums of the squared samples of the latest sub-blocks, up-to the length of a
+    /// short-term block.
+    sub_blocks: VecDeque<f64>,
+    /// The mean-square energy of every momentary block.
+    momentary_blocks: Vec<f64>,
+    /// The mean-square energy of every short-term block.
+    short_term_blocks: Vec<f64>,
+    /// The resampler used to oversample the audio to measure the true peak, if required.
+    oversampler: Option<Resampler>,
+}
+
+impl LoudnessMeter {
+    /// Instantiate a new `LoudnessMeter` for audio with the signal specification `spec`.
+    pub fn new(spec: SignalSpec) -> Self {
+        assert!(spec.rate > 0, "sample rate must be greater than 0");
+
+        let rate = f64::from(spec.rate);
+
+        let channels = (0..32)
+            .map(|i| Channels::from_bits_truncate(1 << i))
+            .filter(|&channel| !channel.is_empty() && spec.channels.contains(channel))
+            .map(|channel| ChannelState {
+                weight: channel_weight(channel),
+                pre_filter: Biquad::pre_filter(rate),
+                rlb_filter: Biquad::rlb_filter(rate),
+                sample_peak: 0.0,
+                true_peak: 0.0,
+            })
+            .collect();
+
+        // The true peak is measured by oversampling by a factor of 4 at sample rates below 96 kHz,
+        // and by a factor of 2 at sample rates below 192 kHz.
+        let factor = match spec.rate {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+
+        let oversampler = if factor > 1 {
+            Some(Resampler::new(spec, factor * spec.rate, ResamplerQuality::Medium))
+        }
+        else {
+            None
+        };
+
+        LoudnessMeter {
+            spec,
+            channels,
+            sub_block_len: ((spec.rate + 5) / 10).max(1) as usize,
+            sub_block_pos: 0,
+            sub_block_energy: 0.0,
+            sub_blocks: VecDeque::with_capacity(SHORT_TERM_SUB_BLOCKS),
+            momentary_blocks: Vec::new(),
+            short_term_blocks: Vec::new(),
+            oversampler,
+        }
+    }
+
+    /// Gets the signal specification of the measured audio.
+    pub fn spec(&self) -> &SignalSpec {
+        &self.spec
+    }
+
+    /// Measures the audio in `buf`. The signal specification of `buf` must match the signal
+    /// specification of the meter.
+    pub fn process<S>(&mut self, buf: &AudioBuffer<S>)
+    where
+        S: Sample + IntoSample<f32>,
+    {
+        assert!(buf.spec() == &self.spec, "signal specification does not match");
+
+        let n_frames = buf.frames();
+
+        let mut start = 0;
+
+        while start < n_frames {
+            let len = (self.sub_block_len - self.sub_block_pos).min(n_frames - start);
+
+            for (ch, state) in self.channels.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                let mut peak = state.sample_peak;
+
+                for &s in &buf.chan(ch)[start..start + len] {
+                    let x = f64::from(s.into_sample());
+
+                    let y = state.rlb_filter.process(state.pre_filter.process(x));
+
+                    sum += y * y;
+                    peak = peak.max(x.abs());
+                }
+
+                state.sample_peak = peak;
+                self.sub_block_energy += state.weight * sum;
+            }
+
+            self.sub_block_pos += len;
+            start += len;
+
+            if self.sub_block_pos == self.sub_block_len {
+                self.end_sub_block();
+            }
+        }
+
+        if let Some(oversampler) = &mut self.oversampler {
+            let oversampled = oversampler.resample(buf);
+            update_true_peaks(&mut self.channels, oversampled);
+        }
+    }
+
+    /// Measures the true peak of the last few frames provided to the meter. Due to the delay of
+    /// the oversampling filter, this must be called after all audio has been processed to get the
+    /// exact true peak.
+    pub fn flush(&mut self) {
+        if let Some(oversampler) = &mut self.oversampler {
+            let oversampled = oversampler.flush();
+            update_true_peaks(&mut self.channels, oversampled);
+        }
+    }
+
+    /// Resets the meter, discarding all measurements.
+    pub fn reset(&mut self) {
+        for state in self.channels.iter_mut() {
+            state.pre_filter.reset();
+            state.rlb_filter.reset();
+            state.sample_peak = 0.0;
+            state.true_peak = 0.0;
+        }
+
+        self.sub_block_pos = 0;
+        self.sub_block_energy = 0.0;
+        self.sub_blocks.clear();
+        self.momentary_blocks.clear();
+        self.short_term_blocks.clear();
+
+        if let Some(oversampler) = &mut self.oversampler {
+            oversampler.reset();
+        }
+    }
+
+    fn end_sub_block(&mut self) {
+        if self.sub_blocks.len() == SHORT_TERM_SUB_BLOCKS {
+            self.sub_blocks.pop_front();
+        }
+
+        self.sub_blocks.push_back(self.sub_block_energy);
+
+        self.sub_block_pos = 0;
+        self.sub_block_energy = 0.0;
+
+        if let Some(energy) = self.latest_block_energy(MOMENTARY_SUB_BLOCKS) {
+            self.momentary_blocks.push(energy);
+        }
+
+        if let Some(energy) = self.latest_block_energy(SHORT_TERM_SUB_BLOCKS) {
+            self.short_term_blocks.push(energy);
+        }
+    }
+
+    /// Gets the mean-square energy of the latest `n` sub-blocks.
+    fn latest_block_energy(&self, n: usize) -> Option<f64> {
+        if self.sub_blocks.len() < n {
+            return None;
+        }
+
+        let sum: f64 = self.sub_blocks.iter().rev().take(n).sum();
+
+        Some(sum / (n * self.sub_block_len) as f64)
+    }
+
+    /// Gets the momentary loudness of the latest 400 ms of audio.
+    pub fn momentary_loudness(&self) -> Option<f64> {
+        self.latest_block_energy(MOMENTARY_SUB_BLOCKS).map(energy_to_loudness)
+    }
+
+    /// Gets the short-term loudness of the latest 3 s of audio.
+    pub fn short_term_loudness(&self) -> Option<f64> {
+        self.latest_block_energy(SHORT_TERM_SUB_BLOCKS).map(energy_to_loudness)
+    }
+
+    /// Gets the integrated loudness of all audio measured. If no audio is louder than the absolute
+    /// gating threshold of -70 LUFS, `None` is returned.
+    pub fn integrated_loudness(&self) -> Option<f64> {
+        integrated_loudness(&[&self.momentary_blocks])
+    }
+
+    /// Gets the integrated loudness of the audio measured by all `meters` as if it were measured
+    /// by a single meter. This may be used to measure the loudness of an album.
+    pub fn combined_integrated_loudness(meters: &[&LoudnessMeter]) -> Option<f64> {
+        let blocks: Vec<&[f64]> = meters.iter().map(|meter| &meter.momentary_blocks[..]).collect();
+
+        integrated_loudness(&blocks)
+    }
+
+    /// Gets the loudness range of all audio measured. If there is not enough audio louder than the
+    /// absolute gating threshold of -70 LUFS, `None` is returned.
+    pub fn loudness_range(&self) -> Option<f64> {
+        let blocks = &self.short_term_blocks;
+
+        let mut loudness = gate(&[blocks], RANGE_RELATIVE_GATE)
+            .map(|threshold| {
+                blocks
+                    .iter()
+                    .filter(|&&energy| energy > threshold)
+                    .map(|&energy| energy_to_loudness(energy))
+                    .collect::<Vec<f64>>()
+            })
+            .unwrap_or_default();
+
+        if loudness.is_empty() {
+            return None;
+        }
+
+        loudness.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let percentile = |p: f64| loudness[((loudness.len() - 1) as f64 * p).round() as usize];
+
+        Some(percentile(0.95) - percentile(0.10))
+    }
+
+    /// Gets the largest absolute sample value of any channel.
+    pub fn sample_peak(&self) -> f64 {
+        self.channels.iter().map(|state| state.sample_peak).fold(0.0, f64::max)
+    }
+
+    /// Gets the largest absolute value of any channel after oversampling. The true peak is always
+    /// greater than, or equal to, the sample peak.
+    pub fn true_peak(&self) -> f64 {
+        let true_peak = self.channels.iter().map(|state| state.true_peak).fold(0.0, f64::max);
+
+        true_peak.max(self.sample_peak())
+    }
+
+    /// Gets the ReplayGain 2.0 track gain and peak of all audio measured. The gain normalizes the
+    /// integrated loudness to -18 LUFS, and the peak is the true peak.
+    pub fn replay_gain(&self) -> ReplayGain {
+        ReplayGain {
+            track_gain: self.integrated_loudness().map(|l| (REPLAYGAIN_REFERENCE - l) as f32),
+            track_peak: Some(self.true_peak() as f32),
+            ..Default::default()
+        }
+    }
+}
+
+/// Updates the true peak of each channel from the oversampled audio.
+fn update_true_peaks(channels: &mut [ChannelState], oversampled: &AudioBuffer<f32>) {
+    for (ch, state) in channels.iter_mut().enumerate() {
+        let peak = oversampled.chan(ch).iter().fold(0.0f32, |peak, &s| peak.max(s.abs()));
+
+        state.true_peak = state.true_peak.max(f64::from(peak));
+    }
+}
+
+/// Gets the energy threshold of the relative gate, `relative_gate` LU below the mean loudness of
+/// the blocks louder than the absolute gate. The threshold is never less than the absolute gate.
+/// If no blocks are louder than the absolute gate, `None` is returned.
+fn gate(blocks: &[&[f64]], relative_gate: f64) -> Option<f64> {
+    let absolute = loudness_to_energy(ABSOLUTE_GATE);
+
+    let (sum, count) = blocks
+        .iter()
+        .flat_map(|blocks| blocks.iter())
+        .filter(|&&energy| energy > absolute)
+        .fold((0.0, 0usize), |(sum, count), &energy| (sum + energy, count + 1));
+
+    if count == 0 {
+        return None;
+    }
+
+    let relative = loudness_to_energy(energy_to_loudness(sum / count as f64) + relative_gate);
+
+    Some(relative.max(absolute))
+}
+
+/// Gets the integrated loudness of the momentary blocks.
+fn integrated_loudness(blocks: &[&[f64]]) -> Option<f64> {
+    let threshold = gate(blocks, INTEGRATED_RELATIVE_GATE)?;
+
+    let (sum, count) = blocks
+        .iter()
+        .flat_map(|blocks| blocks.iter())
+        .filter(|&&energy| energy > threshold)
+        .fold((0.0, 0usize), |(sum, count), &energy| (sum + energy, count + 1));
+
+    if count == 0 {
+        return None;
+    }
+
+    Some(energy_to_loudness(sum / count as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+    use super::LoudnessMeter;
+
+    /// Generates a sine wave in every channel with the given peak level in dBFS.
+    fn sine(spec: SignalSpec, secs: f64, freq: f64, level: f64, phase: f64) -> AudioBuffer<f32> {
+        let n_frames = (secs * f64::from(spec.rate)) as usize;
+
+        let mut buf = AudioBuffer::new(n_frames as u64, spec);
+        buf.render_reserved(None);
+
+        let amplitude = 10f64.powf(level / 20.0);
+
+        for ch in 0..spec.channels.count() {
+            for (i, s) in buf.chan_mut(ch).iter_mut().enumerate() {
+                let t = i as f64 / f64::from(spec.rate);
+                *s = (amplitude * (2.0 * PI * freq * t + phase).sin()) as f32;
+            }
+        }
+
+        buf
+    }
+
+    #[test]
+    fn verify_loudness() {
+        let spec = SignalSpec::new(48_000, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+
+        // EBU Tech 3341, test case 1: a stereo 1 kHz sine wave at -23 dBFS measures -23 LUFS.
+        let mut meter = LoudnessMeter::new(spec);
+
+        let buf = sine(spec, 5.0, 1000.0, -23.0, 0.0);
+
+        for chunk in 0..5 {
+            let mut part = AudioBuffer::<f32>::new(48_000, spec);
+            part.render_reserved(None);
+
+            for ch in 0..2 {
+                let start = chunk * 48_000;
+                part.chan_mut(ch).copy_from_slice(&buf.chan(ch)[start..start + 48_000]);
+            }
+
+            meter.process(&part);
+        }
+
+        assert!((meter.integrated_loudness().unwrap() + 23.0).abs() < 0.1);
+        assert!((meter.momentary_loudness().unwrap() + 23.0).abs() < 0.1);
+        assert!((meter.short_term_loudness().unwrap() + 23.0).abs() < 0.1);
+        assert!(meter.loudness_range().unwrap() < 0.1);
+        assert!((meter.replay_gain().track_gain.unwrap() - 5.0).abs() < 0.1);
+
+        // Based on EBU Tech 3342, test case 1: -20 dBFS followed by -30 dBFS has a loudness range
+        // of 10 LU.
+        let spec = SignalSpec::new(48_000, Channels::FRONT_LEFT);
+
+        let mut meter = LoudnessMeter::new(spec);
+
+        meter.process(&sine(spec, 10.0, 1000.0, -20.0, 0.0));
+        meter.process(&sine(spec, 10.0, 1000.0, -30.0, 0.0));
+
+        assert!((meter.loudness_range().unwrap() - 10.0).abs() < 0.1);
+
+        // Silence is below the absolute gate.
+        let mut meter = LoudnessMeter::new(spec);
+
+        meter.process(&sine(spec, 1.0, 1000.0, -100.0, 0.0));
+
+        assert!(meter.integrated_loudness().is_none());
+        assert!(meter.loudness_range().is_none());
+    }
+
+    #[test]
+    fn verify_true_peak() {
+        let spec = SignalSpec::new(48_000, Channels::FRONT_LEFT);
+
+        // A sine wave at a quarter of the sample rate, with a phase of 45 degrees, is sampled at
+        // 0.707 of its peak.
+        let mut meter = LoudnessMeter::new(spec);
+
+        meter.process(&sine(spec, 1.0, 12_000.0, -6.0, PI / 4.0));
+        meter.flush();
+
+        let peak = 10f64.powf(-6.0 / 20.0);
+
+        assert!((meter.sample_peak() - peak * 0.5f64.sqrt()).abs() < 1e-3);
+        // The true peak tolerance of EBU Tech 3341 is +0.2 dB to -0.4 dB.
+        let error = 20.0 * (meter.true_peak() / peak).log10();
+
+        assert!((-0.4..=0.2).contains(&error));
+    }
+}
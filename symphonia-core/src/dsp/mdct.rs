@@ -12,47 +12,60 @@
 
 use std::f64;
 
-use super::dct::Dct;
+use super::fft::{Complex, Fft};
+use super::simd;
 
 /// Inverse Modified Discrete Transform (IMDCT).
 ///
-/// Implements the IMDCT in-terms of a DCT-IV as described in \[1\] and \[2\].
+/// Implements the IMDCT in-terms of a DCT-IV, which is in-turn computed using a N/2-point complex
+/// FFT with pre- and post-twiddling as described in \[1\].
 ///
-/// \[1\] Mu-Huo Cheng and Yu-Hsin Hsu, "Fast IMDCT and MDCT algorithms - a matrix approach,"
-///       in IEEE Transactions on Signal Processing, vol. 51, no. 1, pp. 221-229, Jan. 2003,
-///       doi: 10.1109/TSP.2002.806566.
-///
-/// \[2\] Tan Li, R. Zhang, R. Yang, Heyun Huang and Fuhuei Lin, "A unified computing kernel for
-///       MDCT/IMDCT in modern audio coding standards," 2007 International Symposium on
-///       Communications and Information Technologies, Sydney, NSW, 2007, pp. 546-550,
-///       doi: 10.1109/ISCIT.2007.4392079.
+/// \[1\] P. Duhamel, Y. Mahieux and J. P. Petit, "A fast algorithm for the implementation of
+///       filter banks based on 'time domain aliasing cancellation'," ICASSP 91, Toronto, ON,
+///       1991, pp. 2209-2212, vol.3, doi: 10.1109/ICASSP.1991.150853.
 pub struct Imdct {
-    dct: Dct,
+    fft: Fft,
     n: u32,
-    table: Vec<f32>,
+    pre_twiddle: Vec<Complex>,
+    post_twiddle: Vec<Complex>,
+    fft_in: Vec<Complex>,
+    fft_out: Vec<Complex>,
 }
 
 impl Imdct {
 
     /// Instantiate a N-point IMDCT.
     ///
-    /// The value of `n` must be a power-of-2, and less-than or equal to 8192.
+    /// The value of `n` must be a power-of-2, greater-than 1, and less-than or equal to 8192.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is not a power-of-2, is 1, or is greater-than 8192. A 1-point transform
+    /// has no N/2-point FFT to compute it with.
     pub fn new(n: u32) -> Imdct {
         // The algorithm implemented requires a power-of-two N.
         assert!(n.is_power_of_two(), "n must be a power of two");
         // This limitation is somewhat arbitrary, but a limit must be set somewhere.
         assert!(n <= 8192, "maximum of 8192-point imdct");
+        assert!(n >= 2, "minimum of 2-point imdct");
 
-        let c = f64::consts::PI / f64::from(2 * 2 * n);
+        let n2 = n as usize / 2;
 
-        let table: Vec<f32> =
-            (0..n).map(|i| (2.0 * (c * f64::from(2 * i + 1)).cos()) as f32)
-                    .collect();
+        let twiddle = |k: f64| {
+            let theta = -f64::consts::PI * k / f64::from(n);
+            Complex::new(theta.cos() as f32, theta.sin() as f32)
+        };
+
+        let pre_twiddle = (0..n2).map(|k| twiddle(k as f64)).collect();
+        let post_twiddle = (0..n2).map(|k| twiddle(k as f64 + 0.25)).collect();
 
         Imdct {
-            dct: Dct::new(n),
+            fft: Fft::new(n2),
             n,
-            table,
+            pre_twiddle,
+            post_twiddle,
+            fft_in: vec![Default::default(); n2],
+            fft_out: vec![Default::default(); n2],
         }
     }
 
@@ -75,59 +88,140 @@ impl Imdct {
         assert_eq!(dst.len(), n);
         assert_eq!(src.len(), n2);
 
-        // Pre-process the input and place it in the second-half of dst.
-        for ((ds, &src), &cos) in dst[n2..].iter_mut().zip(src).zip(&self.table) {
-            *ds = src * cos;
+        // Pre-twiddle: pack the even samples, and the odd samples in reverse, into a complex
+        // signal of length N/2 and rotate.
+        let pairs = src.chunks_exact(2).zip(src.rchunks_exact(2));
+
+        for (z, (even, odd)) in self.fft_in.iter_mut().zip(pairs) {
+            *z = Complex::new(even[0], odd[1]);
         }
 
-        // Compute the DCT-II in-place using the pre-processed samples that reside in the second-
-        // half of dst.
-        self.dct.dct_ii_inplace(&mut dst[n2..]);
+        simd::complex_mul(&mut self.fft_in, &self.pre_twiddle);
+
+        self.fft.fft(&self.fft_in, &mut self.fft_out);
+
+        simd::complex_mul(&mut self.fft_out, &self.post_twiddle);
+
+        // Post-twiddle to obtain the DCT-IV, u, and place it in the middle-half of dst. Using
+        // symmetry, the middle-half of the IMDCT is -u reversed. The even DCT-IV samples, u[2k],
+        // are the real part of the rotated FFT output, and the odd samples, u[N-1-2k], are the
+        // negated imaginary part.
+        let mid = &mut dst[n4..n4 + n2];
+
+        for (k, &y) in self.fft_out.iter().enumerate() {
+            mid[2 * k] = scale * y.im;
+            mid[n2 - 1 - 2 * k] = -scale * y.re;
+        }
 
-        // DCT-II to DCT-IV
-        //
-        // Split dst into 4 evenly sized N/4 regions: [ a, b, c, d ]. Regions c & d contain the
-        // DCT-II transformed samples from the previous step. After this step, regions b & c will
-        // contain the DCT-II transformed samples.
+        // Expand the DCT-IV to the full IMDCT. The first-quarter of dst is the first-half of the
+        // middle-half reversed and inverted, and the last-quarter of dst is the second-half of the
+        // middle-half reversed.
         let (a, b) = dst.split_at_mut(n4);
         let (b, c) = b.split_at_mut(n4);
         let (c, d) = c.split_at_mut(n4);
 
-        // Map c to b.
-        b[0] = -0.5 * c[0];
+        for (sa, &sb) in a.iter_mut().zip(b.iter().rev()) {
+            *sa = -sb;
+        }
 
-        for i in 1..n4 {
-            b[i] = -1.0 * (c[i] + b[i - 1]);
+        for (sd, &sc) in d.iter_mut().zip(c.iter().rev()) {
+            *sd = sc;
         }
+    }
+}
 
-        // Map d to c.
-        c[0] = d[0] + b[n4 - 1];
+/// Modified Discrete Cosine Transform (MDCT).
+///
+/// Implements the MDCT in-terms of a DCT-IV, which is in-turn computed using a N/2-point complex
+/// FFT with pre- and post-twiddling in the same manner as the IMDCT.
+pub struct Mdct {
+    fft: Fft,
+    n: u32,
+    pre_twiddle: Vec<Complex>,
+    post_twiddle: Vec<Complex>,
+    fft_in: Vec<Complex>,
+    fft_out: Vec<Complex>,
+}
 
-        for i in 1..n4 {
-            c[i] = d[i] - c[i - 1];
-        }
+impl Mdct {
 
-        // DCT-IV to IMDCT
-        //
-        // Using symmetry, expand the DCT-IV to IMDCT. Multiply by the scale factor as this
-        // is done.
-        for (sa, &sc) in a.iter_mut().zip(c.iter()) {
-            // Region a is a scaled copy of region c.
-            *sa = scale * sc;
+    /// Instantiate a N-point MDCT.
+    ///
+    /// The value of `n` must be a power-of-2, greater-than 1, and less-than or equal to 8192.
+    pub fn new(n: u32) -> Mdct {
+        // The algorithm implemented requires a power-of-two N.
+        assert!(n.is_power_of_two(), "n must be a power of two");
+        // This limitation is somewhat arbitrary, but a limit must be set somewhere.
+        assert!(n <= 8192, "maximum of 8192-point mdct");
+        assert!(n >= 2, "minimum of 2-point mdct");
+
+        let n2 = n as usize / 2;
+
+        let twiddle = |k: f64| {
+            let theta = -f64::consts::PI * k / f64::from(n);
+            Complex::new(theta.cos() as f32, theta.sin() as f32)
+        };
+
+        let pre_twiddle = (0..n2).map(|k| twiddle(k as f64)).collect();
+        let post_twiddle = (0..n2).map(|k| twiddle(k as f64 + 0.25)).collect();
+
+        Mdct {
+            fft: Fft::new(n2),
+            n,
+            pre_twiddle,
+            post_twiddle,
+            fft_in: vec![Default::default(); n2],
+            fft_out: vec![Default::default(); n2],
         }
+    }
+
+    /// Performs the N-point Modified Discrete Cosine Transform.
+    ///
+    /// The number of output samples in `dst`, N, must equal the value `Mdct` was instantiated
+    /// with. The length of the input slice, `src`, must equal 2N. Failing to meet these
+    /// requirements will throw an assertion.
+    ///
+    /// This function performs no windowing, but each sample will be multiplied by `scale`.
+    pub fn mdct(&mut self, src: &[f32], dst: &mut [f32], scale: f32) {
+        let n = self.n as usize;
+        let n2 = n >> 1;
+
+        assert_eq!(src.len(), 2 * n);
+        assert_eq!(dst.len(), n);
+
+        // The MDCT is the DCT-IV of the input folded into N samples. If the input is split into
+        // quarters (a, b, c, d), then the folded input is (-c_r - d, a - b_r), where the suffix _r
+        // denotes reversal.
+        let (ab, cd) = src.split_at(n);
+        let (a, b) = ab.split_at(n2);
+        let (c, d) = cd.split_at(n2);
+
+        let fold = |i: usize| {
+            if i < n2 {
+                -c[n2 - 1 - i] - d[i]
+            }
+            else {
+                a[i - n2] - b[n - 1 - i]
+            }
+        };
 
-        for ((sd, sc), &sb) in d.iter_mut().zip(c.iter_mut().rev()).zip(b.iter()) {
-            // Region d is a scaled copy of region b.
-            // Region c is a reversed and scaled copy of region b.
-            let s = scale * sb;
-            *sd = s;
-            *sc = s;
+        // Pre-twiddle: pack the even samples, and the odd samples in reverse, of the folded input
+        // into a complex signal of length N/2 and rotate.
+        for (k, z) in self.fft_in.iter_mut().enumerate() {
+            *z = Complex::new(fold(2 * k), fold(n - 1 - 2 * k));
         }
 
-        for (sb, &sa) in b.iter_mut().zip(a.iter().rev()) {
-            // Region b is an inverted copy of region c. Region c was overwrittern above,
-            // but region a is a copy of the original region c.
-            *sb = -1.0 * sa;
+        simd::complex_mul(&mut self.fft_in, &self.pre_twiddle);
+
+        self.fft.fft(&self.fft_in, &mut self.fft_out);
+
+        simd::complex_mul(&mut self.fft_out, &self.post_twiddle);
+
+        // Post-twiddle to obtain the DCT-IV. The even samples are the real part of the rotated FFT
+        // output, and the odd samples, in reverse, are the negated imaginary part.
+        for (k, &y) in self.fft_out.iter().enumerate() {
+            dst[2 * k] = scale * y.re;
+            dst[n - 1 - 2 * k] = -scale * y.im;
         }
     }
 }
@@ -157,6 +251,26 @@ mod tests {
         }
     }
 
+    fn mdct_analytical(x: &[f32], y: &mut [f32], scale: f64) {
+        assert!(x.len() == 2 * y.len());
+
+        // Generates N outputs from 2N inputs.
+        let n_in = x.len();
+        let n_out = y.len();
+
+        let pi_2n = f64::consts::PI / (2 * n_in) as f64;
+
+        for (i, y) in y.iter_mut().enumerate() {
+            let mut accum = 0.0;
+
+            for (j, &x) in x.iter().enumerate() {
+                accum += f64::from(x) * (pi_2n * ((2*j + 1 + n_out) * (2*i + 1)) as f64).cos();
+            }
+
+            *y = (scale * accum) as f32;
+        }
+    }
+
     #[test]
     fn verify_imdct() {
         const TEST_VECTOR: [f32; 32] = [
@@ -180,4 +294,46 @@ mod tests {
             assert!((actual[i] - expected[i]).abs() < 0.00001);
         }
     }
+
+    #[test]
+    fn verify_imdct_sizes() {
+        for &n in &[2, 4, 16, 64, 128, 256, 1024, 2048] {
+            let input: Vec<f32> = (0..n).map(|i| ((i * 7) % 13) as f32 / 6.0 - 1.0).collect();
+
+            let mut actual = vec![0f32; 2 * n];
+            let mut expected = vec![0f32; 2 * n];
+
+            let scale = (1.0 / n as f64).sqrt();
+
+            imdct_analytical(&input, &mut expected, scale);
+
+            let mut mdct = Imdct::new(n as u32);
+            mdct.imdct(&input, &mut actual, scale as f32);
+
+            for (&a, &e) in actual.iter().zip(&expected) {
+                assert!((a - e).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_mdct_sizes() {
+        for &n in &[2, 4, 16, 64, 128, 256, 1024, 2048] {
+            let input: Vec<f32> = (0..2 * n).map(|i| ((i * 7) % 13) as f32 / 6.0 - 1.0).collect();
+
+            let mut actual = vec![0f32; n];
+            let mut expected = vec![0f32; n];
+
+            let scale = (1.0 / n as f64).sqrt();
+
+            mdct_analytical(&input, &mut expected, scale);
+
+            let mut mdct = Mdct::new(n as u32);
+            mdct.mdct(&input, &mut actual, scale as f32);
+
+            for (&a, &e) in actual.iter().zip(&expected) {
+                assert!((a - e).abs() < 0.0001);
+            }
+        }
+    }
 }
@@ -8,4 +8,12 @@
 //! The `dsp` module provides efficient implementations of common signal processing algorithms.
 
 pub mod dct;
+pub mod fft;
+pub mod gain;
+pub mod loudness;
 pub mod mdct;
+pub mod remix;
+pub mod resampler;
+pub mod simd;
+pub mod stft;
+pub mod window;
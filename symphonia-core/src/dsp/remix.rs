@@ -0,0 +1,399 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `remix` module implements channel remixing.
+//!
+//! A remix converts audio from one set of channels to another by a matrix of gains, where each
+//! output channel is a weighted sum of the input channels. Standard downmix matrices are derived
+//! using the coefficients of ITU-R BS.775 \[1\]: centre and surround channels are mixed into the
+//! front channels at -3 dB, and low-frequency channels are discarded.
+//!
+//! \[1\] ITU-R, "Multichannel stereophonic sound system with and without accompanying picture,"
+//!       Recommendation ITU-R BS.775-3, 2012.
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use crate::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+use crate::conv::IntoSample;
+use crate::sample::Sample;
+
+/// The maximum number of times a channel may be folded into other channels before being
+/// discarded.
+const MAX_FOLD_DEPTH: usize = 4;
+
+/// Gets the channels that `channel` may be folded into, in order of preference, when it is not
+/// present in the output. Each alternative is a list of target channels and the gain applied to
+/// each. If no alternative is present in the output, the last alternative is folded further.
+fn fold_targets(channel: Channels) -> &'static [&'static [(Channels, f32)]] {
+    const C: f32 = FRAC_1_SQRT_2;
+
+    match channel {
+        Channels::FRONT_LEFT => &[&[(Channels::FRONT_CENTRE, C)]],
+        Channels::FRONT_RIGHT => &[&[(Channels::FRONT_CENTRE, C)]],
+        Channels::FRONT_CENTRE => {
+            &[&[(Channels::FRONT_LEFT, C), (Channels::FRONT_RIGHT, C)]]
+        }
+        Channels::SIDE_LEFT => {
+            &[&[(Channels::REAR_LEFT, 1.0)], &[(Channels::FRONT_LEFT, C)]]
+        }
+        Channels::SIDE_RIGHT => {
+            &[&[(Channels::REAR_RIGHT, 1.0)], &[(Channels::FRONT_RIGHT, C)]]
+        }
+        Channels::REAR_LEFT => {
+            &[&[(Channels::SIDE_LEFT, 1.0)], &[(Channels::FRONT_LEFT, C)]]
+        }
+        Channels::REAR_RIGHT => {
+            &[&[(Channels::SIDE_RIGHT, 1.0)], &[(Channels::FRONT_RIGHT, C)]]
+        }
+        Channels::REAR_CENTRE => &[
+            &[(Channels::REAR_LEFT, C), (Channels::REAR_RIGHT, C)],
+            &[(Channels::SIDE_LEFT, C), (Channels::SIDE_RIGHT, C)],
+            &[(Channels::FRONT_LEFT, 0.5), (Channels::FRONT_RIGHT, 0.5)],
+        ],
+        Channels::FRONT_LEFT_CENTRE => &[&[(Channels::FRONT_LEFT, 1.0)]],
+        Channels::FRONT_RIGHT_CENTRE => &[&[(Channels::FRONT_RIGHT, 1.0)]],
+        Channels::FRONT_LEFT_WIDE => &[&[(Channels::FRONT_LEFT, 1.0)]],
+        Channels::FRONT_RIGHT_WIDE => &[&[(Channels::FRONT_RIGHT, 1.0)]],
+        Channels::REAR_LEFT_CENTRE => &[&[(Channels::REAR_LEFT, 1.0)]],
+        Channels::REAR_RIGHT_CENTRE => &[&[(Channels::REAR_RIGHT, 1.0)]],
+        Channels::FRONT_LEFT_HIGH => &[&[(Channels::FRONT_LEFT, C)]],
+        Channels::FRONT_RIGHT_HIGH => &[&[(Channels::FRONT_RIGHT, C)]],
+        Channels::FRONT_CENTRE_HIGH => &[&[(Channels::FRONT_CENTRE, C)]],
+        Channels::TOP_FRONT_LEFT => &[&[(Channels::FRONT_LEFT, C)]],
+        Channels::TOP_FRONT_RIGHT => &[&[(Channels::FRONT_RIGHT, C)]],
+        Channels::TOP_FRONT_CENTRE => &[&[(Channels::FRONT_CENTRE, C)]],
+        Channels::TOP_CENTRE => &[&[(Channels::FRONT_CENTRE, C)]],
+        Channels::TOP_REAR_LEFT => &[&[(Channels::REAR_LEFT, C)]],
+        Channels::TOP_REAR_RIGHT => &[&[(Channels::REAR_RIGHT, C)]],
+        Channels::TOP_REAR_CENTRE => &[&[(Channels::REAR_CENTRE, C)]],
+        Channels::LFE2 => &[&[(Channels::LFE1, 1.0)]],
+        // Low-frequency effects channels are discarded.
+        _ => &[],
+    }
+}
+
+/// Gets the index of the plane of `channel` in a buffer containing `channels`.
+fn plane_index(channels: Channels, channel: Channels) -> usize {
+    (channels.bits() & (channel.bits() - 1)).count_ones() as usize
+}
+
+/// Iterates over each channel in `channels`, in plane order.
+fn iter_channels(channels: Channels) -> impl Iterator<Item = Channels> {
+    (0..32)
+        .map(|i| Channels::from_bits_truncate(1 << i))
+        .filter(move |&channel| !channel.is_empty() && channels.contains(channel))
+}
+
+/// A `RemixMatrix` describes how to derive each output channel from the input channels.
+///
+/// The matrix has a row for each output channel, and a column for each input channel, both in
+/// plane order. Each coefficient is the gain applied to an input channel when mixing it into an
+/// output channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemixMatrix {
+    in_channels: Channels,
+    out_channels: Channels,
+    coeffs: Vec<f32>,
+}
+
+impl RemixMatrix {
+    /// Instantiate a custom `RemixMatrix` from `in_channels` to `out_channels`. The coefficients
+    /// are provided in row-major order, one row of `in_channels.count()` coefficients for each
+    /// output channel.
+    pub fn new(in_channels: Channels, out_channels: Channels, coeffs: Vec<f32>) -> Self {
+        assert!(
+            coeffs.len() == in_channels.count() * out_channels.count(),
+            "number of coefficients does not match the number of channels"
+        );
+
+        RemixMatrix { in_channels, out_channels, coeffs }
+    }
+
+    /// Instantiate a standard `RemixMatrix` from `in_channels` to `out_channels`.
+    ///
+    /// Channels present in both the input and output are passed through unchanged. Any other input
+    /// channel is folded into the nearest channels present in the output using the coefficients of
+    /// ITU-R BS.775. For example, 5.1 is downmixed to stereo as `L = FL + 0.707 C + 0.707 SL`, and
+    /// `R = FR + 0.707 C + 0.707 SR`. The side and rear surround channels are treated as
+    /// equivalent if the output has only one of the pairs.
+    ///
+    /// A single output channel is mixed as `M = 0.707 L + 0.707 R` from the stereo downmix of the
+    /// input, and a single input channel is treated as the centre channel.
+    pub fn standard(in_channels: Channels, out_channels: Channels) -> Self {
+        let n_in = in_channels.count();
+        let n_out = out_channels.count();
+
+        let mut matrix = RemixMatrix::new(in_channels, out_channels, vec![0.0; n_in * n_out]);
+
+        if n_in == 1 && n_out == 1 {
+            matrix.coeffs[0] = 1.0;
+        }
+        else if n_out == 1 {
+            // Mix the stereo downmix of the input into the single output channel.
+            let stereo = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+
+            let downmix = RemixMatrix::standard(in_channels, stereo);
+
+            for (i, coeff) in matrix.coeffs.iter_mut().enumerate() {
+                *coeff = FRAC_1_SQRT_2 * (downmix.coeffs[i] + downmix.coeffs[n_in + i]);
+            }
+        }
+        else if n_in == 1 {
+            matrix.fold(Channels::FRONT_CENTRE, 0, 1.0, 0);
+        }
+        else {
+            for (i, channel) in iter_channels(in_channels).enumerate() {
+                matrix.fold(channel, i, 1.0, 0);
+            }
+        }
+
+        matrix
+    }
+
+    /// Mixes the input channel `channel`, with plane index `input`, into the output channels with
+    /// the given gain.
+    fn fold(&mut self, channel: Channels, input: usize, gain: f32, depth: usize) {
+        if self.out_channels.contains(channel) {
+            let output = plane_index(self.out_channels, channel);
+            self.coeffs[output * self.in_channels.count() + input] += gain;
+            return;
+        }
+
+        if depth >= MAX_FOLD_DEPTH {
+            return;
+        }
+
+        let alternatives = fold_targets(channel);
+
+        // Use the first alternative that is entirely present in the output. Otherwise, fold the
+        // last alternative further.
+        let targets = alternatives
+            .iter()
+            .find(|targets| targets.iter().all(|&(target, _)| self.out_channels.contains(target)))
+            .or_else(|| alternatives.last());
+
+        if let Some(targets) = targets {
+            for &(target, target_gain) in targets.iter() {
+                self.fold(target, input, gain * target_gain, depth + 1);
+            }
+        }
+    }
+
+    /// Gets the input channels.
+    pub fn in_channels(&self) -> Channels {
+        self.in_channels
+    }
+
+    /// Gets the output channels.
+    pub fn out_channels(&self) -> Channels {
+        self.out_channels
+    }
+
+    /// Gets the gain of the input channel with plane index `input` when mixed into the output
+    /// channel with plane index `output`.
+    pub fn coeff(&self, output: usize, input: usize) -> f32 {
+        self.coeffs[output * self.in_channels.count() + input]
+    }
+
+    /// Sets the gain of the input channel with plane index `input` when mixed into the output
+    /// channel with plane index `output`.
+    pub fn set_coeff(&mut self, output: usize, input: usize, gain: f32) {
+        let n_in = self.in_channels.count();
+        self.coeffs[output * n_in + input] = gain;
+    }
+
+    /// Scales the matrix such that the sum of the absolute gains of every output channel is at
+    /// most 1. A remix using a normalized matrix cannot clip, at the cost of a lower output level.
+    pub fn normalize(&mut self) {
+        let n_in = self.in_channels.count();
+
+        if n_in == 0 {
+            return;
+        }
+
+        let max_sum = self
+            .coeffs
+            .chunks_exact(n_in)
+            .map(|row| row.iter().map(|coeff| coeff.abs()).sum::<f32>())
+            .fold(0.0, f32::max);
+
+        if max_sum > 1.0 {
+            for coeff in self.coeffs.iter_mut() {
+                *coeff /= max_sum;
+            }
+        }
+    }
+}
+
+/// A `Remixer` converts audio from one set of channels to another by applying a `RemixMatrix`.
+pub struct Remixer<S: Sample> {
+    matrix: RemixMatrix,
+    buf: AudioBuffer<S>,
+}
+
+impl<S> Remixer<S>
+where
+    S: Sample,
+    f32: IntoSample<S>,
+{
+    /// Instantiate a new `Remixer` using the given matrix.
+    pub fn new(matrix: RemixMatrix) -> Self {
+        Remixer { matrix, buf: AudioBuffer::unused() }
+    }
+
+    /// Gets the remix matrix.
+    pub fn matrix(&self) -> &RemixMatrix {
+        &self.matrix
+    }
+
+    /// Remixes the audio in `input`, and returns a reference to a buffer containing the output. The
+    /// channels of `input` must match the input channels of the remix matrix. Samples are clamped
+    /// to the range of the output sample format.
+    pub fn remix<T>(&mut self, input: &AudioBuffer<T>) -> &AudioBuffer<S>
+    where
+        T: Sample + IntoSample<f32>,
+    {
+        assert!(input.spec().channels == self.matrix.in_channels, "input channels do not match");
+
+        let n_frames = input.frames();
+        let out_spec = SignalSpec::new(input.spec().rate, self.matrix.out_channels);
+
+        if self.buf.capacity() < n_frames || self.buf.spec() != &out_spec {
+            self.buf = AudioBuffer::new(n_frames as u64, out_spec);
+        }
+
+        self.buf.clear();
+        self.buf.render_reserved(Some(n_frames));
+
+        let n_in = self.matrix.in_channels.count();
+
+        let mut mix = vec![0.0f32; n_frames];
+
+        for (output, row) in self.matrix.coeffs.chunks_exact(n_in).enumerate() {
+            mix.iter_mut().for_each(|s| *s = 0.0);
+
+            for (ch, &gain) in row.iter().enumerate() {
+                if gain == 0.0 {
+                    continue;
+                }
+
+                for (m, &s) in mix.iter_mut().zip(input.chan(ch)) {
+                    *m += gain * s.into_sample();
+                }
+            }
+
+            for (out, &m) in self.buf.chan_mut(output).iter_mut().zip(&mix) {
+                *out = m.into_sample();
+            }
+        }
+
+        &self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use crate::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+    use super::{RemixMatrix, Remixer};
+
+    const FL: Channels = Channels::FRONT_LEFT;
+    const FR: Channels = Channels::FRONT_RIGHT;
+    const FC: Channels = Channels::FRONT_CENTRE;
+    const LFE: Channels = Channels::LFE1;
+    const SL: Channels = Channels::SIDE_LEFT;
+    const SR: Channels = Channels::SIDE_RIGHT;
+    const RL: Channels = Channels::REAR_LEFT;
+    const RR: Channels = Channels::REAR_RIGHT;
+
+    fn assert_matrix(matrix: &RemixMatrix, expected: &[&[f32]]) {
+        for (output, row) in expected.iter().enumerate() {
+            for (input, &gain) in row.iter().enumerate() {
+                assert!(
+                    (matrix.coeff(output, input) - gain).abs() < 1e-6,
+                    "coefficient ({}, {}) is {}, expected {}",
+                    output,
+                    input,
+                    matrix.coeff(output, input),
+                    gain
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn verify_standard_matrices() {
+        const C: f32 = FRAC_1_SQRT_2;
+
+        // 5.1 to stereo, with either side or rear surround channels. Planes are in the order: FL,
+        // FR, FC, (RL, RR,) LFE, (SL, SR).
+        let matrix = RemixMatrix::standard(FL | FR | FC | LFE | SL | SR, FL | FR);
+        assert_matrix(&matrix, &[&[1.0, 0.0, C, 0.0, C, 0.0], &[0.0, 1.0, C, 0.0, 0.0, C]]);
+
+        let matrix = RemixMatrix::standard(FL | FR | FC | RL | RR | LFE, FL | FR);
+        assert_matrix(&matrix, &[&[1.0, 0.0, C, C, 0.0, 0.0], &[0.0, 1.0, C, 0.0, C, 0.0]]);
+
+        // 7.1 to 5.1. The rear channels are folded into the side channels.
+        let matrix = RemixMatrix::standard(Channels::SEVEN_POINT_ONE, FL | FR | FC | LFE | SL | SR);
+        assert_matrix(
+            &matrix,
+            &[
+                &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                &[0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                &[0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+                &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+                &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0],
+            ],
+        );
+
+        // Stereo to mono, mono to stereo, and 5.1 to mono.
+        let matrix = RemixMatrix::standard(FL | FR, FL);
+        assert_matrix(&matrix, &[&[C, C]]);
+
+        let matrix = RemixMatrix::standard(FL, FL | FR);
+        assert_matrix(&matrix, &[&[C], &[C]]);
+
+        let matrix = RemixMatrix::standard(FL | FR | FC | RL | RR | LFE, FC);
+        assert_matrix(&matrix, &[&[C, C, 1.0, 0.5, 0.5, 0.0]]);
+
+        // Identity.
+        let matrix = RemixMatrix::standard(FL | FR | FC, FL | FR | FC);
+        assert_matrix(&matrix, &[&[1.0, 0.0, 0.0], &[0.0, 1.0, 0.0], &[0.0, 0.0, 1.0]]);
+    }
+
+    #[test]
+    fn verify_remix() {
+        let spec = SignalSpec::new(44_100, FL | FR);
+
+        let mut input = AudioBuffer::<i16>::new(4, spec);
+        input.render_reserved(None);
+        input.chan_mut(0).copy_from_slice(&[0, 1000, 30000, -32768]);
+        input.chan_mut(1).copy_from_slice(&[0, -1000, 30000, -32768]);
+
+        // A custom matrix that swaps and sums the channels.
+        let matrix = RemixMatrix::new(FL | FR, FL | FR, vec![0.0, 1.0, 1.0, 1.0]);
+
+        let mut remixer = Remixer::<i16>::new(matrix);
+        let output = remixer.remix(&input);
+
+        assert!(output.spec() == &spec);
+        assert_eq!(output.chan(0), &[0, -1000, 30000, -32768]);
+        // The sum clips.
+        assert_eq!(output.chan(1), &[0, 0, 32767, -32768]);
+
+        // Normalizing the matrix prevents clipping.
+        let mut matrix = remixer.matrix().clone();
+        matrix.normalize();
+
+        let mut remixer = Remixer::<f32>::new(matrix);
+        let output = remixer.remix(&input);
+
+        assert_eq!(output.chan(1), &[0.0, 0.0, 30000.0 / 32768.0, -1.0]);
+    }
+}
@@ -0,0 +1,518 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `resampler` module implements sample rate conversion.
+//!
+//! Sample rate conversion is performed by band-limited interpolation using a Kaiser windowed-sinc
+//! filter as described in \[1\]. The filter is decomposed into a bank of polyphase filters, one for
+//! each distinct fractional position of an output sample between two input samples.
+//!
+//! \[1\] J. O. Smith, "Digital Audio Resampling Home Page," Center for Computer Research in Music
+//!       and Acoustics (CCRMA), Stanford University, 2020,
+//!       <https://ccrma.stanford.edu/~jos/resample/>.
+
+use std::f64::consts::PI;
+
+use crate::audio::{AudioBuffer, Signal, SignalSpec};
+use crate::conv::IntoSample;
+use crate::sample::Sample;
+
+/// The maximum number of filter phases. If the resampling ratio, in lowest terms, has an
+/// interpolation factor greater than this, the filter coefficients for a fractional position are
+/// linearly interpolated from the two nearest phases.
+const MAX_FILTER_PHASES: u64 = 1024;
+
+/// The quality of a `Resampler`.
+///
+/// Higher qualities use longer filters that have a narrower transition band and a greater
+/// stop-band attenuation, at the cost of more computation and a greater latency.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ResamplerQuality {
+    /// A 16 tap filter with approximately 60 dB of stop-band attenuation.
+    Low,
+    /// A 48 tap filter with approximately 80 dB of stop-band attenuation.
+    #[default]
+    Medium,
+    /// A 128 tap filter with approximately 100 dB of stop-band attenuation.
+    High,
+}
+
+impl ResamplerQuality {
+    /// Gets the half-length of the filter in taps, and the beta parameter of the Kaiser window.
+    fn filter_params(self) -> (usize, f64) {
+        match self {
+            ResamplerQuality::Low => (8, 6.0),
+            ResamplerQuality::Medium => (24, 8.0),
+            ResamplerQuality::High => (64, 10.0),
+        }
+    }
+}
+
+/// A bank of polyphase filters.
+struct PolyphaseFilter {
+    /// The coefficients of each phase, `n_taps` coefficients per phase. There is one more phase
+    /// than `n_phases` such that the last phase may be used for interpolation.
+    coeffs: Vec<f32>,
+    n_taps: usize,
+    n_phases: u64,
+    /// The interpolation factor of the resampling ratio. Fractional positions are in units of
+    /// `1 / up`.
+    up: u64,
+}
+
+impl PolyphaseFilter {
+    fn new(half_len: usize, up: u64, cutoff: f64, beta: f64) -> Self {
+        let n_taps = 2 * half_len;
+        let n_phases = up.min(MAX_FILTER_PHASES);
+
+        let mut coeffs = Vec::with_capacity((n_phases as usize + 1) * n_taps);
+
+        let i0_beta = bessel_i0(beta);
+
+        for phase in 0..=n_phases {
+            let frac = phase as f64 / n_phases as f64;
+
+            let start = coeffs.len();
+
+            // The filter is applied to the input samples from `half_len - 1` before, up-to
+            // `half_len` after, the input sample preceeding the output sample. Evaluate the
+            // windowed-sinc at the distance of each of those input samples.
+            for tap in 0..n_taps {
+                let t = frac + (half_len - 1) as f64 - tap as f64;
+
+                let x = t / half_len as f64;
+
+                let window = if x.abs() < 1.0 {
+                    bessel_i0(beta * (1.0 - x * x).sqrt()) / i0_beta
+                }
+                else {
+                    0.0
+                };
+
+                coeffs.push(cutoff * sinc(cutoff * t) * window);
+            }
+
+            // Normalize each phase to unity gain at DC.
+            let sum: f64 = coeffs[start..].iter().sum();
+
+            for c in coeffs[start..].iter_mut() {
+                *c /= sum;
+            }
+        }
+
+        PolyphaseFilter {
+            coeffs: coeffs.into_iter().map(|c| c as f32).collect(),
+            n_taps,
+            n_phases,
+            up,
+        }
+    }
+
+    /// Filters `n_taps` input samples to produce an output sample at the fractional position
+    /// `frac / up`.
+    #[inline]
+    fn apply(&self, x: &[f32], frac: u64) -> f32 {
+        debug_assert!(x.len() == self.n_taps);
+
+        if self.n_phases == self.up {
+            dot(x, self.phase(frac as usize))
+        }
+        else {
+            // Linearly interpolate between the outputs of the two nearest phases.
+            let pos = frac * self.n_phases;
+
+            let phase = (pos / self.up) as usize;
+            let mu = (pos % self.up) as f32 / self.up as f32;
+
+            let y0 = dot(x, self.phase(phase));
+            let y1 = dot(x, self.phase(phase + 1));
+
+            y0 + mu * (y1 - y0)
+        }
+    }
+
+    #[inline]
+    fn phase(&self, phase: usize) -> &[f32] {
+        &self.coeffs[phase * self.n_taps..(phase + 1) * self.n_taps]
+    }
+}
+
+/// A streaming sample rate converter.
+///
+/// `Resampler` converts audio from the sample rate of its input signal specification to an output
+/// sample rate. The ratio between the two sample rates may be any rational number. Audio is
+/// provided to the resampler in buffers of any length, and filter history is retained between
+/// calls such that the output is identical regardless of how the input was split.
+///
+/// Due to the length of the filter, some output is delayed until more input is provided. Once all
+/// input has been provided, `flush` should be called to get the remaining output. The total number
+/// of output frames is the number of input frames multiplied by the resampling ratio, rounded up.
+/// Output is time aligned with the input, the first output frame corresponds to the first input
+/// frame.
+pub struct Resampler {
+    in_spec: SignalSpec,
+    out_spec: SignalSpec,
+    filter: PolyphaseFilter,
+    /// The interpolation (up) and decimation (down) factors of the resampling ratio, in lowest
+    /// terms.
+    up: u64,
+    down: u64,
+    /// Half the length of the filter in taps.
+    half_len: usize,
+    /// The input samples for each channel that are yet to be fully consumed.
+    history: Vec<Vec<f32>>,
+    /// The index in `history` of the input sample preceeding the next output sample.
+    pos: usize,
+    /// The fractional position, in units of `1 / up`, of the next output sample after the input
+    /// sample at `pos`.
+    frac: u64,
+    /// The total number of input and output frames since instantiation, or the last reset.
+    n_frames_in: u64,
+    n_frames_out: u64,
+    buf: AudioBuffer<f32>,
+}
+
+impl Resampler {
+    /// Instantiate a new `Resampler` that converts audio with the signal specification `spec` to
+    /// the sample rate `out_rate` at the given quality.
+    pub fn new(spec: SignalSpec, out_rate: u32, quality: ResamplerQuality) -> Self {
+        assert!(spec.rate > 0, "input sample rate must be greater than 0");
+        assert!(out_rate > 0, "output sample rate must be greater than 0");
+        assert!(spec.channels.count() > 0, "at least one channel is required");
+
+        let gcd = gcd(spec.rate, out_rate);
+
+        let up = u64::from(out_rate / gcd);
+        let down = u64::from(spec.rate / gcd);
+
+        let (half_len, cutoff, beta) = if up == down {
+            // If the sample rates are equal, a single tap filter passes the input through
+            // unchanged.
+            (1, 1.0, 0.0)
+        }
+        else {
+            let (half_len, beta) = quality.filter_params();
+
+            // Approximate the width of the filter's transition band, relative to the Nyquist
+            // frequency, from the stop-band attenuation of the Kaiser window. The cutoff frequency
+            // is placed such that the transition band ends at the Nyquist frequency.
+            let atten = beta / 0.1102 + 8.7;
+            let transition = (atten - 8.0) / (2.285 * (2 * half_len) as f64 * PI);
+
+            let cutoff = 1.0 - 0.5 * transition;
+
+            if up < down {
+                // When downsampling, the cutoff frequency must be lowered to the Nyquist frequency
+                // of the output. The filter is lengthened by the same ratio to maintain the same
+                // transition band width relative to the output sample rate.
+                let ratio = up as f64 / down as f64;
+
+                ((half_len as f64 / ratio).ceil() as usize, ratio * cutoff, beta)
+            }
+            else {
+                (half_len, cutoff, beta)
+            }
+        };
+
+        let out_spec = SignalSpec { rate: out_rate, channels: spec.channels };
+
+        let mut resampler = Resampler {
+            in_spec: spec,
+            out_spec,
+            filter: PolyphaseFilter::new(half_len, up, cutoff, beta),
+            up,
+            down,
+            half_len,
+            history: vec![Vec::new(); spec.channels.count()],
+            pos: 0,
+            frac: 0,
+            n_frames_in: 0,
+            n_frames_out: 0,
+            buf: AudioBuffer::unused(),
+        };
+
+        resampler.reset();
+        resampler
+    }
+
+    /// Gets the signal specification of the input.
+    pub fn input_spec(&self) -> &SignalSpec {
+        &self.in_spec
+    }
+
+    /// Gets the signal specification of the output.
+    pub fn output_spec(&self) -> &SignalSpec {
+        &self.out_spec
+    }
+
+    /// Resamples the audio in `input`, and returns a reference to a buffer containing all output
+    /// frames that could be computed. The signal specification of `input` must match the input
+    /// signal specification of the resampler.
+    pub fn resample<S>(&mut self, input: &AudioBuffer<S>) -> &AudioBuffer<f32>
+    where
+        S: Sample + IntoSample<f32>
+    {
+        assert!(input.spec() == &self.in_spec, "input signal specification does not match");
+
+        for (ch, history) in self.history.iter_mut().enumerate() {
+            history.extend(input.chan(ch).iter().map(|&s| s.into_sample()));
+        }
+
+        self.n_frames_in += input.frames() as u64;
+
+        self.process(None);
+
+        &self.buf
+    }
+
+    /// Signals the end of the input, and returns a reference to a buffer containing all remaining
+    /// output frames. The resampler is reset afterwards.
+    pub fn flush(&mut self) -> &AudioBuffer<f32> {
+        let n_frames_total = (self.n_frames_in * self.up).div_ceil(self.down);
+
+        // Pad the input with silence such that the last output frames can be computed.
+        for history in self.history.iter_mut() {
+            history.resize(history.len() + self.half_len, 0.0);
+        }
+
+        self.process(Some(n_frames_total - self.n_frames_out));
+
+        self.reset_state();
+
+        &self.buf
+    }
+
+    /// Resets the resampler, discarding all buffered input.
+    pub fn reset(&mut self) {
+        self.reset_state();
+        self.buf.clear();
+    }
+
+    fn reset_state(&mut self) {
+        // Prime the history with silence such that the first output frame, which is aligned with
+        // the first input frame, has all the preceeding input samples it requires.
+        for history in self.history.iter_mut() {
+            history.clear();
+            history.resize(self.half_len - 1, 0.0);
+        }
+
+        self.pos = self.half_len - 1;
+        self.frac = 0;
+        self.n_frames_in = 0;
+        self.n_frames_out = 0;
+    }
+
+    /// Computes as many output frames as possible, up-to `limit` frames, from the buffered input.
+    fn process(&mut self, limit: Option<u64>) {
+        let n_available = self.history[0].len();
+
+        // An output frame can be computed if `half_len` input samples following its position are
+        // available.
+        let n_frames = if n_available > self.pos + self.half_len {
+            let end = (n_available - self.pos - self.half_len) as u64 * self.up;
+            (end - self.frac).div_ceil(self.down)
+        }
+        else {
+            0
+        };
+
+        let n_frames = limit.map_or(n_frames, |limit| n_frames.min(limit)) as usize;
+
+        if self.buf.capacity() < n_frames {
+            self.buf = AudioBuffer::new(n_frames as u64, self.out_spec);
+        }
+
+        self.buf.clear();
+        self.buf.render_reserved(Some(n_frames));
+
+        for (ch, history) in self.history.iter().enumerate() {
+            let mut pos = self.pos;
+            let mut frac = self.frac;
+
+            for out in self.buf.chan_mut(ch) {
+                let x = &history[pos + 1 - self.half_len..pos + 1 + self.half_len];
+
+                *out = self.filter.apply(x, frac);
+
+                frac += self.down;
+                pos += (frac / self.up) as usize;
+                frac %= self.up;
+            }
+        }
+
+        // Advance the position.
+        let advance = self.frac + n_frames as u64 * self.down;
+
+        self.pos += (advance / self.up) as usize;
+        self.frac = advance % self.up;
+        self.n_frames_out += n_frames as u64;
+
+        // Discard input samples that are no longer required. When downsampling by a large ratio,
+        // the position may be beyond the end of the buffered input.
+        let n_consumed = (self.pos + 1 - self.half_len).min(n_available);
+
+        for history in self.history.iter_mut() {
+            history.drain(..n_consumed);
+        }
+
+        self.pos -= n_consumed;
+    }
+}
+
+/// Computes the dot product of two equal length vectors.
+#[inline]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert!(a.len() == b.len());
+
+    // Accumulate 4 independent partial sums to allow the compiler to vectorize the loop.
+    let mut acc = [0.0f32; 4];
+
+    let a_chunks = a.chunks_exact(4);
+    let b_chunks = b.chunks_exact(4);
+
+    let rem: f32 = a_chunks.remainder()
+                           .iter()
+                           .zip(b_chunks.remainder())
+                           .map(|(&a, &b)| a * b)
+                           .sum();
+
+    for (a, b) in a_chunks.zip(b_chunks) {
+        acc[0] += a[0] * b[0];
+        acc[1] += a[1] * b[1];
+        acc[2] += a[2] * b[2];
+        acc[3] += a[3] * b[3];
+    }
+
+    (acc[0] + acc[1]) + (acc[2] + acc[3]) + rem
+}
+
+/// The normalized sinc function, sin(pi * x) / (pi * x).
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    }
+    else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The zeroth-order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+
+    loop {
+        term *= (0.5 * x / k) * (0.5 * x / k);
+        sum += term;
+
+        if term < 1e-12 * sum {
+            break;
+        }
+
+        k += 1.0;
+    }
+
+    sum
+}
+
+/// Computes the greatest common divisor.
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+    use super::{Resampler, ResamplerQuality};
+
+    fn sine(spec: SignalSpec, n_frames: usize, freq: f64) -> AudioBuffer<f32> {
+        let mut buf = AudioBuffer::new(n_frames as u64, spec);
+        buf.render_reserved(None);
+
+        for ch in 0..spec.channels.count() {
+            for (i, s) in buf.chan_mut(ch).iter_mut().enumerate() {
+                *s = (0.5 * (2.0 * PI * freq * i as f64 / f64::from(spec.rate)).sin()) as f32;
+            }
+        }
+
+        buf
+    }
+
+    fn resample_chunked(
+        resampler: &mut Resampler,
+        input: &AudioBuffer<f32>,
+        chunk: usize,
+    ) -> Vec<f32> {
+        let mut output = Vec::new();
+
+        for start in (0..input.frames()).step_by(chunk) {
+            let len = chunk.min(input.frames() - start);
+
+            let mut part = AudioBuffer::<f32>::new(len as u64, *input.spec());
+            part.render_reserved(None);
+            part.chan_mut(0).copy_from_slice(&input.chan(0)[start..start + len]);
+
+            output.extend_from_slice(resampler.resample(&part).chan(0));
+        }
+
+        output.extend_from_slice(resampler.flush().chan(0));
+        output
+    }
+
+    #[test]
+    fn verify_resampler_ratios() {
+        let spec = SignalSpec::new(44_100, Channels::FRONT_LEFT);
+
+        let input = sine(spec, 10_000, 1000.0);
+
+        // Up, down, arbitrary, and equal ratios.
+        for &out_rate in [48_000, 96_000, 22_050, 44_101, 44_100].iter() {
+            let mut resampler = Resampler::new(spec, out_rate, ResamplerQuality::High);
+
+            let whole = resample_chunked(&mut resampler, &input, 10_000);
+            let chunked = resample_chunked(&mut resampler, &input, 333);
+
+            // The output is independent of how the input was split.
+            assert_eq!(whole, chunked);
+
+            let expected_len = (10_000 * u64::from(out_rate)).div_ceil(44_100);
+            assert_eq!(whole.len() as u64, expected_len);
+
+            // Excluding the edges, the output is the same sine wave at the new sample rate.
+            for (i, &s) in whole.iter().enumerate().skip(200).take(whole.len() - 400) {
+                let expected = 0.5 * (2.0 * PI * 1000.0 * i as f64 / f64::from(out_rate)).sin();
+                assert!((f64::from(s) - expected).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_resampler_anti_aliasing() {
+        // A tone above the Nyquist frequency of the output should be removed.
+        let spec = SignalSpec::new(48_000, Channels::FRONT_LEFT);
+
+        let input = sine(spec, 48_000, 12_000.0);
+
+        let mut resampler = Resampler::new(spec, 16_000, ResamplerQuality::High);
+
+        let output = resample_chunked(&mut resampler, &input, 4096);
+
+        let steady = &output[500..output.len() - 500];
+
+        let peak = steady.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+
+        assert!(peak < 1e-4);
+    }
+}
@@ -0,0 +1,495 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `simd` module provides element-wise vector kernels that use SIMD instructions where
+//! available.
+//!
+//! On x86 and x86-64, the AVX implementation is selected at runtime if the CPU supports it, and
+//! the SSE implementation is used otherwise. On AArch64, the NEON implementation is used. On all
+//! other targets, a scalar implementation is used.
+//!
+//! Every kernel operates on the shortest of its slices, and produces results identical to the
+//! equivalent scalar code since fused multiply-add instructions are not used.
+
+// This is the only module in the crate that may use unsafe code. It is confined to the
+// architecture-specific sub-modules, which only expose safe functions.
+
+use super::fft::Complex;
+
+/// Multiplies each sample in `dst` by the corresponding sample in `src`.
+pub fn mul(dst: &mut [f32], src: &[f32]) {
+    let n = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[..n], &src[..n]);
+
+    let done = arch::mul(dst, src);
+
+    for (d, &s) in dst[done..].iter_mut().zip(&src[done..]) {
+        *d *= s;
+    }
+}
+
+/// Adds each sample in `src` to the corresponding sample in `dst`.
+pub fn add(dst: &mut [f32], src: &[f32]) {
+    let n = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[..n], &src[..n]);
+
+    let done = arch::add(dst, src);
+
+    for (d, &s) in dst[done..].iter_mut().zip(&src[done..]) {
+        *d += s;
+    }
+}
+
+/// Multiplies each sample in `a` by the corresponding sample in `b`, and adds the product to the
+/// corresponding sample in `dst`.
+pub fn mul_add(dst: &mut [f32], a: &[f32], b: &[f32]) {
+    let n = dst.len().min(a.len()).min(b.len());
+    let (dst, a, b) = (&mut dst[..n], &a[..n], &b[..n]);
+
+    let done = arch::mul_add(dst, a, b);
+
+    for ((d, &a), &b) in dst[done..].iter_mut().zip(&a[done..]).zip(&b[done..]) {
+        *d += a * b;
+    }
+}
+
+/// Multiplies each complex number in `dst` by the corresponding complex number in `src`.
+pub fn complex_mul(dst: &mut [Complex], src: &[Complex]) {
+    let n = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[..n], &src[..n]);
+
+    let done = arch::complex_mul::<false>(as_floats_mut(dst), as_floats(src)) / 2;
+
+    for (d, &s) in dst[done..].iter_mut().zip(&src[done..]) {
+        *d = *d * s;
+    }
+}
+
+/// Multiplies each complex number in `dst` by the complex conjugate of the corresponding complex
+/// number in `src`.
+pub fn complex_mul_conj(dst: &mut [Complex], src: &[Complex]) {
+    let n = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[..n], &src[..n]);
+
+    let done = arch::complex_mul::<true>(as_floats_mut(dst), as_floats(src)) / 2;
+
+    for (d, &s) in dst[done..].iter_mut().zip(&src[done..]) {
+        *d = *d * s.conj();
+    }
+}
+
+/// Reinterprets complex numbers as interleaved real and imaginary parts.
+#[allow(unsafe_code)]
+fn as_floats(src: &[Complex]) -> &[f32] {
+    // SAFETY: `Complex` is `repr(C)` with two `f32` fields, and therefore has the size and
+    // alignment of `[f32; 2]`.
+    unsafe { std::slice::from_raw_parts(src.as_ptr() as *const f32, 2 * src.len()) }
+}
+
+/// Reinterprets complex numbers as interleaved real and imaginary parts.
+#[allow(unsafe_code)]
+fn as_floats_mut(src: &mut [Complex]) -> &mut [f32] {
+    // SAFETY: See `as_floats`.
+    unsafe { std::slice::from_raw_parts_mut(src.as_mut_ptr() as *mut f32, 2 * src.len()) }
+}
+
+/// The kernels of each architecture-specific module operate on equal length slices, and return
+/// the number of leading floats processed. The remaining floats are processed by the caller.
+/// Complex numbers are interleaved, and an even number of floats is always processed.
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse"))]
+#[allow(unsafe_code)]
+mod arch {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// Generates an element-wise kernel computing `dst = $op(dst, src)` using vectors of
+    /// `$lanes` floats.
+    macro_rules! binary_kernel {
+        ($name:ident, $feature:literal, $lanes:literal, $load:ident, $store:ident, $op:ident) => {
+            #[target_feature(enable = $feature)]
+            pub unsafe fn $name(dst: &mut [f32], src: &[f32]) -> usize {
+                let n = dst.len() - dst.len() % $lanes;
+
+                for (d, s) in dst[..n].chunks_exact_mut($lanes).zip(src.chunks_exact($lanes)) {
+                    let v = $op($load(d.as_ptr()), $load(s.as_ptr()));
+                    $store(d.as_mut_ptr(), v);
+                }
+
+                n
+            }
+        };
+    }
+
+    /// Generates an element-wise kernel computing `dst = dst + a * b` using vectors of `$lanes`
+    /// floats.
+    macro_rules! mul_add_kernel {
+        ($name:ident, $feature:literal, $lanes:literal, $load:ident, $store:ident,
+         $mul:ident, $add:ident) => {
+            #[target_feature(enable = $feature)]
+            pub unsafe fn $name(dst: &mut [f32], a: &[f32], b: &[f32]) -> usize {
+                let n = dst.len() - dst.len() % $lanes;
+
+                let src = a.chunks_exact($lanes).zip(b.chunks_exact($lanes));
+
+                for (d, (a, b)) in dst[..n].chunks_exact_mut($lanes).zip(src) {
+                    let p = $mul($load(a.as_ptr()), $load(b.as_ptr()));
+                    $store(d.as_mut_ptr(), $add($load(d.as_ptr()), p));
+                }
+
+                n
+            }
+        };
+    }
+
+    binary_kernel!(mul_sse, "sse", 4, _mm_loadu_ps, _mm_storeu_ps, _mm_mul_ps);
+    binary_kernel!(mul_avx, "avx", 8, _mm256_loadu_ps, _mm256_storeu_ps, _mm256_mul_ps);
+    binary_kernel!(add_sse, "sse", 4, _mm_loadu_ps, _mm_storeu_ps, _mm_add_ps);
+    binary_kernel!(add_avx, "avx", 8, _mm256_loadu_ps, _mm256_storeu_ps, _mm256_add_ps);
+
+    mul_add_kernel!(mul_add_sse, "sse", 4, _mm_loadu_ps, _mm_storeu_ps, _mm_mul_ps, _mm_add_ps);
+    mul_add_kernel!(
+        mul_add_avx, "avx", 8, _mm256_loadu_ps, _mm256_storeu_ps, _mm256_mul_ps, _mm256_add_ps
+    );
+
+    /// Gets the sign bits applied to the cross products of a complex multiplication. The real
+    /// part subtracts the cross product, unless the multiplicand is conjugated, in which case the
+    /// imaginary part subtracts it instead.
+    #[inline(always)]
+    fn cross_signs<const CONJ: bool>() -> [f32; 8] {
+        if CONJ {
+            [0.0, -0.0, 0.0, -0.0, 0.0, -0.0, 0.0, -0.0]
+        }
+        else {
+            [-0.0, 0.0, -0.0, 0.0, -0.0, 0.0, -0.0, 0.0]
+        }
+    }
+
+    #[target_feature(enable = "sse")]
+    pub unsafe fn complex_mul_sse<const CONJ: bool>(dst: &mut [f32], src: &[f32]) -> usize {
+        let n = dst.len() - dst.len() % 4;
+
+        let signs = _mm_loadu_ps(cross_signs::<CONJ>().as_ptr());
+
+        for (d, s) in dst[..n].chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            // a = [ar0, ai0, ar1, ai1], b = [br0, bi0, br1, bi1]
+            let a = _mm_loadu_ps(d.as_ptr());
+            let b = _mm_loadu_ps(s.as_ptr());
+
+            // [br0, br0, br1, br1], [bi0, bi0, bi1, bi1], and [ai0, ar0, ai1, ar1]
+            let b_re = _mm_shuffle_ps(b, b, 0b10_10_00_00);
+            let b_im = _mm_shuffle_ps(b, b, 0b11_11_01_01);
+            let a_swap = _mm_shuffle_ps(a, a, 0b10_11_00_01);
+
+            let direct = _mm_mul_ps(a, b_re);
+            let cross = _mm_xor_ps(_mm_mul_ps(a_swap, b_im), signs);
+
+            _mm_storeu_ps(d.as_mut_ptr(), _mm_add_ps(direct, cross));
+        }
+
+        n
+    }
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn complex_mul_avx<const CONJ: bool>(dst: &mut [f32], src: &[f32]) -> usize {
+        let n = dst.len() - dst.len() % 8;
+
+        let signs = _mm256_loadu_ps(cross_signs::<CONJ>().as_ptr());
+
+        for (d, s) in dst[..n].chunks_exact_mut(8).zip(src.chunks_exact(8)) {
+            // The permutations are those used by the SSE kernel applied to each 128-bit lane.
+            let a = _mm256_loadu_ps(d.as_ptr());
+            let b = _mm256_loadu_ps(s.as_ptr());
+
+            let b_re = _mm256_permute_ps(b, 0b10_10_00_00);
+            let b_im = _mm256_permute_ps(b, 0b11_11_01_01);
+            let a_swap = _mm256_permute_ps(a, 0b10_11_00_01);
+
+            let direct = _mm256_mul_ps(a, b_re);
+            let cross = _mm256_xor_ps(_mm256_mul_ps(a_swap, b_im), signs);
+
+            _mm256_storeu_ps(d.as_mut_ptr(), _mm256_add_ps(direct, cross));
+        }
+
+        n
+    }
+
+    // SAFETY: For the functions below, AVX support is detected at runtime, and SSE support is
+    // enabled at compile time.
+
+    pub fn mul(dst: &mut [f32], src: &[f32]) -> usize {
+        unsafe {
+            if is_x86_feature_detected!("avx") { mul_avx(dst, src) } else { mul_sse(dst, src) }
+        }
+    }
+
+    pub fn add(dst: &mut [f32], src: &[f32]) -> usize {
+        unsafe {
+            if is_x86_feature_detected!("avx") { add_avx(dst, src) } else { add_sse(dst, src) }
+        }
+    }
+
+    pub fn mul_add(dst: &mut [f32], a: &[f32], b: &[f32]) -> usize {
+        unsafe {
+            if is_x86_feature_detected!("avx") {
+                mul_add_avx(dst, a, b)
+            }
+            else {
+                mul_add_sse(dst, a, b)
+            }
+        }
+    }
+
+    pub fn complex_mul<const CONJ: bool>(dst: &mut [f32], src: &[f32]) -> usize {
+        unsafe {
+            if is_x86_feature_detected!("avx") {
+                complex_mul_avx::<CONJ>(dst, src)
+            }
+            else {
+                complex_mul_sse::<CONJ>(dst, src)
+            }
+        }
+    }
+
+    /// Gets every implementation of the kernels supported by the CPU.
+    #[cfg(test)]
+    pub fn kernels() -> Vec<super::tests::Kernels> {
+        use super::tests::Kernels;
+
+        let mut kernels = vec![Kernels {
+            name: "sse",
+            mul: |d, s| unsafe { mul_sse(d, s) },
+            add: |d, s| unsafe { add_sse(d, s) },
+            mul_add: |d, a, b| unsafe { mul_add_sse(d, a, b) },
+            complex_mul: |d, s| unsafe { complex_mul_sse::<false>(d, s) },
+            complex_mul_conj: |d, s| unsafe { complex_mul_sse::<true>(d, s) },
+        }];
+
+        if is_x86_feature_detected!("avx") {
+            kernels.push(Kernels {
+                name: "avx",
+                mul: |d, s| unsafe { mul_avx(d, s) },
+                add: |d, s| unsafe { add_avx(d, s) },
+                mul_add: |d, a, b| unsafe { mul_add_avx(d, a, b) },
+                complex_mul: |d, s| unsafe { complex_mul_avx::<false>(d, s) },
+                complex_mul_conj: |d, s| unsafe { complex_mul_avx::<true>(d, s) },
+            });
+        }
+
+        kernels
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+#[allow(unsafe_code)]
+mod arch {
+    use std::arch::aarch64::*;
+
+    // SAFETY: For the functions below, NEON support is enabled at compile time, and every load
+    // and store is of a chunk of exactly 4 or 8 floats.
+
+    pub fn mul(dst: &mut [f32], src: &[f32]) -> usize {
+        let n = dst.len() - dst.len() % 4;
+
+        for (d, s) in dst[..n].chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            unsafe {
+                let v = vmulq_f32(vld1q_f32(d.as_ptr()), vld1q_f32(s.as_ptr()));
+                vst1q_f32(d.as_mut_ptr(), v);
+            }
+        }
+
+        n
+    }
+
+    pub fn add(dst: &mut [f32], src: &[f32]) -> usize {
+        let n = dst.len() - dst.len() % 4;
+
+        for (d, s) in dst[..n].chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            unsafe {
+                let v = vaddq_f32(vld1q_f32(d.as_ptr()), vld1q_f32(s.as_ptr()));
+                vst1q_f32(d.as_mut_ptr(), v);
+            }
+        }
+
+        n
+    }
+
+    pub fn mul_add(dst: &mut [f32], a: &[f32], b: &[f32]) -> usize {
+        let n = dst.len() - dst.len() % 4;
+
+        let src = a.chunks_exact(4).zip(b.chunks_exact(4));
+
+        for (d, (a, b)) in dst[..n].chunks_exact_mut(4).zip(src) {
+            unsafe {
+                let p = vmulq_f32(vld1q_f32(a.as_ptr()), vld1q_f32(b.as_ptr()));
+                vst1q_f32(d.as_mut_ptr(), vaddq_f32(vld1q_f32(d.as_ptr()), p));
+            }
+        }
+
+        n
+    }
+
+    pub fn complex_mul<const CONJ: bool>(dst: &mut [f32], src: &[f32]) -> usize {
+        let n = dst.len() - dst.len() % 8;
+
+        for (d, s) in dst[..n].chunks_exact_mut(8).zip(src.chunks_exact(8)) {
+            unsafe {
+                // De-interleave the real and imaginary parts of 4 complex numbers.
+                let a = vld2q_f32(d.as_ptr());
+                let b = vld2q_f32(s.as_ptr());
+
+                let rr = vmulq_f32(a.0, b.0);
+                let ii = vmulq_f32(a.1, b.1);
+                let ri = vmulq_f32(a.0, b.1);
+                let ir = vmulq_f32(a.1, b.0);
+
+                let y = if CONJ {
+                    float32x4x2_t(vaddq_f32(rr, ii), vsubq_f32(ir, ri))
+                }
+                else {
+                    float32x4x2_t(vsubq_f32(rr, ii), vaddq_f32(ri, ir))
+                };
+
+                vst2q_f32(d.as_mut_ptr(), y);
+            }
+        }
+
+        n
+    }
+
+    /// Gets every implementation of the kernels supported by the CPU.
+    #[cfg(test)]
+    pub fn kernels() -> Vec<super::tests::Kernels> {
+        vec![super::tests::Kernels {
+            name: "neon",
+            mul,
+            add,
+            mul_add,
+            complex_mul: complex_mul::<false>,
+            complex_mul_conj: complex_mul::<true>,
+        }]
+    }
+}
+
+#[cfg(not(any(
+    all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse"),
+    all(target_arch = "aarch64", target_feature = "neon"),
+)))]
+mod arch {
+    pub fn mul(_: &mut [f32], _: &[f32]) -> usize {
+        0
+    }
+
+    pub fn add(_: &mut [f32], _: &[f32]) -> usize {
+        0
+    }
+
+    pub fn mul_add(_: &mut [f32], _: &[f32], _: &[f32]) -> usize {
+        0
+    }
+
+    pub fn complex_mul<const CONJ: bool>(_: &mut [f32], _: &[f32]) -> usize {
+        0
+    }
+
+    #[cfg(test)]
+    pub fn kernels() -> Vec<super::tests::Kernels> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An implementation of the architecture-specific kernels.
+    pub struct Kernels {
+        pub name: &'static str,
+        pub mul: fn(&mut [f32], &[f32]) -> usize,
+        pub add: fn(&mut [f32], &[f32]) -> usize,
+        pub mul_add: fn(&mut [f32], &[f32], &[f32]) -> usize,
+        pub complex_mul: fn(&mut [f32], &[f32]) -> usize,
+        pub complex_mul_conj: fn(&mut [f32], &[f32]) -> usize,
+    }
+
+    fn signal(n: usize, seed: u32) -> Vec<f32> {
+        (0..n as u32).map(|i| ((i * 7919 + seed) % 257) as f32 / 64.0 - 2.0).collect()
+    }
+
+    fn complex(x: &[f32]) -> Vec<Complex> {
+        x.chunks_exact(2).map(|c| Complex::new(c[0], c[1])).collect()
+    }
+
+    #[test]
+    fn verify_kernels() {
+        for n in (0..40).chain([1023, 1024].iter().copied()) {
+            let a = signal(2 * n, 1);
+            let b = signal(2 * n, 2);
+            let c = signal(2 * n, 3);
+
+            // The scalar results.
+            let mul: Vec<f32> = a.iter().zip(&b).map(|(&a, &b)| a * b).collect();
+            let add: Vec<f32> = a.iter().zip(&b).map(|(&a, &b)| a + b).collect();
+            let mul_add: Vec<f32> =
+                a.iter().zip(&b).zip(&c).map(|((&a, &b), &c)| a + b * c).collect();
+
+            let (za, zb) = (complex(&a), complex(&b));
+            let cmul: Vec<Complex> = za.iter().zip(&zb).map(|(&a, &b)| a * b).collect();
+            let cmul_conj: Vec<Complex> = za.iter().zip(&zb).map(|(&a, &b)| a * b.conj()).collect();
+
+            // The public kernels must be identical to the scalar results.
+            let mut x = a.clone();
+            super::mul(&mut x, &b);
+            assert_eq!(x, mul);
+
+            let mut x = a.clone();
+            super::add(&mut x, &b);
+            assert_eq!(x, add);
+
+            let mut x = a.clone();
+            super::mul_add(&mut x, &b, &c);
+            assert_eq!(x, mul_add);
+
+            let mut z = za.clone();
+            super::complex_mul(&mut z, &zb);
+            assert_eq!(z, cmul);
+
+            let mut z = za.clone();
+            super::complex_mul_conj(&mut z, &zb);
+            assert_eq!(z, cmul_conj);
+
+            // As must the processed part of the results of every architecture-specific kernel.
+            for k in arch::kernels() {
+                let mut x = a.clone();
+                let done = (k.mul)(&mut x, &b);
+                assert_eq!(x[..done], mul[..done], "{}", k.name);
+
+                let mut x = a.clone();
+                let done = (k.add)(&mut x, &b);
+                assert_eq!(x[..done], add[..done], "{}", k.name);
+
+                let mut x = a.clone();
+                let done = (k.mul_add)(&mut x, &b, &c);
+                assert_eq!(x[..done], mul_add[..done], "{}", k.name);
+
+                let mut x = a.clone();
+                let done = (k.complex_mul)(&mut x, &b);
+                assert_eq!(complex(&x[..done]), cmul[..done / 2], "{}", k.name);
+
+                let mut x = a.clone();
+                let done = (k.complex_mul_conj)(&mut x, &b);
+                assert_eq!(complex(&x[..done]), cmul_conj[..done / 2], "{}", k.name);
+            }
+        }
+
+        // Kernels process the shortest of their slices.
+        let mut x = vec![1.0; 10];
+        super::mul_add(&mut x, &[2.0; 9], &[3.0; 20]);
+        assert_eq!(x[8..], [7.0, 1.0]);
+    }
+}
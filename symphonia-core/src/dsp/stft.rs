@@ -0,0 +1,242 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `stft` module implements the Short-Time Fourier Transform (STFT).
+//!
+//! The STFT splits a signal into overlapping frames, applies a window function to each frame, and
+//! computes the magnitude spectrum of each frame. A sequence of magnitude spectra may be rendered
+//! as a spectrogram, or averaged to find the bandwidth of a signal. For example, a lossy codec
+//! typically removes all content above a cutoff frequency that depends on the bitrate.
+
+use crate::audio::{AudioBuffer, Signal, SignalSpec};
+
+use super::fft::{Complex, RealFft};
+use super::window::Window;
+
+/// A Short-Time Fourier Transform that produces the magnitude spectra of an audio signal.
+pub struct Stft {
+    spec: SignalSpec,
+    hop: usize,
+    fft: RealFft,
+    window: Vec<f32>,
+    /// The scale applied to the magnitude spectrum.
+    scale: f32,
+    /// The buffered samples of each channel that are not yet part of a complete frame.
+    pending: Vec<Vec<f32>>,
+    /// The number of samples to discard before the next frame begins.
+    skip: usize,
+    frame: Vec<f32>,
+    spectrum: Vec<Complex>,
+    power: Vec<f32>,
+}
+
+impl Stft {
+    /// Instantiate a new `Stft` for audio with the signal specification provided. Each frame is
+    /// `size` samples long, and a new frame begins every `hop` samples. The size must be even.
+    pub fn new(spec: SignalSpec, size: usize, hop: usize, window: Window) -> Self {
+        assert!(hop > 0, "hop must be greater than 0");
+
+        let n_channels = spec.channels.count();
+
+        let window = window.generate(size);
+
+        // Scale the spectrum such that a sinusoid of amplitude A has a peak magnitude of A.
+        let scale = 2.0 / window.iter().sum::<f32>();
+
+        Stft {
+            spec,
+            hop,
+            fft: RealFft::new(size),
+            window,
+            scale,
+            pending: vec![Vec::with_capacity(size); n_channels],
+            skip: 0,
+            frame: vec![0.0; size],
+            spectrum: vec![Default::default(); size / 2 + 1],
+            power: vec![0.0; size / 2 + 1],
+        }
+    }
+
+    /// Gets the signal specification of the audio being analyzed.
+    pub fn spec(&self) -> &SignalSpec {
+        &self.spec
+    }
+
+    /// Gets the number of frequency bins in each magnitude spectrum.
+    pub fn num_bins(&self) -> usize {
+        self.spectrum.len()
+    }
+
+    /// Gets the centre frequency, in Hz, of a frequency bin.
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.spec.rate as f32 / self.fft.size() as f32
+    }
+
+    /// Analyzes the audio in `buf`. For each complete frame, `f` is called with the magnitude
+    /// spectrum of the frame. The magnitude of each bin is the root-mean-square magnitude of that
+    /// bin across all channels.
+    pub fn process<F>(&mut self, buf: &AudioBuffer<f32>, mut f: F)
+    where
+        F: FnMut(&[f32]),
+    {
+        assert!(buf.spec() == &self.spec, "buffer signal specification does not match");
+
+        let size = self.fft.size();
+
+        let mut offset = 0;
+
+        while offset < buf.frames() {
+            // Discard samples between frames if the hop is larger than the frame size.
+            if self.skip > 0 {
+                let skip = self.skip.min(buf.frames() - offset);
+                self.skip -= skip;
+                offset += skip;
+                continue;
+            }
+
+            // Buffer samples until a frame is complete.
+            let len = (size - self.pending[0].len()).min(buf.frames() - offset);
+
+            for (ch, pending) in self.pending.iter_mut().enumerate() {
+                pending.extend_from_slice(&buf.chan(ch)[offset..offset + len]);
+            }
+
+            offset += len;
+
+            if self.pending[0].len() == size {
+                self.analyze_frame(&mut f);
+            }
+        }
+    }
+
+    /// Clears all buffered samples.
+    pub fn reset(&mut self) {
+        for pending in self.pending.iter_mut() {
+            pending.clear();
+        }
+
+        self.skip = 0;
+    }
+
+    fn analyze_frame<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&[f32]),
+    {
+        for power in self.power.iter_mut() {
+            *power = 0.0;
+        }
+
+        for pending in self.pending.iter() {
+            for ((x, &s), &w) in self.frame.iter_mut().zip(pending).zip(&self.window) {
+                *x = s * w;
+            }
+
+            self.fft.fft(&self.frame, &mut self.spectrum);
+
+            for (power, bin) in self.power.iter_mut().zip(&self.spectrum) {
+                *power += bin.norm_sqr();
+            }
+        }
+
+        let n_channels = self.pending.len() as f32;
+
+        for power in self.power.iter_mut() {
+            *power = self.scale * (*power / n_channels).sqrt();
+        }
+
+        f(&self.power);
+
+        // Advance to the next frame.
+        let size = self.fft.size();
+
+        for pending in self.pending.iter_mut() {
+            pending.drain(..self.hop.min(size));
+        }
+
+        self.skip = self.hop.saturating_sub(size);
+    }
+}
+
+/// Finds the cutoff bin of a magnitude spectrum. The cutoff bin is the highest frequency bin
+/// with a magnitude no more than `threshold` decibels below the peak magnitude of the spectrum.
+///
+/// The spectrum should be averaged over many frames to be robust to the content of the signal.
+/// Returns `None` if the spectrum is silent.
+pub fn find_cutoff(spectrum: &[f32], threshold: f32) -> Option<usize> {
+    let peak = spectrum.iter().cloned().fold(0.0, f32::max);
+
+    if peak <= 0.0 {
+        return None;
+    }
+
+    let min_magnitude = peak * 10f32.powf(-threshold.abs() / 20.0);
+
+    spectrum.iter().rposition(|&m| m >= min_magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+
+    use crate::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+    use super::super::window::Window;
+    use super::{find_cutoff, Stft};
+
+    #[test]
+    fn verify_stft() {
+        let spec = SignalSpec::new(48_000, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+
+        // A 3 kHz sinusoid with an amplitude of 0.5, in 3 buffers of unequal length.
+        let mut bufs = Vec::new();
+        let mut t = 0;
+
+        for &len in &[1000, 3000, 2000] {
+            let mut buf = AudioBuffer::<f32>::new(len, spec);
+            buf.render_reserved(None);
+
+            for ch in 0..2 {
+                for (i, s) in buf.chan_mut(ch).iter_mut().enumerate() {
+                    let theta = 2.0 * f32::consts::PI * 3000.0 * (t + i) as f32 / 48_000.0;
+                    *s = 0.5 * theta.sin();
+                }
+            }
+
+            t += len as usize;
+            bufs.push(buf);
+        }
+
+        let mut stft = Stft::new(spec, 1024, 512, Window::Hann);
+        assert_eq!(stft.num_bins(), 513);
+        assert_eq!(stft.bin_frequency(64), 3000.0);
+
+        let mut n_frames = 0;
+
+        for buf in &bufs {
+            stft.process(buf, |spectrum| {
+                let peak = spectrum.iter().cloned().fold(0.0, f32::max);
+
+                assert!((spectrum[64] - 0.5).abs() < 1e-3);
+                assert_eq!(spectrum[64], peak);
+                assert_eq!(find_cutoff(spectrum, 20.0), Some(65));
+
+                n_frames += 1;
+            });
+        }
+
+        assert_eq!(n_frames, (6000 - 1024) / 512 + 1);
+
+        // With a hop larger than the frame size, samples between frames are skipped.
+        let mut stft = Stft::new(spec, 1024, 2500, Window::Hann);
+        let mut n_frames = 0;
+
+        for buf in &bufs {
+            stft.process(buf, |_| n_frames += 1);
+        }
+
+        assert_eq!(n_frames, 2);
+    }
+}
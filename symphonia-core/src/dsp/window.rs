@@ -0,0 +1,63 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `window` module implements standard window functions for spectral analysis.
+
+use std::f64;
+
+/// A window function.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Window {
+    /// The rectangular window. The signal is not modified.
+    Rectangular,
+    /// The Hann window.
+    #[default]
+    Hann,
+    /// The Hamming window.
+    Hamming,
+    /// The Blackman window.
+    Blackman,
+    /// The 4-term Blackman-Harris window.
+    BlackmanHarris,
+}
+
+impl Window {
+    /// Gets the cosine series coefficients of the window.
+    fn cosine_terms(self) -> &'static [f64] {
+        match self {
+            Window::Rectangular => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::Blackman => &[0.42, 0.5, 0.08],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+        }
+    }
+
+    /// Generates the `n` coefficients of the window.
+    ///
+    /// The window is periodic, rather than symmetric, as is appropriate for spectral analysis.
+    pub fn generate(self, n: usize) -> Vec<f32> {
+        let terms = self.cosine_terms();
+
+        (0..n)
+            .map(|i| {
+                let theta = 2.0 * f64::consts::PI * i as f64 / n as f64;
+
+                let w = terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * theta).cos()
+                    })
+                    .sum::<f64>();
+
+                w as f32
+            })
+            .collect()
+    }
+}
@@ -43,6 +43,8 @@ pub enum Error {
     IoError(std::io::Error),
     /// The stream contained malformed data and could not be decoded or demuxed.
     DecodeError(&'static str),
+    /// The provided data is invalid and could not be encoded or muxed.
+    EncodeError(&'static str),
     /// The stream could not be seeked.
     SeekError(SeekErrorKind),
     /// An unsupported container or codec feature was encounted.
@@ -61,6 +63,9 @@ impl fmt::Display for Error {
             Error::DecodeError(msg) => {
                 write!(f, "malformed stream: {}", msg)
             },
+            Error::EncodeError(msg) => {
+                write!(f, "invalid input: {}", msg)
+            },
             Error::SeekError(ref kind) => {
                 write!(f, "seek error: {}", kind.as_str())
             }
@@ -82,6 +87,7 @@ impl std::error::Error for Error {
         match *self {
             Error::IoError(ref err) => Some(err),
             Error::DecodeError(_) => None,
+            Error::EncodeError(_) => None,
             Error::SeekError(_) => None,
             Error::Unsupported(_) => None,
             Error::LimitError(_) => None,
@@ -103,6 +109,11 @@ pub fn decode_error<T>(desc: &'static str) -> Result<T> {
     Err(Error::DecodeError(desc))
 }
 
+/// Convenience function to create an encode error.
+pub fn encode_error<T>(desc: &'static str) -> Result<T> {
+    Err(Error::EncodeError(desc))
+}
+
 /// Convenience function to create a seek error.
 pub fn seek_error<T>(kind: SeekErrorKind) -> Result<T> {
     Err(Error::SeekError(kind))
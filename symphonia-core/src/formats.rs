@@ -6,12 +6,12 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! The `format` module provides the traits and support structures necessary to implement media
-//! demuxers.
+//! demuxers and muxers.
 
 use crate::codecs::CodecParameters;
 use crate::errors::Result;
-use crate::io::{BufReader, MediaSourceStream};
-use crate::meta::{Metadata, Tag};
+use crate::io::{BufReader, MediaSink, MediaSourceStream, SharedBuf};
+use crate::meta::{Metadata, MetadataRevision, Tag};
 use crate::units::{Time, TimeStamp};
 
 pub mod prelude {
@@ -23,6 +23,7 @@ pub mod prelude {
         Cue,
         FormatOptions,
         FormatReader,
+        FormatWriter,
         Packet,
         SeekedTo,
         SeekMode,
@@ -206,24 +207,72 @@ pub trait FormatReader: Send {
     fn into_inner(self: Box<Self>) -> MediaSourceStream;
 }
 
+/// A `FormatWriter` is a container muxer. It is the counterpart of a `FormatReader`, and writes the
+/// `Packet`s of one or more tracks into a media container.
+///
+/// All the tracks, and any metadata, to be written must be provided when the `FormatWriter` is
+/// instantiated. Packets are then written one-by-one, and must be in the order in which they should
+/// appear in the container. Once all packets are written, the `FormatWriter` must be finalized to
+/// complete the container.
+pub trait FormatWriter: Send {
+    /// Attempt to instantiate a `FormatWriter` that will write the provided `Track`s, and
+    /// optionally a metadata revision, to the `MediaSink`. If the container does not support one
+    /// or more of the tracks, an error is returned.
+    fn try_new(
+        sink: Box<dyn MediaSink>,
+        tracks: &[Track],
+        metadata: Option<&MetadataRevision>,
+    ) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Writes a `Packet` to the container. The track the packet belongs to is identified by the
+    /// packet's track ID.
+    fn write_packet(&mut self, packet: &Packet) -> Result<()>;
+
+    /// Updates the `CodecParameters` of a track. Some parameters, such as the total number of
+    /// frames, or a checksum of the audio, are only known once the track's `Encoder` is finalized.
+    /// If the container stores these parameters, they are written when the `FormatWriter` is
+    /// finalized. By default, the updated parameters are ignored.
+    fn update_codec_params(&mut self, _track_id: u32, _params: &CodecParameters) -> Result<()> {
+        Ok(())
+    }
+
+    /// Finalizes the container. Any headers that depend on the amount of media written are
+    /// updated if the `MediaSink` is seekable. No packets may be written after finalization.
+    fn finalize(&mut self) -> Result<()>;
+
+    /// Destroys the `FormatWriter` and returns the underlying sink.
+    fn into_inner(self: Box<Self>) -> Box<dyn MediaSink>;
+}
+
 /// A `Packet` contains a discrete amount of encoded data for a single codec bitstream. The exact
 /// amount of data is bounded, but not defined, and is dependant on the container and/or the
 /// encapsulated codec.
+///
+/// The packet data is stored in a reference-counted [`SharedBuf`]. Therefore, cloning a `Packet`,
+/// or creating a `Packet` from a part of a larger buffer, does not copy the packet data.
+#[derive(Clone, Debug)]
 pub struct Packet {
     track_id: u32,
     pts: u64,
     dur: u64,
-    data: Box<[u8]>,
+    data: SharedBuf,
 }
 
 impl Packet {
-    /// Create a new `Packet` from a slice.
+    /// Create a new `Packet` from a slice. The slice is copied.
     pub fn new_from_slice(track_id: u32, pts: u64, dur: u64, buf: &[u8]) -> Self {
-        Packet { track_id, pts, dur, data: Box::from(buf) }
+        Packet { track_id, pts, dur, data: SharedBuf::from(buf) }
     }
 
     /// Create a new `Packet` from a boxed slice.
     pub fn new_from_boxed_slice(track_id: u32, pts: u64, dur: u64, data: Box<[u8]>) -> Self {
+        Packet { track_id, pts, dur, data: SharedBuf::from(data) }
+    }
+
+    /// Create a new `Packet` from a shared buffer.
+    pub fn new_from_shared_buf(track_id: u32, pts: u64, dur: u64, data: SharedBuf) -> Self {
         Packet { track_id, pts, dur, data }
     }
 
@@ -247,6 +296,12 @@ impl Packet {
         &self.data
     }
 
+    /// Get the shared buffer backing the packet. The buffer may be cloned or sliced without copying
+    /// the packet data.
+    pub fn shared_buf(&self) -> &SharedBuf {
+        &self.data
+    }
+
     /// Get a `BufStream` to read the packet data buffer sequentially.
     pub fn as_buf_reader(&self) -> BufReader {
         BufReader::new(&self.data)
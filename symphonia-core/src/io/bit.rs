@@ -903,6 +903,123 @@ impl<'a> FiniteBitStream for BitReaderLtr<'a> {
     }
 }
 
+/// `BitWriterLtr` writes bits from most-significant to least-significant into a growable byte
+/// buffer.
+///
+/// Stated another way, if N-bits are written to a `BitWriterLtr` then bit 0, the first bit
+/// written, is the most-significant bit, and bit N-1, the last bit written, is the
+/// least-significant. It is the counterpart of `BitReaderLtr`.
+#[derive(Default)]
+pub struct BitWriterLtr {
+    buf: Vec<u8>,
+    bits: u64,
+    n_bits: u32,
+}
+
+impl BitWriterLtr {
+    /// Instantiate a new, empty, `BitWriterLtr`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Instantiate a new, empty, `BitWriterLtr` with space reserved for `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        BitWriterLtr {
+            buf: Vec::with_capacity(capacity),
+            bits: 0,
+            n_bits: 0,
+        }
+    }
+
+    /// Moves all whole bytes from the bit accumulator into the byte buffer.
+    #[inline(always)]
+    fn flush_bytes(&mut self) {
+        while self.n_bits >= 8 {
+            self.n_bits -= 8;
+            self.buf.push((self.bits >> self.n_bits) as u8);
+        }
+    }
+
+    /// Writes a single bit.
+    #[inline(always)]
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write_bits_leq32(u32::from(bit), 1);
+    }
+
+    /// Writes the `bit_width` least-significant bits of `bits`. `bit_width` must be <= 32.
+    #[inline(always)]
+    pub fn write_bits_leq32(&mut self, bits: u32, bit_width: u32) {
+        debug_assert!(bit_width <= u32::BITS);
+
+        if bit_width > 0 {
+            let mask = !0u64 >> (u64::BITS - bit_width);
+            self.bits = (self.bits << bit_width) | (u64::from(bits) & mask);
+            self.n_bits += bit_width;
+            self.flush_bytes();
+        }
+    }
+
+    /// Writes the `bit_width` least-significant bits of `bits`. `bit_width` must be <= 64.
+    pub fn write_bits_leq64(&mut self, bits: u64, bit_width: u32) {
+        debug_assert!(bit_width <= u64::BITS);
+
+        if bit_width > u32::BITS {
+            self.write_bits_leq32((bits >> u32::BITS) as u32, bit_width - u32::BITS);
+            self.write_bits_leq32(bits as u32, u32::BITS);
+        }
+        else {
+            self.write_bits_leq32(bits as u32, bit_width);
+        }
+    }
+
+    /// Writes `num` 0s followed by a terminating 1. This is the inverse of
+    /// `ReadBitsLtr::read_unary_zeros`.
+    pub fn write_unary_zeros(&mut self, mut num: u32) {
+        while num > u32::BITS {
+            self.write_bits_leq32(0, u32::BITS);
+            num -= u32::BITS;
+        }
+
+        // The zeros and the terminating 1 will not fit in one write if num is 32.
+        if num == u32::BITS {
+            self.write_bits_leq32(0, u32::BITS);
+            num = 0;
+        }
+
+        self.write_bits_leq32(1, num + 1);
+    }
+
+    /// Pads the bitstream with 0s until it is aligned to the next byte boundary. Does nothing if
+    /// the bitstream is already aligned.
+    pub fn realign(&mut self) {
+        if self.n_bits > 0 {
+            self.write_bits_leq32(0, 8 - self.n_bits);
+        }
+    }
+
+    /// Returns true if the bitstream is aligned to a byte boundary.
+    pub fn is_aligned(&self) -> bool {
+        self.n_bits == 0
+    }
+
+    /// Gets the total number of bits written.
+    pub fn bits_written(&self) -> u64 {
+        8 * self.buf.len() as u64 + u64::from(self.n_bits)
+    }
+
+    /// Gets all the whole bytes written so far. Any bits written after the last byte boundary are
+    /// not included.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Pads the bitstream to the next byte boundary, and returns the written bytes.
+    pub fn into_inner(mut self) -> Vec<u8> {
+        self.realign();
+        self.buf
+    }
+}
+
 /// `ReadBitsRtl` reads bits from least-significant to most-significant.
 pub trait ReadBitsRtl : private::FetchBitsRtl {
     /// Discards any saved bits and resets the `BitStream` to prepare it for a byte-aligned read.
@@ -1292,7 +1409,7 @@ impl<'a> FiniteBitStream for BitReaderRtl<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::{BitReaderLtr, ReadBitsLtr};
+    use super::{BitReaderLtr, BitWriterLtr, ReadBitsLtr};
     use super::{BitReaderRtl, ReadBitsRtl};
     use super::vlc::{BitOrder, Codebook, CodebookBuilder, Entry8x8};
 
@@ -1488,6 +1605,42 @@ mod tests {
         assert!(bs.read_unary_zeros().is_err());
     }
 
+    #[test]
+    fn verify_bitwriterltr() {
+        let mut bw = BitWriterLtr::new();
+
+        bw.write_bit(true);
+        bw.write_bits_leq32(0b0101, 4);
+        bw.write_bits_leq32(u32::MAX, 32);
+        bw.write_bits_leq64(0x1122334455667788, 64);
+        bw.write_unary_zeros(0);
+        bw.write_unary_zeros(7);
+        bw.write_unary_zeros(32);
+        bw.write_unary_zeros(63);
+        bw.write_bits_leq32(0x3, 0);
+
+        assert_eq!(bw.bits_written(), 1 + 4 + 32 + 64 + 1 + 8 + 33 + 64);
+        assert!(!bw.is_aligned());
+
+        let buf = bw.into_inner();
+
+        assert_eq!(buf.len(), 26);
+
+        let mut bs = BitReaderLtr::new(&buf);
+
+        assert!(bs.read_bit().unwrap());
+        assert_eq!(bs.read_bits_leq32(4).unwrap(), 0b0101);
+        assert_eq!(bs.read_bits_leq32(32).unwrap(), u32::MAX);
+        assert_eq!(bs.read_bits_leq64(64).unwrap(), 0x1122334455667788);
+        assert_eq!(bs.read_unary_zeros().unwrap(), 0);
+        assert_eq!(bs.read_unary_zeros().unwrap(), 7);
+        assert_eq!(bs.read_unary_zeros().unwrap(), 32);
+        assert_eq!(bs.read_unary_zeros().unwrap(), 63);
+
+        // The remaining bit is padding.
+        assert_eq!(bs.read_bits_leq32(1).unwrap(), 0);
+    }
+
     #[test]
     fn verify_bitstreamltr_read_unary_ones() {
         // General tests
@@ -27,12 +27,14 @@ mod buf_reader;
 mod media_source_stream;
 mod monitor_stream;
 mod scoped_stream;
+mod shared_buf;
 
 pub use bit::*;
 pub use buf_reader::BufReader;
 pub use media_source_stream::{MediaSourceStream, MediaSourceStreamOptions};
 pub use monitor_stream::{Monitor, MonitorStream};
 pub use scoped_stream::ScopedStream;
+pub use shared_buf::{BufPool, PooledBuf, SharedBuf};
 
 /// `MediaSource` is a composite trait of [`std::io::Read`] and [`std::io::Seek`]. A source *must*
 /// implement this trait to be used by [`MediaSourceStream`].
@@ -140,6 +142,87 @@ impl<R: io::Read> io::Seek for ReadOnlySource<R> {
     }
 }
 
+/// `MediaSink` is a composite trait of [`std::io::Write`] and [`std::io::Seek`]. A sink *must*
+/// implement this trait to be written to by a `FormatWriter`.
+///
+/// Despite requiring the [`std::io::Seek`] trait, seeking is an optional capability that can be
+/// queried at runtime. Writers use seeking to update headers once the amount of media written is
+/// known.
+pub trait MediaSink: io::Write + io::Seek + Send {
+    /// Returns if the sink is seekable. This may be an expensive operation.
+    fn is_seekable(&self) -> bool;
+}
+
+impl MediaSink for std::fs::File {
+    /// Returns if the `std::io::File` backing the `MediaSink` is seekable.
+    ///
+    /// Note: This operation involves querying the underlying file descriptor for information and
+    /// may be moderately expensive. Therefore it is recommended to cache this value if used often.
+    fn is_seekable(&self) -> bool {
+        match self.metadata() {
+            Ok(metadata) => metadata.is_file(),
+            _ => false
+        }
+    }
+}
+
+impl MediaSink for io::Cursor<Vec<u8>> {
+    /// Always returns true since a `io::Cursor<Vec<u8>>` is always seekable.
+    fn is_seekable(&self) -> bool {
+        true
+    }
+}
+
+/// `WriteOnlySink` wraps any sink implementing [`std::io::Write`] in an unseekable [`MediaSink`].
+pub struct WriteOnlySink<W: io::Write> {
+    inner: W,
+}
+
+impl<W: io::Write + Send> WriteOnlySink<W> {
+    /// Instantiates a new `WriteOnlySink<W>` by taking ownership and wrapping the provided
+    /// `Write`r.
+    pub fn new(inner: W) -> Self {
+        WriteOnlySink { inner }
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwraps this `WriteOnlySink<W>`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: io::Write + Send> MediaSink for WriteOnlySink<W> {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+}
+
+impl<W: io::Write> io::Write for WriteOnlySink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: io::Write> io::Seek for WriteOnlySink<W> {
+    fn seek(&mut self, _: io::SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::Other, "sink does not support seeking"))
+    }
+}
+
 /// `ReadBytes` provides functions to read bytes and interpret them as little- or big-endian
 /// unsigned integers or floating-point values of standard widths.
 pub trait ReadBytes {
@@ -0,0 +1,281 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::{Arc, Mutex, Weak};
+
+/// The free list of a `BufPool`.
+struct FreeList {
+    bufs: Mutex<Vec<Vec<u8>>>,
+    max_bufs: usize,
+}
+
+impl FreeList {
+    /// Returns a buffer to the free list if there is space for it, or drops it otherwise.
+    fn release(&self, mut buf: Vec<u8>) {
+        if let Ok(mut bufs) = self.bufs.lock() {
+            if bufs.len() < self.max_bufs {
+                buf.clear();
+                bufs.push(buf);
+            }
+        }
+    }
+}
+
+/// The backing storage of a `SharedBuf`.
+struct Storage {
+    data: Vec<u8>,
+    pool: Option<Weak<FreeList>>,
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        // If the storage was allocated from a pool that still exists, return it to that pool for
+        // reuse.
+        if let Some(pool) = self.pool.take().and_then(|pool| pool.upgrade()) {
+            pool.release(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+/// `SharedBuf` is an immutable, reference-counted, byte buffer.
+///
+/// Cloning or slicing a `SharedBuf` does not copy the underlying data. Instead, each clone or
+/// slice is a view into the same storage, which is freed, or returned to the [`BufPool`] it was
+/// allocated from, once the last view is dropped.
+#[derive(Clone)]
+pub struct SharedBuf {
+    storage: Arc<Storage>,
+    start: usize,
+    end: usize,
+}
+
+impl SharedBuf {
+    fn new(data: Vec<u8>, pool: Option<Weak<FreeList>>) -> Self {
+        let end = data.len();
+        SharedBuf { storage: Arc::new(Storage { data, pool }), start: 0, end }
+    }
+
+    /// Gets the length of the buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns `true` if the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns a new `SharedBuf` that is a view of `range` bytes of this buffer. The data is not
+    /// copied. Panics if `range` is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> SharedBuf {
+        assert!(range.start <= range.end && range.end <= self.len(), "range out of bounds");
+
+        SharedBuf {
+            storage: Arc::clone(&self.storage),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        }
+    }
+}
+
+impl Deref for SharedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.storage.data[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for SharedBuf {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for SharedBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedBuf").field("len", &self.len()).finish()
+    }
+}
+
+impl From<Vec<u8>> for SharedBuf {
+    fn from(data: Vec<u8>) -> Self {
+        SharedBuf::new(data, None)
+    }
+}
+
+impl From<Box<[u8]>> for SharedBuf {
+    fn from(data: Box<[u8]>) -> Self {
+        SharedBuf::new(data.into_vec(), None)
+    }
+}
+
+impl From<&[u8]> for SharedBuf {
+    fn from(data: &[u8]) -> Self {
+        SharedBuf::new(data.to_vec(), None)
+    }
+}
+
+/// `BufPool` is a pool of reusable byte buffers.
+///
+/// A buffer obtained from the pool is writable until it is frozen into a [`SharedBuf`]. When the
+/// last view of the `SharedBuf` is dropped, the allocation is returned to the pool instead of being
+/// freed. This avoids an allocation per packet when demuxing at high packet rates.
+///
+/// Cloning a `BufPool` returns a new handle to the same pool.
+#[derive(Clone)]
+pub struct BufPool {
+    free: Arc<FreeList>,
+}
+
+impl BufPool {
+    /// The default maximum number of free buffers retained by a pool.
+    const DEFAULT_MAX_BUFS: usize = 16;
+
+    /// Instantiate a new `BufPool` that retains up-to `max_bufs` free buffers for reuse.
+    pub fn new(max_bufs: usize) -> Self {
+        BufPool { free: Arc::new(FreeList { bufs: Mutex::new(Vec::new()), max_bufs }) }
+    }
+
+    /// Gets a zero-filled buffer of `len` bytes from the pool. A new buffer is allocated if the
+    /// pool has no free buffers.
+    pub fn get(&self, len: usize) -> PooledBuf {
+        let free = self.free.bufs.lock().ok().and_then(|mut bufs| bufs.pop());
+
+        let mut data = free.unwrap_or_default();
+
+        data.resize(len, 0);
+
+        PooledBuf { data, pool: Arc::downgrade(&self.free) }
+    }
+
+    /// Gets the number of free buffers in the pool.
+    pub fn num_free(&self) -> usize {
+        self.free.bufs.lock().map(|bufs| bufs.len()).unwrap_or(0)
+    }
+}
+
+impl Default for BufPool {
+    fn default() -> Self {
+        BufPool::new(BufPool::DEFAULT_MAX_BUFS)
+    }
+}
+
+impl fmt::Debug for BufPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufPool").field("num_free", &self.num_free()).finish()
+    }
+}
+
+/// `PooledBuf` is a writable byte buffer obtained from a [`BufPool`].
+///
+/// Once written, a `PooledBuf` may be frozen into an immutable [`SharedBuf`]. A `PooledBuf` that
+/// is dropped without being frozen is returned to the pool immediately.
+pub struct PooledBuf {
+    data: Vec<u8>,
+    pool: Weak<FreeList>,
+}
+
+impl PooledBuf {
+    /// Shortens the buffer to `len` bytes. Has no effect if `len` is greater than the current
+    /// length of the buffer.
+    pub fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+    }
+
+    /// Converts the buffer into an immutable `SharedBuf` without copying.
+    pub fn freeze(mut self) -> SharedBuf {
+        let data = std::mem::take(&mut self.data);
+        let pool = std::mem::take(&mut self.pool);
+
+        SharedBuf::new(data, Some(pool))
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        // A frozen buffer leaves behind an empty allocation that is not worth returning.
+        if self.data.capacity() > 0 {
+            if let Some(pool) = self.pool.upgrade() {
+                pool.release(std::mem::take(&mut self.data));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufPool, SharedBuf};
+
+    #[test]
+    fn verify_shared_buf_slice() {
+        let buf = SharedBuf::from(vec![0u8, 1, 2, 3, 4, 5, 6, 7]);
+
+        let a = buf.slice(2..6);
+        assert_eq!(&a[..], &[2, 3, 4, 5]);
+
+        let b = a.slice(1..3);
+        assert_eq!(&b[..], &[3, 4]);
+        assert!(b.slice(2..2).is_empty());
+
+        // All views share the same storage.
+        assert_eq!(a.as_ptr(), buf[2..].as_ptr());
+        assert_eq!(b.as_ptr(), buf[3..].as_ptr());
+    }
+
+    #[test]
+    fn verify_buf_pool_reuse() {
+        let pool = BufPool::new(1);
+
+        let mut buf = pool.get(16);
+        buf[0] = 1;
+        buf.truncate(8);
+
+        let shared = buf.freeze();
+        let view = shared.slice(0..4);
+        let ptr = shared.as_ptr();
+        assert_eq!(view.len(), 4);
+
+        // The buffer is returned to the pool only after the last view is dropped.
+        drop(shared);
+        assert_eq!(pool.num_free(), 0);
+        drop(view);
+        assert_eq!(pool.num_free(), 1);
+
+        // The next buffer reuses the allocation and is zero-filled.
+        let buf = pool.get(8);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert!(buf.iter().all(|&b| b == 0));
+
+        // The pool retains no more than the maximum number of free buffers.
+        let other = pool.get(8);
+        drop(buf);
+        drop(other);
+        assert_eq!(pool.num_free(), 1);
+
+        // Buffers outliving their pool are simply freed.
+        let buf = pool.get(8).freeze();
+        drop(pool);
+        drop(buf);
+    }
+}
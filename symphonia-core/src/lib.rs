@@ -4,7 +4,8 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
-#![forbid(unsafe_code)]
+// Unsafe code is denied, except in `dsp::simd` where it is required for SIMD intrinsics.
+#![deny(unsafe_code)]
 
 pub mod audio;
 pub mod checksum;
@@ -17,5 +18,6 @@ pub mod io;
 pub mod meta;
 pub mod probe;
 pub mod sample;
+pub mod track;
 pub mod units;
 pub mod util;
\ No newline at end of file
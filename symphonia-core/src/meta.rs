@@ -9,7 +9,7 @@
 
 use std::borrow::Cow;
 use std::collections::VecDeque;
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::fmt;
 use std::num::NonZeroU32;
 
@@ -172,6 +172,8 @@ pub enum StandardTagKey {
     PodcastKeywords,
     Producer,
     PurchaseDate,
+    R128AlbumGain,
+    R128TrackGain,
     Rating,
     ReleaseCountry,
     ReleaseDate,
@@ -339,6 +341,127 @@ impl fmt::Display for Tag {
     }
 }
 
+/// The ReplayGain reference level is 5 dB louder than the EBU R128 reference level of -23 LUFS.
+const R128_TO_REPLAYGAIN_OFFSET: f32 = 5.0;
+
+/// `ReplayGain` contains the loudness normalization information of a track.
+///
+/// Gains are in decibels relative to the ReplayGain reference level. Peaks are the largest absolute
+/// sample value of the track or album, where 1.0 is full scale.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+    /// The gain to apply to normalize the loudness of the track.
+    pub track_gain: Option<f32>,
+    /// The peak sample value of the track.
+    pub track_peak: Option<f32>,
+    /// The gain to apply to normalize the loudness of the album the track belongs to.
+    pub album_gain: Option<f32>,
+    /// The peak sample value of the album the track belongs to.
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Reads the ReplayGain information from a list of `Tag`s.
+    ///
+    /// In addition to the ReplayGain tags, the R128 gain tags used by Opus are recognized. R128
+    /// gains are relative to the EBU R128 reference level, and are converted to be relative to the
+    /// ReplayGain reference level. If both are present, the R128 gains are preferred.
+    pub fn from_tags(tags: &[Tag]) -> ReplayGain {
+        let mut replay_gain = ReplayGain::default();
+        let mut r128 = ReplayGain::default();
+
+        for tag in tags {
+            match tag.std_key {
+                Some(StandardTagKey::ReplayGainTrackGain) => {
+                    replay_gain.track_gain = parse_gain(&tag.value);
+                }
+                Some(StandardTagKey::ReplayGainTrackPeak) => {
+                    replay_gain.track_peak = parse_peak(&tag.value);
+                }
+                Some(StandardTagKey::ReplayGainAlbumGain) => {
+                    replay_gain.album_gain = parse_gain(&tag.value);
+                }
+                Some(StandardTagKey::ReplayGainAlbumPeak) => {
+                    replay_gain.album_peak = parse_peak(&tag.value);
+                }
+                Some(StandardTagKey::R128TrackGain) => {
+                    r128.track_gain = parse_r128_gain(&tag.value);
+                }
+                Some(StandardTagKey::R128AlbumGain) => {
+                    r128.album_gain = parse_r128_gain(&tag.value);
+                }
+                _ => (),
+            }
+        }
+
+        ReplayGain {
+            track_gain: r128.track_gain.or(replay_gain.track_gain),
+            album_gain: r128.album_gain.or(replay_gain.album_gain),
+            ..replay_gain
+        }
+    }
+
+    /// Returns a `ReplayGain` where any missing value is taken from `other`.
+    pub fn or(self, other: ReplayGain) -> ReplayGain {
+        ReplayGain {
+            track_gain: self.track_gain.or(other.track_gain),
+            track_peak: self.track_peak.or(other.track_peak),
+            album_gain: self.album_gain.or(other.album_gain),
+            album_peak: self.album_peak.or(other.album_peak),
+        }
+    }
+
+    /// Returns `true` if no gain or peak is known.
+    pub fn is_empty(&self) -> bool {
+        *self == ReplayGain::default()
+    }
+}
+
+/// Parses a floating point number from a `Value`, ignoring any trailing unit.
+fn parse_number(value: &Value, unit: &str) -> Option<f32> {
+    match value {
+        Value::Float(num) => Some(*num as f32),
+        Value::SignedInt(num) => Some(*num as f32),
+        Value::UnsignedInt(num) => Some(*num as f32),
+        Value::String(string) => {
+            let string = string.trim();
+
+            let end = string.len().saturating_sub(unit.len());
+
+            let string = match string.get(end..) {
+                Some(suffix) if suffix.eq_ignore_ascii_case(unit) => &string[..end],
+                _ => string,
+            };
+
+            string.trim_end().parse::<f32>().ok()
+        }
+        _ => None,
+    }
+    .filter(|num| num.is_finite())
+}
+
+/// Parses a ReplayGain gain value, such as "-6.50 dB".
+fn parse_gain(value: &Value) -> Option<f32> {
+    parse_number(value, "dB")
+}
+
+/// Parses a ReplayGain peak value, such as "0.988".
+fn parse_peak(value: &Value) -> Option<f32> {
+    parse_number(value, "").filter(|&peak| peak > 0.0)
+}
+
+/// Parses an R128 gain value, a Q7.8 fixed-point number of decibels, such as "-1536".
+fn parse_r128_gain(value: &Value) -> Option<f32> {
+    let gain = match value {
+        Value::SignedInt(num) => i16::try_from(*num).ok(),
+        Value::UnsignedInt(num) => i16::try_from(*num).ok(),
+        Value::String(string) => string.trim().parse::<i16>().ok(),
+        _ => None,
+    }?;
+
+    Some(f32::from(gain) / 256.0 + R128_TO_REPLAYGAIN_OFFSET)
+}
+
 /// A 2 dimensional (width and height) size type.
 #[derive(Copy, Clone)]
 pub struct Size {
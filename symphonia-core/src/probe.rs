@@ -8,12 +8,12 @@
 //! The `probe` module provides methods and traits to support auto-detection of media formats from
 //! arbitrary media streams.
 
-use crate::errors::{Result, unsupported_error};
+use crate::errors::{Result, limit_error, unsupported_error};
 use crate::formats::{FormatOptions, FormatReader};
 use crate::io::{ReadBytes, MediaSourceStream};
 use crate::meta::{MetadataReader, MetadataOptions, MetadataLog, Metadata};
 
-use log::{error, info};
+use log::{debug, error, info};
 
 mod bloom {
 
@@ -119,6 +119,10 @@ pub trait QueryDescriptor {
 
     /// Using the provided context buffer, score calculate and returns a value between 0 and 255
     /// indicating the confidence of the reader in decoding or parsing the source stream.
+    ///
+    /// The context buffer starts at the matched marker and contains a window of the stream that
+    /// follows it. The window may be truncated if the end of the stream was reached. A score of 0
+    /// indicates that the reader cannot decode or parse the stream at all.
     fn score(context: &[u8]) -> u8;
 }
 
@@ -154,6 +158,25 @@ impl Hint {
         self.mime_type = Some(mime_type.to_owned());
         self
     }
+
+    /// Counts the number of hints that match the provided `Descriptor`.
+    fn matches(&self, descriptor: &Descriptor) -> u8 {
+        let mut count = 0;
+
+        if let Some(extension) = &self.extension {
+            if descriptor.extensions.iter().any(|ext| ext.eq_ignore_ascii_case(extension)) {
+                count += 1;
+            }
+        }
+
+        if let Some(mime_type) = &self.mime_type {
+            if descriptor.mime_types.iter().any(|mime| mime.eq_ignore_ascii_case(mime_type)) {
+                count += 1;
+            }
+        }
+
+        count
+    }
 }
 
 /// Metadata that came from the `metadata` field of [`ProbeResult`].
@@ -207,6 +230,9 @@ impl Default for Probe {
 impl Probe {
     const PROBE_SEARCH_LIMIT: u64 = 1 * 1024 * 1024;
 
+    /// The maximum length of the context buffer provided to the scoring function of a candidate.
+    const PROBE_SCORE_WINDOW_LEN: usize = 8 * 1024;
+
     /// Register all `Descriptor`s supported by the parameterized type.
     pub fn register_all<Q: QueryDescriptor>(&mut self) {
         for descriptor in Q::query() {
@@ -231,8 +257,70 @@ impl Probe {
         self.registered.push(*descriptor);
     }
 
-    /// Searches the provided `MediaSourceStream` for metadata or a container format.
-    pub fn next(&self, mss: &mut MediaSourceStream) -> Result<Instantiate> {
+    /// Reads the scoring context buffer starting from the current position of the
+    /// `MediaSourceStream`. The stream is rewound to its original position afterwards. An error is
+    /// returned if the window could not be rewound because it is no longer buffered.
+    fn read_score_window(mss: &mut MediaSourceStream) -> Result<Vec<u8>> {
+        let mut window = vec![0; Probe::PROBE_SCORE_WINDOW_LEN];
+        let mut len = 0;
+
+        // The window is truncated if the end of the stream is reached.
+        while len < window.len() {
+            match mss.read_buf(&mut window[len..]) {
+                Ok(read) => len += read,
+                _ => break,
+            }
+        }
+
+        // Rewinding is clamped to the buffered data, therefore the entire window must still be
+        // buffered to return to the original position.
+        if len > mss.read_buffer_len() {
+            return limit_error("probe: score window exceeds the stream's seekback buffer");
+        }
+
+        mss.rewind(len);
+
+        window.truncate(len);
+        Ok(window)
+    }
+
+    /// Scores all candidate `Descriptor`s using the provided context buffer, and returns the
+    /// candidate with the highest score. Candidates with equal scores are ranked by the number of
+    /// matching hints, and then by the order of registration. Candidates that score 0 are rejected.
+    fn select<'a>(
+        candidates: &[&'a Descriptor],
+        context: &[u8],
+        hint: &Hint,
+    ) -> Option<&'a Descriptor> {
+        let mut best: Option<(&Descriptor, (u8, u8))> = None;
+
+        for &candidate in candidates {
+            let score = (candidate.score)(context);
+
+            debug!("candidate {} scored {}.", candidate.short_name, score);
+
+            if score == 0 {
+                continue;
+            }
+
+            let rank = (score, hint.matches(candidate));
+
+            let is_better = match best {
+                Some((_, best_rank)) => rank > best_rank,
+                None => true,
+            };
+
+            if is_better {
+                best = Some((candidate, rank));
+            }
+        }
+
+        best.map(|(descriptor, _)| descriptor)
+    }
+
+    /// Searches the provided `MediaSourceStream` for the `Descriptor` of the best matching
+    /// metadata or container format.
+    fn search(&self, mss: &mut MediaSourceStream, hint: &Hint) -> Result<&Descriptor> {
         let mut win = 0u16;
 
         let init_pos = mss.pos();
@@ -269,34 +357,40 @@ impl Probe {
                     count,
                 );
 
-                // Search for registered markers in the 16-byte window.
-                for registered in &self.registered {
-                    for marker in registered.markers {
-                        let len = marker.len();
-
-                        // If a match is found, return the instantiate.
-                        if context[0..len] == **marker {
-                            // Re-align the stream to the start of the marker.
-                            mss.rewind(16);
-
-                            // TODO: Implement scoring.
-
-                            info!(
-                                "found the stream marker {:x?} @ {}+{} bytes.",
-                                &context[0..len],
-                                init_pos,
-                                count,
-                            );
-
-                            return Ok(registered.inst)
-                        }
+                // Gather all registered descriptors with a marker in the 16-byte window.
+                let candidates = self.registered
+                    .iter()
+                    .filter(|registered| {
+                        registered.markers.iter().any(|marker| context[..marker.len()] == **marker)
+                    })
+                    .collect::<Vec<&Descriptor>>();
+
+                // Re-align the stream to the start of the marker.
+                mss.rewind(16);
+
+                if !candidates.is_empty() {
+                    // Score each candidate using a window of the stream starting at the marker.
+                    let window = Probe::read_score_window(mss)?;
+
+                    if let Some(descriptor) = Probe::select(&candidates, &window, hint) {
+                        info!(
+                            "found the stream marker {:x?} @ {}+{} bytes for {}.",
+                            &context[0..2],
+                            init_pos,
+                            count,
+                            descriptor.short_name,
+                        );
+
+                        return Ok(descriptor);
                     }
+
+                    info!("all candidates rejected the stream marker, continuing search.");
                 }
 
-                // If no registered markers were matched, then the bloom filter returned a false
-                // positive. Re-align the stream to the end of the 2-byte window and continue the
-                // search.
-                mss.rewind(16 - 2);
+                // Either no registered markers were matched and the bloom filter returned a false
+                // positive, or all candidates rejected the stream. Re-align the stream to the end
+                // of the 2-byte window and continue the search.
+                mss.seek_buffered_rel(2);
             }
         }
 
@@ -306,12 +400,25 @@ impl Probe {
         unsupported_error("no suitable reader found")
     }
 
+    /// Searches the provided `MediaSourceStream` for metadata or a container format. If more than
+    /// one registered reader supports a stream marker, the reader that scores the stream the
+    /// highest is selected.
+    pub fn next(&self, mss: &mut MediaSourceStream) -> Result<Instantiate> {
+        self.next_with_hint(mss, &Hint::new())
+    }
+
+    /// Like [`Probe::next`], but the provided `Hint` is used to break ties between readers that
+    /// score the stream equally.
+    pub fn next_with_hint(&self, mss: &mut MediaSourceStream, hint: &Hint) -> Result<Instantiate> {
+        Ok(self.search(mss, hint)?.inst)
+    }
+
     /// Searches the provided `MediaSourceStream` for a container format. Any metadata that is read
     /// during the search will be queued and attached to the `FormatReader` instance once a
     /// container format is found.
     pub fn format(
         &self,
-        _hint: &Hint,
+        hint: &Hint,
         mut mss: MediaSourceStream,
         format_opts: &FormatOptions,
         metadata_opts: &MetadataOptions,
@@ -321,7 +428,7 @@ impl Probe {
 
         // Loop over all elements in the stream until a container format is found.
         loop {
-            match self.next(&mut mss)? {
+            match self.next_with_hint(&mut mss, hint)? {
                 // If a container format is found, return an instance to it's reader.
                 Instantiate::Format(fmt) => {
                     let format = fmt(mss, format_opts)?;
@@ -385,3 +492,88 @@ macro_rules! support_metadata {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Descriptor, Hint, Instantiate, Probe};
+    use crate::errors::unsupported_error;
+    use crate::io::{MediaSourceStream, MediaSourceStreamOptions, ReadBytes};
+
+    fn descriptor(
+        short_name: &'static str,
+        extensions: &'static [&'static str],
+        score: fn(&[u8]) -> u8,
+    ) -> Descriptor {
+        Descriptor {
+            short_name,
+            long_name: short_name,
+            extensions,
+            mime_types: &[],
+            markers: &[b"TEST"],
+            score,
+            inst: Instantiate::Format(|_, _| unsupported_error("test")),
+        }
+    }
+
+    fn search(probe: &Probe, buf: &[u8], hint: &Hint) -> (&'static str, u64) {
+        search_with_options(probe, buf, hint, Default::default())
+    }
+
+    fn search_with_options(
+        probe: &Probe,
+        buf: &[u8],
+        hint: &Hint,
+        options: MediaSourceStreamOptions,
+    ) -> (&'static str, u64) {
+        let source = Box::new(Cursor::new(buf.to_vec()));
+        let mut mss = MediaSourceStream::new(source, options);
+        let descriptor = probe.search(&mut mss, hint).unwrap();
+        (descriptor.short_name, mss.pos())
+    }
+
+    #[test]
+    fn verify_probe_scoring() {
+        let mut buf = b"junkTEST?junkjunkjunkjunkTEST!".to_vec();
+        buf.resize(64, 0);
+
+        // A candidate that rejects the first marker.
+        let mut probe = Probe::default();
+        probe.register(&descriptor("a", &[], |ctx| if ctx[4] == b'!' { 100 } else { 0 }));
+        assert_eq!(search(&probe, &buf, &Hint::new()), ("a", 25));
+
+        // The highest scoring candidate is selected.
+        probe.register(&descriptor("b", &["b"], |_| 200));
+        assert_eq!(search(&probe, &buf, &Hint::new()), ("b", 4));
+
+        // Equally scored candidates are ranked by hints, then by registration order.
+        let mut probe = Probe::default();
+        probe.register(&descriptor("a", &["a"], |_| 100));
+        probe.register(&descriptor("b", &["b"], |_| 100));
+        assert_eq!(search(&probe, &buf, &Hint::new()), ("a", 4));
+        assert_eq!(search(&probe, &buf, Hint::new().with_extension("B")), ("b", 4));
+    }
+
+    #[test]
+    fn verify_probe_score_window_rewind() {
+        // Place markers such that the score window straddles read blocks and wraps around the ring
+        // buffer of the smallest allowed stream buffer.
+        for &offset in &[1020, 32 * 1024 - 2, 60 * 1024, 63 * 1024 + 1, 100 * 1024] {
+            let mut buf = vec![0; 128 * 1024];
+            buf[offset..offset + 5].copy_from_slice(b"TEST!");
+
+            // The candidate only accepts a full-length window starting at the marker, and the
+            // stream must be returned to the start of the marker.
+            let mut probe = Probe::default();
+            probe.register(&descriptor("a", &[], |ctx| {
+                let is_full = ctx.len() == Probe::PROBE_SCORE_WINDOW_LEN;
+                if is_full && &ctx[..5] == b"TEST!" { 1 } else { 0 }
+            }));
+
+            let options = MediaSourceStreamOptions { buffer_len: 64 * 1024 };
+            let result = search_with_options(&probe, &buf, &Hint::new(), options);
+            assert_eq!(result, ("a", offset as u64));
+        }
+    }
+}
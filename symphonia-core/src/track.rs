@@ -0,0 +1,594 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The `track` module provides a `TrackDecoder` that pairs a `FormatReader` with a `Decoder` to
+//! decode a single track, and seek within it, with audio frame accuracy.
+
+use std::cmp::max;
+
+use crate::audio::{AudioBufferMut, AudioBufferRef};
+use crate::codecs::{CodecParameters, CodecRegistry, Decoder, DecoderOptions};
+use crate::codecs::{CODEC_TYPE_AAC, CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3};
+use crate::codecs::{CODEC_TYPE_OPUS, CODEC_TYPE_VORBIS};
+use crate::errors::{Error, Result, SeekErrorKind, seek_error, unsupported_error};
+use crate::formats::{FormatReader, Packet, SeekMode, SeekTo, SeekedTo};
+use crate::units::{TimeBase, TimeStamp};
+
+use log::{debug, warn};
+
+/// Gets the number of audio frames that must be decoded, and discarded, before the audio frames
+/// decoded after a seek are valid.
+///
+/// Codecs with overlapping transforms require at least the preceding packet to be decoded to
+/// reconstruct the first frames of a packet.
+fn seek_preroll(params: &CodecParameters) -> u64 {
+    match params.codec {
+        CODEC_TYPE_MP1 | CODEC_TYPE_MP2 | CODEC_TYPE_MP3 => 1152,
+        CODEC_TYPE_AAC => 1024,
+        CODEC_TYPE_VORBIS => 4096,
+        // The Ogg Opus specification recommends a pre-roll of at least 80ms.
+        CODEC_TYPE_OPUS => 3840,
+        _ => 0,
+    }
+}
+
+/// `TrackDecoder` decodes the packets of a single track read from a `FormatReader`.
+///
+/// Unlike `FormatReader::seek`, which can only seek to the nearest packet, `TrackDecoder::seek`
+/// seeks to the exact audio frame requested. After a seek, the `TrackDecoder` decodes and discards
+/// any pre-roll required by the codec, and trims the first audio buffer it returns such that it
+/// starts at the requested audio frame.
+pub struct TrackDecoder {
+    reader: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    is_gapless: bool,
+    /// The numerator and denominator to convert a timestamp to an audio frame.
+    ts_scale: Option<(u128, u128)>,
+    /// If a seek is in-progress, the audio frame that was seeked to.
+    seek_frame: Option<u64>,
+}
+
+impl TrackDecoder {
+    /// Instantiates a `TrackDecoder` for the track identified by `track_id`. The `Decoder` is
+    /// instantiated from the `CodecRegistry` using the provided `DecoderOptions`.
+    pub fn try_new(
+        reader: Box<dyn FormatReader>,
+        track_id: u32,
+        codecs: &CodecRegistry,
+        options: &DecoderOptions,
+    ) -> Result<Self> {
+        let params = match reader.tracks().iter().find(|track| track.id == track_id) {
+            Some(track) => &track.codec_params,
+            None => return unsupported_error("track does not exist"),
+        };
+
+        let decoder = codecs.make(params, options)?;
+
+        // If the time base of the track is not the sample rate, then timestamps must be scaled to
+        // get audio frames.
+        let ts_scale = match (params.time_base, params.sample_rate) {
+            (Some(tb), Some(sample_rate)) if tb.numer != 1 || tb.denom != sample_rate => {
+                Some((u128::from(tb.numer) * u128::from(sample_rate), u128::from(tb.denom)))
+            }
+            _ => None,
+        };
+
+        Ok(TrackDecoder {
+            reader,
+            decoder,
+            track_id,
+            is_gapless: options.gapless,
+            ts_scale,
+            seek_frame: None,
+        })
+    }
+
+    /// Gets the ID of the track being decoded.
+    pub fn track_id(&self) -> u32 {
+        self.track_id
+    }
+
+    /// Gets an immutable reference to the `FormatReader`.
+    pub fn reader(&self) -> &dyn FormatReader {
+        self.reader.as_ref()
+    }
+
+    /// Gets a mutable reference to the `FormatReader`.
+    pub fn reader_mut(&mut self) -> &mut dyn FormatReader {
+        self.reader.as_mut()
+    }
+
+    /// Gets an immutable reference to the `Decoder`.
+    pub fn decoder(&self) -> &dyn Decoder {
+        self.decoder.as_ref()
+    }
+
+    /// Gets a mutable reference to the `Decoder`.
+    pub fn decoder_mut(&mut self) -> &mut dyn Decoder {
+        self.decoder.as_mut()
+    }
+
+    /// Destroys the `TrackDecoder` and returns the `FormatReader`.
+    pub fn into_reader(self) -> Box<dyn FormatReader> {
+        self.reader
+    }
+
+    fn ts_to_frame(&self, ts: TimeStamp) -> u64 {
+        match self.ts_scale {
+            Some((numer, denom)) => (u128::from(ts) * numer / denom) as u64,
+            None => ts,
+        }
+    }
+
+    fn frame_to_ts(&self, frame: u64) -> TimeStamp {
+        match self.ts_scale {
+            Some((numer, denom)) => (u128::from(frame) * denom / numer) as u64,
+            None => frame,
+        }
+    }
+
+    /// Seeks to the exact audio frame at the `Time` or track `TimeStamp` requested. The next
+    /// audio buffer returned by `next_buffer` will start at the requested audio frame.
+    ///
+    /// If gapless decoding is enabled, a `Time` is relative to the first audio frame after the
+    /// leading padding, whereas a `TimeStamp` always counts the leading padding.
+    pub fn seek(&mut self, to: SeekTo) -> Result<SeekedTo> {
+        let params = self.decoder.codec_params();
+
+        // The seek must be relative to the track being decoded.
+        let track_id = match to {
+            SeekTo::TimeStamp { track_id, .. } => track_id,
+            SeekTo::Time { track_id, .. } => track_id.unwrap_or(self.track_id),
+        };
+
+        if track_id != self.track_id {
+            return seek_error(SeekErrorKind::InvalidTrack);
+        }
+
+        let required_frame = match to {
+            SeekTo::TimeStamp { ts, .. } => self.ts_to_frame(ts),
+            SeekTo::Time { time, .. } => {
+                let sample_rate = match params.sample_rate {
+                    Some(sample_rate) => sample_rate,
+                    None => return seek_error(SeekErrorKind::Unseekable),
+                };
+
+                let frame = TimeBase::new(1, sample_rate).calc_timestamp(time);
+
+                if self.is_gapless {
+                    frame + u64::from(params.leading_padding.unwrap_or(0))
+                }
+                else {
+                    frame
+                }
+            }
+        };
+
+        // Seek the reader to a packet before the required audio frame such that the decoder has
+        // enough pre-roll to produce valid audio frames from the required audio frame onwards.
+        let preroll_frame = required_frame.saturating_sub(seek_preroll(params));
+
+        let seek_to = SeekTo::TimeStamp {
+            ts: self.frame_to_ts(preroll_frame),
+            track_id: self.track_id,
+        };
+
+        let seeked_to = self.reader.seek(SeekMode::Accurate, seek_to)?;
+
+        self.decoder.reset();
+
+        debug!(
+            "seeked track={} to frame={} via pre-roll frame={} (packet_ts={})",
+            self.track_id,
+            required_frame,
+            preroll_frame,
+            seeked_to.actual_ts,
+        );
+
+        // The reader should never seek past the required timestamp in accurate mode, but if it
+        // does, then the first frame returned will be later than requested.
+        let required_ts = self.frame_to_ts(required_frame);
+
+        self.seek_frame = Some(required_frame);
+
+        Ok(SeekedTo {
+            track_id: self.track_id,
+            required_ts,
+            actual_ts: max(required_ts, seeked_to.actual_ts),
+        })
+    }
+
+    /// Reads and decodes the next packet of the track, and returns the decoded audio. After a
+    /// seek, the returned audio starts at the exact audio frame seeked to.
+    ///
+    /// The first audio buffer returned after a seek is a copy of the frames from the seeked to
+    /// audio frame onwards. All other audio buffers are borrowed from the `Decoder`.
+    pub fn next_buffer(&mut self) -> Result<AudioBufferRef<'_>> {
+        loop {
+            let packet = self.next_packet()?;
+
+            let seek_frame = match self.seek_frame {
+                Some(seek_frame) => seek_frame,
+                None => return self.decoder.decode(&packet),
+            };
+
+            let first_frame = self.first_frame(&packet);
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    preroll_error(err)?;
+                    continue;
+                }
+            };
+
+            let n_frames = decoded.frames();
+
+            if first_frame + n_frames as u64 <= seek_frame {
+                continue;
+            }
+
+            self.seek_frame = None;
+
+            // The audio frames before the seeked to audio frame must be trimmed.
+            let n_trim = seek_frame.saturating_sub(first_frame) as usize;
+
+            return Ok(decoded.slice(n_trim..n_frames));
+        }
+    }
+
+    /// Reads and decodes the next packet of the track into a caller-provided audio buffer. The
+    /// contents of `dest` are replaced with the decoded audio, converted to the sample format of
+    /// `dest` if required. After a seek, the decoded audio starts at the exact audio frame seeked
+    /// to. See [`Decoder::decode_into`] for the requirements on `dest`.
+    pub fn next_buffer_into(&mut self, mut dest: AudioBufferMut<'_>) -> Result<()> {
+        loop {
+            let packet = self.next_packet()?;
+
+            let seek_frame = match self.seek_frame {
+                Some(seek_frame) => seek_frame,
+                None => return self.decoder.decode_into(&packet, dest),
+            };
+
+            let first_frame = self.first_frame(&packet);
+
+            if let Err(err) = self.decoder.decode_into(&packet, dest.reborrow()) {
+                preroll_error(err)?;
+                continue;
+            }
+
+            if first_frame + dest.frames() as u64 <= seek_frame {
+                continue;
+            }
+
+            self.seek_frame = None;
+
+            // The audio frames before the seeked to audio frame must be trimmed.
+            dest.trim(seek_frame.saturating_sub(first_frame) as usize, 0);
+
+            return Ok(());
+        }
+    }
+
+    /// Reads the next packet of the track, skipping the packets of all other tracks.
+    fn next_packet(&mut self) -> Result<Packet> {
+        loop {
+            let packet = self.reader.next_packet()?;
+
+            if packet.track_id() == self.track_id {
+                return Ok(packet);
+            }
+        }
+    }
+
+    /// Gets the first audio frame the `Decoder` will return for a packet.
+    fn first_frame(&self, packet: &Packet) -> u64 {
+        let first_frame = self.ts_to_frame(packet.pts());
+
+        // If gapless decoding is enabled, the decoder will have trimmed the leading padding.
+        if self.is_gapless {
+            let leading = self.decoder.codec_params().leading_padding.unwrap_or(0);
+            max(first_frame, u64::from(leading))
+        }
+        else {
+            first_frame
+        }
+    }
+}
+
+/// Handles an error that occured while decoding the pre-roll after a seek. Decode errors are
+/// expected while decoding the pre-roll, and are ignored. All other errors are returned.
+fn preroll_error(err: Error) -> Result<()> {
+    match err {
+        Error::DecodeError(err) => {
+            warn!("decode error during seek pre-roll: {}", err);
+            Ok(())
+        }
+        err => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::audio::{AsAudioBufferRef, AudioBuffer, AudioBufferMut, Channels, Signal, SignalSpec};
+    use crate::codecs::{CodecDescriptor, CodecType, FinalizeResult, CODEC_TYPE_PCM_S32LE};
+    use crate::errors::{decode_error, end_of_stream_error};
+    use crate::formats::{Cue, FormatOptions, Track};
+    use crate::io::MediaSourceStream;
+    use crate::meta::{Metadata, MetadataLog};
+    use crate::support_codec;
+    use crate::units::Time;
+
+    const PACKET_LEN: u64 = 1152;
+
+    /// A reader of packets with a fixed duration. A packet with data is damaged. The timestamps
+    /// of all packets read are logged.
+    struct TestReader {
+        tracks: Vec<Track>,
+        packets: Vec<Packet>,
+        pos: usize,
+        log: Arc<Mutex<Vec<u64>>>,
+        metadata: MetadataLog,
+    }
+
+    impl TestReader {
+        fn new(params: CodecParameters, damaged: &[u64], log: Arc<Mutex<Vec<u64>>>) -> Self {
+            let n_packets = params.n_frames.unwrap() / PACKET_LEN;
+
+            let packets = (0..n_packets)
+                .map(|i| {
+                    let ts = i * PACKET_LEN;
+                    let data: &[u8] = if damaged.contains(&ts) { &[1] } else { &[] };
+                    Packet::new_from_slice(0, ts, PACKET_LEN, data)
+                })
+                .collect();
+
+            TestReader {
+                tracks: vec![Track::new(0, params)],
+                packets,
+                pos: 0,
+                log,
+                metadata: Default::default(),
+            }
+        }
+    }
+
+    impl FormatReader for TestReader {
+        fn try_new(_: MediaSourceStream, _: &FormatOptions) -> Result<Self> {
+            unsupported_error("test reader")
+        }
+
+        fn cues(&self) -> &[Cue] {
+            &[]
+        }
+
+        fn metadata(&mut self) -> Metadata<'_> {
+            self.metadata.metadata()
+        }
+
+        fn seek(&mut self, _: SeekMode, to: SeekTo) -> Result<SeekedTo> {
+            let ts = match to {
+                SeekTo::TimeStamp { ts, .. } => ts,
+                _ => unreachable!(),
+            };
+
+            self.pos = (ts / PACKET_LEN) as usize;
+
+            Ok(SeekedTo { track_id: 0, required_ts: ts, actual_ts: self.packets[self.pos].pts() })
+        }
+
+        fn tracks(&self) -> &[Track] {
+            &self.tracks
+        }
+
+        fn next_packet(&mut self) -> Result<Packet> {
+            match self.packets.get(self.pos) {
+                Some(packet) => {
+                    self.pos += 1;
+                    self.log.lock().unwrap().push(packet.pts());
+                    Ok(packet.clone())
+                }
+                None => end_of_stream_error(),
+            }
+        }
+
+        fn into_inner(self: Box<Self>) -> MediaSourceStream {
+            MediaSourceStream::new(Box::new(Cursor::new(Vec::new())), Default::default())
+        }
+    }
+
+    /// A decoder that decodes a packet to a ramp where each sample is its own audio frame index.
+    /// Like a real decoder, any padding is trimmed if gapless decoding is enabled.
+    struct RampDecoder {
+        params: CodecParameters,
+        is_gapless: bool,
+        buf: AudioBuffer<i32>,
+    }
+
+    impl Decoder for RampDecoder {
+        fn try_new(params: &CodecParameters, options: &DecoderOptions) -> Result<Self> {
+            let spec = SignalSpec::new(params.sample_rate.unwrap(), Channels::FRONT_LEFT);
+
+            Ok(RampDecoder {
+                params: params.clone(),
+                is_gapless: options.gapless,
+                buf: AudioBuffer::new(PACKET_LEN, spec),
+            })
+        }
+
+        fn supported_codecs() -> &'static [CodecDescriptor] {
+            &[
+                support_codec!(CODEC_TYPE_MP3, "mp3", "Ramp"),
+                support_codec!(CODEC_TYPE_PCM_S32LE, "pcm", "Ramp"),
+            ]
+        }
+
+        fn reset(&mut self) { }
+
+        fn codec_params(&self) -> &CodecParameters {
+            &self.params
+        }
+
+        fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+            if !packet.buf().is_empty() {
+                return decode_error("damaged packet");
+            }
+
+            self.buf.clear();
+            self.buf.render_reserved(Some(packet.duration() as usize));
+
+            for (i, s) in self.buf.chan_mut(0).iter_mut().enumerate() {
+                *s = (packet.pts() + i as u64) as i32;
+            }
+
+            if self.is_gapless {
+                let (start, end) = self.params.padding_trim(packet.pts(), self.buf.frames());
+                self.buf.trim(start, end);
+            }
+
+            Ok(self.buf.as_audio_buffer_ref())
+        }
+
+        fn finalize(&mut self) -> FinalizeResult {
+            Default::default()
+        }
+    }
+
+    fn track_decoder(
+        params: CodecParameters,
+        damaged: &[u64],
+        gapless: bool,
+    ) -> (TrackDecoder, Arc<Mutex<Vec<u64>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let reader = Box::new(TestReader::new(params, damaged, log.clone()));
+
+        let mut codecs = CodecRegistry::new();
+        codecs.register_all::<RampDecoder>();
+
+        let mut options = DecoderOptions::default();
+        options.with_gapless(gapless);
+
+        (TrackDecoder::try_new(reader, 0, &codecs, &options).unwrap(), log)
+    }
+
+    /// Gets the first sample, and number of frames, of the next audio buffer.
+    fn next_ramp(decoder: &mut TrackDecoder) -> (i32, usize) {
+        match decoder.next_buffer().unwrap() {
+            AudioBufferRef::S32(buf) => (buf.chan(0)[0], buf.frames()),
+            _ => unreachable!(),
+        }
+    }
+
+    fn params(codec: CodecType) -> CodecParameters {
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(codec)
+            .with_sample_rate(48_000)
+            .with_time_base(TimeBase::new(1, 48_000))
+            .with_n_frames(10 * PACKET_LEN);
+        params
+    }
+
+    #[test]
+    fn verify_seek_preroll() {
+        let (mut decoder, log) = track_decoder(params(CODEC_TYPE_MP3), &[], false);
+
+        decoder.seek(SeekTo::TimeStamp { ts: 5000, track_id: 0 }).unwrap();
+
+        // The packet before the packet containing the seeked to frame is decoded and discarded.
+        assert_eq!(next_ramp(&mut decoder), (5000, 5 * 1152 - 5000));
+        assert_eq!(*log.lock().unwrap(), [3 * 1152, 4 * 1152]);
+
+        // The following packets are not trimmed.
+        assert_eq!(next_ramp(&mut decoder), (5 * 1152, 1152));
+
+        // Decode errors during the pre-roll are ignored.
+        let (mut decoder, _) = track_decoder(params(CODEC_TYPE_MP3), &[3 * 1152], false);
+
+        decoder.seek(SeekTo::TimeStamp { ts: 5000, track_id: 0 }).unwrap();
+        assert_eq!(next_ramp(&mut decoder), (5000, 5 * 1152 - 5000));
+
+        // Codecs without pre-roll seek directly to the packet containing the seeked to frame.
+        let (mut decoder, log) = track_decoder(params(CODEC_TYPE_PCM_S32LE), &[], false);
+
+        decoder.seek(SeekTo::TimeStamp { ts: 5000, track_id: 0 }).unwrap();
+        assert_eq!(next_ramp(&mut decoder), (5000, 5 * 1152 - 5000));
+        assert_eq!(*log.lock().unwrap(), [4 * 1152]);
+    }
+
+    #[test]
+    fn verify_seek_gapless_trim() {
+        let mut params = params(CODEC_TYPE_MP3);
+        params.with_leading_padding(1105).with_trailing_padding(1000);
+
+        let (mut decoder, _) = track_decoder(params, &[], true);
+
+        // Seeking to the start skips the leading padding.
+        decoder.seek(SeekTo::Time { time: Time::new(0, 0.0), track_id: None }).unwrap();
+        assert_eq!(next_ramp(&mut decoder), (1105, 1152 - 1105));
+
+        let mut n_frames = 1152 - 1105;
+
+        while let Ok(buf) = decoder.next_buffer() {
+            n_frames += buf.frames();
+        }
+
+        assert_eq!(n_frames, 10 * 1152 - 1105 - 1000);
+
+        // Seeking close to the end stops at the trailing padding. A time is relative to the end
+        // of the leading padding.
+        let seeked = decoder
+            .seek(SeekTo::Time { time: Time::new(0, 0.1875), track_id: None })
+            .unwrap();
+
+        assert_eq!(seeked.required_ts, 9000 + 1105);
+
+        assert_eq!(next_ramp(&mut decoder), (10105, 9 * 1152 - 10105));
+        assert_eq!(next_ramp(&mut decoder), (9 * 1152, 1152 - 1000));
+        assert!(decoder.next_buffer().is_err());
+    }
+
+    #[test]
+    fn verify_next_buffer_into() {
+        let (mut decoder, _) = track_decoder(params(CODEC_TYPE_MP3), &[3 * 1152], false);
+
+        let mut buf = AudioBuffer::<i32>::unused();
+
+        decoder.next_buffer_into(AudioBufferMut::from(&mut buf)).unwrap();
+        assert_eq!((buf.chan(0)[0], buf.frames()), (0, 1152));
+
+        // After a seek, the pre-roll is discarded, decode errors during the pre-roll are ignored,
+        // and the buffer is trimmed to the seeked to frame.
+        decoder.seek(SeekTo::TimeStamp { ts: 5000, track_id: 0 }).unwrap();
+
+        decoder.next_buffer_into(AudioBufferMut::from(&mut buf)).unwrap();
+        assert_eq!((buf.chan(0)[0], buf.frames()), (5000, 5 * 1152 - 5000));
+
+        // Samples are converted to the sample format of the buffer.
+        let mut buf = AudioBuffer::<f64>::unused();
+
+        decoder.next_buffer_into(AudioBufferMut::from(&mut buf)).unwrap();
+        assert_eq!(buf.frames(), 1152);
+        assert_eq!(buf.chan(0)[0], f64::from(5 * 1152) / f64::from(1u32 << 31));
+    }
+
+    #[test]
+    fn verify_seek_preroll_lookup() {
+        assert_eq!(seek_preroll(&params(CODEC_TYPE_MP1)), 1152);
+        assert_eq!(seek_preroll(&params(CODEC_TYPE_MP2)), 1152);
+        assert_eq!(seek_preroll(&params(CODEC_TYPE_MP3)), 1152);
+        assert_eq!(seek_preroll(&params(CODEC_TYPE_AAC)), 1024);
+        assert_eq!(seek_preroll(&params(CODEC_TYPE_VORBIS)), 4096);
+        assert_eq!(seek_preroll(&params(CODEC_TYPE_OPUS)), 3840);
+        assert_eq!(seek_preroll(&params(CODEC_TYPE_PCM_S32LE)), 0);
+    }
+}
@@ -340,8 +340,14 @@ impl QueryDescriptor for IsoMp4Reader {
         ]
     }
 
-    fn score(_context: &[u8]) -> u8 {
-        255
+    fn score(context: &[u8]) -> u8 {
+        // The marker is the type of the file type atom, and it is followed by the major brand
+        // which is a four character code.
+        match context.get(4..8) {
+            Some(brand) if brand.iter().all(|&c| c.is_ascii_alphanumeric() || c == b' ') => 255,
+            Some(_) => 0,
+            None => 128,
+        }
     }
 }
 
@@ -0,0 +1,224 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::audio::Channels;
+use symphonia_core::codecs::{CodecParameters, CodecType, VerificationCheck, CODEC_TYPE_NULL};
+use symphonia_core::codecs::{CODEC_TYPE_AAC, CODEC_TYPE_FLAC, CODEC_TYPE_MP1, CODEC_TYPE_MP2};
+use symphonia_core::codecs::{CODEC_TYPE_MP3, CODEC_TYPE_OPUS, CODEC_TYPE_VORBIS};
+use symphonia_core::codecs::{CODEC_TYPE_PCM_F32LE, CODEC_TYPE_PCM_F64LE, CODEC_TYPE_PCM_U8};
+use symphonia_core::codecs::{CODEC_TYPE_PCM_S16BE, CODEC_TYPE_PCM_S24BE, CODEC_TYPE_PCM_S32BE};
+use symphonia_core::codecs::{CODEC_TYPE_PCM_S16LE, CODEC_TYPE_PCM_S24LE, CODEC_TYPE_PCM_S32LE};
+use symphonia_core::codecs::CODEC_TYPE_PCM_S8;
+use symphonia_core::errors::{decode_error, Result};
+use symphonia_core::io::{BufReader, ReadBytes};
+
+use symphonia_utils_xiph::flac::metadata::{MetadataBlockHeader, MetadataBlockType, StreamInfo};
+
+use crate::lacing::read_xiph_sizes;
+use crate::segment::TrackElement;
+
+/// The native FLAC signature.
+const FLAC_SIGNATURE: &[u8] = b"fLaC";
+
+/// The maximum number of frames of PCM audio in a packet.
+pub const PCM_MAX_FRAMES_PER_PACKET: u64 = 1152;
+
+/// The kind of codec used by a track. Some codecs require additional processing by the demuxer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CodecKind {
+    Pcm,
+    Other,
+}
+
+/// Gets the channels for a channel count. Matroska does not signal a channel layout, therefore
+/// the channels are assumed to be in the order of the `Channels` bit flags.
+fn channels_for_count(count: u64) -> Option<Channels> {
+    match count {
+        0 => None,
+        1 => Some(Channels::FRONT_LEFT),
+        2 => Some(Channels::FRONT_LEFT | Channels::FRONT_RIGHT),
+        n if n <= 32 => Channels::from_bits(((1u64 << n) - 1) as u32),
+        _ => None,
+    }
+}
+
+/// Gets the PCM codec type for a sample format and bit depth.
+fn pcm_codec_type(codec_id: &str, bit_depth: u64) -> CodecType {
+    match (codec_id, bit_depth) {
+        ("A_PCM/INT/LIT", 8) => CODEC_TYPE_PCM_U8,
+        ("A_PCM/INT/LIT", 16) => CODEC_TYPE_PCM_S16LE,
+        ("A_PCM/INT/LIT", 24) => CODEC_TYPE_PCM_S24LE,
+        ("A_PCM/INT/LIT", 32) => CODEC_TYPE_PCM_S32LE,
+        // Big-endian 8-bit PCM is signed.
+        ("A_PCM/INT/BIG", 8) => CODEC_TYPE_PCM_S8,
+        ("A_PCM/INT/BIG", 16) => CODEC_TYPE_PCM_S16BE,
+        ("A_PCM/INT/BIG", 24) => CODEC_TYPE_PCM_S24BE,
+        ("A_PCM/INT/BIG", 32) => CODEC_TYPE_PCM_S32BE,
+        ("A_PCM/FLOAT/IEEE", 32) => CODEC_TYPE_PCM_F32LE,
+        ("A_PCM/FLOAT/IEEE", 64) => CODEC_TYPE_PCM_F64LE,
+        _ => CODEC_TYPE_NULL,
+    }
+}
+
+/// Reads the Vorbis codec private data, the three Xiph laced Vorbis header packets, and returns
+/// the identification and setup headers concatenated as expected by the Vorbis decoder.
+fn read_vorbis_private(buf: &[u8]) -> Result<Box<[u8]>> {
+    let mut reader = BufReader::new(buf);
+
+    if reader.read_u8()? != 2 {
+        return decode_error("mkv: invalid vorbis codec private data");
+    }
+
+    let sizes = read_xiph_sizes(&mut reader, 2)?;
+
+    let ident_pos = reader.pos() as usize;
+    let setup_pos = ident_pos + sizes[0] + sizes[1];
+
+    if setup_pos > buf.len() {
+        return decode_error("mkv: invalid vorbis codec private data");
+    }
+
+    let mut extra_data = Vec::with_capacity(buf.len() - ident_pos - sizes[1]);
+    extra_data.extend_from_slice(&buf[ident_pos..ident_pos + sizes[0]]);
+    extra_data.extend_from_slice(&buf[setup_pos..]);
+
+    Ok(extra_data.into_boxed_slice())
+}
+
+/// Reads the FLAC codec private data, the native FLAC signature followed by the metadata blocks,
+/// into the codec parameters.
+fn read_flac_private(buf: &[u8], codec_params: &mut CodecParameters) -> Result<()> {
+    let mut reader = BufReader::new(buf);
+
+    if reader.read_quad_bytes()? != FLAC_SIGNATURE {
+        return decode_error("mkv: invalid flac codec private data");
+    }
+
+    // The first metadata block must be the stream information block.
+    let header = MetadataBlockHeader::read(&mut reader)?;
+
+    if header.block_type != MetadataBlockType::StreamInfo {
+        return decode_error("mkv: invalid flac codec private data");
+    }
+
+    let stream_info = StreamInfo::read(&mut reader)?;
+
+    codec_params
+        .with_sample_rate(stream_info.sample_rate)
+        .with_bits_per_sample(stream_info.bits_per_sample)
+        .with_max_frames_per_packet(u64::from(stream_info.block_len_max))
+        .with_channels(stream_info.channels)
+        .with_packet_data_integrity(true)
+        .with_verification_code(VerificationCheck::Md5(stream_info.md5));
+
+    if let Some(n_frames) = stream_info.n_samples {
+        codec_params.with_n_frames(n_frames);
+    }
+
+    Ok(())
+}
+
+/// Populates codec parameters for an audio track.
+pub fn make_audio_codec_params(track: &TrackElement) -> Result<(CodecParameters, CodecKind)> {
+    let mut codec_params = CodecParameters::new();
+    let mut kind = CodecKind::Other;
+
+    let audio = match track.audio {
+        Some(ref audio) => audio,
+        _ => return decode_error("mkv: audio track is missing audio settings"),
+    };
+
+    let sample_rate =
+        audio.output_sampling_frequency.unwrap_or(audio.sampling_frequency).round() as u32;
+
+    codec_params.with_sample_rate(sample_rate);
+
+    if let Some(channels) = channels_for_count(audio.channels) {
+        codec_params.with_channels(channels);
+    }
+
+    if let Some(bit_depth) = audio.bit_depth {
+        codec_params.with_bits_per_sample(bit_depth as u32);
+    }
+
+    let codec_private = track.codec_private.as_deref();
+
+    match track.codec_id.as_str() {
+        "A_VORBIS" => {
+            codec_params.for_codec(CODEC_TYPE_VORBIS);
+
+            if let Some(buf) = codec_private {
+                codec_params.with_extra_data(read_vorbis_private(buf)?);
+            }
+        }
+        "A_OPUS" => {
+            // The codec private data is the Opus identification header.
+            codec_params.for_codec(CODEC_TYPE_OPUS).with_sample_rate(48_000);
+
+            if let Some(buf) = codec_private {
+                codec_params.with_extra_data(Box::from(buf));
+            }
+
+            // The codec delay is the pre-skip in nanoseconds.
+            if let Some(delay) = track.codec_delay {
+                codec_params.with_leading_padding((delay * 48 / 1_000_000) as u32);
+            }
+        }
+        "A_FLAC" => {
+            codec_params.for_codec(CODEC_TYPE_FLAC);
+
+            if let Some(buf) = codec_private {
+                read_flac_private(buf, &mut codec_params)?;
+            }
+        }
+        "A_MPEG/L1" => {
+            codec_params.for_codec(CODEC_TYPE_MP1);
+        }
+        "A_MPEG/L2" => {
+            codec_params.for_codec(CODEC_TYPE_MP2);
+        }
+        "A_MPEG/L3" => {
+            codec_params.for_codec(CODEC_TYPE_MP3);
+        }
+        id if id.starts_with("A_AAC") => {
+            // Legacy AAC codec IDs do not have an audio specific config, in which case the decoder
+            // will fallback to the sample rate and channels.
+            codec_params.for_codec(CODEC_TYPE_AAC);
+
+            if let Some(buf) = codec_private {
+                codec_params.with_extra_data(Box::from(buf));
+            }
+        }
+        id @ "A_PCM/INT/LIT" | id @ "A_PCM/INT/BIG" | id @ "A_PCM/FLOAT/IEEE" => {
+            let bit_depth = audio.bit_depth.unwrap_or(0);
+
+            codec_params
+                .for_codec(pcm_codec_type(id, bit_depth))
+                .with_bits_per_coded_sample(bit_depth as u32)
+                .with_max_frames_per_packet(PCM_MAX_FRAMES_PER_PACKET);
+
+            kind = CodecKind::Pcm;
+        }
+        _ => (),
+    }
+
+    Ok((codec_params, kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_vorbis_private;
+
+    #[test]
+    fn verify_read_vorbis_private() {
+        let buf = [2, 3, 1, 1, 1, 1, 2, 3, 3, 3];
+
+        assert_eq!(&*read_vorbis_private(&buf).unwrap(), &[1, 1, 1, 3, 3, 3][..]);
+
+        assert!(read_vorbis_private(&[2, 3, 1, 1, 1]).is_err());
+    }
+}
@@ -0,0 +1,766 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::VecDeque;
+use std::io::{self, Seek, SeekFrom};
+
+use symphonia_core::errors::{end_of_stream_error, seek_error, unsupported_error};
+use symphonia_core::errors::{Error, Result, SeekErrorKind};
+use symphonia_core::formats::prelude::*;
+use symphonia_core::formats::CuePoint;
+use symphonia_core::formats::util::{SeekIndex, SeekSearchResult};
+use symphonia_core::io::{BufReader, MediaSource, MediaSourceStream};
+use symphonia_core::meta::{Metadata, MetadataLog, StandardTagKey, Tag, Value};
+use symphonia_core::probe::{Descriptor, Instantiate, QueryDescriptor};
+use symphonia_core::support_format;
+use symphonia_core::units::TimeBase;
+
+use log::{debug, info, warn};
+
+use crate::codecs::{make_audio_codec_params, CodecKind, PCM_MAX_FRAMES_PER_PACKET};
+use crate::ebml::{ElementHeader, ElementIterator, ElementType};
+use crate::lacing::read_block;
+use crate::meta::read_tags;
+use crate::segment::{ChapterAtomElement, ChaptersElement, CuesElement, EbmlHeaderElement};
+use crate::segment::{InfoElement, SeekHeadElement, TagsElement, TrackElement, TracksElement};
+
+/// The track type of audio tracks.
+const TRACK_TYPE_AUDIO: u64 = 2;
+
+/// The header stripping content compression algorithm.
+const CONTENT_COMP_ALGO_HEADER_STRIPPING: u64 = 3;
+
+/// The number of nanoseconds in a second.
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Converts a duration in nanoseconds to a number of frames at the given sample rate.
+fn nanos_to_frames(nanos: u128, sample_rate: u32) -> u64 {
+    (nanos * u128::from(sample_rate) / NANOS_PER_SEC) as u64
+}
+
+struct TrackState {
+    /// The track number.
+    number: u64,
+    /// The kind of codec used by the track.
+    kind: CodecKind,
+    /// The sample rate of the track.
+    sample_rate: u32,
+    /// The duration of each frame in nanoseconds, if constant.
+    default_duration: Option<u64>,
+    /// The bytes stripped from the start of each frame, if any.
+    stripped_header: Option<Box<[u8]>>,
+    /// The size of one PCM audio frame in bytes, if the track is PCM.
+    pcm_frame_len: usize,
+}
+
+/// A frame read from a block, yet to be returned as a packet.
+struct Frame {
+    track_id: u32,
+    ts: u64,
+    dur: u64,
+    data: Box<[u8]>,
+}
+
+/// The state of the cluster currently being read.
+struct ClusterState {
+    /// The position of the cluster element.
+    pos: u64,
+    /// The end of the cluster, if known.
+    end: Option<u64>,
+    /// The timestamp of the cluster in segment ticks.
+    timestamp: Option<u64>,
+}
+
+/// Matroska and WebM format reader.
+///
+/// `MkvReader` implements a demuxer for the Matroska media container format, and its WebM subset.
+pub struct MkvReader {
+    /// Iterator over the children of the segment element.
+    iter: ElementIterator<MediaSourceStream>,
+    tracks: Vec<Track>,
+    track_states: Vec<TrackState>,
+    /// The index of the default track, if one is flagged.
+    default_track: Option<usize>,
+    cues: Vec<Cue>,
+    metadata: MetadataLog,
+    /// Frames read from the last block that are yet to be returned.
+    frames: VecDeque<Frame>,
+    /// The number of nanoseconds per segment tick.
+    timestamp_scale: u64,
+    /// The position of the data of the segment element. All positions in the segment are relative
+    /// to this position.
+    segment_data_pos: u64,
+    /// The position of the first cluster.
+    first_cluster_pos: Option<u64>,
+    /// The current cluster.
+    cluster: Option<ClusterState>,
+    /// Index of cluster timestamps, in segment ticks, to cluster positions.
+    index: SeekIndex,
+    /// If true, the seek index was built from the cueing data.
+    has_cues: bool,
+}
+
+impl MkvReader {
+    /// Starts reading the cluster with the provided header.
+    fn enter_cluster(&mut self, header: ElementHeader) {
+        self.cluster = Some(ClusterState { pos: header.pos, end: header.end(), timestamp: None });
+        self.iter.descend();
+    }
+
+    /// Adds the cue points to the seek index.
+    fn add_cues(&mut self, cues: &CuesElement) {
+        for point in cues.points.iter() {
+            let position = point.positions.iter().find(|position| {
+                self.track_states.iter().any(|track| track.number == position.track)
+            });
+
+            if let Some(position) = position {
+                let pos = self.segment_data_pos + position.cluster_pos;
+                self.index.insert(point.time, pos, 0);
+            }
+        }
+
+        self.has_cues = true;
+    }
+
+    /// Converts the chapters of the default, or first, edition into cues.
+    fn add_chapters(&mut self, chapters: &ChaptersElement) {
+        let edition = chapters
+            .editions
+            .iter()
+            .find(|edition| edition.default)
+            .or_else(|| chapters.editions.first());
+
+        // Chapter times are in nanoseconds, and cues are in frames of the default track.
+        let sample_rate = match self.track_states.get(self.default_track.unwrap_or(0)) {
+            Some(track) => track.sample_rate,
+            _ => return,
+        };
+
+        let is_visible = |atom: &&ChapterAtomElement| !atom.hidden && atom.enabled;
+
+        let title_tags = |atom: &ChapterAtomElement| -> Vec<Tag> {
+            atom.displays
+                .first()
+                .map(|display| {
+                    let value = Value::from(display.string.as_str());
+                    Tag::new(Some(StandardTagKey::TrackTitle), "TITLE", value)
+                })
+                .into_iter()
+                .collect()
+        };
+
+        if let Some(edition) = edition {
+            for (index, atom) in edition.atoms.iter().filter(is_visible).enumerate() {
+                let points = atom
+                    .atoms
+                    .iter()
+                    .filter(is_visible)
+                    .map(|child| {
+                        let offset = child.time_start.saturating_sub(atom.time_start);
+
+                        CuePoint {
+                            start_offset_ts: nanos_to_frames(u128::from(offset), sample_rate),
+                            tags: title_tags(child),
+                        }
+                    })
+                    .collect();
+
+                self.cues.push(Cue {
+                    index: index as u32,
+                    start_ts: nanos_to_frames(u128::from(atom.time_start), sample_rate),
+                    tags: title_tags(atom),
+                    points,
+                });
+            }
+        }
+    }
+
+    /// Adds the audio tracks to the reader.
+    fn add_tracks(&mut self, tracks: TracksElement, info: &InfoElement) -> Result<()> {
+        for track in tracks.tracks {
+            if track.track_type != TRACK_TYPE_AUDIO {
+                debug!("ignoring non-audio track {}", track.number);
+                continue;
+            }
+
+            if track.number > u64::from(u32::MAX) {
+                warn!("ignoring track with an invalid track number");
+                continue;
+            }
+
+            let stripped_header = match get_stripped_header(&track) {
+                Ok(header) => header,
+                Err(_) => {
+                    warn!("ignoring track {} with unsupported content encoding", track.number);
+                    continue;
+                }
+            };
+
+            let (mut codec_params, kind) = make_audio_codec_params(&track)?;
+
+            let sample_rate = match codec_params.sample_rate {
+                Some(sample_rate) if sample_rate > 0 => sample_rate,
+                _ => {
+                    warn!("ignoring track {} with an invalid sample rate", track.number);
+                    continue;
+                }
+            };
+
+            codec_params.with_time_base(TimeBase::new(1, sample_rate));
+
+            if codec_params.n_frames.is_none() {
+                if let Some(duration) = info.duration {
+                    let nanos = duration * info.timestamp_scale as f64;
+                    codec_params.with_n_frames(nanos_to_frames(nanos as u128, sample_rate));
+                }
+            }
+
+            let pcm_frame_len = match (kind, codec_params.bits_per_coded_sample) {
+                (CodecKind::Pcm, Some(bits)) => {
+                    let n_channels = codec_params.channels.map_or(0, |c| c.count());
+                    n_channels * (bits as usize / 8)
+                }
+                _ => 0,
+            };
+
+            // The first audio track flagged as default is the default track.
+            if track.flag_default && self.default_track.is_none() {
+                self.default_track = Some(self.tracks.len());
+            }
+
+            let mut new_track = Track::new(track.number as u32, codec_params);
+            new_track.language = track.language.clone();
+
+            self.tracks.push(new_track);
+
+            self.track_states.push(TrackState {
+                number: track.number,
+                kind,
+                sample_rate,
+                default_duration: track.default_duration,
+                stripped_header,
+                pcm_frame_len,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Reads a block, and queues its frames.
+    fn read_block(&mut self, data: &[u8], block_duration: Option<u64>) -> Result<()> {
+        let block = read_block(data)?;
+
+        let track = match self.track_states.iter().find(|track| track.number == block.track) {
+            Some(track) => track,
+            None => return Ok(()),
+        };
+
+        let track_id = track.number as u32;
+
+        // The timestamp of a block is relative to its cluster. Blocks before the start of the
+        // segment are clamped to the start.
+        let cluster_ts = self.cluster.as_ref().and_then(|cluster| cluster.timestamp).unwrap_or(0);
+
+        let ticks = (cluster_ts as i64).saturating_add(i64::from(block.rel_ts)).max(0) as u64;
+
+        let block_nanos = u128::from(ticks) * u128::from(self.timestamp_scale);
+
+        // The duration of each frame of the block, if known.
+        let frame_nanos = match (track.default_duration, block_duration) {
+            (Some(dur), _) => u128::from(dur),
+            (None, Some(dur)) => {
+                u128::from(dur) * u128::from(self.timestamp_scale) / block.frames.len() as u128
+            }
+            (None, None) => 0,
+        };
+
+        for (i, frame) in block.frames.iter().enumerate() {
+            let nanos = block_nanos + i as u128 * frame_nanos;
+
+            let ts = nanos_to_frames(nanos, track.sample_rate);
+
+            let data = match track.stripped_header {
+                Some(ref header) => [header, *frame].concat().into_boxed_slice(),
+                None => Box::from(*frame),
+            };
+
+            if track.kind == CodecKind::Pcm && track.pcm_frame_len > 0 {
+                // PCM blocks may be arbitrarily large, split them into packets of a bounded size.
+                let max_len = PCM_MAX_FRAMES_PER_PACKET as usize * track.pcm_frame_len;
+
+                let mut ts = ts;
+
+                for chunk in data.chunks(max_len) {
+                    let dur = (chunk.len() / track.pcm_frame_len) as u64;
+
+                    self.frames.push_back(Frame { track_id, ts, dur, data: Box::from(chunk) });
+
+                    ts += dur;
+                }
+            }
+            else {
+                let dur = nanos_to_frames(frame_nanos, track.sample_rate);
+
+                self.frames.push_back(Frame { track_id, ts, dur, data });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next element of the segment. Frames from any block read are queued.
+    fn read_next_element(&mut self) -> Result<()> {
+        let header = match self.iter.next()? {
+            Some(header) => header,
+            None => return end_of_stream_error(),
+        };
+
+        // A cluster ends at its end position, if known, or the first top-level element.
+        if let Some(ref cluster) = self.cluster {
+            let is_past_end = matches!(cluster.end, Some(end) if header.pos >= end);
+
+            if is_past_end || header.etype.is_top_level() {
+                self.cluster = None;
+            }
+        }
+
+        let in_cluster = self.cluster.is_some();
+
+        match header.etype {
+            ElementType::Cluster => self.enter_cluster(header),
+            ElementType::Timestamp if in_cluster => {
+                let ts = self.iter.read_unsigned()?;
+
+                let cluster = self.cluster.as_mut().unwrap();
+                cluster.timestamp = Some(ts);
+
+                // Without cueing data, build the seek index while reading.
+                if !self.has_cues {
+                    self.index.insert(ts, cluster.pos, 0);
+                }
+            }
+            ElementType::SimpleBlock if in_cluster => {
+                let data = self.iter.read_binary()?;
+                self.read_block(&data, None)?;
+            }
+            ElementType::BlockGroup if in_cluster => {
+                let mut group = ElementIterator::new(self.iter.inner_mut(), header);
+
+                let mut data = None;
+                let mut duration = None;
+
+                while let Some(header) = group.next()? {
+                    match header.etype {
+                        ElementType::Block => data = Some(group.read_binary()?),
+                        ElementType::BlockDuration => duration = Some(group.read_unsigned()?),
+                        _ => (),
+                    }
+                }
+
+                if let Some(data) = data {
+                    self.read_block(&data, duration)?;
+                }
+            }
+            ElementType::Cues => {
+                let cues = self.iter.read_element::<CuesElement>()?;
+
+                if !self.has_cues {
+                    self.add_cues(&cues);
+                }
+            }
+            ElementType::Chapters => {
+                let chapters = self.iter.read_element::<ChaptersElement>()?;
+
+                if self.cues.is_empty() {
+                    self.add_chapters(&chapters);
+                }
+            }
+            ElementType::Tags => {
+                let tags = self.iter.read_element::<TagsElement>()?;
+                self.metadata.push(read_tags(&tags));
+            }
+            ElementType::Ebml | ElementType::Segment => {
+                // Chained segments are not supported.
+                info!("stopping at a chained segment");
+                return end_of_stream_error();
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Reads top-level elements that are located after the first cluster, by seeking to them.
+    fn read_indexed_elements(
+        &mut self,
+        seek_head: &SeekHeadElement,
+        info: &mut Option<InfoElement>,
+        tracks: &mut Option<TracksElement>,
+        cues: &mut Option<CuesElement>,
+        chapters: &mut Option<ChaptersElement>,
+        tags: &mut Vec<TagsElement>,
+    ) -> Result<()> {
+        for seek in seek_head.seeks.iter() {
+            let is_needed = match seek.etype {
+                ElementType::Info => info.is_none(),
+                ElementType::Tracks => tracks.is_none(),
+                ElementType::Cues => cues.is_none(),
+                ElementType::Chapters => chapters.is_none(),
+                ElementType::Tags => tags.is_empty(),
+                _ => false,
+            };
+
+            if !is_needed {
+                continue;
+            }
+
+            self.iter.inner_mut().seek(SeekFrom::Start(self.segment_data_pos + seek.pos))?;
+            self.iter.reset();
+
+            let header = match self.iter.next()? {
+                Some(header) if header.etype == seek.etype => header,
+                _ => {
+                    warn!("seek head entry for {:?} is invalid", seek.etype);
+                    continue;
+                }
+            };
+
+            match header.etype {
+                ElementType::Info => *info = Some(self.iter.read_element()?),
+                ElementType::Tracks => *tracks = Some(self.iter.read_element()?),
+                ElementType::Cues => *cues = Some(self.iter.read_element()?),
+                ElementType::Chapters => *chapters = Some(self.iter.read_element()?),
+                ElementType::Tags => tags.push(self.iter.read_element()?),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets the header bytes stripped from each frame of a track, if any. Returns an error if the
+/// track uses an unsupported content encoding.
+fn get_stripped_header(track: &TrackElement) -> Result<Option<Box<[u8]>>> {
+    match track.encodings {
+        Some(ref encodings) if encodings.is_encrypted => {
+            unsupported_error("mkv: encrypted tracks are not supported")
+        }
+        Some(ref encodings) => match encodings.compression {
+            Some(ref comp) if comp.algo == CONTENT_COMP_ALGO_HEADER_STRIPPING => {
+                Ok(comp.settings.clone())
+            }
+            Some(_) => unsupported_error("mkv: compressed tracks are not supported"),
+            None => Ok(None),
+        },
+        None => Ok(None),
+    }
+}
+
+impl QueryDescriptor for MkvReader {
+    fn query() -> &'static [Descriptor] {
+        &[
+            support_format!(
+                "matroska",
+                "Matroska / WebM",
+                &["mkv", "mka", "webm"],
+                &["video/webm", "audio/webm", "video/x-matroska", "audio/x-matroska"],
+                &[b"\x1a\x45\xdf\xa3"] // The EBML element ID.
+            ),
+        ]
+    }
+
+    fn score(context: &[u8]) -> u8 {
+        let mut it = ElementIterator::new_root(BufReader::new(context), None);
+
+        let header = match it.next() {
+            Ok(Some(header)) if header.etype == ElementType::Ebml => header,
+            _ => return 0,
+        };
+
+        // The EBML header must declare a Matroska or WebM document. If the EBML header is not
+        // entirely contained within the context, then it cannot be verified.
+        match (it.read_element::<EbmlHeaderElement>(), header.end()) {
+            (Ok(_), _) => 255,
+            (Err(_), Some(end)) if end <= context.len() as u64 => 0,
+            (Err(_), _) => 128,
+        }
+    }
+}
+
+impl FormatReader for MkvReader {
+    fn try_new(source: MediaSourceStream, _options: &FormatOptions) -> Result<Self> {
+        let mut it = ElementIterator::new_root(source, None);
+
+        // The stream must start with an EBML header that declares a Matroska or WebM document.
+        match it.next()? {
+            Some(header) if header.etype == ElementType::Ebml => {
+                let ebml = it.read_element::<EbmlHeaderElement>()?;
+                debug!("document type: {}", ebml.doc_type);
+            }
+            _ => return unsupported_error("mkv: missing ebml header"),
+        }
+
+        // The EBML header is followed by a segment.
+        let segment = loop {
+            match it.next()? {
+                Some(header) if header.etype == ElementType::Segment => break header,
+                Some(header) if header.etype == ElementType::Void => continue,
+                _ => return unsupported_error("mkv: missing segment"),
+            }
+        };
+
+        let mut reader = MkvReader {
+            iter: ElementIterator::new(it.into_inner(), segment),
+            tracks: Vec::new(),
+            track_states: Vec::new(),
+            default_track: None,
+            cues: Vec::new(),
+            metadata: Default::default(),
+            frames: VecDeque::new(),
+            timestamp_scale: 0,
+            segment_data_pos: segment.data_pos,
+            first_cluster_pos: None,
+            cluster: None,
+            index: SeekIndex::new(),
+            has_cues: false,
+        };
+
+        let mut seek_head = None;
+        let mut info = None;
+        let mut tracks = None;
+        let mut cues = None;
+        let mut chapters = None;
+        let mut tags = Vec::new();
+
+        // Read all top-level elements up-to the first cluster.
+        let mut first_cluster = None;
+
+        while let Some(header) = reader.iter.next()? {
+            match header.etype {
+                ElementType::SeekHead if seek_head.is_none() => {
+                    seek_head = Some(reader.iter.read_element::<SeekHeadElement>()?);
+                }
+                ElementType::Info => info = Some(reader.iter.read_element()?),
+                ElementType::Tracks => tracks = Some(reader.iter.read_element()?),
+                ElementType::Cues => cues = Some(reader.iter.read_element()?),
+                ElementType::Chapters => chapters = Some(reader.iter.read_element()?),
+                ElementType::Tags => tags.push(reader.iter.read_element()?),
+                ElementType::Cluster => {
+                    first_cluster = Some(header);
+                    break;
+                }
+                _ => (),
+            }
+        }
+
+        // If the stream is seekable, the cueing data, chapters, and tags may be located after the
+        // clusters. Read them now using the seek head, and then return to the first cluster.
+        if let (Some(seek_head), Some(cluster)) = (&seek_head, first_cluster) {
+            if reader.iter.inner_mut().is_seekable() {
+                reader.read_indexed_elements(
+                    seek_head,
+                    &mut info,
+                    &mut tracks,
+                    &mut cues,
+                    &mut chapters,
+                    &mut tags,
+                )?;
+
+                reader.iter.inner_mut().seek(SeekFrom::Start(cluster.pos))?;
+                reader.iter.reset();
+                reader.iter.next()?;
+            }
+        }
+
+        let info = info.unwrap_or_default();
+
+        let tracks = match tracks {
+            Some(tracks) => tracks,
+            _ => return unsupported_error("mkv: missing tracks"),
+        };
+
+        reader.timestamp_scale = info.timestamp_scale;
+
+        reader.add_tracks(tracks, &info)?;
+
+        if reader.tracks.is_empty() {
+            return unsupported_error("mkv: no supported audio tracks");
+        }
+
+        if let Some(cues) = cues {
+            reader.add_cues(&cues);
+        }
+
+        if let Some(chapters) = chapters {
+            reader.add_chapters(&chapters);
+        }
+
+        for tags in tags.iter() {
+            reader.metadata.push(read_tags(tags));
+        }
+
+        if let Some(cluster) = first_cluster {
+            reader.first_cluster_pos = Some(cluster.pos);
+            reader.enter_cluster(cluster);
+        }
+
+        Ok(reader)
+    }
+
+    fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    fn metadata(&mut self) -> Metadata<'_> {
+        self.metadata.metadata()
+    }
+
+    fn seek(&mut self, _mode: SeekMode, to: SeekTo) -> Result<SeekedTo> {
+        let (track_id, required_ts) = match to {
+            SeekTo::TimeStamp { ts, track_id } => (track_id, ts),
+            SeekTo::Time { time, track_id } => {
+                let track = match track_id {
+                    Some(track_id) => self.tracks.iter().find(|track| track.id == track_id),
+                    None => self.default_track(),
+                };
+
+                match track {
+                    Some(track) => {
+                        let ts = track.codec_params.time_base.unwrap().calc_timestamp(time);
+                        (track.id, ts)
+                    }
+                    None => return seek_error(SeekErrorKind::InvalidTrack),
+                }
+            }
+        };
+
+        let sample_rate = match self.track_states.iter().find(|t| t.number == track_id.into()) {
+            Some(track) => track.sample_rate,
+            None => return seek_error(SeekErrorKind::InvalidTrack),
+        };
+
+        debug!("seeking track={} to frame_ts={}", track_id, required_ts);
+
+        let is_seekable = self.iter.inner_mut().is_seekable();
+
+        if is_seekable {
+            // Convert the timestamp to segment ticks to search the seek index for the cluster to
+            // start reading from.
+            let required_nanos = u128::from(required_ts) * NANOS_PER_SEC / u128::from(sample_rate);
+            let required_ticks = (required_nanos / u128::from(self.timestamp_scale)) as u64;
+
+            let pos = match self.index.search(required_ticks) {
+                SeekSearchResult::Lower(point) | SeekSearchResult::Range(point, _) => {
+                    point.byte_offset
+                }
+                SeekSearchResult::Stream | SeekSearchResult::Upper(_) => {
+                    match self.first_cluster_pos {
+                        Some(pos) => pos,
+                        None => return seek_error(SeekErrorKind::OutOfRange),
+                    }
+                }
+            };
+
+            self.iter.inner_mut().seek(SeekFrom::Start(pos))?;
+            self.iter.reset();
+
+            self.cluster = None;
+            self.frames.clear();
+        }
+
+        // Read forward until the frame containing the required timestamp is found.
+        let mut prev: Option<Frame> = None;
+
+        loop {
+            let frame = match self.frames.pop_front() {
+                Some(frame) => frame,
+                None => match self.read_next_element() {
+                    Ok(()) => continue,
+                    Err(Error::IoError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        // The required timestamp is beyond the last frame.
+                        match prev.take() {
+                            Some(prev) => {
+                                self.frames.push_front(prev);
+                                break;
+                            }
+                            None => return seek_error(SeekErrorKind::OutOfRange),
+                        }
+                    }
+                    Err(err) => return Err(err),
+                },
+            };
+
+            if frame.track_id != track_id {
+                continue;
+            }
+
+            if frame.ts > required_ts {
+                // The previous frame contains the required timestamp.
+                match prev.take() {
+                    Some(prev) => {
+                        self.frames.push_front(frame);
+                        self.frames.push_front(prev);
+                    }
+                    None if !is_seekable => {
+                        // The stream is already past the required timestamp.
+                        self.frames.push_front(frame);
+                        return seek_error(SeekErrorKind::ForwardOnly);
+                    }
+                    None => self.frames.push_front(frame),
+                }
+                break;
+            }
+
+            if frame.ts + frame.dur > required_ts {
+                self.frames.push_front(frame);
+                break;
+            }
+
+            prev = Some(frame);
+        }
+
+        let actual_ts = self.frames.front().unwrap().ts;
+
+        debug!(
+            "seeked track={} to packet_ts={} (delta={})",
+            track_id,
+            actual_ts,
+            actual_ts as i64 - required_ts as i64
+        );
+
+        Ok(SeekedTo { track_id, required_ts, actual_ts })
+    }
+
+    fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    fn default_track(&self) -> Option<&Track> {
+        self.tracks.get(self.default_track.unwrap_or(0))
+    }
+
+    fn next_packet(&mut self) -> Result<Packet> {
+        loop {
+            if let Some(frame) = self.frames.pop_front() {
+                return Ok(Packet::new_from_boxed_slice(
+                    frame.track_id,
+                    frame.ts,
+                    frame.dur,
+                    frame.data,
+                ));
+            }
+
+            self.read_next_element()?;
+        }
+    }
+
+    fn into_inner(self: Box<Self>) -> MediaSourceStream {
+        self.iter.into_inner()
+    }
+}
@@ -0,0 +1,548 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Extensible Binary Meta Language (EBML) primitives (RFC 8794).
+
+use std::io;
+
+use symphonia_core::errors::{decode_error, Error, Result};
+use symphonia_core::io::ReadBytes;
+
+/// The maximum length, in bytes, of an element ID.
+const MAX_ID_LEN: u32 = 4;
+
+/// The maximum length, in bytes, of a variable-size integer.
+const MAX_VINT_LEN: u32 = 8;
+
+/// The maximum length, in bytes, of a binary or string element that will be read into memory.
+const MAX_BINARY_LEN: u64 = 64 * 1024 * 1024;
+
+/// Element types.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ElementType {
+    Attachments,
+    Audio,
+    BitDepth,
+    Block,
+    BlockDuration,
+    BlockGroup,
+    Channels,
+    ChapLanguage,
+    ChapString,
+    ChapterAtom,
+    ChapterDisplay,
+    ChapterFlagEnabled,
+    ChapterFlagHidden,
+    ChapterTimeEnd,
+    ChapterTimeStart,
+    ChapterUid,
+    Chapters,
+    Cluster,
+    CodecDelay,
+    CodecId,
+    CodecPrivate,
+    ContentCompAlgo,
+    ContentCompSettings,
+    ContentCompression,
+    ContentEncoding,
+    ContentEncodings,
+    Crc32,
+    CueClusterPosition,
+    CuePoint,
+    CueTime,
+    CueTrack,
+    CueTrackPositions,
+    Cues,
+    DefaultDuration,
+    DiscardPadding,
+    DocType,
+    DocTypeReadVersion,
+    Duration,
+    Ebml,
+    EbmlReadVersion,
+    EditionEntry,
+    EditionFlagDefault,
+    EditionFlagHidden,
+    FlagDefault,
+    Info,
+    Language,
+    MuxingApp,
+    Name,
+    OutputSamplingFrequency,
+    SamplingFrequency,
+    Seek,
+    SeekHead,
+    SeekId,
+    SeekPosition,
+    SeekPreRoll,
+    Segment,
+    SimpleBlock,
+    SimpleTag,
+    Tag,
+    TagBinary,
+    TagLanguage,
+    TagName,
+    TagString,
+    Tags,
+    Targets,
+    TargetTypeValue,
+    Timestamp,
+    TimestampScale,
+    Title,
+    TrackEntry,
+    TrackNumber,
+    TrackType,
+    TrackUid,
+    Tracks,
+    Void,
+    WritingApp,
+    Other(u32),
+}
+
+impl From<u32> for ElementType {
+    fn from(id: u32) -> Self {
+        match id {
+            0x1a45dfa3 => ElementType::Ebml,
+            0x42f7 => ElementType::EbmlReadVersion,
+            0x4282 => ElementType::DocType,
+            0x4285 => ElementType::DocTypeReadVersion,
+            0xec => ElementType::Void,
+            0xbf => ElementType::Crc32,
+            0x18538067 => ElementType::Segment,
+            // Meta seek information.
+            0x114d9b74 => ElementType::SeekHead,
+            0x4dbb => ElementType::Seek,
+            0x53ab => ElementType::SeekId,
+            0x53ac => ElementType::SeekPosition,
+            // Segment information.
+            0x1549a966 => ElementType::Info,
+            0x2ad7b1 => ElementType::TimestampScale,
+            0x4489 => ElementType::Duration,
+            0x7ba9 => ElementType::Title,
+            0x4d80 => ElementType::MuxingApp,
+            0x5741 => ElementType::WritingApp,
+            // Clusters.
+            0x1f43b675 => ElementType::Cluster,
+            0xe7 => ElementType::Timestamp,
+            0xa3 => ElementType::SimpleBlock,
+            0xa0 => ElementType::BlockGroup,
+            0xa1 => ElementType::Block,
+            0x9b => ElementType::BlockDuration,
+            0x75a2 => ElementType::DiscardPadding,
+            // Tracks.
+            0x1654ae6b => ElementType::Tracks,
+            0xae => ElementType::TrackEntry,
+            0xd7 => ElementType::TrackNumber,
+            0x73c5 => ElementType::TrackUid,
+            0x83 => ElementType::TrackType,
+            0x88 => ElementType::FlagDefault,
+            0x23e383 => ElementType::DefaultDuration,
+            0x536e => ElementType::Name,
+            0x22b59c => ElementType::Language,
+            0x86 => ElementType::CodecId,
+            0x63a2 => ElementType::CodecPrivate,
+            0x56aa => ElementType::CodecDelay,
+            0x56bb => ElementType::SeekPreRoll,
+            0xe1 => ElementType::Audio,
+            0xb5 => ElementType::SamplingFrequency,
+            0x78b5 => ElementType::OutputSamplingFrequency,
+            0x9f => ElementType::Channels,
+            0x6264 => ElementType::BitDepth,
+            0x6d80 => ElementType::ContentEncodings,
+            0x6240 => ElementType::ContentEncoding,
+            0x5034 => ElementType::ContentCompression,
+            0x4254 => ElementType::ContentCompAlgo,
+            0x4255 => ElementType::ContentCompSettings,
+            // Cueing data.
+            0x1c53bb6b => ElementType::Cues,
+            0xbb => ElementType::CuePoint,
+            0xb3 => ElementType::CueTime,
+            0xb7 => ElementType::CueTrackPositions,
+            0xf7 => ElementType::CueTrack,
+            0xf1 => ElementType::CueClusterPosition,
+            // Attachments.
+            0x1941a469 => ElementType::Attachments,
+            // Chapters.
+            0x1043a770 => ElementType::Chapters,
+            0x45b9 => ElementType::EditionEntry,
+            0x45bd => ElementType::EditionFlagHidden,
+            0x45db => ElementType::EditionFlagDefault,
+            0xb6 => ElementType::ChapterAtom,
+            0x73c4 => ElementType::ChapterUid,
+            0x91 => ElementType::ChapterTimeStart,
+            0x92 => ElementType::ChapterTimeEnd,
+            0x98 => ElementType::ChapterFlagHidden,
+            0x4598 => ElementType::ChapterFlagEnabled,
+            0x80 => ElementType::ChapterDisplay,
+            0x85 => ElementType::ChapString,
+            0x437c => ElementType::ChapLanguage,
+            // Tagging.
+            0x1254c367 => ElementType::Tags,
+            0x7373 => ElementType::Tag,
+            0x63c0 => ElementType::Targets,
+            0x68ca => ElementType::TargetTypeValue,
+            0x67c8 => ElementType::SimpleTag,
+            0x45a3 => ElementType::TagName,
+            0x447a => ElementType::TagLanguage,
+            0x4487 => ElementType::TagString,
+            0x4485 => ElementType::TagBinary,
+            _ => ElementType::Other(id),
+        }
+    }
+}
+
+impl ElementType {
+    /// Returns true if the element is a child of the segment element. Elements of these types
+    /// terminate a cluster of unknown size.
+    pub fn is_top_level(&self) -> bool {
+        matches!(
+            self,
+            ElementType::SeekHead
+                | ElementType::Info
+                | ElementType::Tracks
+                | ElementType::Cluster
+                | ElementType::Cues
+                | ElementType::Attachments
+                | ElementType::Chapters
+                | ElementType::Tags
+        )
+    }
+}
+
+/// Reads a variable-size integer, returning the value, with the length marker removed, and the
+/// length of the integer in bytes.
+fn read_vint<B: ReadBytes>(reader: &mut B) -> Result<(u64, u32)> {
+    let byte = reader.read_byte()?;
+
+    // The number of leading zeros of the first byte is the number of bytes that follow.
+    let len = byte.leading_zeros() + 1;
+
+    if len > MAX_VINT_LEN {
+        return decode_error("mkv: invalid variable-size integer");
+    }
+
+    let mut value = u64::from(byte) & (0xff >> len);
+
+    for _ in 1..len {
+        value = (value << 8) | u64::from(reader.read_byte()?);
+    }
+
+    Ok((value, len))
+}
+
+/// Reads an unsigned variable-size integer.
+pub fn read_unsigned_vint<B: ReadBytes>(reader: &mut B) -> Result<u64> {
+    Ok(read_vint(reader)?.0)
+}
+
+/// Reads a signed variable-size integer. The signed value is stored with a bias of half the
+/// range of the integer.
+pub fn read_signed_vint<B: ReadBytes>(reader: &mut B) -> Result<i64> {
+    let (value, len) = read_vint(reader)?;
+
+    let bias = (1 << (7 * len - 1)) - 1;
+
+    Ok(value as i64 - bias)
+}
+
+/// Reads an element ID. Unlike other variable-size integers, the length marker is part of the ID.
+fn read_id<B: ReadBytes>(reader: &mut B) -> Result<u32> {
+    let byte = reader.read_byte()?;
+
+    let len = byte.leading_zeros() + 1;
+
+    if len > MAX_ID_LEN {
+        return decode_error("mkv: invalid element id");
+    }
+
+    let mut id = u32::from(byte);
+
+    for _ in 1..len {
+        id = (id << 8) | u32::from(reader.read_byte()?);
+    }
+
+    Ok(id)
+}
+
+/// Common element header.
+#[derive(Copy, Clone, Debug)]
+pub struct ElementHeader {
+    /// The element type.
+    pub etype: ElementType,
+    /// The position of the element in the stream.
+    pub pos: u64,
+    /// The position of the element's data in the stream.
+    pub data_pos: u64,
+    /// The size of the element's data. `None` if the size is unknown, in which case the element
+    /// spans up-to the next element that cannot be its child.
+    pub data_len: Option<u64>,
+}
+
+impl ElementHeader {
+    /// Reads an element header from the provided `ReadBytes`.
+    pub fn read<B: ReadBytes>(reader: &mut B) -> Result<ElementHeader> {
+        let pos = reader.pos();
+
+        let etype = ElementType::from(read_id(reader)?);
+
+        let (size, len) = read_vint(reader)?;
+
+        // A size with all value bits set indicates an unknown size.
+        let data_len = if size == (1 << (7 * len)) - 1 { None } else { Some(size) };
+
+        Ok(ElementHeader { etype, pos, data_pos: reader.pos(), data_len })
+    }
+
+    /// Gets the position of the end of the element, if known.
+    pub fn end(&self) -> Option<u64> {
+        self.data_len.map(|len| self.data_pos + len)
+    }
+}
+
+pub trait Element: Sized {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self>;
+}
+
+/// Reads the data of an unsigned integer element.
+pub fn read_unsigned<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<u64> {
+    let len = header.data_len.unwrap_or(0);
+
+    if len > 8 {
+        return decode_error("mkv: invalid unsigned integer element length");
+    }
+
+    let mut value = 0;
+
+    for _ in 0..len {
+        value = (value << 8) | u64::from(reader.read_byte()?);
+    }
+
+    Ok(value)
+}
+
+/// Reads the data of a floating point element.
+pub fn read_float<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<f64> {
+    match header.data_len.unwrap_or(0) {
+        0 => Ok(0.0),
+        4 => Ok(f64::from(reader.read_be_f32()?)),
+        8 => Ok(reader.read_be_f64()?),
+        _ => decode_error("mkv: invalid float element length"),
+    }
+}
+
+/// Reads the data of a binary element.
+pub fn read_binary<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Box<[u8]>> {
+    match header.data_len {
+        Some(len) if len > MAX_BINARY_LEN => decode_error("mkv: binary element is too large"),
+        Some(len) => Ok(reader.read_boxed_slice_exact(len as usize)?),
+        None => decode_error("mkv: binary element has an unknown size"),
+    }
+}
+
+/// Reads the data of a string element. Strings may be padded with trailing zeros.
+pub fn read_string<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<String> {
+    let buf = read_binary(reader, header)?;
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// An iterator over the headers of sibling elements.
+pub struct ElementIterator<B: ReadBytes> {
+    reader: B,
+    /// The end of the parent element, if known.
+    end: Option<u64>,
+    /// The current element, if it was not read.
+    cur_element: Option<ElementHeader>,
+    /// The position of the next element.
+    next_pos: u64,
+}
+
+impl<B: ReadBytes> ElementIterator<B> {
+    /// Instantiate a new iterator starting at the current position of the reader. If `end` is
+    /// provided, elements will not be read beyond it.
+    pub fn new_root(reader: B, end: Option<u64>) -> Self {
+        let next_pos = reader.pos();
+
+        ElementIterator { reader, end, cur_element: None, next_pos }
+    }
+
+    /// Instantiate a new iterator over the children of the element with the provided header. The
+    /// reader must be positioned at the start of the element's data.
+    pub fn new(reader: B, parent: ElementHeader) -> Self {
+        ElementIterator { reader, end: parent.end(), cur_element: None, next_pos: parent.data_pos }
+    }
+
+    pub fn into_inner(self) -> B {
+        self.reader
+    }
+
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.reader
+    }
+
+    /// Resets the iterator to continue from the current position of the reader. Must be called
+    /// after repositioning the underlying reader.
+    pub fn reset(&mut self) {
+        self.cur_element = None;
+        self.next_pos = self.reader.pos();
+    }
+
+    /// Gets the header of the next element, skipping any data of the previous element that was not
+    /// read. The children of elements of unknown size are not skipped.
+    pub fn next(&mut self) -> Result<Option<ElementHeader>> {
+        let cur_pos = self.reader.pos();
+
+        if cur_pos < self.next_pos {
+            self.reader.ignore_bytes(self.next_pos - cur_pos)?;
+        }
+        else if cur_pos > self.next_pos {
+            return decode_error("mkv: overread element");
+        }
+
+        if let Some(end) = self.end {
+            if self.next_pos >= end {
+                return Ok(None);
+            }
+        }
+
+        let header = match ElementHeader::read(&mut self.reader) {
+            Ok(header) => header,
+            // If the end of the parent element is unknown, then the end of the stream terminates
+            // the parent element.
+            Err(Error::IoError(ref err))
+                if self.end.is_none() && err.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+
+        // The next element follows this element. If the size of this element is unknown then its
+        // children must be iterated.
+        self.next_pos = header.end().unwrap_or(header.data_pos);
+
+        self.cur_element = Some(header);
+
+        Ok(self.cur_element)
+    }
+
+    /// Descend into the current element such that the next element is its first child.
+    pub fn descend(&mut self) {
+        let header = self.cur_element.take().unwrap();
+        self.next_pos = header.data_pos;
+    }
+
+    /// Reads the current element.
+    pub fn read_element<E: Element>(&mut self) -> Result<E> {
+        // The element data can only be read once. Assert the element was not read previously.
+        let header = self.cur_element.take().unwrap();
+        E::read(&mut self.reader, header)
+    }
+
+    /// Reads the current element as an unsigned integer.
+    pub fn read_unsigned(&mut self) -> Result<u64> {
+        read_unsigned(&mut self.reader, self.cur_element.take().unwrap())
+    }
+
+    /// Reads the current element as a floating point number.
+    pub fn read_float(&mut self) -> Result<f64> {
+        read_float(&mut self.reader, self.cur_element.take().unwrap())
+    }
+
+    /// Reads the current element as a string.
+    pub fn read_string(&mut self) -> Result<String> {
+        let header = self.take_bounded_element()?;
+        read_string(&mut self.reader, header)
+    }
+
+    /// Reads the current element as binary data.
+    pub fn read_binary(&mut self) -> Result<Box<[u8]>> {
+        let header = self.take_bounded_element()?;
+        read_binary(&mut self.reader, header)
+    }
+
+    /// Takes the current element, checking that its data does not extend beyond the end of the
+    /// parent element, if known.
+    fn take_bounded_element(&mut self) -> Result<ElementHeader> {
+        let header = self.cur_element.take().unwrap();
+
+        if let (Some(end), Some(parent_end)) = (header.end(), self.end) {
+            if end > parent_end {
+                return decode_error("mkv: element exceeds the bounds of its parent");
+            }
+        }
+
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia_core::io::BufReader;
+
+    use super::*;
+
+    #[test]
+    fn verify_read_vint() {
+        assert_eq!(read_unsigned_vint(&mut BufReader::new(&[0x82])).unwrap(), 2);
+        assert_eq!(read_unsigned_vint(&mut BufReader::new(&[0x40, 0x02])).unwrap(), 2);
+        assert_eq!(read_unsigned_vint(&mut BufReader::new(&[0x10, 0, 0, 0x02])).unwrap(), 2);
+        assert_eq!(read_unsigned_vint(&mut BufReader::new(&[1, 0, 0, 0, 0, 0, 0, 2])).unwrap(), 2);
+        assert!(read_unsigned_vint(&mut BufReader::new(&[0x00, 0x02])).is_err());
+
+        assert_eq!(read_signed_vint(&mut BufReader::new(&[0x80])).unwrap(), -63);
+        assert_eq!(read_signed_vint(&mut BufReader::new(&[0xbf])).unwrap(), 0);
+        assert_eq!(read_signed_vint(&mut BufReader::new(&[0x40, 0x00])).unwrap(), -8191);
+    }
+
+    #[test]
+    fn verify_read_element_header() {
+        // A segment of unknown size.
+        let header =
+            ElementHeader::read(&mut BufReader::new(&[0x18, 0x53, 0x80, 0x67, 0xff])).unwrap();
+
+        assert_eq!(header.etype, ElementType::Segment);
+        assert_eq!(header.data_pos, 5);
+        assert_eq!(header.data_len, None);
+
+        // A timestamp of known size.
+        let header = ElementHeader::read(&mut BufReader::new(&[0xe7, 0x81, 0x10])).unwrap();
+
+        assert_eq!(header.etype, ElementType::Timestamp);
+        assert_eq!(header.end(), Some(3));
+    }
+
+    #[test]
+    fn verify_read_binary_bounds() {
+        // A tag binary element claiming 256 bytes within a parent of 8 bytes.
+        let data = [0x44, 0x85, 0x41, 0x00, 1, 2, 3, 4];
+        let parent =
+            ElementHeader { etype: ElementType::SimpleTag, pos: 0, data_pos: 0, data_len: Some(8) };
+
+        let mut it = ElementIterator::new(BufReader::new(&data), parent);
+        assert_eq!(it.next().unwrap().unwrap().etype, ElementType::TagBinary);
+        assert!(matches!(it.read_binary(), Err(Error::DecodeError(_))));
+
+        // A tag binary element of (nearly) maximum size without a known parent.
+        let data = [0x44, 0x85, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe];
+
+        let mut it = ElementIterator::new_root(BufReader::new(&data), None);
+        assert_eq!(it.next().unwrap().unwrap().etype, ElementType::TagBinary);
+        assert!(matches!(it.read_binary(), Err(Error::DecodeError(_))));
+
+        // A tag binary element within the bounds of its parent.
+        let data = [0x44, 0x85, 0x84, 1, 2, 3, 4];
+        let parent =
+            ElementHeader { etype: ElementType::SimpleTag, pos: 0, data_pos: 0, data_len: Some(7) };
+
+        let mut it = ElementIterator::new(BufReader::new(&data), parent);
+        assert_eq!(it.next().unwrap().unwrap().etype, ElementType::TagBinary);
+        assert_eq!(&*it.read_binary().unwrap(), &[1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,185 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::{decode_error, Result};
+use symphonia_core::io::{BufReader, ReadBytes};
+
+use crate::ebml::{read_signed_vint, read_unsigned_vint};
+
+/// The lacing flags of a block header.
+const BLOCK_FLAG_LACING_MASK: u8 = 0x06;
+
+/// A `Block` or `SimpleBlock` split into its constituent frames.
+pub struct Block<'a> {
+    /// The track number of the block.
+    pub track: u64,
+    /// The timestamp of the block relative to the timestamp of the cluster, in segment ticks.
+    pub rel_ts: i16,
+    /// The frames of the block.
+    pub frames: Vec<&'a [u8]>,
+}
+
+/// Reads the sizes of `n` Xiph laced frames. The size of the final frame is not coded and must be
+/// inferred by the caller.
+pub fn read_xiph_sizes<B: ReadBytes>(reader: &mut B, n: usize) -> Result<Vec<usize>> {
+    let mut sizes = Vec::with_capacity(n);
+
+    for _ in 0..n {
+        let mut size = 0;
+
+        loop {
+            let byte = reader.read_byte()?;
+            size += usize::from(byte);
+
+            if byte < 255 {
+                break;
+            }
+        }
+
+        sizes.push(size);
+    }
+
+    Ok(sizes)
+}
+
+/// Reads the sizes of `n` EBML laced frames. The size of the final frame is not coded and must be
+/// inferred by the caller.
+fn read_ebml_sizes<B: ReadBytes>(reader: &mut B, n: usize) -> Result<Vec<usize>> {
+    let mut sizes = Vec::with_capacity(n);
+
+    if n > 0 {
+        // The first size is coded as is, and the remaining sizes are coded as the difference from
+        // the previous size.
+        let mut size = read_unsigned_vint(reader)? as i64;
+        sizes.push(size as usize);
+
+        for _ in 1..n {
+            size += read_signed_vint(reader)?;
+
+            if size < 0 {
+                return decode_error("mkv: invalid ebml lace size");
+            }
+
+            sizes.push(size as usize);
+        }
+    }
+
+    Ok(sizes)
+}
+
+/// Splits `data` into frames of the given sizes. The final frame consumes the remainder.
+fn split_frames<'a>(data: &'a [u8], sizes: &[usize]) -> Result<Vec<&'a [u8]>> {
+    let mut frames = Vec::with_capacity(sizes.len() + 1);
+    let mut rem = data;
+
+    for &size in sizes {
+        if size > rem.len() {
+            return decode_error("mkv: lace size exceeds block size");
+        }
+
+        let (frame, next) = rem.split_at(size);
+        frames.push(frame);
+        rem = next;
+    }
+
+    frames.push(rem);
+
+    Ok(frames)
+}
+
+/// Parses the header of a `Block` or `SimpleBlock` and splits it into frames.
+pub fn read_block(data: &[u8]) -> Result<Block<'_>> {
+    let mut reader = BufReader::new(data);
+
+    let track = read_unsigned_vint(&mut reader)?;
+    let rel_ts = reader.read_be_u16()? as i16;
+    let flags = reader.read_u8()?;
+
+    let lacing = (flags & BLOCK_FLAG_LACING_MASK) >> 1;
+
+    let frames = if lacing == 0 {
+        vec![&data[reader.pos() as usize..]]
+    }
+    else {
+        // The number of frames in the lace, minus one.
+        let n = usize::from(reader.read_u8()?);
+
+        match lacing {
+            // Xiph lacing.
+            1 => {
+                let sizes = read_xiph_sizes(&mut reader, n)?;
+                split_frames(&data[reader.pos() as usize..], &sizes)?
+            }
+            // Fixed-size lacing.
+            2 => {
+                let rem = &data[reader.pos() as usize..];
+
+                let frame_len = rem.len() / (n + 1);
+
+                if frame_len == 0 || frame_len * (n + 1) != rem.len() {
+                    return decode_error("mkv: invalid fixed-size lace");
+                }
+
+                rem.chunks_exact(frame_len).collect()
+            }
+            // EBML lacing.
+            _ => {
+                let sizes = read_ebml_sizes(&mut reader, n)?;
+                split_frames(&data[reader.pos() as usize..], &sizes)?
+            }
+        }
+    };
+
+    Ok(Block { track, rel_ts, frames })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_block;
+
+    #[test]
+    fn verify_no_lacing() {
+        let block = read_block(&[0x81, 0xff, 0xfe, 0x80, 1, 2, 3]).unwrap();
+
+        assert_eq!(block.track, 1);
+        assert_eq!(block.rel_ts, -2);
+        assert_eq!(block.frames, [&[1, 2, 3][..]]);
+    }
+
+    #[test]
+    fn verify_xiph_lacing() {
+        let mut data = vec![0x82, 0x00, 0x10, 0x82, 2, 255, 1, 2];
+        data.extend_from_slice(&[0xaa; 256]);
+        data.extend_from_slice(&[0xbb; 2]);
+        data.extend_from_slice(&[0xcc; 3]);
+
+        let block = read_block(&data).unwrap();
+
+        assert_eq!(block.track, 2);
+        assert_eq!(block.rel_ts, 16);
+        assert_eq!(block.frames, [&[0xaa; 256][..], &[0xbb; 2][..], &[0xcc; 3][..]]);
+    }
+
+    #[test]
+    fn verify_fixed_lacing() {
+        let block = read_block(&[0x81, 0x00, 0x00, 0x84, 1, 1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(block.frames, [&[1, 2, 3][..], &[4, 5, 6][..]]);
+
+        assert!(read_block(&[0x81, 0x00, 0x00, 0x84, 1, 1, 2, 3, 4, 5]).is_err());
+    }
+
+    #[test]
+    fn verify_ebml_lacing() {
+        // Sizes 3, 1 (-2), and the remaining 2 bytes.
+        let data = [0x81, 0x00, 0x00, 0x86, 2, 0x83, 0xbd, 1, 2, 3, 4, 5, 6];
+
+        let block = read_block(&data).unwrap();
+
+        assert_eq!(block.frames, [&[1, 2, 3][..], &[4][..], &[5, 6][..]]);
+    }
+}
@@ -0,0 +1,18 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![warn(rust_2018_idioms)]
+#![forbid(unsafe_code)]
+
+mod codecs;
+mod demuxer;
+mod ebml;
+mod lacing;
+mod meta;
+mod segment;
+
+pub use demuxer::MkvReader;
@@ -0,0 +1,111 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::meta::{MetadataBuilder, MetadataRevision, StandardTagKey, Tag, Value};
+
+use crate::segment::{SimpleTagElement, TagsElement};
+
+/// The target type value of tags that apply to an album.
+const TARGET_TYPE_ALBUM: u64 = 50;
+
+/// Maps the name of a top-level simple tag to a standard tag key. Some tag names have a different
+/// meaning depending on the target of the tag.
+fn std_key_for_tag(name: &str, target_type_value: u64) -> Option<StandardTagKey> {
+    let is_album = target_type_value >= TARGET_TYPE_ALBUM;
+
+    let std_key = match name {
+        "TITLE" if is_album => StandardTagKey::Album,
+        "TITLE" => StandardTagKey::TrackTitle,
+        "SUBTITLE" => StandardTagKey::TrackSubtitle,
+        "ARTIST" if is_album => StandardTagKey::AlbumArtist,
+        "ARTIST" => StandardTagKey::Artist,
+        "PART_NUMBER" if is_album => StandardTagKey::DiscNumber,
+        "PART_NUMBER" => StandardTagKey::TrackNumber,
+        "TOTAL_PARTS" if is_album => StandardTagKey::TrackTotal,
+        "ALBUM" => StandardTagKey::Album,
+        "ALBUM_ARTIST" => StandardTagKey::AlbumArtist,
+        "ARRANGER" => StandardTagKey::Arranger,
+        "BPM" => StandardTagKey::Bpm,
+        "CATALOG_NUMBER" => StandardTagKey::IdentCatalogNumber,
+        "COMMENT" => StandardTagKey::Comment,
+        "COMPOSER" => StandardTagKey::Composer,
+        "CONDUCTOR" => StandardTagKey::Conductor,
+        "COPYRIGHT" => StandardTagKey::Copyright,
+        "DATE" | "DATE_RECORDED" => StandardTagKey::Date,
+        "DATE_RELEASED" => StandardTagKey::ReleaseDate,
+        "DATE_TAGGED" => StandardTagKey::TaggingDate,
+        "DESCRIPTION" => StandardTagKey::Description,
+        "ENCODED_BY" => StandardTagKey::EncodedBy,
+        "ENCODER" => StandardTagKey::Encoder,
+        "ENCODER_SETTINGS" => StandardTagKey::EncoderSettings,
+        "GENRE" => StandardTagKey::Genre,
+        "ISRC" => StandardTagKey::IdentIsrc,
+        "LABEL" => StandardTagKey::Label,
+        "LANGUAGE" => StandardTagKey::Language,
+        "LICENSE" => StandardTagKey::License,
+        "LYRICIST" => StandardTagKey::Lyricist,
+        "LYRICS" => StandardTagKey::Lyrics,
+        "MOOD" => StandardTagKey::Mood,
+        "PRODUCER" => StandardTagKey::Producer,
+        "PUBLISHER" => StandardTagKey::Label,
+        "PURCHASE_INFO" => StandardTagKey::UrlPurchase,
+        "RATING" => StandardTagKey::Rating,
+        "REMIXED_BY" => StandardTagKey::Remixer,
+        "REPLAYGAIN_ALBUM_GAIN" => StandardTagKey::ReplayGainAlbumGain,
+        "REPLAYGAIN_ALBUM_PEAK" => StandardTagKey::ReplayGainAlbumPeak,
+        "REPLAYGAIN_TRACK_GAIN" => StandardTagKey::ReplayGainTrackGain,
+        "REPLAYGAIN_TRACK_PEAK" => StandardTagKey::ReplayGainTrackPeak,
+        "SORT_WITH" => StandardTagKey::SortTrackTitle,
+        "URL" => StandardTagKey::Url,
+        "WRITTEN_BY" => StandardTagKey::Writer,
+        _ => return None,
+    };
+
+    Some(std_key)
+}
+
+/// Adds a simple tag, and all its nested simple tags, to the metadata. Nested tags are keyed by
+/// the path of names from the top-level tag, separated by a '/', and are never mapped to a
+/// standard tag key.
+fn add_simple_tag(
+    builder: &mut MetadataBuilder,
+    tag: &SimpleTagElement,
+    parent: Option<&str>,
+    target_type_value: u64,
+) {
+    let (key, std_key) = match parent {
+        Some(parent) => (format!("{}/{}", parent, tag.name), None),
+        None => (tag.name.clone(), std_key_for_tag(&tag.name, target_type_value)),
+    };
+
+    let value = match (&tag.string, &tag.binary) {
+        (Some(string), _) => Some(Value::from(string.as_str())),
+        (None, Some(binary)) => Some(Value::from(&binary[..])),
+        (None, None) => None,
+    };
+
+    if let Some(value) = value {
+        builder.add_tag(Tag::new(std_key, &key, value));
+    }
+
+    for child in tag.tags.iter() {
+        add_simple_tag(builder, child, Some(&key), target_type_value);
+    }
+}
+
+/// Flattens all tags into a metadata revision.
+pub fn read_tags(tags: &TagsElement) -> MetadataRevision {
+    let mut builder = MetadataBuilder::new();
+
+    for tag in tags.tags.iter() {
+        for simple_tag in tag.tags.iter() {
+            add_simple_tag(&mut builder, simple_tag, None, tag.target_type_value);
+        }
+    }
+
+    builder.metadata()
+}
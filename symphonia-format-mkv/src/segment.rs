@@ -0,0 +1,627 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::{decode_error, unsupported_error, Result};
+use symphonia_core::io::ReadBytes;
+
+use log::debug;
+
+use crate::ebml::{Element, ElementHeader, ElementIterator, ElementType};
+
+/// The default number of nanoseconds per segment tick.
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+
+/// The maximum supported EBML read version.
+const MAX_EBML_READ_VERSION: u64 = 1;
+
+/// The EBML header.
+#[derive(Debug)]
+pub struct EbmlHeaderElement {
+    pub doc_type: String,
+}
+
+impl Element for EbmlHeaderElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut read_version = 1;
+        let mut doc_type = String::from("matroska");
+
+        while let Some(header) = it.next()? {
+            match header.etype {
+                ElementType::EbmlReadVersion => read_version = it.read_unsigned()?,
+                ElementType::DocType => doc_type = it.read_string()?,
+                _ => (),
+            }
+        }
+
+        if read_version > MAX_EBML_READ_VERSION {
+            return unsupported_error("mkv: unsupported ebml read version");
+        }
+
+        if doc_type != "matroska" && doc_type != "webm" {
+            return unsupported_error("mkv: unsupported document type");
+        }
+
+        Ok(EbmlHeaderElement { doc_type })
+    }
+}
+
+/// A reference to a top-level element.
+#[derive(Debug)]
+pub struct SeekElement {
+    pub etype: ElementType,
+    /// The position of the element relative to the start of the segment's data.
+    pub pos: u64,
+}
+
+/// The meta seek information. An index of top-level elements.
+#[derive(Debug, Default)]
+pub struct SeekHeadElement {
+    pub seeks: Vec<SeekElement>,
+}
+
+impl Element for SeekHeadElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut seeks = Vec::new();
+
+        while let Some(header) = it.next()? {
+            if header.etype != ElementType::Seek {
+                continue;
+            }
+
+            let mut seek_it = ElementIterator::new(it.inner_mut(), header);
+
+            let mut id = None;
+            let mut pos = None;
+
+            while let Some(header) = seek_it.next()? {
+                match header.etype {
+                    ElementType::SeekId => {
+                        // The ID is stored as binary data, including the length marker.
+                        let buf = seek_it.read_binary()?;
+                        id = Some(buf.iter().fold(0, |id, &b| (id << 8) | u32::from(b)));
+                    }
+                    ElementType::SeekPosition => pos = Some(seek_it.read_unsigned()?),
+                    _ => (),
+                }
+            }
+
+            if let (Some(id), Some(pos)) = (id, pos) {
+                seeks.push(SeekElement { etype: ElementType::from(id), pos });
+            }
+        }
+
+        Ok(SeekHeadElement { seeks })
+    }
+}
+
+/// The segment information.
+#[derive(Debug)]
+pub struct InfoElement {
+    /// The number of nanoseconds per segment tick.
+    pub timestamp_scale: u64,
+    /// The duration of the segment in segment ticks.
+    pub duration: Option<f64>,
+    pub title: Option<String>,
+    pub muxing_app: Option<String>,
+    pub writing_app: Option<String>,
+}
+
+impl Default for InfoElement {
+    fn default() -> Self {
+        InfoElement {
+            timestamp_scale: DEFAULT_TIMESTAMP_SCALE,
+            duration: None,
+            title: None,
+            muxing_app: None,
+            writing_app: None,
+        }
+    }
+}
+
+impl Element for InfoElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut info = InfoElement::default();
+
+        while let Some(header) = it.next()? {
+            match header.etype {
+                ElementType::TimestampScale => {
+                    info.timestamp_scale = it.read_unsigned()?;
+
+                    if info.timestamp_scale == 0 {
+                        return decode_error("mkv: invalid timestamp scale");
+                    }
+                }
+                ElementType::Duration => info.duration = Some(it.read_float()?),
+                ElementType::Title => info.title = Some(it.read_string()?),
+                ElementType::MuxingApp => info.muxing_app = Some(it.read_string()?),
+                ElementType::WritingApp => info.writing_app = Some(it.read_string()?),
+                _ => (),
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+/// The audio settings of a track.
+#[derive(Debug)]
+pub struct AudioElement {
+    pub sampling_frequency: f64,
+    pub output_sampling_frequency: Option<f64>,
+    pub channels: u64,
+    pub bit_depth: Option<u64>,
+}
+
+impl Element for AudioElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut audio = AudioElement {
+            sampling_frequency: 8000.0,
+            output_sampling_frequency: None,
+            channels: 1,
+            bit_depth: None,
+        };
+
+        while let Some(header) = it.next()? {
+            match header.etype {
+                ElementType::SamplingFrequency => audio.sampling_frequency = it.read_float()?,
+                ElementType::OutputSamplingFrequency => {
+                    audio.output_sampling_frequency = Some(it.read_float()?)
+                }
+                ElementType::Channels => audio.channels = it.read_unsigned()?,
+                ElementType::BitDepth => audio.bit_depth = Some(it.read_unsigned()?),
+                _ => (),
+            }
+        }
+
+        Ok(audio)
+    }
+}
+
+/// The compression applied to the frames of a track.
+#[derive(Debug)]
+pub struct ContentCompressionElement {
+    pub algo: u64,
+    pub settings: Option<Box<[u8]>>,
+}
+
+/// The content encodings of a track. Only compression is supported.
+#[derive(Debug, Default)]
+pub struct ContentEncodingsElement {
+    pub compression: Option<ContentCompressionElement>,
+    /// True if an encoding other than compression, such as encryption, is used.
+    pub is_encrypted: bool,
+}
+
+impl Element for ContentEncodingsElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut encodings = ContentEncodingsElement::default();
+
+        while let Some(header) = it.next()? {
+            if header.etype != ElementType::ContentEncoding {
+                continue;
+            }
+
+            if encodings.compression.is_some() {
+                return unsupported_error("mkv: multiple content encodings are not supported");
+            }
+
+            // The content encoding may contain a content compression element, or a content
+            // encryption element.
+            let mut encoding = ElementIterator::new(it.inner_mut(), header);
+
+            let mut has_compression = false;
+
+            while let Some(header) = encoding.next()? {
+                if header.etype != ElementType::ContentCompression {
+                    continue;
+                }
+
+                let mut compression = ElementIterator::new(encoding.inner_mut(), header);
+
+                let mut algo = 0;
+                let mut settings = None;
+
+                while let Some(header) = compression.next()? {
+                    match header.etype {
+                        ElementType::ContentCompAlgo => algo = compression.read_unsigned()?,
+                        ElementType::ContentCompSettings => {
+                            settings = Some(compression.read_binary()?)
+                        }
+                        _ => (),
+                    }
+                }
+
+                encodings.compression = Some(ContentCompressionElement { algo, settings });
+                has_compression = true;
+            }
+
+            if !has_compression {
+                encodings.is_encrypted = true;
+            }
+        }
+
+        Ok(encodings)
+    }
+}
+
+/// A track entry.
+#[derive(Debug)]
+pub struct TrackElement {
+    pub number: u64,
+    pub uid: Option<u64>,
+    pub track_type: u64,
+    pub flag_default: bool,
+    /// The duration of each frame in nanoseconds.
+    pub default_duration: Option<u64>,
+    pub language: Option<String>,
+    pub codec_id: String,
+    pub codec_private: Option<Box<[u8]>>,
+    /// The decoder delay in nanoseconds.
+    pub codec_delay: Option<u64>,
+    /// The seek pre-roll in nanoseconds.
+    pub seek_pre_roll: Option<u64>,
+    pub audio: Option<AudioElement>,
+    pub encodings: Option<ContentEncodingsElement>,
+}
+
+impl Element for TrackElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut number = None;
+        let mut track = TrackElement {
+            number: 0,
+            uid: None,
+            track_type: 0,
+            flag_default: true,
+            default_duration: None,
+            language: None,
+            codec_id: String::new(),
+            codec_private: None,
+            codec_delay: None,
+            seek_pre_roll: None,
+            audio: None,
+            encodings: None,
+        };
+
+        while let Some(header) = it.next()? {
+            match header.etype {
+                ElementType::TrackNumber => number = Some(it.read_unsigned()?),
+                ElementType::TrackUid => track.uid = Some(it.read_unsigned()?),
+                ElementType::TrackType => track.track_type = it.read_unsigned()?,
+                ElementType::FlagDefault => track.flag_default = it.read_unsigned()? != 0,
+                ElementType::DefaultDuration => {
+                    track.default_duration = Some(it.read_unsigned()?)
+                }
+                ElementType::Language => track.language = Some(it.read_string()?),
+                ElementType::CodecId => track.codec_id = it.read_string()?,
+                ElementType::CodecPrivate => track.codec_private = Some(it.read_binary()?),
+                ElementType::CodecDelay => track.codec_delay = Some(it.read_unsigned()?),
+                ElementType::SeekPreRoll => track.seek_pre_roll = Some(it.read_unsigned()?),
+                ElementType::Audio => track.audio = Some(it.read_element()?),
+                ElementType::ContentEncodings => track.encodings = Some(it.read_element()?),
+                _ => (),
+            }
+        }
+
+        track.number = match number {
+            Some(number) if number > 0 => number,
+            _ => return decode_error("mkv: missing or invalid track number"),
+        };
+
+        Ok(track)
+    }
+}
+
+/// The track entries of a segment.
+#[derive(Debug, Default)]
+pub struct TracksElement {
+    pub tracks: Vec<TrackElement>,
+}
+
+impl Element for TracksElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut tracks = Vec::new();
+
+        while let Some(header) = it.next()? {
+            if header.etype == ElementType::TrackEntry {
+                tracks.push(it.read_element()?);
+            }
+        }
+
+        Ok(TracksElement { tracks })
+    }
+}
+
+/// The position of a cue point within a track.
+#[derive(Debug)]
+pub struct CueTrackPositionElement {
+    pub track: u64,
+    /// The position of the cluster relative to the start of the segment's data.
+    pub cluster_pos: u64,
+}
+
+/// A cue point.
+#[derive(Debug)]
+pub struct CuePointElement {
+    /// The timestamp of the cue point in segment ticks.
+    pub time: u64,
+    pub positions: Vec<CueTrackPositionElement>,
+}
+
+/// The cueing data (seek index) of a segment.
+#[derive(Debug, Default)]
+pub struct CuesElement {
+    pub points: Vec<CuePointElement>,
+}
+
+impl Element for CuesElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut points = Vec::new();
+
+        while let Some(header) = it.next()? {
+            if header.etype != ElementType::CuePoint {
+                continue;
+            }
+
+            let mut point_it = ElementIterator::new(it.inner_mut(), header);
+
+            let mut time = None;
+            let mut positions = Vec::new();
+
+            while let Some(header) = point_it.next()? {
+                match header.etype {
+                    ElementType::CueTime => time = Some(point_it.read_unsigned()?),
+                    ElementType::CueTrackPositions => {
+                        let mut pos_it = ElementIterator::new(point_it.inner_mut(), header);
+
+                        let mut track = None;
+                        let mut cluster_pos = None;
+
+                        while let Some(header) = pos_it.next()? {
+                            match header.etype {
+                                ElementType::CueTrack => track = Some(pos_it.read_unsigned()?),
+                                ElementType::CueClusterPosition => {
+                                    cluster_pos = Some(pos_it.read_unsigned()?)
+                                }
+                                _ => (),
+                            }
+                        }
+
+                        if let (Some(track), Some(cluster_pos)) = (track, cluster_pos) {
+                            positions.push(CueTrackPositionElement { track, cluster_pos });
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            match time {
+                Some(time) => points.push(CuePointElement { time, positions }),
+                None => debug!("skipping cue point without a time"),
+            }
+        }
+
+        Ok(CuesElement { points })
+    }
+}
+
+/// A chapter title.
+#[derive(Debug)]
+pub struct ChapterDisplayElement {
+    pub string: String,
+}
+
+/// A chapter atom. Atoms may be nested.
+#[derive(Debug)]
+pub struct ChapterAtomElement {
+    pub uid: Option<u64>,
+    /// The start time of the chapter in nanoseconds.
+    pub time_start: u64,
+    /// The end time of the chapter in nanoseconds.
+    pub time_end: Option<u64>,
+    pub hidden: bool,
+    pub enabled: bool,
+    pub displays: Vec<ChapterDisplayElement>,
+    pub atoms: Vec<ChapterAtomElement>,
+}
+
+impl Element for ChapterAtomElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(&mut *reader, header);
+
+        let mut atom = ChapterAtomElement {
+            uid: None,
+            time_start: 0,
+            time_end: None,
+            hidden: false,
+            enabled: true,
+            displays: Vec::new(),
+            atoms: Vec::new(),
+        };
+
+        while let Some(header) = it.next()? {
+            match header.etype {
+                ElementType::ChapterUid => atom.uid = Some(it.read_unsigned()?),
+                ElementType::ChapterTimeStart => atom.time_start = it.read_unsigned()?,
+                ElementType::ChapterTimeEnd => atom.time_end = Some(it.read_unsigned()?),
+                ElementType::ChapterFlagHidden => atom.hidden = it.read_unsigned()? != 0,
+                ElementType::ChapterFlagEnabled => atom.enabled = it.read_unsigned()? != 0,
+                ElementType::ChapterDisplay => {
+                    let mut display_it = ElementIterator::new(it.inner_mut(), header);
+
+                    while let Some(header) = display_it.next()? {
+                        if header.etype == ElementType::ChapString {
+                            let string = display_it.read_string()?;
+                            atom.displays.push(ChapterDisplayElement { string });
+                        }
+                    }
+                }
+                ElementType::ChapterAtom => {
+                    // Chapter atoms nest, so read the child atom with the parent's reader to
+                    // avoid an unbounded chain of reader types.
+                    atom.atoms.push(ChapterAtomElement::read(&mut **it.inner_mut(), header)?);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(atom)
+    }
+}
+
+/// An edition of chapters.
+#[derive(Debug)]
+pub struct EditionElement {
+    pub default: bool,
+    pub hidden: bool,
+    pub atoms: Vec<ChapterAtomElement>,
+}
+
+/// The chapters of a segment.
+#[derive(Debug, Default)]
+pub struct ChaptersElement {
+    pub editions: Vec<EditionElement>,
+}
+
+impl Element for ChaptersElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut editions = Vec::new();
+
+        while let Some(header) = it.next()? {
+            if header.etype != ElementType::EditionEntry {
+                continue;
+            }
+
+            let mut edition_it = ElementIterator::new(it.inner_mut(), header);
+
+            let mut edition = EditionElement { default: false, hidden: false, atoms: Vec::new() };
+
+            while let Some(header) = edition_it.next()? {
+                match header.etype {
+                    ElementType::EditionFlagDefault => {
+                        edition.default = edition_it.read_unsigned()? != 0
+                    }
+                    ElementType::EditionFlagHidden => {
+                        edition.hidden = edition_it.read_unsigned()? != 0
+                    }
+                    ElementType::ChapterAtom => edition.atoms.push(edition_it.read_element()?),
+                    _ => (),
+                }
+            }
+
+            editions.push(edition);
+        }
+
+        Ok(ChaptersElement { editions })
+    }
+}
+
+/// A simple tag. Simple tags may be nested.
+#[derive(Debug)]
+pub struct SimpleTagElement {
+    pub name: String,
+    pub string: Option<String>,
+    pub binary: Option<Box<[u8]>>,
+    pub tags: Vec<SimpleTagElement>,
+}
+
+impl Element for SimpleTagElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(&mut *reader, header);
+
+        let mut tag = SimpleTagElement {
+            name: String::new(),
+            string: None,
+            binary: None,
+            tags: Vec::new(),
+        };
+
+        while let Some(header) = it.next()? {
+            match header.etype {
+                ElementType::TagName => tag.name = it.read_string()?,
+                ElementType::TagString => tag.string = Some(it.read_string()?),
+                ElementType::TagBinary => tag.binary = Some(it.read_binary()?),
+                ElementType::SimpleTag => {
+                    // Simple tags nest, see the note for chapter atoms.
+                    tag.tags.push(SimpleTagElement::read(&mut **it.inner_mut(), header)?);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(tag)
+    }
+}
+
+/// A tag, a set of simple tags that apply to a target.
+#[derive(Debug)]
+pub struct TagElement {
+    /// The logical level of the target. For example, 50 for an album, or 30 for a track.
+    pub target_type_value: u64,
+    pub tags: Vec<SimpleTagElement>,
+}
+
+/// The tags of a segment.
+#[derive(Debug, Default)]
+pub struct TagsElement {
+    pub tags: Vec<TagElement>,
+}
+
+impl Element for TagsElement {
+    fn read<B: ReadBytes>(reader: &mut B, header: ElementHeader) -> Result<Self> {
+        let mut it = ElementIterator::new(reader, header);
+
+        let mut tags = Vec::new();
+
+        while let Some(header) = it.next()? {
+            if header.etype != ElementType::Tag {
+                continue;
+            }
+
+            let mut tag_it = ElementIterator::new(it.inner_mut(), header);
+
+            let mut tag = TagElement { target_type_value: 50, tags: Vec::new() };
+
+            while let Some(header) = tag_it.next()? {
+                match header.etype {
+                    ElementType::Targets => {
+                        let mut targets_it = ElementIterator::new(tag_it.inner_mut(), header);
+
+                        while let Some(header) = targets_it.next()? {
+                            if header.etype == ElementType::TargetTypeValue {
+                                tag.target_type_value = targets_it.read_unsigned()?;
+                            }
+                        }
+                    }
+                    ElementType::SimpleTag => tag.tags.push(tag_it.read_element()?),
+                    _ => (),
+                }
+            }
+
+            tags.push(tag);
+        }
+
+        Ok(TagsElement { tags })
+    }
+}
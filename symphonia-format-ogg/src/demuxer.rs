@@ -286,6 +286,11 @@ impl OggReader {
             self.consume_logical_packet();
         };
 
+        // The mappers may track packet timestamps, reset them since packets were skipped.
+        for mapper in self.mappers.values_mut() {
+            mapper.reset();
+        }
+
         debug!(
             "seeked track={:#x} to packet_ts={} (delta={})",
             serial, actual_ts, actual_ts as i64 - required_ts as i64);
@@ -293,6 +298,40 @@ impl OggReader {
         Ok(SeekedTo { track_id: serial, actual_ts, required_ts })
     }
 
+    /// Scans the end of the media source for the timestamp of the last page of each logical stream
+    /// in `serials`. The position of the reader is restored afterwards.
+    fn scan_end_timestamps(&mut self, serials: &[u32]) -> Result<BTreeMap<u32, u64>> {
+        // The maximum size of an OGG page is slightly less than 64kB. Scanning the final 128kB
+        // should therefore find the last page of each logical stream in most cases.
+        const SCAN_LEN: u64 = 128 * 1024;
+
+        let mut end_ts = BTreeMap::new();
+
+        let len = match self.reader.len() {
+            Some(len) => len,
+            None => return Ok(end_ts),
+        };
+
+        let original_pos = self.reader.pos();
+
+        let scan_pos = len.saturating_sub(SCAN_LEN).max(original_pos);
+
+        self.reader.seek(SeekFrom::Start(scan_pos))?;
+
+        // Pages are read until the end of the media source is reached, which is expected to
+        // terminate the loop with an error.
+        while let Ok(page) = resync_page(&mut self.reader) {
+            // A timestamp of -1 indicates no packets were completed on the page.
+            if serials.contains(&page.header.serial) && page.header.ts != u64::MAX {
+                end_ts.insert(page.header.serial, page.header.ts);
+            }
+        }
+
+        self.reader.seek(SeekFrom::Start(original_pos))?;
+
+        Ok(end_ts)
+    }
+
     fn start_new_physical_stream(&mut self) -> Result<()> {
         // The new mapper set.
         let mut mappers = BTreeMap::<u32, Box<dyn mappings::Mapper>>::new();
@@ -356,9 +395,27 @@ impl OggReader {
         // First, clear the existing track listing.
         self.tracks.clear();
 
+        // If the media source is seekable, the timestamp of the last page of each logical stream
+        // is the total number of frames in that stream.
+        let end_ts = if self.reader.is_seekable() {
+            let serials = mappers.keys().copied().collect::<Vec<u32>>();
+            self.scan_end_timestamps(&serials)?
+        }
+        else {
+            Default::default()
+        };
+
         // Second, add a track for all mappers.
         for (&serial, mapper) in mappers.iter() {
-            self.tracks.push(Track::new(serial, mapper.codec().clone()));
+            let mut codec_params = mapper.codec().clone();
+
+            if codec_params.n_frames.is_none() {
+                if let Some(&n_frames) = end_ts.get(&serial) {
+                    codec_params.with_n_frames(n_frames);
+                }
+            }
+
+            self.tracks.push(Track::new(serial, codec_params));
 
             // Warn if the track is not ready. This should not happen if the physical stream was
             // muxed properly.
@@ -371,6 +428,12 @@ impl OggReader {
         // effectively removes all the logical streams from the previous physical stream.
         self.streams.retain(|serial, _| mappers.contains_key(serial));
 
+        // The first bitstream packet was mapped above to detect the end of the header packets, but
+        // it was not consumed. Reset the mappers so that it will be mapped again.
+        for mapper in mappers.values_mut() {
+            mapper.reset();
+        }
+
         // Fourth, replace the previous set of mappers with the new set.
         self.mappers = mappers;
 
@@ -395,8 +458,35 @@ impl QueryDescriptor for OggReader {
         ]
     }
 
-    fn score(_context: &[u8]) -> u8 {
-        255
+    fn score(context: &[u8]) -> u8 {
+        // The page header must be valid.
+        let header = match read_page_header(&mut BufReader::new(context)) {
+            Ok(header) => header,
+            _ => return 0,
+        };
+
+        // If the entire page is contained within the context, then verify the page's CRC.
+        let data_pos = OGG_PAGE_HEADER_SIZE + usize::from(header.n_segments);
+
+        if context.len() < data_pos {
+            return 128;
+        }
+
+        let data_len: usize =
+            context[OGG_PAGE_HEADER_SIZE..data_pos].iter().map(|&len| usize::from(len)).sum();
+
+        if context.len() < data_pos + data_len {
+            return 128;
+        }
+
+        // The page header CRC is zeroed when calculating the actual CRC.
+        let mut crc32 = Crc32::new(0);
+
+        crc32.process_buf_bytes(&context[..22]);
+        crc32.process_buf_bytes(&[0u8; 4]);
+        crc32.process_buf_bytes(&context[26..data_pos + data_len]);
+
+        if crc32.crc() == header.crc { 255 } else { 0 }
     }
 }
 
@@ -54,6 +54,9 @@ pub trait Mapper: Send {
     fn is_stream_ready(&self) -> bool {
         true
     }
+
+    /// Resets any packet-handling state. Called after the demuxer seeks.
+    fn reset(&mut self) {}
 }
 
 fn make_null_mapper() -> Option<Box<dyn Mapper>> {
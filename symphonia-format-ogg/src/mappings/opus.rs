@@ -143,14 +143,49 @@ pub fn detect(buf: &[u8]) -> Result<Option<Box<dyn Mapper>>> {
     let mapper = Box::new(OpusMapper {
         codec_params,
         need_comment: true,
+        page_ts: 0,
+        next_ts: 0,
     });
 
     Ok(Some(mapper))
 }
 
+/// Gets the duration of an Opus packet in samples at 48kHz from its table-of-contents (TOC) byte,
+/// and for packets with an arbitrary number of frames, the frame count byte.
+fn packet_duration(data: &[u8]) -> u64 {
+    let toc = match data.first() {
+        Some(&toc) => toc,
+        None => return 0,
+    };
+
+    let config = toc >> 3;
+
+    // The frame size is determined by the configuration number (RFC6716 Section 3.1).
+    let frame_size = match config {
+        0..=11  => [480, 960, 1920, 2880][usize::from(config & 0x3)],
+        12..=15 => [480, 960][usize::from(config & 0x1)],
+        _       => [120, 240, 480, 960][usize::from(config & 0x3)],
+    };
+
+    let n_frames = match toc & 0x3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => match data.get(1) {
+            Some(&count) => u64::from(count & 0x3f),
+            None => 0,
+        },
+    };
+
+    frame_size * n_frames
+}
+
 struct OpusMapper {
     codec_params: CodecParameters,
     need_comment: bool,
+    /// The base timestamp of the page the previous packet was read from.
+    page_ts: u64,
+    /// The timestamp of the next packet.
+    next_ts: u64,
 }
 
 impl Mapper for OpusMapper {
@@ -162,8 +197,20 @@ impl Mapper for OpusMapper {
     fn map_packet(&mut self, packet: &OggPacket) -> Result<MapResult> {
         // After the comment packet there should only be bitstream packets.
         if !self.need_comment {
-            // TODO: Decode the correct timestamp and duration.
-            Ok(MapResult::Bitstream(Bitstream { ts: 0, dur: 0 }))
+            // The base timestamp of a packet is the timestamp at the end of the previous page. When
+            // a packet from a new page is encountered, restart the timestamp from that page.
+            // Otherwise, the timestamp follows the previous packet.
+            if packet.base_ts != self.page_ts {
+                self.page_ts = packet.base_ts;
+                self.next_ts = packet.base_ts;
+            }
+
+            let ts = self.next_ts;
+            let dur = packet_duration(&packet.data);
+
+            self.next_ts += dur;
+
+            Ok(MapResult::Bitstream(Bitstream { ts, dur }))
         }
         else {
             // If the comment packet is still required, check if the packet is the comment packet.
@@ -185,4 +232,9 @@ impl Mapper for OpusMapper {
 
     }
 
+    fn reset(&mut self) {
+        self.page_ts = 0;
+        self.next_ts = 0;
+    }
+
 }
\ No newline at end of file
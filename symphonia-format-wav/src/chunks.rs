@@ -27,6 +27,43 @@ use symphonia_metadata::riff;
 
 use log::info;
 
+// The definition of these format identifiers can be found in mmreg.h of the Microsoft
+// Windows Platform SDK.
+pub const WAVE_FORMAT_PCM: u16        = 0x0001;
+// pub const WAVE_FORMAT_ADPCM: u16      = 0x0002;
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+pub const WAVE_FORMAT_ALAW: u16       = 0x0006;
+pub const WAVE_FORMAT_MULAW: u16      = 0x0007;
+pub const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+// These GUIDs identifiy the format of the data chunks. These definitions can be found in
+// ksmedia.h of the Microsoft Windows Platform SDK.
+pub const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] =
+    [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+        0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+    ];
+// pub const KSDATAFORMAT_SUBTYPE_ADPCM: [u8; 16] =
+//     [
+//         0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+//         0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+//     ];
+pub const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: [u8; 16] =
+    [
+        0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+        0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+    ];
+pub const KSDATAFORMAT_SUBTYPE_ALAW: [u8; 16] =
+    [
+        0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+        0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+    ];
+pub const KSDATAFORMAT_SUBTYPE_MULAW: [u8; 16] =
+    [
+        0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+        0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+    ];
+
 /// `ParseChunkTag` implements `parse_tag` to map between the 4-byte chunk identifier and the
 /// enumeration
 pub trait ParseChunkTag : Sized {
@@ -152,26 +189,28 @@ impl<P: ParseChunk> ChunkParser<P> {
     }
 }
 
-fn map_wave_channels(channel_mask: u32) -> Channels {
-    const SPEAKER_FRONT_LEFT: u32            = 0x1;
-    const SPEAKER_FRONT_RIGHT: u32           = 0x2;
-    const SPEAKER_FRONT_CENTER: u32          = 0x4;
-    const SPEAKER_LOW_FREQUENCY: u32         = 0x8;
-    const SPEAKER_BACK_LEFT: u32             = 0x10;
-    const SPEAKER_BACK_RIGHT: u32            = 0x20;
-    const SPEAKER_FRONT_LEFT_OF_CENTER: u32  = 0x40;
-    const SPEAKER_FRONT_RIGHT_OF_CENTER: u32 = 0x80;
-    const SPEAKER_BACK_CENTER: u32           = 0x100;
-    const SPEAKER_SIDE_LEFT: u32             = 0x200;
-    const SPEAKER_SIDE_RIGHT: u32            = 0x400;
-    const SPEAKER_TOP_CENTER: u32            = 0x800;
-    const SPEAKER_TOP_FRONT_LEFT: u32        = 0x1000;
-    const SPEAKER_TOP_FRONT_CENTER: u32      = 0x2000;
-    const SPEAKER_TOP_FRONT_RIGHT: u32       = 0x4000;
-    const SPEAKER_TOP_BACK_LEFT: u32         = 0x8000;
-    const SPEAKER_TOP_BACK_CENTER: u32       = 0x10000;
-    const SPEAKER_TOP_BACK_RIGHT: u32        = 0x20000;
+// The definition of these speaker positions can be found in ksmedia.h of the Microsoft Windows
+// Platform SDK.
+const SPEAKER_FRONT_LEFT: u32            = 0x1;
+const SPEAKER_FRONT_RIGHT: u32           = 0x2;
+const SPEAKER_FRONT_CENTER: u32          = 0x4;
+const SPEAKER_LOW_FREQUENCY: u32         = 0x8;
+const SPEAKER_BACK_LEFT: u32             = 0x10;
+const SPEAKER_BACK_RIGHT: u32            = 0x20;
+const SPEAKER_FRONT_LEFT_OF_CENTER: u32  = 0x40;
+const SPEAKER_FRONT_RIGHT_OF_CENTER: u32 = 0x80;
+const SPEAKER_BACK_CENTER: u32           = 0x100;
+const SPEAKER_SIDE_LEFT: u32             = 0x200;
+const SPEAKER_SIDE_RIGHT: u32            = 0x400;
+const SPEAKER_TOP_CENTER: u32            = 0x800;
+const SPEAKER_TOP_FRONT_LEFT: u32        = 0x1000;
+const SPEAKER_TOP_FRONT_CENTER: u32      = 0x2000;
+const SPEAKER_TOP_FRONT_RIGHT: u32       = 0x4000;
+const SPEAKER_TOP_BACK_LEFT: u32         = 0x8000;
+const SPEAKER_TOP_BACK_CENTER: u32       = 0x10000;
+const SPEAKER_TOP_BACK_RIGHT: u32        = 0x20000;
 
+fn map_wave_channels(channel_mask: u32) -> Channels {
     let mut channels = Channels::empty();
 
     if channel_mask & SPEAKER_FRONT_LEFT            != 0 { channels |= Channels::FRONT_LEFT;         }
@@ -196,6 +235,44 @@ fn map_wave_channels(channel_mask: u32) -> Channels {
     channels
 }
 
+/// Maps `Channels` to a WAVE channel mask. Returns `None` if a channel cannot be represented in a
+/// WAVE channel mask.
+pub fn wave_channel_mask(channels: Channels) -> Option<u32> {
+    const SPEAKERS: [(Channels, u32); 18] = [
+        (Channels::FRONT_LEFT,         SPEAKER_FRONT_LEFT),
+        (Channels::FRONT_RIGHT,        SPEAKER_FRONT_RIGHT),
+        (Channels::FRONT_CENTRE,       SPEAKER_FRONT_CENTER),
+        (Channels::LFE1,               SPEAKER_LOW_FREQUENCY),
+        (Channels::REAR_LEFT,          SPEAKER_BACK_LEFT),
+        (Channels::REAR_RIGHT,         SPEAKER_BACK_RIGHT),
+        (Channels::FRONT_LEFT_CENTRE,  SPEAKER_FRONT_LEFT_OF_CENTER),
+        (Channels::FRONT_RIGHT_CENTRE, SPEAKER_FRONT_RIGHT_OF_CENTER),
+        (Channels::REAR_CENTRE,        SPEAKER_BACK_CENTER),
+        (Channels::SIDE_LEFT,          SPEAKER_SIDE_LEFT),
+        (Channels::SIDE_RIGHT,         SPEAKER_SIDE_RIGHT),
+        (Channels::TOP_CENTRE,         SPEAKER_TOP_CENTER),
+        (Channels::TOP_FRONT_LEFT,     SPEAKER_TOP_FRONT_LEFT),
+        (Channels::TOP_FRONT_CENTRE,   SPEAKER_TOP_FRONT_CENTER),
+        (Channels::TOP_FRONT_RIGHT,    SPEAKER_TOP_FRONT_RIGHT),
+        (Channels::TOP_REAR_LEFT,      SPEAKER_TOP_BACK_LEFT),
+        (Channels::TOP_REAR_CENTRE,    SPEAKER_TOP_BACK_CENTER),
+        (Channels::TOP_REAR_RIGHT,     SPEAKER_TOP_BACK_RIGHT),
+    ];
+
+    let channel_mask = SPEAKERS
+        .iter()
+        .filter(|(channel, _)| channels.contains(*channel))
+        .fold(0, |mask, (_, speaker)| mask | speaker);
+
+    // All channels must have been mapped.
+    if map_wave_channels(channel_mask) == channels {
+        Some(channel_mask)
+    }
+    else {
+        None
+    }
+}
+
 pub enum WaveFormatData {
     Pcm(WaveFormatPcm),
     IeeeFloat(WaveFormatIeeeFloat),
@@ -399,34 +476,6 @@ impl WaveFormatChunk {
         let mut sub_format_guid = [0u8; 16];
         reader.read_buf_exact(&mut sub_format_guid)?;
 
-        // These GUIDs identifiy the format of the data chunks. These definitions can be found in
-        // ksmedia.h of the Microsoft Windows Platform SDK.
-        const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] =
-            [
-                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
-                0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
-            ];
-        // const KSDATAFORMAT_SUBTYPE_ADPCM: [u8; 16] =
-        //     [
-        //         0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
-        //         0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
-        //     ];
-        const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: [u8; 16] =
-            [
-                0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
-                0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
-            ];
-        const KSDATAFORMAT_SUBTYPE_ALAW: [u8; 16] =
-            [
-                0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
-                0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
-            ];
-        const KSDATAFORMAT_SUBTYPE_MULAW: [u8; 16] =
-            [
-                0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
-                0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
-            ];
-
         // Verify support based on the format GUID.
         let codec = match sub_format_guid {
             KSDATAFORMAT_SUBTYPE_PCM => {
@@ -544,15 +593,6 @@ impl ParseChunk for WaveFormatChunk {
         let block_align = reader.read_u16()?;
         let bits_per_sample = reader.read_u16()?;
 
-        // The definition of these format identifiers can be found in mmreg.h of the Microsoft
-        // Windows Platform SDK.
-        const WAVE_FORMAT_PCM: u16        = 0x0001;
-        // const WAVE_FORMAT_ADPCM: u16      = 0x0002;
-        const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
-        const WAVE_FORMAT_ALAW: u16       = 0x0006;
-        const WAVE_FORMAT_MULAW: u16      = 0x0007;
-        const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
-
         let format_data = match format {
             // The PCM Wave Format
             WAVE_FORMAT_PCM => {
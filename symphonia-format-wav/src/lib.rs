@@ -21,9 +21,12 @@ use symphonia_core::probe::{Descriptor, Instantiate, QueryDescriptor};
 use log::{debug, error};
 
 mod chunks;
+mod writer;
 
 use chunks::*;
 
+pub use writer::WavWriter;
+
 /// WAVE is actually a RIFF stream, with a "RIFF" ASCII stream marker.
 const WAVE_STREAM_MARKER: [u8; 4] = *b"RIFF";
 
@@ -43,6 +46,7 @@ pub struct WavReader {
     metadata: MetadataLog,
     frame_len: u16,
     data_start_pos: u64,
+    pool: BufPool,
 }
 
 impl QueryDescriptor for WavReader {
@@ -59,8 +63,13 @@ impl QueryDescriptor for WavReader {
         ]
     }
 
-    fn score(_context: &[u8]) -> u8 {
-        255
+    fn score(context: &[u8]) -> u8 {
+        // The RIFF form must be a WAVE form.
+        match context.get(8..12) {
+            Some(form) if form == b"WAVE" => 255,
+            Some(_) => 0,
+            None => 128,
+        }
     }
 }
 
@@ -147,6 +156,7 @@ impl FormatReader for WavReader {
                         metadata,
                         frame_len,
                         data_start_pos,
+                        pool: Default::default(),
                     });
                 }
             }
@@ -162,12 +172,15 @@ impl FormatReader for WavReader {
 
         // Read up-to WAVE_MAX_FRAMES_PER_PACKET number of frames per packet.
         let packet_len = WAVE_MAX_FRAMES_PER_PACKET * u64::from(self.frame_len);
-        let packet_buf = self.reader.read_boxed_slice(packet_len as usize)?;
+        let mut packet_buf = self.pool.get(packet_len as usize);
+
+        let len = self.reader.read_buf(&mut packet_buf)?;
+        packet_buf.truncate(len);
 
         // The packet duration is the length of the packet in bytes divided by the length per frame.
-        let dur = packet_buf.len() as u64 / u64::from(self.frame_len);
+        let dur = len as u64 / u64::from(self.frame_len);
 
-        Ok(Packet::new_from_boxed_slice(0, pts, dur, packet_buf))
+        Ok(Packet::new_from_shared_buf(0, pts, dur, packet_buf.freeze()))
     }
 
     fn metadata(&mut self) -> Metadata<'_> {
@@ -0,0 +1,516 @@
+// Symphonia
+// Copyright (c) 2021 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use symphonia_core::audio::Channels;
+use symphonia_core::codecs::CodecParameters;
+use symphonia_core::codecs::{CODEC_TYPE_PCM_U8, CODEC_TYPE_PCM_S16LE, CODEC_TYPE_PCM_S24LE};
+use symphonia_core::codecs::{CODEC_TYPE_PCM_S32LE, CODEC_TYPE_PCM_F32LE, CODEC_TYPE_PCM_F64LE};
+use symphonia_core::codecs::{CODEC_TYPE_PCM_ALAW, CODEC_TYPE_PCM_MULAW};
+use symphonia_core::errors::{Result, encode_error, limit_error, unsupported_error};
+use symphonia_core::formats::{FormatWriter, Packet, Track};
+use symphonia_core::io::MediaSink;
+use symphonia_core::meta::MetadataRevision;
+use symphonia_metadata::riff;
+
+use log::{debug, info};
+
+use crate::chunks::*;
+
+/// The total size of the RIFF chunk, and all sub-chunks, must fit in 32-bits.
+const WAVE_MAX_RIFF_LEN: u64 = u32::MAX as u64;
+
+/// The encoding of the audio samples, and the format chunk required to describe it.
+struct WaveFormat {
+    /// The format tag written in the format chunk.
+    format: u16,
+    /// The sub-format GUID, if the extensible format is used.
+    sub_format_guid: Option<[u8; 16]>,
+    /// The number of channels.
+    n_channels: u16,
+    /// The WAVE channel mask.
+    channel_mask: u32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The number of bits per sample as stored in the stream.
+    bits_per_coded_sample: u16,
+    /// The number of valid bits per sample.
+    bits_per_sample: u16,
+}
+
+impl WaveFormat {
+    fn new(params: &CodecParameters) -> Result<Self> {
+        // Select the format, and sample width, for the codec.
+        let (format, sub_format_guid, bits_per_coded_sample) = match params.codec {
+            CODEC_TYPE_PCM_U8    => (WAVE_FORMAT_PCM, KSDATAFORMAT_SUBTYPE_PCM, 8),
+            CODEC_TYPE_PCM_S16LE => (WAVE_FORMAT_PCM, KSDATAFORMAT_SUBTYPE_PCM, 16),
+            CODEC_TYPE_PCM_S24LE => (WAVE_FORMAT_PCM, KSDATAFORMAT_SUBTYPE_PCM, 24),
+            CODEC_TYPE_PCM_S32LE => (WAVE_FORMAT_PCM, KSDATAFORMAT_SUBTYPE_PCM, 32),
+            CODEC_TYPE_PCM_F32LE => (WAVE_FORMAT_IEEE_FLOAT, KSDATAFORMAT_SUBTYPE_IEEE_FLOAT, 32),
+            CODEC_TYPE_PCM_F64LE => (WAVE_FORMAT_IEEE_FLOAT, KSDATAFORMAT_SUBTYPE_IEEE_FLOAT, 64),
+            CODEC_TYPE_PCM_ALAW  => (WAVE_FORMAT_ALAW, KSDATAFORMAT_SUBTYPE_ALAW, 8),
+            CODEC_TYPE_PCM_MULAW => (WAVE_FORMAT_MULAW, KSDATAFORMAT_SUBTYPE_MULAW, 8),
+            _ => return unsupported_error("codec is not supported by wave"),
+        };
+
+        let sample_rate = match params.sample_rate {
+            Some(sample_rate) if sample_rate > 0 => sample_rate,
+            _ => return unsupported_error("sample rate is required to write wave"),
+        };
+
+        let channels = match params.channels {
+            Some(channels) if channels.count() > 0 => channels,
+            _ => return unsupported_error("channels are required to write wave"),
+        };
+
+        let channel_mask = match wave_channel_mask(channels) {
+            Some(channel_mask) => channel_mask,
+            None => return unsupported_error("channel layout is not supported by wave"),
+        };
+
+        // The number of valid bits per sample may be less than the sample width for integer PCM.
+        let bits_per_sample = match params.bits_per_sample {
+            Some(bits) if format == WAVE_FORMAT_PCM => {
+                if bits == 0 || bits > u32::from(bits_per_coded_sample) {
+                    return unsupported_error("bits per sample exceeds the sample width");
+                }
+                bits as u16
+            }
+            _ => bits_per_coded_sample,
+        };
+
+        // The plain formats only support mono and stereo channel layouts, and full-width samples.
+        // Otherwise, the extensible format must be used.
+        let is_plain_layout = channels == Channels::FRONT_LEFT
+            || channels == Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+
+        let sub_format_guid = if is_plain_layout && bits_per_sample == bits_per_coded_sample {
+            None
+        }
+        else {
+            Some(sub_format_guid)
+        };
+
+        Ok(WaveFormat {
+            format,
+            sub_format_guid,
+            n_channels: channels.count() as u16,
+            channel_mask,
+            sample_rate,
+            bits_per_coded_sample,
+            bits_per_sample,
+        })
+    }
+
+    /// Gets the length of one audio frame in bytes.
+    fn block_align(&self) -> u16 {
+        self.n_channels * (self.bits_per_coded_sample / 8)
+    }
+
+    /// Returns true if a fact chunk is required. All formats, other than integer PCM, require
+    /// one.
+    fn needs_fact(&self) -> bool {
+        self.format != WAVE_FORMAT_PCM
+    }
+
+    /// Serializes the format chunk body.
+    fn to_bytes(&self) -> Vec<u8> {
+        let block_align = self.block_align();
+
+        let format = match self.sub_format_guid {
+            Some(_) => WAVE_FORMAT_EXTENSIBLE,
+            None => self.format,
+        };
+
+        let mut buf = Vec::with_capacity(40);
+        buf.extend_from_slice(&format.to_le_bytes());
+        buf.extend_from_slice(&self.n_channels.to_le_bytes());
+        buf.extend_from_slice(&self.sample_rate.to_le_bytes());
+        buf.extend_from_slice(&(self.sample_rate * u32::from(block_align)).to_le_bytes());
+        buf.extend_from_slice(&block_align.to_le_bytes());
+        buf.extend_from_slice(&self.bits_per_coded_sample.to_le_bytes());
+
+        match self.sub_format_guid {
+            Some(guid) => {
+                buf.extend_from_slice(&22u16.to_le_bytes());
+                buf.extend_from_slice(&self.bits_per_sample.to_le_bytes());
+                buf.extend_from_slice(&self.channel_mask.to_le_bytes());
+                buf.extend_from_slice(&guid);
+            }
+            // Non-PCM formats must have the extra data length field, even if there is no extra
+            // data.
+            None if self.format != WAVE_FORMAT_PCM => {
+                buf.extend_from_slice(&0u16.to_le_bytes());
+            }
+            None => (),
+        }
+
+        buf
+    }
+}
+
+/// Serializes the tags of a metadata revision into the body of a LIST chunk with the INFO form.
+/// Returns `None` if none of the tags can be represented as INFO chunks.
+fn info_list_to_bytes(metadata: &MetadataRevision) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+
+    for tag in metadata.tags() {
+        match riff::serialize(tag) {
+            Some((id, value)) => write_chunk(&mut buf, &id, &value),
+            None => info!("ignoring tag not representable in riff info: key={}", tag.key),
+        }
+    }
+
+    if buf.is_empty() {
+        return None;
+    }
+
+    let mut list = b"INFO".to_vec();
+    list.append(&mut buf);
+
+    Some(list)
+}
+
+/// Appends a chunk, including its header and any padding, to the buffer.
+fn write_chunk(buf: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    buf.extend_from_slice(id);
+    buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    buf.extend_from_slice(body);
+
+    // Chunks are aligned to 2-byte boundaries.
+    if body.len() & 0x1 == 1 {
+        buf.push(0);
+    }
+}
+
+/// WAVE (WAV) format writer.
+///
+/// `WavWriter` implements a muxer for the WAVE container format. A single PCM, IEEE float, A-law,
+/// or Mu-law track may be written. The extensible format is used if the channel layout is not mono
+/// or stereo, or if the number of valid bits per sample is less than the sample width.
+///
+/// If the `MediaSink` is seekable, the chunk lengths are updated when the writer is finalized.
+/// Otherwise, the lengths are calculated from the number of frames in the track's
+/// `CodecParameters`, or are set to the maximum length if the number of frames is unknown.
+pub struct WavWriter {
+    sink: Box<dyn MediaSink>,
+    track_id: u32,
+    frame_len: u16,
+    /// The position of the RIFF chunk within the sink.
+    riff_pos: u64,
+    /// The position of the fact chunk's frame count relative to the RIFF chunk, if present.
+    fact_offset: Option<u64>,
+    /// The position of the data chunk's contents relative to the RIFF chunk.
+    data_offset: u64,
+    /// The number of bytes written to the data chunk.
+    data_len: u64,
+}
+
+impl FormatWriter for WavWriter {
+
+    fn try_new(
+        mut sink: Box<dyn MediaSink>,
+        tracks: &[Track],
+        metadata: Option<&MetadataRevision>,
+    ) -> Result<Self> {
+        let track = match tracks {
+            [track] => track,
+            _ => return unsupported_error("wave only supports a single track"),
+        };
+
+        let format = WaveFormat::new(&track.codec_params)?;
+        let frame_len = format.block_align();
+
+        // If the sink is not seekable, the chunk lengths can only be known ahead of time if the
+        // number of frames is known.
+        let is_seekable = sink.is_seekable();
+
+        let data_len = match track.codec_params.n_frames {
+            Some(n_frames) if !is_seekable => Some(n_frames * u64::from(frame_len)),
+            _ => None,
+        };
+
+        let mut buf = Vec::new();
+
+        buf.extend_from_slice(b"RIFF");
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(b"WAVE");
+
+        write_chunk(&mut buf, b"fmt ", &format.to_bytes());
+
+        let fact_offset = if format.needs_fact() {
+            let n_frames = track.codec_params.n_frames.unwrap_or(0);
+            write_chunk(&mut buf, b"fact", &(n_frames.min(WAVE_MAX_RIFF_LEN) as u32).to_le_bytes());
+            Some(buf.len() as u64 - 4)
+        }
+        else {
+            None
+        };
+
+        if let Some(list) = metadata.and_then(info_list_to_bytes) {
+            write_chunk(&mut buf, b"LIST", &list);
+        }
+
+        let data_offset = buf.len() as u64 + 8;
+
+        // The length of the RIFF chunk includes everything after the RIFF chunk header, and the
+        // padding of the data chunk. If the lengths cannot be known, then they are set to the
+        // maximum.
+        let (riff_len, data_chunk_len) = match data_len {
+            Some(len) => {
+                let riff_len = data_offset - 8 + len + (len & 0x1);
+
+                if riff_len > WAVE_MAX_RIFF_LEN {
+                    return limit_error("wave data exceeds the maximum riff chunk length");
+                }
+
+                (riff_len as u32, len as u32)
+            }
+            None => (u32::MAX, u32::MAX),
+        };
+
+        buf[4..8].copy_from_slice(&riff_len.to_le_bytes());
+
+        buf.extend_from_slice(b"data");
+        buf.extend_from_slice(&data_chunk_len.to_le_bytes());
+
+        let riff_pos = if is_seekable { sink.stream_position()? } else { 0 };
+
+        sink.write_all(&buf)?;
+
+        debug!("wrote wave header: format={:#x}, len={}", format.format, buf.len());
+
+        Ok(WavWriter {
+            sink,
+            track_id: track.id,
+            frame_len,
+            riff_pos,
+            fact_offset,
+            data_offset,
+            data_len: 0,
+        })
+    }
+
+    fn write_packet(&mut self, packet: &Packet) -> Result<()> {
+        if packet.track_id() != self.track_id {
+            return unsupported_error("packet does not belong to the wave track");
+        }
+
+        let buf = packet.buf();
+
+        // Packets must only contain whole audio frames.
+        let n_frames = buf.len() / usize::from(self.frame_len);
+
+        if n_frames * usize::from(self.frame_len) != buf.len() {
+            return encode_error("packet length is not a multiple of the wave frame length");
+        }
+
+        let data_len = self.data_len + buf.len() as u64;
+
+        if self.data_offset - 8 + data_len > WAVE_MAX_RIFF_LEN {
+            return limit_error("wave data exceeds the maximum riff chunk length");
+        }
+
+        self.sink.write_all(buf)?;
+        self.data_len = data_len;
+
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        // The data chunk must be padded to a 2-byte boundary.
+        if self.data_len & 0x1 == 1 {
+            self.sink.write_all(&[0])?;
+        }
+
+        if self.sink.is_seekable() {
+            let end_pos = self.sink.stream_position()?;
+
+            let riff_len = self.data_offset - 8 + self.data_len + (self.data_len & 0x1);
+
+            // RIFF chunk length.
+            self.sink.seek(SeekFrom::Start(self.riff_pos + 4))?;
+            self.sink.write_all(&(riff_len as u32).to_le_bytes())?;
+
+            // Fact chunk frame count.
+            if let Some(fact_offset) = self.fact_offset {
+                let n_frames = self.data_len / u64::from(self.frame_len);
+
+                self.sink.seek(SeekFrom::Start(self.riff_pos + fact_offset))?;
+                self.sink.write_all(&(n_frames as u32).to_le_bytes())?;
+            }
+
+            // Data chunk length.
+            self.sink.seek(SeekFrom::Start(self.riff_pos + self.data_offset - 4))?;
+            self.sink.write_all(&(self.data_len as u32).to_le_bytes())?;
+
+            self.sink.seek(SeekFrom::Start(end_pos))?;
+        }
+
+        self.sink.flush()?;
+
+        Ok(())
+    }
+
+    fn into_inner(self: Box<Self>) -> Box<dyn MediaSink> {
+        self.sink
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Seek, SeekFrom, Write};
+    use std::sync::{Arc, Mutex};
+
+    use symphonia_core::audio::Channels;
+    use symphonia_core::codecs::{CodecParameters, CODEC_TYPE_PCM_F32LE, CODEC_TYPE_PCM_S16LE};
+    use symphonia_core::codecs::CODEC_TYPE_PCM_MULAW;
+    use symphonia_core::formats::{FormatOptions, FormatReader, FormatWriter, Packet, Track};
+    use symphonia_core::io::{MediaSink, MediaSourceStream};
+    use symphonia_core::meta::{MetadataBuilder, StandardTagKey, Tag, Value};
+
+    use super::{WaveFormat, WavWriter};
+    use crate::WavReader;
+
+    /// A seekable in-memory sink that can be read back after it is given to a writer.
+    #[derive(Clone, Default)]
+    struct MemorySink(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+    impl Write for MemorySink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for MemorySink {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.lock().unwrap().seek(pos)
+        }
+    }
+
+    impl MediaSink for MemorySink {
+        fn is_seekable(&self) -> bool {
+            true
+        }
+    }
+
+    fn write_and_read(params: CodecParameters, data: &[u8]) -> WavReader {
+        let mut builder = MetadataBuilder::new();
+        builder.add_tag(Tag::new(Some(StandardTagKey::TrackTitle), "TITLE", Value::from("Song")));
+        builder.add_tag(Tag::new(None, "ISBJ", Value::from("Subject")));
+        builder.add_tag(Tag::new(None, "not a riff key", Value::from("Ignored")));
+
+        let sink = MemorySink::default();
+        let tracks = [Track::new(0, params)];
+
+        let metadata = builder.metadata();
+        let mut writer = WavWriter::try_new(Box::new(sink.clone()), &tracks, Some(&metadata))
+            .unwrap();
+
+        // Write the data in two packets to exercise appending to the data chunk.
+        let (first, second) = data.split_at(data.len() / 2);
+        writer.write_packet(&Packet::new_from_slice(0, 0, 0, first)).unwrap();
+        writer.write_packet(&Packet::new_from_slice(0, 0, 0, second)).unwrap();
+        writer.finalize().unwrap();
+
+        let buf = sink.0.lock().unwrap().get_ref().clone();
+
+        let mss = MediaSourceStream::new(Box::new(Cursor::new(buf)), Default::default());
+        WavReader::try_new(mss, &FormatOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn verify_wav_writer_pcm() {
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_PCM_S16LE)
+            .with_sample_rate(44100)
+            .with_channels(Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+
+        let data: Vec<u8> = (0..400).map(|i| i as u8).collect();
+
+        let mut reader = write_and_read(params, &data);
+
+        let params = &reader.tracks()[0].codec_params;
+        assert!(params.codec == CODEC_TYPE_PCM_S16LE);
+        assert_eq!(params.sample_rate, Some(44100));
+        assert_eq!(params.bits_per_sample, Some(16));
+        assert_eq!(params.n_frames, Some(100));
+
+        {
+            let metadata = reader.metadata();
+            let tags = metadata.current().unwrap().tags();
+            assert_eq!(tags.len(), 2);
+            assert_eq!(tags[0].key, "INAM");
+            assert_eq!(tags[0].value.to_string(), "Song");
+            assert_eq!(tags[1].value.to_string(), "Subject");
+        }
+
+        assert_eq!(reader.next_packet().unwrap().buf(), &data[..]);
+    }
+
+    #[test]
+    fn verify_wav_writer_extensible_float() {
+        let channels = Channels::FRONT_LEFT
+            | Channels::FRONT_RIGHT
+            | Channels::FRONT_CENTRE
+            | Channels::LFE1
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT;
+
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_PCM_F32LE).with_sample_rate(48000).with_channels(channels);
+
+        // 4 frames of 6 channels of 32-bit samples.
+        let data = [0x3fu8; 4 * 6 * 4];
+
+        let mut reader = write_and_read(params, &data);
+
+        let params = &reader.tracks()[0].codec_params;
+        assert!(params.codec == CODEC_TYPE_PCM_F32LE);
+        assert_eq!(params.channels, Some(channels));
+        assert_eq!(params.n_frames, Some(4));
+
+        assert_eq!(reader.next_packet().unwrap().buf(), &data[..]);
+    }
+
+    #[test]
+    fn verify_wav_writer_extensible_mulaw() {
+        let channels = Channels::FRONT_LEFT | Channels::FRONT_RIGHT | Channels::FRONT_CENTRE;
+
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_PCM_MULAW).with_sample_rate(8000).with_channels(channels);
+
+        // The extensible format is used, and the sub-format GUID must be the standard Mu-law GUID.
+        let fmt = WaveFormat::new(&params).unwrap().to_bytes();
+
+        let guid = [
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+            0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+        ];
+
+        assert_eq!(fmt[24..], guid);
+
+        // 10 frames of 3 channels of 8-bit samples.
+        let data: Vec<u8> = (0..30).collect();
+
+        let mut reader = write_and_read(params, &data);
+
+        let params = &reader.tracks()[0].codec_params;
+        assert!(params.codec == CODEC_TYPE_PCM_MULAW);
+        assert_eq!(params.channels, Some(channels));
+        assert_eq!(params.n_frames, Some(10));
+
+        assert_eq!(reader.next_packet().unwrap().buf(), &data[..]);
+    }
+}
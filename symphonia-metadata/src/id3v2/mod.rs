@@ -388,8 +388,19 @@ impl QueryDescriptor for Id3v2Reader {
         &[ support_metadata!("id3v2", "ID3v2", &[], &[], &[ b"ID3" ]) ]
     }
 
-    fn score(_context: &[u8]) -> u8 {
-        255
+    fn score(context: &[u8]) -> u8 {
+        // The tag size is a 28-bit synchsafe integer, therefore the most-significant bit of each
+        // byte must be 0.
+        match context.get(6..10) {
+            Some(size) if size.iter().any(|&byte| byte & 0x80 != 0) => return 0,
+            Some(_) => (),
+            None => return 128,
+        }
+
+        match read_id3v2_header(&mut BufReader::new(context)) {
+            Ok(_) => 255,
+            Err(_) => 0,
+        }
     }
 }
 
@@ -60,7 +60,10 @@ lazy_static! {
 pub fn parse(tag: [u8; 4], buf: &[u8]) -> Tag {
     // TODO: Key should be checked that it only contains ASCII characters.
     let key = String::from_utf8_lossy(&tag);
-    let value = String::from_utf8_lossy(buf);
+
+    // INFO block values are null-terminated strings. Strip the null terminator(s), if present.
+    let len = buf.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+    let value = String::from_utf8_lossy(&buf[..len]);
 
     // Attempt to assign a standardized tag key.
     let std_tag = match RIFF_INFO_MAP.get(key.to_lowercase().as_str()) {
@@ -69,4 +72,83 @@ pub fn parse(tag: [u8; 4], buf: &[u8]) -> Tag {
     };
 
     Tag::new(std_tag, &key, Value::from(value))
-}
\ No newline at end of file
+}
+
+/// Get the RIFF INFO block identifier tag used to store a `StandardTagKey`, if one exists.
+fn info_id_for_std_key(std_key: StandardTagKey) -> Option<&'static [u8; 4]> {
+    let id = match std_key {
+        StandardTagKey::Album => b"IPRD",
+        StandardTagKey::Artist => b"IART",
+        StandardTagKey::Comment => b"ICMT",
+        StandardTagKey::Composer => b"IMUS",
+        StandardTagKey::Copyright => b"ICOP",
+        StandardTagKey::Date => b"ICRD",
+        StandardTagKey::EncodedBy => b"ITCH",
+        StandardTagKey::Encoder => b"ISFT",
+        StandardTagKey::Engineer => b"IENG",
+        StandardTagKey::Genre => b"IGNR",
+        StandardTagKey::Language => b"ILNG",
+        StandardTagKey::MediaFormat => b"ISRF",
+        StandardTagKey::OriginalDate => b"IDIT",
+        StandardTagKey::Producer => b"IPRO",
+        StandardTagKey::Rating => b"IRTD",
+        StandardTagKey::TrackNumber => b"IPRT",
+        StandardTagKey::TrackTitle => b"INAM",
+        StandardTagKey::TrackTotal => b"IFRM",
+        StandardTagKey::Writer => b"IWRI",
+        _ => return None,
+    };
+
+    Some(id)
+}
+
+/// Serialize a `Tag` into a RIFF INFO block identifier tag and the block's contents. Returns
+/// `None` if the `Tag` cannot be represented as a RIFF INFO block.
+pub fn serialize(tag: &Tag) -> Option<([u8; 4], Vec<u8>)> {
+    // Prefer the identifier for the standard tag key. Otherwise, the key itself may be used if it
+    // is a valid identifier.
+    let id = match tag.std_key.and_then(info_id_for_std_key) {
+        Some(id) => *id,
+        None => {
+            let key = tag.key.as_bytes();
+
+            if key.len() != 4 || !key.iter().all(u8::is_ascii_alphanumeric) {
+                return None;
+            }
+
+            [key[0], key[1], key[2], key[3]]
+        }
+    };
+
+    // Binary data and flags have no string representation.
+    let mut buf = match tag.value {
+        Value::Binary(_) | Value::Flag => return None,
+        ref value => value.to_string().into_bytes(),
+    };
+
+    // INFO block values are null-terminated strings.
+    buf.push(0);
+
+    Some((id, buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use symphonia_core::meta::StandardTagKey;
+
+    #[test]
+    fn verify_parse_null_terminated() {
+        let tag = parse(*b"INAM", b"Song\0");
+        assert!(matches!(tag.std_key, Some(StandardTagKey::TrackTitle)));
+        assert_eq!(tag.value.to_string(), "Song");
+
+        // Values padded with multiple null terminators, and values without a null terminator.
+        assert_eq!(parse(*b"INAM", b"Song\0\0").value.to_string(), "Song");
+        assert_eq!(parse(*b"INAM", b"Song").value.to_string(), "Song");
+        assert_eq!(parse(*b"INAM", b"\0").value.to_string(), "");
+
+        // Only trailing null terminators are stripped.
+        assert_eq!(parse(*b"INAM", b"A\0B\0").value.to_string(), "A\0B");
+    }
+}
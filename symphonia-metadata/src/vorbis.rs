@@ -85,6 +85,8 @@ lazy_static! {
         m.insert("productnumber"               , StandardTagKey::IdentPn);
         // TODO: Is Publisher a synonym for Label?
         m.insert("publisher"                   , StandardTagKey::Label);
+        m.insert("r128_album_gain"             , StandardTagKey::R128AlbumGain);
+        m.insert("r128_track_gain"             , StandardTagKey::R128TrackGain);
         m.insert("rating"                      , StandardTagKey::Rating);
         m.insert("releasecountry"              , StandardTagKey::ReleaseCountry);
         m.insert("remixer"                     , StandardTagKey::Remixer);
@@ -164,3 +166,126 @@ pub fn read_comment_no_framing<B : ReadBytes>(
 
     Ok(())
 }
+
+/// Get the canonical Vorbis Comment field name used to store a `StandardTagKey`, if one exists.
+fn comment_key_for_std_key(std_key: StandardTagKey) -> Option<&'static str> {
+    let key = match std_key {
+        StandardTagKey::Album                       => "ALBUM",
+        StandardTagKey::AlbumArtist                 => "ALBUMARTIST",
+        StandardTagKey::Arranger                    => "ARRANGER",
+        StandardTagKey::Artist                      => "ARTIST",
+        StandardTagKey::Bpm                         => "BPM",
+        StandardTagKey::Comment                     => "COMMENT",
+        StandardTagKey::Compilation                 => "COMPILATION",
+        StandardTagKey::Composer                    => "COMPOSER",
+        StandardTagKey::Conductor                   => "CONDUCTOR",
+        StandardTagKey::Copyright                   => "COPYRIGHT",
+        StandardTagKey::Date                        => "DATE",
+        StandardTagKey::Description                 => "DESCRIPTION",
+        StandardTagKey::DiscNumber                  => "DISCNUMBER",
+        StandardTagKey::DiscSubtitle                => "DISCSUBTITLE",
+        StandardTagKey::DiscTotal                   => "DISCTOTAL",
+        StandardTagKey::EncodedBy                   => "ENCODED-BY",
+        StandardTagKey::Encoder                     => "ENCODER",
+        StandardTagKey::EncoderSettings             => "ENCODER SETTINGS",
+        StandardTagKey::Engineer                    => "ENGINEER",
+        StandardTagKey::Ensemble                    => "ENSEMBLE",
+        StandardTagKey::Genre                       => "GENRE",
+        StandardTagKey::IdentBarcode                => "BARCODE",
+        StandardTagKey::IdentCatalogNumber          => "CATALOGNUMBER",
+        StandardTagKey::IdentEanUpn                 => "EAN/UPN",
+        StandardTagKey::IdentIsrc                   => "ISRC",
+        StandardTagKey::IdentPn                     => "PRODUCTNUMBER",
+        StandardTagKey::IdentUpc                    => "UPC",
+        StandardTagKey::Label                       => "LABEL",
+        StandardTagKey::Language                    => "LANGUAGE",
+        StandardTagKey::License                     => "LICENSE",
+        StandardTagKey::Lyricist                    => "LYRICIST",
+        StandardTagKey::Lyrics                      => "LYRICS",
+        StandardTagKey::MediaFormat                 => "MEDIA",
+        StandardTagKey::MixDj                       => "DJMIXER",
+        StandardTagKey::MixEngineer                 => "MIXER",
+        StandardTagKey::Mood                        => "MOOD",
+        StandardTagKey::MusicBrainzAlbumArtistId    => "MUSICBRAINZ_ALBUMARTISTID",
+        StandardTagKey::MusicBrainzAlbumId          => "MUSICBRAINZ_ALBUMID",
+        StandardTagKey::MusicBrainzArtistId         => "MUSICBRAINZ_ARTISTID",
+        StandardTagKey::MusicBrainzDiscId           => "MUSICBRAINZ_DISCID",
+        StandardTagKey::MusicBrainzOriginalAlbumId  => "MUSICBRAINZ_ORIGINALALBUMID",
+        StandardTagKey::MusicBrainzOriginalArtistId => "MUSICBRAINZ_ORIGINALARTISTID",
+        StandardTagKey::MusicBrainzRecordingId      => "MUSICBRAINZ_RECORDINGID",
+        StandardTagKey::MusicBrainzReleaseGroupId   => "MUSICBRAINZ_RELEASEGROUPID",
+        StandardTagKey::MusicBrainzReleaseTrackId   => "MUSICBRAINZ_RELEASETRACKID",
+        StandardTagKey::MusicBrainzTrackId          => "MUSICBRAINZ_TRACKID",
+        StandardTagKey::MusicBrainzWorkId           => "MUSICBRAINZ_WORKID",
+        StandardTagKey::Opus                        => "OPUS",
+        StandardTagKey::OriginalDate                => "ORIGINALDATE",
+        StandardTagKey::Part                        => "PART",
+        StandardTagKey::Performer                   => "PERFORMER",
+        StandardTagKey::Producer                    => "PRODUCER",
+        StandardTagKey::R128AlbumGain               => "R128_ALBUM_GAIN",
+        StandardTagKey::R128TrackGain               => "R128_TRACK_GAIN",
+        StandardTagKey::Rating                      => "RATING",
+        StandardTagKey::ReleaseCountry              => "RELEASECOUNTRY",
+        StandardTagKey::Remixer                     => "REMIXER",
+        StandardTagKey::ReplayGainAlbumGain         => "REPLAYGAIN_ALBUM_GAIN",
+        StandardTagKey::ReplayGainAlbumPeak         => "REPLAYGAIN_ALBUM_PEAK",
+        StandardTagKey::ReplayGainTrackGain         => "REPLAYGAIN_TRACK_GAIN",
+        StandardTagKey::ReplayGainTrackPeak         => "REPLAYGAIN_TRACK_PEAK",
+        StandardTagKey::Script                      => "SCRIPT",
+        StandardTagKey::SortAlbum                   => "ALBUMSORT",
+        StandardTagKey::SortAlbumArtist             => "ALBUMARTISTSORT",
+        StandardTagKey::SortArtist                  => "ARTISTSORT",
+        StandardTagKey::SortTrackTitle              => "TITLESORT",
+        StandardTagKey::TrackNumber                 => "TRACKNUMBER",
+        StandardTagKey::TrackSubtitle               => "SUBTITLE",
+        StandardTagKey::TrackTitle                  => "TITLE",
+        StandardTagKey::TrackTotal                  => "TRACKTOTAL",
+        StandardTagKey::Version                     => "VERSION",
+        StandardTagKey::Writer                      => "WRITER",
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Serialize a `Tag` into a Vorbis Comment string. Returns `None` if the `Tag` cannot be
+/// represented as a Vorbis Comment.
+pub fn serialize(tag: &Tag) -> Option<String> {
+    // Prefer the canonical field name for the standard tag key. Otherwise, the key itself may be
+    // used if it is a valid field name.
+    let key = match tag.std_key.and_then(comment_key_for_std_key) {
+        Some(key) => key.to_string(),
+        None => {
+            let is_valid = !tag.key.is_empty()
+                && tag.key.bytes().all(|c| (0x20..=0x7d).contains(&c) && c != b'=');
+
+            if !is_valid {
+                return None;
+            }
+
+            tag.key.to_uppercase()
+        }
+    };
+
+    // Binary data and flags have no string representation.
+    match tag.value {
+        Value::Binary(_) | Value::Flag => None,
+        ref value => Some(format!("{}={}", key, value)),
+    }
+}
+
+/// Serializes a vendor string and tags as a Vorbis Comment, without the framing bit, and appends
+/// it to `buf`. Tags that cannot be represented as a Vorbis Comment are ignored.
+pub fn write_comment_no_framing(buf: &mut Vec<u8>, vendor: &str, tags: &[Tag]) {
+    let comments: Vec<String> = tags.iter().filter_map(serialize).collect();
+
+    buf.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    buf.extend_from_slice(vendor.as_bytes());
+
+    buf.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+
+    for comment in comments {
+        buf.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        buf.extend_from_slice(comment.as_bytes());
+    }
+}
@@ -19,10 +19,12 @@ use std::path::Path;
 use symphonia;
 use symphonia::core::errors::{Result, Error};
 use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::formats::{Cue, FormatReader, FormatOptions, SeekMode, SeekTo, Track};
-use symphonia::core::meta::{ColorMode, MetadataOptions, Tag, Value, Visual};
+use symphonia::core::dsp::gain::{Normalizer, NormalizerOptions, ReplayGainMode};
+use symphonia::core::formats::{Cue, FormatReader, FormatOptions, SeekTo, Track};
+use symphonia::core::meta::{ColorMode, MetadataOptions, ReplayGain, Tag, Value, Visual};
 use symphonia::core::io::{MediaSourceStream, MediaSource, ReadOnlySource};
 use symphonia::core::probe::{Hint, ProbeResult};
+use symphonia::core::track::TrackDecoder;
 use symphonia::core::units::{Duration, Time};
 
 use clap::{Arg, App};
@@ -72,6 +74,14 @@ fn main() {
                             .long("verify")
                             .short("-V")
                             .help("Verify the decoded audio is valid during playback"))
+                        .arg(Arg::with_name("no-gapless")
+                            .long("no-gapless")
+                            .help("Disable gapless decoding and playback"))
+                        .arg(Arg::with_name("replaygain")
+                            .long("replaygain")
+                            .value_name("MODE")
+                            .possible_values(&[ "track", "album" ])
+                            .help("Normalize the loudness of the audio using ReplayGain"))
                        .arg(Arg::with_name("verbose")
                             .short("v")
                             .multiple(true)
@@ -118,16 +128,25 @@ fn main() {
         _ => None,
     };
 
+    // Gapless decoding is enabled unless explicitly disabled.
+    let gapless = !matches.is_present("no-gapless");
+
     // Probe the media source stream for metadata and get the format reader.
     match symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
         Ok(mut probed) => {
             let result = if matches.is_present("verify-only") {
                 // Verify-only mode decodes and verifies the audio, but does not play it.
-                decode_only(probed.format, &DecoderOptions { verify: true, ..Default::default() })
+                let mut options = DecoderOptions::default();
+                options.with_verify(true).with_gapless(gapless);
+
+                decode_only(probed.format, &options)
             }
             else if matches.is_present("decode-only") {
                 // Decode-only mode decodes the audio, but does not play or verify it.
-                decode_only(probed.format, &DecoderOptions { verify: false, ..Default::default() })
+                let mut options = DecoderOptions::default();
+                options.with_gapless(gapless);
+
+                decode_only(probed.format, &options)
             }
             else if matches.is_present("probe-only") {
                 // Probe-only mode only prints information about the format, tracks, metadata, etc.
@@ -142,13 +161,24 @@ fn main() {
                 let seek_time = matches.value_of("seek").map(|p| p.parse::<f64>().unwrap_or(0.0));
 
                 // Set the decoder options.
-                let options = DecoderOptions {
-                    verify: matches.is_present("verify"),
-                    ..Default::default()
-                };
+                let mut options = DecoderOptions::default();
+                options.with_verify(matches.is_present("verify")).with_gapless(gapless);
+
+                // If requested, normalize the loudness using the ReplayGain information in the
+                // tags, and the codec parameters of the track.
+                let replay_gain = matches.value_of("replaygain").map(|mode| {
+                    let mode = match mode {
+                        "album" => ReplayGainMode::Album,
+                        _ => ReplayGainMode::Track,
+                    };
+
+                    let options = NormalizerOptions { mode, ..Default::default() };
+
+                    (read_replay_gain(&mut probed), options)
+                });
 
                 // Play it!
-                play(probed.format, track, seek_time, &options)
+                play(probed.format, track, seek_time, &options, replay_gain)
             };
 
             if let Err(err) = result {
@@ -207,10 +237,11 @@ fn decode_only(mut reader: Box<dyn FormatReader>, decode_options: &DecoderOption
 }
 
 fn play(
-    mut reader: Box<dyn FormatReader>,
+    reader: Box<dyn FormatReader>,
     play_track: Option<usize>,
     seek_time: Option<f64>,
-    decode_options: &DecoderOptions
+    decode_options: &DecoderOptions,
+    replay_gain: Option<(ReplayGain, NormalizerOptions)>,
 ) -> Result<()> {
     // The audio output device.
     let mut audio_output = None;
@@ -224,65 +255,87 @@ fn play(
         _ => reader.default_track().unwrap(),
     };
 
-    let mut track_id = track.id;
+    let track_id = track.id;
 
-    // Create a decoder for the track.
-    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &decode_options)?;
+    // Tags are preferred over the ReplayGain information in the codec parameters.
+    let normalizer = replay_gain.map(|(replay_gain, options)| {
+        let replay_gain = replay_gain.or(track.codec_params.replay_gain.unwrap_or_default());
+
+        let normalizer = Normalizer::new(&replay_gain, &options);
+
+        info!("applying replaygain: {:.2} dB", 20.0 * normalizer.gain().log10());
+
+        normalizer
+    });
 
-    // If there is a seek time, seek the reader to the time specified and get the timestamp of the
-    // seeked position. All packets with a timestamp < the seeked position will not be played.
-    //
-    // Note: This is a half-baked approach to seeking! After seeking the reader, packets should be
-    // decoded and *samples* discarded up-to the exact *sample* indicated by required_ts. The current
-    // approach will discard excess samples if seeking to a sample within a packet.
-    let seek_ts = if let Some(time) = seek_time {
+    // Create a track decoder for the track.
+    let codecs = symphonia::default::get_codecs();
+
+    let mut track_decoder = TrackDecoder::try_new(reader, track_id, codecs, decode_options)?;
+
+    // If there is a seek time, seek to the time specified. The track decoder will discard all
+    // audio frames before the exact audio frame seeked to.
+    if let Some(time) = seek_time {
         let seek_to = SeekTo::Time { time: Time::from(time), track_id: Some(track_id) };
 
-        // Attempt the seek. If the seek fails, ignore the error and return a seek timestamp of 0 so
-        // that no samples are trimmed.
-        match reader.seek(SeekMode::Accurate, seek_to) {
-            Ok(seeked_to) => {
-                seeked_to.required_ts
-            }
-            Err(err) => {
-                // Don't give-up on a seek error.
-                warn!("seek error: {}", err);
-                0
-            }
+        // Attempt the seek. If the seek fails, ignore the error and play from the current
+        // position.
+        if let Err(err) = track_decoder.seek(seek_to) {
+            // Don't give-up on a seek error.
+            warn!("seek error: {}", err);
         }
     }
-    else {
-        // If not seeking, the seek timestamp is 0.
-        0
-    };
 
     // Decode and play the packets belonging to the selected track.
     let result = loop {
-        // Get the next packet from the media container.
-        let packet = match reader.next_packet() {
-            Ok(packet) => packet,
+        // Get the next buffer of decoded audio.
+        let result = track_decoder.next_buffer().map(|mut decoded| {
+            // If the audio output is not open, try to open it.
+            if audio_output.is_none() {
+                // Get the buffer specification. This is a description of the decoded audio
+                // buffer's sample format.
+                let spec = decoded.spec().clone();
+
+                // Get the duration of the decoded buffer.
+                let duration = Duration::from(decoded.capacity() as u64);
+
+                // Try to open the audio output.
+                audio_output = Some(output::try_open(spec, duration).unwrap());
+            }
+
+            if let Some(normalizer) = &normalizer {
+                normalizer.apply_ref(&mut decoded);
+            }
+
+            // Write the decoded audio samples to the audio output.
+            if let Some(audio_output) = audio_output.as_mut() {
+                audio_output.write(decoded).unwrap()
+            }
+        });
+
+        match result {
+            Ok(_) => (),
             Err(Error::ResetRequired) => {
                 // The demuxer indicated that a reset is required. This is sometimes seen with
                 // streaming OGG (e.g., Icecast) wherein the entire contents of the container change
                 // (new tracks, codecs, metadata, etc.). Therefore, we must select a new track and
                 // recreate the decoder.
-                let track = reader.default_track().unwrap();
-                track_id = track.id;
-                decoder = symphonia::default::get_codecs().make(
-                    &track.codec_params,
-                    &decode_options
-                )?;
+                let reader = track_decoder.into_reader();
+                let track_id = reader.default_track().unwrap().id;
+                track_decoder = TrackDecoder::try_new(reader, track_id, codecs, decode_options)?;
                 continue;
             }
+            Err(Error::DecodeError(err)) => {
+                // Decode errors are not fatal. Print the error message and try to decode the next
+                // packet as usual.
+                warn!("decode error: {}", err);
+            }
             Err(err) => break Err(err),
-        };
-
-        // If the packet does not belong to the selected track, skip over it.
-        if packet.track_id() != track_id {
-            continue;
         }
 
         // Print out new metadata.
+        let reader = track_decoder.reader_mut();
+
         while !reader.metadata().is_latest() {
             reader.metadata().pop();
 
@@ -291,38 +344,6 @@ fn play(
                 pretty_print_visuals(rev.visuals());
             }
         }
-
-        // Decode the packet into audio samples.
-        match decoder.decode(&packet) {
-            Ok(decoded) => {
-                // If the audio output is not open, try to open it.
-                if audio_output.is_none() {
-                    // Get the buffer specification. This is a description of the decoded audio
-                    // buffer's sample format.
-                    let spec = decoded.spec().clone();
-
-                    // Get the duration of the decoded buffer.
-                    let duration = Duration::from(decoded.capacity() as u64);
-
-                    // Try to open the audio output.
-                    audio_output = Some(output::try_open(spec, duration).unwrap());
-                }
-
-                // Write the decoded audio samples to the audio output if the presentation timestamp
-                // for the packet is >= the seeked position (0 if not seeking).
-                if packet.pts() >= seek_ts {
-                    if let Some(audio_output) = audio_output.as_mut() {
-                        audio_output.write(decoded).unwrap()
-                    }
-                }
-            }
-            Err(Error::DecodeError(err)) => {
-                // Decode errors are not fatal. Print the error message and try to decode the next
-                // packet as usual.
-                warn!("decode error: {}", err);
-            }
-            Err(err) => break Err(err),
-        }
     };
 
     // Flush the audio output to finish playing back any leftover samples.
@@ -331,7 +352,7 @@ fn play(
     }
 
     // Regardless of result, finalize the decoder to get the verification result.
-    let finalize_result = decoder.finalize();
+    let finalize_result = track_decoder.decoder_mut().finalize();
 
     if let Some(verify_ok) = finalize_result.verify_ok {
         if verify_ok {
@@ -345,6 +366,25 @@ fn play(
     result
 }
 
+/// Reads the ReplayGain information from the tags, preferring tags that are part of the container
+/// format over tags found while probing.
+fn read_replay_gain(probed: &mut ProbeResult) -> ReplayGain {
+    let container = probed.format
+        .metadata()
+        .current()
+        .map(|rev| ReplayGain::from_tags(rev.tags()))
+        .unwrap_or_default();
+
+    let probe = probed.metadata
+        .get()
+        .as_ref()
+        .and_then(|m| m.current())
+        .map(|rev| ReplayGain::from_tags(rev.tags()))
+        .unwrap_or_default();
+
+    container.or(probe)
+}
+
 fn pretty_print_format(path: &str, probed: &mut ProbeResult) {
     println!("+ {}", path);
     pretty_print_tracks(probed.format.tracks());
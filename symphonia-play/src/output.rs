@@ -128,16 +128,21 @@ mod pulseaudio {
 mod cpal {
     use super::{AudioOutput, AudioOutputError, Result};
 
+    use std::borrow::Cow;
+
     use symphonia;
-    use symphonia::core::audio::{AudioBufferRef, SampleBuffer, SignalSpec, RawSample};
+    use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Channels, RawSample, SampleBuffer};
+    use symphonia::core::audio::SignalSpec;
     use symphonia::core::conv::ConvertibleSample;
+    use symphonia::core::dsp::remix::{RemixMatrix, Remixer};
+    use symphonia::core::dsp::resampler::{Resampler, ResamplerQuality};
     use symphonia::core::units::Duration;
 
     use cpal;
     use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
     use rb::*;
 
-    use log::error;
+    use log::{error, info};
 
     pub struct CpalAudioOutput;
 
@@ -174,16 +179,49 @@ mod cpal {
                 }
             };
 
+            let supported_configs = match device.supported_output_configs() {
+                Ok(configs) => configs.collect(),
+                Err(_) => Vec::new(),
+            };
+
+            // If the device does not support the number of channels of the audio, open the device
+            // in mono or stereo instead and downmix.
+            let is_channels_supported = supported_configs.iter().any(|range| {
+                usize::from(range.channels()) == spec.channels.count()
+            });
+
+            let channels = if is_channels_supported {
+                spec.channels
+            }
+            else if config.channels() == 1 {
+                Channels::FRONT_LEFT
+            }
+            else {
+                Channels::FRONT_LEFT | Channels::FRONT_RIGHT
+            };
+
+            // If the device does not support the sample rate of the audio, open the device at its
+            // default sample rate instead and resample.
+            let is_rate_supported = supported_configs.iter().any(|range| {
+                usize::from(range.channels()) == channels.count()
+                    && range.min_sample_rate().0 <= spec.rate
+                    && range.max_sample_rate().0 >= spec.rate
+            });
+
+            let rate = if is_rate_supported { spec.rate } else { config.sample_rate().0 };
+
+            let out_spec = SignalSpec::new(rate, channels);
+
             // Select proper playback routine based on sample format.
             match config.sample_format() {
                 cpal::SampleFormat::F32 => {
-                    CpalAudioOutputImpl::<f32>::try_open(spec, duration, &device)
+                    CpalAudioOutputImpl::<f32>::try_open(spec, out_spec, duration, &device)
                 }
                 cpal::SampleFormat::I16 => {
-                    CpalAudioOutputImpl::<i16>::try_open(spec, duration, &device)
+                    CpalAudioOutputImpl::<i16>::try_open(spec, out_spec, duration, &device)
                 }
                 cpal::SampleFormat::U16 => {
-                    CpalAudioOutputImpl::<u16>::try_open(spec, duration, &device)
+                    CpalAudioOutputImpl::<u16>::try_open(spec, out_spec, duration, &device)
                 }
             }
         }
@@ -196,20 +234,23 @@ mod cpal {
         ring_buf_producer: rb::Producer<T>,
         sample_buf: SampleBuffer<T>,
         stream: cpal::Stream,
+        remixer: Option<Remixer<f32>>,
+        resampler: Option<Resampler>,
     }
 
     impl<T: AudioOutputSample> CpalAudioOutputImpl<T>
     {
         pub fn try_open(
             spec: SignalSpec,
+            out_spec: SignalSpec,
             duration: Duration,
             device: &cpal::Device
         ) -> Result<Box<dyn AudioOutput>>
         {
             // Output audio stream config.
             let config = cpal::StreamConfig {
-                channels: spec.channels.count() as cpal::ChannelCount,
-                sample_rate: cpal::SampleRate(spec.rate),
+                channels: out_spec.channels.count() as cpal::ChannelCount,
+                sample_rate: cpal::SampleRate(out_spec.rate),
                 buffer_size: cpal::BufferSize::Default,
             };
 
@@ -246,9 +287,79 @@ mod cpal {
                 return Err(AudioOutputError::PlayStreamError);
             }
 
-            let sample_buf = SampleBuffer::<T>::new(duration, spec);
+            let remixer = if out_spec.channels != spec.channels {
+                info!(
+                    "downmixing {} channels to {}",
+                    spec.channels.count(),
+                    out_spec.channels.count()
+                );
+
+                Some(Remixer::new(RemixMatrix::standard(spec.channels, out_spec.channels)))
+            }
+            else {
+                None
+            };
+
+            let resampler = if out_spec.rate != spec.rate {
+                info!("resampling {} Hz to {} Hz", spec.rate, out_spec.rate);
+
+                let remixed_spec = SignalSpec::new(spec.rate, out_spec.channels);
+
+                Some(Resampler::new(remixed_spec, out_spec.rate, ResamplerQuality::Medium))
+            }
+            else {
+                None
+            };
 
-            Ok(Box::new(CpalAudioOutputImpl { ring_buf_producer, sample_buf, stream }))
+            let sample_buf = SampleBuffer::<T>::new(duration, out_spec);
+
+            Ok(Box::new(CpalAudioOutputImpl {
+                ring_buf_producer,
+                sample_buf,
+                stream,
+                remixer,
+                resampler,
+            }))
+        }
+    }
+
+    /// Remixes an `AudioBufferRef` of any sample format.
+    fn remix<'a>(
+        remixer: &'a mut Remixer<f32>,
+        src: &AudioBufferRef<'_>,
+    ) -> &'a AudioBuffer<f32>
+    {
+        match src {
+            AudioBufferRef::U8(buf)  => remixer.remix(&**buf),
+            AudioBufferRef::U16(buf) => remixer.remix(&**buf),
+            AudioBufferRef::U24(buf) => remixer.remix(&**buf),
+            AudioBufferRef::U32(buf) => remixer.remix(&**buf),
+            AudioBufferRef::S8(buf)  => remixer.remix(&**buf),
+            AudioBufferRef::S16(buf) => remixer.remix(&**buf),
+            AudioBufferRef::S24(buf) => remixer.remix(&**buf),
+            AudioBufferRef::S32(buf) => remixer.remix(&**buf),
+            AudioBufferRef::F32(buf) => remixer.remix(&**buf),
+            AudioBufferRef::F64(buf) => remixer.remix(&**buf),
+        }
+    }
+
+    /// Resamples an `AudioBufferRef` of any sample format.
+    fn resample<'a>(
+        resampler: &'a mut Resampler,
+        src: &AudioBufferRef<'_>,
+    ) -> &'a AudioBuffer<f32>
+    {
+        match src {
+            AudioBufferRef::U8(buf)  => resampler.resample(&**buf),
+            AudioBufferRef::U16(buf) => resampler.resample(&**buf),
+            AudioBufferRef::U24(buf) => resampler.resample(&**buf),
+            AudioBufferRef::U32(buf) => resampler.resample(&**buf),
+            AudioBufferRef::S8(buf)  => resampler.resample(&**buf),
+            AudioBufferRef::S16(buf) => resampler.resample(&**buf),
+            AudioBufferRef::S24(buf) => resampler.resample(&**buf),
+            AudioBufferRef::S32(buf) => resampler.resample(&**buf),
+            AudioBufferRef::F32(buf) => resampler.resample(&**buf),
+            AudioBufferRef::F64(buf) => resampler.resample(&**buf),
         }
     }
 
@@ -260,6 +371,34 @@ mod cpal {
                 return Ok(())
             }
 
+            // Downmix the audio if the device does not support its channels.
+            let decoded = match &mut self.remixer {
+                Some(remixer) => AudioBufferRef::F32(Cow::Borrowed(remix(remixer, &decoded))),
+                None => decoded,
+            };
+
+            // Resample the audio if the device does not support its sample rate. The resampler may
+            // not produce any frames until it is given more audio.
+            let decoded = match &mut self.resampler {
+                Some(resampler) => {
+                    let resampled = resample(resampler, &decoded);
+
+                    if resampled.frames() == 0 {
+                        return Ok(())
+                    }
+
+                    AudioBufferRef::F32(Cow::Borrowed(resampled))
+                }
+                None => decoded,
+            };
+
+            // The resampler may produce more frames than the sample buffer was sized for.
+            let n_samples = decoded.frames() * decoded.spec().channels.count();
+
+            if self.sample_buf.capacity() < n_samples {
+                self.sample_buf = SampleBuffer::<T>::new(decoded.frames() as u64, *decoded.spec());
+            }
+
             // Audio samples must be interleaved for cpal. Interleave the samples in the audio
             // buffer into the sample buffer.
             self.sample_buf.copy_interleaved_ref(decoded);
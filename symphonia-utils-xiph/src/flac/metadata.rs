@@ -11,7 +11,7 @@ use std::num::NonZeroU32;
 use symphonia_core::audio::Channels;
 use symphonia_core::errors::{Result, decode_error};
 use symphonia_core::formats::{Cue, CuePoint};
-use symphonia_core::formats::util::SeekIndex;
+use symphonia_core::formats::util::{SeekIndex, SeekPoint};
 use symphonia_core::io::*;
 use symphonia_core::meta::{ColorMode, MetadataBuilder, Size, StandardTagKey, Tag, Value};
 use symphonia_core::meta::{VendorData, Visual};
@@ -191,6 +191,36 @@ impl StreamInfo {
 
         Ok(info)
     }
+
+    /// Serializes the `StreamInfo` and appends it to `buf`. This is the inverse of `read`.
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        debug_assert!(self.sample_rate > 0 && self.sample_rate <= 655_350);
+        debug_assert!(self.bits_per_sample >= 4 && self.bits_per_sample <= 32);
+
+        let n_channels = self.channels.count() as u64;
+
+        debug_assert!(n_channels > 0 && n_channels < 9);
+
+        buf.extend_from_slice(&self.block_len_min.to_be_bytes());
+        buf.extend_from_slice(&self.block_len_max.to_be_bytes());
+        buf.extend_from_slice(&self.frame_byte_len_min.to_be_bytes()[1..]);
+        buf.extend_from_slice(&self.frame_byte_len_max.to_be_bytes()[1..]);
+
+        // The sample rate (20 bits), number of channels minus 1 (3 bits), bits per sample minus 1
+        // (5 bits), and the total number of samples (36 bits) are packed into 64 bits. A total
+        // number of samples of 0 indicates a stream of unknown length.
+        let n_samples = self.n_samples.unwrap_or(0);
+
+        debug_assert!(n_samples <= 0xf_ffff_ffff);
+
+        let packed = (u64::from(self.sample_rate) << 44)
+            | ((n_channels - 1) << 41)
+            | (u64::from(self.bits_per_sample - 1) << 36)
+            | n_samples;
+
+        buf.extend_from_slice(&packed.to_be_bytes());
+        buf.extend_from_slice(&self.md5);
+    }
 }
 
 pub fn read_comment_block<B : ReadBytes>(
@@ -226,6 +256,31 @@ pub fn read_seek_table_block<B : ReadBytes>(
     Ok(())
 }
 
+/// Serializes a seek table block and appends it to `buf`. The block will contain `n_points` seek
+/// points. The points given in `points` are written first, and the remainder of the block is
+/// filled with placeholder points.
+pub fn write_seek_table_block(buf: &mut Vec<u8>, points: &[SeekPoint], n_points: usize) {
+    debug_assert!(points.len() <= n_points);
+
+    for point in points {
+        buf.extend_from_slice(&point.frame_ts.to_be_bytes());
+        buf.extend_from_slice(&point.byte_offset.to_be_bytes());
+        buf.extend_from_slice(&(point.n_frames as u16).to_be_bytes());
+    }
+
+    // Placeholder points have a sample number of 0xFFFFFFFFFFFFFFFF. The remaining 10 bytes are
+    // undefined, but are set to 0.
+    for _ in points.len()..n_points {
+        buf.extend_from_slice(&[0xff; 8]);
+        buf.extend_from_slice(&[0; 10]);
+    }
+}
+
+/// Serializes the tags into a Vorbis Comment block and appends it to `buf`.
+pub fn write_comment_block(buf: &mut Vec<u8>, vendor: &str, tags: &[Tag]) {
+    vorbis::write_comment_no_framing(buf, vendor, tags)
+}
+
 /// Converts a string of bytes to an ASCII string if all characters are within the printable ASCII
 /// range. If a null byte is encounted, the string terminates at that point.
 fn printable_ascii_to_string(bytes: &[u8]) -> Option<String> {
@@ -523,5 +578,28 @@ impl MetadataBlockHeader {
             block_len,
         })
     }
+
+    /// Serializes the `MetadataBlockHeader` and appends it to `buf`. This is the inverse of
+    /// `read`.
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let block_type_id = match self.block_type {
+            MetadataBlockType::StreamInfo    => 0,
+            MetadataBlockType::Padding       => 1,
+            MetadataBlockType::Application   => 2,
+            MetadataBlockType::SeekTable     => 3,
+            MetadataBlockType::VorbisComment => 4,
+            MetadataBlockType::Cuesheet      => 5,
+            MetadataBlockType::Picture       => 6,
+            MetadataBlockType::Unknown(id)   => id & 0x7f,
+        };
+
+        debug_assert!(self.block_len <= 0xff_ffff);
+
+        // The first bit of the header indicates if this is the last metadata block.
+        let is_last_flag = if self.is_last { 0x80 } else { 0x00 };
+
+        buf.push(is_last_flag | block_type_id);
+        buf.extend_from_slice(&self.block_len.to_be_bytes()[1..]);
+    }
 }
 
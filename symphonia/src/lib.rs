@@ -25,8 +25,10 @@
 //! | Format   | Feature Flag | Default |
 //! |----------|--------------|---------|
 //! | ISO/MP4  | `isomp4`     | No      |
+//! | MKV/WebM | `mkv`        | Yes     |
 //! | OGG      | `ogg`        | Yes     |
 //! | Wave     | `wav`        | Yes     |
+//! | WavPack  | `wavpack`    | Yes     |
 //!
 //! ## Codecs
 //!
@@ -37,7 +39,9 @@
 //! | AAC-LC   | `aac`        | No      |
 //! | FLAC     | `flac`       | Yes     |
 //! | MP3      | `mp3`        | No      |
+//! | Opus     | `opus`       | Yes     |
 //! | PCM      | `pcm`        | Yes     |
+//! | WavPack  | `wavpack`    | Yes     |
 //!
 //! ## Metadata
 //!
@@ -104,33 +108,47 @@ pub mod default {
     //! module is completely optional and incurs no overhead unless actually used.
 
     pub mod codecs {
-        //! The `codecs` module re-exports all enabled Symphonia decoders.
+        //! The `codecs` module re-exports all enabled Symphonia decoders and encoders.
 
         #[cfg(feature = "aac")]
         pub use symphonia_codec_aac::AacDecoder as AacDecoder;
         #[cfg(feature = "flac")]
         pub use symphonia_bundle_flac::FlacDecoder as FlacDecoder;
+        #[cfg(feature = "flac")]
+        pub use symphonia_bundle_flac::FlacEncoder as FlacEncoder;
         #[cfg(feature = "mp3")]
         pub use symphonia_bundle_mp3::Mp3Decoder as Mp3Decoder;
+        #[cfg(feature = "opus")]
+        pub use symphonia_codec_opus::OpusDecoder as OpusDecoder;
         #[cfg(feature = "pcm")]
         pub use symphonia_codec_pcm::PcmDecoder as PcmDecoder;
         #[cfg(feature = "vorbis")]
         pub use symphonia_codec_vorbis::VorbisDecoder as VorbisDecoder;
+        #[cfg(feature = "wavpack")]
+        pub use symphonia_codec_wavpack::WavPackDecoder as WavPackDecoder;
     }
 
     pub mod formats {
-        //! The `formats` module re-exports all enabled Symphonia format readers.
+        //! The `formats` module re-exports all enabled Symphonia format readers and writers.
 
         #[cfg(feature = "aac")]
         pub use symphonia_codec_aac::AdtsReader as AdtsReader;
         #[cfg(feature = "flac")]
         pub use symphonia_bundle_flac::FlacReader as FlacReader;
+        #[cfg(feature = "flac")]
+        pub use symphonia_bundle_flac::FlacWriter as FlacWriter;
         #[cfg(feature = "isomp4")]
         pub use symphonia_format_isomp4::IsoMp4Reader as IsoMp4Reader;
+        #[cfg(feature = "mkv")]
+        pub use symphonia_format_mkv::MkvReader as MkvReader;
         #[cfg(feature = "mp3")]
         pub use symphonia_bundle_mp3::Mp3Reader as Mp3Reader;
         #[cfg(feature = "wav")]
         pub use symphonia_format_wav::WavReader as WavReader;
+        #[cfg(feature = "wav")]
+        pub use symphonia_format_wav::WavWriter as WavWriter;
+        #[cfg(feature = "wavpack")]
+        pub use symphonia_codec_wavpack::WavPackReader as WavPackReader;
         #[cfg(feature = "ogg")]
         pub use symphonia_format_ogg::OggReader as OggReader;
     }
@@ -188,14 +206,23 @@ pub mod default {
         #[cfg(feature = "flac")]
         registry.register_all::<codecs::FlacDecoder>();
 
+        #[cfg(feature = "flac")]
+        registry.register_all_encoders::<codecs::FlacEncoder>();
+
         #[cfg(feature = "mp3")]
         registry.register_all::<codecs::Mp3Decoder>();
 
+        #[cfg(feature = "opus")]
+        registry.register_all::<codecs::OpusDecoder>();
+
         #[cfg(feature = "pcm")]
         registry.register_all::<codecs::PcmDecoder>();
 
         #[cfg(feature = "vorbis")]
         registry.register_all::<codecs::VorbisDecoder>();
+
+        #[cfg(feature = "wavpack")]
+        registry.register_all::<codecs::WavPackDecoder>();
     }
 
     /// Registers all the formats selected by the `feature` flags in the includer's `Cargo.toml` on
@@ -216,6 +243,9 @@ pub mod default {
         #[cfg(feature = "isomp4")]
         probe.register_all::<formats::IsoMp4Reader>();
 
+        #[cfg(feature = "mkv")]
+        probe.register_all::<formats::MkvReader>();
+
         #[cfg(feature = "mp3")]
         probe.register_all::<formats::Mp3Reader>();
 
@@ -225,6 +255,9 @@ pub mod default {
         #[cfg(feature = "ogg")]
         probe.register_all::<formats::OggReader>();
 
+        #[cfg(feature = "wavpack")]
+        probe.register_all::<formats::WavPackReader>();
+
         // Metadata
         probe.register_all::<Id3v2Reader>();
     }